@@ -0,0 +1,12 @@
+use std::env;
+use std::process::Command;
+
+
+// Toolchain recorded in setup reports
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(rustc).arg("--version").output().ok()
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .unwrap_or_else(|| "rustc unknown".to_string());
+    println!("cargo:rustc-env=ZWAVES_RUSTC_VERSION={}", version.trim());
+}
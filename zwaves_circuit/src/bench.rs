@@ -0,0 +1,257 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use bellman::groth16::{Parameters, generate_random_parameters};
+use sapling_crypto::jubjub::JubjubBls12;
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+use sapling_crypto::circuit::test::TestConstraintSystem;
+use sapling_crypto::pedersen_hash::Personalization;
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{PrimeField, PrimeFieldRepr};
+use rand::{ChaChaRng, SeedableRng, Rng};
+
+use zwaves_primitives::{pedersen_hasher, pedersen_segments, transactions, verifier};
+use zwaves_primitives::pedersen_segments::{SegmentedParams, Segmenting};
+use zwaves_primitives::transactions::NoteData;
+use crate::gadgets::transactions as gadgets;
+use crate::gadgets::pedersen_segments::pedersen_hash as segmented_hash;
+
+use blake2_rfc::blake2s::Blake2s;
+
+use crate::circuit::alloc_note_data;
+use crate::prover::{self, Threads, ProverScratch};
+
+use std::io;
+
+
+// Standardized workloads to compare devices and browsers. The inputs and the proving randomness
+// come from a fixed seed, so every run does the same work and gives the same digests, only the
+// timings differ: a report with a wrong digest is not comparable. The clock is passed in as
+// milliseconds, std::time is not available on wasm32-unknown-unknown and a wasm binding passes
+// performance.now. Setup of a workload (proving parameters) is not timed.
+//
+// The proving workloads run zwaves_circuit::prover, so they run on wasm too, but their parameters
+// come from bellman's generator, which needs threads: a wasm host loads the file of bench_params
+// (zwaves_setup) instead, the same parameters as bench_parameters gives natively.
+pub const BENCH_VERSION: u32 = 2;
+pub const BENCH_SEED: [u32; 4] = [0x7a77_6176, 0x6573_6265, 0x6e63_6800, 1];
+pub const BENCH_PARAMS_SEED: [u32; 4] = [0x7a77_6176, 0x6573_6265, 0x6e63_6800, 2];
+pub const BENCH_PROVE_SEED: [u32; 4] = [0x7a77_6176, 0x6573_6265, 0x6e63_6800, 3];
+pub const BENCH_DIGEST_PERSONALIZATION: &[u8; 8] = b"ZWbench_";
+
+
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub iterations: usize,
+    pub total_ms: f64,
+    pub digest: String
+}
+
+impl BenchResult {
+    pub fn per_iteration_ms(&self) -> f64 {
+        self.total_ms / self.iterations as f64
+    }
+}
+
+
+struct Digest(Blake2s);
+
+impl Digest {
+    fn new() -> Self {
+        Digest(Blake2s::with_params(32, &[], &[], BENCH_DIGEST_PERSONALIZATION))
+    }
+
+    fn fr(&mut self, x: &Fr) {
+        let mut data = vec![];
+        x.into_repr().write_le(&mut data).unwrap();
+        self.0.update(&data);
+    }
+
+    fn finalize(self) -> String {
+        self.0.finalize().as_bytes()[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+
+// One note hash with the hash as the public input, about the size of a single spend check
+struct NoteHash<'a> {
+    params: &'a JubjubBls12,
+    note: Option<NoteData<Bls12>>
+}
+
+impl<'a> Circuit<Bls12> for NoteHash<'a> {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let note = alloc_note_data(cs.namespace(|| "alloc note"), self.note)?;
+        let hash = gadgets::note_hash(cs.namespace(|| "note hash"), &note, self.params)?;
+        hash.inputize(cs.namespace(|| "inputize hash"))
+    }
+}
+
+fn random_note<R: Rng>(rng: &mut R) -> NoteData<Bls12> {
+    NoteData {
+        asset_id: Fr::from_repr(rng.gen::<u64>().into()).unwrap(),
+        amount: Fr::from_repr(rng.gen::<u64>().into()).unwrap(),
+        native_amount: Fr::from_repr(rng.gen::<u64>().into()).unwrap(),
+        txid: rng.gen(),
+        owner: rng.gen()
+    }
+}
+
+
+fn timed<F: FnMut() -> f64, W: FnMut(&mut Digest)>(now: &mut F, name: &'static str, iterations: usize, mut work: W) -> BenchResult {
+    let mut digest = Digest::new();
+    let start = now();
+    for _ in 0..iterations {
+        work(&mut digest);
+    }
+    let total_ms = now() - start;
+    BenchResult { name, iterations, total_ms, digest: digest.finalize() }
+}
+
+// Hashing workloads, the first results of run_benches with the same digests
+fn hash_benches<F: FnMut() -> f64, R: Rng>(now: &mut F, rng: &mut R, params: &JubjubBls12) -> Vec<BenchResult> {
+    let mut res = vec![];
+
+    let notes = (0..32).map(|_| random_note(rng)).collect::<Vec<_>>();
+    let mut i = 0;
+    res.push(timed(now, "pedersen_note_hash", notes.len(), |d| {
+        d.fr(&transactions::note_hash(&notes[i], params).unwrap());
+        i += 1;
+    }));
+
+    let keys = (0..64).map(|_| (rng.gen::<Fr>(), rng.gen::<Fr>())).collect::<Vec<_>>();
+    let mut i = 0;
+    res.push(timed(now, "blake2s_nullifier", keys.len(), |d| {
+        d.fr(&transactions::nullifier_from_key::<Bls12>(&keys[i].0, &keys[i].1));
+        i += 1;
+    }));
+
+    let proofs = (0..4).map(|_| (
+        (0..crate::circuit::MERKLE_PROOF_LEN).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>(),
+        rng.gen::<u64>() >> (64 - crate::circuit::MERKLE_PROOF_LEN),
+        rng.gen::<Fr>()
+    )).collect::<Vec<_>>();
+    let mut i = 0;
+    res.push(timed(now, "merkle_root_48", proofs.len(), |d| {
+        let (sibling, index, leaf) = &proofs[i];
+        d.fr(&pedersen_hasher::merkle_root::<Bls12>(sibling, *index, leaf, params));
+        i += 1;
+    }));
+
+    res
+}
+
+pub fn run_hash_benches<F: FnMut() -> f64>(mut now: F) -> Vec<BenchResult> {
+    hash_benches(&mut now, &mut ChaChaRng::from_seed(&BENCH_SEED), &JubjubBls12::new())
+}
+
+// Parameters of the proving workloads, the same on every run
+pub fn bench_parameters() -> Parameters<Bls12> {
+    let mut rng = ChaChaRng::from_seed(&BENCH_PARAMS_SEED);
+    generate_random_parameters(NoteHash { params: &JubjubBls12::new(), note: None }, &mut rng).unwrap()
+}
+
+// Proving and verifying workloads, circuit_params should be those of bench_parameters: with
+// other ones the proofs fail or the digests differ. The digests do not depend on threads.
+pub fn run_prove_benches<F: FnMut() -> f64>(mut now: F, circuit_params: &Parameters<Bls12>, threads: Threads) -> io::Result<Vec<BenchResult>> {
+    let params = JubjubBls12::new();
+    let mut rng = ChaChaRng::from_seed(&BENCH_PROVE_SEED);
+    let mut res = vec![];
+
+    let notes = (0..2).map(|_| random_note(&mut rng)).collect::<Vec<_>>();
+    let mut scratch = ProverScratch::default();
+    let mut proofs = vec![];
+    let mut i = 0;
+    res.push(timed(&mut now, "prove_note_hash", notes.len(), |d| {
+        let proof = prover::create_random_proof(NoteHash { params: &params, note: Some(notes[i].clone()) }, circuit_params, &mut rng, threads, &mut scratch);
+        if let Ok(proof) = proof.as_ref() {
+            let mut data = vec![];
+            proof.write(&mut data).unwrap();
+            d.0.update(&data);
+        }
+        proofs.push(proof);
+        i += 1;
+    }));
+    let proofs = proofs.into_iter().collect::<io::Result<Vec<_>>>()?;
+
+    let tvk = verifier::truncate_verifying_key(&circuit_params.vk);
+    let inputs = notes.iter().map(|n| transactions::note_hash(n, &params)).collect::<io::Result<Vec<_>>>()?;
+    let mut valid = vec![];
+    let mut i = 0;
+    res.push(timed(&mut now, "verify_note_hash", proofs.len(), |d| {
+        let v = verifier::verify_proof(&tvk, &proofs[i], &inputs[i..i + 1]);
+        d.0.update(&[*v.as_ref().unwrap_or(&false) as u8]);
+        valid.push(v);
+        i += 1;
+    }));
+    for v in valid {
+        v.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    }
+
+    Ok(res)
+}
+
+// Every workload, natively; the prover on the calling thread, as on wasm without threads
+pub fn run_benches<F: FnMut() -> f64>(mut now: F) -> Vec<BenchResult> {
+    let mut res = run_hash_benches(&mut now);
+    res.extend(run_prove_benches(&mut now, &bench_parameters(), Threads::Single).unwrap());
+    res
+}
+
+
+// Segmentings of the Pedersen hash compared on inputs of input_bits, personalization excluded
+// (510 for a compress): constraints of gadgets::pedersen_segments, without the booleanity of the
+// input, and native hashes timed with now, the tables not timed. Kept out of run_benches, whose
+// digests are of Sapling's hash; the digests tell which segmentings are the same function.
+#[derive(Clone, Debug)]
+pub struct SegmentingResult {
+    pub segmenting: Segmenting,
+    pub constraints: usize,
+    pub native: BenchResult
+}
+
+pub fn bench_segmenting<F: FnMut() -> f64>(mut now: F, segmentings: &[Segmenting], input_bits: usize, iterations: usize) -> io::Result<Vec<SegmentingResult>> {
+    if iterations == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "at least one iteration is needed"));
+    }
+    let params = JubjubBls12::new();
+    let mut rng = ChaChaRng::from_seed(&BENCH_SEED);
+    let inputs = (0..iterations).map(|_| (0..input_bits).map(|_| rng.gen()).collect::<Vec<bool>>()).collect::<Vec<_>>();
+    let personalization = Personalization::NoteCommitment;
+    let max_bits = personalization.get_bits().len() + input_bits;
+
+    segmentings.iter().map(|&segmenting| {
+        let sp = SegmentedParams::<Bls12>::new(segmenting, max_bits, &params)?;
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        inputs[0].iter().enumerate()
+            .map(|(i, &b)| AllocatedBit::alloc(cs.namespace(|| format!("bit[{}]", i)), Some(b)).map(Boolean::from))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|bits| segmented_hash(cs.namespace(|| "hash"), personalization, &bits, &sp, &params))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let constraints = cs.num_constraints() - input_bits;
+
+        let mut i = 0;
+        let native = timed(&mut now, "pedersen_segmented", iterations, |d| {
+            d.fr(&pedersen_segments::pedersen_hash(personalization, inputs[i].iter().cloned(), &sp, &params).unwrap().into_xy().0);
+            i += 1;
+        });
+        Ok(SegmentingResult { segmenting, constraints, native })
+    }).collect()
+}
+
+// {"version":1,"results":[{"name":"...","iterations":32,"total_ms":1.5,"per_iteration_ms":0.05,"digest":"..."}]}
+pub fn to_json(results: &[BenchResult]) -> String {
+    let items = results.iter().map(|r| format!(
+        "{{\"name\":\"{}\",\"iterations\":{},\"total_ms\":{:.3},\"per_iteration_ms\":{:.3},\"digest\":\"{}\"}}",
+        r.name, r.iterations, r.total_ms, r.per_iteration_ms(), r.digest
+    )).collect::<Vec<_>>();
+    format!("{{\"version\":{},\"results\":[{}]}}", BENCH_VERSION, items.join(","))
+}
+
+pub fn bench_json<F: FnMut() -> f64>(now: F) -> String {
+    to_json(&run_benches(now))
+}
+
+pub fn hash_bench_json<F: FnMut() -> f64>(now: F) -> String {
+    to_json(&run_hash_benches(now))
+}
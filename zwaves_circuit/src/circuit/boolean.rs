@@ -0,0 +1,13 @@
+use bellman::{ConstraintSystem, SynthesisError};
+use pairing::Engine;
+
+pub use sapling_crypto::circuit::boolean::{
+    Boolean, AllocatedBit, u64_into_boolean_vec_le, field_into_boolean_vec_le, field_into_allocated_bits_le
+};
+pub use crate::gadgets::bitify::{from_bits_le_to_num, from_bits_le_to_num_limited};
+
+
+// Boolean witness, None when the circuit is blank
+pub fn alloc_bit<E: Engine, CS: ConstraintSystem<E>>(cs: CS, value: Option<bool>) -> Result<Boolean, SynthesisError> {
+    Ok(Boolean::Is(AllocatedBit::alloc(cs, value)?))
+}
@@ -0,0 +1,2 @@
+pub use sapling_crypto::circuit::ecc::{EdwardsPoint, MontgomeryPoint, fixed_base_multiplication};
+pub use sapling_crypto::jubjub::FixedGenerators;
@@ -0,0 +1,5 @@
+pub use crate::gadgets::merkle_proof::{
+    merkle_proof, merkle_proof_pair, merkle_proof_any_root, merkle_proof_shifted, merkle_insert, merkle_insert_subtree,
+    subtree_root, subtree_proof, compress4, merkle_proof4
+};
+pub use crate::gadgets::mmr::{mmr_root, mmr_inclusion};
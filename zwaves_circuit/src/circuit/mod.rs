@@ -1,8 +1,4 @@
-use bellman::{Circuit, ConstraintSystem, SynthesisError};
-use sapling_crypto::jubjub::{JubjubEngine, JubjubParams, JubjubBls12};
-use sapling_crypto::circuit::{pedersen_hash};
-use sapling_crypto::circuit::num::{AllocatedNum, Num};
-use sapling_crypto::circuit::boolean::{Boolean, AllocatedBit};
+use sapling_crypto::jubjub::{JubjubParams, JubjubBls12, Unknown, edwards::Point};
 use bellman::groth16::{Proof, generate_random_parameters, prepare_verifying_key, create_random_proof, verify_proof};
 use pairing::bls12_381::{Bls12, Fr, FrRepr};
 use pairing::{PrimeField, PrimeFieldRepr};
@@ -11,9 +7,13 @@ use rand::Rng;
 use sapling_crypto::constants;
 
 use sapling_crypto::pedersen_hash::{pedersen_hash, Personalization};
-use zwaves_primitives::circuit::transactions;
-use zwaves_primitives::transactions::NoteData;
+use crate::gadgets::transactions;
+use zwaves_primitives::transactions::{NoteData, AccountData};
+use zwaves_primitives::merkle_path::MerklePath;
+use zwaves_primitives::pedersen_hasher::MERKLE_MAX_DEPTH;
 use zwaves_primitives::fieldtools;
+use crate::gadgets::input_hash::HashedInputs;
+use zwaves_primitives::public_values::{TransferPublicValues, UtxoAccumulatorPublicValues, AccountUpdatePublicValues};
 
 
 use blake2_rfc::blake2s::Blake2s;
@@ -21,8 +21,37 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use itertools::Itertools;
 use arrayvec::ArrayVec;
 
+pub mod prelude;
+pub mod boolean;
+pub mod num;
+pub mod ecc;
+pub mod pedersen;
+pub mod merkle;
+pub mod signature;
+
+use self::prelude::*;
+use self::boolean::from_bits_le_to_num;
+
 pub const MERKLE_PROOF_LEN:usize = 48;
 
+// Paths of the note tree. The accumulator proves the parent of a pair of leaves, one level less.
+pub type NotePath<E> = MerklePath<E, MERKLE_PROOF_LEN>;
+const _: () = assert!(MERKLE_PROOF_LEN > 1 && MERKLE_PROOF_LEN < MERKLE_MAX_DEPTH, "note tree depth is out of the pedersen personalizations");
+
+
+// Circuits that can be built with no witness at all, for parameter generation and for checking
+// MPC contributions. Setup code should only construct circuits through blank(), so no witness
+// data could be baked into the keys.
+pub trait Blank<'a, E: JubjubEngine>: Circuit<E> {
+    fn blank(params: &'a E::Params) -> Self;
+}
+
+impl<'a, E: JubjubEngine, C: Blank<'a, E>> Blank<'a, E> for HashedInputs<C> {
+    fn blank(params: &'a E::Params) -> Self {
+        HashedInputs(C::blank(params))
+    }
+}
+
 
 
 
@@ -52,27 +81,29 @@ pub fn alloc_note_data<E: JubjubEngine, CS:ConstraintSystem<E>>(
         })
 }
 
-pub fn alloc_proof_data<E: JubjubEngine, CS:ConstraintSystem<E>>(
-    mut cs: CS, 
-    data: Option<Vec<(E::Fr, bool)>>) -> Result<Vec<(AllocatedNum<E>, Boolean)>, SynthesisError> {
-    Ok(match data {
-        Some(data) => {
-            data.iter().enumerate().map(|(i, (sibling, path))| 
-                (
-                    AllocatedNum::alloc(cs.namespace(|| format!("sibling[{}]", i)), || Ok(sibling.clone())).unwrap(),
-                    Boolean::Is(AllocatedBit::alloc(cs.namespace(|| format!("path[{}]", i)), Some(path.clone())).unwrap())
-                )
-            ).collect::<Vec<(AllocatedNum<E>, Boolean)>>()
-        },
-        None => {
-            (0..MERKLE_PROOF_LEN).map(|i| 
-                (
-                    AllocatedNum::alloc(cs.namespace(|| format!("sibling[{}]", i)), || Err(SynthesisError::AssignmentMissing)).unwrap(),
-                    Boolean::Is(AllocatedBit::alloc(cs.namespace(|| format!("path[{}]", i)), None).unwrap())
-                )
-            ).collect::<Vec<(AllocatedNum<E>, Boolean)>>()
-        }
-    })
+pub fn alloc_account_data<E: JubjubEngine, CS:ConstraintSystem<E>>(
+    mut cs: CS,
+    data: Option<AccountData<E>>) -> Result<transactions::Account<E>, SynthesisError> {
+        let value = |f: fn(&AccountData<E>) -> E::Fr| data.as_ref().map(f).ok_or(SynthesisError::AssignmentMissing);
+        Ok(transactions::Account {
+            asset_id: AllocatedNum::alloc(cs.namespace(|| "alloc asset_id"), || value(|d| d.asset_id))?,
+            balance: AllocatedNum::alloc(cs.namespace(|| "alloc balance"), || value(|d| d.balance))?,
+            native_balance: AllocatedNum::alloc(cs.namespace(|| "alloc native_balance"), || value(|d| d.native_balance))?,
+            nonce: AllocatedNum::alloc(cs.namespace(|| "alloc nonce"), || value(|d| d.nonce))?,
+            owner: AllocatedNum::alloc(cs.namespace(|| "alloc owner"), || value(|d| d.owner))?
+        })
+}
+
+pub fn alloc_proof_data<E: JubjubEngine, CS:ConstraintSystem<E>, const DEPTH: usize>(
+    mut cs: CS,
+    data: Option<MerklePath<E, DEPTH>>) -> Result<Vec<(AllocatedNum<E>, Boolean)>, SynthesisError> {
+    let bits = data.as_ref().map(|p| p.index_bits());
+    (0..DEPTH).map(|i|
+        Ok((
+            num::alloc(cs.namespace(|| format!("sibling[{}]", i)), data.as_ref().map(|p| p.sibling[i]))?,
+            alloc_bit(cs.namespace(|| format!("path[{}]", i)), bits.map(|b| b[i]))?
+        ))
+    ).collect()
 }
 
 pub fn alloc_fr_vec<E: JubjubEngine, CS:ConstraintSystem<E>>(
@@ -95,23 +126,41 @@ pub fn alloc_fr_vec<E: JubjubEngine, CS:ConstraintSystem<E>>(
 pub struct Transfer<'a, E: JubjubEngine> {
     pub receiver: Option<E::Fr>,
     pub in_note: [Option<NoteData<E>>; 2],
-    pub in_proof: [Option<Vec<(E::Fr, bool)>>; 2],
+    pub in_proof: [Option<NotePath<E>>; 2],
     pub out_note: [Option<NoteData<E>>; 2],
     pub root_hash: Option<E::Fr>,
     pub sk: Option<E::Fr>,
+    pub alpha: Option<E::Fr>,
     pub packed_asset: Option<E::Fr>,
     pub params: &'a E::Params
 }
 
 
+impl<'a, E: JubjubEngine> Blank<'a, E> for Transfer<'a, E> {
+    fn blank(params: &'a E::Params) -> Self {
+        Transfer {
+            receiver: None,
+            in_note: [None, None],
+            in_proof: [None, None],
+            out_note: [None, None],
+            root_hash: None,
+            sk: None,
+            alpha: None,
+            packed_asset: None,
+            params
+        }
+    }
+}
+
+// Public inputs: TransferPublicValues
 impl <'a, E: JubjubEngine> Circuit<E> for Transfer<'a, E> {
     fn synthesize<CS: ConstraintSystem<E>>(
         self,
         cs: &mut CS
     ) -> Result<(), SynthesisError>
     {
-        let receiver = AllocatedNum::alloc(cs.namespace(|| "allocate receiver"), || self.receiver.ok_or(SynthesisError::AssignmentMissing)).unwrap();
-        receiver.inputize(cs.namespace(|| "inputize receiver")).unwrap();
+        let receiver = num::alloc(cs.namespace(|| "allocate receiver"), self.receiver).unwrap();
+        receiver.inputize(cs.namespace(|| "inputize receiver"))?;
 
 
         let in_note = (0..2).map(|i| alloc_note_data(cs.namespace(|| format!("alloc note data in_note[{}]", i)), self.in_note[i].clone()))
@@ -123,33 +172,136 @@ impl <'a, E: JubjubEngine> Circuit<E> for Transfer<'a, E> {
         let in_proof = (0..2).map(|i| alloc_proof_data(cs.namespace(|| format!("alloc proof data in_proof[{}]", i)), self.in_proof[i].clone()))
         .collect::<Result<ArrayVec<[Vec<(AllocatedNum<E>, Boolean)>;2]>, SynthesisError>>()?;
 
-        let root_hash = AllocatedNum::alloc(cs.namespace(|| "alloc root_hash"), || self.root_hash.ok_or(SynthesisError::AssignmentMissing))?;
-        let sk = AllocatedNum::alloc(cs.namespace(|| "alloc sk"), || self.sk.ok_or(SynthesisError::AssignmentMissing))?;
+        let root_hash = num::alloc(cs.namespace(|| "alloc root_hash"), self.root_hash)?;
+        let sk = num::alloc(cs.namespace(|| "alloc sk"), self.sk)?;
+        let alpha = num::alloc(cs.namespace(|| "alloc alpha"), self.alpha)?;
 
-        let packed_asset = AllocatedNum::alloc(cs.namespace(|| "alloc packed_asset"), || self.packed_asset.ok_or(SynthesisError::AssignmentMissing))?;
+        let packed_asset = num::alloc(cs.namespace(|| "alloc packed_asset"), self.packed_asset)?;
 
-        let (out_hash, nf) = transactions::transfer(cs.namespace(|| "transfer"),
+        let (out_hash, nf, rk) = transactions::transfer(cs.namespace(|| "transfer"),
             &in_note,
             &in_proof,
             &out_note,
             &root_hash,
             &sk,
+            &alpha,
             &packed_asset,
             self.params)?;
 
-        root_hash.inputize(cs.namespace(|| "root_hash inputize")).unwrap();
-        packed_asset.inputize(cs.namespace(|| "packed asset inputize")).unwrap();
+        TransferPublicValues {
+            receiver,
+            root_hash,
+            packed_asset,
+            out_hash: [out_hash[0].clone(), out_hash[1].clone()],
+            nf: [nf[0].clone(), nf[1].clone()],
+            rk: [rk.get_x().clone(), rk.get_y().clone()]
+        }.inputize_from(cs.namespace(|| "public values"), 1)?;
+        Ok(())
+    }
+
+}
 
-        out_hash.iter().enumerate().for_each(|(i, n)| 
-            n.inputize(cs.namespace(|| format!("inputize out_hash[{}]", i))).unwrap()
-        );
 
-        nf.iter().enumerate().for_each(|(i, n)| 
-            n.inputize(cs.namespace(|| format!("inputize nf[{}]", i))).unwrap()
-        );
-        Ok(())
+// Transfer proven without sk: the witness has ak and nk and the input notes should be owned by
+// transactions::threshold_owner(x(ak), nk), the owner which commits to nk. A remote prover or a
+// host working with a hardware signer holds nothing which spends, the bundle is only valid with
+// a signature under rk by the holder of sk (or of the threshold shares).
+//
+// Notes owned by pubkey(sk) = x(ak) can not be spent here: their nullifier needs sk, which
+// the circuit would have to hold. A wallet which wants to spend through a remote prover receives
+// to threshold_owner(x(ak), nk) instead; detached::record_transfer rejects the other notes.
+// Public inputs: TransferPublicValues
+pub struct AuthorityTransfer<'a, E: JubjubEngine> {
+    pub receiver: Option<E::Fr>,
+    pub in_note: [Option<NoteData<E>>; 2],
+    pub in_proof: [Option<NotePath<E>>; 2],
+    pub out_note: [Option<NoteData<E>>; 2],
+    pub root_hash: Option<E::Fr>,
+    pub ak: Option<Point<E, Unknown>>,
+    pub nk: Option<E::Fr>,
+    pub alpha: Option<E::Fr>,
+    pub packed_asset: Option<E::Fr>,
+    pub params: &'a E::Params
+}
+
+// by hand, derive would ask for E: Clone
+impl<'a, E: JubjubEngine> Clone for AuthorityTransfer<'a, E> {
+    fn clone(&self) -> Self {
+        AuthorityTransfer {
+            receiver: self.receiver,
+            in_note: self.in_note.clone(),
+            in_proof: self.in_proof.clone(),
+            out_note: self.out_note.clone(),
+            root_hash: self.root_hash,
+            ak: self.ak.clone(),
+            nk: self.nk,
+            alpha: self.alpha,
+            packed_asset: self.packed_asset,
+            params: self.params
+        }
     }
+}
 
+impl<'a, E: JubjubEngine> Blank<'a, E> for AuthorityTransfer<'a, E> {
+    fn blank(params: &'a E::Params) -> Self {
+        AuthorityTransfer {
+            receiver: None,
+            in_note: [None, None],
+            in_proof: [None, None],
+            out_note: [None, None],
+            root_hash: None,
+            ak: None,
+            nk: None,
+            alpha: None,
+            packed_asset: None,
+            params
+        }
+    }
+}
+
+impl <'a, E: JubjubEngine> Circuit<E> for AuthorityTransfer<'a, E> {
+    fn synthesize<CS: ConstraintSystem<E>>(
+        self,
+        cs: &mut CS
+    ) -> Result<(), SynthesisError>
+    {
+        let receiver = num::alloc(cs.namespace(|| "allocate receiver"), self.receiver)?;
+
+        let in_note = (0..2).map(|i| alloc_note_data(cs.namespace(|| format!("alloc note data in_note[{}]", i)), self.in_note[i].clone()))
+            .collect::<Result<ArrayVec<[transactions::Note<E>;2]>, SynthesisError>>()?;
+
+        let out_note = (0..2).map(|i| alloc_note_data(cs.namespace(|| format!("alloc note data out_note[{}]", i)), self.out_note[i].clone()))
+            .collect::<Result<ArrayVec<[transactions::Note<E>;2]>, SynthesisError>>()?;
+
+        let in_proof = (0..2).map(|i| alloc_proof_data(cs.namespace(|| format!("alloc proof data in_proof[{}]", i)), self.in_proof[i].clone()))
+            .collect::<Result<ArrayVec<[Vec<(AllocatedNum<E>, Boolean)>;2]>, SynthesisError>>()?;
+
+        let root_hash = num::alloc(cs.namespace(|| "alloc root_hash"), self.root_hash)?;
+        let nk = num::alloc(cs.namespace(|| "alloc nk"), self.nk)?;
+        let authority = transactions::threshold_authority(cs.namespace(|| "authority"), self.ak, &nk, self.params)?;
+        let alpha = num::alloc(cs.namespace(|| "alloc alpha"), self.alpha)?;
+
+        let packed_asset = num::alloc(cs.namespace(|| "alloc packed_asset"), self.packed_asset)?;
+
+        let (out_hash, nf, rk) = transactions::transfer_with_authority(cs.namespace(|| "transfer"),
+            &in_note,
+            &in_proof,
+            &out_note,
+            &root_hash,
+            &authority,
+            &alpha,
+            &packed_asset,
+            self.params)?;
+
+        TransferPublicValues {
+            receiver,
+            root_hash,
+            packed_asset,
+            out_hash: [out_hash[0].clone(), out_hash[1].clone()],
+            nf: [nf[0].clone(), nf[1].clone()],
+            rk: [rk.get_x().clone(), rk.get_y().clone()]
+        }.inputize(cs.namespace(|| "public values"))
+    }
 }
 
 
@@ -162,6 +314,17 @@ pub struct UtxoAccumulator<'a, E: JubjubEngine> {
     pub params: &'a E::Params
 }
 
+impl<'a, E: JubjubEngine> Blank<'a, E> for UtxoAccumulator<'a, E> {
+    fn blank(params: &'a E::Params) -> Self {
+        UtxoAccumulator {
+            note_hashes: [None, None],
+            index: None,
+            old_proof: None,
+            new_proof: None,
+            params
+        }
+    }
+}
 
 
 impl <'a, E: JubjubEngine> Circuit<E> for UtxoAccumulator<'a, E> {
@@ -171,23 +334,80 @@ impl <'a, E: JubjubEngine> Circuit<E> for UtxoAccumulator<'a, E> {
     ) -> Result<(), SynthesisError>
     {
         let note_hashes = (0..2).map(|i| {
-            let n = AllocatedNum::alloc(cs.namespace(|| format!("alloc note_hashes[{}]", i)), || self.note_hashes[i].ok_or(SynthesisError::AssignmentMissing)).unwrap();
+            let n = num::alloc(cs.namespace(|| format!("alloc note_hashes[{}]", i)), self.note_hashes[i]).unwrap();
             n.inputize(cs.namespace(|| format!("inputize note_hashes[{}]", i))).unwrap();
             n
         }).collect::<Vec<_>>();
 
-
-        let index = AllocatedNum::alloc(cs.namespace(|| "allocate index"), || self.index.ok_or(SynthesisError::AssignmentMissing)).unwrap();
-        index.inputize(cs.namespace(|| "inputize index")).unwrap();
+        let index = num::alloc(cs.namespace(|| "allocate index"), self.index).unwrap();
+        index.inputize(cs.namespace(|| "inputize index"))?;
 
         let old_proof = alloc_fr_vec(cs.namespace(|| "alloc old_proof"), self.old_proof, MERKLE_PROOF_LEN-1)?;
         let new_proof = alloc_fr_vec(cs.namespace(|| "alloc new_proof"), self.new_proof, MERKLE_PROOF_LEN-1)?;
 
         let (old_root, new_root) = transactions::utxo_accumulator(cs.namespace(|| "process dual merkle proofs"), &note_hashes, &index, &old_proof, &new_proof, self.params)?;
         
-        old_root.inputize(cs.namespace(|| "inputize old_root"))?;
-        new_root.inputize(cs.namespace(|| "inputize new_root"))?;
+        UtxoAccumulatorPublicValues {
+            note_hashes: [note_hashes[0].clone(), note_hashes[1].clone()],
+            index,
+            old_root,
+            new_root
+        }.inputize_from(cs.namespace(|| "public values"), 3)
+    }
+}
 
-        Ok(())
+
+// Account model counterpart of Transfer: a single leaf is updated in place.
+// Public inputs: AccountUpdatePublicValues
+#[derive(Clone)]
+pub struct AccountUpdate<'a, E: JubjubEngine> {
+    pub account: Option<AccountData<E>>,
+    pub proof: Option<NotePath<E>>,
+    pub sk: Option<E::Fr>,
+    pub packed_asset: Option<E::Fr>,
+    pub params: &'a E::Params
+}
+
+impl<'a, E: JubjubEngine> Blank<'a, E> for AccountUpdate<'a, E> {
+    fn blank(params: &'a E::Params) -> Self {
+        AccountUpdate {
+            account: None,
+            proof: None,
+            sk: None,
+            packed_asset: None,
+            params
+        }
     }
-}
\ No newline at end of file
+}
+
+
+impl <'a, E: JubjubEngine> Circuit<E> for AccountUpdate<'a, E> {
+    fn synthesize<CS: ConstraintSystem<E>>(
+        self,
+        cs: &mut CS
+    ) -> Result<(), SynthesisError>
+    {
+        let account = alloc_account_data(cs.namespace(|| "alloc account data"), self.account.clone())?;
+        let proof = alloc_proof_data(cs.namespace(|| "alloc proof data"), self.proof.clone())?;
+        let sk = num::alloc(cs.namespace(|| "alloc sk"), self.sk)?;
+        let packed_asset = num::alloc(cs.namespace(|| "alloc packed_asset"), self.packed_asset)?;
+
+        let (old_root, new_root, new_hash) = transactions::account_update(cs.namespace(|| "account update"),
+            &account,
+            &proof,
+            &sk,
+            &packed_asset,
+            self.params)?;
+
+        let index_bits = proof.iter().map(|(_, b)| b.clone()).collect::<Vec<_>>();
+        let index = from_bits_le_to_num(cs.namespace(|| "pack index"), &index_bits)?;
+
+        AccountUpdatePublicValues {
+            old_root,
+            new_root,
+            index,
+            packed_asset,
+            new_hash
+        }.inputize(cs.namespace(|| "public values"))
+    }
+}
@@ -0,0 +1,10 @@
+use bellman::{ConstraintSystem, SynthesisError};
+use pairing::Engine;
+
+pub use sapling_crypto::circuit::num::{AllocatedNum, Num};
+
+
+// Field witness, None when the circuit is blank
+pub fn alloc<E: Engine, CS: ConstraintSystem<E>>(cs: CS, value: Option<E::Fr>) -> Result<AllocatedNum<E>, SynthesisError> {
+    AllocatedNum::alloc(cs, || value.ok_or(SynthesisError::AssignmentMissing))
+}
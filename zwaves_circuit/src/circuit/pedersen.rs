@@ -0,0 +1,19 @@
+use bellman::{ConstraintSystem, SynthesisError};
+use sapling_crypto::jubjub::JubjubEngine;
+
+use super::boolean::Boolean;
+use super::num::AllocatedNum;
+
+pub use sapling_crypto::circuit::pedersen_hash::{pedersen_hash, Personalization};
+pub use crate::gadgets::merkle_proof::compress;
+
+
+// x coordinate of the hash point, the way every hash of the protocol is taken
+pub fn hash<E: JubjubEngine, CS: ConstraintSystem<E>>(
+    cs: CS,
+    personalization: Personalization,
+    bits: &[Boolean],
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError> {
+    Ok(pedersen_hash(cs, personalization, bits, params)?.get_x().clone())
+}
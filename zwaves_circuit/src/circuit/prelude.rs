@@ -0,0 +1,9 @@
+// What a circuit built on the gadgets of this crate usually needs, for `use zwaves_circuit::circuit::prelude::*`
+pub use bellman::{Circuit, ConstraintSystem, SynthesisError};
+pub use sapling_crypto::jubjub::JubjubEngine;
+
+pub use super::Blank;
+pub use super::boolean::{Boolean, AllocatedBit, alloc_bit};
+pub use super::num::{AllocatedNum, Num};
+pub use super::ecc::EdwardsPoint;
+pub use super::pedersen::Personalization;
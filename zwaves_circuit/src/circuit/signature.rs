@@ -0,0 +1,3 @@
+// Spend authorization: the key of sk, rk = ak + alpha*G checked by the verifier of the
+// signature out of the circuit, and the authority of a threshold group
+pub use crate::gadgets::transactions::{pubkey, randomized_pubkey, threshold_authority, SpendAuthority};
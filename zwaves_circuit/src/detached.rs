@@ -0,0 +1,298 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError, Variable, Index, LinearCombination};
+use bellman::groth16::{Parameters, Proof, create_random_proof};
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+use rand::Rng;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use std::io::{self, Read, Write};
+
+use zwaves_primitives::serialization::read_fr_repr_be;
+use zwaves_primitives::metrics::{self, Counter, Timing};
+
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use zwaves_primitives::transactions;
+
+use crate::circuit::{AuthorityTransfer, Blank};
+use crate::setup::circuit_hash;
+
+
+// Witness of a circuit taken apart from the circuit, for a proving service which never holds
+// the wallet: the wallet synthesizes the circuit once, records the value of every variable in
+// the order of allocation and hands the file over; the service replays the values into the
+// blank circuit and proves. The proof is bound to the public inputs the signer expects, so a
+// service can not prove another statement from a file it was given, and the signer only signs
+// the bundle when the proof is checked against the inputs it chose.
+//
+// The file holds every private value of the circuit, so transfers are detached with
+// AuthorityTransfer (record_transfer, prove_detached_transfer) and never with Transfer, whose
+// witness has sk. The service learns the notes, ak and nk: it could link the nullifiers of the
+// owner, as a detection service does, but it can not spend, the bundle needs a signature under
+// rk which only the holder of sk gives.
+//
+// Encoding (big-endian): version u8 | circuit hash (32) | u32 count | inputs (32 each) |
+// u32 count | aux (32 each). Inputs do not include the constant one.
+pub const DETACHED_WITNESS_VERSION: u8 = 1;
+pub const MAX_DETACHED_VARIABLES: usize = 1 << 24;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetachedWitness {
+    pub circuit_hash: [u8; 32],
+    pub inputs: Vec<Fr>,
+    pub aux: Vec<Fr>
+}
+
+
+// Synthesizes with the witness, keeps the values and checks every constraint, so a wrong
+// witness is found by the wallet and not by the service after the proof
+struct Recorder {
+    inputs: Vec<Fr>,
+    aux: Vec<Fr>,
+    namespace: Vec<String>,
+    unsatisfied: Option<String>
+}
+
+impl Recorder {
+    fn eval(&self, lc: &LinearCombination<Bls12>) -> Fr {
+        let mut res = Fr::zero();
+        for (v, c) in lc.as_ref().iter() {
+            let mut x = match v.get_unchecked() {
+                Index::Input(0) => Fr::one(),
+                Index::Input(i) => self.inputs[i - 1],
+                Index::Aux(i) => self.aux[i]
+            };
+            x.mul_assign(c);
+            res.add_assign(&x);
+        }
+        res
+    }
+}
+
+impl ConstraintSystem<Bls12> for Recorder {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.aux.push(f()?);
+        Ok(Variable::new_unchecked(Index::Aux(self.aux.len() - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.inputs.push(f()?);
+        Ok(Variable::new_unchecked(Index::Input(self.inputs.len())))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+        LA: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LB: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LC: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>
+    {
+        let mut ab = self.eval(&a(LinearCombination::zero()));
+        ab.mul_assign(&self.eval(&b(LinearCombination::zero())));
+        if self.unsatisfied.is_none() && ab != self.eval(&c(LinearCombination::zero())) {
+            let mut path = self.namespace.clone();
+            path.push(annotation().into());
+            self.unsatisfied = Some(path.join("/"));
+        }
+    }
+
+    fn push_namespace<NR, N>(&mut self, name: N) where NR: Into<String>, N: FnOnce() -> NR {
+        self.namespace.push(name().into());
+    }
+
+    fn pop_namespace(&mut self) {
+        self.namespace.pop();
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+
+// Feeds the recorded values to the constraint system of the prover, in allocation order
+struct Replay<'a, CS> {
+    cs: &'a mut CS,
+    witness: &'a DetachedWitness,
+    next_input: usize,
+    next_aux: usize
+}
+
+impl<'a, CS: ConstraintSystem<Bls12>> ConstraintSystem<Bls12> for Replay<'a, CS> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, annotation: A, _: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let value = *self.witness.aux.get(self.next_aux).ok_or(SynthesisError::AssignmentMissing)?;
+        self.next_aux += 1;
+        self.cs.alloc(annotation, || Ok(value))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, annotation: A, _: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let value = *self.witness.inputs.get(self.next_input).ok_or(SynthesisError::AssignmentMissing)?;
+        self.next_input += 1;
+        self.cs.alloc_input(annotation, || Ok(value))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+        LA: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LB: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LC: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>
+    {
+        self.cs.enforce(annotation, a, b, c)
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N) where NR: Into<String>, N: FnOnce() -> NR {}
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+struct Replayed<'a, C> {
+    circuit: C,
+    witness: &'a DetachedWitness
+}
+
+impl<'a, C: Circuit<Bls12>> Circuit<Bls12> for Replayed<'a, C> {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let mut replay = Replay { cs, witness: self.witness, next_input: 0, next_aux: 0 };
+        self.circuit.synthesize(&mut replay)?;
+        if replay.next_input != self.witness.inputs.len() || replay.next_aux != self.witness.aux.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        Ok(())
+    }
+}
+
+
+fn write_fr_vec<W: Write>(v: &[Fr], mut writer: W) -> io::Result<()> {
+    writer.write_u32::<BigEndian>(v.len() as u32)?;
+    for x in v.iter() {
+        x.into_repr().write_be(&mut writer)?;
+    }
+    Ok(())
+}
+
+fn read_fr_vec<R: Read>(mut reader: R) -> io::Result<Vec<Fr>> {
+    let n = reader.read_u32::<BigEndian>()? as usize;
+    if n > MAX_DETACHED_VARIABLES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "too many variables in the witness"));
+    }
+    let mut buf = [0u8; 32];
+    (0..n).map(|_| {
+        reader.read_exact(&mut buf)?;
+        Fr::from_repr(read_fr_repr_be::<Fr>(&buf)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))
+    }).collect()
+}
+
+
+impl DetachedWitness {
+    // circuit should carry the whole witness
+    pub fn record<C: Circuit<Bls12> + Clone>(circuit: C) -> io::Result<Self> {
+        let hash = circuit_hash(circuit.clone()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut cs = Recorder { inputs: vec![], aux: vec![], namespace: vec![], unsatisfied: None };
+        circuit.synthesize(&mut cs).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if let Some(name) = cs.unsatisfied {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("constraint {} is not satisfied", name)));
+        }
+        Ok(DetachedWitness { circuit_hash: hash, inputs: cs.inputs, aux: cs.aux })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(DETACHED_WITNESS_VERSION)?;
+        writer.write_all(&self.circuit_hash)?;
+        write_fr_vec(&self.inputs, &mut writer)?;
+        write_fr_vec(&self.aux, &mut writer)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = vec![];
+        self.write(&mut res).unwrap();
+        res
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        if reader.read_u8()? != DETACHED_WITNESS_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown witness version"));
+        }
+        let mut circuit_hash = [0u8; 32];
+        reader.read_exact(&mut circuit_hash)?;
+        let inputs = read_fr_vec(&mut reader)?;
+        let aux = read_fr_vec(&mut reader)?;
+        Ok(DetachedWitness { circuit_hash, inputs, aux })
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut reader = data;
+        let res = Self::read(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "trailing bytes after witness"));
+        }
+        Ok(res)
+    }
+}
+
+
+// Proof of blank filled with the witness. The witness should be made for the circuit of blank
+// and should give exactly public_inputs, the inputs the signer expects.
+pub fn prove_detached<C: Circuit<Bls12> + Clone, R: Rng>(
+    params: &Parameters<Bls12>,
+    blank: C,
+    witness: &DetachedWitness,
+    public_inputs: &[Fr],
+    rng: &mut R
+) -> io::Result<Proof<Bls12>> {
+    let hash = circuit_hash(blank.clone()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if hash != witness.circuit_hash {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "witness is made for another circuit"));
+    }
+    if witness.inputs.as_slice() != public_inputs {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "witness does not give the expected public inputs"));
+    }
+    let proof = metrics::time(Timing::Prove, || create_random_proof(Replayed { circuit: blank, witness }, params, rng))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    metrics::inc(Counter::ProofsGenerated, 1);
+    Ok(proof)
+}
+
+
+// Only notes owned by threshold_owner(x(ak), nk) can be detached, see AuthorityTransfer; any
+// other note is rejected here with its index rather than as an unsatisfied constraint.
+pub fn record_transfer(transfer: AuthorityTransfer<Bls12>) -> io::Result<DetachedWitness> {
+    if let (Some(ak), Some(nk)) = (transfer.ak.as_ref(), transfer.nk.as_ref()) {
+        let ak_x = ak.into_xy().0;
+        let owner = transactions::threshold_owner::<Bls12>(&ak_x, nk);
+        for (i, note) in transfer.in_note.iter().enumerate() {
+            match note {
+                Some(n) if n.owner == ak_x => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                    format!("in_note[{}] is owned by pubkey(sk), a detached transfer spends only notes owned by threshold_owner(x(ak), nk)", i))),
+                Some(n) if n.owner != owner => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                    format!("in_note[{}] is not owned by threshold_owner(x(ak), nk)", i))),
+                _ => {}
+            }
+        }
+    }
+    DetachedWitness::record(transfer)
+}
+
+pub fn prove_detached_transfer<R: Rng>(
+    params: &Parameters<Bls12>,
+    witness: &DetachedWitness,
+    public_inputs: &[Fr],
+    rng: &mut R
+) -> io::Result<Proof<Bls12>> {
+    prove_detached(params, AuthorityTransfer::blank(&*JUBJUB_PARAMS), witness, public_inputs, rng)
+}
@@ -0,0 +1,68 @@
+use pairing::{Field, PrimeField};
+use bellman::{ConstraintSystem, SynthesisError};
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::{ecc, blake2s};
+
+use zwaves_primitives::audit::{AUDIT_GENERATOR, AUDIT_MAX_FIELDS, AUDIT_MASK_PERSONALIZATION};
+use crate::gadgets::bitify::from_bits_le_to_num;
+use crate::gadgets::transactions::Note;
+
+
+// Encryption of fields to auditor_pk with randomness r, see zwaves_primitives::audit.
+// Returns y(epk) and the ciphertext, which the caller inputizes.
+pub fn encrypt<E: JubjubEngine, CS>(
+    mut cs: CS,
+    fields: &[AllocatedNum<E>],
+    auditor_pk: &ecc::EdwardsPoint<E>,
+    r: &[Boolean],
+    params: &E::Params
+) -> Result<(AllocatedNum<E>, Vec<AllocatedNum<E>>), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(fields.len() <= AUDIT_MAX_FIELDS, "at most {} fields could be encrypted", AUDIT_MAX_FIELDS);
+
+    let epk = ecc::fixed_base_multiplication(cs.namespace(|| "epk computation"), AUDIT_GENERATOR, r, params)?;
+    let shared = auditor_pk.mul(cs.namespace(|| "shared secret computation"), r, params)?;
+    let mut k_bits = shared.get_y().into_bits_le_strict(cs.namespace(|| "bitify shared secret"))?;
+    k_bits.extend((E::Fr::NUM_BITS..256).map(|_| Boolean::constant(false)));
+
+    let mut data = vec![];
+    for (i, m) in fields.iter().enumerate() {
+        let mut bits = k_bits.clone();
+        bits.extend((0..8).map(|j| Boolean::constant((i >> j) & 1 == 1)));
+
+        let mask_bits = blake2s::blake2s(cs.namespace(|| format!("mask[{}] computation", i)), &bits, AUDIT_MASK_PERSONALIZATION)?;
+        let mask = from_bits_le_to_num(cs.namespace(|| format!("mask[{}] packing", i)), &mask_bits)?;
+
+        let c = AllocatedNum::alloc(cs.namespace(|| format!("c[{}]", i)), || {
+            let mut c = mask.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+            c.add_assign(&m.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+            Ok(c)
+        })?;
+        cs.enforce(
+            || format!("c[{}] = m[{}] + mask[{}]", i, i, i),
+            |lc| lc + m.get_variable() + mask.get_variable(),
+            |lc| lc + CS::one(),
+            |lc| lc + c.get_variable()
+        );
+        data.push(c);
+    }
+
+    Ok((epk.get_y().clone(), data))
+}
+
+pub fn encrypt_note<E: JubjubEngine, CS>(
+    cs: CS,
+    note: &Note<E>,
+    auditor_pk: &ecc::EdwardsPoint<E>,
+    r: &[Boolean],
+    params: &E::Params
+) -> Result<(AllocatedNum<E>, Vec<AllocatedNum<E>>), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let fields = [note.asset_id.clone(), note.amount.clone(), note.native_amount.clone(), note.txid.clone(), note.owner.clone()];
+    encrypt(cs, &fields, auditor_pk, r, params)
+}
@@ -8,6 +8,27 @@ use sapling_crypto::circuit::num::{AllocatedNum, Num};
 use sapling_crypto::circuit::boolean::{Boolean};
 
 
+// Gadgets of fieldtools::to_bits_le_strict and fieldtools::to_bits_le, see there
+pub fn to_bits_le_strict<E: JubjubEngine, CS>(
+    cs: CS,
+    x: &AllocatedNum<E>
+) -> Result<Vec<Boolean>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    x.into_bits_le_strict(cs)
+}
+
+// n above CAPACITY is a bug of the circuit, not of the witness
+pub fn to_bits_le<E: JubjubEngine, CS>(
+    cs: CS,
+    x: &AllocatedNum<E>,
+    n: usize
+) -> Result<Vec<Boolean>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(n <= E::Fr::CAPACITY as usize, "decomposition into {} bits is not unique, use to_bits_le_strict", n);
+    x.into_bits_le_limited(cs, n)
+}
 
 
 pub fn from_bits_le_to_num<E: JubjubEngine, CS>(
@@ -0,0 +1,96 @@
+use bellman::{SynthesisError, ConstraintSystem};
+
+use sapling_crypto::jubjub::{JubjubEngine, FixedGenerators};
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::{Boolean, AllocatedBit};
+use sapling_crypto::circuit::{ecc, pedersen_hash};
+
+use zwaves_primitives::commitment::{Commitment, PedersenCommitment, HashCommitment, FixedBaseCommitter, PEDERSEN_COMMITMENT_DOMAIN};
+use zwaves_primitives::pedersen_hasher::{HASH_PERSONALIZATION, leaf_personalization};
+
+
+// Gadgets of zwaves_primitives::commitment, see there for what the schemes promise. Circuits
+// generic over C: CommitmentGadget<E> + Hiding<E> take the same bounds as the native code. The
+// randomness is its RANDOMNESS_BITS little-endian bits, allocated by alloc_randomness or taken
+// from elsewhere in the circuit; the value is bitified strictly.
+pub trait CommitmentGadget<E: JubjubEngine>: Commitment<E> {
+    fn commitment_gadget<CS: ConstraintSystem<E>>(
+        cs: CS,
+        value: &AllocatedNum<E>,
+        r: &[Boolean],
+        params: &E::Params
+    ) -> Result<AllocatedNum<E>, SynthesisError>;
+
+    fn alloc_randomness<CS: ConstraintSystem<E>>(mut cs: CS, r: Option<&Self::Randomness>) -> Result<Vec<Boolean>, SynthesisError> {
+        let bits = r.map(Self::randomness_bits);
+        (0..Self::RANDOMNESS_BITS)
+            .map(|i| AllocatedBit::alloc(cs.namespace(|| format!("randomness bit[{}]", i)), bits.as_ref().map(|b| b[i])).map(Boolean::from))
+            .collect()
+    }
+
+    fn enforce_opening<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        commitment: &AllocatedNum<E>,
+        value: &AllocatedNum<E>,
+        r: &[Boolean],
+        params: &E::Params
+    ) -> Result<(), SynthesisError> {
+        let c = Self::commitment_gadget(cs.namespace(|| "commitment computation"), value, r, params)?;
+        cs.enforce(
+            || "enforce opening",
+            |lc| lc + c.get_variable(),
+            |lc| lc + CS::one(),
+            |lc| lc + commitment.get_variable()
+        );
+        Ok(())
+    }
+}
+
+impl<E: JubjubEngine> CommitmentGadget<E> for PedersenCommitment {
+    fn commitment_gadget<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        value: &AllocatedNum<E>,
+        r: &[Boolean],
+        params: &E::Params
+    ) -> Result<AllocatedNum<E>, SynthesisError> {
+        assert!(r.len() == <Self as Commitment<E>>::RANDOMNESS_BITS, "randomness should have {} bits", <Self as Commitment<E>>::RANDOMNESS_BITS);
+        let bits = value.into_bits_le_strict(cs.namespace(|| "bitify value"))?;
+        let h = pedersen_hash::pedersen_hash(cs.namespace(|| "hash computation"), leaf_personalization(PEDERSEN_COMMITMENT_DOMAIN), &bits, params)?;
+        let rg = ecc::fixed_base_multiplication(cs.namespace(|| "randomness computation"), FixedGenerators::NoteCommitmentRandomness, r, params)?;
+        Ok(h.add(cs.namespace(|| "blinding"), &rg, params)?.get_x().clone())
+    }
+}
+
+impl<E: JubjubEngine> CommitmentGadget<E> for HashCommitment {
+    fn commitment_gadget<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        value: &AllocatedNum<E>,
+        r: &[Boolean],
+        params: &E::Params
+    ) -> Result<AllocatedNum<E>, SynthesisError> {
+        assert!(r.is_empty(), "hash commitments have no randomness");
+        let bits = value.into_bits_le_strict(cs.namespace(|| "bitify value"))?;
+        Ok(pedersen_hash::pedersen_hash(cs.namespace(|| "hash computation"), HASH_PERSONALIZATION, &bits, params)?.get_x().clone())
+    }
+}
+
+
+// v*G + r*H of committer, see FixedBaseCommitter: a fixed-base multiplication with 3 bit windows
+// for each and an addition, 255 bits at most each (85 windows). The bits are little-endian; the
+// caller range checks the value bits, e.g. by allocating 64 of them for an amount.
+pub fn commit<E: JubjubEngine, CS>(
+    mut cs: CS,
+    committer: &FixedBaseCommitter,
+    value_bits: &[Boolean],
+    randomness_bits: &[Boolean],
+    params: &E::Params
+) -> Result<ecc::EdwardsPoint<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(!value_bits.is_empty() && value_bits.len() <= 255, "value should have 1 to 255 bits");
+    assert!(!randomness_bits.is_empty() && randomness_bits.len() <= 255, "randomness should have 1 to 255 bits");
+
+    let v = ecc::fixed_base_multiplication(cs.namespace(|| "value computation"), committer.value_base, value_bits, params)?;
+    let r = ecc::fixed_base_multiplication(cs.namespace(|| "randomness computation"), committer.randomness_base, randomness_bits, params)?;
+    v.add(cs.namespace(|| "commitment computation"), &r, params)
+}
@@ -0,0 +1,153 @@
+use bellman::{SynthesisError, ConstraintSystem, LinearCombination};
+use pairing::{Field, PrimeField};
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+
+
+// Gadgets of fieldtools::less_than, is_equal and conditionally_select, see there. Constraint
+// counts include the allocation of the result:
+//   less_than             n + 3
+//   is_equal              3
+//   conditionally_select  1
+//   enforce_one_of        max(1, k - 1) for k values, no result
+
+
+// a < b as the top bit of a - b + 2^n. a and b should be range checked to n bits by the
+// caller, the system is unsatisfied if the difference does not fit n + 1 bits.
+pub fn less_than<E: JubjubEngine, CS>(
+    mut cs: CS,
+    a: &AllocatedNum<E>,
+    b: &AllocatedNum<E>,
+    n: usize
+) -> Result<Boolean, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    if n >= E::Fr::CAPACITY as usize {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    let mut shift = E::Fr::one();
+    (0..n).for_each(|_| shift.double());
+    let t = AllocatedNum::alloc(cs.namespace(|| "alloc a - b + 2^n"), || {
+        let mut res = a.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        res.sub_assign(&b.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+        res.add_assign(&shift);
+        Ok(res)
+    })?;
+    cs.enforce(
+        || "enforce a - b + 2^n",
+        |lc| lc + a.get_variable() - b.get_variable() + (shift, CS::one()),
+        |lc| lc + CS::one(),
+        |lc| lc + t.get_variable()
+    );
+    let bits = t.into_bits_le_limited(cs.namespace(|| "bitify a - b + 2^n"), n + 1)?;
+    Ok(bits[n].not())
+}
+
+// r with (a - b) inv = 1 - r and (a - b) r = 0: r = 1 iff a = b
+pub fn is_equal<E: JubjubEngine, CS>(
+    mut cs: CS,
+    a: &AllocatedNum<E>,
+    b: &AllocatedNum<E>
+) -> Result<Boolean, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let diff = match (a.get_value(), b.get_value()) {
+        (Some(x), Some(y)) => Some({ let mut d = x; d.sub_assign(&y); d }),
+        _ => None
+    };
+    let r = AllocatedBit::alloc(cs.namespace(|| "alloc result"), diff.map(|d| d.is_zero()))?;
+    let inv = AllocatedNum::alloc(cs.namespace(|| "alloc inverse"), || {
+        Ok(diff.ok_or(SynthesisError::AssignmentMissing)?.inverse().unwrap_or_else(E::Fr::zero))
+    })?;
+    cs.enforce(
+        || "enforce inverse",
+        |lc| lc + a.get_variable() - b.get_variable(),
+        |lc| lc + inv.get_variable(),
+        |lc| lc + CS::one() - r.get_variable()
+    );
+    cs.enforce(
+        || "enforce zero difference",
+        |lc| lc + a.get_variable() - b.get_variable(),
+        |lc| lc + r.get_variable(),
+        |lc| lc
+    );
+    Ok(Boolean::from(r))
+}
+
+// a if condition else b: (a - b) condition = res - b
+pub fn conditionally_select<E: JubjubEngine, CS>(
+    mut cs: CS,
+    a: &AllocatedNum<E>,
+    b: &AllocatedNum<E>,
+    condition: &Boolean
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let res = AllocatedNum::alloc(cs.namespace(|| "alloc result"), || {
+        let x = if condition.get_value().ok_or(SynthesisError::AssignmentMissing)? { a } else { b };
+        x.get_value().ok_or(SynthesisError::AssignmentMissing)
+    })?;
+    cs.enforce(
+        || "enforce selection",
+        |lc| lc + a.get_variable() - b.get_variable(),
+        |_| condition.lc(CS::one(), E::Fr::one()),
+        |lc| lc + res.get_variable() - b.get_variable()
+    );
+    Ok(res)
+}
+
+// x is one of values: the product of (x - v) over the values is zero. The partial products
+// are allocated, the last one is enforced to zero directly.
+pub fn enforce_one_of<E: JubjubEngine, CS>(
+    mut cs: CS,
+    x: &AllocatedNum<E>,
+    values: &[AllocatedNum<E>]
+) -> Result<(), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(!values.is_empty(), "x should be checked against at least one value");
+
+    let diff = |v: &AllocatedNum<E>| match (x.get_value(), v.get_value()) {
+        (Some(a), Some(b)) => Some({ let mut d = a; d.sub_assign(&b); d }),
+        _ => None
+    };
+    if values.len() == 1 {
+        cs.enforce(
+            || "enforce zero difference",
+            |lc| lc + x.get_variable() - values[0].get_variable(),
+            |lc| lc + CS::one(),
+            |lc| lc
+        );
+        return Ok(());
+    }
+
+    let mut acc = LinearCombination::<E>::zero() + x.get_variable() - values[0].get_variable();
+    let mut acc_value = diff(&values[0]);
+    for (i, v) in values.iter().enumerate().skip(1) {
+        if i + 1 == values.len() {
+            cs.enforce(
+                || "enforce zero product",
+                |_| acc.clone(),
+                |lc| lc + x.get_variable() - v.get_variable(),
+                |lc| lc
+            );
+            break;
+        }
+        acc_value = match (acc_value, diff(v)) {
+            (Some(a), Some(d)) => Some({ let mut p = a; p.mul_assign(&d); p }),
+            _ => None
+        };
+        let prod = AllocatedNum::alloc(cs.namespace(|| format!("alloc product [{}]", i)), || acc_value.ok_or(SynthesisError::AssignmentMissing))?;
+        cs.enforce(
+            || format!("enforce product [{}]", i),
+            |_| acc.clone(),
+            |lc| lc + x.get_variable() - v.get_variable(),
+            |lc| lc + prod.get_variable()
+        );
+        acc = LinearCombination::<E>::zero() + prod.get_variable();
+    }
+    Ok(())
+}
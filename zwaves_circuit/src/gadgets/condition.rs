@@ -0,0 +1,125 @@
+use bellman::{SynthesisError, ConstraintSystem};
+use pairing::Field;
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+use sapling_crypto::circuit::{blake2s, sha256};
+
+use crate::gadgets::transactions::{condition_hash, conditioned_owner};
+use crate::gadgets::bitify::from_bits_le_to_num;
+use crate::gadgets::multipack::pack_into_inputs;
+use zwaves_primitives::condition::{TIMELOCK_TYPE_ID, HASHLOCK_TYPE_ID, HASHLOCK_PERSONALIZATION, HASHLOCK_PREIMAGE_LEN};
+use zwaves_primitives::field;
+
+
+// Public values a condition may depend on. height should be range checked to 64 bits by the
+// caller, the same value is usually an input of the outer circuit, see alloc_height_input.
+pub struct ConditionContext<E: JubjubEngine> {
+    pub height: AllocatedNum<E>
+}
+
+// Current height as a public input, the verifier passes field::from_u64(height). Range checked
+// to 64 bits, as enforce_height_at_least expects.
+pub fn alloc_height_input<E: JubjubEngine, CS>(mut cs: CS, height: Option<u64>) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let res = AllocatedNum::alloc(cs.namespace(|| "alloc height"), || height.map(field::from_u64).ok_or(SynthesisError::AssignmentMissing))?;
+    res.inputize(cs.namespace(|| "inputize height"))?;
+    res.into_bits_le_limited(cs.namespace(|| "bitify height"), 64)?;
+    Ok(res)
+}
+
+// Enforces height >= unlock_height, both range checked to 64 bits by the caller.
+pub fn enforce_height_at_least<E: JubjubEngine, CS>(
+    mut cs: CS,
+    height: &AllocatedNum<E>,
+    unlock_height: &AllocatedNum<E>
+) -> Result<(), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    // height - unlock_height fits in 64 bits only if it is not negative
+    let diff = AllocatedNum::alloc(cs.namespace(|| "alloc height diff"), || {
+        let mut res = height.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        res.sub_assign(&unlock_height.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+        Ok(res)
+    })?;
+    cs.enforce(
+        || "enforce height diff",
+        |lc| lc + height.get_variable() - unlock_height.get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc + diff.get_variable()
+    );
+    diff.into_bits_le_limited(cs.namespace(|| "bitify height diff"), 64)?;
+    Ok(())
+}
+
+// Circuit side of condition::Condition: allocates the witness of the condition, enforces that it
+// holds in ctx and returns its params_hash. Witnesses are None in blank circuits.
+pub trait ConditionGadget<E: JubjubEngine> {
+    const TYPE_ID: u64;
+
+    fn synthesize<CS: ConstraintSystem<E>>(&self, cs: CS, ctx: &ConditionContext<E>) -> Result<AllocatedNum<E>, SynthesisError>;
+}
+
+
+pub struct TimelockGadget {
+    pub unlock_height: Option<u64>
+}
+
+impl<E: JubjubEngine> ConditionGadget<E> for TimelockGadget {
+    const TYPE_ID: u64 = TIMELOCK_TYPE_ID;
+
+    fn synthesize<CS: ConstraintSystem<E>>(&self, mut cs: CS, ctx: &ConditionContext<E>) -> Result<AllocatedNum<E>, SynthesisError> {
+        let unlock_height = AllocatedNum::alloc(cs.namespace(|| "alloc unlock height"), || self.unlock_height.map(field::from_u64).ok_or(SynthesisError::AssignmentMissing))?;
+        unlock_height.into_bits_le_limited(cs.namespace(|| "bitify unlock height"), 64)?;
+
+        enforce_height_at_least(cs.namespace(|| "unlocked"), &ctx.height, &unlock_height)?;
+
+        Ok(unlock_height)
+    }
+}
+
+
+// Circuit side of condition::Hashlock. The preimage is revealed: its bits are packed into public
+// inputs as Hashlock::preimage_inputs packs them, in the order the condition is synthesized.
+pub struct HashlockGadget {
+    pub preimage: Option<[u8; HASHLOCK_PREIMAGE_LEN]>
+}
+
+// sha256 takes and returns the bits of every byte from the highest, multipack and blake2s from
+// the lowest
+fn swap_bit_order(bits: &[Boolean]) -> Vec<Boolean> {
+    bits.chunks(8).flat_map(|byte| byte.iter().rev().cloned()).collect()
+}
+
+impl<E: JubjubEngine> ConditionGadget<E> for HashlockGadget {
+    const TYPE_ID: u64 = HASHLOCK_TYPE_ID;
+
+    fn synthesize<CS: ConstraintSystem<E>>(&self, mut cs: CS, _ctx: &ConditionContext<E>) -> Result<AllocatedNum<E>, SynthesisError> {
+        let preimage = (0..8 * HASHLOCK_PREIMAGE_LEN).map(|i| {
+            let bit = self.preimage.map(|p| (p[i / 8] >> (7 - i % 8)) & 1 == 1);
+            Ok(Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("preimage bit {}", i)), bit)?))
+        }).collect::<Result<Vec<_>, SynthesisError>>()?;
+        pack_into_inputs(cs.namespace(|| "reveal preimage"), &swap_bit_order(&preimage))?;
+
+        let digest = sha256::sha256(cs.namespace(|| "sha256 of preimage"), &preimage)?;
+        let params_hash = blake2s::blake2s(cs.namespace(|| "params hash computation"), &swap_bit_order(&digest), HASHLOCK_PERSONALIZATION)?;
+        from_bits_le_to_num(cs.namespace(|| "compress params hash"), &params_hash)
+    }
+}
+
+// Enforces the condition and returns the owner of the notes it locks, to be compared with
+// the owner field of the input note instead of pk.
+pub fn spend_condition<E: JubjubEngine, C: ConditionGadget<E>, CS>(
+    mut cs: CS,
+    condition: &C,
+    pk: &AllocatedNum<E>,
+    ctx: &ConditionContext<E>
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let params_hash = condition.synthesize(cs.namespace(|| "condition"), ctx)?;
+    let h = condition_hash(cs.namespace(|| "condition hash"), C::TYPE_ID, &params_hash)?;
+    conditioned_owner(cs.namespace(|| "owner"), pk, &h)
+}
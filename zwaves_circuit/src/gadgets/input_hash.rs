@@ -0,0 +1,105 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError, Variable, LinearCombination};
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::blake2s;
+
+use crate::gadgets::bitify::from_bits_le_to_num;
+use zwaves_primitives::input_hash::INPUT_HASH_PERSONALIZATION;
+
+
+// In-circuit counterpart of input_hash::input_hash
+pub fn input_hash<E: JubjubEngine, CS>(
+    mut cs: CS,
+    inputs: &[AllocatedNum<E>]
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let mut preimage = vec![];
+    for (i, x) in inputs.iter().enumerate() {
+        let bits = x.into_bits_le_strict(cs.namespace(|| format!("bitify input[{}]", i)))?;
+        let len = bits.len();
+        preimage.extend(bits);
+        preimage.extend((0..256-len).map(|_| Boolean::Constant(false)));
+    }
+
+    let bitrepr = blake2s::blake2s(cs.namespace(|| "input hash computation"), &preimage, INPUT_HASH_PERSONALIZATION)?;
+    from_bits_le_to_num(cs.namespace(|| "compress bitrepr"), &bitrepr)
+}
+
+
+// Runs the circuit with every alloc_input turned into a private variable, then exposes
+// the hash of those variables, in allocation order, as the only public input.
+pub struct HashedInputs<C>(pub C);
+
+impl<E: JubjubEngine, C: Circuit<E>> Circuit<E> for HashedInputs<C> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let inputs = {
+            let mut ns = cs.namespace(|| "circuit");
+            let mut collector = InputCollector { cs: &mut ns, inputs: vec![] };
+            self.0.synthesize(&mut collector)?;
+            collector.inputs
+        };
+
+        let mut nums = vec![];
+        for (i, (var, value)) in inputs.into_iter().enumerate() {
+            let num = AllocatedNum::alloc(cs.namespace(|| format!("input[{}]", i)), || value.ok_or(SynthesisError::AssignmentMissing))?;
+            cs.enforce(|| format!("input[{}] is the circuit input", i), |lc| lc + var, |lc| lc + CS::one(), |lc| lc + num.get_variable());
+            nums.push(num);
+        }
+
+        let hash = input_hash(cs.namespace(|| "input hash"), &nums)?;
+        hash.inputize(cs.namespace(|| "inputize input hash"))
+    }
+}
+
+
+struct InputCollector<'a, E: JubjubEngine, CS: ConstraintSystem<E> + 'a> {
+    cs: &'a mut CS,
+    inputs: Vec<(Variable, Option<E::Fr>)>
+}
+
+impl<'a, E: JubjubEngine, CS: ConstraintSystem<E>> ConstraintSystem<E> for InputCollector<'a, E, CS> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, annotation: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<E::Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.cs.alloc(annotation, f)
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, annotation: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<E::Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let mut value = None;
+        let var = self.cs.alloc(annotation, || {
+            let x = f()?;
+            value = Some(x);
+            Ok(x)
+        })?;
+        self.inputs.push((var, value));
+        Ok(var)
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>
+    {
+        self.cs.enforce(annotation, a, b, c)
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N) where NR: Into<String>, N: FnOnce() -> NR {
+        self.cs.get_root().push_namespace(name_fn)
+    }
+
+    fn pop_namespace(&mut self) {
+        self.cs.get_root().pop_namespace()
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
@@ -0,0 +1,138 @@
+use bellman::{SynthesisError, ConstraintSystem};
+use pairing::{Field, PrimeField};
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::num::{AllocatedNum, Num};
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+
+use zwaves_primitives::field;
+use zwaves_primitives::limbs::LIMB_BITS;
+
+
+fn limb_value<E: JubjubEngine>(x: &AllocatedNum<E>) -> Option<u64> {
+    x.get_value().and_then(|v| field::to_u64(&v))
+}
+
+// low = t + sign * 2^64 * bit, with low range checked to 64 bits. Only one bit value fits,
+// because |t| < 2^65.
+fn split_limb<E: JubjubEngine, CS>(
+    mut cs: CS,
+    t: Num<E>,
+    low_value: Option<u64>,
+    bit_value: Option<bool>,
+    negative: bool
+) -> Result<(AllocatedNum<E>, Boolean), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let low = AllocatedNum::alloc(cs.namespace(|| "alloc low"), || low_value.map(field::from_u64).ok_or(SynthesisError::AssignmentMissing))?;
+    low.into_bits_le_limited(cs.namespace(|| "bitify low"), LIMB_BITS)?;
+    let bit = Boolean::from(AllocatedBit::alloc(cs.namespace(|| "alloc carry"), bit_value)?);
+
+    let mut radix = field::from_u128::<E::Fr>(1 << 64);
+    if negative {
+        radix.negate();
+    }
+    let t = t.add_bool_with_coeff(CS::one(), &bit, radix);
+    cs.enforce(|| "enforce limb", |_| t.lc(E::Fr::one()), |lc| lc + CS::one(), |lc| lc + low.get_variable());
+    Ok((low, bit))
+}
+
+
+// Every limb fits in 64 bits. Inputs of add and sub should be checked, their outputs are.
+pub fn range_check<E: JubjubEngine, CS>(
+    mut cs: CS,
+    limbs: &[AllocatedNum<E>]
+) -> Result<(), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    for (i, x) in limbs.iter().enumerate() {
+        x.into_bits_le_limited(cs.namespace(|| format!("bitify limb [{}]", i)), LIMB_BITS)?;
+    }
+    Ok(())
+}
+
+// Same as limbs::add: the sum modulo 2^(64 n) and the carry out of the top limb
+pub fn add<E: JubjubEngine, CS>(
+    mut cs: CS,
+    a: &[AllocatedNum<E>],
+    b: &[AllocatedNum<E>]
+) -> Result<(Vec<AllocatedNum<E>>, Boolean), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(a.len() == b.len(), "operands should have the same number of limbs");
+
+    let mut carry = Boolean::constant(false);
+    let mut res = vec![];
+    for (i, (x, y)) in a.iter().zip(b.iter()).enumerate() {
+        let value = match (limb_value(x), limb_value(y), carry.get_value()) {
+            (Some(x), Some(y), Some(c)) => Some(x as u128 + y as u128 + c as u128),
+            _ => None
+        };
+        let t = (Num::from(x.clone()) + y.clone()).add_bool_with_coeff(CS::one(), &carry, E::Fr::one());
+        let (low, c) = split_limb(
+            cs.namespace(|| format!("limb [{}]", i)),
+            t,
+            value.map(|v| v as u64),
+            value.map(|v| v >> 64 == 1),
+            true
+        )?;
+        res.push(low);
+        carry = c;
+    }
+    Ok((res, carry))
+}
+
+// Same as limbs::sub: the difference modulo 2^(64 n) and the borrow, set iff a < b
+pub fn sub<E: JubjubEngine, CS>(
+    mut cs: CS,
+    a: &[AllocatedNum<E>],
+    b: &[AllocatedNum<E>]
+) -> Result<(Vec<AllocatedNum<E>>, Boolean), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(a.len() == b.len(), "operands should have the same number of limbs");
+
+    let mut borrow = Boolean::constant(false);
+    let mut res = vec![];
+    for (i, (x, y)) in a.iter().zip(b.iter()).enumerate() {
+        let value = match (limb_value(x), limb_value(y), borrow.get_value()) {
+            (Some(x), Some(y), Some(b)) => Some(x as i128 - y as i128 - b as i128),
+            _ => None
+        };
+        let mut minus_one = E::Fr::one();
+        minus_one.negate();
+        let t = (Num::from(x.clone()) - y.clone()).add_bool_with_coeff(CS::one(), &borrow, minus_one);
+        let (low, b) = split_limb(
+            cs.namespace(|| format!("limb [{}]", i)),
+            t,
+            value.map(|v| (v + (1 << 64)) as u64),
+            value.map(|v| v < 0),
+            false
+        )?;
+        res.push(low);
+        borrow = b;
+    }
+    Ok((res, borrow))
+}
+
+// Same as limbs::pack, for range checked limbs of a value narrower than the field
+pub fn pack<E: JubjubEngine, CS>(
+    mut cs: CS,
+    limbs: &[AllocatedNum<E>]
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(limbs.len() * LIMB_BITS < E::Fr::CAPACITY as usize, "value does not fit in the field");
+
+    let mut num = Num::<E>::zero();
+    let mut coeff = E::Fr::one();
+    let radix = field::from_u128::<E::Fr>(1 << 64);
+    for x in limbs.iter() {
+        num = num + (coeff, x.clone());
+        coeff.mul_assign(&radix);
+    }
+
+    let res = AllocatedNum::alloc(cs.namespace(|| "packed limbs"), || num.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+    cs.enforce(|| "checking resulting variable", |_| num.lc(E::Fr::one()), |lc| lc + CS::one(), |lc| lc + res.get_variable());
+    Ok(res)
+}
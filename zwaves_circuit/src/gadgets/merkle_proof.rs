@@ -0,0 +1,333 @@
+
+
+use bellman::{SynthesisError, ConstraintSystem};
+use pairing::{Field, PrimeField};
+
+use sapling_crypto::jubjub::{JubjubEngine};
+use sapling_crypto::circuit::{pedersen_hash};
+use sapling_crypto::circuit::num::{AllocatedNum};
+use sapling_crypto::circuit::boolean::{Boolean};
+
+use zwaves_primitives::fieldtools;
+use zwaves_primitives::pedersen_hasher::{MERKLE_MAX_DEPTH, MERKLE4_MAX_DEPTH};
+
+use crate::gadgets::compare::enforce_one_of;
+
+use std::slice;
+
+
+
+
+
+pub fn compress<E: JubjubEngine, CS>(
+    mut cs: CS,
+    personalization: pedersen_hash::Personalization,
+    left:&AllocatedNum<E>,
+    right:&AllocatedNum<E>,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+  let left_bits = left.into_bits_le_strict(cs.namespace(|| "left_bits <== bitify(left)"))?;
+  let right_bits = right.into_bits_le_strict(cs.namespace(|| "right_bits <== bitify(right)"))?;
+  compress_bits(cs, personalization, &left_bits, &right_bits, params)
+}
+
+// compress of the strict bit decompositions, one of them may be constant
+fn compress_bits<E: JubjubEngine, CS>(
+    mut cs: CS,
+    personalization: pedersen_hash::Personalization,
+    left_bits: &[Boolean],
+    right_bits: &[Boolean],
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+  let res = pedersen_hash::pedersen_hash(
+            cs.namespace(|| "res <== pedersen_hash(total_bits)"),
+            personalization,
+            left_bits.iter().chain(right_bits.iter()).cloned().collect::<Vec<_>>().as_slice(),
+            params
+        )?.get_x().clone();
+  Ok(res)
+}
+
+pub fn merkle_proof<E: JubjubEngine, CS>(
+    mut cs: CS,
+    proof: &[(AllocatedNum<E>, Boolean)],
+    leaf: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+  if proof.len() > MERKLE_MAX_DEPTH {
+    return Err(SynthesisError::Unsatisfiable);
+  }
+  let mut cur : AllocatedNum<E> = leaf.clone();
+
+  for (i, e) in proof.into_iter().enumerate() {
+    let cur_is_right = e.1.clone();
+    let path_element = e.0.clone();
+
+    let (xl, xr) = AllocatedNum::conditionally_reverse(
+        cs.namespace(|| format!("conditional reversal of preimage [{}]", i)),
+        &cur,
+        &path_element,
+        &cur_is_right
+    )?;
+
+    cur = compress(cs.namespace(|| format!("Merkle hash layer [{}]", i)), pedersen_hash::Personalization::MerkleTree(i as usize), &xl, &xr, params)?;
+  }
+  Ok(cur)
+}
+
+
+// The leaves of two trees of the same depth at one index, e.g. a note commitment and its
+// metadata in an auxiliary tree. The index bits are witnessed once and shared by both paths,
+// so the leaves can't be taken from different positions. Returns the two roots.
+pub fn merkle_proof_pair<E: JubjubEngine, CS>(
+    mut cs: CS,
+    index: &[Boolean],
+    first_sibling: &[AllocatedNum<E>],
+    first_leaf: &AllocatedNum<E>,
+    second_sibling: &[AllocatedNum<E>],
+    second_leaf: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<(AllocatedNum<E>, AllocatedNum<E>), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    if first_sibling.len() != index.len() || second_sibling.len() != index.len() {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    let path = |sibling: &[AllocatedNum<E>]| sibling.iter().cloned().zip(index.iter().cloned()).collect::<Vec<_>>();
+    let first_root = merkle_proof(cs.namespace(|| "first root"), &path(first_sibling), first_leaf, params)?;
+    let second_root = merkle_proof(cs.namespace(|| "second root"), &path(second_sibling), second_leaf, params)?;
+    Ok((first_root, second_root))
+}
+
+// Membership under any of several roots, e.g. the recent anchors a verifier accepts, without a
+// tree of past roots. The roots should be inputized by the caller; which of them the path leads
+// to is not revealed. Costs the path and roots.len() - 1 constraints.
+pub fn merkle_proof_any_root<E: JubjubEngine, CS>(
+    mut cs: CS,
+    proof: &[(AllocatedNum<E>, Boolean)],
+    leaf: &AllocatedNum<E>,
+    roots: &[AllocatedNum<E>],
+    params: &E::Params
+) -> Result<(), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let root = merkle_proof(cs.namespace(|| "root"), proof, leaf, params)?;
+    enforce_one_of(cs.namespace(|| "root is one of roots"), &root, roots)
+}
+
+pub fn merkle_proof_shifted<E: JubjubEngine, CS>(
+    mut cs: CS,
+    proof: &[(AllocatedNum<E>, Boolean)],
+    leaf: &AllocatedNum<E>,
+    shift: usize,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+where CS: ConstraintSystem<E>
+{
+    if shift + proof.len() > MERKLE_MAX_DEPTH {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    let mut cur : AllocatedNum<E> = leaf.clone();
+
+    for (i, e) in proof.into_iter().enumerate() {
+        let cur_is_right = e.1.clone();
+        let path_element = e.0.clone();
+
+        let (xl, xr) = AllocatedNum::conditionally_reverse(
+            cs.namespace(|| format!("conditional reversal of preimage [{}]", i)),
+            &cur,
+            &path_element,
+            &cur_is_right
+        )?;
+
+        cur = compress(cs.namespace(|| format!("Merkle hash layer [{}]", i)), pedersen_hash::Personalization::MerkleTree(i as usize + shift), &xl, &xr, params)?;
+    }
+    Ok(cur)
+}
+
+pub fn subtree_root<E: JubjubEngine, CS>(
+    mut cs: CS,
+    leaves: &[AllocatedNum<E>],
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+where CS: ConstraintSystem<E>
+{
+    if !leaves.len().is_power_of_two() || leaves.len().trailing_zeros() as usize > MERKLE_MAX_DEPTH {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    let mut level = leaves.to_vec();
+    let mut i = 0;
+    while level.len() > 1 {
+        level = level.chunks(2).enumerate().map(|(j, p)|
+            compress(cs.namespace(|| format!("subtree hash [{}][{}]", i, j)), pedersen_hash::Personalization::MerkleTree(i), &p[0], &p[1], params)
+        ).collect::<Result<Vec<_>, _>>()?;
+        i += 1;
+    }
+    Ok(level[0].clone())
+}
+
+// Root of the tree containing the 2^k block of leaves, proof is the path from level k to the root.
+pub fn subtree_proof<E: JubjubEngine, CS>(
+    mut cs: CS,
+    proof: &[(AllocatedNum<E>, Boolean)],
+    leaves: &[AllocatedNum<E>],
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+where CS: ConstraintSystem<E>
+{
+    let root = subtree_root(cs.namespace(|| "subtree root"), leaves, params)?;
+    merkle_proof_shifted(cs.namespace(|| "subtree merkle proof"), proof, &root, leaves.len().trailing_zeros() as usize, params)
+}
+
+// (old_root, new_root, next_index)
+pub type Insertion<E> = (AllocatedNum<E>, AllocatedNum<E>, AllocatedNum<E>);
+
+// Insertion of leaf at the next free index, the number of leaves of the tree before it. The path
+// is the bits of index, and every right sibling on it (the index bit is 0) is the empty subtree
+// root of its level, so the cell and everything to the right of it are empty in old_root: nothing
+// is overwritten. Returns (old_root, new_root, next_index) with next_index = index + 1, the
+// circuit-side counterpart of update_merkle_root_and_proof for a single leaf. A caller keeping
+// (root, next_index) as its state, from the empty tree and 0, can't skip cells either.
+// defaults are merkle_defaults, at least one per proof level.
+pub fn merkle_insert<E: JubjubEngine, CS>(
+    cs: CS,
+    index: &AllocatedNum<E>,
+    sibling: &[AllocatedNum<E>],
+    leaf: &AllocatedNum<E>,
+    defaults: &[E::Fr],
+    params: &E::Params
+) -> Result<Insertion<E>, SynthesisError>
+where CS: ConstraintSystem<E>
+{
+    merkle_insert_subtree(cs, index, sibling, slice::from_ref(leaf), defaults, params)
+}
+
+// Batch insertion of 2^k leaves at the next free index, which should be a multiple of 2^k
+// (blocks are padded with empty leaves, like the note pairs of utxo_accumulator), sibling is the
+// path from level k. The old and new roots share it: the empty subtree is replaced by the subtree
+// of leaves. Same roots as update_merkle_root_and_proof for an aligned index, next_index is
+// index + 2^k.
+pub fn merkle_insert_subtree<E: JubjubEngine, CS>(
+    mut cs: CS,
+    index: &AllocatedNum<E>,
+    sibling: &[AllocatedNum<E>],
+    leaves: &[AllocatedNum<E>],
+    defaults: &[E::Fr],
+    params: &E::Params
+) -> Result<Insertion<E>, SynthesisError>
+where CS: ConstraintSystem<E>
+{
+    if !leaves.len().is_power_of_two() || sibling.is_empty() {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    let k = leaves.len().trailing_zeros() as usize;
+    let depth = k + sibling.len();
+    if depth > MERKLE_MAX_DEPTH || defaults.len() < depth {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    // index < 2^depth and aligned to the block
+    let bits = index.into_bits_le_limited(cs.namespace(|| "bitify index"), depth)?;
+    for (i, b) in bits[..k].iter().enumerate() {
+        Boolean::enforce_equal(cs.namespace(|| format!("index bit [{}] is 0", i)), b, &Boolean::constant(false))?;
+    }
+    let proof = sibling.iter().cloned().zip(bits[k..].iter().cloned()).collect::<Vec<_>>();
+
+    for (i, (sibling, is_right)) in proof.iter().enumerate() {
+        let mut empty = defaults[k + i];
+        empty.negate();
+        cs.enforce(
+            || format!("right sibling [{}] is empty", i),
+            |lc| lc + CS::one() - &is_right.lc(CS::one(), E::Fr::one()),
+            |lc| lc + sibling.get_variable() + (empty, CS::one()),
+            |lc| lc
+        );
+    }
+
+    // The lowest level of the old path is (sibling, empty) for both index bits: for a 0 the
+    // sibling is the empty subtree too. So the empty subtree is a constant, not a witness.
+    let empty_bits = fieldtools::fr_to_repr_bool(&defaults[k]).into_iter().take(E::Fr::NUM_BITS as usize).map(Boolean::constant).collect::<Vec<_>>();
+    let lowest_bits = proof[0].0.into_bits_le_strict(cs.namespace(|| "bitify lowest sibling"))?;
+    let lowest = compress_bits(cs.namespace(|| "old root lowest level"), pedersen_hash::Personalization::MerkleTree(k), &lowest_bits, &empty_bits, params)?;
+    let old_root = merkle_proof_shifted(cs.namespace(|| "old root"), &proof[1..], &lowest, k + 1, params)?;
+    let new_root = subtree_proof(cs.namespace(|| "new root"), &proof, leaves, params)?;
+
+    let mut step = E::Fr::one();
+    (0..k).for_each(|_| step.double());
+    let next_index = AllocatedNum::alloc(cs.namespace(|| "alloc next index"), || {
+        let mut x = index.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        x.add_assign(&step);
+        Ok(x)
+    })?;
+    cs.enforce(
+        || "next index",
+        |lc| lc + index.get_variable() + (step, CS::one()),
+        |lc| lc + CS::one(),
+        |lc| lc + next_index.get_variable()
+    );
+    Ok((old_root, new_root, next_index))
+}
+
+
+// 4-ary counterparts of compress and merkle_proof, see pedersen_hasher::compress4. With Pedersen
+// a 4-ary level is three compressions where the two binary levels it replaces are two, so these
+// give the 4-ary proof layout and no saving: that needs a hash of four inputs in compress4.
+pub fn compress4<E: JubjubEngine, CS>(
+    mut cs: CS,
+    x: &[AllocatedNum<E>; 4],
+    level: usize,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    if level >= MERKLE4_MAX_DEPTH {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    let left = compress(cs.namespace(|| "left pair"), pedersen_hash::Personalization::MerkleTree(2 * level), &x[0], &x[1], params)?;
+    let right = compress(cs.namespace(|| "right pair"), pedersen_hash::Personalization::MerkleTree(2 * level), &x[2], &x[3], params)?;
+    compress(cs.namespace(|| "top"), pedersen_hash::Personalization::MerkleTree(2 * level + 1), &left, &right, params)
+}
+
+// b if bit else a
+fn select<E: JubjubEngine, CS>(cs: CS, a: &AllocatedNum<E>, b: &AllocatedNum<E>, bit: &Boolean) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    Ok(AllocatedNum::conditionally_reverse(cs, a, b, bit)?.0)
+}
+
+// Every level has the three other children left to right and the position of cur as two
+// bits, low bit first, the same bits as the binary path of merkle_proof.
+pub fn merkle_proof4<E: JubjubEngine, CS>(
+    mut cs: CS,
+    proof: &[([AllocatedNum<E>; 3], [Boolean; 2])],
+    leaf: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let mut cur = leaf.clone();
+
+    for (i, (s, b)) in proof.iter().enumerate() {
+        let mut cs = cs.namespace(|| format!("level [{}]", i));
+        let first = Boolean::and(cs.namespace(|| "position is 0"), &b[0].not(), &b[1].not())?;
+        let last = Boolean::and(cs.namespace(|| "position is 3"), &b[0], &b[1])?;
+
+        let c0 = select(cs.namespace(|| "child 0"), &s[0], &cur, &first)?;
+        let low = select(cs.namespace(|| "child 1 of a left position"), &s[0], &cur, &b[0])?;
+        let c1 = select(cs.namespace(|| "child 1"), &low, &s[1], &b[1])?;
+        let high = select(cs.namespace(|| "child 2 of a right position"), &cur, &s[2], &b[0])?;
+        let c2 = select(cs.namespace(|| "child 2"), &s[1], &high, &b[1])?;
+        let c3 = select(cs.namespace(|| "child 3"), &s[2], &cur, &last)?;
+
+        cur = compress4(cs.namespace(|| "hash"), &[c0, c1, c2, c3], i, params)?;
+    }
+    Ok(cur)
+}
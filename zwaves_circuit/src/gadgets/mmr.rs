@@ -0,0 +1,74 @@
+use bellman::{SynthesisError, ConstraintSystem};
+use pairing::{Field, PrimeField};
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::Boolean;
+
+use crate::gadgets::merkle_proof::{compress, merkle_proof};
+use zwaves_primitives::mmr::{MMR_BAG_PERSONALIZATION, peak_heights};
+
+
+fn constant<E: JubjubEngine, CS>(mut cs: CS, value: E::Fr) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let res = AllocatedNum::alloc(cs.namespace(|| "alloc constant"), || Ok(value))?;
+    let mut neg = value;
+    neg.negate();
+    cs.enforce(|| "enforce constant", |lc| lc + res.get_variable() + (neg, CS::one()), |lc| lc + CS::one(), |lc| lc);
+    Ok(res)
+}
+
+// Same as mmr::bag_peaks. The size is a constant of the circuit: it fixes the number and
+// heights of the peaks, so a circuit is built for one MMR size. Peaks of another number are
+// Unsatisfiable, as a path out of the depth is for merkle_proof.
+pub fn mmr_root<E: JubjubEngine, CS>(
+    mut cs: CS,
+    peaks: &[AllocatedNum<E>],
+    size: u64,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    if peaks.len() != peak_heights(size).len() {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    let mut bag = match peaks.last() {
+        Some(p) => p.clone(),
+        None => constant(cs.namespace(|| "empty bag"), E::Fr::zero())?
+    };
+    for (i, p) in peaks.iter().enumerate().rev().skip(1) {
+        bag = compress(cs.namespace(|| format!("bag peak [{}]", i)), MMR_BAG_PERSONALIZATION, p, &bag, params)?;
+    }
+    let size = constant(cs.namespace(|| "size"), E::Fr::from_repr(size.into()).unwrap())?;
+    compress(cs.namespace(|| "root"), MMR_BAG_PERSONALIZATION, &size, &bag, params)
+}
+
+// Inclusion of leaf in an MMR of the given size, the counterpart of mmr::verify_proof. proof is
+// the path of the leaf inside its peak; peak heights are distinct, so the path length selects
+// the peak, which is enforced equal to the recomputed one. Returns the root.
+pub fn mmr_inclusion<E: JubjubEngine, CS>(
+    mut cs: CS,
+    proof: &[(AllocatedNum<E>, Boolean)],
+    leaf: &AllocatedNum<E>,
+    peaks: &[AllocatedNum<E>],
+    size: u64,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let pos = match peak_heights(size).iter().position(|&h| h == proof.len()) {
+        Some(pos) if pos < peaks.len() => pos,
+        _ => return Err(SynthesisError::Unsatisfiable)
+    };
+
+    let peak = merkle_proof(cs.namespace(|| "peak"), proof, leaf, params)?;
+    cs.enforce(
+        || "enforce peak",
+        |lc| lc + peak.get_variable() - peaks[pos].get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc
+    );
+    mmr_root(cs.namespace(|| "mmr root"), peaks, size, params)
+}
@@ -0,0 +1,19 @@
+#[cfg(test)]
+pub mod test;
+
+pub mod merkle_proof;
+pub mod transactions;
+pub mod bitify;
+pub mod multipack;
+pub mod input_hash;
+pub mod audit;
+pub mod mmr;
+pub mod limbs;
+pub mod condition;
+pub mod uint64;
+pub mod compare;
+pub mod pedersen_segments;
+pub mod commitment;
+
+#[cfg(feature = "recursion")]
+pub mod recursion;
@@ -0,0 +1,42 @@
+use pairing::{Engine, Field, PrimeField};
+
+use bellman::{SynthesisError, ConstraintSystem};
+
+use sapling_crypto::circuit::num::{AllocatedNum, Num};
+use sapling_crypto::circuit::boolean::Boolean;
+
+
+// Gadget of zwaves_primitives::multipack::pack_bits_to_fields, the same chunks of CAPACITY bits
+pub fn pack_bits_to_fields<E: Engine, CS>(
+    mut cs: CS,
+    bits: &[Boolean]
+) -> Result<Vec<AllocatedNum<E>>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    bits.chunks(E::Fr::CAPACITY as usize).enumerate().map(|(i, chunk)| {
+        let mut num = Num::<E>::zero();
+        let mut coeff = E::Fr::one();
+        for bit in chunk.iter() {
+            num = num.add_bool_with_coeff(CS::one(), bit, coeff);
+            coeff.double();
+        }
+
+        let res = AllocatedNum::alloc(cs.namespace(|| format!("packed chunk {}", i)), || num.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+        cs.enforce(|| format!("packing constraint {}", i), |_| num.lc(E::Fr::one()), |lc| lc + CS::one(), |lc| lc + res.get_variable());
+        Ok(res)
+    }).collect()
+}
+
+// The packed bits as public inputs, in chunk order
+pub fn pack_into_inputs<E: Engine, CS>(
+    mut cs: CS,
+    bits: &[Boolean]
+) -> Result<Vec<AllocatedNum<E>>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let packed = pack_bits_to_fields(cs.namespace(|| "pack bits"), bits)?;
+    for (i, x) in packed.iter().enumerate() {
+        x.inputize(cs.namespace(|| format!("inputize chunk {}", i)))?;
+    }
+    Ok(packed)
+}
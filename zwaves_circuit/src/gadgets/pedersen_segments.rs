@@ -0,0 +1,52 @@
+use bellman::{SynthesisError, ConstraintSystem};
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::ecc::{EdwardsPoint, MontgomeryPoint};
+use sapling_crypto::circuit::lookup::lookup3_xy_with_conditional_negation;
+use sapling_crypto::pedersen_hash::Personalization;
+
+use zwaves_primitives::pedersen_segments::SegmentedParams;
+
+
+// Gadget of pedersen_segments::pedersen_hash, the steps of circuit::pedersen_hash with the
+// generators of sp. Constraints: 2 per chunk, 3 per chunk after the first of its segment and
+// the conversion and addition of every segment after the first.
+pub fn pedersen_hash<E: JubjubEngine, CS>(
+    mut cs: CS,
+    personalization: Personalization,
+    bits: &[Boolean],
+    sp: &SegmentedParams<E>,
+    params: &E::Params
+) -> Result<EdwardsPoint<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let personalization = personalization.get_bits().into_iter().map(Boolean::constant).collect::<Vec<_>>();
+    assert!(personalization.len() + bits.len() <= sp.max_bits(), "input is longer than {} bits", sp.max_bits());
+
+    let all = personalization.iter().chain(bits.iter()).cloned().collect::<Vec<_>>();
+    let mut edwards_result: Option<EdwardsPoint<E>> = None;
+    for (i, (segment, windows)) in all.chunks(sp.segmenting().segment_bits()).zip(sp.circuit_generators().iter()).enumerate() {
+        let mut segment_result: Option<MontgomeryPoint<E>> = None;
+        for (j, (chunk, window)) in segment.chunks(3).zip(windows.iter()).enumerate() {
+            let bit = |k: usize| chunk.get(k).cloned().unwrap_or_else(|| Boolean::constant(false));
+            let (x, y) = lookup3_xy_with_conditional_negation(
+                cs.namespace(|| format!("segment {}, window {}", i, j)),
+                &[bit(0), bit(1), bit(2)],
+                window
+            )?;
+            let tmp = MontgomeryPoint::interpret_unchecked(x, y);
+            segment_result = Some(match segment_result {
+                None => tmp,
+                Some(acc) => tmp.add(cs.namespace(|| format!("addition of segment {}, window {}", i, j)), &acc, params)?
+            });
+        }
+
+        let segment_result = segment_result.unwrap().into_edwards(cs.namespace(|| format!("conversion of segment {} into edwards", i)), params)?;
+        edwards_result = Some(match edwards_result {
+            None => segment_result,
+            Some(acc) => segment_result.add(cs.namespace(|| format!("addition of segment {} to accumulator", i)), &acc, params)?
+        });
+    }
+    Ok(edwards_result.unwrap())
+}
@@ -0,0 +1,58 @@
+use bellman::{ConstraintSystem, SynthesisError};
+use bellman::groth16::Proof;
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+use sapling_crypto::circuit::blake2s;
+
+use crate::gadgets::bitify::from_bits_le_to_num;
+use zwaves_primitives::fieldtools;
+use zwaves_primitives::recursion::DEFERRED_PERSONALIZATION;
+use zwaves_primitives::verifier::PROOF_LEN;
+
+
+// Inner proof as private bits: bytes in the serialized order, bits of a byte little-endian.
+// The encoding is not checked here, verify_deferred parses the proof outside the circuit.
+pub fn witness_proof<E: JubjubEngine, CS>(
+    mut cs: CS,
+    proof: Option<&Proof<E>>
+) -> Result<Vec<Boolean>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let data = proof.map(|p| {
+        let mut data = vec![];
+        p.write(&mut data).unwrap();
+        data
+    });
+
+    (0..PROOF_LEN * 8).map(|i| {
+        let bit = data.as_ref().map(|d| (d[i / 8] >> (i % 8)) & 1 == 1);
+        Ok(Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("proof bit {}", i)), bit)?))
+    }).collect()
+}
+
+
+// In-circuit counterpart of recursion::deferred_hash, the vk digest is a constant of the circuit
+pub fn deferred_proof<E: JubjubEngine, CS>(
+    mut cs: CS,
+    vk_digest: &E::Fr,
+    proof: &[Boolean],
+    inputs: &[AllocatedNum<E>]
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(proof.len() == PROOF_LEN * 8, "proof should be {} bits", PROOF_LEN * 8);
+
+    let mut preimage = fieldtools::fr_to_repr_bool(vk_digest).into_iter().map(Boolean::constant).collect::<Vec<_>>();
+    preimage.extend(proof.iter().cloned());
+    for (i, x) in inputs.iter().enumerate() {
+        let bits = x.into_bits_le_strict(cs.namespace(|| format!("bitify input[{}]", i)))?;
+        let len = bits.len();
+        preimage.extend(bits);
+        preimage.extend((0..256-len).map(|_| Boolean::Constant(false)));
+    }
+
+    let bitrepr = blake2s::blake2s(cs.namespace(|| "deferred hash computation"), &preimage, DEFERRED_PERSONALIZATION)?;
+    from_bits_le_to_num(cs.namespace(|| "compress bitrepr"), &bitrepr)
+}
@@ -0,0 +1,49 @@
+use bellman::ConstraintSystem;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::test::TestConstraintSystem;
+
+use pairing::{Field, PrimeField};
+use pairing::bls12_381::{Bls12, Fr};
+
+use rand::os::OsRng;
+use rand::Rng;
+
+use crate::gadgets::bitify;
+use zwaves_primitives::fieldtools::{self, LeBits};
+
+
+fn values(bits: &[Boolean]) -> LeBits {
+    LeBits(bits.iter().map(|b| b.get_value().unwrap()).collect())
+}
+
+// bits of the gadget and whether the constraint system is satisfied
+fn circuit_bits(x: Fr, n: Option<usize>) -> (LeBits, bool) {
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let num = AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(x)).unwrap();
+    let bits = match n {
+        Some(n) => bitify::to_bits_le(cs.namespace(|| "bits"), &num, n).unwrap(),
+        None => bitify::to_bits_le_strict(cs.namespace(|| "bits"), &num).unwrap()
+    };
+    (values(&bits), cs.is_satisfied())
+}
+
+#[test]
+fn test_native_and_circuit_bits_agree() {
+    let mut rng = OsRng::new().unwrap();
+    let mut minus_one = Fr::one();
+    minus_one.negate();
+    for x in [rng.gen::<Fr>(), Fr::zero(), minus_one].iter() {
+        assert!(circuit_bits(*x, None) == (fieldtools::to_bits_le_strict(x), true), "Strict decompositions should agree");
+    }
+
+    let fits = Fr::from_repr(u64::max_value().into()).unwrap();
+    assert!(circuit_bits(fits, Some(64)) == (fieldtools::to_bits_le(&fits, 64).unwrap(), true));
+
+    let mut too_big = fits;
+    too_big.add_assign(&Fr::one());
+    assert!(fieldtools::to_bits_le(&too_big, 64).is_err(), "Value out of 64 bits should be rejected natively");
+    assert!(!circuit_bits(too_big, Some(64)).1, "and in the circuit");
+
+    assert!(fieldtools::to_bits_le(&fits, Fr::NUM_BITS as usize).is_err(), "Non-unique decomposition should be refused");
+}
@@ -0,0 +1,65 @@
+use bellman::ConstraintSystem;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::{Boolean, AllocatedBit, u64_into_boolean_vec_le};
+use sapling_crypto::circuit::test::TestConstraintSystem;
+use sapling_crypto::jubjub::fs::Fs;
+
+use pairing::bls12_381::{Bls12, Fr};
+
+use rand::os::OsRng;
+use rand::Rng;
+
+use zwaves_primitives::commitment::{Commitment, PedersenCommitment, HashCommitment, VALUE_COMMITTER};
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use crate::gadgets::commitment::{CommitmentGadget, commit};
+
+
+// Constraints of an opening, when it is satisfied
+fn check<C: CommitmentGadget<Bls12>>(rng: &mut OsRng) -> usize {
+    let params = &*JUBJUB_PARAMS;
+    let value = rng.gen::<Fr>();
+    let (c, r) = C::commit(&value, rng, params);
+
+    let exec = |opened: Fr| {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let c = AllocatedNum::alloc(cs.namespace(|| "commitment"), || Ok(c)).unwrap();
+        let v = AllocatedNum::alloc(cs.namespace(|| "value"), || Ok(opened)).unwrap();
+        let bits = C::alloc_randomness(cs.namespace(|| "randomness"), Some(&r)).unwrap();
+        let computed = C::commitment_gadget(cs.namespace(|| "computed"), &v, &bits, params).unwrap();
+        assert!(computed.get_value() == Some(C::open(&opened, &r, params)), "Gadget should commit as the native scheme");
+        C::enforce_opening(cs.namespace(|| "opening"), &c, &v, &bits, params).unwrap();
+        (cs.is_satisfied(), cs.num_constraints())
+    };
+
+    let (satisfied, n) = exec(value);
+    assert!(satisfied);
+    assert!(!exec(rng.gen()).0, "Opening to another value should not satisfy");
+    n
+}
+
+#[test]
+fn test_commitment_gadgets() {
+    let mut rng = OsRng::new().unwrap();
+    let pedersen = check::<PedersenCommitment>(&mut rng);
+    let hash = check::<HashCommitment>(&mut rng);
+    assert!(hash < pedersen);
+}
+
+#[test]
+fn test_value_commitment_gadget() {
+    let params = &*JUBJUB_PARAMS;
+    let mut rng = OsRng::new().unwrap();
+    let value = rng.gen::<u64>();
+    let r = rng.gen::<Fs>();
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let value_bits = u64_into_boolean_vec_le(cs.namespace(|| "value"), Some(value)).unwrap();
+    let r_bits = <PedersenCommitment as Commitment<Bls12>>::randomness_bits(&r).iter().enumerate()
+        .map(|(i, &b)| Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("r bit[{}]", i)), Some(b)).unwrap()))
+        .collect::<Vec<_>>();
+    let cv = commit(cs.namespace(|| "cv"), &VALUE_COMMITTER, &value_bits, &r_bits, params).unwrap();
+
+    let expected = VALUE_COMMITTER.commit_u64::<Bls12>(value, &r, params).into_xy();
+    assert!(cs.is_satisfied());
+    assert!(cv.get_x().get_value() == Some(expected.0) && cv.get_y().get_value() == Some(expected.1), "Gadget should commit as FixedBaseCommitter");
+}
@@ -0,0 +1,87 @@
+use bellman::ConstraintSystem;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+use sapling_crypto::circuit::test::TestConstraintSystem;
+
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField};
+
+use rand::os::OsRng;
+use rand::Rng;
+
+use crate::gadgets::compare;
+use zwaves_primitives::fieldtools;
+use zwaves_primitives::field;
+
+
+fn alloc(cs: &mut TestConstraintSystem<Bls12>, name: &str, x: Fr) -> AllocatedNum<Bls12> {
+    AllocatedNum::alloc(cs.namespace(|| name), || Ok(x)).unwrap()
+}
+
+// result, satisfied, constraints of the gadget
+fn circuit_less_than(a: Fr, b: Fr, n: usize) -> (bool, bool, usize) {
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let (x, y) = (alloc(&mut cs, "a", a), alloc(&mut cs, "b", b));
+    let r = compare::less_than(cs.namespace(|| "lt"), &x, &y, n).unwrap();
+    (r.get_value().unwrap(), cs.is_satisfied(), cs.num_constraints())
+}
+
+#[test]
+fn test_native_and_circuit_comparisons_agree() {
+    let mut rng = OsRng::new().unwrap();
+    let (a, b) = (rng.gen::<u64>(), rng.gen::<u64>());
+    for (x, y) in [(a, b), (b, a), (a, a), (0, u64::max_value())].iter() {
+        let (x, y) = (field::from_u64::<Fr>(*x), field::from_u64::<Fr>(*y));
+        assert!(circuit_less_than(x, y, 64) == (fieldtools::less_than(&x, &y, 64).unwrap(), true, 64 + 3));
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let (u, v) = (alloc(&mut cs, "a", x), alloc(&mut cs, "b", y));
+        let eq = compare::is_equal(cs.namespace(|| "eq"), &u, &v).unwrap();
+        assert!(eq.get_value() == Some(fieldtools::is_equal(&x, &y)) && cs.is_satisfied() && cs.num_constraints() == 3);
+    }
+
+    // a - b + 2^n should fit n + 1 bits, the operands are not checked by themselves
+    let (top, max) = (field::from_u128::<Fr>(1 << 64), field::from_u64::<Fr>(u64::max_value()));
+    let mut below_zero = Fr::zero();
+    below_zero.sub_assign(&Fr::one());
+    for (x, y) in [(top, Fr::one()), (Fr::zero(), top), (top, max), (max, top), (top, Fr::zero()), (Fr::zero(), field::from_u128(1 << 65)), (below_zero, Fr::zero())].iter() {
+        let native = fieldtools::less_than(x, y, 64);
+        let (r, satisfied, _) = circuit_less_than(*x, *y, 64);
+        assert!(native.is_ok() == satisfied, "Native and circuit should reject the same operands");
+        assert!(native.map_or(true, |lt| lt == r));
+    }
+    assert!(fieldtools::less_than(&top, &Fr::zero(), 64).is_err(), "Difference of 2^n should be rejected");
+
+    let n = Fr::CAPACITY as usize - 1;
+    assert!(circuit_less_than(Fr::zero(), Fr::one(), n) == (fieldtools::less_than(&Fr::zero(), &Fr::one(), n).unwrap(), true, n + 3));
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let (x, y) = (alloc(&mut cs, "a", Fr::one()), alloc(&mut cs, "b", Fr::zero()));
+    assert!(compare::less_than(cs.namespace(|| "lt"), &x, &y, n + 1).is_err());
+    assert!(fieldtools::less_than(&Fr::one(), &Fr::zero(), n + 1).is_err());
+
+    // a prover claiming a != b for equal values
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let x = rng.gen::<Fr>();
+    let (u, v) = (alloc(&mut cs, "a", x), alloc(&mut cs, "b", x));
+    compare::is_equal(cs.namespace(|| "eq"), &u, &v).unwrap();
+    cs.set("eq/alloc result/boolean", Fr::zero());
+    assert!(!cs.is_satisfied(), "Wrong equality should be rejected");
+}
+
+#[test]
+fn test_native_and_circuit_select_agree() {
+    let mut rng = OsRng::new().unwrap();
+    let (a, b) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+    for condition in [false, true].iter() {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let (x, y) = (alloc(&mut cs, "a", a), alloc(&mut cs, "b", b));
+        let bit = Boolean::from(AllocatedBit::alloc(cs.namespace(|| "condition"), Some(*condition)).unwrap());
+        let before = cs.num_constraints();
+        let res = compare::conditionally_select(cs.namespace(|| "select"), &x, &y, &bit).unwrap();
+        assert!(res.get_value() == Some(fieldtools::conditionally_select(&a, &b, *condition)));
+        assert!(cs.is_satisfied() && cs.num_constraints() - before == 1);
+
+        let not = compare::conditionally_select(cs.namespace(|| "select not"), &x, &y, &bit.not()).unwrap();
+        assert!(not.get_value() == Some(fieldtools::conditionally_select(&a, &b, !*condition)) && cs.is_satisfied());
+    }
+}
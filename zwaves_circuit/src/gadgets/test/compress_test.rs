@@ -10,8 +10,8 @@ use pairing::bls12_381::{Bls12, Fr};
 use rand::os::OsRng;
 use rand::Rng;
 
-use crate::circuit::merkle_proof;
-use crate::pedersen_hasher;
+use crate::gadgets::merkle_proof;
+use zwaves_primitives::pedersen_hasher;
 
 #[derive(Clone)]
 pub struct CompressDemo<E: JubjubEngine> {
@@ -0,0 +1,660 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use bellman::groth16::{generate_random_parameters, create_random_proof};
+use sapling_crypto::jubjub::JubjubBls12;
+use sapling_crypto::pedersen_hash::Personalization;
+use sapling_crypto::circuit::{ecc, pedersen_hash};
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::test::TestConstraintSystem;
+
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+use pairing::bls12_381::{Bls12, Fr};
+
+use rand::os::OsRng;
+use rand::Rng;
+use lazy_static::lazy_static;
+
+use zwaves_primitives::{pedersen_hasher, input_hash, verifier};
+use zwaves_primitives::transactions::{self, NoteData, AccountData};
+use crate::gadgets::{merkle_proof, transactions as gadgets};
+use crate::gadgets::input_hash::{self as input_hash_gadgets, HashedInputs};
+use crate::gadgets::audit as audit_gadgets;
+use crate::gadgets::mmr as mmr_gadgets;
+use crate::gadgets::limbs as limbs_gadgets;
+use zwaves_primitives::audit;
+
+
+const ROUNDS: usize = 4;
+
+lazy_static! {
+    static ref JUBJUB_PARAMS: JubjubBls12 = JubjubBls12::new();
+}
+
+
+// The constraint system of a gadget should not depend on its witness: the first synthesis
+// stores the hash of cs, the next ones compare with it.
+pub fn check_cs_hash(cs_hash: &mut Option<String>, cs: &TestConstraintSystem<Bls12>) {
+    let h = cs.hash();
+    match cs_hash {
+        None => *cs_hash = Some(h),
+        Some(expected) => assert!(*expected == h, "Constraint system depends on inputs")
+    }
+}
+
+// Synthesizes the gadget on every input set and checks that it is satisfied, that its output
+// is equal to the native hasher, and that the constraint system is the same for all inputs.
+pub fn check_gadget_consistency<N, G>(hasher: N, gadget: G, inputs: &[Vec<Fr>])
+    where N: Fn(&[Fr]) -> Fr,
+    G: Fn(&mut TestConstraintSystem<Bls12>, &[AllocatedNum<Bls12>]) -> Result<AllocatedNum<Bls12>, SynthesisError>
+{
+    let mut cs_hash = None;
+
+    for (i, input) in inputs.iter().enumerate() {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let vars = input.iter().enumerate()
+            .map(|(j, &x)| AllocatedNum::alloc(cs.namespace(|| format!("input[{}]", j)), || Ok(x)))
+            .collect::<Result<Vec<_>, _>>().unwrap();
+
+        let res = gadget(&mut cs, &vars).unwrap();
+
+        if !cs.is_satisfied() {
+            let not_satisfied = cs.which_is_unsatisfied().unwrap_or("");
+            assert!(false, "Constraints not satisfied for input set {}: {}", i, not_satisfied);
+        }
+        assert!(res.get_value().unwrap() == hasher(input), "Gadget result differs from native for input set {}", i);
+
+        check_cs_hash(&mut cs_hash, &cs);
+    }
+}
+
+// Random inputs, the i-th element of each set fits into bits[i] bits.
+pub fn random_inputs<R: Rng>(rng: &mut R, bits: &[usize], count: usize) -> Vec<Vec<Fr>> {
+    (0..count).map(|_| bits.iter().map(|&n| {
+        let mut repr = rng.gen::<Fr>().into_repr();
+        if n < Fr::NUM_BITS as usize {
+            repr.shr(Fr::NUM_BITS - n as u32);
+        }
+        Fr::from_repr(repr).unwrap()
+    }).collect()).collect()
+}
+
+
+#[test]
+fn test_hash_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    check_gadget_consistency(
+        |x| pedersen_hasher::hash::<Bls12>(&x[0], pedersen_hasher::HASH_PERSONALIZATION, &JUBJUB_PARAMS),
+        |cs, x| {
+            let bits = x[0].into_bits_le_strict(cs.namespace(|| "bitify"))?;
+            Ok(pedersen_hash::pedersen_hash(cs.namespace(|| "hash"), Personalization::NoteCommitment, &bits, &*JUBJUB_PARAMS)?.get_x().clone())
+        },
+        &random_inputs(rng, &[255], ROUNDS)
+    );
+}
+
+#[test]
+fn test_compress_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    let level = rng.gen_range(0, pedersen_hasher::MERKLE_MAX_DEPTH);
+    check_gadget_consistency(
+        |x| pedersen_hasher::compress::<Bls12>(&x[0], &x[1], Personalization::MerkleTree(level), &JUBJUB_PARAMS),
+        |cs, x| merkle_proof::compress(cs.namespace(|| "compress"), Personalization::MerkleTree(level), &x[0], &x[1], &*JUBJUB_PARAMS),
+        &random_inputs(rng, &[255, 255], ROUNDS)
+    );
+}
+
+#[test]
+fn test_merkle_insert_consistency() {
+    const DEPTH: usize = 6;
+    let rng = &mut OsRng::new().unwrap();
+    let defaults = pedersen_hasher::merkle_defaults::<Bls12>(DEPTH + 1, &JUBJUB_PARAMS);
+    let mut cs_hash = None;
+
+    // past ROUNDS: the last cell, a non-empty right sibling, an index out of the tree
+    for round in 0..ROUNDS + 3 {
+        let index = if round == ROUNDS { (1 << DEPTH) - 1 } else { (rng.gen::<u64>() % (1 << DEPTH)) & if round > ROUNDS { !1 } else { !0 } };
+        let leaf = rng.gen::<Fr>();
+        let sibling = (0..DEPTH).map(|i| if (index >> i) & 1 == 1 { rng.gen::<Fr>() } else { defaults[i] }).collect::<Vec<_>>();
+
+        let old_root = pedersen_hasher::merkle_root::<Bls12>(&sibling, index, &Fr::zero(), &JUBJUB_PARAMS);
+        let new_root = pedersen_hasher::merkle_root::<Bls12>(&sibling, index, &leaf, &JUBJUB_PARAMS);
+
+        let mut wrong_sibling = sibling.clone();
+        if round == ROUNDS + 1 {
+            wrong_sibling[0] = rng.gen::<Fr>();
+        }
+        let witness_index = if round == ROUNDS + 2 { index + (1 << DEPTH) } else { index };
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let index_var = AllocatedNum::alloc(cs.namespace(|| "index"), || Ok(Fr::from_str(&witness_index.to_string()).unwrap())).unwrap();
+        let sibling_vars = wrong_sibling.iter().enumerate()
+            .map(|(i, s)| AllocatedNum::alloc(cs.namespace(|| format!("sibling[{}]", i)), || Ok(*s)).unwrap())
+            .collect::<Vec<_>>();
+        let leaf_var = AllocatedNum::alloc(cs.namespace(|| "leaf"), || Ok(leaf)).unwrap();
+        let (old, new, next) = merkle_proof::merkle_insert(cs.namespace(|| "insert"), &index_var, &sibling_vars, &leaf_var, &defaults, &*JUBJUB_PARAMS).unwrap();
+
+        if round == ROUNDS + 1 {
+            assert!(!cs.is_satisfied(), "Non-empty right sibling should be rejected");
+        } else if round == ROUNDS + 2 {
+            assert!(!cs.is_satisfied(), "Index out of the tree should be rejected");
+        } else {
+            assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+            assert!(old.get_value().unwrap() == old_root, "Old root differs from native");
+            assert!(new.get_value().unwrap() == new_root, "New root differs from native");
+            assert!(next.get_value().unwrap() == Fr::from_str(&(index + 1).to_string()).unwrap(), "Next index should follow the leaf");
+        }
+
+        check_cs_hash(&mut cs_hash, &cs);
+    }
+}
+
+#[test]
+fn test_merkle_insert_subtree_consistency() {
+    const DEPTH: usize = 6;
+    const K: usize = 2;
+    let rng = &mut OsRng::new().unwrap();
+    let defaults = pedersen_hasher::merkle_defaults::<Bls12>(DEPTH + 1, &JUBJUB_PARAMS);
+    let mut cs_hash = None;
+
+    // the last round fills the tree up, the one before takes an unaligned index
+    for round in 0..ROUNDS + 2 {
+        let block = if round == ROUNDS + 1 { (1 << (DEPTH - K)) - 1 } else { rng.gen::<u64>() % (1 << (DEPTH - K)) };
+        let index = (block << K) | if round == ROUNDS { 1 } else { 0 };
+        let leaves = (0..1 << K).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let sibling = (0..DEPTH).map(|i| if (index >> i) & 1 == 1 { rng.gen::<Fr>() } else { defaults[i] }).collect::<Vec<_>>();
+
+        let old_root = pedersen_hasher::merkle_root_from_subtree::<Bls12>(&sibling[K..], block, &defaults[K], K, &JUBJUB_PARAMS).unwrap();
+        let subtree = pedersen_hasher::subtree_root::<Bls12>(&leaves, &JUBJUB_PARAMS).unwrap();
+        let new_root = pedersen_hasher::merkle_root_from_subtree::<Bls12>(&sibling[K..], block, &subtree, K, &JUBJUB_PARAMS).unwrap();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let index_var = AllocatedNum::alloc(cs.namespace(|| "index"), || Ok(Fr::from_str(&index.to_string()).unwrap())).unwrap();
+        let sibling_vars = sibling.iter().enumerate().skip(K)
+            .map(|(i, s)| AllocatedNum::alloc(cs.namespace(|| format!("sibling[{}]", i)), || Ok(*s)).unwrap())
+            .collect::<Vec<_>>();
+        let leaf_vars = leaves.iter().enumerate()
+            .map(|(i, x)| AllocatedNum::alloc(cs.namespace(|| format!("leaf[{}]", i)), || Ok(*x)).unwrap())
+            .collect::<Vec<_>>();
+        let (old, new, next) = merkle_proof::merkle_insert_subtree(cs.namespace(|| "insert"), &index_var, &sibling_vars, &leaf_vars, &defaults, &*JUBJUB_PARAMS).unwrap();
+
+        if round == ROUNDS {
+            assert!(!cs.is_satisfied(), "Index not aligned to the block should be rejected");
+        } else {
+            assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+            assert!(old.get_value().unwrap() == old_root, "Old root differs from native");
+            assert!(new.get_value().unwrap() == new_root, "New root differs from native");
+            assert!(next.get_value().unwrap() == Fr::from_str(&(index + (1 << K)).to_string()).unwrap(), "Next index should follow the block");
+        }
+
+        check_cs_hash(&mut cs_hash, &cs);
+    }
+}
+
+#[test]
+fn test_merkle_proof4_consistency() {
+    use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+
+    const DEPTH: usize = 3;
+    let rng = &mut OsRng::new().unwrap();
+    let mut cs_hash = None;
+
+    for round in 0..ROUNDS {
+        // every position occurs on the lowest level
+        let index = (rng.gen::<u64>() % (1 << (2 * DEPTH))) & !3 | round as u64;
+        let leaf = rng.gen::<Fr>();
+        let sibling = (0..DEPTH).map(|_| [rng.gen::<Fr>(), rng.gen::<Fr>(), rng.gen::<Fr>()]).collect::<Vec<_>>();
+        let root = pedersen_hasher::merkle_root4::<Bls12>(&sibling, index, &leaf, &JUBJUB_PARAMS).unwrap();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let proof = sibling.iter().enumerate().map(|(i, s)| {
+            let mut cs = cs.namespace(|| format!("proof[{}]", i));
+            let s = [0, 1, 2].iter().map(|&j| AllocatedNum::alloc(cs.namespace(|| format!("sibling[{}]", j)), || Ok(s[j])).unwrap()).collect::<Vec<_>>();
+            let b = [0, 1].iter().map(|&j| Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("path[{}]", j)), Some((index >> (2 * i + j)) & 1 == 1)).unwrap())).collect::<Vec<_>>();
+            ([s[0].clone(), s[1].clone(), s[2].clone()], [b[0].clone(), b[1].clone()])
+        }).collect::<Vec<_>>();
+        let leaf_var = AllocatedNum::alloc(cs.namespace(|| "leaf"), || Ok(leaf)).unwrap();
+        let res = merkle_proof::merkle_proof4(cs.namespace(|| "proof"), &proof, &leaf_var, &*JUBJUB_PARAMS).unwrap();
+
+        assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+        assert!(res.get_value().unwrap() == root, "Root differs from native for position {}", round);
+
+        check_cs_hash(&mut cs_hash, &cs);
+    }
+}
+
+#[test]
+fn test_merkle_depth_errors() {
+    use sapling_crypto::circuit::boolean::Boolean;
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let x = AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(Fr::zero())).unwrap();
+    let proof = vec![(x.clone(), Boolean::constant(false)); pedersen_hasher::MERKLE_MAX_DEPTH + 1];
+    assert!(merkle_proof::merkle_proof(cs.namespace(|| "proof"), &proof, &x, &*JUBJUB_PARAMS).is_err(), "Path past the personalizations should be rejected");
+    assert!(merkle_proof::merkle_proof_shifted(cs.namespace(|| "shifted"), &proof[..2], &x, pedersen_hasher::MERKLE_MAX_DEPTH - 1, &*JUBJUB_PARAMS).is_err());
+    assert!(merkle_proof::subtree_root(cs.namespace(|| "subtree"), &[x.clone(), x.clone(), x.clone()], &*JUBJUB_PARAMS).is_err(), "Subtree of 3 leaves should be rejected");
+    let four = [x.clone(), x.clone(), x.clone(), x.clone()];
+    assert!(merkle_proof::compress4(cs.namespace(|| "compress4"), &four, pedersen_hasher::MERKLE4_MAX_DEPTH, &*JUBJUB_PARAMS).is_err());
+}
+
+#[test]
+fn test_mmr_errors() {
+    use sapling_crypto::circuit::boolean::Boolean;
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let x = AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(Fr::zero())).unwrap();
+    let proof = vec![(x.clone(), Boolean::constant(false)); 2];
+    // size 6 has peaks of heights 2 and 1
+    assert!(mmr_gadgets::mmr_root(cs.namespace(|| "root"), &[x.clone()], 6, &*JUBJUB_PARAMS).is_err(), "Wrong number of peaks should be rejected");
+    assert!(mmr_gadgets::mmr_inclusion(cs.namespace(|| "no peak"), &proof[..0], &x, &[x.clone(), x.clone()], 6, &*JUBJUB_PARAMS).is_err(), "Path of no peak height should be rejected");
+    assert!(mmr_gadgets::mmr_inclusion(cs.namespace(|| "missing peak"), &proof, &x, &[], 6, &*JUBJUB_PARAMS).is_err(), "Missing peak should be rejected");
+}
+
+#[test]
+fn test_mmr_inclusion_consistency() {
+    use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+    use zwaves_primitives::mmr::Mmr;
+
+    const SIZE: u64 = 11;
+    let rng = &mut OsRng::new().unwrap();
+    let mut cs_hash = None;
+
+    for _ in 0..ROUNDS {
+        let leaves = (0..SIZE).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let mut mmr = Mmr::<Bls12>::new();
+        leaves.iter().for_each(|x| mmr.append(*x, &JUBJUB_PARAMS));
+        let root = mmr.root(&JUBJUB_PARAMS);
+
+        // leaves 0..8 are in the highest peak, so the circuit shape is the same
+        let index = rng.gen::<u64>() % 8;
+        let native = mmr.proof(index).unwrap();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let proof = native.sibling.iter().enumerate().map(|(i, s)| (
+            AllocatedNum::alloc(cs.namespace(|| format!("sibling[{}]", i)), || Ok(*s)).unwrap(),
+            Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("path[{}]", i)), Some((index >> i) & 1 == 1)).unwrap())
+        )).collect::<Vec<_>>();
+        let peaks = native.peaks.iter().enumerate()
+            .map(|(i, x)| AllocatedNum::alloc(cs.namespace(|| format!("peak[{}]", i)), || Ok(*x)).unwrap())
+            .collect::<Vec<_>>();
+        let leaf = AllocatedNum::alloc(cs.namespace(|| "leaf"), || Ok(leaves[index as usize])).unwrap();
+        let res = mmr_gadgets::mmr_inclusion(cs.namespace(|| "inclusion"), &proof, &leaf, &peaks, SIZE, &*JUBJUB_PARAMS).unwrap();
+
+        assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+        assert!(res.get_value().unwrap() == root, "Root differs from native");
+
+        check_cs_hash(&mut cs_hash, &cs);
+
+        cs.set("leaf/num", leaves[(index as usize + 1) % 8]);
+        assert!(!cs.is_satisfied(), "Wrong leaf should not satisfy the constraints");
+    }
+}
+
+#[test]
+fn test_limbs_consistency() {
+    use zwaves_primitives::limbs::{self, U256};
+
+    let rng = &mut OsRng::new().unwrap();
+    let mut cs_hash = None;
+    let mut cases = vec![([u64::max_value(); 4], [1, 0, 0, 0]), ([0; 4], [1, 0, 0, 0])];
+    cases.extend((0..ROUNDS).map(|_| (rng.gen::<U256>(), rng.gen::<U256>())));
+
+    for (a, b) in cases.iter() {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let alloc = |cs: &mut TestConstraintSystem<Bls12>, name: &str, x: &U256| limbs::to_limbs::<Fr>(x).into_iter().enumerate()
+            .map(|(i, v)| AllocatedNum::alloc(cs.namespace(|| format!("{}[{}]", name, i)), || Ok(v)).unwrap())
+            .collect::<Vec<_>>();
+        let a_vars = alloc(&mut cs, "a", a);
+        let b_vars = alloc(&mut cs, "b", b);
+        limbs_gadgets::range_check(cs.namespace(|| "range a"), &a_vars).unwrap();
+        limbs_gadgets::range_check(cs.namespace(|| "range b"), &b_vars).unwrap();
+
+        let (sum, carry) = limbs_gadgets::add(cs.namespace(|| "add"), &a_vars, &b_vars).unwrap();
+        let (diff, borrow) = limbs_gadgets::sub(cs.namespace(|| "sub"), &a_vars, &b_vars).unwrap();
+        let packed = limbs_gadgets::pack(cs.namespace(|| "pack"), &a_vars[..2]).unwrap();
+
+        assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+        let value = |v: &[AllocatedNum<Bls12>]| limbs::from_limbs(&v.iter().map(|x| x.get_value().unwrap()).collect::<Vec<_>>()).unwrap();
+        assert!((value(&sum), carry.get_value().unwrap()) == limbs::add(a, b), "Sum differs from native");
+        assert!((value(&diff), borrow.get_value().unwrap()) == limbs::sub(a, b), "Difference differs from native");
+        assert!(packed.get_value().unwrap() == limbs::pack::<Fr>(&a[..2]), "Packed value differs from native");
+
+        check_cs_hash(&mut cs_hash, &cs);
+
+        cs.set("a[1]/num", zwaves_primitives::field::from_u128(1 << 64));
+        assert!(!cs.is_satisfied(), "Limb above 64 bits should not satisfy the constraints");
+    }
+}
+
+#[test]
+fn test_spend_condition_consistency() {
+    use zwaves_primitives::condition::{Condition, Timelock};
+    use crate::gadgets::condition::{ConditionContext, TimelockGadget, spend_condition};
+
+    let rng = &mut OsRng::new().unwrap();
+    let mut cs_hash = None;
+
+    for _ in 0..ROUNDS {
+        let pk = rng.gen::<Fr>();
+        let unlock_height = 1 + rng.gen::<u32>() as u64;
+        let height = unlock_height + rng.gen::<u16>() as u64;
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let pk_var = AllocatedNum::alloc(cs.namespace(|| "pk"), || Ok(pk)).unwrap();
+        let ctx = ConditionContext { height: AllocatedNum::alloc(cs.namespace(|| "height"), || Ok(zwaves_primitives::field::from_u64(height))).unwrap() };
+        let owner = spend_condition(cs.namespace(|| "spend"), &TimelockGadget { unlock_height: Some(unlock_height) }, &pk_var, &ctx).unwrap();
+
+        assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+        assert!(owner.get_value().unwrap() == Condition::<Bls12>::owner(&Timelock { unlock_height }, &pk), "Owner differs from native");
+
+        check_cs_hash(&mut cs_hash, &cs);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let pk_var = AllocatedNum::alloc(cs.namespace(|| "pk"), || Ok(pk)).unwrap();
+        let ctx = ConditionContext { height: AllocatedNum::alloc(cs.namespace(|| "height"), || Ok(zwaves_primitives::field::from_u64(unlock_height - 1))).unwrap() };
+        spend_condition(cs.namespace(|| "spend"), &TimelockGadget { unlock_height: Some(unlock_height) }, &pk_var, &ctx).unwrap();
+        assert!(!cs.is_satisfied(), "Locked note should not be spendable");
+    }
+}
+
+#[test]
+fn test_height_input() {
+    use crate::gadgets::condition::{alloc_height_input, enforce_height_at_least};
+    use zwaves_primitives::condition::Timelock;
+
+    let rng = &mut OsRng::new().unwrap();
+    let unlock_height = 1 + rng.gen::<u32>() as u64;
+
+    for &height in [unlock_height - 1, unlock_height, unlock_height + rng.gen::<u16>() as u64].iter() {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let height_var = alloc_height_input(cs.namespace(|| "height"), Some(height)).unwrap();
+        let unlock_var = AllocatedNum::alloc(cs.namespace(|| "unlock height"), || Ok(zwaves_primitives::field::from_u64(unlock_height))).unwrap();
+        enforce_height_at_least(cs.namespace(|| "unlocked"), &height_var, &unlock_var).unwrap();
+
+        assert!(cs.num_inputs() == 2 && cs.verify(&[zwaves_primitives::field::from_u64(height)]), "Height should be the public input");
+        assert!(cs.is_satisfied() == (Timelock { unlock_height }).check_spendable(height).is_ok(), "Circuit and native disagree at height {}", height);
+    }
+}
+
+#[test]
+fn test_hashlock_consistency() {
+    use zwaves_primitives::condition::{Condition, Hashlock};
+    use crate::gadgets::condition::{ConditionContext, HashlockGadget, spend_condition};
+
+    let rng = &mut OsRng::new().unwrap();
+    let pk = rng.gen::<Fr>();
+    let (preimage, lock) = Hashlock::random(rng);
+
+    let exec = |preimage: [u8; 32]| {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let pk_var = AllocatedNum::alloc(cs.namespace(|| "pk"), || Ok(pk)).unwrap();
+        let ctx = ConditionContext { height: AllocatedNum::alloc(cs.namespace(|| "height"), || Ok(Fr::zero())).unwrap() };
+        let owner = spend_condition(cs.namespace(|| "spend"), &HashlockGadget { preimage: Some(preimage) }, &pk_var, &ctx).unwrap();
+        assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+        assert!(cs.verify(&Hashlock::preimage_inputs::<Fr>(&preimage)), "Preimage should be revealed as the inputs");
+        owner.get_value().unwrap()
+    };
+
+    assert!(exec(preimage) == Condition::<Bls12>::owner(&lock, &pk), "Owner differs from native");
+    let mut wrong = preimage;
+    wrong[31] ^= 0x80;
+    assert!(exec(wrong) != Condition::<Bls12>::owner(&lock, &pk), "Another preimage should give another owner");
+}
+
+#[test]
+fn test_threshold_authority_consistency() {
+    use zwaves_primitives::spend_auth::spend_auth_key;
+
+    let rng = &mut OsRng::new().unwrap();
+    let mut cs_hash = None;
+
+    for _ in 0..ROUNDS {
+        let ak = spend_auth_key::<Bls12>(&rng.gen::<Fr>(), &JUBJUB_PARAMS).0;
+        let nk = rng.gen::<Fr>();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let nk_var = AllocatedNum::alloc(cs.namespace(|| "nk"), || Ok(nk)).unwrap();
+        let authority = gadgets::threshold_authority(cs.namespace(|| "authority"), Some(ak.clone()), &nk_var, &*JUBJUB_PARAMS).unwrap();
+
+        assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+        assert!(authority.owner.get_value().unwrap() == transactions::threshold_owner::<Bls12>(&ak.into_xy().0, &nk), "Owner differs from native");
+
+        check_cs_hash(&mut cs_hash, &cs);
+    }
+}
+
+#[test]
+fn test_note_hash_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    check_gadget_consistency(
+        |x| transactions::note_hash::<Bls12>(&NoteData {
+            asset_id: x[0],
+            amount: x[1],
+            native_amount: x[2],
+            txid: x[3],
+            owner: x[4]
+        }, &JUBJUB_PARAMS).unwrap(),
+        |cs, x| gadgets::note_hash(cs.namespace(|| "note hash"), &gadgets::Note {
+            asset_id: x[0].clone(),
+            amount: x[1].clone(),
+            native_amount: x[2].clone(),
+            txid: x[3].clone(),
+            owner: x[4].clone()
+        }, &*JUBJUB_PARAMS),
+        &random_inputs(rng, &[64, 64, 64, 255, 255], ROUNDS)
+    );
+}
+
+#[test]
+fn test_account_hash_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    check_gadget_consistency(
+        |x| transactions::account_hash::<Bls12>(&AccountData {
+            asset_id: x[0],
+            balance: x[1],
+            native_balance: x[2],
+            nonce: x[3],
+            owner: x[4]
+        }, &x[5], &JUBJUB_PARAMS).unwrap(),
+        |cs, x| gadgets::account_hash(cs.namespace(|| "account hash"), &gadgets::Account {
+            asset_id: x[0].clone(),
+            balance: x[1].clone(),
+            native_balance: x[2].clone(),
+            nonce: x[3].clone(),
+            owner: x[4].clone()
+        }, &x[5], &*JUBJUB_PARAMS),
+        &random_inputs(rng, &[64, 64, 64, 48, 255, 255], ROUNDS)
+    );
+}
+
+#[test]
+fn test_key_derivation_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    let inputs = random_inputs(rng, &[255], ROUNDS);
+
+    check_gadget_consistency(
+        |x| transactions::pubkey::<Bls12>(&x[0], &JUBJUB_PARAMS),
+        |cs, x| {
+            let bits = x[0].into_bits_le_strict(cs.namespace(|| "bitify sk"))?;
+            gadgets::pubkey(cs.namespace(|| "pubkey"), &bits, &*JUBJUB_PARAMS)
+        },
+        &inputs
+    );
+
+    check_gadget_consistency(
+        |x| transactions::nullifier_key::<Bls12>(&x[0], &JUBJUB_PARAMS),
+        |cs, x| {
+            let bits = x[0].into_bits_le_strict(cs.namespace(|| "bitify sk"))?;
+            gadgets::nullifier_key(cs.namespace(|| "nullifier key"), &bits, &*JUBJUB_PARAMS)
+        },
+        &inputs
+    );
+}
+
+#[test]
+fn test_nullifier_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    let inputs = random_inputs(rng, &[255, 255], ROUNDS);
+
+    check_gadget_consistency(
+        |x| transactions::nullifier_from_key::<Bls12>(&x[0], &x[1]),
+        |cs, x| gadgets::nullifier_from_key(cs.namespace(|| "nullifier from key"), &x[0], &x[1]),
+        &inputs
+    );
+
+    check_gadget_consistency(
+        |x| transactions::nullifier::<Bls12>(&x[0], &x[1], &JUBJUB_PARAMS),
+        |cs, x| {
+            let bits = x[1].into_bits_le_strict(cs.namespace(|| "bitify sk"))?;
+            gadgets::nullifier(cs.namespace(|| "nullifier"), &x[0], &bits, &*JUBJUB_PARAMS)
+        },
+        &inputs
+    );
+}
+
+fn fr_to_u64(x: &Fr) -> u64 {
+    x.into_repr().as_ref()[0]
+}
+
+#[test]
+fn test_epoch_nullifier_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    let inputs = random_inputs(rng, &[255, 255, 64], ROUNDS);
+
+    check_gadget_consistency(
+        |x| transactions::epoch_nullifier_from_key::<Bls12>(&x[0], &x[1], fr_to_u64(&x[2])),
+        |cs, x| gadgets::epoch_nullifier_from_key(cs.namespace(|| "epoch nullifier from key"), &x[0], &x[1], &x[2]),
+        &inputs
+    );
+
+    check_gadget_consistency(
+        |x| transactions::epoch_nullifier::<Bls12>(&x[0], &x[1], fr_to_u64(&x[2]), &JUBJUB_PARAMS),
+        |cs, x| {
+            let bits = x[1].into_bits_le_strict(cs.namespace(|| "bitify sk"))?;
+            gadgets::epoch_nullifier(cs.namespace(|| "epoch nullifier"), &x[0], &bits, &x[2], &*JUBJUB_PARAMS)
+        },
+        &inputs
+    );
+
+    let (nh, nk) = (inputs[0][0], inputs[0][1]);
+    let nf = transactions::nullifier_from_key::<Bls12>(&nh, &nk);
+    let nf0 = transactions::epoch_nullifier_from_key::<Bls12>(&nh, &nk, 0);
+    let nf1 = transactions::epoch_nullifier_from_key::<Bls12>(&nh, &nk, 1);
+    assert!(nf0 != nf1, "Epochs should give different nullifiers");
+    assert!(nf0 != nf && nf1 != nf, "Epoch nullifiers should differ from the plain one");
+}
+
+#[test]
+fn test_input_hash_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    for &n in [1, 3, 9].iter() {
+        check_gadget_consistency(
+            |x| input_hash::input_hash::<Bls12>(x),
+            |cs, x| input_hash_gadgets::input_hash(cs.namespace(|| "input hash"), x),
+            &random_inputs(rng, &vec![255; n], ROUNDS)
+        );
+    }
+}
+
+
+// x * y = z with all three public
+struct Mul(Option<(Fr, Fr)>);
+
+impl Circuit<Bls12> for Mul {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let v = self.0;
+        let x = AllocatedNum::alloc(cs.namespace(|| "x"), || v.map(|v| v.0).ok_or(SynthesisError::AssignmentMissing))?;
+        let y = AllocatedNum::alloc(cs.namespace(|| "y"), || v.map(|v| v.1).ok_or(SynthesisError::AssignmentMissing))?;
+        let z = x.mul(cs.namespace(|| "x * y"), &y)?;
+        x.inputize(cs.namespace(|| "inputize x"))?;
+        y.inputize(cs.namespace(|| "inputize y"))?;
+        z.inputize(cs.namespace(|| "inputize z"))
+    }
+}
+
+#[test]
+fn test_hashed_inputs() {
+    let rng = &mut OsRng::new().unwrap();
+    let (x, y) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+    let mut z = x;
+    z.mul_assign(&y);
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    HashedInputs(Mul(Some((x, y)))).synthesize(&mut cs).unwrap();
+    assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+    assert!(cs.num_inputs() == 2, "Input hash should be the only public input");
+    assert!(cs.verify(&[input_hash::input_hash::<Bls12>(&[x, y, z])]));
+
+    let params = generate_random_parameters::<Bls12, _, _>(HashedInputs(Mul(None)), rng).unwrap();
+    let tvk = verifier::truncate_verifying_key(&params.vk);
+    assert!(tvk.ic.len() == 2);
+    let proof = create_random_proof(HashedInputs(Mul(Some((x, y)))), &params, rng).unwrap();
+    assert!(input_hash::verify_proof(&tvk, &proof, &[x, y, z]).unwrap(), "Proof should be valid");
+    assert!(!input_hash::verify_proof(&tvk, &proof, &[y, x, z]).unwrap(), "Reordered inputs should be rejected");
+    assert!(verifier::verify_proof(&tvk, &proof, &[input_hash::input_hash::<Bls12>(&[x, y, z])]).unwrap());
+}
+
+#[test]
+fn test_audit_encryption_consistency() {
+    let rng = &mut OsRng::new().unwrap();
+    let mut cs_hash = None;
+
+    for _ in 0..ROUNDS {
+        let sk = rng.gen::<Fr>();
+        let pk = audit::auditor_key::<Bls12>(&sk, &JUBJUB_PARAMS);
+        let r = rng.gen::<Fr>();
+        let fields = random_inputs(rng, &[64, 64, 64, 255, 255], 1).pop().unwrap();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let vars = fields.iter().enumerate()
+            .map(|(j, &x)| AllocatedNum::alloc(cs.namespace(|| format!("input[{}]", j)), || Ok(x)))
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        let pk_var = ecc::EdwardsPoint::witness(cs.namespace(|| "auditor pk"), Some(pk.clone()), &*JUBJUB_PARAMS).unwrap();
+        let r_var = AllocatedNum::alloc(cs.namespace(|| "r"), || Ok(r)).unwrap();
+        let r_bits = r_var.into_bits_le_strict(cs.namespace(|| "bitify r")).unwrap();
+
+        let (epk, data) = audit_gadgets::encrypt(cs.namespace(|| "encrypt"), &vars, &pk_var, &r_bits, &*JUBJUB_PARAMS).unwrap();
+        assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+
+        let ct = audit::encrypt::<Bls12>(&fields, &pk.into(), &r, &JUBJUB_PARAMS);
+        assert!(epk.get_value().unwrap() == ct.epk, "epk differs from native");
+        assert!(data.iter().map(|c| c.get_value().unwrap()).collect::<Vec<_>>() == ct.data, "Ciphertext differs from native");
+        assert!(audit::decrypt::<Bls12>(&ct, &sk, &JUBJUB_PARAMS).unwrap() == fields);
+
+        check_cs_hash(&mut cs_hash, &cs);
+    }
+}
+
+#[cfg(feature = "recursion")]
+#[test]
+fn test_deferred_proof_consistency() {
+    use zwaves_primitives::recursion;
+    use crate::gadgets::recursion as recursion_gadgets;
+
+    let rng = &mut OsRng::new().unwrap();
+    let params = generate_random_parameters::<Bls12, _, _>(Mul(None), rng).unwrap();
+    let tvk = verifier::truncate_verifying_key(&params.vk);
+    let digest = recursion::vk_digest(&tvk);
+    let mut cs_hash = None;
+
+    for _ in 0..ROUNDS {
+        let (x, y) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+        let mut z = x;
+        z.mul_assign(&y);
+        let inputs = [x, y, z];
+        let proof = create_random_proof(Mul(Some((x, y))), &params, rng).unwrap();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let vars = inputs.iter().enumerate()
+            .map(|(j, &x)| AllocatedNum::alloc(cs.namespace(|| format!("input[{}]", j)), || Ok(x)))
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        let proof_bits = recursion_gadgets::witness_proof(cs.namespace(|| "proof"), Some(&proof)).unwrap();
+        let res = recursion_gadgets::deferred_proof(cs.namespace(|| "deferred"), &digest, &proof_bits, &vars).unwrap();
+        assert!(cs.is_satisfied(), "Constraints not satisfied: {}", cs.which_is_unsatisfied().unwrap_or(""));
+
+        let deferred = res.get_value().unwrap();
+        assert!(deferred == recursion::deferred_hash(&digest, &proof, &inputs), "Deferred hash differs from native");
+        assert!(recursion::verify_deferred(&tvk, &proof, &inputs, &deferred).unwrap(), "Deferred proof should be valid");
+        assert!(!recursion::verify_deferred(&tvk, &proof, &[y, x, z], &deferred).unwrap(), "Other inputs should be rejected");
+
+        check_cs_hash(&mut cs_hash, &cs);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod compress_test;
+pub mod transaction_test;
+pub mod consistency_test;
+pub mod bitify_test;
+pub mod multipack_test;
+pub mod uint64_test;
+pub mod compare_test;
+pub mod pedersen_segments_test;
+pub mod commitment_test;
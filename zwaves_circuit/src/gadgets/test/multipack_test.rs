@@ -0,0 +1,33 @@
+use bellman::ConstraintSystem;
+use sapling_crypto::circuit::boolean::{Boolean, AllocatedBit};
+use sapling_crypto::circuit::test::TestConstraintSystem;
+
+use pairing::bls12_381::{Bls12, Fr};
+
+use rand::os::OsRng;
+use rand::Rng;
+
+use crate::gadgets::multipack;
+use zwaves_primitives::multipack::{pack_bits_to_fields, bytes_to_bits_le};
+
+
+#[test]
+fn test_multipack_agrees_with_native() {
+    let mut rng = OsRng::new().unwrap();
+    // a 512 bit hash and a string ending inside a chunk
+    for len in [64, 40].iter() {
+        let bytes = (0..*len).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+        let bits = bytes_to_bits_le(&bytes);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let allocated = bits.iter().enumerate()
+            .map(|(i, b)| Ok(Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("bit {}", i)), Some(*b))?)))
+            .collect::<Result<Vec<_>, bellman::SynthesisError>>().unwrap();
+        let packed = multipack::pack_into_inputs(cs.namespace(|| "pack"), &allocated).unwrap();
+
+        let native = pack_bits_to_fields::<Fr>(&bits);
+        assert!(cs.is_satisfied());
+        assert!(packed.iter().map(|x| x.get_value().unwrap()).collect::<Vec<_>>() == native);
+        assert!(cs.verify(&native), "Native packing should give the public inputs of the circuit");
+    }
+}
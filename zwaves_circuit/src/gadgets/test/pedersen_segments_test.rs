@@ -0,0 +1,46 @@
+use bellman::ConstraintSystem;
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+use sapling_crypto::circuit::pedersen_hash;
+use sapling_crypto::circuit::test::TestConstraintSystem;
+use sapling_crypto::pedersen_hash::Personalization;
+
+use pairing::bls12_381::Bls12;
+
+use rand::os::OsRng;
+use rand::Rng;
+
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use zwaves_primitives::pedersen_segments::{self, SegmentedParams, Segmenting, SAPLING_SEGMENTING};
+use crate::gadgets::pedersen_segments::pedersen_hash as segmented_hash;
+
+
+fn alloc_bits(cs: &mut TestConstraintSystem<Bls12>, bits: &[bool]) -> Vec<Boolean> {
+    bits.iter().enumerate()
+        .map(|(i, &b)| Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("bit[{}]", i)), Some(b)).unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_segmented_pedersen_gadget() {
+    let params = &*JUBJUB_PARAMS;
+    let mut rng = OsRng::new().unwrap();
+    let sapling = SegmentedParams::<Bls12>::new(SAPLING_SEGMENTING, 945, params).unwrap();
+    let short = SegmentedParams::<Bls12>::new(Segmenting { chunks_per_segment: 29, exp_window: 8 }, 945, params).unwrap();
+    let bits = (0..510).map(|_| rng.gen()).collect::<Vec<bool>>();
+    let p = Personalization::MerkleTree(3);
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let input = alloc_bits(&mut cs, &bits);
+    let expected = pedersen_hash::pedersen_hash(cs.namespace(|| "sapling"), p, &input, params).unwrap();
+    let sapling_count = cs.num_constraints();
+    let res = segmented_hash(cs.namespace(|| "segmented"), p, &input, &sapling, params).unwrap();
+    assert!(cs.num_constraints() - sapling_count == sapling_count - 510, "Sapling segmenting should cost as circuit::pedersen_hash");
+    assert!(res.get_x().get_value() == expected.get_x().get_value() && res.get_y().get_value() == expected.get_y().get_value());
+
+    let before = cs.num_constraints();
+    let res = segmented_hash(cs.namespace(|| "short"), p, &input, &short, params).unwrap();
+    let native = pedersen_segments::pedersen_hash(p, bits.iter().cloned(), &short, params).unwrap().into_xy();
+    assert!(res.get_x().get_value() == Some(native.0) && res.get_y().get_value() == Some(native.1));
+    assert!(cs.is_satisfied());
+    assert!(cs.num_constraints() - before > sapling_count - 510, "Shorter segments should cost more constraints");
+}
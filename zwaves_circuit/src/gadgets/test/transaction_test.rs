@@ -13,14 +13,16 @@ use pairing::bls12_381::{Bls12, Fr, FrRepr};
 use pairing::{PrimeField, Field};
 
 
-use crate::pedersen_hasher;
-use crate::circuit::merkle_proof;
-use crate::transactions::{NoteData, pubkey, note_hash};
-use crate::circuit::transactions::{transfer, Note, nullifier};
+use zwaves_primitives::pedersen_hasher;
+use crate::gadgets::merkle_proof;
+use zwaves_primitives::transactions::{NoteData, AccountData, pubkey, note_hash, nullifier_key, account_hash};
+use zwaves_primitives::spend_auth::{spend_auth_key, randomized_public_key, rk_to_inputs};
+use crate::gadgets::transactions::{transfer, account_update, Note, Account, nullifier, nullifier_from_key};
 
 
 use rand::os::OsRng;
 use rand::Rng;
+use lazy_static::lazy_static;
 use num::BigInt;
 use num::Num as NumTrait;
 use std::str::FromStr;
@@ -31,15 +33,6 @@ const PROOF_LENGTH:usize = 32;
 
 lazy_static! {
     static ref JUBJUB_PARAMS: JubjubBls12 = JubjubBls12::new();
-    static ref MERKLE_DEFAULTS: Vec<Fr> = {
-        let mut c = Fr::zero();
-        let mut res = vec![];
-        for i in 0 .. PROOF_LENGTH+1 {
-            res.push(c);
-            c = pedersen_hasher::compress::<Bls12>(&c, &c,  Personalization::MerkleTree(i), &JUBJUB_PARAMS)
-        }
-        res
-    };
 }
 
 
@@ -58,7 +51,7 @@ impl MerkleTreeAccumulator {
         if index < self.0[row].len() {
             self.0[row][index]
         } else {
-            MERKLE_DEFAULTS[row]
+            pedersen_hasher::empty_root(row)
         }
     }
 
@@ -202,7 +195,7 @@ pub fn test_merkle_tree_struct(){
     mt.pushMany(&note_hashes);
     let sibling = mt.proof(index as usize);
     let leaf_data = note_hashes[index];
-    let cmp_root = crate::pedersen_hasher::merkle_root::<Bls12>(&sibling, index as u64, &leaf_data, &JUBJUB_PARAMS);
+    let cmp_root = zwaves_primitives::pedersen_hasher::merkle_root::<Bls12>(&sibling, index as u64, &leaf_data, &JUBJUB_PARAMS);
     assert!(cmp_root == mt.root(), "merkle proof results should be equal");
     
 }
@@ -237,7 +230,7 @@ pub fn test_merkle_proof(){
         let not_satisfied = cs.which_is_unsatisfied().unwrap_or("");
         assert!(false, format!("Constraints not satisfied: {}", not_satisfied));
     }
-    assert!(crate::pedersen_hasher::merkle_root::<Bls12>(&sibling, index as u64, &leaf_data, &JUBJUB_PARAMS) == res.get_value().unwrap(), "merkle proof results should be equal");
+    assert!(zwaves_primitives::pedersen_hasher::merkle_root::<Bls12>(&sibling, index as u64, &leaf_data, &JUBJUB_PARAMS) == res.get_value().unwrap(), "merkle proof results should be equal");
 }
 
 
@@ -253,7 +246,7 @@ fn test_transaction() {
 
     let notes = (0..n_notes).map(|_| rand_note(Some(Fr::zero()), None, None, None, Some(pk), &mut rng)).collect::<Vec<_>>();
 
-    let note_hashes = notes.iter().map(|n| note_hash::<Bls12>(n, &JUBJUB_PARAMS)).collect::<Vec<_>>();
+    let note_hashes = notes.iter().map(|n| note_hash::<Bls12>(n, &JUBJUB_PARAMS).unwrap()).collect::<Vec<_>>();
 
     let mut mt = MerkleTreeAccumulator::new();
     mt.pushMany(&note_hashes);
@@ -300,7 +293,10 @@ fn test_transaction() {
     let packed_asset = AllocatedNum::alloc(cs.namespace(|| "alloc packed_asset"), || Ok(big2fr(packed_asset_bn))).unwrap();
     let root_hash =  AllocatedNum::alloc(cs.namespace(|| "alloc root_hash"), || Ok(mt.root())).unwrap();
 
-    let (out_hash, nf) = transfer(cs.namespace(||"exec transfer"), &in_note, &in_proof, &out_note, &root_hash, &sk, &packed_asset, &JUBJUB_PARAMS).unwrap();
+    let alpha_data: Fr = rng.gen();
+    let alpha = AllocatedNum::alloc(cs.namespace(|| "alloc alpha"), || Ok(alpha_data)).unwrap();
+
+    let (out_hash, nf, rk) = transfer(cs.namespace(||"exec transfer"), &in_note, &in_proof, &out_note, &root_hash, &sk, &alpha, &packed_asset, &JUBJUB_PARAMS).unwrap();
 
     if !cs.is_satisfied() {
         let not_satisfied = cs.which_is_unsatisfied().unwrap_or("");
@@ -308,15 +304,17 @@ fn test_transaction() {
     }
 
     let nf_computed = in_note_data.iter().map(|note| {
-        let hash = crate::transactions::note_hash(note, &JUBJUB_PARAMS);
-        crate::transactions::nullifier::<Bls12>(&hash, &sk_data, &JUBJUB_PARAMS)
+        let hash = zwaves_primitives::transactions::note_hash(note, &JUBJUB_PARAMS).unwrap();
+        zwaves_primitives::transactions::nullifier::<Bls12>(&hash, &sk_data, &JUBJUB_PARAMS)
     });
 
-    let out_hash_computed = out_note_data.iter().map(|note| crate::transactions::note_hash(note, &JUBJUB_PARAMS));
+    let out_hash_computed = out_note_data.iter().map(|note| zwaves_primitives::transactions::note_hash(note, &JUBJUB_PARAMS).unwrap());
 
     assert!(out_hash.iter().zip(out_hash_computed).all(|(a, b)| a.get_value().unwrap() == b), "out hashes should be the same");
     assert!(nf.iter().zip(nf_computed).all(|(a, b)| a.get_value().unwrap() == b), "nullifiers should be the same");
 
+    let rk_computed = randomized_public_key(&spend_auth_key::<Bls12>(&sk_data, &JUBJUB_PARAMS), &alpha_data, &JUBJUB_PARAMS);
+    assert!((rk.get_x().get_value().unwrap(), rk.get_y().get_value().unwrap()) == rk_to_inputs(&rk_computed), "rk should be the same");
 }
 
 
@@ -333,7 +331,7 @@ fn test_transaction_withdraw() {
 
     let notes = (0..n_notes).map(|_| rand_note(Some(Fr::zero()), None, None, None, Some(pk), &mut rng)).collect::<Vec<_>>();
 
-    let note_hashes = notes.iter().map(|n| note_hash::<Bls12>(n, &JUBJUB_PARAMS)).collect::<Vec<_>>();
+    let note_hashes = notes.iter().map(|n| note_hash::<Bls12>(n, &JUBJUB_PARAMS).unwrap()).collect::<Vec<_>>();
 
     let mut mt = MerkleTreeAccumulator::new();
     mt.pushMany(&note_hashes);
@@ -382,7 +380,10 @@ fn test_transaction_withdraw() {
     let packed_asset = AllocatedNum::alloc(cs.namespace(|| "alloc packed_asset"), || Ok(big2fr(packed_asset_bn))).unwrap();
     let root_hash =  AllocatedNum::alloc(cs.namespace(|| "alloc root_hash"), || Ok(mt.root())).unwrap();
 
-    let (out_hash, nf) = transfer(cs.namespace(||"exec transfer"), &in_note, &in_proof, &out_note, &root_hash, &sk, &packed_asset, &JUBJUB_PARAMS).unwrap();
+    let alpha_data: Fr = rng.gen();
+    let alpha = AllocatedNum::alloc(cs.namespace(|| "alloc alpha"), || Ok(alpha_data)).unwrap();
+
+    let (out_hash, nf, rk) = transfer(cs.namespace(||"exec transfer"), &in_note, &in_proof, &out_note, &root_hash, &sk, &alpha, &packed_asset, &JUBJUB_PARAMS).unwrap();
 
     if !cs.is_satisfied() {
         let not_satisfied = cs.which_is_unsatisfied().unwrap_or("");
@@ -390,15 +391,17 @@ fn test_transaction_withdraw() {
     }
 
     let nf_computed = in_note_data.iter().map(|note| {
-        let hash = crate::transactions::note_hash(note, &JUBJUB_PARAMS);
-        crate::transactions::nullifier::<Bls12>(&hash, &sk_data, &JUBJUB_PARAMS)
+        let hash = zwaves_primitives::transactions::note_hash(note, &JUBJUB_PARAMS).unwrap();
+        zwaves_primitives::transactions::nullifier::<Bls12>(&hash, &sk_data, &JUBJUB_PARAMS)
     });
 
-    let out_hash_computed = out_note_data.iter().map(|note| crate::transactions::note_hash(note, &JUBJUB_PARAMS));
+    let out_hash_computed = out_note_data.iter().map(|note| zwaves_primitives::transactions::note_hash(note, &JUBJUB_PARAMS).unwrap());
 
     assert!(out_hash.iter().zip(out_hash_computed).all(|(a, b)| a.get_value().unwrap() == b), "out hashes should be the same");
     assert!(nf.iter().zip(nf_computed).all(|(a, b)| a.get_value().unwrap() == b), "nullifiers should be the same");
 
+    let rk_computed = randomized_public_key(&spend_auth_key::<Bls12>(&sk_data, &JUBJUB_PARAMS), &alpha_data, &JUBJUB_PARAMS);
+    assert!((rk.get_x().get_value().unwrap(), rk.get_y().get_value().unwrap()) == rk_to_inputs(&rk_computed), "rk should be the same");
 }
 
 
@@ -414,7 +417,7 @@ fn test_nullifier() -> Result<(), SynthesisError> {
     let sk = rng.gen::<Fr>();
 
 
-    let nf = crate::transactions::nullifier::<Bls12>(&nh, &sk, &params);
+    let nf = zwaves_primitives::transactions::nullifier::<Bls12>(&nh, &sk, &params);
 
 
     let nh_a = AllocatedNum::alloc(cs.namespace(|| "var nh_a"), || Ok(nh))?;
@@ -430,4 +433,261 @@ fn test_nullifier() -> Result<(), SynthesisError> {
     assert!(nf_a.get_value().unwrap() == nf, "Nf value should be the same");
 
     Ok(())
-}
\ No newline at end of file
+}
+
+
+#[test]
+fn test_nullifier_from_key() -> Result<(), SynthesisError> {
+    let rng = &mut OsRng::new().unwrap();
+    let params = JubjubBls12::new();
+
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+
+    let nh = rng.gen::<Fr>();
+    let sk = rng.gen::<Fr>();
+    let nk = zwaves_primitives::transactions::nullifier_key::<Bls12>(&sk, &params);
+
+
+    let nf = zwaves_primitives::transactions::nullifier::<Bls12>(&nh, &sk, &params);
+    assert!(zwaves_primitives::transactions::nullifier_from_key::<Bls12>(&nh, &nk) == nf, "Nf derived from nk should be the same");
+
+
+    let nh_a = AllocatedNum::alloc(cs.namespace(|| "var nh_a"), || Ok(nh))?;
+    let nk_a = AllocatedNum::alloc(cs.namespace(|| "var nk_a"), || Ok(nk))?;
+
+    let nf_a = nullifier_from_key(&mut cs, &nh_a, &nk_a)?;
+
+    if !cs.is_satisfied() {
+        let not_satisfied = cs.which_is_unsatisfied().unwrap_or("");
+        assert!(false, format!("Constraints not satisfied: {}", not_satisfied));
+    }
+    assert!(nf_a.get_value().unwrap() == nf, "Nf value should be the same");
+
+    Ok(())
+}
+
+
+fn alloc_account_data<E: JubjubEngine, CS:ConstraintSystem<E>>(mut cs: CS, data: &AccountData<E>) -> Account<E> {
+    Account {
+        asset_id: AllocatedNum::alloc(cs.namespace(|| "alloc asset_id"), || Ok(data.asset_id)).unwrap(),
+        balance: AllocatedNum::alloc(cs.namespace(|| "alloc balance"), || Ok(data.balance)).unwrap(),
+        native_balance: AllocatedNum::alloc(cs.namespace(|| "alloc native_balance"), || Ok(data.native_balance)).unwrap(),
+        nonce: AllocatedNum::alloc(cs.namespace(|| "alloc nonce"), || Ok(data.nonce)).unwrap(),
+        owner: AllocatedNum::alloc(cs.namespace(|| "alloc owner"), || Ok(data.owner)).unwrap()
+    }
+}
+
+
+fn exec_account_update(accounts: &[AccountData<Bls12>], index: usize, sk_data: Fr, packed_asset_bn: BigInt) -> (TestConstraintSystem<Bls12>, Fr, Fr, Fr) {
+    let nk = nullifier_key::<Bls12>(&sk_data, &JUBJUB_PARAMS);
+    let account_hashes = accounts.iter().map(|a| account_hash::<Bls12>(a, &nk, &JUBJUB_PARAMS).unwrap()).collect::<Vec<_>>();
+
+    let mut mt = MerkleTreeAccumulator::new();
+    mt.pushMany(&account_hashes);
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+
+    let account = alloc_account_data(cs.namespace(|| "alloc account"), &accounts[index]);
+    let index_bits = (0..PROOF_LENGTH).map(|j| (index>>j) & 1 == 1);
+    let proof_data = mt.proof(index).into_iter().zip(index_bits).collect::<Vec<_>>();
+    let proof = alloc_proof_data(cs.namespace(|| "alloc proof"), Some(proof_data)).unwrap();
+
+    let sk = AllocatedNum::alloc(cs.namespace(|| "alloc sk"), || Ok(sk_data)).unwrap();
+    let packed_asset = AllocatedNum::alloc(cs.namespace(|| "alloc packed_asset"), || Ok(big2fr(packed_asset_bn))).unwrap();
+
+    let (old_root, new_root, new_hash) = account_update(cs.namespace(|| "exec account update"), &account, &proof, &sk, &packed_asset, &JUBJUB_PARAMS).unwrap();
+    assert!(old_root.get_value().unwrap() == mt.root(), "old root should be the same");
+
+    let mut account_hashes = account_hashes;
+    account_hashes[index] = new_hash.get_value().unwrap();
+    let mut mt = MerkleTreeAccumulator::new();
+    mt.pushMany(&account_hashes);
+
+    (cs, new_root.get_value().unwrap(), mt.root(), new_hash.get_value().unwrap())
+}
+
+
+#[test]
+fn test_account_update() {
+    let mut rng = OsRng::new().unwrap();
+
+    let n_accounts = 16;
+    let sk_data: Fr = rng.gen();
+    let pk = pubkey::<Bls12>(&sk_data, &JUBJUB_PARAMS);
+    let nk = nullifier_key::<Bls12>(&sk_data, &JUBJUB_PARAMS);
+
+    let accounts = (0..n_accounts).map(|_| AccountData::<Bls12> {
+        asset_id: Fr::zero(),
+        balance: gen_rand_fr_limited(32, &mut rng),
+        native_balance: gen_rand_fr_limited(32, &mut rng),
+        nonce: gen_rand_fr_limited(16, &mut rng),
+        owner: pk
+    }).collect::<Vec<_>>();
+    let index = rng.gen_range(0, n_accounts);
+
+    let u64num = BigInt::from_str("18446744073709551616").unwrap();
+    let amount = fr2big(accounts[index].balance) / BigInt::from(3);
+    let native_amount = BigInt::from(1000);
+
+    // withdraw a part of balance, deposit native amount
+    let packed_asset_bn = (&native_amount << 128) + ((&u64num - &amount) << 64);
+    let (cs, new_root, new_root_computed, new_hash) = exec_account_update(&accounts, index, sk_data, packed_asset_bn);
+
+    if !cs.is_satisfied() {
+        let not_satisfied = cs.which_is_unsatisfied().unwrap_or("");
+        assert!(false, format!("Constraints not satisfied: {}", not_satisfied));
+    }
+    assert!(new_root == new_root_computed, "new root should be the same");
+
+    let mut nonce = accounts[index].nonce;
+    nonce.add_assign(&Fr::one());
+    let new_account = AccountData::<Bls12> {
+        asset_id: Fr::zero(),
+        balance: big2fr(fr2big(accounts[index].balance) - &amount),
+        native_balance: big2fr(fr2big(accounts[index].native_balance) + &native_amount),
+        nonce,
+        owner: pk
+    };
+    assert!(account_hash::<Bls12>(&new_account, &nk, &JUBJUB_PARAMS).unwrap() == new_hash, "new account hash should be the same");
+
+    // overdraft
+    let amount = fr2big(accounts[index].balance) + BigInt::from(1);
+    let packed_asset_bn = (&u64num - &amount) << 64;
+    let (cs, _, _, _) = exec_account_update(&accounts, index, sk_data, packed_asset_bn);
+    assert!(!cs.is_satisfied(), "Overdraft should not be provable");
+}
+
+
+#[test]
+fn test_subtree_proof() {
+    let mut rng = OsRng::new().unwrap();
+    let mut mt = MerkleTreeAccumulator::new();
+    let leaves = (0..20).map(|_| rng.gen()).collect::<Vec<Fr>>();
+    mt.pushMany(&leaves);
+
+    let k = 2;
+    let block = 3;
+    let block_leaves = &leaves[block << k .. (block + 1) << k];
+    let sibling = mt.proof(block << k)[k..].to_vec();
+
+    let subtree_root = pedersen_hasher::subtree_root::<Bls12>(block_leaves, &JUBJUB_PARAMS).unwrap();
+    assert!(subtree_root == mt.cell(k, block), "subtree root should match the tree node");
+    assert!(pedersen_hasher::merkle_root_from_subtree::<Bls12>(&sibling, block as u64, &subtree_root, k, &JUBJUB_PARAMS).unwrap() == mt.root());
+    assert!(pedersen_hasher::subtree_root::<Bls12>(&leaves[..3], &JUBJUB_PARAMS).is_none());
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let leaves = block_leaves.iter().enumerate()
+        .map(|(i, &l)| AllocatedNum::alloc(cs.namespace(|| format!("leaf[{}]", i)), || Ok(l)).unwrap())
+        .collect::<Vec<_>>();
+    let proof = sibling.iter().enumerate().map(|(i, &s)| {
+        let s = AllocatedNum::alloc(cs.namespace(|| format!("sibling[{}]", i)), || Ok(s)).unwrap();
+        let b = AllocatedBit::alloc(cs.namespace(|| format!("index bit[{}]", i)), Some((block >> i) & 1 == 1)).unwrap();
+        (s, Boolean::from(b))
+    }).collect::<Vec<_>>();
+
+    let root = merkle_proof::subtree_proof(cs.namespace(|| "subtree proof"), &proof, &leaves, &JUBJUB_PARAMS).unwrap();
+
+    if !cs.is_satisfied() {
+        let not_satisfied = cs.which_is_unsatisfied().unwrap_or("");
+        assert!(false, format!("Constraints not satisfied: {}", not_satisfied));
+    }
+    assert!(root.get_value().unwrap() == mt.root(), "circuit root should be the same");
+}
+
+#[test]
+fn test_merkle_proof_pair() {
+    let mut rng = OsRng::new().unwrap();
+    let n_notes = 20;
+    let notes = (0..n_notes).map(|_| rng.gen()).collect::<Vec<Fr>>();
+    let metadata = (0..n_notes).map(|_| rng.gen()).collect::<Vec<Fr>>();
+    let mut notes_mt = MerkleTreeAccumulator::new();
+    notes_mt.pushMany(&notes);
+    let mut metadata_mt = MerkleTreeAccumulator::new();
+    metadata_mt.pushMany(&metadata);
+
+    // the metadata leaf is taken from metadata_index, its proof is always the one of metadata_index
+    let exec = |index: usize, metadata_index: usize| {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let bits = (0..PROOF_LENGTH)
+            .map(|j| Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("index bit[{}]", j)), Some((index >> j) & 1 == 1)).unwrap()))
+            .collect::<Vec<_>>();
+        let alloc = |cs: &mut TestConstraintSystem<Bls12>, name: &str, x: &[Fr]| x.iter().enumerate()
+            .map(|(j, &x)| AllocatedNum::alloc(cs.namespace(|| format!("{}[{}]", name, j)), || Ok(x)).unwrap())
+            .collect::<Vec<_>>();
+        let notes_sibling = alloc(&mut cs, "notes sibling", &notes_mt.proof(index));
+        let metadata_sibling = alloc(&mut cs, "metadata sibling", &metadata_mt.proof(metadata_index));
+        let note = AllocatedNum::alloc(cs.namespace(|| "note"), || Ok(notes[index])).unwrap();
+        let meta = AllocatedNum::alloc(cs.namespace(|| "metadata"), || Ok(metadata[metadata_index])).unwrap();
+
+        let (notes_root, metadata_root) = merkle_proof::merkle_proof_pair(cs.namespace(|| "pair"), &bits, &notes_sibling, &note, &metadata_sibling, &meta, &JUBJUB_PARAMS).unwrap();
+        assert!(cs.is_satisfied());
+        (notes_root.get_value().unwrap(), metadata_root.get_value().unwrap())
+    };
+
+    let index = rng.gen_range(0, n_notes);
+    assert!(exec(index, index) == (notes_mt.root(), metadata_mt.root()), "both roots should be the same as the trees");
+    let (notes_root, metadata_root) = exec(index, (index + 1) % n_notes);
+    assert!(notes_root == notes_mt.root() && metadata_root != metadata_mt.root(), "metadata of another index should not give the metadata root");
+}
+
+#[test]
+fn test_merkle_proof_any_root() {
+    let mut rng = OsRng::new().unwrap();
+    let mut mt = MerkleTreeAccumulator::new();
+    let mut roots = vec![];
+    let mut leaf_proof = None;
+    for k in 0..4 {
+        let leaves = (0..5).map(|_| rng.gen()).collect::<Vec<Fr>>();
+        mt.pushMany(&leaves);
+        roots.push(mt.root());
+        if k == 1 {
+            // a proof against the anchor after the second batch
+            let index = 5 + rng.gen_range(0, 5);
+            leaf_proof = Some((index, leaves[index - 5], mt.proof(index)));
+        }
+    }
+    let (index, leaf, sibling) = leaf_proof.unwrap();
+
+    let exec = |accepted: &[Fr]| {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let proof = sibling.iter().enumerate().map(|(j, &s)| (
+            AllocatedNum::alloc(cs.namespace(|| format!("sibling[{}]", j)), || Ok(s)).unwrap(),
+            Boolean::from(AllocatedBit::alloc(cs.namespace(|| format!("index bit[{}]", j)), Some((index >> j) & 1 == 1)).unwrap())
+        )).collect::<Vec<_>>();
+        let leaf = AllocatedNum::alloc(cs.namespace(|| "leaf"), || Ok(leaf)).unwrap();
+        let roots = accepted.iter().enumerate().map(|(j, &r)| {
+            let r = AllocatedNum::alloc(cs.namespace(|| format!("root[{}]", j)), || Ok(r)).unwrap();
+            r.inputize(cs.namespace(|| format!("root input[{}]", j))).unwrap();
+            r
+        }).collect::<Vec<_>>();
+        merkle_proof::merkle_proof_any_root(cs.namespace(|| "any root"), &proof, &leaf, &roots, &JUBJUB_PARAMS).unwrap();
+        cs.is_satisfied()
+    };
+
+    assert!(exec(&roots), "root among the accepted ones should satisfy");
+    assert!(exec(&roots[1..2]));
+    assert!(exec(&[roots[3], roots[1]]));
+    assert!(!exec(&[roots[0], roots[2], roots[3]]), "path to a root not accepted should not satisfy");
+    assert!(!exec(&roots[3..]));
+}
+
+#[test]
+fn test_note_hash_range() {
+    let rng = &mut OsRng::new().unwrap();
+    let mut note = NoteData::<Bls12> {
+        asset_id: Fr::from_repr(FrRepr([7, 0, 0, 0])).unwrap(),
+        amount: Fr::from_repr(FrRepr([u64::max_value(), 0, 0, 0])).unwrap(),
+        native_amount: Fr::zero(),
+        txid: rng.gen(),
+        owner: rng.gen()
+    };
+    assert!(note_hash(&note, &pedersen_hasher::JUBJUB_PARAMS).is_ok());
+
+    let low = note.amount;
+    note.amount = Fr::from_repr(FrRepr([u64::max_value(), 1, 0, 0])).unwrap();
+    assert!(note_hash(&note, &pedersen_hasher::JUBJUB_PARAMS).is_err(), "Amount over 64 bits should not be truncated");
+    note.amount = low;
+    note.asset_id = Fr::from_repr(FrRepr([0, 1, 0, 0])).unwrap();
+    assert!(note_hash(&note, &pedersen_hasher::JUBJUB_PARAMS).is_err());
+}
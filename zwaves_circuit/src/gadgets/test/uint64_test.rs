@@ -0,0 +1,65 @@
+use bellman::ConstraintSystem;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::test::TestConstraintSystem;
+
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::Field;
+
+use rand::os::OsRng;
+use rand::Rng;
+
+use crate::gadgets::uint64::UInt64;
+use zwaves_primitives::uint64;
+
+
+// values of add, sub, lt, le of the gadget, and whether the system is satisfied
+fn circuit_ops(a: u64, b: u64) -> ((u64, bool), (u64, bool), bool, bool, bool) {
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let x = UInt64::alloc(cs.namespace(|| "a"), Some(a)).unwrap();
+    let y = UInt64::alloc(cs.namespace(|| "b"), Some(b)).unwrap();
+    let (s, carry) = x.add(cs.namespace(|| "add"), &y).unwrap();
+    let (d, borrow) = x.sub(cs.namespace(|| "sub"), &y).unwrap();
+    let lt = x.lt(cs.namespace(|| "lt"), &y).unwrap();
+    let le = x.le(cs.namespace(|| "le"), &y).unwrap();
+    (
+        (s.get_value().unwrap(), carry.get_value().unwrap()),
+        (d.get_value().unwrap(), borrow.get_value().unwrap()),
+        lt.get_value().unwrap(),
+        le.get_value().unwrap(),
+        cs.is_satisfied()
+    )
+}
+
+fn checked_satisfied(a: u64, b: u64, add: bool) -> bool {
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let x = UInt64::alloc(cs.namespace(|| "a"), Some(a)).unwrap();
+    let y = UInt64::alloc(cs.namespace(|| "b"), Some(b)).unwrap();
+    if add {
+        x.add_checked(cs.namespace(|| "add"), &y).unwrap();
+    } else {
+        x.sub_checked(cs.namespace(|| "sub"), &y).unwrap();
+    }
+    cs.is_satisfied()
+}
+
+#[test]
+fn test_native_and_circuit_uint64_agree() {
+    let mut rng = OsRng::new().unwrap();
+    let max = u64::max_value();
+    for (a, b) in [(rng.gen(), rng.gen()), (max, 1), (0, 1), (7, 7), (max, max)].iter() {
+        let (na, nb) = (uint64::UInt64(*a), uint64::UInt64(*b));
+        let (s, carry) = na.add(nb);
+        let (d, borrow) = na.sub(nb);
+        assert!(circuit_ops(*a, *b) == ((s.0, carry), (d.0, borrow), na.lt(nb), na.le(nb), true));
+        assert!(checked_satisfied(*a, *b, true) == na.add_checked(nb).is_ok(), "Checked add should fail on both sides");
+        assert!(checked_satisfied(*a, *b, false) == na.sub_checked(nb).is_ok(), "Checked sub should fail on both sides");
+    }
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let mut too_big = uint64::UInt64(max).to_fr::<Fr>();
+    too_big.add_assign(&Fr::one());
+    assert!(uint64::UInt64::from_fr(&too_big).is_err());
+    let num = AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(too_big)).unwrap();
+    UInt64::from_num(cs.namespace(|| "range"), num).unwrap();
+    assert!(!cs.is_satisfied(), "Value above 64 bits should be rejected");
+}
@@ -0,0 +1,594 @@
+
+use pairing::{PrimeField, Field};
+use bellman::{SynthesisError, ConstraintSystem};
+use sapling_crypto::jubjub::{FixedGenerators, JubjubEngine, Unknown, edwards::Point};
+use sapling_crypto::jubjub::fs::{Fs, FsRepr};
+
+use sapling_crypto::circuit::num::{AllocatedNum, Num};
+use sapling_crypto::circuit::boolean::{Boolean, AllocatedBit};
+use sapling_crypto::circuit::{ecc, blake2s, pedersen_hash};
+use sapling_crypto::constants;
+
+use crate::gadgets::bitify::{from_bits_le_to_num_limited, from_bits_le_to_num};
+use crate::gadgets::{merkle_proof};
+use zwaves_primitives::pedersen_hasher::leaf_personalization;
+use zwaves_primitives::transactions::{ACCOUNT_PRF_PERSONALIZATION, ACCOUNT_HASH_DOMAIN, EPOCH_NF_PERSONALIZATION, CONDITION_PERSONALIZATION, CONDITION_OWNER_PERSONALIZATION, THRESHOLD_OWNER_PERSONALIZATION};
+
+use arrayvec::ArrayVec;
+use std::ops::{Add, Sub};
+
+
+pub struct Note<E: JubjubEngine> {
+    pub asset_id: AllocatedNum<E>,       // 64 bits
+    pub amount: AllocatedNum<E>,        // 64 bits
+    pub native_amount: AllocatedNum<E>,  // 64 bits
+    pub txid: AllocatedNum<E>,          // 255 bits
+    pub owner: AllocatedNum<E>          // 255 bits
+}
+
+
+pub struct Account<E: JubjubEngine> {
+    pub asset_id: AllocatedNum<E>,       // 64 bits
+    pub balance: AllocatedNum<E>,        // 64 bits
+    pub native_balance: AllocatedNum<E>, // 64 bits
+    pub nonce: AllocatedNum<E>,          // 48 bits, the whole leaf should fit into 4 pedersen segments
+    pub owner: AllocatedNum<E>           // 255 bits
+}
+
+
+pub fn asset_unpack<E: JubjubEngine, CS>(
+    mut cs: CS,
+    packed_asset: &AllocatedNum<E>
+) -> Result<(AllocatedNum<E>, AllocatedNum<E>, AllocatedNum<E>), SynthesisError>
+where CS: ConstraintSystem<E>
+{
+    let packed_asset_bits = packed_asset.into_bits_le_limited(cs.namespace(|| "bitify packed_asset"), 192)?;
+
+    let asset_id = from_bits_le_to_num_limited(cs.namespace(|| "preparing asset_id"), &packed_asset_bits[0..64], 64)?;
+    let amount = from_bits_le_to_num_limited(cs.namespace(|| "preparing amount"), &packed_asset_bits[64..128], 64)?;
+    let native_amount = from_bits_le_to_num_limited(cs.namespace(|| "preparing native_amount"), &packed_asset_bits[128..192], 64)?;
+
+    Ok((asset_id, amount, native_amount))
+}
+
+
+
+pub fn signed_asset_unpack<E: JubjubEngine, CS>(
+    mut cs: CS,
+    packed_asset: &AllocatedNum<E>
+) -> Result<(AllocatedNum<E>, AllocatedNum<E>, AllocatedNum<E>), SynthesisError>
+where CS: ConstraintSystem<E>
+{
+
+    let packed_asset_bits = packed_asset.into_bits_le_limited(cs.namespace(|| "bitify packed_asset"), 192)?;
+
+    let asset_id = from_bits_le_to_num_limited(cs.namespace(|| "preparing asset_id"), &packed_asset_bits[0..64], 64)?;
+    let amount = from_bits_le_to_num_limited(cs.namespace(|| "preparing amount"), &packed_asset_bits[64..128], 64)?;
+    let native_amount = from_bits_le_to_num_limited(cs.namespace(|| "preparing native_amount"), &packed_asset_bits[128..192], 64)?;
+
+    let minus_64_num = E::Fr::from_str("52435875175126190479447740508185965837690552500527637822585211955864871632897").unwrap();
+
+    let mut signed_amount_num = Num::<E>::zero();
+    signed_amount_num = signed_amount_num.add_bool_with_coeff(CS::one(), &packed_asset_bits[127], minus_64_num) + amount;
+    let signed_amont = AllocatedNum::alloc(cs.namespace(|| "alloc signed amount"), || signed_amount_num.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+
+
+    let mut signed_native_amount_num = Num::<E>::zero();
+    signed_native_amount_num = signed_native_amount_num.add_bool_with_coeff(CS::one(), &packed_asset_bits[191], minus_64_num) + native_amount;
+    let signed_native_amount = AllocatedNum::alloc(cs.namespace(|| "alloc signed native_amount"), || signed_native_amount_num.get_value().ok_or(SynthesisError::AssignmentMissing))?;
+
+    Ok((asset_id, signed_amont, signed_native_amount))
+}
+
+pub fn note_hash<E: JubjubEngine, CS>(
+    mut cs: CS,
+    note: &Note<E>,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let mut total_bits = vec![];
+    total_bits.extend(note.asset_id.into_bits_le_limited(cs.namespace(|| "bitify assetId into 64 bits"), 64)?);
+    total_bits.extend(note.amount.into_bits_le_limited(cs.namespace(|| "bitify amount into 64 bits"), 64)?);
+    total_bits.extend(note.native_amount.into_bits_le_limited(cs.namespace(|| "bitify nativeAmount into 64 bits"), 64)?);
+    total_bits.extend(note.txid.into_bits_le_strict(cs.namespace(|| "bitify txId"))?);
+    total_bits.extend(note.owner.into_bits_le_strict(cs.namespace(|| "bitify owner"))?);
+    assert!(total_bits.len()==702);
+
+    let res = pedersen_hash::pedersen_hash(
+                cs.namespace(|| "res <== pedersen_hash(total_bits)"),
+                pedersen_hash::Personalization::NoteCommitment,
+                &total_bits,
+                params
+            )?.get_x().clone();
+
+    Ok(res)
+}
+
+
+pub fn pubkey<E: JubjubEngine, CS>(
+    mut cs: CS,
+    sk: &[Boolean],
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let res = ecc::fixed_base_multiplication(
+        cs.namespace(|| "public key computation"),
+        FixedGenerators::SpendingKeyGenerator,
+        &sk,
+        params
+    )?.get_x().clone();
+
+    Ok(res)
+}
+
+
+
+// rk = ak + alpha*G, see zwaves_primitives::spend_auth
+pub fn randomized_pubkey<E: JubjubEngine, CS>(
+    mut cs: CS,
+    ak: &ecc::EdwardsPoint<E>,
+    alpha: &[Boolean],
+    params: &E::Params
+) -> Result<ecc::EdwardsPoint<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let alpha_g = ecc::fixed_base_multiplication(
+        cs.namespace(|| "alpha computation"),
+        FixedGenerators::SpendingKeyGenerator,
+        alpha,
+        params
+    )?;
+
+    ak.add(cs.namespace(|| "rk computation"), &alpha_g, params)
+}
+
+
+pub fn nullifier_key<E: JubjubEngine, CS>(
+    mut cs: CS,
+    sk: &[Boolean],
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let res = ecc::fixed_base_multiplication(
+        cs.namespace(|| "nullifier key computation"),
+        FixedGenerators::ProofGenerationKey,
+        &sk,
+        params
+    )?.get_x().clone();
+
+    Ok(res)
+}
+
+
+fn prf<E: JubjubEngine, CS>(
+    mut cs: CS,
+    x: &AllocatedNum<E>,
+    key: &AllocatedNum<E>,
+    tail: &[Boolean],
+    personalization: &[u8]
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let x_bits = x.into_bits_le_strict(cs.namespace(|| "x bitification"))?;
+    let key_bits = key.into_bits_le_strict(cs.namespace(|| "key bitification"))?;
+
+    let mut preimage = vec![];
+    let x_len = x_bits.len();
+    let key_len = key_bits.len();
+    preimage.extend(x_bits);
+    preimage.extend((0..256-x_len).map(|_| Boolean::Constant(false) ));
+    preimage.extend(key_bits);
+    preimage.extend((0..256-key_len).map(|_| Boolean::Constant(false) ));
+    preimage.extend(tail.iter().cloned());
+
+    let bitrepr = blake2s::blake2s(
+        cs.namespace(|| "prf computation"),
+        &preimage,
+        personalization
+    )?;
+
+
+    let res = from_bits_le_to_num(cs.namespace(|| "compress bitrepr"), &bitrepr)?;
+    Ok(res)
+}
+
+
+pub fn nullifier_from_key<E: JubjubEngine, CS>(
+    mut cs: CS,
+    nh: &AllocatedNum<E>,
+    nk: &AllocatedNum<E>
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    prf(cs.namespace(|| "nf computation"), nh, nk, &[], constants::PRF_NF_PERSONALIZATION)
+}
+
+
+pub fn nullifier<E: JubjubEngine, CS>(
+    mut cs: CS,
+    nh: &AllocatedNum<E>,
+    sk: &[Boolean],
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let nk = nullifier_key(cs.namespace(|| "nullifier key"), sk, params)?;
+    nullifier_from_key(cs.namespace(|| "nullifier from key"), nh, &nk)
+}
+
+
+// epoch is a 64 bit number, see transactions::epoch_nullifier_from_key
+pub fn epoch_nullifier_from_key<E: JubjubEngine, CS>(
+    mut cs: CS,
+    nh: &AllocatedNum<E>,
+    nk: &AllocatedNum<E>,
+    epoch: &AllocatedNum<E>
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let epoch_bits = epoch.into_bits_le_limited(cs.namespace(|| "bitify epoch"), 64)?;
+    prf(cs.namespace(|| "epoch nf computation"), nh, nk, &epoch_bits, EPOCH_NF_PERSONALIZATION)
+}
+
+
+pub fn epoch_nullifier<E: JubjubEngine, CS>(
+    mut cs: CS,
+    nh: &AllocatedNum<E>,
+    sk: &[Boolean],
+    epoch: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let nk = nullifier_key(cs.namespace(|| "nullifier key"), sk, params)?;
+    epoch_nullifier_from_key(cs.namespace(|| "epoch nullifier from key"), nh, &nk, epoch)
+}
+
+
+// type_id is a constant of the circuit, see transactions::condition_hash
+pub fn condition_hash<E: JubjubEngine, CS>(
+    mut cs: CS,
+    type_id: u64,
+    params_hash: &AllocatedNum<E>
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let id = E::Fr::from_repr(type_id.into()).unwrap();
+    let type_num = AllocatedNum::alloc(cs.namespace(|| "alloc type id"), || Ok(id))?;
+    cs.enforce(|| "enforce type id", |lc| lc + type_num.get_variable(), |lc| lc + CS::one(), |lc| lc + (id, CS::one()));
+    prf(cs.namespace(|| "condition hash computation"), params_hash, &type_num, &[], CONDITION_PERSONALIZATION)
+}
+
+
+pub fn conditioned_owner<E: JubjubEngine, CS>(
+    mut cs: CS,
+    pk: &AllocatedNum<E>,
+    condition_hash: &AllocatedNum<E>
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    prf(cs.namespace(|| "conditioned owner computation"), pk, condition_hash, &[], CONDITION_OWNER_PERSONALIZATION)
+}
+
+
+pub fn utxo_accumulator<E: JubjubEngine, CS>(
+    mut cs: CS,
+    note_hashes: &[AllocatedNum<E>],
+    index: &AllocatedNum<E>,
+    old_proof: &[AllocatedNum<E>],
+    new_proof: &[AllocatedNum<E>],
+    params: &E::Params
+) -> Result<(AllocatedNum<E>, AllocatedNum<E>), SynthesisError>
+where CS: ConstraintSystem<E> {
+    assert!(note_hashes.len() == 2, "should be 2 utxo");
+    let prooflen = old_proof.len();
+    assert!(new_proof.len() == prooflen, "proof length should be equal");
+
+    let bits = index.into_bits_le_limited(cs.namespace(|| "bitify index"), prooflen+1)?;
+
+    let old_proof = old_proof.iter().zip(bits.iter().skip(1)).map(|(n,b)| (n.clone(), b.clone())).collect::<Vec<_>>();
+    let new_proof = new_proof.iter().zip(bits.iter().skip(1)).map(|(n,b)| (n.clone(), b.clone())).collect::<Vec<_>>();
+    
+
+    let twozeros = E::Fr::from_str("2844901669415300281300718346195343338354231404922385839670861864158643284316").unwrap();
+    let twozeros_num = AllocatedNum::alloc(cs.namespace(|| "alloc twozeros_num"), || Ok(twozeros))?;
+    cs.enforce(|| "enforce twozeros_num", |lc| lc + twozeros_num.get_variable(), |lc| lc+CS::one(), |lc| lc + (twozeros, CS::one()));
+
+    let old_root = merkle_proof::merkle_proof_shifted(
+        cs.namespace(|| "compute merkle proof"), 
+        &old_proof, &twozeros_num, 1, params)?;
+
+    let twonotes = merkle_proof::compress(cs.namespace(|| "compress utxo"), 
+        pedersen_hash::Personalization::MerkleTree(0), &note_hashes[0], &note_hashes[1], params)?;
+
+    let new_root = merkle_proof::merkle_proof_shifted(
+            cs.namespace(|| "compute merkle proof"), 
+            &new_proof, &twonotes, 1, params)?;
+    
+    
+
+    Ok((old_root, new_root))
+}
+
+
+// What the input notes are spent with: ak for rk, the value of their owner field and nk
+pub struct SpendAuthority<E: JubjubEngine> {
+    pub ak: ecc::EdwardsPoint<E>,
+    pub owner: AllocatedNum<E>,
+    pub nk: AllocatedNum<E>
+}
+
+
+// Authority of a threshold group, see transactions::threshold_owner. ak is the group key.
+pub fn threshold_authority<E: JubjubEngine, CS>(
+    mut cs: CS,
+    ak: Option<Point<E, Unknown>>,
+    nk: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<SpendAuthority<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let ak = ecc::EdwardsPoint::witness(cs.namespace(|| "witness ak"), ak, params)?;
+    ak.assert_not_small_order(cs.namespace(|| "ak not small order"), params)?;
+    let owner = prf(cs.namespace(|| "threshold owner computation"), ak.get_x(), nk, &[], THRESHOLD_OWNER_PERSONALIZATION)?;
+    Ok(SpendAuthority { ak, owner, nk: nk.clone() })
+}
+
+
+pub fn transfer<E: JubjubEngine, CS>(
+    mut cs: CS,
+    in_note: &[Note<E>],
+    in_proof: &[Vec<(AllocatedNum<E>, Boolean)>],
+    out_note: &[Note<E>],
+    root_hash: &AllocatedNum<E>,
+    sk: &AllocatedNum<E>,
+    alpha: &AllocatedNum<E>,
+    packed_asset: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<(ArrayVec<[AllocatedNum<E>; 2]>, ArrayVec<[AllocatedNum<E>; 2]>, ecc::EdwardsPoint<E>), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let sk_bits = sk.into_bits_le_strict(cs.namespace(|| "bitify sk"))?;
+    let ak = ecc::fixed_base_multiplication(
+        cs.namespace(|| "pubkey compute"),
+        FixedGenerators::SpendingKeyGenerator,
+        &sk_bits,
+        params
+    )?;
+    let pk = ak.get_x().clone();
+    let nk = nullifier_key(cs.namespace(|| "nullifier key compute"), &sk_bits, params)?;
+
+    let authority = SpendAuthority { ak, owner: pk, nk };
+    transfer_with_authority(cs, in_note, in_proof, out_note, root_hash, &authority, alpha, packed_asset, params)
+}
+
+
+pub fn transfer_with_authority<E: JubjubEngine, CS>(
+    mut cs: CS,
+    in_note: &[Note<E>],
+    in_proof: &[Vec<(AllocatedNum<E>, Boolean)>],
+    out_note: &[Note<E>],
+    root_hash: &AllocatedNum<E>,
+    authority: &SpendAuthority<E>,
+    alpha: &AllocatedNum<E>,
+    packed_asset: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<(ArrayVec<[AllocatedNum<E>; 2]>, ArrayVec<[AllocatedNum<E>; 2]>, ecc::EdwardsPoint<E>), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert!(in_note.len()==2, "in_note length should be equal 2");
+    assert!(in_proof.len()==2, "in_proof length should be equal 2");
+    assert!(out_note.len()==2, "out_note length should be equal 2");
+    assert!(in_proof[0].len() == in_proof[1].len(), "vectors in proof should be the same length");
+
+    let pk = &authority.owner;
+    let nk = &authority.nk;
+
+    let alpha_bits = alpha.into_bits_le_strict(cs.namespace(|| "bitify alpha"))?;
+    let rk = randomized_pubkey(cs.namespace(|| "randomized pubkey compute"), &authority.ak, &alpha_bits, params)?;
+
+    let in_hash : Vec<_> = (0..2).map(|i| {
+        note_hash(cs.namespace(|| format!("hashing {} input", i)), &in_note[i], params).unwrap()
+    }).collect();
+
+    let in_root = (0..2).map( |i| {
+        merkle_proof::merkle_proof(
+            cs.namespace(|| format!("compute merkle proof for {} input", i)), 
+            &in_proof[i], 
+            &in_hash[i], 
+            params)
+    }).collect::<Result<Vec<_>,_>>()?;
+
+    let out_hash = (0..2).map(|i| note_hash(cs.namespace(|| format!("hashing {} output", i)), &out_note[i], params))
+        .collect::<Result<ArrayVec<[AllocatedNum<E>;2]>, SynthesisError>>()?;
+    
+    let nf = (0..2).map(|i| nullifier_from_key(
+        cs.namespace(|| format!("compute nullifier for {} input", i)), 
+        &in_hash[i],
+        nk))
+        .collect::<Result<ArrayVec<[AllocatedNum<E>;2]>, SynthesisError>>()?;
+    
+
+    for i in 0..2 {
+        
+        
+        cs.enforce(
+            || format!("cheking ownership for {} input", i),
+            |lc| lc + in_note[i].owner.get_variable(),
+            |lc| lc + CS::one(),
+            |lc| lc + pk.get_variable()
+        );
+        
+        cs.enforce(
+            || format!("verification of root for {} input", i), 
+            |lc| lc + root_hash.get_variable() - in_root[i].get_variable(), 
+            |lc| lc + in_note[i].amount.get_variable() + in_note[i].native_amount.get_variable(), 
+            |lc| lc); 
+
+
+        cs.enforce(
+            || format!("cheking asset id for {}th input and output must be the same", i),
+            |lc| lc + in_note[i].asset_id.get_variable(),
+            |lc| lc + CS::one(),
+            |lc| lc + out_note[i].asset_id.get_variable()
+        );
+    }
+
+    let (asset_id, asset_amount, asset_native_amount) = signed_asset_unpack(cs.namespace(|| "unpacking asset"), packed_asset)?;
+
+    cs.enforce(
+        || "check asset_id must be the same as for first input and output for nonzero asset_amount", 
+        |lc| lc + in_note[0].asset_id.get_variable() - asset_id.get_variable(), 
+        |lc| lc + asset_amount.get_variable(), 
+        |lc| lc
+    );
+    
+
+    cs.enforce(
+        || "verification of native amount sum",
+        |lc| lc + in_note[0].native_amount.get_variable() + in_note[1].native_amount.get_variable() + asset_native_amount.get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc + out_note[0].native_amount.get_variable() + out_note[1].native_amount.get_variable()
+    );
+
+
+    cs.enforce(
+        || "verification of amount sum",
+        |lc| lc + in_note[0].amount.get_variable() + in_note[1].amount.get_variable() + asset_amount.get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc + out_note[0].amount.get_variable() + out_note[1].amount.get_variable()
+    );
+
+    cs.enforce(
+        || "verification of second elements amount sum in case with different amount types",
+        |lc| lc + in_note[1].amount.get_variable() - out_note[1].amount.get_variable(),
+        |lc| lc + in_note[0].asset_id.get_variable() - in_note[1].asset_id.get_variable(),
+        |lc| lc 
+    );
+
+    (Num::zero() + nf[0].clone() - nf[1].clone()).assert_nonzero(cs.namespace(|| "doublespend protection"))?;
+    
+
+    Ok((out_hash, nf, rk))
+}
+
+
+
+pub fn account_hash<E: JubjubEngine, CS>(
+    mut cs: CS,
+    account: &Account<E>,
+    nk: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let blinding = prf(cs.namespace(|| "account blinding"), &account.nonce, nk, &[], ACCOUNT_PRF_PERSONALIZATION)?;
+
+    let mut total_bits = vec![];
+    total_bits.extend(account.asset_id.into_bits_le_limited(cs.namespace(|| "bitify assetId into 64 bits"), 64)?);
+    total_bits.extend(account.balance.into_bits_le_limited(cs.namespace(|| "bitify balance into 64 bits"), 64)?);
+    total_bits.extend(account.native_balance.into_bits_le_limited(cs.namespace(|| "bitify nativeBalance into 64 bits"), 64)?);
+    total_bits.extend(account.nonce.into_bits_le_limited(cs.namespace(|| "bitify nonce into 48 bits"), 48)?);
+    total_bits.extend(account.owner.into_bits_le_strict(cs.namespace(|| "bitify owner"))?);
+    total_bits.extend(blinding.into_bits_le_strict(cs.namespace(|| "bitify blinding"))?);
+    assert!(total_bits.len()==750);
+
+    let res = pedersen_hash::pedersen_hash(
+                cs.namespace(|| "res <== pedersen_hash(total_bits)"),
+                leaf_personalization(ACCOUNT_HASH_DOMAIN),
+                &total_bits,
+                params
+            )?.get_x().clone();
+
+    Ok(res)
+}
+
+
+fn alloc_sum<E: JubjubEngine, CS>(
+    mut cs: CS,
+    a: &AllocatedNum<E>,
+    b: &AllocatedNum<E>
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let res = AllocatedNum::alloc(cs.namespace(|| "alloc sum"), || {
+        let mut res = a.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        res.add_assign(&b.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+        Ok(res)
+    })?;
+
+    cs.enforce(
+        || "enforce sum",
+        |lc| lc + a.get_variable() + b.get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc + res.get_variable()
+    );
+
+    Ok(res)
+}
+
+
+// Applies packed_asset to the account and moves its leaf from old_root to new_root.
+// Balances stay 64-bit in the new leaf, so an overdraft could not be proven.
+// Returns (old_root, new_root, new account hash).
+pub fn account_update<E: JubjubEngine, CS>(
+    mut cs: CS,
+    account: &Account<E>,
+    proof: &[(AllocatedNum<E>, Boolean)],
+    sk: &AllocatedNum<E>,
+    packed_asset: &AllocatedNum<E>,
+    params: &E::Params
+) -> Result<(AllocatedNum<E>, AllocatedNum<E>, AllocatedNum<E>), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let sk_bits = sk.into_bits_le_strict(cs.namespace(|| "bitify sk"))?;
+    let pk = pubkey(cs.namespace(|| "pubkey compute"), &sk_bits, params)?;
+    let nk = nullifier_key(cs.namespace(|| "nullifier key compute"), &sk_bits, params)?;
+
+    cs.enforce(
+        || "cheking ownership of account",
+        |lc| lc + account.owner.get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc + pk.get_variable()
+    );
+
+    let old_hash = account_hash(cs.namespace(|| "hashing old account"), account, &nk, params)?;
+    let old_root = merkle_proof::merkle_proof(cs.namespace(|| "compute old merkle root"), proof, &old_hash, params)?;
+
+    let (asset_id, asset_amount, asset_native_amount) = signed_asset_unpack(cs.namespace(|| "unpacking asset"), packed_asset)?;
+
+    cs.enforce(
+        || "check asset_id must be the same as for account for nonzero asset_amount",
+        |lc| lc + account.asset_id.get_variable() - asset_id.get_variable(),
+        |lc| lc + asset_amount.get_variable(),
+        |lc| lc
+    );
+
+    let new_nonce = AllocatedNum::alloc(cs.namespace(|| "alloc new nonce"), || {
+        let mut res = account.nonce.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        res.add_assign(&E::Fr::one());
+        Ok(res)
+    })?;
+    cs.enforce(
+        || "new nonce = nonce + 1",
+        |lc| lc + account.nonce.get_variable() + CS::one(),
+        |lc| lc + CS::one(),
+        |lc| lc + new_nonce.get_variable()
+    );
+
+    let new_account = Account {
+        asset_id: account.asset_id.clone(),
+        balance: alloc_sum(cs.namespace(|| "new balance"), &account.balance, &asset_amount)?,
+        native_balance: alloc_sum(cs.namespace(|| "new native balance"), &account.native_balance, &asset_native_amount)?,
+        nonce: new_nonce,
+        owner: account.owner.clone()
+    };
+
+    let new_hash = account_hash(cs.namespace(|| "hashing new account"), &new_account, &nk, params)?;
+    let new_root = merkle_proof::merkle_proof(cs.namespace(|| "compute new merkle root"), proof, &new_hash, params)?;
+
+    Ok((old_root, new_root, new_hash))
+}
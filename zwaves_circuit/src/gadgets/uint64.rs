@@ -0,0 +1,87 @@
+use bellman::{SynthesisError, ConstraintSystem};
+
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::circuit::boolean::Boolean;
+
+use crate::gadgets::limbs;
+use zwaves_primitives::limbs::LIMB_BITS;
+use zwaves_primitives::field;
+
+
+// A number range checked to 64 bits, with the arithmetic of uint64::UInt64. Balances are
+// written with it instead of field operations: a + b below the field modulus is not a 64 bit
+// value, add_checked and sub_checked are unsatisfied exactly where the native ones fail.
+#[derive(Clone)]
+pub struct UInt64<E: JubjubEngine> {
+    num: AllocatedNum<E>
+}
+
+impl<E: JubjubEngine> UInt64<E> {
+    pub fn alloc<CS>(mut cs: CS, value: Option<u64>) -> Result<Self, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        let num = AllocatedNum::alloc(cs.namespace(|| "alloc value"), || value.map(field::from_u64).ok_or(SynthesisError::AssignmentMissing))?;
+        Self::from_num(cs.namespace(|| "range check"), num)
+    }
+
+    // Range checks a number of the circuit
+    pub fn from_num<CS>(cs: CS, num: AllocatedNum<E>) -> Result<Self, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        num.into_bits_le_limited(cs, LIMB_BITS)?;
+        Ok(UInt64 { num })
+    }
+
+    pub fn num(&self) -> &AllocatedNum<E> {
+        &self.num
+    }
+
+    pub fn get_value(&self) -> Option<u64> {
+        self.num.get_value().and_then(|v| field::to_u64(&v))
+    }
+
+    // Sum modulo 2^64 and the carry out
+    pub fn add<CS>(&self, cs: CS, other: &Self) -> Result<(Self, Boolean), SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        let (mut res, carry) = limbs::add(cs, &[self.num.clone()], &[other.num.clone()])?;
+        Ok((UInt64 { num: res.remove(0) }, carry))
+    }
+
+    pub fn add_checked<CS>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        let (res, carry) = self.add(cs.namespace(|| "add"), other)?;
+        Boolean::enforce_equal(cs.namespace(|| "no carry"), &carry, &Boolean::constant(false))?;
+        Ok(res)
+    }
+
+    // Difference modulo 2^64 and the borrow, set iff self < other
+    pub fn sub<CS>(&self, cs: CS, other: &Self) -> Result<(Self, Boolean), SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        let (mut res, borrow) = limbs::sub(cs, &[self.num.clone()], &[other.num.clone()])?;
+        Ok((UInt64 { num: res.remove(0) }, borrow))
+    }
+
+    pub fn sub_checked<CS>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        let (res, borrow) = self.sub(cs.namespace(|| "sub"), other)?;
+        Boolean::enforce_equal(cs.namespace(|| "no borrow"), &borrow, &Boolean::constant(false))?;
+        Ok(res)
+    }
+
+    pub fn lt<CS>(&self, cs: CS, other: &Self) -> Result<Boolean, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        Ok(self.sub(cs, other)?.1)
+    }
+
+    pub fn le<CS>(&self, cs: CS, other: &Self) -> Result<Boolean, SynthesisError>
+        where CS: ConstraintSystem<E>
+    {
+        Ok(other.lt(cs, self)?.not())
+    }
+}
@@ -1,4 +1,20 @@
+// Re-exported so a consumer of the circuits needs a single dependency for the native side too
+pub use zwaves_primitives;
+
 #[cfg(test)]
 pub mod test;
 
-pub mod circuit;
\ No newline at end of file
+pub mod circuit;
+pub mod gadgets;
+pub mod setup;
+pub mod bench;
+pub mod policy;
+pub mod detached;
+pub mod validate;
+pub mod padding;
+pub mod refresh;
+pub mod proving;
+pub mod prover;
+#[cfg(feature = "marlin")]
+pub mod marlin;
+
@@ -0,0 +1,995 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError, Variable, Index, LinearCombination};
+use pairing::bls12_381::{Bls12, Fr, FrRepr, Fq12, G1, G1Affine, G2Affine};
+use pairing::{Engine, CurveAffine, CurveProjective, Field, PrimeField, PrimeFieldRepr};
+use blake2_rfc::blake2s::Blake2s;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use rand::Rng;
+
+use std::io::{self, Read, Write};
+
+use zwaves_primitives::fieldtools;
+use zwaves_primitives::point::read_point;
+use zwaves_primitives::serialization::read_fr_repr_be;
+use zwaves_primitives::metrics::{self, Counter, Timing};
+
+use crate::proving::ProvingSystem;
+
+
+// Experimental backend with a universal setup, after Marlin (Chiesa et al. 2019): one SRS of
+// powers of tau serves every circuit up to its degree, and the keys of a circuit are derived from
+// it by anyone (index), so a circuit update needs no new ceremony. It is a slow reference for
+// measuring proof sizes and verification costs against Groth16, not a production prover:
+//
+// - it is NOT zero-knowledge, the witness polynomials are not masked and the evaluations in a
+//   proof leak the witness. It must not be used with real notes;
+// - polynomial arithmetic and commitments are serial and naive (no multiexp, no threads);
+// - the matrices A, B, C are indexed separately (9 index polynomials) as in the paper, not
+//   merged.
+//
+// The circuit is the R1CS of the bellman circuit, A z * B z = C z for z = (1, inputs, aux),
+// over the domain H of the rows and columns; the public part of z lives on the subgroup H_x of H.
+// The proof is the AHP of Marlin made non-interactive with a blake2s transcript, its oracles
+// are KZG commitments and the degree bounds of g_1 and g_2 are enforced by commitments shifted
+// to the top of the SRS.
+//
+// Verifying key (big-endian): version u8 | log |H| u8 | log |H_x| u8 | log |K| u8 |
+// u32 inputs | u32 max degree | 9 index commitments | g | h | tau h (compressed).
+// Proof: 10 commitments | sigma | 19 evaluations | 2 openings (field elements 32 bytes each).
+pub const MARLIN_VK_VERSION: u8 = 1;
+pub const MARLIN_PERSONALIZATION: &[u8; 8] = b"ZWmarlin";
+pub const MAX_MARLIN_DEGREE: usize = 1 << 26;
+
+const PROOF_COMMITMENTS: usize = 10;
+const PROOF_EVALUATIONS: usize = 19;
+
+
+// Multiplicative subgroup of size 2^log
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Domain {
+    size: usize,
+    log: u32,
+    omega: Fr,
+    omega_inv: Fr,
+    size_inv: Fr
+}
+
+impl Domain {
+    fn from_log(log: u32) -> io::Result<Self> {
+        // log comes from a verifying key, 1 << log must not overflow
+        if log >= usize::BITS || log > Fr::S || (1usize << log) > MAX_MARLIN_DEGREE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "domain is too large"));
+        }
+        let mut omega = Fr::root_of_unity();
+        for _ in log..Fr::S {
+            omega.square();
+        }
+        let size = 1usize << log;
+        Ok(Domain {
+            size,
+            log,
+            omega,
+            omega_inv: omega.inverse().unwrap(),
+            size_inv: Fr::from_repr(FrRepr::from(size as u64)).unwrap().inverse().unwrap()
+        })
+    }
+
+    // smallest domain of at least min_size elements, 2 at least
+    fn new(min_size: usize) -> io::Result<Self> {
+        let mut log = 1;
+        while (1usize << log) < min_size {
+            log += 1;
+            if log > Fr::S {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "domain is too large"));
+            }
+        }
+        Self::from_log(log)
+    }
+
+    fn elements(&self) -> Vec<Fr> {
+        powers(&self.omega, self.size)
+    }
+
+    // X^n - 1
+    fn vanishing(&self, x: &Fr) -> Fr {
+        let mut res = x.pow([self.size as u64]);
+        res.sub_assign(&Fr::one());
+        res
+    }
+
+    fn fft(&self, coeffs: &[Fr]) -> Vec<Fr> {
+        assert!(coeffs.len() <= self.size);
+        let mut a = coeffs.to_vec();
+        a.resize(self.size, Fr::zero());
+        serial_fft(&mut a, &self.omega, self.log);
+        a
+    }
+
+    fn ifft(&self, evals: &[Fr]) -> Vec<Fr> {
+        assert!(evals.len() == self.size);
+        let mut a = evals.to_vec();
+        serial_fft(&mut a, &self.omega_inv, self.log);
+        a.iter_mut().for_each(|x| x.mul_assign(&self.size_inv));
+        a
+    }
+}
+
+fn serial_fft(a: &mut [Fr], omega: &Fr, log_n: u32) {
+    fn bitreverse(mut n: usize, l: u32) -> usize {
+        let mut r = 0;
+        for _ in 0..l {
+            r = (r << 1) | (n & 1);
+            n >>= 1;
+        }
+        r
+    }
+
+    let n = a.len();
+    for k in 0..n {
+        let rk = bitreverse(k, log_n);
+        if k < rk {
+            a.swap(rk, k);
+        }
+    }
+
+    let mut m = 1;
+    for _ in 0..log_n {
+        let w_m = omega.pow([(n / (2 * m)) as u64]);
+        let mut k = 0;
+        while k < n {
+            let mut w = Fr::one();
+            for j in 0..m {
+                let mut t = a[k + j + m];
+                t.mul_assign(&w);
+                let mut tmp = a[k + j];
+                tmp.sub_assign(&t);
+                a[k + j + m] = tmp;
+                a[k + j].add_assign(&t);
+                w.mul_assign(&w_m);
+            }
+            k += 2 * m;
+        }
+        m *= 2;
+    }
+}
+
+
+// Polynomials are coefficient vectors, lowest first
+
+fn powers(x: &Fr, n: usize) -> Vec<Fr> {
+    let mut res = Vec::with_capacity(n);
+    let mut p = Fr::one();
+    for _ in 0..n {
+        res.push(p);
+        p.mul_assign(x);
+    }
+    res
+}
+
+fn poly_eval(p: &[Fr], x: &Fr) -> Fr {
+    let mut res = Fr::zero();
+    for c in p.iter().rev() {
+        res.mul_assign(x);
+        res.add_assign(c);
+    }
+    res
+}
+
+// p + s * q
+fn poly_add_scaled(p: &[Fr], q: &[Fr], s: &Fr) -> Vec<Fr> {
+    let mut res = p.to_vec();
+    if res.len() < q.len() {
+        res.resize(q.len(), Fr::zero());
+    }
+    for (r, c) in res.iter_mut().zip(q.iter()) {
+        let mut c = *c;
+        c.mul_assign(s);
+        r.add_assign(&c);
+    }
+    res
+}
+
+fn poly_mul(p: &[Fr], q: &[Fr]) -> Vec<Fr> {
+    if p.is_empty() || q.is_empty() {
+        return vec![];
+    }
+    let len = p.len() + q.len() - 1;
+    let d = Domain::new(len).unwrap();
+    let (pe, qe) = (d.fft(p), d.fft(q));
+    let mut res = d.ifft(&pe.iter().zip(qe.iter()).map(|(a, b)| { let mut a = *a; a.mul_assign(b); a }).collect::<Vec<_>>());
+    res.truncate(len);
+    res
+}
+
+// quotient and remainder by X^n - 1
+fn poly_div_vanishing(p: &[Fr], n: usize) -> (Vec<Fr>, Vec<Fr>) {
+    let mut rem = p.to_vec();
+    if rem.len() <= n {
+        rem.resize(n, Fr::zero());
+        return (vec![], rem);
+    }
+    let mut quot = vec![Fr::zero(); rem.len() - n];
+    for i in (n..rem.len()).rev() {
+        let c = rem[i];
+        quot[i - n].add_assign(&c);
+        rem[i - n].add_assign(&c);
+    }
+    rem.truncate(n);
+    (quot, rem)
+}
+
+// (p - p(z)) / (X - z)
+fn poly_div_linear(p: &[Fr], z: &Fr) -> Vec<Fr> {
+    if p.len() < 2 {
+        return vec![];
+    }
+    let mut quot = vec![Fr::zero(); p.len() - 1];
+    let mut acc = Fr::zero();
+    for i in (1..p.len()).rev() {
+        acc.mul_assign(z);
+        acc.add_assign(&p[i]);
+        quot[i - 1] = acc;
+    }
+    quot
+}
+
+// X^shift p
+fn poly_shift(p: &[Fr], shift: usize) -> Vec<Fr> {
+    let mut res = vec![Fr::zero(); shift];
+    res.extend_from_slice(p);
+    res
+}
+
+
+// Powers tau^i g for i up to the max degree, and h, tau h. The trapdoor is known to whoever runs
+// new_insecure, so it is for tests; a real SRS comes from a powers of tau ceremony, and one is
+// enough for every circuit of degree up to max_degree (see circuit_degree).
+#[derive(Clone, Debug, PartialEq)]
+pub struct UniversalSrs {
+    pub powers: Vec<G1Affine>,
+    pub h: G2Affine,
+    pub tau_h: G2Affine
+}
+
+impl UniversalSrs {
+    pub fn new_insecure<R: Rng>(max_degree: usize, rng: &mut R) -> io::Result<Self> {
+        if max_degree > MAX_MARLIN_DEGREE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "degree is too large"));
+        }
+        let tau = rng.gen::<Fr>();
+        let g = G1Affine::one();
+        let mut p = powers(&tau, max_degree + 1).iter().map(|t| g.mul(t.into_repr())).collect::<Vec<_>>();
+        G1::batch_normalization(&mut p);
+        Ok(UniversalSrs {
+            powers: p.iter().map(|x| x.into_affine()).collect(),
+            h: G2Affine::one(),
+            tau_h: G2Affine::one().mul(tau.into_repr()).into_affine()
+        })
+    }
+
+    pub fn max_degree(&self) -> usize {
+        self.powers.len() - 1
+    }
+}
+
+// the vectors may have zeros above the degree, as the products computed over a domain
+fn commit(powers: &[G1Affine], p: &[Fr]) -> io::Result<G1Affine> {
+    if p.iter().skip(powers.len()).any(|c| !c.is_zero()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "polynomial degree is above the SRS"));
+    }
+    let mut res = G1::zero();
+    for (g, c) in powers.iter().zip(p.iter()) {
+        if !c.is_zero() {
+            res.add_assign(&g.mul(c.into_repr()));
+        }
+    }
+    Ok(res.into_affine())
+}
+
+
+// The constraints of a circuit, and the values of z when synthesized with a witness.
+// inputs[0] is the constant one.
+struct R1cs {
+    witness: bool,
+    inputs: Vec<Fr>,
+    aux: Vec<Fr>,
+    constraints: Vec<[LinearCombination<Bls12>; 3]>
+}
+
+impl R1cs {
+    fn synthesize<C: Circuit<Bls12>>(circuit: C, witness: bool) -> io::Result<Self> {
+        let mut cs = R1cs { witness, inputs: vec![Fr::one()], aux: vec![], constraints: vec![] };
+        circuit.synthesize(&mut cs).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(cs)
+    }
+}
+
+impl ConstraintSystem<Bls12> for R1cs {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.aux.push(if self.witness { f()? } else { Fr::zero() });
+        Ok(Variable::new_unchecked(Index::Aux(self.aux.len() - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.inputs.push(if self.witness { f()? } else { Fr::zero() });
+        Ok(Variable::new_unchecked(Index::Input(self.inputs.len() - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+        LA: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LB: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LC: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>
+    {
+        self.constraints.push([a(LinearCombination::zero()), b(LinearCombination::zero()), c(LinearCombination::zero())]);
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N) where NR: Into<String>, N: FnOnce() -> NR {}
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+
+// Domains of a circuit: H for the rows and the columns, H_x for the public inputs (with the
+// one), K for the nonzero entries of the largest matrix. The inputs take the columns of H_x, at
+// every stride-th element of H, the aux variables fill the columns between them in order.
+struct Shape {
+    h: Domain,
+    x: Domain,
+    k: Domain,
+    // per matrix, the nonzero entries (row, column, coefficient)
+    matrices: [Vec<(usize, usize, Fr)>; 3]
+}
+
+impl Shape {
+    fn new(cs: &R1cs) -> io::Result<Self> {
+        let x = Domain::new(cs.inputs.len())?;
+        let h = Domain::new(cs.constraints.len().max(x.size + cs.aux.len()))?;
+        let mut shape = Shape { h, x, k: x, matrices: Default::default() };
+        for (r, lcs) in cs.constraints.iter().enumerate() {
+            for (i, lc) in lcs.iter().enumerate() {
+                let entries = lc.as_ref().iter().map(|(v, c)| (r, shape.column(v.get_unchecked()), *c)).collect::<Vec<_>>();
+                shape.matrices[i].extend(entries);
+            }
+        }
+        shape.k = Domain::new(shape.matrices.iter().map(|m| m.len()).max().unwrap())?;
+        Ok(shape)
+    }
+
+    fn column(&self, v: Index) -> usize {
+        let stride = self.h.size / self.x.size;
+        match v {
+            Index::Input(i) => i * stride,
+            Index::Aux(i) => i / (stride - 1) * stride + i % (stride - 1) + 1
+        }
+    }
+
+    fn degree(&self) -> usize {
+        required_degree(&self.h, &self.x, &self.k)
+    }
+}
+
+// z is at most of degree |H| - 1 + |H_x|, h_2 of 6 |K|
+fn required_degree(h: &Domain, x: &Domain, k: &Domain) -> usize {
+    (h.size + x.size).max(6 * k.size)
+}
+
+// Degree of the smallest SRS which can index the circuit
+pub fn circuit_degree<C: Circuit<Bls12>>(circuit: C) -> io::Result<usize> {
+    Ok(Shape::new(&R1cs::synthesize(circuit, false)?)?.degree())
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarlinVerifyingKey {
+    pub h_log: u32,
+    pub x_log: u32,
+    pub k_log: u32,
+    // public inputs, without the one
+    pub num_inputs: usize,
+    // of the SRS, the degree bounds are enforced against it
+    pub max_degree: usize,
+    // per matrix A, B, C: row, col, val
+    pub index_commitments: [[G1Affine; 3]; 3],
+    pub g: G1Affine,
+    pub h: G2Affine,
+    pub tau_h: G2Affine
+}
+
+// The prover keeps the whole SRS, the shifted commitments reach its top
+pub struct MarlinParameters {
+    pub vk: MarlinVerifyingKey,
+    powers: Vec<G1Affine>,
+    shape: Shape,
+    // per matrix: row, col, val as polynomials and as evaluations over K
+    index_polys: [[Vec<Fr>; 3]; 3],
+    index_evals: [[Vec<Fr>; 3]; 3]
+}
+
+// Keys of a circuit from the universal SRS; deterministic, anyone can run and check it
+pub fn index<C: Circuit<Bls12>>(srs: &UniversalSrs, circuit: C) -> io::Result<(MarlinParameters, MarlinVerifyingKey)> {
+    index_r1cs(srs, &R1cs::synthesize(circuit, false)?)
+}
+
+fn index_r1cs(srs: &UniversalSrs, cs: &R1cs) -> io::Result<(MarlinParameters, MarlinVerifyingKey)> {
+    let shape = Shape::new(cs)?;
+    if srs.max_degree() < shape.degree() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("circuit needs an SRS of degree {}, got {}", shape.degree(), srs.max_degree())));
+    }
+    let (h, k) = (&shape.h, &shape.k);
+    let h_elements = h.elements();
+
+    // M(r, c) * c / |H|: t(beta) interpolates the columns over H, the Lagrange basis of c is
+    // v_H(X) c / (|H| (X - c)). The padding entries are zero at row = col = 1.
+    let mut index_polys: [[Vec<Fr>; 3]; 3] = Default::default();
+    let mut index_evals: [[Vec<Fr>; 3]; 3] = Default::default();
+    let mut index_commitments = [[G1Affine::zero(); 3]; 3];
+    for (i, m) in shape.matrices.iter().enumerate() {
+        let (mut row, mut col, mut val) = (vec![Fr::one(); k.size], vec![Fr::one(); k.size], vec![Fr::zero(); k.size]);
+        for (j, (r, c, coeff)) in m.iter().enumerate() {
+            row[j] = h_elements[*r];
+            col[j] = h_elements[*c];
+            val[j] = *coeff;
+            val[j].mul_assign(&h_elements[*c]);
+            val[j].mul_assign(&h.size_inv);
+        }
+        index_evals[i] = [row, col, val];
+        for t in 0..3 {
+            index_polys[i][t] = k.ifft(&index_evals[i][t]);
+            index_commitments[i][t] = commit(&srs.powers, &index_polys[i][t])?;
+        }
+    }
+
+    let vk = MarlinVerifyingKey {
+        h_log: h.log,
+        x_log: shape.x.log,
+        k_log: k.log,
+        num_inputs: cs.inputs.len() - 1,
+        max_degree: srs.max_degree(),
+        index_commitments,
+        g: srs.powers[0],
+        h: srs.h,
+        tau_h: srs.tau_h
+    };
+    let params = MarlinParameters { vk: vk.clone(), powers: srs.powers.clone(), shape, index_polys, index_evals };
+    Ok((params, vk))
+}
+
+impl MarlinVerifyingKey {
+    fn domains(&self) -> io::Result<(Domain, Domain, Domain)> {
+        if self.x_log > self.h_log {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "input domain is larger than H"));
+        }
+        let (h, x, k) = (Domain::from_log(self.h_log)?, Domain::from_log(self.x_log)?, Domain::from_log(self.k_log)?);
+        if self.num_inputs >= x.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many inputs for the input domain"));
+        }
+        if self.max_degree < required_degree(&h, &x, &k) || self.max_degree > MAX_MARLIN_DEGREE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong SRS degree"));
+        }
+        Ok((h, x, k))
+    }
+}
+
+
+// commitments: w, z_A, z_B, h_0, g_1, shifted g_1, h_1, g_2, shifted g_2, h_2;
+// evaluations: the first 7 commitments at beta, then the last 3 and the index polynomials
+// (row, col, val of A, B, C) at gamma; openings at beta and at gamma
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarlinProof {
+    pub commitments: [G1Affine; PROOF_COMMITMENTS],
+    pub sigma: Fr,
+    pub evaluations: [Fr; PROOF_EVALUATIONS],
+    pub openings: [G1Affine; 2]
+}
+
+
+struct Transcript {
+    state: [u8; 32],
+    data: Vec<u8>
+}
+
+impl Transcript {
+    fn new(vk: &MarlinVerifyingKey, inputs: &[Fr]) -> Self {
+        let mut t = Transcript { state: [0; 32], data: vec![] };
+        vk.write(&mut t.data).unwrap();
+        inputs.iter().for_each(|x| t.fr(x));
+        t
+    }
+
+    fn g1(&mut self, p: &G1Affine) {
+        self.data.extend_from_slice(p.into_compressed().as_ref());
+    }
+
+    fn fr(&mut self, x: &Fr) {
+        x.into_repr().write_le(&mut self.data).unwrap();
+    }
+
+    fn challenge(&mut self) -> Fr {
+        loop {
+            let mut h = Blake2s::with_params(32, &[], &[], MARLIN_PERSONALIZATION);
+            h.update(&self.state);
+            h.update(&self.data);
+            self.state.copy_from_slice(h.finalize().as_bytes());
+            self.data.clear();
+
+            let mut repr = FrRepr::default();
+            repr.read_le(&self.state[..]).unwrap();
+            let x: Fr = fieldtools::affine(repr);
+            if !x.is_zero() {
+                return x;
+            }
+        }
+    }
+
+    // off H, where the sumchecks divide by v_H
+    fn challenge_outside(&mut self, h: &Domain) -> io::Result<Fr> {
+        let x = self.challenge();
+        if h.vanishing(&x).is_zero() {
+            return Err(io::Error::other("challenge in the domain"));
+        }
+        Ok(x)
+    }
+}
+
+
+fn minus_one() -> Fr {
+    let mut x = Fr::one();
+    x.negate();
+    x
+}
+
+fn inverse(x: &Fr) -> io::Result<Fr> {
+    x.inverse().ok_or_else(|| io::Error::other("division by zero"))
+}
+
+// a and b of the second sumcheck at a point, from row, col, val of A, B, C there:
+// b = prod (alpha - row_M)(beta - col_M),
+// a = sum eta_M v_H(alpha) v_H(beta) val_M prod_{N != M} (alpha - row_N)(beta - col_N)
+fn sumcheck_ab(eta: &[Fr; 3], alpha: &Fr, beta: &Fr, v_ab: &Fr, index: &[[Fr; 3]; 3]) -> (Fr, Fr) {
+    let d = [0, 1, 2].iter().map(|&i| {
+        let (mut r, mut c) = (*alpha, *beta);
+        r.sub_assign(&index[i][0]);
+        c.sub_assign(&index[i][1]);
+        r.mul_assign(&c);
+        r
+    }).collect::<Vec<_>>();
+    let mut b = Fr::one();
+    d.iter().for_each(|x| b.mul_assign(x));
+    let mut a = Fr::zero();
+    for i in 0..3 {
+        let mut t = eta[i];
+        t.mul_assign(v_ab);
+        t.mul_assign(&index[i][2]);
+        (0..3).filter(|&j| j != i).for_each(|j| t.mul_assign(&d[j]));
+        a.add_assign(&t);
+    }
+    (a, b)
+}
+
+// sum xi^i p_i
+fn combine<T: AsRef<[Fr]>>(polys: &[T], xi: &Fr) -> Vec<Fr> {
+    polys.iter().zip(powers(xi, polys.len()).iter()).fold(vec![], |acc, (p, s)| poly_add_scaled(&acc, p.as_ref(), s))
+}
+
+
+fn prove_r1cs(params: &MarlinParameters, cs: &R1cs) -> io::Result<MarlinProof> {
+    let vk = &params.vk;
+    let shape = &params.shape;
+    if Shape::new(cs)?.matrices != shape.matrices {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "circuit does not match the parameters"));
+    }
+    let (h, x, k) = (&shape.h, &shape.x, &shape.k);
+    let h_elements = h.elements();
+    let stride = h.size / x.size;
+
+    // z and A z, B z, C z over H
+    let mut z = vec![Fr::zero(); h.size];
+    for (i, v) in cs.inputs.iter().enumerate() {
+        z[shape.column(Index::Input(i))] = *v;
+    }
+    for (i, v) in cs.aux.iter().enumerate() {
+        z[shape.column(Index::Aux(i))] = *v;
+    }
+    let mut z_m: [Vec<Fr>; 3] = Default::default();
+    for (zm, m) in z_m.iter_mut().zip(shape.matrices.iter()) {
+        *zm = vec![Fr::zero(); h.size];
+        for (r, c, coeff) in m.iter() {
+            let mut t = z[*c];
+            t.mul_assign(coeff);
+            zm[*r].add_assign(&t);
+        }
+    }
+    for (r, ((a, b), c)) in z_m[0].iter().zip(z_m[1].iter()).zip(z_m[2].iter()).enumerate() {
+        let mut ab = *a;
+        ab.mul_assign(b);
+        if ab != *c {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("constraint {} is not satisfied", r)));
+        }
+    }
+
+    // z = w v_{H_x} + x^ with x^ interpolating the inputs over H_x
+    let mut x_evals = cs.inputs.clone();
+    x_evals.resize(x.size, Fr::zero());
+    let x_poly = x.ifft(&x_evals);
+    let x_on_h = h.fft(&x_poly);
+    let mut w_evals = vec![Fr::zero(); h.size];
+    for p in (0..h.size).filter(|p| p % stride != 0) {
+        w_evals[p] = z[p];
+        w_evals[p].sub_assign(&x_on_h[p]);
+        w_evals[p].mul_assign(&inverse(&x.vanishing(&h_elements[p]))?);
+    }
+    let w_poly = h.ifft(&w_evals);
+    let z_poly = poly_add_scaled(&poly_add_scaled(&poly_shift(&w_poly, x.size), &w_poly, &minus_one()), &x_poly, &Fr::one());
+    let z_polys = [h.ifft(&z_m[0]), h.ifft(&z_m[1]), h.ifft(&z_m[2])];
+    let (h0_poly, _) = poly_div_vanishing(&poly_add_scaled(&poly_mul(&z_polys[0], &z_polys[1]), &z_polys[2], &minus_one()), h.size);
+
+    let mut commitments = [G1Affine::zero(); PROOF_COMMITMENTS];
+    let mut t = Transcript::new(vk, &cs.inputs[1..]);
+    for (i, p) in [&w_poly, &z_polys[0], &z_polys[1], &h0_poly].iter().enumerate() {
+        commitments[i] = commit(&params.powers, p)?;
+        t.g1(&commitments[i]);
+    }
+
+    // first sumcheck, over H:
+    // u_H(alpha, X) sum eta_M z_M(X) - t(X) z(X) = h_1(X) v_H(X) + X g_1(X)
+    let alpha = t.challenge_outside(h)?;
+    let eta = [t.challenge(), t.challenge(), t.challenge()];
+    let v_h_alpha = h.vanishing(&alpha);
+    let mut u_poly = powers(&alpha, h.size);
+    u_poly.reverse();
+    let mut u_evals = vec![v_h_alpha; h.size];
+    for (u, e) in u_evals.iter_mut().zip(h_elements.iter()) {
+        let mut d = alpha;
+        d.sub_assign(e);
+        u.mul_assign(&inverse(&d)?);
+    }
+    let mut t_evals = vec![Fr::zero(); h.size];
+    for (e, m) in eta.iter().zip(shape.matrices.iter()) {
+        for (r, c, coeff) in m.iter() {
+            let mut v = u_evals[*r];
+            v.mul_assign(coeff);
+            v.mul_assign(e);
+            t_evals[*c].add_assign(&v);
+        }
+    }
+    let t_poly = h.ifft(&t_evals);
+    let sum_z = combine_eta(&z_polys, &eta);
+    let q1 = poly_add_scaled(&poly_mul(&u_poly, &sum_z), &poly_mul(&t_poly, &z_poly), &minus_one());
+    let (h1_poly, rem) = poly_div_vanishing(&q1, h.size);
+    let g1_poly = rem[1..].to_vec();
+    let g1_shifted = poly_shift(&g1_poly, vk.max_degree - (h.size - 2));
+    for (i, p) in [&g1_poly, &g1_shifted, &h1_poly].iter().enumerate() {
+        commitments[4 + i] = commit(&params.powers, p)?;
+        t.g1(&commitments[4 + i]);
+    }
+
+    // second sumcheck, of sigma = t(beta) over K:
+    // a(X) - b(X) (X g_2(X) + sigma / |K|) = h_2(X) v_K(X)
+    let beta = t.challenge_outside(h)?;
+    let sigma = poly_eval(&t_poly, &beta);
+    t.fr(&sigma);
+    let mut v_ab = v_h_alpha;
+    v_ab.mul_assign(&h.vanishing(&beta));
+
+    let l = Domain::new(6 * k.size)?;
+    let index_on_l = params.index_polys.iter().map(|m| [l.fft(&m[0]), l.fft(&m[1]), l.fft(&m[2])]).collect::<Vec<_>>();
+    let at = |index: &[[Vec<Fr>; 3]], j: usize| {
+        let mut res = [[Fr::zero(); 3]; 3];
+        for i in 0..3 {
+            for p in 0..3 {
+                res[i][p] = index[i][p][j];
+            }
+        }
+        res
+    };
+    let (a_evals, b_evals): (Vec<_>, Vec<_>) = (0..l.size).map(|j| sumcheck_ab(&eta, &alpha, &beta, &v_ab, &at(&index_on_l, j))).unzip();
+    let (a_poly, b_poly) = (l.ifft(&a_evals), l.ifft(&b_evals));
+    let f_evals = (0..k.size).map(|j| {
+        let (mut a, b) = sumcheck_ab(&eta, &alpha, &beta, &v_ab, &at(&params.index_evals, j));
+        a.mul_assign(&inverse(&b)?);
+        Ok(a)
+    }).collect::<io::Result<Vec<_>>>()?;
+    let f_poly = k.ifft(&f_evals);
+    let g2_poly = f_poly[1..].to_vec();
+    let g2_shifted = poly_shift(&g2_poly, vk.max_degree - (k.size - 2));
+    let (h2_poly, _) = poly_div_vanishing(&poly_add_scaled(&a_poly, &poly_mul(&b_poly, &f_poly), &minus_one()), k.size);
+    for (i, p) in [&g2_poly, &g2_shifted, &h2_poly].iter().enumerate() {
+        commitments[7 + i] = commit(&params.powers, p)?;
+        t.g1(&commitments[7 + i]);
+    }
+
+    // openings
+    let gamma = t.challenge();
+    let beta_polys: Vec<&[Fr]> = vec![&w_poly, &z_polys[0], &z_polys[1], &h0_poly, &g1_poly, &g1_shifted, &h1_poly];
+    let mut gamma_polys: Vec<&[Fr]> = vec![&g2_poly, &g2_shifted, &h2_poly];
+    gamma_polys.extend(params.index_polys.iter().flatten().map(|p| &p[..]));
+    let mut evaluations = [Fr::zero(); PROOF_EVALUATIONS];
+    for (e, p) in evaluations.iter_mut().zip(beta_polys.iter().map(|p| (p, beta)).chain(gamma_polys.iter().map(|p| (p, gamma)))) {
+        *e = poly_eval(p.0, &p.1);
+        t.fr(e);
+    }
+    let (xi_beta, xi_gamma) = (t.challenge(), t.challenge());
+    let openings = [
+        commit(&params.powers, &poly_div_linear(&combine(&beta_polys, &xi_beta), &beta))?,
+        commit(&params.powers, &poly_div_linear(&combine(&gamma_polys, &xi_gamma), &gamma))?
+    ];
+
+    Ok(MarlinProof { commitments, sigma, evaluations, openings })
+}
+
+fn combine_eta(polys: &[Vec<Fr>; 3], eta: &[Fr; 3]) -> Vec<Fr> {
+    polys.iter().zip(eta.iter()).fold(vec![], |acc, (p, e)| poly_add_scaled(&acc, p, e))
+}
+
+
+fn verify_marlin(vk: &MarlinVerifyingKey, proof: &MarlinProof, inputs: &[Fr]) -> io::Result<bool> {
+    if inputs.len() != vk.num_inputs {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("expected {} inputs, got {}", vk.num_inputs, inputs.len())));
+    }
+    let (h, x, k) = vk.domains()?;
+    let c = &proof.commitments;
+    let e = &proof.evaluations;
+
+    let mut t = Transcript::new(vk, inputs);
+    c[..4].iter().for_each(|p| t.g1(p));
+    let alpha = t.challenge_outside(&h)?;
+    let eta = [t.challenge(), t.challenge(), t.challenge()];
+    c[4..7].iter().for_each(|p| t.g1(p));
+    let beta = t.challenge_outside(&h)?;
+    t.fr(&proof.sigma);
+    c[7..].iter().for_each(|p| t.g1(p));
+    let gamma = t.challenge();
+    e.iter().for_each(|x| t.fr(x));
+    let (xi_beta, xi_gamma) = (t.challenge(), t.challenge());
+    proof.openings.iter().for_each(|p| t.g1(p));
+    let r = t.challenge();
+
+    let (w, z_a, z_b, h_0, g_1, g_1_shifted, h_1) = (e[0], e[1], e[2], e[3], e[4], e[5], e[6]);
+    let (g_2, g_2_shifted, h_2) = (e[7], e[8], e[9]);
+    let (v_h_alpha, v_h_beta) = (h.vanishing(&alpha), h.vanishing(&beta));
+
+    // x^(beta) = sum x_j v_{H_x}(beta) w_j / (|H_x| (beta - w_j)) over H_x, z(beta) = w(beta) v_{H_x}(beta) + x^(beta)
+    let v_x_beta = x.vanishing(&beta);
+    let mut z = Fr::zero();
+    for (xj, wj) in Some(Fr::one()).iter().chain(inputs.iter()).zip(x.elements().iter()) {
+        let mut d = beta;
+        d.sub_assign(wj);
+        let mut l = inverse(&d)?;
+        l.mul_assign(&v_x_beta);
+        l.mul_assign(wj);
+        l.mul_assign(&x.size_inv);
+        l.mul_assign(xj);
+        z.add_assign(&l);
+    }
+    let mut wv = w;
+    wv.mul_assign(&v_x_beta);
+    z.add_assign(&wv);
+
+    // z_C(beta) = z_A(beta) z_B(beta) - h_0(beta) v_H(beta)
+    let mut z_c = z_a;
+    z_c.mul_assign(&z_b);
+    let mut hv = h_0;
+    hv.mul_assign(&v_h_beta);
+    z_c.sub_assign(&hv);
+
+    // first sumcheck at beta
+    let mut u = v_h_alpha;
+    u.sub_assign(&v_h_beta);
+    let mut d = alpha;
+    d.sub_assign(&beta);
+    u.mul_assign(&inverse(&d)?);
+    let mut lhs = Fr::zero();
+    for (zm, et) in [z_a, z_b, z_c].iter().zip(eta.iter()) {
+        let mut s = *zm;
+        s.mul_assign(et);
+        lhs.add_assign(&s);
+    }
+    lhs.mul_assign(&u);
+    let mut sz = proof.sigma;
+    sz.mul_assign(&z);
+    lhs.sub_assign(&sz);
+    let mut rhs = h_1;
+    rhs.mul_assign(&v_h_beta);
+    let mut bg = beta;
+    bg.mul_assign(&g_1);
+    rhs.add_assign(&bg);
+    if lhs != rhs {
+        return Ok(false);
+    }
+
+    // second sumcheck at gamma
+    let mut index = [[Fr::zero(); 3]; 3];
+    for i in 0..9 {
+        index[i / 3][i % 3] = e[10 + i];
+    }
+    let mut v_ab = v_h_alpha;
+    v_ab.mul_assign(&v_h_beta);
+    let (mut lhs, b) = sumcheck_ab(&eta, &alpha, &beta, &v_ab, &index);
+    let mut f = gamma;
+    f.mul_assign(&g_2);
+    let mut s = proof.sigma;
+    s.mul_assign(&k.size_inv);
+    f.add_assign(&s);
+    f.mul_assign(&b);
+    lhs.sub_assign(&f);
+    let mut rhs = h_2;
+    rhs.mul_assign(&k.vanishing(&gamma));
+    if lhs != rhs {
+        return Ok(false);
+    }
+
+    // degree bounds: deg g_1 < |H| - 1, deg g_2 < |K| - 1
+    for (g, shifted, point, bound) in [(g_1, g_1_shifted, beta, h.size - 2), (g_2, g_2_shifted, gamma, k.size - 2)].iter() {
+        let mut g = *g;
+        g.mul_assign(&point.pow([(vk.max_degree - bound) as u64]));
+        if g != *shifted {
+            return Ok(false);
+        }
+    }
+
+    // both batched openings in one pairing check:
+    // e(sum r^j (C_j - v_j g + z_j pi_j), h) = e(sum r^j pi_j, tau h)
+    let index_commitments = vk.index_commitments.iter().flatten().cloned().collect::<Vec<_>>();
+    let batches = [
+        (c[..7].to_vec(), &e[..7], xi_beta, beta),
+        ([&c[7..], &index_commitments[..]].concat(), &e[7..], xi_gamma, gamma)
+    ];
+    let (mut acc, mut acc_pi) = (G1::zero(), G1::zero());
+    for ((commitments, evals, xi, point), (pi, rj)) in batches.iter().zip(proof.openings.iter().zip(powers(&r, 2).iter())) {
+        let xis = powers(xi, commitments.len());
+        let mut v = Fr::zero();
+        let mut sum = G1::zero();
+        for ((cm, ev), s) in commitments.iter().zip(evals.iter()).zip(xis.iter()) {
+            sum.add_assign(&cm.mul(s.into_repr()));
+            let mut ev = *ev;
+            ev.mul_assign(s);
+            v.add_assign(&ev);
+        }
+        v.negate();
+        sum.add_assign(&vk.g.mul(v.into_repr()));
+        sum.add_assign(&pi.mul(point.into_repr()));
+        sum.mul_assign(rj.into_repr());
+        acc.add_assign(&sum);
+        acc_pi.add_assign(&pi.mul(rj.into_repr()));
+    }
+    acc_pi.negate();
+    Ok(Bls12::final_exponentiation(&Bls12::miller_loop([
+        (&acc.into_affine().prepare(), &vk.h.prepare()),
+        (&acc_pi.into_affine().prepare(), &vk.tau_h.prepare())
+    ].iter())).unwrap() == Fq12::one())
+}
+
+
+fn write_fr<W: Write>(x: &Fr, mut writer: W) -> io::Result<()> {
+    x.into_repr().write_be(&mut writer)
+}
+
+fn read_fr<R: Read>(mut reader: R) -> io::Result<Fr> {
+    let mut buf = [0u8; 32];
+    reader.read_exact(&mut buf)?;
+    Fr::from_repr(read_fr_repr_be::<Fr>(&buf)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))
+}
+
+impl MarlinVerifyingKey {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(MARLIN_VK_VERSION)?;
+        writer.write_u8(self.h_log as u8)?;
+        writer.write_u8(self.x_log as u8)?;
+        writer.write_u8(self.k_log as u8)?;
+        writer.write_u32::<BigEndian>(self.num_inputs as u32)?;
+        writer.write_u32::<BigEndian>(self.max_degree as u32)?;
+        for p in self.index_commitments.iter().flatten().chain(Some(&self.g)) {
+            writer.write_all(p.into_compressed().as_ref())?;
+        }
+        writer.write_all(self.h.into_compressed().as_ref())?;
+        writer.write_all(self.tau_h.into_compressed().as_ref())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        if reader.read_u8()? != MARLIN_VK_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown verifying key version"));
+        }
+        let (h_log, x_log, k_log) = (u32::from(reader.read_u8()?), u32::from(reader.read_u8()?), u32::from(reader.read_u8()?));
+        let num_inputs = reader.read_u32::<BigEndian>()? as usize;
+        let max_degree = reader.read_u32::<BigEndian>()? as usize;
+        let mut index_commitments = [[G1Affine::zero(); 3]; 3];
+        for p in index_commitments.iter_mut().flatten() {
+            *p = read_point(&mut reader, true)?;
+        }
+        let vk = MarlinVerifyingKey {
+            h_log, x_log, k_log, num_inputs, max_degree, index_commitments,
+            g: read_point(&mut reader, true)?,
+            h: read_point(&mut reader, true)?,
+            tau_h: read_point(&mut reader, true)?
+        };
+        vk.domains()?;
+        Ok(vk)
+    }
+}
+
+impl MarlinProof {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for p in self.commitments.iter() {
+            writer.write_all(p.into_compressed().as_ref())?;
+        }
+        for x in Some(&self.sigma).into_iter().chain(self.evaluations.iter()) {
+            write_fr(x, &mut writer)?;
+        }
+        for p in self.openings.iter() {
+            writer.write_all(p.into_compressed().as_ref())?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut commitments = [G1Affine::zero(); PROOF_COMMITMENTS];
+        for p in commitments.iter_mut() {
+            *p = read_point(&mut reader, true)?;
+        }
+        let sigma = read_fr(&mut reader)?;
+        let mut evaluations = [Fr::zero(); PROOF_EVALUATIONS];
+        for x in evaluations.iter_mut() {
+            *x = read_fr(&mut reader)?;
+        }
+        let openings = [read_point(&mut reader, true)?, read_point(&mut reader, true)?];
+        Ok(MarlinProof { commitments, sigma, evaluations, openings })
+    }
+}
+
+
+// The setup makes an SRS just large enough for the circuit, as Groth16::setup makes its own
+// parameters; with a shared SRS use index. The proof is deterministic, the rng is not used.
+// Marlin is not proving::ZeroKnowledge, so prove_many and the other entry points for real notes
+// do not take it.
+pub struct Marlin;
+
+impl ProvingSystem<Bls12> for Marlin {
+    type Parameters = MarlinParameters;
+    type VerifyingKey = MarlinVerifyingKey;
+    type Proof = MarlinProof;
+    type Scratch = ();
+
+    fn setup<C: Circuit<Bls12>, R: Rng>(circuit: C, rng: &mut R) -> io::Result<(Self::Parameters, Self::VerifyingKey)> {
+        let cs = R1cs::synthesize(circuit, false)?;
+        let srs = UniversalSrs::new_insecure(Shape::new(&cs)?.degree(), rng)?;
+        index_r1cs(&srs, &cs)
+    }
+
+    fn prove<C: Circuit<Bls12>, R: Rng>(params: &Self::Parameters, circuit: C, _: &mut R) -> io::Result<Self::Proof> {
+        let proof = metrics::time(Timing::Prove, || prove_r1cs(params, &R1cs::synthesize(circuit, true)?))?;
+        metrics::inc(Counter::ProofsGenerated, 1);
+        Ok(proof)
+    }
+
+    fn verify(vk: &Self::VerifyingKey, proof: &Self::Proof, inputs: &[Fr]) -> io::Result<bool> {
+        let res = metrics::time(Timing::Verify, || verify_marlin(vk, proof, inputs))?;
+        metrics::inc(if res { Counter::ProofsVerified } else { Counter::ProofsRejected }, 1);
+        Ok(res)
+    }
+
+    fn write_proof<W: Write>(proof: &Self::Proof, writer: W) -> io::Result<()> {
+        proof.write(writer)
+    }
+
+    fn read_proof<R: Read>(reader: R) -> io::Result<Self::Proof> {
+        MarlinProof::read(reader)
+    }
+
+    fn write_verifying_key<W: Write>(vk: &Self::VerifyingKey, writer: W) -> io::Result<()> {
+        vk.write(writer)
+    }
+
+    fn read_verifying_key<R: Read>(reader: R) -> io::Result<Self::VerifyingKey> {
+        MarlinVerifyingKey::read(reader)
+    }
+}
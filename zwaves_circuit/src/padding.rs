@@ -0,0 +1,70 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::Field;
+use rand::Rng;
+
+use zwaves_primitives::transactions::NoteData;
+
+use std::io;
+
+use crate::circuit::{NotePath, MERKLE_PROOF_LEN};
+
+
+// Every transfer spends two notes and creates two, whatever the wallet actually moves, so the
+// shape of a transaction does not tell how many real notes it spent. The missing ones are dummy
+// notes: zero amount and zero native amount, a random txid so the commitment and the nullifier
+// look like those of any other note, the asset of the transfer.
+//
+// A dummy input is not checked against the root (the root constraint is multiplied by the
+// amounts), so it needs no leaf in the tree: its path is random, as a real path is to anybody
+// without the tree. It should still be owned by sk. Dummy outputs go to the sender, as change.
+pub const TRANSFER_INPUTS: usize = 2;
+pub const TRANSFER_OUTPUTS: usize = 2;
+
+
+pub fn is_dummy<E: JubjubEngine>(note: &NoteData<E>) -> bool {
+    note.amount.is_zero() && note.native_amount.is_zero()
+}
+
+pub fn dummy_note<E: JubjubEngine, R: Rng>(asset_id: E::Fr, owner: E::Fr, rng: &mut R) -> NoteData<E> {
+    NoteData { asset_id, amount: E::Fr::zero(), native_amount: E::Fr::zero(), txid: rng.gen(), owner }
+}
+
+pub fn dummy_path<E: JubjubEngine, R: Rng>(rng: &mut R) -> NotePath<E> {
+    let mut sibling = [E::Fr::zero(); MERKLE_PROOF_LEN];
+    sibling.iter_mut().for_each(|s| *s = rng.gen());
+    NotePath::new(sibling, rng.gen::<u64>() >> (64 - MERKLE_PROOF_LEN)).unwrap()
+}
+
+// Real inputs first, dummy inputs owned by pk after them
+pub fn pad_inputs<E: JubjubEngine, R: Rng>(
+    inputs: &[(NoteData<E>, NotePath<E>)],
+    pk: &E::Fr,
+    asset_id: &E::Fr,
+    rng: &mut R
+) -> io::Result<([NoteData<E>; TRANSFER_INPUTS], [NotePath<E>; TRANSFER_INPUTS])> {
+    if inputs.len() > TRANSFER_INPUTS {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("transfer spends at most {} notes, got {}", TRANSFER_INPUTS, inputs.len())));
+    }
+    let mut padded = inputs.to_vec();
+    while padded.len() < TRANSFER_INPUTS {
+        padded.push((dummy_note(*asset_id, *pk, rng), dummy_path(rng)));
+    }
+    Ok(([padded[0].0.clone(), padded[1].0.clone()], [padded[0].1.clone(), padded[1].1.clone()]))
+}
+
+// Real outputs first, dummy outputs to pk after them
+pub fn pad_outputs<E: JubjubEngine, R: Rng>(
+    outputs: &[NoteData<E>],
+    pk: &E::Fr,
+    asset_id: &E::Fr,
+    rng: &mut R
+) -> io::Result<[NoteData<E>; TRANSFER_OUTPUTS]> {
+    if outputs.len() > TRANSFER_OUTPUTS {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("transfer creates at most {} notes, got {}", TRANSFER_OUTPUTS, outputs.len())));
+    }
+    let mut padded = outputs.to_vec();
+    while padded.len() < TRANSFER_OUTPUTS {
+        padded.push(dummy_note(*asset_id, *pk, rng));
+    }
+    Ok([padded[0].clone(), padded[1].clone()])
+}
@@ -0,0 +1,72 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::PrimeField;
+
+use zwaves_primitives::fieldtools::fr_to_repr_u64;
+
+use std::io;
+
+use crate::circuit::Transfer;
+use crate::padding::is_dummy;
+
+
+// Relayer policies checked on the witness before proving, so a transfer the relayer would
+// reject fails in milliseconds with the reason instead of after the proof is made.
+// Dummy outputs, zero amount and zero native amount, are padding and are not checked.
+// An output owned by the owner of the first input is change.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferPolicy {
+    pub min_output_amount: u64,
+    pub min_native_amount: u64,
+    pub max_outputs: usize,
+    // at most one change output: two change outputs waste an output and leave dust
+    pub consolidate_change: bool
+}
+
+impl Default for TransferPolicy {
+    fn default() -> Self {
+        TransferPolicy { min_output_amount: 0, min_native_amount: 0, max_outputs: 2, consolidate_change: false }
+    }
+}
+
+
+fn to_u64<F: PrimeField>(x: &F, name: &str, i: usize) -> io::Result<u64> {
+    let limbs = fr_to_repr_u64(x).into_iter().collect::<Vec<_>>();
+    if limbs[1..].iter().any(|&l| l != 0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("output {} {} does not fit 64 bits", i, name)));
+    }
+    Ok(limbs[0])
+}
+
+impl TransferPolicy {
+    pub fn check<E: JubjubEngine>(&self, transfer: &Transfer<E>) -> io::Result<()> {
+        let outputs = transfer.out_note.iter().enumerate()
+            .map(|(i, n)| n.as_ref().map(|n| (i, n)).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("output {} is missing", i))))
+            .collect::<io::Result<Vec<_>>>()?;
+        let used = outputs.into_iter().filter(|(_, n)| !is_dummy(n)).collect::<Vec<_>>();
+
+        if used.len() > self.max_outputs {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("transfer has {} outputs, at most {} are allowed", used.len(), self.max_outputs)));
+        }
+
+        for (i, n) in used.iter() {
+            let amount = to_u64(&n.amount, "amount", *i)?;
+            if amount != 0 && amount < self.min_output_amount {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("output {} amount {} is below the minimum {}", i, amount, self.min_output_amount)));
+            }
+            let native_amount = to_u64(&n.native_amount, "native amount", *i)?;
+            if native_amount != 0 && native_amount < self.min_native_amount {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("output {} native amount {} is below the minimum {}", i, native_amount, self.min_native_amount)));
+            }
+        }
+
+        if self.consolidate_change {
+            if let Some(ref sender) = transfer.in_note[0] {
+                if used.iter().filter(|(_, n)| n.owner == sender.owner).count() > 1 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "change should be consolidated into one output"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,502 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError, Variable, Index, LinearCombination};
+use bellman::groth16::{Parameters, Proof};
+use pairing::bls12_381::{Bls12, Fr, FrRepr};
+use pairing::{CurveAffine, CurveProjective, Field, PrimeField};
+use rand::Rng;
+#[cfg(feature = "threads")]
+use rayon::prelude::*;
+
+use std::{io, mem};
+use std::ops::Range;
+
+use zwaves_primitives::memory;
+
+
+// Groth16 prover for the parameters and proofs of bellman 0.1: the computation of its
+// create_proof, giving the same proof for the same r and s, without its futures-cpupool Worker.
+// That worker spawns OS threads inside every proof and can not be replaced, so bellman's prover
+// does not run on wasm32-unknown-unknown, and it allocates its buffers anew for every proof.
+// Here the FFT and the multiexps run on the calling thread or on the rayon pool (Threads), and
+// the evaluation vectors live in a ProverScratch the caller keeps for its next proof.
+//
+// Synthesis is single threaded in both cases, as in bellman.
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Threads {
+    // everything on the calling thread, what wasm without SharedArrayBuffer can do
+    Single,
+    // FFT and multiexp on the global rayon pool; in a browser the pool of wasm-bindgen-rayon,
+    // which has to be initialized before proving
+    #[cfg(feature = "threads")]
+    Rayon
+}
+
+impl Threads {
+    fn count(self) -> usize {
+        match self {
+            Threads::Single => 1,
+            #[cfg(feature = "threads")]
+            Threads::Rayon => rayon::current_num_threads()
+        }
+    }
+
+    // f(start, chunk) for the chunks of v of chunk_size elements
+    fn for_each_chunk<T, F>(self, v: &mut [T], chunk_size: usize, f: F)
+        where T: Send, F: Fn(usize, &mut [T]) + Send + Sync
+    {
+        match self {
+            Threads::Single => v.chunks_mut(chunk_size).enumerate().for_each(|(i, c)| f(i * chunk_size, c)),
+            #[cfg(feature = "threads")]
+            Threads::Rayon => v.par_chunks_mut(chunk_size).enumerate().for_each(|(i, c)| f(i * chunk_size, c))
+        }
+    }
+
+    // f(start, chunk of a, chunk of b) for the chunks of a and b of the same length
+    fn for_each_chunk_pair<F>(self, a: &mut [Fr], b: &mut [Fr], chunk_size: usize, f: F)
+        where F: Fn(usize, &mut [Fr], &mut [Fr]) + Send + Sync
+    {
+        match self {
+            Threads::Single => a.chunks_mut(chunk_size).zip(b.chunks_mut(chunk_size)).enumerate()
+                .for_each(|(i, (a, b))| f(i * chunk_size, a, b)),
+            #[cfg(feature = "threads")]
+            Threads::Rayon => a.par_chunks_mut(chunk_size).zip(b.par_chunks_mut(chunk_size)).enumerate()
+                .for_each(|(i, (a, b))| f(i * chunk_size, a, b))
+        }
+    }
+
+    // f on consecutive ranges covering 0..n, one per thread, the results in order
+    fn map_ranges<T, F>(self, n: usize, f: F) -> Vec<T>
+        where T: Send, F: Fn(Range<usize>) -> T + Send + Sync
+    {
+        let size = chunk_size(n, self.count());
+        let ranges = (0..n).step_by(size).map(|s| s..(s + size).min(n)).collect::<Vec<_>>();
+        match self {
+            Threads::Single => ranges.into_iter().map(f).collect(),
+            #[cfg(feature = "threads")]
+            Threads::Rayon => ranges.into_par_iter().map(f).collect()
+        }
+    }
+}
+
+// n elements split between threads, at least one per chunk
+fn chunk_size(n: usize, threads: usize) -> usize {
+    n.div_ceil(threads).max(1)
+}
+
+
+// Buffers of a proof which the next proof can reuse: the evaluations of A, B and C over the
+// domain, the assignment and the coefficients of h. A relayer proving circuit after circuit keeps
+// one per thread and does not allocate them again (see prove_many).
+#[derive(Default)]
+pub struct ProverScratch {
+    a: Vec<Fr>,
+    b: Vec<Fr>,
+    c: Vec<Fr>,
+    inputs: Vec<Fr>,
+    aux: Vec<Fr>,
+    h: Vec<FrRepr>
+}
+
+
+// The assignment of a circuit and the evaluations of its constraints, with the variables which
+// appear in A and in B: the queries of the parameters have bases for those only.
+struct Assignment {
+    inputs: Vec<Fr>,
+    aux: Vec<Fr>,
+    a_aux_density: Vec<bool>,
+    b_input_density: Vec<bool>,
+    b_aux_density: Vec<bool>,
+    a: Vec<Fr>,
+    b: Vec<Fr>,
+    c: Vec<Fr>
+}
+
+fn eval(lc: &LinearCombination<Bls12>, inputs: &[Fr], aux: &[Fr], mut input_density: Option<&mut Vec<bool>>, mut aux_density: Option<&mut Vec<bool>>) -> Fr {
+    let mut acc = Fr::zero();
+    for (v, coeff) in lc.as_ref() {
+        let mut x = match v.get_unchecked() {
+            Index::Input(i) => {
+                if let Some(d) = input_density.as_mut() {
+                    d[i] = true;
+                }
+                inputs[i]
+            },
+            Index::Aux(i) => {
+                if let Some(d) = aux_density.as_mut() {
+                    d[i] = true;
+                }
+                aux[i]
+            }
+        };
+        x.mul_assign(coeff);
+        acc.add_assign(&x);
+    }
+    acc
+}
+
+impl ConstraintSystem<Bls12> for Assignment {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.aux.push(f()?);
+        self.a_aux_density.push(false);
+        self.b_aux_density.push(false);
+        Ok(Variable::new_unchecked(Index::Aux(self.aux.len() - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.inputs.push(f()?);
+        self.b_input_density.push(false);
+        Ok(Variable::new_unchecked(Index::Input(self.inputs.len() - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+        LA: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LB: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LC: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>
+    {
+        let (a, b, c) = (a(LinearCombination::zero()), b(LinearCombination::zero()), c(LinearCombination::zero()));
+        self.a.push(eval(&a, &self.inputs, &self.aux, None, Some(&mut self.a_aux_density)));
+        self.b.push(eval(&b, &self.inputs, &self.aux, Some(&mut self.b_input_density), Some(&mut self.b_aux_density)));
+        self.c.push(eval(&c, &self.inputs, &self.aux, None, None));
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N) where NR: Into<String>, N: FnOnce() -> NR {}
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+
+// Multiplicative subgroup of size 2^log, with the coset g H of the multiplicative generator
+struct Domain {
+    size: usize,
+    log: u32,
+    omega: Fr,
+    omega_inv: Fr,
+    size_inv: Fr,
+    g: Fr,
+    g_inv: Fr
+}
+
+impl Domain {
+    // the smallest domain of at least n elements, as EvaluationDomain::from_coeffs
+    fn new(n: usize) -> io::Result<Self> {
+        let mut log = 0;
+        while (1usize << log) < n {
+            log += 1;
+            if log >= Fr::S {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "circuit has too many constraints for the field"));
+            }
+        }
+        let mut omega = Fr::root_of_unity();
+        for _ in log..Fr::S {
+            omega.square();
+        }
+        let size = 1usize << log;
+        let g = Fr::multiplicative_generator();
+        Ok(Domain {
+            size,
+            log,
+            omega,
+            omega_inv: omega.inverse().unwrap(),
+            size_inv: Fr::from_repr(FrRepr::from(size as u64)).unwrap().inverse().unwrap(),
+            g,
+            g_inv: g.inverse().unwrap()
+        })
+    }
+
+    fn ifft(&self, a: &mut [Fr], threads: Threads) {
+        fft(a, &self.omega_inv, self.log, threads);
+        distribute_powers(a, &self.size_inv, &Fr::one(), threads);
+    }
+
+    fn coset_fft(&self, a: &mut [Fr], threads: Threads) {
+        distribute_powers(a, &Fr::one(), &self.g, threads);
+        fft(a, &self.omega, self.log, threads);
+    }
+
+    fn icoset_fft(&self, a: &mut [Fr], threads: Threads) {
+        fft(a, &self.omega_inv, self.log, threads);
+        distribute_powers(a, &self.size_inv, &self.g_inv, threads);
+    }
+
+    // 1 / Z(x) on the coset, where Z = X^size - 1 is the same at every point of it
+    fn z_on_coset_inv(&self) -> Fr {
+        let mut z = self.g.pow([self.size as u64]);
+        z.sub_assign(&Fr::one());
+        z.inverse().unwrap()
+    }
+}
+
+// a[i] *= c g^i
+fn distribute_powers(a: &mut [Fr], c: &Fr, g: &Fr, threads: Threads) {
+    let size = chunk_size(a.len(), threads.count());
+    threads.for_each_chunk(a, size, |start, chunk| {
+        let mut u = g.pow([start as u64]);
+        u.mul_assign(c);
+        for x in chunk {
+            x.mul_assign(&u);
+            u.mul_assign(g);
+        }
+    });
+}
+
+// x, y = x + w y, x - w y for the pairs of lo and hi, w going from w_start by w_m
+fn butterflies(lo: &mut [Fr], hi: &mut [Fr], w_start: Fr, w_m: &Fr) {
+    let mut w = w_start;
+    for (x, y) in lo.iter_mut().zip(hi.iter_mut()) {
+        let mut t = *y;
+        t.mul_assign(&w);
+        *y = *x;
+        y.sub_assign(&t);
+        x.add_assign(&t);
+        w.mul_assign(w_m);
+    }
+}
+
+// Radix-2 in place. The blocks of a layer are independent: while there are enough of them they
+// are the tasks, in the last layers the butterflies of each block are split instead.
+fn fft(a: &mut [Fr], omega: &Fr, log_n: u32, threads: Threads) {
+    fn bitreverse(mut n: usize, l: u32) -> usize {
+        let mut r = 0;
+        for _ in 0..l {
+            r = (r << 1) | (n & 1);
+            n >>= 1;
+        }
+        r
+    }
+
+    let n = a.len();
+    for k in 0..n {
+        let rk = bitreverse(k, log_n);
+        if k < rk {
+            a.swap(rk, k);
+        }
+    }
+
+    let mut m = 1;
+    for _ in 0..log_n {
+        let w_m = omega.pow([(n / (2 * m)) as u64]);
+        if n / (2 * m) >= threads.count() {
+            threads.for_each_chunk(a, 2 * m, |_, block| {
+                let (lo, hi) = block.split_at_mut(m);
+                butterflies(lo, hi, Fr::one(), &w_m);
+            });
+        } else {
+            let size = chunk_size(m, threads.count());
+            for block in a.chunks_mut(2 * m) {
+                let (lo, hi) = block.split_at_mut(m);
+                threads.for_each_chunk_pair(lo, hi, size, |start, lo, hi| butterflies(lo, hi, w_m.pow([start as u64]), &w_m));
+            }
+        }
+        m *= 2;
+    }
+}
+
+
+// bits [start, start + c) of s
+fn window(s: &FrRepr, start: u32, c: u32) -> usize {
+    let limbs = s.as_ref();
+    let (limb, shift) = ((start / 64) as usize, start % 64);
+    if limb >= limbs.len() {
+        return 0;
+    }
+    let mut bits = limbs[limb] >> shift;
+    if shift + c > 64 && limb + 1 < limbs.len() {
+        bits |= limbs[limb + 1] << (64 - shift);
+    }
+    (bits & ((1u64 << c) - 1)) as usize
+}
+
+// Pippenger: windows of c bits from the top, in each the bases go to the bucket of their digit
+// and the buckets are summed with their weights by a running sum
+fn serial_multiexp<G: CurveAffine<Scalar = Fr>>(bases: &[G], scalars: &[FrRepr]) -> G::Projective {
+    let c = if scalars.len() < 32 { 3 } else { (scalars.len() as f64).ln().ceil() as u32 };
+    let mut buckets = vec![G::Projective::zero(); (1usize << c) - 1];
+    let mut acc = G::Projective::zero();
+    for w in (0..Fr::NUM_BITS.div_ceil(c)).rev() {
+        for _ in 0..c {
+            acc.double();
+        }
+        for b in buckets.iter_mut() {
+            *b = G::Projective::zero();
+        }
+        for (base, s) in bases.iter().zip(scalars.iter()) {
+            let digit = window(s, w * c, c);
+            if digit != 0 {
+                buckets[digit - 1].add_assign_mixed(base);
+            }
+        }
+        let mut running = G::Projective::zero();
+        for b in buckets.iter().rev() {
+            running.add_assign(b);
+            acc.add_assign(&running);
+        }
+    }
+    acc
+}
+
+// sum s_i g_i, a chunk of the terms per thread
+fn multiexp<G: CurveAffine<Scalar = Fr>>(bases: &[G], scalars: &[FrRepr], threads: Threads) -> G::Projective {
+    threads.map_ranges(scalars.len(), |r| serial_multiexp(&bases[r.clone()], &scalars[r]))
+        .iter()
+        .fold(G::Projective::zero(), |mut acc, p| {
+            acc.add_assign(p);
+            acc
+        })
+}
+
+// count bases of a query from start; a shorter query is of the parameters of another circuit
+fn query<G>(bases: &[G], start: usize, count: usize) -> io::Result<&[G]> {
+    bases.get(start..start + count).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "parameters do not match the circuit"))
+}
+
+fn dense(scalars: &[FrRepr], density: &[bool]) -> Vec<FrRepr> {
+    scalars.iter().zip(density.iter()).filter(|(_, d)| **d).map(|(s, _)| *s).collect()
+}
+
+
+// Coefficients of h = (A B - C) / Z, Z vanishing on the domain. The division is done on the
+// coset g H, where Z has no roots. a, b and c are the evaluations of the constraints.
+fn h_coefficients(domain: &Domain, a: &mut Vec<Fr>, b: &mut Vec<Fr>, c: &mut Vec<Fr>, threads: Threads, out: &mut Vec<FrRepr>) {
+    for v in [&mut *a, &mut *b, &mut *c].iter_mut() {
+        v.resize(domain.size, Fr::zero());
+        domain.ifft(&mut v[..], threads);
+        domain.coset_fft(&mut v[..], threads);
+    }
+
+    let (b, c) = (&b[..], &c[..]);
+    let z_inv = domain.z_on_coset_inv();
+    let size = chunk_size(domain.size, threads.count());
+    threads.for_each_chunk(&mut a[..], size, |start, chunk| {
+        for (j, x) in chunk.iter_mut().enumerate() {
+            x.mul_assign(&b[start + j]);
+            x.sub_assign(&c[start + j]);
+            x.mul_assign(&z_inv);
+        }
+    });
+    domain.icoset_fft(&mut a[..], threads);
+
+    // h is of degree size - 2
+    out.clear();
+    out.extend(a[..domain.size - 1].iter().map(|x| x.into_repr()));
+}
+
+fn cleared(v: &mut Vec<Fr>) -> Vec<Fr> {
+    let mut v = mem::take(v);
+    v.clear();
+    v
+}
+
+fn proving_err(e: SynthesisError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+pub fn create_proof<C: Circuit<Bls12>>(
+    circuit: C,
+    params: &Parameters<Bls12>,
+    r: Fr,
+    s: Fr,
+    threads: Threads,
+    scratch: &mut ProverScratch
+) -> io::Result<Proof<Bls12>> {
+    let vk = &params.vk;
+    if vk.delta_g1.is_zero() || vk.delta_g2.is_zero() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "parameters have a zero delta"));
+    }
+    memory::check_proving_memory_limit(params)?;
+
+    let mut cs = Assignment {
+        inputs: cleared(&mut scratch.inputs),
+        aux: cleared(&mut scratch.aux),
+        a_aux_density: vec![],
+        b_input_density: vec![],
+        b_aux_density: vec![],
+        a: cleared(&mut scratch.a),
+        b: cleared(&mut scratch.b),
+        c: cleared(&mut scratch.c)
+    };
+
+    cs.alloc_input(|| "", || Ok(Fr::one())).map_err(proving_err)?;
+    circuit.synthesize(&mut cs).map_err(proving_err)?;
+    // input * 0 = 0 for every input, as bellman does: it puts every input into the A query
+    for x in cs.inputs.iter() {
+        cs.a.push(*x);
+        cs.b.push(Fr::zero());
+        cs.c.push(Fr::zero());
+    }
+
+    let domain = Domain::new(cs.a.len())?;
+    h_coefficients(&domain, &mut cs.a, &mut cs.b, &mut cs.c, threads, &mut scratch.h);
+
+    let inputs = cs.inputs.iter().map(|x| x.into_repr()).collect::<Vec<_>>();
+    let aux = cs.aux.iter().map(|x| x.into_repr()).collect::<Vec<_>>();
+    let a_aux = dense(&aux, &cs.a_aux_density);
+    let b_inputs = dense(&inputs, &cs.b_input_density);
+    let b_aux = dense(&aux, &cs.b_aux_density);
+
+    let h = multiexp(query(&params.h[..], 0, scratch.h.len())?, &scratch.h, threads);
+    let l = multiexp(query(&params.l[..], 0, aux.len())?, &aux, threads);
+
+    let mut a_answer = multiexp(query(&params.a[..], 0, inputs.len())?, &inputs, threads);
+    a_answer.add_assign(&multiexp(query(&params.a[..], inputs.len(), a_aux.len())?, &a_aux, threads));
+
+    let mut b1_answer = multiexp(query(&params.b_g1[..], 0, b_inputs.len())?, &b_inputs, threads);
+    b1_answer.add_assign(&multiexp(query(&params.b_g1[..], b_inputs.len(), b_aux.len())?, &b_aux, threads));
+
+    let mut b2_answer = multiexp(query(&params.b_g2[..], 0, b_inputs.len())?, &b_inputs, threads);
+    b2_answer.add_assign(&multiexp(query(&params.b_g2[..], b_inputs.len(), b_aux.len())?, &b_aux, threads));
+
+    scratch.inputs = cs.inputs;
+    scratch.aux = cs.aux;
+    scratch.a = cs.a;
+    scratch.b = cs.b;
+    scratch.c = cs.c;
+
+    // A = alpha + a + r delta, B = beta + b + s delta,
+    // C = s A + r B - r s delta + h + l
+    let mut g_a = vk.delta_g1.mul(r.into_repr());
+    g_a.add_assign_mixed(&vk.alpha_g1);
+    let mut g_b = vk.delta_g2.mul(s.into_repr());
+    g_b.add_assign_mixed(&vk.beta_g2);
+    let mut rs = r;
+    rs.mul_assign(&s);
+    let mut g_c = vk.delta_g1.mul(rs.into_repr());
+    g_c.add_assign(&vk.alpha_g1.mul(s.into_repr()));
+    g_c.add_assign(&vk.beta_g1.mul(r.into_repr()));
+
+    g_a.add_assign(&a_answer);
+    a_answer.mul_assign(s.into_repr());
+    g_c.add_assign(&a_answer);
+
+    g_b.add_assign(&b2_answer);
+    b1_answer.mul_assign(r.into_repr());
+    g_c.add_assign(&b1_answer);
+
+    g_c.add_assign(&h);
+    g_c.add_assign(&l);
+
+    Ok(Proof { a: g_a.into_affine(), b: g_b.into_affine(), c: g_c.into_affine() })
+}
+
+pub fn create_random_proof<C: Circuit<Bls12>, R: Rng>(
+    circuit: C,
+    params: &Parameters<Bls12>,
+    rng: &mut R,
+    threads: Threads,
+    scratch: &mut ProverScratch
+) -> io::Result<Proof<Bls12>> {
+    let r = rng.gen();
+    let s = rng.gen();
+    create_proof(circuit, params, r, s, threads, scratch)
+}
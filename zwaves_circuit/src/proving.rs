@@ -0,0 +1,164 @@
+use bellman::Circuit;
+use bellman::groth16::{Parameters, Proof, generate_random_parameters, create_random_proof};
+use pairing::Engine;
+use pairing::bls12_381::{Bls12, Fr};
+use rand::{Rng, ChaChaRng};
+
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::thread;
+
+use zwaves_primitives::verifier::{PreparedTruncatedVerifyingKey, prepare_truncated_verifying_key, truncate_verifying_key, verify_prepared_proof};
+use zwaves_primitives::point::read_proof;
+use zwaves_primitives::metrics::{self, Counter, Timing};
+use zwaves_primitives::memory;
+
+use crate::prover::{self, Threads, ProverScratch};
+
+
+// What an integrator needs of a proof system, with its artifacts as associated types, so code
+// written against ProvingSystem<E> keeps working when another backend (PLONK, Halo2) is added
+// next to Groth16. The circuits are bellman circuits for every backend.
+//
+// Malformed artifacts and inputs are errors; Ok(false) means a well formed proof which is wrong.
+pub trait ProvingSystem<E: Engine> {
+    type Parameters;
+    type VerifyingKey;
+    type Proof;
+    // buffers a prover keeps from one proof to the next, () for a backend without them
+    type Scratch: Default;
+
+    fn setup<C: Circuit<E>, R: Rng>(circuit: C, rng: &mut R) -> io::Result<(Self::Parameters, Self::VerifyingKey)>;
+    fn prove<C: Circuit<E>, R: Rng>(params: &Self::Parameters, circuit: C, rng: &mut R) -> io::Result<Self::Proof>;
+
+    // A proof on the calling thread alone, reusing the buffers of the previous one, see prove_many
+    fn prove_with_scratch<C: Circuit<E>, R: Rng>(params: &Self::Parameters, circuit: C, rng: &mut R, _scratch: &mut Self::Scratch) -> io::Result<Self::Proof> {
+        Self::prove(params, circuit, rng)
+    }
+
+    fn verify(vk: &Self::VerifyingKey, proof: &Self::Proof, inputs: &[E::Fr]) -> io::Result<bool>;
+
+    fn write_proof<W: Write>(proof: &Self::Proof, writer: W) -> io::Result<()>;
+    fn read_proof<R: Read>(reader: R) -> io::Result<Self::Proof>;
+    fn write_verifying_key<W: Write>(vk: &Self::VerifyingKey, writer: W) -> io::Result<()>;
+    fn read_verifying_key<R: Read>(reader: R) -> io::Result<Self::VerifyingKey>;
+}
+
+
+// Backends whose proofs hide the witness. Everything proving notes or keys takes a
+// ZeroKnowledge system (prove_many); a backend for measurements only, like crate::marlin,
+// implements ProvingSystem alone and can not be passed there.
+pub trait ZeroKnowledge<E: Engine>: ProvingSystem<E> {}
+
+
+// Groth16 of bellman. The verifying key is the prepared truncated key, its encoding is
+// PreparedTruncatedVerifyingKey::write; the proof is the 192 byte compressed encoding.
+// The setup is a local trusted setup, for tests and development: production parameters
+// come from the ceremony (crate::setup). prove runs bellman's prover on its own worker threads,
+// prove_with_scratch the single threaded one of crate::prover with its buffers.
+pub struct Groth16;
+
+impl ProvingSystem<Bls12> for Groth16 {
+    type Parameters = Parameters<Bls12>;
+    type VerifyingKey = PreparedTruncatedVerifyingKey<Bls12>;
+    type Proof = Proof<Bls12>;
+    type Scratch = ProverScratch;
+
+    fn setup<C: Circuit<Bls12>, R: Rng>(circuit: C, rng: &mut R) -> io::Result<(Self::Parameters, Self::VerifyingKey)> {
+        let params = generate_random_parameters(circuit, rng).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let vk = prepare_truncated_verifying_key(&truncate_verifying_key(&params.vk));
+        Ok((params, vk))
+    }
+
+    fn prove<C: Circuit<Bls12>, R: Rng>(params: &Self::Parameters, circuit: C, rng: &mut R) -> io::Result<Self::Proof> {
+        memory::check_proving_memory_limit(params)?;
+        let proof = metrics::time(Timing::Prove, || create_random_proof(circuit, params, rng))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        metrics::inc(Counter::ProofsGenerated, 1);
+        Ok(proof)
+    }
+
+    fn prove_with_scratch<C: Circuit<Bls12>, R: Rng>(params: &Self::Parameters, circuit: C, rng: &mut R, scratch: &mut Self::Scratch) -> io::Result<Self::Proof> {
+        let proof = metrics::time(Timing::Prove, || prover::create_random_proof(circuit, params, rng, Threads::Single, scratch))?;
+        metrics::inc(Counter::ProofsGenerated, 1);
+        Ok(proof)
+    }
+
+    fn verify(vk: &Self::VerifyingKey, proof: &Self::Proof, inputs: &[Fr]) -> io::Result<bool> {
+        verify_prepared_proof(vk, proof, inputs).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    fn write_proof<W: Write>(proof: &Self::Proof, writer: W) -> io::Result<()> {
+        proof.write(writer)
+    }
+
+    fn read_proof<R: Read>(reader: R) -> io::Result<Self::Proof> {
+        read_proof::<Bls12, _>(reader, true)
+    }
+
+    fn write_verifying_key<W: Write>(vk: &Self::VerifyingKey, writer: W) -> io::Result<()> {
+        vk.write(writer)
+    }
+
+    fn read_verifying_key<R: Read>(reader: R) -> io::Result<Self::VerifyingKey> {
+        PreparedTruncatedVerifyingKey::<Bls12>::read(reader)
+    }
+}
+
+impl ZeroKnowledge<Bls12> for Groth16 {}
+
+
+// Proofs for a batch of witnesses, e.g. a relayer proving the transfers of its users, on parallelism
+// threads taking the next witness as they finish (0 is taken as 1). The parameters are shared by
+// every proof, for Groth16 their bases are behind Arc and nothing is copied. Every thread proves
+// with prove_with_scratch and keeps its scratch for its next proof: for Groth16 the FFT
+// evaluations, the assignment and the coefficients of h (crate::prover), allocated once per
+// thread and not once per proof. The proofs run single threaded, so there are exactly
+// parallelism threads and as many sets of buffers; the number of cores is a good value.
+//
+// Every worker proves with its own ChaChaRng seeded from rng. Results are in the order of the
+// witnesses, a witness which does not satisfy the circuit fails alone, and so does one whose
+// circuit panics: the workspace profiles build with panic = 'unwind' for that (see Cargo.toml).
+pub fn prove_many<E, S, C, R>(params: &S::Parameters, witnesses: Vec<C>, parallelism: usize, rng: &mut R) -> Vec<io::Result<S::Proof>>
+    where E: Engine,
+          S: ZeroKnowledge<E>,
+          S::Parameters: Sync,
+          S::Proof: Send,
+          C: Circuit<E> + Send,
+          R: Rng
+{
+    let parallelism = parallelism.max(1);
+
+    let n = witnesses.len();
+    let queue = Mutex::new(witnesses.into_iter().enumerate());
+    let rngs = (0..parallelism.min(n)).map(|_| rng.gen::<ChaChaRng>()).collect::<Vec<_>>();
+    let mut res = (0..n).map(|_| None).collect::<Vec<_>>();
+    thread::scope(|scope| {
+        let workers = rngs.into_iter().map(|mut rng| {
+            let queue = &queue;
+            scope.spawn(move || {
+                let mut scratch = S::Scratch::default();
+                let mut done = vec![];
+                loop {
+                    let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    match next {
+                        Some((i, circuit)) => {
+                            let proof = panic::catch_unwind(AssertUnwindSafe(|| S::prove_with_scratch(params, circuit, &mut rng, &mut scratch)))
+                                .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "proving panicked")));
+                            done.push((i, proof));
+                        },
+                        None => break
+                    }
+                }
+                done
+            })
+        }).collect::<Vec<_>>();
+        for worker in workers {
+            for (i, proof) in worker.join().unwrap_or_default() {
+                res[i] = Some(proof);
+            }
+        }
+    });
+    res.into_iter().map(|proof| proof.unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::Other, "proving worker failed")))).collect()
+}
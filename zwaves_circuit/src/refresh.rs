@@ -0,0 +1,72 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::Field;
+use rand::Rng;
+
+use zwaves_primitives::transactions::{self, NoteData};
+
+use std::io;
+
+use crate::circuit::{Blank, Transfer, NotePath};
+use crate::padding::{is_dummy, dummy_note, dummy_path};
+
+
+// A refresh spends notes of a wallet to new notes of the same owner, asset and amounts with a
+// new txid: the old commitments are nullified and the new ones are not linked to them, so a
+// wallet which rotates its notes does not leave old commitments an observer can follow.
+//
+// It is proved with the transfer circuit: output i mirrors input i, which satisfies the asset
+// checks for notes of any two assets, and a single note is paired with a dummy. On chain a
+// refresh is a private transfer like any other and needs no keys of its own. Nothing leaves
+// the pool, receiver and packed_asset are zero.
+fn refreshed<E: JubjubEngine, R: Rng>(note: &NoteData<E>, rng: &mut R) -> NoteData<E> {
+    NoteData { txid: rng.gen(), ..note.clone() }
+}
+
+// notes: one or two notes of pk with their paths to root_hash
+pub fn refresh_transfer<'a, E: JubjubEngine, R: Rng>(
+    notes: &[(NoteData<E>, NotePath<E>)],
+    root_hash: E::Fr,
+    sk: E::Fr,
+    params: &'a E::Params,
+    rng: &mut R
+) -> io::Result<Transfer<'a, E>> {
+    if notes.is_empty() || notes.len() > 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("refresh takes 1 or 2 notes, got {}", notes.len())));
+    }
+    let pk = transactions::pubkey::<E>(&sk, params);
+    for (i, (note, _)) in notes.iter().enumerate() {
+        if note.owner != pk {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("note {} is not owned by sk", i)));
+        }
+        if is_dummy(note) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("note {} is empty, nothing to refresh", i)));
+        }
+    }
+
+    let mut inputs = notes.to_vec();
+    if inputs.len() == 1 {
+        inputs.push((dummy_note(inputs[0].0.asset_id, pk, rng), dummy_path(rng)));
+    }
+
+    let mut t = Transfer::blank(params);
+    t.receiver = Some(E::Fr::zero());
+    t.out_note = [Some(refreshed(&inputs[0].0, rng)), Some(refreshed(&inputs[1].0, rng))];
+    t.in_note = [Some(inputs[0].0.clone()), Some(inputs[1].0.clone())];
+    t.in_proof = [Some(inputs[0].1.clone()), Some(inputs[1].1.clone())];
+    t.root_hash = Some(root_hash);
+    t.sk = Some(sk);
+    t.alpha = Some(rng.gen());
+    t.packed_asset = Some(E::Fr::zero());
+    Ok(t)
+}
+
+// Refreshes of all the notes of a wallet, two notes a transfer
+pub fn rotate_notes<'a, E: JubjubEngine, R: Rng>(
+    notes: &[(NoteData<E>, NotePath<E>)],
+    root_hash: E::Fr,
+    sk: E::Fr,
+    params: &'a E::Params,
+    rng: &mut R
+) -> io::Result<Vec<Transfer<'a, E>>> {
+    notes.chunks(2).map(|c| refresh_transfer(c, root_hash, sk, params, rng)).collect()
+}
@@ -0,0 +1,332 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError, Variable, Index, LinearCombination};
+use bellman::groth16::{Parameters, generate_random_parameters};
+use sapling_crypto::jubjub::JubjubBls12;
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{PrimeField, PrimeFieldRepr};
+use rand::{ChaChaRng, SeedableRng};
+
+use blake2_rfc::blake2s::Blake2s;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use std::io;
+
+use crate::circuit::{UtxoAccumulator, Transfer, AccountUpdate, Blank};
+
+
+pub const SETUP_SEED_PERSONALIZATION: &[u8; 8] = b"ZWsetupS";
+pub const SETUP_RNG_PERSONALIZATION: &[u8; 8] = b"ZWsetupR";
+pub const SETUP_TRANSCRIPT_PERSONALIZATION: &[u8; 8] = b"ZWsetupT";
+pub const SETUP_ARTIFACT_PERSONALIZATION: &[u8; 8] = b"ZWsetupA";
+pub const CIRCUIT_HASH_PERSONALIZATION: &[u8; 8] = b"ZWcircH_";
+
+pub const SETUP_REPORT_VERSION: u32 = 1;
+
+
+pub struct CircuitDescriptor {
+    pub name: &'static str,
+    pub generate: fn(&JubjubBls12, &mut ChaChaRng) -> Result<Parameters<Bls12>, SynthesisError>,
+    // circuit_hash of the blank circuit
+    pub shape: fn(&JubjubBls12) -> Result<[u8; 32], SynthesisError>
+}
+
+pub struct CircuitSetup {
+    pub name: String,
+    pub circuit_hash: [u8; 32],
+    pub params: Parameters<Bls12>,
+    pub params_hash: [u8; 32],
+    pub vk_hash: [u8; 32]
+}
+
+pub struct SetupOutput {
+    pub circuits: Vec<CircuitSetup>,
+    pub transcript: [u8; 32]
+}
+
+
+fn digest(personalization: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut h = Blake2s::with_params(32, &[], &[], personalization);
+    for p in parts.iter() {
+        h.update(p);
+    }
+    let mut res = [0u8; 32];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> io::Result<[u8; 32]> {
+    let err = || io::Error::new(io::ErrorKind::InvalidData, format!("wrong hash {}", s));
+    if s.len() != 64 || !s.is_ascii() {
+        return Err(err());
+    }
+    let mut res = [0u8; 32];
+    for (i, b) in res.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).map_err(|_| err())?;
+    }
+    Ok(res)
+}
+
+
+// Hash of the constraint system of a circuit: every constraint in order, as the variables and
+// the coefficients of its three linear combinations, then the numbers of variables. Two
+// circuits with the same hash give the same parameters for the same randomness.
+struct ShapeHasher {
+    h: Blake2s,
+    inputs: u64,
+    aux: u64
+}
+
+impl ShapeHasher {
+    fn lc(&mut self, lc: &LinearCombination<Bls12>) {
+        let mut data = vec![];
+        data.write_u32::<LittleEndian>(lc.as_ref().len() as u32).unwrap();
+        for (v, c) in lc.as_ref().iter() {
+            match v.get_unchecked() {
+                Index::Input(i) => { data.push(0); data.write_u64::<LittleEndian>(i as u64).unwrap(); },
+                Index::Aux(i) => { data.push(1); data.write_u64::<LittleEndian>(i as u64).unwrap(); }
+            }
+            c.into_repr().write_le(&mut data).unwrap();
+        }
+        self.h.update(&data);
+    }
+}
+
+impl ConstraintSystem<Bls12> for ShapeHasher {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, _: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.aux as usize - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, _: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.inputs as usize)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+        LA: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LB: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LC: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>
+    {
+        self.lc(&a(LinearCombination::zero()));
+        self.lc(&b(LinearCombination::zero()));
+        self.lc(&c(LinearCombination::zero()));
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N) where NR: Into<String>, N: FnOnce() -> NR {}
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+pub fn circuit_hash<C: Circuit<Bls12>>(circuit: C) -> Result<[u8; 32], SynthesisError> {
+    let mut cs = ShapeHasher { h: Blake2s::with_params(32, &[], &[], CIRCUIT_HASH_PERSONALIZATION), inputs: 0, aux: 0 };
+    circuit.synthesize(&mut cs)?;
+    let mut counts = vec![];
+    counts.write_u64::<LittleEndian>(cs.inputs).unwrap();
+    counts.write_u64::<LittleEndian>(cs.aux).unwrap();
+    cs.h.update(&counts);
+    let mut res = [0u8; 32];
+    res.copy_from_slice(cs.h.finalize().as_bytes());
+    Ok(res)
+}
+
+
+pub fn protocol_circuits() -> Vec<CircuitDescriptor> {
+    vec![
+        CircuitDescriptor {
+            name: "accumulator",
+            generate: |params, rng| generate_random_parameters(UtxoAccumulator::<Bls12>::blank(params), rng),
+            shape: |params| circuit_hash(UtxoAccumulator::<Bls12>::blank(params))
+        },
+        CircuitDescriptor {
+            name: "transfer",
+            generate: |params, rng| generate_random_parameters(Transfer::<Bls12>::blank(params), rng),
+            shape: |params| circuit_hash(Transfer::<Bls12>::blank(params))
+        },
+        CircuitDescriptor {
+            name: "account",
+            generate: |params, rng| generate_random_parameters(AccountUpdate::<Bls12>::blank(params), rng),
+            shape: |params| circuit_hash(AccountUpdate::<Bls12>::blank(params))
+        }
+    ]
+}
+
+
+// Generates parameters for all circuits from one seed. The randomness of every circuit is
+// derived from the transcript of the circuits before it, so a release is reproducible from
+// (seed, circuit list) and the final transcript hash commits to every verifying key of it.
+//
+// Whoever knows the seed knows the toxic waste: use it for testnets and release rehearsals,
+// mainnet parameters come from the MPC ceremony.
+pub fn setup_all(descriptors: &[CircuitDescriptor], seed: &[u8]) -> io::Result<SetupOutput> {
+    let jubjub_params = JubjubBls12::new();
+    let mut transcript = digest(SETUP_SEED_PERSONALIZATION, &[seed]);
+    let mut circuits = Vec::with_capacity(descriptors.len());
+
+    for d in descriptors.iter() {
+        let rng_seed = digest(SETUP_RNG_PERSONALIZATION, &[&transcript, d.name.as_bytes()]);
+        let mut rng_seed_words = [0u32; 8];
+        LittleEndian::read_u32_into(&rng_seed, &mut rng_seed_words);
+        let mut rng = ChaChaRng::from_seed(&rng_seed_words);
+
+        let params = (d.generate)(&jubjub_params, &mut rng)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{}: {}", d.name, e)))?;
+        let circuit_hash = (d.shape)(&jubjub_params)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{}: {}", d.name, e)))?;
+
+        let mut params_data = vec![];
+        params.write(&mut params_data)?;
+        let mut vk_data = vec![];
+        params.vk.write(&mut vk_data)?;
+
+        let params_hash = digest(SETUP_ARTIFACT_PERSONALIZATION, &[&params_data]);
+        let vk_hash = digest(SETUP_ARTIFACT_PERSONALIZATION, &[&vk_data]);
+        transcript = digest(SETUP_TRANSCRIPT_PERSONALIZATION, &[&transcript, d.name.as_bytes(), &vk_hash]);
+
+        circuits.push(CircuitSetup { name: d.name.to_string(), circuit_hash, params, params_hash, vk_hash });
+    }
+
+    Ok(SetupOutput { circuits, transcript })
+}
+
+
+impl SetupOutput {
+    // One line per circuit: name, params hash, vk hash (blake2s, hex), then the transcript hash.
+    pub fn manifest(&self) -> String {
+        let mut res = String::new();
+        for c in self.circuits.iter() {
+            res.push_str(&format!("{} {} {}\n", c.name, to_hex(&c.params_hash), to_hex(&c.vk_hash)));
+        }
+        res.push_str(&format!("transcript {}\n", to_hex(&self.transcript)));
+        res
+    }
+}
+
+
+// Machine readable record of a setup for public audits, one item per line:
+//
+// zwaves-setup-report <version>
+// toolchain <rustc version> | zwaves_circuit <crate version>
+// circuit <name> <circuit hash> <params hash> <vk hash>
+// transcript <transcript hash>
+//
+// The hashes are hex blake2s. An auditor reruns the setup from the published seed and checks
+// the report with verify; the toolchain is informational, parameters do not depend on it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitReport {
+    pub name: String,
+    pub circuit_hash: [u8; 32],
+    pub params_hash: [u8; 32],
+    pub vk_hash: [u8; 32]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetupReport {
+    pub toolchain: String,
+    pub circuits: Vec<CircuitReport>,
+    pub transcript: [u8; 32]
+}
+
+pub fn toolchain() -> String {
+    format!("{} | zwaves_circuit {}", env!("ZWAVES_RUSTC_VERSION"), env!("CARGO_PKG_VERSION"))
+}
+
+impl SetupOutput {
+    pub fn report(&self) -> SetupReport {
+        SetupReport {
+            toolchain: toolchain(),
+            circuits: self.circuits.iter().map(|c| CircuitReport {
+                name: c.name.clone(),
+                circuit_hash: c.circuit_hash,
+                params_hash: c.params_hash,
+                vk_hash: c.vk_hash
+            }).collect(),
+            transcript: self.transcript
+        }
+    }
+}
+
+impl SetupReport {
+    pub fn to_text(&self) -> String {
+        let mut res = format!("zwaves-setup-report {}\ntoolchain {}\n", SETUP_REPORT_VERSION, self.toolchain);
+        for c in self.circuits.iter() {
+            res.push_str(&format!("circuit {} {} {} {}\n", c.name, to_hex(&c.circuit_hash), to_hex(&c.params_hash), to_hex(&c.vk_hash)));
+        }
+        res.push_str(&format!("transcript {}\n", to_hex(&self.transcript)));
+        res
+    }
+
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let err = |m: &str| io::Error::new(io::ErrorKind::InvalidData, m.to_string());
+        let mut lines = text.lines();
+
+        if lines.next() != Some(format!("zwaves-setup-report {}", SETUP_REPORT_VERSION).as_str()) {
+            return Err(err("not a setup report of a known version"));
+        }
+        let toolchain = match lines.next() {
+            Some(l) if l.starts_with("toolchain ") => l["toolchain ".len()..].to_string(),
+            _ => return Err(err("toolchain is missing"))
+        };
+
+        let mut circuits = vec![];
+        let mut transcript = None;
+        for l in lines {
+            let words = l.split(' ').collect::<Vec<_>>();
+            match words.as_slice() {
+                ["circuit", name, c, p, v] if transcript.is_none() => circuits.push(CircuitReport {
+                    name: name.to_string(),
+                    circuit_hash: from_hex(c)?,
+                    params_hash: from_hex(p)?,
+                    vk_hash: from_hex(v)?
+                }),
+                ["transcript", t] if transcript.is_none() => transcript = Some(from_hex(t)?),
+                _ => return Err(err(&format!("unexpected line: {}", l)))
+            }
+        }
+
+        Ok(SetupReport { toolchain, circuits, transcript: transcript.ok_or_else(|| err("transcript is missing"))? })
+    }
+
+    // Fails on the first difference to the report of a rerun, the toolchain is not compared
+    pub fn verify(&self, rerun: &SetupReport) -> io::Result<()> {
+        let err = |m: String| Err(io::Error::new(io::ErrorKind::InvalidData, m));
+        if self.circuits.len() != rerun.circuits.len() {
+            return err(format!("report has {} circuits, the rerun has {}", self.circuits.len(), rerun.circuits.len()));
+        }
+        for (a, b) in self.circuits.iter().zip(rerun.circuits.iter()) {
+            if a.name != b.name {
+                return err(format!("circuit {} is reported in place of {}", a.name, b.name));
+            }
+            if a.circuit_hash != b.circuit_hash {
+                return err(format!("circuit hash of {} differs", a.name));
+            }
+            if a.params_hash != b.params_hash || a.vk_hash != b.vk_hash {
+                return err(format!("parameters of {} differ", a.name));
+            }
+        }
+        if self.transcript != rerun.transcript {
+            return err("transcript differs".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Reruns the setup and checks a published report against it
+pub fn verify_report(text: &str, descriptors: &[CircuitDescriptor], seed: &[u8]) -> io::Result<SetupReport> {
+    let report = SetupReport::from_text(text)?;
+    report.verify(&setup_all(descriptors, seed)?.report())?;
+    Ok(report)
+}
@@ -0,0 +1,51 @@
+use bellman::groth16::generate_random_parameters;
+use pairing::bls12_381::Bls12;
+use rand::{XorShiftRng, SeedableRng};
+
+use crate::bench::{run_benches, run_hash_benches, run_prove_benches, bench_parameters, to_json, bench_segmenting};
+use crate::prover::Threads;
+use zwaves_primitives::test_circuits::Squares;
+use zwaves_primitives::pedersen_segments::{Segmenting, SAPLING_SEGMENTING};
+
+use std::time::Instant;
+
+
+#[test]
+fn test_benches_deterministic() {
+    let mut t = 0.0;
+    let clock = || { t += 1.5; t };
+    let a = run_benches(clock);
+    let start = Instant::now();
+    let b = run_benches(|| start.elapsed().as_secs_f64() * 1000.0);
+
+    assert!(a.len() == 5);
+    assert!(a.iter().zip(b.iter()).all(|(x, y)| x.name == y.name && x.digest == y.digest), "Digests should not depend on the run");
+    assert!(a.iter().all(|r| r.total_ms == 1.5), "Every workload should read the clock twice");
+
+    let json = to_json(&a);
+    assert!(json.starts_with("{\"version\":2,\"results\":[{\"name\":\"pedersen_note_hash\",\"iterations\":32,\"total_ms\":1.500,\"per_iteration_ms\":0.047,\"digest\":\""));
+    assert!(a.iter().all(|r| json.contains(&format!("\"digest\":\"{}\"", r.digest))));
+
+    let h = run_hash_benches(|| 0.0);
+    assert!(h.len() == 3 && h.iter().zip(a.iter()).all(|(x, y)| x.name == y.name && x.digest == y.digest), "Hash benches should be the first ones of run_benches");
+
+    // what a wasm host runs with the parameters from a file
+    let p = run_prove_benches(|| 0.0, &bench_parameters(), Threads::Single).unwrap();
+    assert!(p.len() == 2 && p.iter().zip(a[3..].iter()).all(|(x, y)| x.name == y.name && x.digest == y.digest), "Proving benches should be the last ones of run_benches");
+
+    let mut rng = XorShiftRng::from_seed([0x1dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let other = generate_random_parameters::<Bls12, _, _>(Squares(10), &mut rng).unwrap();
+    assert!(run_prove_benches(|| 0.0, &other, Threads::Single).is_err(), "Parameters of another circuit should be an error");
+}
+
+#[test]
+fn test_bench_segmenting() {
+    let mut t = 0.0;
+    let segmentings = [SAPLING_SEGMENTING, Segmenting { exp_window: 4, ..SAPLING_SEGMENTING }, Segmenting { chunks_per_segment: 21, exp_window: 8 }];
+    let res = bench_segmenting(|| { t += 1.0; t }, &segmentings, 510, 2).unwrap();
+
+    assert!(res.len() == 3 && res.iter().all(|r| r.native.iterations == 2 && r.native.total_ms == 1.0));
+    assert!(res[0].constraints == res[1].constraints && res[0].native.digest == res[1].native.digest, "Window should change neither the circuit nor the hash");
+    assert!(res[2].constraints > res[0].constraints && res[2].native.digest != res[0].native.digest);
+    assert!(bench_segmenting(|| 0.0, &[Segmenting { chunks_per_segment: 64, exp_window: 8 }], 510, 1).is_err());
+}
@@ -0,0 +1,126 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use bellman::groth16::{generate_random_parameters, prepare_verifying_key, verify_proof};
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField};
+use rand::{Rng, XorShiftRng, SeedableRng};
+
+use std::io;
+
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use zwaves_primitives::transactions::{self, NoteData};
+use zwaves_primitives::tree::MerkleTree;
+use zwaves_primitives::spend_auth;
+
+use crate::circuit::{AuthorityTransfer, Blank, NotePath, MERKLE_PROOF_LEN};
+use crate::detached::{DetachedWitness, prove_detached, record_transfer};
+
+
+// x * y = z and y * y = w, x and z public
+#[derive(Clone)]
+struct MulSquare(Option<(Fr, Fr)>, bool);
+
+impl Circuit<Bls12> for MulSquare {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let v = self.0;
+        let prod = |a: Fr, b: Fr| { let mut r = a; r.mul_assign(&b); r };
+        let x = cs.alloc_input(|| "x", || v.map(|v| v.0).ok_or(SynthesisError::AssignmentMissing))?;
+        let y = cs.alloc(|| "y", || v.map(|v| v.1).ok_or(SynthesisError::AssignmentMissing))?;
+        let w = cs.alloc(|| "w", || v.map(|v| prod(v.1, v.1)).ok_or(SynthesisError::AssignmentMissing))?;
+        let z = cs.alloc_input(|| "z", || v.map(|v| prod(v.0, v.1)).ok_or(SynthesisError::AssignmentMissing))?;
+        cs.enforce(|| "x * y = z", |lc| lc + x, |lc| lc + y, |lc| lc + z);
+        if self.1 {
+            cs.enforce(|| "y * y = w", |lc| lc + y, |lc| lc + y, |lc| lc + w);
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_detached_witness() {
+    let mut rng = XorShiftRng::from_seed([0x5dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let params = generate_random_parameters::<Bls12, _, _>(MulSquare(None, true), &mut rng).unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+
+    let (x, y) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+    let mut z = x;
+    z.mul_assign(&y);
+
+    // wallet side
+    let witness = DetachedWitness::record(MulSquare(Some((x, y)), true)).unwrap();
+    let data = witness.to_bytes();
+    assert!(data.len() == 1 + 32 + 4 + 2 * 32 + 4 + 2 * 32);
+
+    // service side, with the inputs the signer expects
+    let witness = DetachedWitness::from_bytes(&data).unwrap();
+    let proof = prove_detached(&params, MulSquare(None, true), &witness, &[x, z], &mut rng).unwrap();
+    assert!(verify_proof(&pvk, &proof, &[x, z]).unwrap(), "Replayed witness should give a valid proof");
+
+    let err = prove_detached(&params, MulSquare(None, true), &witness, &[x, x], &mut rng).err().unwrap();
+    assert!(err.to_string() == "witness does not give the expected public inputs");
+    assert!(prove_detached(&params, MulSquare(None, false), &witness, &[x, z], &mut rng).is_err(), "Witness of another circuit should be rejected");
+
+    let mut short = witness.clone();
+    short.aux.pop();
+    assert!(prove_detached(&params, MulSquare(None, true), &short, &[x, z], &mut rng).is_err());
+
+    assert!(DetachedWitness::from_bytes(&data[..data.len() - 1]).is_err());
+}
+
+#[derive(Clone)]
+struct Broken;
+
+impl Circuit<Bls12> for Broken {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let x = cs.alloc(|| "x", || Ok(Fr::one()))?;
+        cs.namespace(|| "inner").enforce(|| "x * x = 0", |lc| lc + x, |lc| lc + x, |lc| lc);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_detached_witness_unsatisfied() {
+    let err = DetachedWitness::record(Broken).unwrap_err();
+    assert!(err.to_string() == "constraint inner/x * x = 0 is not satisfied", "Wrong witness should be found by the wallet");
+}
+
+#[test]
+fn test_detached_transfer() {
+    let rng = &mut XorShiftRng::from_seed([0x6dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let params = &*JUBJUB_PARAMS;
+    let sk = rng.gen::<Fr>();
+    let ak_key = spend_auth::spend_auth_key::<Bls12>(&sk, params);
+    let ak = ak_key.0.clone();
+    let nk = transactions::nullifier_key::<Bls12>(&sk, params);
+    let owner = transactions::threshold_owner::<Bls12>(&ak.into_xy().0, &nk);
+    let note = |amount: u64, owner: Fr, rng: &mut XorShiftRng| NoteData::<Bls12> {
+        asset_id: Fr::zero(), amount: Fr::from_repr(amount.into()).unwrap(), native_amount: Fr::zero(), txid: rng.gen(), owner
+    };
+    let in_note = [note(70, owner, rng), note(30, owner, rng)];
+    let mut tree = MerkleTree::<Bls12>::new(MERKLE_PROOF_LEN, params);
+    tree.push_many(&[transactions::note_hash(&in_note[0], params).unwrap(), transactions::note_hash(&in_note[1], params).unwrap()], params).unwrap();
+
+    let mut t = AuthorityTransfer::blank(params);
+    t.receiver = Some(rng.gen());
+    t.in_proof = [Some(NotePath::from_tree(&tree, 0).unwrap()), Some(NotePath::from_tree(&tree, 1).unwrap())];
+    t.out_note = [Some(note(90, rng.gen(), rng)), Some(note(10, owner, rng))];
+    t.in_note = [Some(in_note[0].clone()), Some(in_note[1].clone())];
+    t.root_hash = Some(tree.root());
+    t.ak = Some(ak);
+    t.nk = Some(nk);
+    t.alpha = Some(rng.gen());
+    t.packed_asset = Some(Fr::zero());
+
+    let witness = record_transfer(t.clone()).unwrap();
+    let (rk_x, rk_y) = spend_auth::rk_to_inputs(&spend_auth::randomized_public_key(&ak_key, &t.alpha.unwrap(), params));
+    assert!(witness.inputs.contains(&rk_x) && witness.inputs.contains(&rk_y), "Proof should be bound to rk");
+    assert!(!witness.aux.contains(&sk), "Detached witness should not hold sk");
+
+    let mut own = t.clone();
+    own.in_note[1] = Some(note(30, transactions::pubkey::<Bls12>(&sk, params), rng));
+    let err = record_transfer(own).unwrap_err();
+    assert!(err.kind() == io::ErrorKind::InvalidInput && err.to_string().starts_with("in_note[1] is owned by pubkey(sk)"), "Notes of pubkey(sk) should be rejected");
+
+    t.nk = Some(rng.gen());
+    assert!(record_transfer(t).is_err(), "Notes should only be spent with the nk of their owner");
+}
@@ -0,0 +1,74 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField};
+use rand::{Rng, XorShiftRng, SeedableRng};
+
+use crate::marlin::{Marlin, UniversalSrs, circuit_degree, index};
+use crate::proving::ProvingSystem;
+use zwaves_primitives::test_circuits::Mul;
+
+use crate::test::proving_test::prove_and_verify;
+
+
+// y_0 = x, y_{i+1} = y_i^2 + 3 y_i + x, y_n public
+struct Chain(usize, Option<Fr>);
+
+fn step(y: &Fr, x: &Fr) -> Fr {
+    let mut t = *y;
+    t.mul_assign(&Fr::from_str("3").unwrap());
+    let mut res = *y;
+    res.square();
+    res.add_assign(&t);
+    res.add_assign(x);
+    res
+}
+
+impl Circuit<Bls12> for Chain {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let (n, x_value) = (self.0, self.1);
+        let x = cs.alloc(|| "x", || x_value.ok_or(SynthesisError::AssignmentMissing))?;
+        let (mut v, mut y_value) = (x, x_value);
+        for i in 0..n {
+            y_value = x_value.and_then(|x| y_value.map(|y| step(&y, &x)));
+            let value = || y_value.ok_or(SynthesisError::AssignmentMissing);
+            let y = if i + 1 == n { cs.alloc_input(|| "y", value)? } else { cs.alloc(|| "y", value)? };
+            cs.enforce(|| "step", |lc| lc + v, |lc| lc + v + (Fr::from_str("3").unwrap(), CS::one()), |lc| lc + y - x);
+            v = y;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_marlin_proving_system() {
+    prove_and_verify::<Marlin>();
+}
+
+#[test]
+fn test_marlin_universal_srs() {
+    let mut rng = XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let degree = circuit_degree(Chain(20, None)).unwrap().max(circuit_degree(Mul(None)).unwrap());
+    let srs = UniversalSrs::new_insecure(degree, &mut rng).unwrap();
+
+    // both circuits indexed from the one SRS
+    let (params, vk) = index(&srs, Chain(20, None)).unwrap();
+    let (_, mul_vk) = index(&srs, Mul(None)).unwrap();
+    assert!(index(&srs, Chain(200, None)).is_err(), "SRS should be too small for a larger circuit");
+
+    let x = rng.gen::<Fr>();
+    let y = (0..20).fold(x, |y, _| step(&y, &x));
+    let proof = Marlin::prove(&params, Chain(20, Some(x)), &mut rng).unwrap();
+    assert!(Marlin::verify(&vk, &proof, &[y]).unwrap());
+    assert!(!Marlin::verify(&vk, &proof, &[x]).unwrap(), "Wrong input should give false");
+    assert!(!Marlin::verify(&mul_vk, &proof, &[y, y]).unwrap_or(false), "Proof should not verify for another circuit");
+
+    let mut tampered = proof.clone();
+    tampered.evaluations[12].add_assign(&Fr::one());
+    assert!(!Marlin::verify(&vk, &tampered, &[y]).unwrap(), "Tampered evaluation should give false");
+
+    let mut bad_vk = vk.clone();
+    bad_vk.k_log = 200;
+    assert!(Marlin::verify(&bad_vk, &proof, &[y]).is_err(), "Domain out of usize should be rejected");
+
+    assert!(Marlin::prove(&params, Chain(19, Some(x)), &mut rng).is_err(), "Another circuit should be rejected");
+}
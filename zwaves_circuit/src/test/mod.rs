@@ -1 +1,12 @@
-pub mod pedersen_test;
\ No newline at end of file
+pub mod pedersen_test;
+pub mod setup_test;
+pub mod bench_test;
+pub mod policy_test;
+pub mod detached_test;
+pub mod validate_test;
+pub mod padding_test;
+pub mod refresh_test;
+pub mod proving_test;
+pub mod prover_test;
+#[cfg(feature = "marlin")]
+pub mod marlin_test;
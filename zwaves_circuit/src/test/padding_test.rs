@@ -0,0 +1,52 @@
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField};
+use rand::{Rng, XorShiftRng, SeedableRng};
+use sapling_crypto::circuit::test::TestConstraintSystem;
+use bellman::Circuit;
+
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use zwaves_primitives::transactions::{self, NoteData};
+use zwaves_primitives::tree::MerkleTree;
+
+use crate::circuit::{Blank, Transfer, NotePath, MERKLE_PROOF_LEN};
+use crate::padding::{pad_inputs, pad_outputs, is_dummy};
+use crate::validate::validate_witness;
+
+
+#[test]
+fn test_padded_transfer() {
+    let rng = &mut XorShiftRng::from_seed([0x6dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let params = &*JUBJUB_PARAMS;
+    let sk = rng.gen::<Fr>();
+    let pk = transactions::pubkey::<Bls12>(&sk, params);
+    let amount = Fr::from_repr(100.into()).unwrap();
+    let asset_id = Fr::zero();
+
+    // one real note spent whole to one receiver
+    let in_note = NoteData::<Bls12> { asset_id, amount, native_amount: Fr::zero(), txid: rng.gen(), owner: pk };
+    let mut tree = MerkleTree::<Bls12>::new(MERKLE_PROOF_LEN, params);
+    tree.push_many(&[rng.gen(), transactions::note_hash(&in_note, params).unwrap()], params).unwrap();
+    let out_note = NoteData::<Bls12> { asset_id, amount, native_amount: Fr::zero(), txid: rng.gen(), owner: rng.gen() };
+
+    let (in_notes, in_paths) = pad_inputs(&[(in_note, NotePath::from_tree(&tree, 1).unwrap())], &pk, &asset_id, rng).unwrap();
+    let out_notes = pad_outputs(&[out_note], &pk, &asset_id, rng).unwrap();
+    assert!(!is_dummy(&in_notes[0]) && is_dummy(&in_notes[1]) && is_dummy(&out_notes[1]));
+    assert!(in_notes[1].txid != out_notes[1].txid, "Dummy notes should not repeat");
+
+    let mut t = Transfer::blank(params);
+    t.receiver = Some(rng.gen());
+    t.in_note = [Some(in_notes[0].clone()), Some(in_notes[1].clone())];
+    t.in_proof = [Some(in_paths[0].clone()), Some(in_paths[1].clone())];
+    t.out_note = [Some(out_notes[0].clone()), Some(out_notes[1].clone())];
+    t.root_hash = Some(tree.root());
+    t.sk = Some(sk);
+    t.alpha = Some(rng.gen());
+    t.packed_asset = Some(Fr::zero());
+    validate_witness(&t).unwrap();
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    t.synthesize(&mut cs).unwrap();
+    assert!(cs.is_satisfied(), "Padded transfer should satisfy the circuit");
+
+    let three = vec![out_notes[0].clone(); 3];
+    assert!(pad_outputs(&three, &pk, &asset_id, rng).is_err(), "Too many outputs should be rejected");
+}
@@ -1,12 +1,12 @@
-use bellman::{Circuit, ConstraintSystem, SynthesisError};
-use sapling_crypto::jubjub::{JubjubEngine, JubjubParams, JubjubBls12};
-use sapling_crypto::circuit::{pedersen_hash};
-use sapling_crypto::circuit::num::{AllocatedNum, Num};
+use sapling_crypto::jubjub::{JubjubParams, JubjubBls12};
 use bellman::groth16::{Proof, generate_random_parameters, prepare_verifying_key, create_random_proof, verify_proof};
 use pairing::bls12_381::{Bls12, Fr};
 use rand::os::OsRng;
 use rand::Rng;
 
+use crate::circuit::prelude::*;
+use crate::circuit::{num, pedersen};
+
 
 
 #[derive(Clone)]
@@ -22,16 +22,16 @@ impl <E: JubjubEngine> Circuit<E> for PedersenDemo<E> {
         cs: &mut CS
     ) -> Result<(), SynthesisError>
     {
-        let image = AllocatedNum::alloc(cs.namespace(|| "signal public input image"), || self.image.ok_or(SynthesisError::AssignmentMissing))?;
+        let image = num::alloc(cs.namespace(|| "signal public input image"), self.image)?;
         image.inputize(cs.namespace(|| "image inputize"));
-        let preimage = AllocatedNum::alloc(cs.namespace(|| "signal input preimage"), || self.preimage.ok_or(SynthesisError::AssignmentMissing))?;
+        let preimage = num::alloc(cs.namespace(|| "signal input preimage"), self.preimage)?;
         let preimage_bits = preimage.into_bits_le_strict(cs.namespace(|| "preimage_bits <== bitify(preimage)"))?;
-        let image_calculated = pedersen_hash::pedersen_hash(
+        let image_calculated = pedersen::hash(
             cs.namespace(|| "image_calculated <== pedersen_hash(preimage_bits)"),
-            pedersen_hash::Personalization::NoteCommitment,
+            Personalization::NoteCommitment,
             &preimage_bits,
             &self.params
-        )?.get_x().clone();
+        )?;
         cs.enforce(|| "image_calculated === image", |lc| lc + image.get_variable(), |lc| lc + CS::one(), |lc| lc + image_calculated.get_variable());
         Ok(())
     }
@@ -55,7 +55,7 @@ mod tests {
 
         let preimage = rng.gen();
         
-        let image = pedersen_hasher::hash::<Bls12>(&preimage, &params);
+        let image = pedersen_hasher::hash::<Bls12>(&preimage, pedersen_hasher::HASH_PERSONALIZATION, &params);
 
         println!("Preimage: {}", preimage);
         println!("Hash: {}", image);
@@ -93,4 +93,46 @@ mod tests {
     }
 
 
-}
\ No newline at end of file
+    #[test]
+    pub fn test_pedersen_proof_with_verifier_context(){
+        use std::sync::Arc;
+        use std::thread;
+        use zwaves_primitives::verifier::{truncate_verifying_key, VerifierContext};
+
+        let rng = &mut OsRng::new().unwrap();
+        let params = JubjubBls12::new();
+
+        let preimage = rng.gen();
+        let image = pedersen_hasher::hash::<Bls12>(&preimage, pedersen_hasher::HASH_PERSONALIZATION, &params);
+
+        let params = {
+            let c = PedersenDemo::<Bls12> {
+                params: Box::new(JubjubBls12::new()),
+                image: None,
+                preimage: None
+            };
+            generate_random_parameters(c, rng).unwrap()
+        };
+
+        let ctx = Arc::new(VerifierContext::<Bls12>::new(&truncate_verifying_key(&params.vk), JubjubBls12::new(), 48));
+        assert!(ctx.defaults().len() == 49, "defaults should cover every level up to the root");
+
+        let c = PedersenDemo::<Bls12> {
+            params: Box::new(JubjubBls12::new()),
+            image: Some(image),
+            preimage: Some(preimage)
+        };
+        let proof = Arc::new(create_random_proof(c, &params, rng).unwrap());
+
+        let handles = (0..4).map(|_| {
+            let ctx = ctx.clone();
+            let proof = proof.clone();
+            thread::spawn(move || ctx.verify(&proof, &[image]).unwrap())
+        }).collect::<Vec<_>>();
+
+        assert!(handles.into_iter().all(|h| h.join().unwrap()), "Proof is correct");
+        assert!(!ctx.verify(&proof, &[preimage]).unwrap(), "Proof should not match wrong inputs");
+    }
+
+
+}
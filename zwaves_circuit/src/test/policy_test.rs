@@ -0,0 +1,53 @@
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField};
+use rand::{Rng, XorShiftRng, SeedableRng};
+
+use zwaves_primitives::pedersen_hasher;
+use zwaves_primitives::transactions::NoteData;
+
+use crate::circuit::{Blank, Transfer};
+use crate::policy::TransferPolicy;
+
+
+fn note(amount: u64, native_amount: u64, owner: Fr) -> NoteData<Bls12> {
+    NoteData {
+        asset_id: Fr::zero(),
+        amount: Fr::from_repr(amount.into()).unwrap(),
+        native_amount: Fr::from_repr(native_amount.into()).unwrap(),
+        txid: Fr::zero(),
+        owner
+    }
+}
+
+fn transfer(out: [NoteData<Bls12>; 2], sender: Fr) -> Transfer<'static, Bls12> {
+    let mut t = Transfer::blank(&*pedersen_hasher::JUBJUB_PARAMS);
+    t.in_note = [Some(note(100, 0, sender)), Some(note(0, 0, sender))];
+    let [a, b] = out;
+    t.out_note = [Some(a), Some(b)];
+    t
+}
+
+
+#[test]
+fn test_transfer_policy() {
+    let mut rng = XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let (me, other) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+    let policy = TransferPolicy { min_output_amount: 10, min_native_amount: 5, max_outputs: 2, consolidate_change: true };
+
+    policy.check(&transfer([note(90, 0, other), note(10, 0, me)], me)).unwrap();
+    policy.check(&transfer([note(100, 0, other), note(0, 0, me)], me)).unwrap();
+
+    let err = policy.check(&transfer([note(95, 0, other), note(5, 0, me)], me)).unwrap_err();
+    assert!(err.to_string() == "output 1 amount 5 is below the minimum 10", "Dust output should be rejected with the reason");
+    assert!(policy.check(&transfer([note(100, 1, other), note(0, 0, me)], me)).is_err(), "Dust native amount should be rejected");
+    assert!(policy.check(&transfer([note(50, 0, me), note(50, 0, me)], me)).is_err(), "Split change should be rejected");
+    assert!(TransferPolicy::default().check(&transfer([note(50, 0, me), note(50, 0, me)], me)).is_ok());
+
+    let single = TransferPolicy { max_outputs: 1, ..TransferPolicy::default() };
+    assert!(single.check(&transfer([note(100, 0, other), note(0, 0, me)], me)).is_ok(), "Padding should not count as an output");
+    assert!(single.check(&transfer([note(90, 0, other), note(10, 0, me)], me)).is_err());
+
+    let mut missing = transfer([note(100, 0, other), note(0, 0, me)], me);
+    missing.out_note[1] = None;
+    assert!(policy.check(&missing).is_err());
+}
@@ -0,0 +1,48 @@
+use bellman::groth16::{Proof, generate_random_parameters, create_proof as bellman_create_proof, prepare_verifying_key, verify_proof};
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::Field;
+use rand::{Rng, XorShiftRng, SeedableRng};
+
+use zwaves_primitives::test_circuits::{Mul, Squares};
+
+use crate::prover::{Threads, ProverScratch, create_proof, create_random_proof};
+
+
+fn same_proof(a: &Proof<Bls12>, b: &Proof<Bls12>) -> bool {
+    a.a == b.a && a.b == b.b && a.c == b.c
+}
+
+fn check_threads(threads: Threads) {
+    let mut rng = XorShiftRng::from_seed([0x2dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let mut scratch = ProverScratch::default();
+
+    // Mul has an aux variable in B, Squares none in A and B and a domain of 1024
+    let params = generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap();
+    let (x, y, r, s) = (rng.gen::<Fr>(), rng.gen::<Fr>(), rng.gen::<Fr>(), rng.gen::<Fr>());
+    let proof = create_proof(Mul(Some((x, y))), &params, r, s, threads, &mut scratch).unwrap();
+    assert!(same_proof(&proof, &bellman_create_proof(Mul(Some((x, y))), &params, r, s).unwrap()), "Proof should be the one of bellman");
+
+    let mut z = x;
+    z.mul_assign(&y);
+    let proof = create_random_proof(Mul(Some((x, y))), &params, &mut rng, threads, &mut scratch).unwrap();
+    assert!(verify_proof(&prepare_verifying_key(&params.vk), &proof, &[x, z]).unwrap());
+
+    let params = generate_random_parameters::<Bls12, _, _>(Squares(1000), &mut rng).unwrap();
+    let (r, s) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+    let proof = create_proof(Squares(1000), &params, r, s, threads, &mut scratch).unwrap();
+    assert!(same_proof(&proof, &bellman_create_proof(Squares(1000), &params, r, s).unwrap()), "Scratch of a smaller circuit should not change the proof");
+
+    assert!(create_proof(Squares(2000), &params, r, s, threads, &mut scratch).is_err(), "Parameters of another circuit should be rejected");
+    assert!(create_proof(Mul(None), &params, r, s, threads, &mut scratch).is_err(), "Missing witness should be an error");
+}
+
+#[test]
+fn test_prover_single_thread() {
+    check_threads(Threads::Single);
+}
+
+#[cfg(feature = "threads")]
+#[test]
+fn test_prover_rayon() {
+    check_threads(Threads::Rayon);
+}
@@ -0,0 +1,89 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::Field;
+use rand::{Rng, XorShiftRng, SeedableRng};
+
+use zwaves_primitives::test_circuits::Mul;
+
+use crate::proving::{ProvingSystem, Groth16, prove_many};
+
+
+// written against the trait only, as integrators would
+pub(crate) fn prove_and_verify<S: ProvingSystem<Bls12>>() {
+    let mut rng = XorShiftRng::from_seed([0x8dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let (params, vk) = S::setup(Mul(None), &mut rng).unwrap();
+    let (x, y) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+    let mut z = x;
+    z.mul_assign(&y);
+    let proof = S::prove(&params, Mul(Some((x, y))), &mut rng).unwrap();
+
+    let mut proof_data = vec![];
+    S::write_proof(&proof, &mut proof_data).unwrap();
+    let mut vk_data = vec![];
+    S::write_verifying_key(&vk, &mut vk_data).unwrap();
+    let proof = S::read_proof(&proof_data[..]).unwrap();
+    let vk = S::read_verifying_key(&vk_data[..]).unwrap();
+
+    assert!(S::verify(&vk, &proof, &[x, z]).unwrap(), "Proof should be valid after the roundtrip");
+    assert!(!S::verify(&vk, &proof, &[z, x]).unwrap(), "Wrong inputs should give false");
+    assert!(S::verify(&vk, &proof, &[x]).is_err(), "Inputs should match the key");
+    assert!(S::read_proof(&proof_data[..100]).is_err());
+}
+
+#[test]
+fn test_groth16_proving_system() {
+    prove_and_verify::<Groth16>();
+}
+
+// Mul, or a circuit panicking in synthesize
+enum MaybePanic {
+    Mul(Mul),
+    Panic
+}
+
+impl Circuit<Bls12> for MaybePanic {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        match self {
+            MaybePanic::Mul(c) => c.synthesize(cs),
+            MaybePanic::Panic => panic!("bug in the circuit")
+        }
+    }
+}
+
+#[test]
+fn test_prove_many() {
+    let mut rng = XorShiftRng::from_seed([0x8dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let (params, vk) = Groth16::setup(Mul(None), &mut rng).unwrap();
+    let values = (0..5).map(|_| (rng.gen::<Fr>(), rng.gen::<Fr>())).collect::<Vec<_>>();
+    let mut witnesses = values.iter().map(|v| Mul(Some(*v))).collect::<Vec<_>>();
+    witnesses[3] = Mul(None);
+
+    let proofs = prove_many::<Bls12, Groth16, _, _>(&params, witnesses, 2, &mut rng);
+    assert!(proofs.len() == 5);
+    for (i, (proof, (x, y))) in proofs.iter().zip(values.iter()).enumerate() {
+        if i == 3 {
+            assert!(proof.is_err(), "Missing witness should fail its proof only");
+            continue;
+        }
+        let mut z = *x;
+        z.mul_assign(y);
+        assert!(Groth16::verify(&vk, proof.as_ref().unwrap(), &[*x, z]).unwrap(), "Proof {} should be of its witness", i);
+    }
+    assert!(prove_many::<Bls12, Groth16, Mul, _>(&params, vec![], 4, &mut rng).is_empty());
+
+    // one worker, its scratch reused from one proof to the next; 0 is taken as 1
+    for parallelism in [0, 1].iter() {
+        let witnesses = values.iter().map(|v| Mul(Some(*v))).collect::<Vec<_>>();
+        let proofs = prove_many::<Bls12, Groth16, _, _>(&params, witnesses, *parallelism, &mut rng);
+        for (proof, (x, y)) in proofs.iter().zip(values.iter()) {
+            let mut z = *x;
+            z.mul_assign(y);
+            assert!(Groth16::verify(&vk, proof.as_ref().unwrap(), &[*x, z]).unwrap(), "Proofs should not depend on the previous ones");
+        }
+    }
+
+    let witnesses = vec![MaybePanic::Panic, MaybePanic::Mul(Mul(Some(values[0]))), MaybePanic::Panic];
+    let proofs = prove_many::<Bls12, Groth16, _, _>(&params, witnesses, 2, &mut rng);
+    assert!(proofs[0].is_err() && proofs[2].is_err(), "Panicking circuit should fail its proof only");
+    assert!(proofs[1].is_ok());
+}
@@ -0,0 +1,48 @@
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField};
+use rand::{Rng, XorShiftRng, SeedableRng};
+use sapling_crypto::circuit::test::TestConstraintSystem;
+use bellman::Circuit;
+
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use zwaves_primitives::transactions::{self, NoteData};
+use zwaves_primitives::tree::MerkleTree;
+
+use crate::circuit::{NotePath, MERKLE_PROOF_LEN};
+use crate::refresh::rotate_notes;
+use crate::validate::validate_witness;
+
+
+#[test]
+fn test_rotate_notes() {
+    let rng = &mut XorShiftRng::from_seed([0x7dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let params = &*JUBJUB_PARAMS;
+    let sk = rng.gen::<Fr>();
+    let pk = transactions::pubkey::<Bls12>(&sk, params);
+
+    // notes of two assets, the last one is refreshed alone
+    let notes = [(0, 70), (1, 30), (0, 5)].iter().map(|&(asset, amount)| NoteData::<Bls12> {
+        asset_id: Fr::from_repr(asset.into()).unwrap(),
+        amount: Fr::from_repr(amount.into()).unwrap(),
+        native_amount: Fr::zero(),
+        txid: rng.gen(),
+        owner: pk
+    }).collect::<Vec<_>>();
+    let mut tree = MerkleTree::<Bls12>::new(MERKLE_PROOF_LEN, params);
+    tree.push_many(&notes.iter().map(|n| transactions::note_hash(n, params).unwrap()).collect::<Vec<_>>(), params).unwrap();
+    let owned = notes.iter().enumerate().map(|(i, n)| (n.clone(), NotePath::from_tree(&tree, i as u64).unwrap())).collect::<Vec<_>>();
+
+    let transfers = rotate_notes(&owned, tree.root(), sk, params, rng).unwrap();
+    assert!(transfers.len() == 2);
+    for t in transfers {
+        let out = t.out_note[0].clone().unwrap();
+        let old = t.in_note[0].clone().unwrap();
+        assert!(out.amount == old.amount && out.owner == pk && out.txid != old.txid, "Refresh should keep the note but its txid");
+        validate_witness(&t).unwrap();
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        t.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "Refresh should satisfy the transfer circuit");
+    }
+
+    assert!(rotate_notes(&owned, tree.root(), rng.gen(), params, rng).is_err(), "Notes of another key should be rejected");
+}
@@ -0,0 +1,123 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError, Variable, Index, LinearCombination};
+use bellman::groth16::generate_random_parameters;
+use sapling_crypto::jubjub::JubjubBls12;
+use pairing::bls12_381::{Bls12, Fr};
+
+use zwaves_primitives::test_circuits::Squares;
+
+use crate::gadgets::input_hash::HashedInputs;
+
+use crate::circuit::{Blank, Transfer, UtxoAccumulator, AccountUpdate};
+use crate::setup::{CircuitDescriptor, SetupReport, setup_all, verify_report, circuit_hash};
+
+
+fn descriptors() -> Vec<CircuitDescriptor> {
+    vec![
+        CircuitDescriptor { name: "small", generate: |_, rng| generate_random_parameters(Squares(4), rng), shape: |_| circuit_hash(Squares(4)) },
+        CircuitDescriptor { name: "big", generate: |_, rng| generate_random_parameters(Squares(40), rng), shape: |_| circuit_hash(Squares(40)) }
+    ]
+}
+
+
+#[test]
+fn test_setup_all_deterministic() {
+    let a = setup_all(&descriptors(), b"seed").unwrap();
+    let b = setup_all(&descriptors(), b"seed").unwrap();
+    assert!(a.manifest() == b.manifest(), "Same seed should give the same release");
+    assert!(a.circuits[1].params.vk.alpha_g1 == b.circuits[1].params.vk.alpha_g1);
+    assert!(a.manifest().lines().count() == 3);
+
+    let c = setup_all(&descriptors(), b"other seed").unwrap();
+    assert!(a.transcript != c.transcript && a.circuits[0].vk_hash != c.circuits[0].vk_hash);
+
+    // the second circuit depends on the first one through the transcript
+    let mut reordered = descriptors();
+    reordered.reverse();
+    let d = setup_all(&reordered, b"seed").unwrap();
+    assert!(d.circuits[1].vk_hash != a.circuits[0].vk_hash, "Randomness should be bound to the transcript");
+}
+
+
+#[test]
+fn test_setup_report() {
+    let report = setup_all(&descriptors(), b"seed").unwrap().report();
+    let text = report.to_text();
+    assert!(SetupReport::from_text(&text).unwrap() == report);
+    assert!(verify_report(&text, &descriptors(), b"seed").unwrap() == report, "Rerun should reproduce the report");
+    assert!(report.circuits[0].circuit_hash != report.circuits[1].circuit_hash);
+    assert!(circuit_hash(Squares(4)).unwrap() == report.circuits[0].circuit_hash);
+
+    assert!(verify_report(&text, &descriptors(), b"other seed").is_err(), "Other seed should not reproduce the report");
+    let other_toolchain = text.replace(&report.toolchain, "rustc 0.0.0");
+    assert!(verify_report(&other_toolchain, &descriptors(), b"seed").is_ok(), "Toolchain should not be compared");
+    let tampered = text.replacen("circuit small ", "circuit big ", 1);
+    assert!(verify_report(&tampered, &descriptors(), b"seed").is_err());
+    assert!(SetupReport::from_text(&text.replace("transcript", "transcripts")).is_err());
+}
+
+
+// Counts public inputs and constraints, and the inputs that got a value during synthesis
+#[derive(Default)]
+struct InputProbe {
+    inputs: usize,
+    assigned: usize,
+    constraints: usize
+}
+
+impl ConstraintSystem<Bls12> for InputProbe {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, _: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        Ok(Variable::new_unchecked(Index::Aux(0)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        self.inputs += 1;
+        self.assigned += f().is_ok() as usize;
+        Ok(Variable::new_unchecked(Index::Input(self.inputs)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, _: LA, _: LB, _: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+        LA: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LB: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>,
+        LC: FnOnce(LinearCombination<Bls12>) -> LinearCombination<Bls12>
+    {
+        self.constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N) where NR: Into<String>, N: FnOnce() -> NR {}
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+fn probe<C: Circuit<Bls12>>(c: C) -> InputProbe {
+    let mut cs = InputProbe::default();
+    c.synthesize(&mut cs).unwrap();
+    cs
+}
+
+#[test]
+fn test_blank_circuits() {
+    let params = JubjubBls12::new();
+
+    let probes = [
+        (probe(Transfer::blank(&params)), 9),
+        (probe(UtxoAccumulator::blank(&params)), 5),
+        (probe(AccountUpdate::blank(&params)), 5),
+        (probe(HashedInputs(UtxoAccumulator::blank(&params))), 1)
+    ];
+    for (i, (p, inputs)) in probes.iter().enumerate() {
+        assert!(p.inputs == *inputs, "Circuit {} should have {} inputs", i, inputs);
+        assert!(p.assigned == 0, "Blank circuit {} should not assign any input", i);
+        assert!(p.constraints > 0);
+    }
+}
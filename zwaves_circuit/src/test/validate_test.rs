@@ -0,0 +1,93 @@
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField};
+use rand::{Rng, XorShiftRng, SeedableRng};
+use sapling_crypto::circuit::test::TestConstraintSystem;
+use bellman::Circuit;
+
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use zwaves_primitives::transactions::{self, NoteData};
+use zwaves_primitives::tree::MerkleTree;
+
+use crate::circuit::{Blank, Transfer, NotePath, MERKLE_PROOF_LEN};
+use crate::validate::validate_witness;
+
+
+fn fr(x: u64) -> Fr {
+    Fr::from_repr(x.into()).unwrap()
+}
+
+fn note<R: Rng>(rng: &mut R, amount: u64, owner: Fr) -> NoteData<Bls12> {
+    NoteData { asset_id: Fr::zero(), amount: fr(amount), native_amount: Fr::zero(), txid: rng.gen(), owner }
+}
+
+// Transfer is not Clone for Bls12, every case rebuilds it from the same seed
+fn transfer() -> Transfer<'static, Bls12> {
+    let rng = &mut XorShiftRng::from_seed([0x4dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let params = &*JUBJUB_PARAMS;
+    let sk = rng.gen::<Fr>();
+    let pk = transactions::pubkey::<Bls12>(&sk, params);
+    let in_note = [note(rng, 70, pk), note(rng, 30, pk)];
+
+    let mut tree = MerkleTree::<Bls12>::new(MERKLE_PROOF_LEN, params);
+    tree.push_many(&[rng.gen(), transactions::note_hash(&in_note[0], params).unwrap(), transactions::note_hash(&in_note[1], params).unwrap()], params).unwrap();
+
+    let mut t = Transfer::blank(params);
+    t.receiver = Some(rng.gen());
+    t.in_proof = [Some(NotePath::from_tree(&tree, 1).unwrap()), Some(NotePath::from_tree(&tree, 2).unwrap())];
+    let receiver = rng.gen();
+    t.out_note = [Some(note(rng, 90, receiver)), Some(note(rng, 10, pk))];
+    t.in_note = [Some(in_note[0].clone()), Some(in_note[1].clone())];
+    t.root_hash = Some(tree.root());
+    t.sk = Some(sk);
+    t.alpha = Some(rng.gen());
+    t.packed_asset = Some(Fr::zero());
+    t
+}
+
+fn error(t: &Transfer<Bls12>) -> String {
+    validate_witness(t).unwrap_err().to_string()
+}
+
+
+#[test]
+fn test_validate_transfer() {
+    let mut rng = XorShiftRng::from_seed([0x5dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    validate_witness(&transfer()).unwrap();
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    transfer().synthesize(&mut cs).unwrap();
+    assert!(cs.is_satisfied(), "Valid witness should satisfy the circuit");
+
+    let mut bad = transfer();
+    bad.sk = Some(rng.gen());
+    assert!(error(&bad) == "input 0 is not owned by sk");
+
+    let mut bad = transfer();
+    bad.root_hash = Some(rng.gen());
+    assert!(error(&bad) == "input 0 is not in the tree of root_hash");
+
+    let mut bad = transfer();
+    bad.out_note[0].as_mut().unwrap().amount = fr(91);
+    assert!(error(&bad) == "amounts of inputs and outputs do not balance");
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    bad.synthesize(&mut cs).unwrap();
+    assert!(cs.which_is_unsatisfied() == Some("transfer/verification of amount sum"));
+
+    let mut bad = transfer();
+    bad.out_note[1].as_mut().unwrap().amount.negate();
+    assert!(error(&bad) == "output 1: value has 255 significant bits, 64 expected");
+
+    let mut bad = transfer();
+    bad.in_note[1] = bad.in_note[0].clone();
+    bad.in_proof[1] = bad.in_proof[0].clone();
+    bad.out_note[0].as_mut().unwrap().amount = fr(130);
+    assert!(error(&bad) == "inputs have the same nullifier, input 1 should be another note");
+
+    let mut bad = transfer();
+    bad.packed_asset = Some(fr(1));
+    bad.out_note[0].as_mut().unwrap().asset_id = fr(1);
+    assert!(error(&bad) == "input 0 and output 0 have different asset ids");
+
+    let mut bad = transfer();
+    bad.alpha = None;
+    assert!(error(&bad) == "alpha is missing");
+}
@@ -0,0 +1,170 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::{Field, PrimeField};
+
+use zwaves_primitives::transactions::{self, NoteData};
+use zwaves_primitives::fieldtools::significant_bits;
+
+use std::io;
+
+use crate::circuit::{Transfer, UtxoAccumulator, AccountUpdate, MERKLE_PROOF_LEN};
+
+
+// The statements of the circuits checked on the witness out of the circuit, before synthesis,
+// so a wrong witness is reported as "input 1 is not owned by sk" instead of an unsatisfied
+// constraint somewhere in the circuit or a panic in an allocation. The checks follow the
+// constraints of crate::gadgets::transactions one by one.
+//
+// None of the circuits witnesses a curve point: ak is sk*G computed in the circuit, so there is
+// no subgroup membership to check on the witness.
+pub trait ValidateWitness {
+    fn validate_witness(&self) -> io::Result<()>;
+}
+
+pub fn validate_witness<C: ValidateWitness>(circuit: &C) -> io::Result<()> {
+    circuit.validate_witness()
+}
+
+
+fn invalid<S: Into<String>>(msg: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+fn present<'a, T>(x: &'a Option<T>, name: &str) -> io::Result<&'a T> {
+    x.as_ref().ok_or_else(|| invalid(format!("{} is missing", name)))
+}
+
+fn sum<F: Field>(xs: &[F]) -> F {
+    xs.iter().fold(F::zero(), |mut acc, x| { acc.add_assign(x); acc })
+}
+
+// asset_id, amount and native amount of packed_asset, the amounts are signed 64 bit values as
+// in signed_asset_unpack
+fn unpack_asset<F: PrimeField>(packed_asset: &F) -> io::Result<(F, F, F)> {
+    if significant_bits(packed_asset) > 192 {
+        return Err(invalid("packed_asset does not fit 192 bits"));
+    }
+    let limbs = packed_asset.into_repr();
+    let limb = |x: u64| F::from_repr(x.into()).unwrap();
+    let signed = |x: u64| {
+        let mut res = limb(x);
+        if x >> 63 == 1 {
+            let mut two_64 = limb(1 << 32);
+            two_64.square();
+            res.sub_assign(&two_64);
+        }
+        res
+    };
+    Ok((limb(limbs.as_ref()[0]), signed(limbs.as_ref()[1]), signed(limbs.as_ref()[2])))
+}
+
+fn note_hash<E: JubjubEngine>(note: &NoteData<E>, name: &str, params: &E::Params) -> io::Result<E::Fr> {
+    transactions::note_hash(note, params).map_err(|e| invalid(format!("{}: {}", name, e)))
+}
+
+
+impl<'a, E: JubjubEngine> ValidateWitness for Transfer<'a, E> {
+    fn validate_witness(&self) -> io::Result<()> {
+        present(&self.receiver, "receiver")?;
+        let root_hash = present(&self.root_hash, "root_hash")?;
+        let sk = present(&self.sk, "sk")?;
+        present(&self.alpha, "alpha")?;
+        let packed_asset = present(&self.packed_asset, "packed_asset")?;
+
+        let mut in_note = vec![];
+        let mut out_note = vec![];
+        let mut in_hash = vec![];
+        for i in 0..2 {
+            in_note.push(present(&self.in_note[i], &format!("input {}", i))?);
+            out_note.push(present(&self.out_note[i], &format!("output {}", i))?);
+            in_hash.push(note_hash(in_note[i], &format!("input {}", i), self.params)?);
+            note_hash(out_note[i], &format!("output {}", i), self.params)?;
+        }
+
+        let pk = transactions::pubkey::<E>(sk, self.params);
+        for i in 0..2 {
+            if in_note[i].owner != pk {
+                return Err(invalid(format!("input {} is not owned by sk", i)));
+            }
+            let path = present(&self.in_proof[i], &format!("merkle path of input {}", i))?;
+            // empty inputs are not checked against the root
+            if !sum(&[in_note[i].amount, in_note[i].native_amount]).is_zero() && path.root(&in_hash[i], self.params) != *root_hash {
+                return Err(invalid(format!("input {} is not in the tree of root_hash", i)));
+            }
+            if in_note[i].asset_id != out_note[i].asset_id {
+                return Err(invalid(format!("input {} and output {} have different asset ids", i, i)));
+            }
+        }
+
+        let (asset_id, asset_amount, asset_native_amount) = unpack_asset(packed_asset)?;
+        if !asset_amount.is_zero() && asset_id != in_note[0].asset_id {
+            return Err(invalid("asset of packed_asset is not the asset of input 0"));
+        }
+        if sum(&[in_note[0].native_amount, in_note[1].native_amount, asset_native_amount]) != sum(&[out_note[0].native_amount, out_note[1].native_amount]) {
+            return Err(invalid("native amounts of inputs and outputs do not balance"));
+        }
+        if sum(&[in_note[0].amount, in_note[1].amount, asset_amount]) != sum(&[out_note[0].amount, out_note[1].amount]) {
+            return Err(invalid("amounts of inputs and outputs do not balance"));
+        }
+        if in_note[0].asset_id != in_note[1].asset_id && in_note[1].amount != out_note[1].amount {
+            return Err(invalid("input 1 and output 1 should have the same amount when the inputs have different assets"));
+        }
+
+        let nk = transactions::nullifier_key::<E>(sk, self.params);
+        if transactions::nullifier_from_key::<E>(&in_hash[0], &nk) == transactions::nullifier_from_key::<E>(&in_hash[1], &nk) {
+            return Err(invalid("inputs have the same nullifier, input 1 should be another note"));
+        }
+        Ok(())
+    }
+}
+
+
+impl<'a, E: JubjubEngine> ValidateWitness for UtxoAccumulator<'a, E> {
+    fn validate_witness(&self) -> io::Result<()> {
+        for i in 0..2 {
+            present(&self.note_hashes[i], &format!("note hash {}", i))?;
+        }
+        let index = present(&self.index, "index")?;
+        if significant_bits(index) > MERKLE_PROOF_LEN {
+            return Err(invalid(format!("index should be less than 2^{}", MERKLE_PROOF_LEN)));
+        }
+        for (proof, name) in [(&self.old_proof, "old_proof"), (&self.new_proof, "new_proof")].iter() {
+            let proof = present(proof, name)?;
+            if proof.len() != MERKLE_PROOF_LEN - 1 {
+                return Err(invalid(format!("{} should have {} siblings, got {}", name, MERKLE_PROOF_LEN - 1, proof.len())));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+impl<'a, E: JubjubEngine> ValidateWitness for AccountUpdate<'a, E> {
+    fn validate_witness(&self) -> io::Result<()> {
+        let account = present(&self.account, "account")?;
+        present(&self.proof, "merkle path of the account")?;
+        let sk = present(&self.sk, "sk")?;
+        let packed_asset = present(&self.packed_asset, "packed_asset")?;
+
+        if account.owner != transactions::pubkey::<E>(sk, self.params) {
+            return Err(invalid("account is not owned by sk"));
+        }
+        let nk = transactions::nullifier_key::<E>(sk, self.params);
+        transactions::account_hash(account, &nk, self.params).map_err(|e| invalid(format!("account: {}", e)))?;
+
+        let (asset_id, asset_amount, asset_native_amount) = unpack_asset(packed_asset)?;
+        if !asset_amount.is_zero() && asset_id != account.asset_id {
+            return Err(invalid("asset of packed_asset is not the asset of the account"));
+        }
+        let updated = [
+            (sum(&[account.balance, asset_amount]), "balance", 64),
+            (sum(&[account.native_balance, asset_native_amount]), "native balance", 64),
+            (sum(&[account.nonce, E::Fr::one()]), "nonce", 48)
+        ];
+        for (x, name, bits) in updated.iter() {
+            if significant_bits(x) > *bits {
+                return Err(invalid(format!("account {} after the update does not fit {} bits", name, bits)));
+            }
+        }
+        Ok(())
+    }
+}
@@ -75,6 +75,7 @@ let data = {
   in_proof_sibling,
   root_hash: mt.root(),
   sk,
+  alpha: fs_random(),
   packed_asset,
   receiver
 
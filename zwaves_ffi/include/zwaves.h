@@ -0,0 +1,91 @@
+/* C ABI of zwaves_circuit, built with `cargo build --release -p zwaves_ffi`.
+ *
+ * Field elements are 32 bytes big-endian. Parameters and verifying keys are the
+ * enveloped params_<name> / vk_<name> files. Every function returns ZWAVES_OK or
+ * an error code and writes its result through the out pointers. ZWAVES_ERR_PANIC
+ * is an internal error: a panic inside the library is caught at the boundary and
+ * reported with it.
+ */
+
+#ifndef ZWAVES_H
+#define ZWAVES_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define ZWAVES_OK 0
+#define ZWAVES_ERR_NULL_POINTER 1
+#define ZWAVES_ERR_INVALID_DATA 2
+#define ZWAVES_ERR_PROVING 3
+#define ZWAVES_ERR_POLICY 4
+#define ZWAVES_ERR_PANIC 5
+#define ZWAVES_ERR_MEMORY 6
+
+#define ZWAVES_FR_LEN 32
+#define ZWAVES_PROOF_LEN 192
+#define ZWAVES_MERKLE_PROOF_LEN 48
+
+typedef uint8_t zwaves_fr[ZWAVES_FR_LEN];
+
+typedef struct {
+    const uint8_t *data;
+    size_t len;
+} ZwavesBuffer;
+
+typedef struct {
+    zwaves_fr asset_id;
+    zwaves_fr amount;
+    zwaves_fr native_amount;
+    zwaves_fr txid;
+    zwaves_fr owner;
+} ZwavesNote;
+
+typedef struct {
+    zwaves_fr receiver;
+    ZwavesNote in_note[2];
+    zwaves_fr in_proof_sibling[2][ZWAVES_MERKLE_PROOF_LEN];
+    uint64_t in_proof_index[2];
+    ZwavesNote out_note[2];
+    zwaves_fr root_hash;
+    zwaves_fr sk;
+    zwaves_fr alpha;
+    zwaves_fr packed_asset;
+} ZwavesTransfer;
+
+/* Outputs with zero amount and native amount are padding and are not checked */
+typedef struct {
+    uint64_t min_output_amount;
+    uint64_t min_native_amount;
+    uint32_t max_outputs;
+    uint8_t consolidate_change;
+} ZwavesTransferPolicy;
+
+int32_t zwaves_hash(const zwaves_fr *input, zwaves_fr *out);
+
+int32_t zwaves_merkle_root(const zwaves_fr *sibling, size_t sibling_len, uint64_t index,
+                           const zwaves_fr *leaf, zwaves_fr *out);
+
+/* The witness is validated before proving, ZWAVES_ERR_INVALID_DATA for a transfer the
+   circuit would not accept, ZWAVES_ERR_MEMORY when the proof would go over the memory limit */
+int32_t zwaves_prove_transfer(ZwavesBuffer params, const ZwavesTransfer *transfer,
+                              uint8_t (*proof_out)[ZWAVES_PROOF_LEN]);
+
+/* For hosts without system entropy. The proving randomness comes from seed only,
+   it must be fresh and secret for every call */
+int32_t zwaves_prove_transfer_seeded(ZwavesBuffer params, const ZwavesTransfer *transfer,
+                                     ZwavesBuffer seed, uint8_t (*proof_out)[ZWAVES_PROOF_LEN]);
+
+/* Caps the heap a proof may take, in bytes on top of what the library holds already.
+   0 removes the limit */
+int32_t zwaves_set_memory_limit(size_t limit);
+
+/* Current and peak heap of the library, reset_peak != 0 starts a new peak */
+int32_t zwaves_memory_usage(size_t *allocated, size_t *peak, uint8_t reset_peak);
+
+/* Relayer policy checks to run before proving, ZWAVES_ERR_POLICY for a violation */
+int32_t zwaves_check_transfer_policy(const ZwavesTransfer *transfer, const ZwavesTransferPolicy *policy);
+
+/* *valid is 1 for a valid proof and 0 otherwise */
+int32_t zwaves_verify(ZwavesBuffer vk, ZwavesBuffer proof, ZwavesBuffer inputs, uint8_t *valid);
+
+#endif
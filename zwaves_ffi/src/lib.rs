@@ -0,0 +1,417 @@
+// the pointer contracts of the extern functions are documented in include/zwaves.h
+#![allow(clippy::missing_safety_doc)]
+
+use bellman::groth16::create_random_proof;
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+
+use zwaves_primitives::{pedersen_hasher, envelope, verifier};
+use zwaves_primitives::serialization::{read_fr_repr_be, read_fr_vec};
+use zwaves_primitives::transactions::NoteData;
+use zwaves_primitives::point::read_proof;
+use zwaves_primitives::entropy::{EntropySource, SystemEntropy, SeededEntropy};
+use zwaves_primitives::metrics::{self, Counter, Timing};
+use zwaves_primitives::memory::{self, CountingAllocator};
+
+use std::{io, slice};
+use std::alloc::System;
+use std::panic::{self, AssertUnwindSafe};
+
+use zwaves_circuit::circuit::{Transfer, NotePath, MERKLE_PROOF_LEN};
+use zwaves_circuit::policy::TransferPolicy;
+use zwaves_circuit::validate::validate_witness;
+
+
+// C ABI over the core primitives, see include/zwaves.h. Field elements are 32 bytes big-endian,
+// parameters and verifying keys are the enveloped files written by split and setup_all.
+// Every function returns one of the codes below and writes its result through the out pointers.
+// A panic is caught at the boundary and reported as ZWAVES_ERR_PANIC, it must not unwind into
+// the caller. This relies on panic = 'unwind', which the workspace profiles set for this reason.
+
+pub const ZWAVES_OK: i32 = 0;
+pub const ZWAVES_ERR_NULL_POINTER: i32 = 1;
+pub const ZWAVES_ERR_INVALID_DATA: i32 = 2;
+pub const ZWAVES_ERR_PROVING: i32 = 3;
+pub const ZWAVES_ERR_POLICY: i32 = 4;
+pub const ZWAVES_ERR_PANIC: i32 = 5;
+pub const ZWAVES_ERR_MEMORY: i32 = 6;
+
+pub const ZWAVES_FR_LEN: usize = 32;
+pub const ZWAVES_PROOF_LEN: usize = 192;
+
+
+#[repr(C)]
+pub struct ZwavesBuffer {
+    pub data: *const u8,
+    pub len: usize
+}
+
+#[repr(C)]
+pub struct ZwavesNote {
+    pub asset_id: [u8; ZWAVES_FR_LEN],
+    pub amount: [u8; ZWAVES_FR_LEN],
+    pub native_amount: [u8; ZWAVES_FR_LEN],
+    pub txid: [u8; ZWAVES_FR_LEN],
+    pub owner: [u8; ZWAVES_FR_LEN]
+}
+
+#[repr(C)]
+pub struct ZwavesTransfer {
+    pub receiver: [u8; ZWAVES_FR_LEN],
+    pub in_note: [ZwavesNote; 2],
+    pub in_proof_sibling: [[[u8; ZWAVES_FR_LEN]; MERKLE_PROOF_LEN]; 2],
+    pub in_proof_index: [u64; 2],
+    pub out_note: [ZwavesNote; 2],
+    pub root_hash: [u8; ZWAVES_FR_LEN],
+    pub sk: [u8; ZWAVES_FR_LEN],
+    pub alpha: [u8; ZWAVES_FR_LEN],
+    pub packed_asset: [u8; ZWAVES_FR_LEN]
+}
+
+#[repr(C)]
+pub struct ZwavesTransferPolicy {
+    pub min_output_amount: u64,
+    pub min_native_amount: u64,
+    pub max_outputs: u32,
+    pub consolidate_change: u8
+}
+
+
+fn error_code(e: &io::Error) -> i32 {
+    match e.kind() {
+        io::ErrorKind::Other => ZWAVES_ERR_PROVING,
+        io::ErrorKind::OutOfMemory => ZWAVES_ERR_MEMORY,
+        _ => ZWAVES_ERR_INVALID_DATA
+    }
+}
+
+fn read_fr(data: &[u8; ZWAVES_FR_LEN]) -> io::Result<Fr> {
+    Fr::from_repr(read_fr_repr_be::<Fr>(data)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))
+}
+
+fn write_fr(x: &Fr, out: &mut [u8; ZWAVES_FR_LEN]) -> io::Result<()> {
+    x.into_repr().write_be(&mut out[..])
+}
+
+fn guarded<F: FnOnce() -> i32>(f: F) -> i32 {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(ZWAVES_ERR_PANIC)
+}
+
+unsafe fn buffer<'a>(b: &ZwavesBuffer) -> Option<&'a [u8]> {
+    if b.data.is_null() {
+        if b.len == 0 {
+            return Some(&[]);
+        }
+        return None;
+    }
+    Some(slice::from_raw_parts(b.data, b.len))
+}
+
+fn read_note(note: &ZwavesNote) -> io::Result<NoteData<Bls12>> {
+    Ok(NoteData {
+        asset_id: read_fr(&note.asset_id)?,
+        amount: read_fr(&note.amount)?,
+        native_amount: read_fr(&note.native_amount)?,
+        txid: read_fr(&note.txid)?,
+        owner: read_fr(&note.owner)?
+    })
+}
+
+fn read_transfer(t: &ZwavesTransfer) -> io::Result<Transfer<'static, Bls12>> {
+    let mut in_proof = [None, None];
+    for ((p, path), index) in in_proof.iter_mut().zip(t.in_proof_sibling.iter()).zip(t.in_proof_index.iter()) {
+        let mut sibling = [Fr::zero(); MERKLE_PROOF_LEN];
+        for (s, x) in sibling.iter_mut().zip(path.iter()) {
+            *s = read_fr(x)?;
+        }
+        *p = Some(NotePath::new(sibling, *index).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+    }
+
+    Ok(Transfer {
+        receiver: Some(read_fr(&t.receiver)?),
+        in_note: [Some(read_note(&t.in_note[0])?), Some(read_note(&t.in_note[1])?)],
+        in_proof,
+        out_note: [Some(read_note(&t.out_note[0])?), Some(read_note(&t.out_note[1])?)],
+        root_hash: Some(read_fr(&t.root_hash)?),
+        sk: Some(read_fr(&t.sk)?),
+        alpha: Some(read_fr(&t.alpha)?),
+        packed_asset: Some(read_fr(&t.packed_asset)?),
+        params: &pedersen_hasher::JUBJUB_PARAMS
+    })
+}
+
+
+#[no_mangle]
+pub unsafe extern "C" fn zwaves_hash(input: *const [u8; ZWAVES_FR_LEN], out: *mut [u8; ZWAVES_FR_LEN]) -> i32 {
+    guarded(|| {
+        if input.is_null() || out.is_null() {
+            return ZWAVES_ERR_NULL_POINTER;
+        }
+        let res = read_fr(&*input).and_then(|x| {
+            write_fr(&pedersen_hasher::hash::<Bls12>(&x, pedersen_hasher::HASH_PERSONALIZATION, &pedersen_hasher::JUBJUB_PARAMS), &mut *out)
+        });
+
+        match res {
+            Ok(()) => ZWAVES_OK,
+            Err(e) => error_code(&e)
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn zwaves_merkle_root(
+    sibling: *const [u8; ZWAVES_FR_LEN],
+    sibling_len: usize,
+    index: u64,
+    leaf: *const [u8; ZWAVES_FR_LEN],
+    out: *mut [u8; ZWAVES_FR_LEN]
+) -> i32 {
+    guarded(|| {
+        if (sibling.is_null() && sibling_len != 0) || leaf.is_null() || out.is_null() {
+            return ZWAVES_ERR_NULL_POINTER;
+        }
+        if sibling_len > pedersen_hasher::MERKLE_MAX_DEPTH || index >> sibling_len != 0 {
+            return ZWAVES_ERR_INVALID_DATA;
+        }
+
+        let sibling = if sibling_len == 0 { &[][..] } else { slice::from_raw_parts(sibling, sibling_len) };
+        let res = sibling.iter().map(read_fr).collect::<io::Result<Vec<_>>>().and_then(|sibling| {
+            let leaf = read_fr(&*leaf)?;
+            write_fr(&pedersen_hasher::merkle_root::<Bls12>(&sibling, index, &leaf, &pedersen_hasher::JUBJUB_PARAMS), &mut *out)
+        });
+
+        match res {
+            Ok(()) => ZWAVES_OK,
+            Err(e) => error_code(&e)
+        }
+    })
+}
+
+unsafe fn prove_transfer<S: EntropySource>(
+    params: ZwavesBuffer,
+    transfer: *const ZwavesTransfer,
+    entropy: &mut S,
+    proof_out: *mut [u8; ZWAVES_PROOF_LEN]
+) -> i32 {
+    let params = match buffer(&params) {
+        Some(p) => p,
+        None => return ZWAVES_ERR_NULL_POINTER
+    };
+    if transfer.is_null() || proof_out.is_null() {
+        return ZWAVES_ERR_NULL_POINTER;
+    }
+
+    let res = envelope::unwrap_parameters::<Bls12>(params).and_then(|params| {
+        memory::check_proving_memory_limit(&params)?;
+        let c = read_transfer(&*transfer)?;
+        validate_witness(&c)?;
+        let mut rng = entropy.rng()?;
+        let proof = metrics::time(Timing::Prove, || create_random_proof(c, &params, &mut rng)).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        metrics::inc(Counter::ProofsGenerated, 1);
+        Ok(proof)
+    });
+
+    match res.and_then(|proof| proof.write(&mut (&mut *proof_out)[..]).map_err(|e| io::Error::new(io::ErrorKind::Other, e))) {
+        Ok(()) => ZWAVES_OK,
+        Err(e) => error_code(&e)
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn zwaves_prove_transfer(
+    params: ZwavesBuffer,
+    transfer: *const ZwavesTransfer,
+    proof_out: *mut [u8; ZWAVES_PROOF_LEN]
+) -> i32 {
+    guarded(|| {
+        prove_transfer(params, transfer, &mut SystemEntropy, proof_out)
+    })
+}
+
+// For hosts without system entropy: the proving randomness is derived from seed only, so the
+// seed must be fresh and secret for every call.
+#[no_mangle]
+pub unsafe extern "C" fn zwaves_prove_transfer_seeded(
+    params: ZwavesBuffer,
+    transfer: *const ZwavesTransfer,
+    seed: ZwavesBuffer,
+    proof_out: *mut [u8; ZWAVES_PROOF_LEN]
+) -> i32 {
+    guarded(|| {
+        let seed = match buffer(&seed) {
+            Some(s) if !s.is_empty() => s,
+            Some(_) => return ZWAVES_ERR_INVALID_DATA,
+            None => return ZWAVES_ERR_NULL_POINTER
+        };
+        prove_transfer(params, transfer, &mut SeededEntropy::new(seed), proof_out)
+    })
+}
+
+// Heap usage of the library, for the proving limit
+#[global_allocator]
+static ALLOCATOR: CountingAllocator<System> = CountingAllocator::new(System);
+
+// Proofs which would take the heap of the library over limit bytes fail with ZWAVES_ERR_MEMORY
+// before they start. 0 removes the limit.
+#[no_mangle]
+pub extern "C" fn zwaves_set_memory_limit(limit: usize) -> i32 {
+    memory::register_heap(&ALLOCATOR);
+    memory::set_proving_memory_limit(limit);
+    ZWAVES_OK
+}
+
+// Current and peak heap of the library; reset_peak != 0 starts a new peak from the current usage.
+#[no_mangle]
+pub unsafe extern "C" fn zwaves_memory_usage(allocated: *mut usize, peak: *mut usize, reset_peak: u8) -> i32 {
+    if allocated.is_null() || peak.is_null() {
+        return ZWAVES_ERR_NULL_POINTER;
+    }
+    *allocated = ALLOCATOR.allocated();
+    *peak = ALLOCATOR.peak();
+    if reset_peak != 0 {
+        ALLOCATOR.reset_peak();
+    }
+    ZWAVES_OK
+}
+
+// Relayer policy check to run before zwaves_prove_transfer, ZWAVES_ERR_POLICY for a violation.
+#[no_mangle]
+pub unsafe extern "C" fn zwaves_check_transfer_policy(
+    transfer: *const ZwavesTransfer,
+    policy: *const ZwavesTransferPolicy
+) -> i32 {
+    guarded(|| {
+        if transfer.is_null() || policy.is_null() {
+            return ZWAVES_ERR_NULL_POINTER;
+        }
+        let policy = TransferPolicy {
+            min_output_amount: (*policy).min_output_amount,
+            min_native_amount: (*policy).min_native_amount,
+            max_outputs: (*policy).max_outputs as usize,
+            consolidate_change: (*policy).consolidate_change != 0
+        };
+
+        match read_transfer(&*transfer) {
+            Ok(t) => match policy.check(&t) {
+                Ok(()) => ZWAVES_OK,
+                Err(_) => ZWAVES_ERR_POLICY
+            },
+            Err(e) => error_code(&e)
+        }
+    })
+}
+
+// *valid is set to 1 for a valid proof and 0 otherwise; malformed data is reported by the return code.
+#[no_mangle]
+pub unsafe extern "C" fn zwaves_verify(
+    vk: ZwavesBuffer,
+    proof: ZwavesBuffer,
+    inputs: ZwavesBuffer,
+    valid: *mut u8
+) -> i32 {
+    guarded(|| {
+        let (vk, proof, inputs) = match (buffer(&vk), buffer(&proof), buffer(&inputs)) {
+            (Some(vk), Some(proof), Some(inputs)) => (vk, proof, inputs),
+            _ => return ZWAVES_ERR_NULL_POINTER
+        };
+        if valid.is_null() {
+            return ZWAVES_ERR_NULL_POINTER;
+        }
+        if proof.len() != ZWAVES_PROOF_LEN || inputs.len() % ZWAVES_FR_LEN != 0 {
+            return ZWAVES_ERR_INVALID_DATA;
+        }
+
+        let res = envelope::unwrap_verifying_key::<Bls12>(vk).and_then(|vk| {
+            let proof = read_proof::<Bls12, _>(proof, true)?;
+            let inputs = read_fr_vec::<Fr>(inputs)?;
+            verifier::verify_proof(&vk, &proof, &inputs).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        });
+
+        match res {
+            Ok(v) => {
+                *valid = v as u8;
+                ZWAVES_OK
+            },
+            Err(e) => error_code(&e)
+        }
+    })
+}
+
+
+#[cfg(test)]
+mod ffi_tests {
+    use rand::{Rng, XorShiftRng, SeedableRng};
+
+    use bellman::groth16::generate_random_parameters;
+    use zwaves_primitives::test_circuits::Squares;
+
+    use super::*;
+
+    use std::{mem, ptr};
+
+
+    fn to_bytes(x: &Fr) -> [u8; ZWAVES_FR_LEN] {
+        let mut res = [0u8; ZWAVES_FR_LEN];
+        x.into_repr().write_be(&mut res[..]).unwrap();
+        res
+    }
+
+
+    #[test]
+    fn test_ffi_hash_and_merkle_root() {
+        let mut rng = XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = &pedersen_hasher::JUBJUB_PARAMS;
+
+        let x = rng.gen::<Fr>();
+        let mut out = [0u8; ZWAVES_FR_LEN];
+        assert!(unsafe { zwaves_hash(&to_bytes(&x), &mut out) } == ZWAVES_OK);
+        assert!(out == to_bytes(&pedersen_hasher::hash::<Bls12>(&x, pedersen_hasher::HASH_PERSONALIZATION, params)));
+
+        let sibling = (0..8).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let sibling_bytes = sibling.iter().map(to_bytes).collect::<Vec<_>>();
+        let leaf = rng.gen::<Fr>();
+        assert!(unsafe { zwaves_merkle_root(sibling_bytes.as_ptr(), 8, 77, &to_bytes(&leaf), &mut out) } == ZWAVES_OK);
+        assert!(out == to_bytes(&pedersen_hasher::merkle_root::<Bls12>(&sibling, 77, &leaf, params)));
+
+        assert!(unsafe { zwaves_merkle_root(sibling_bytes.as_ptr(), 8, 256, &to_bytes(&leaf), &mut out) } == ZWAVES_ERR_INVALID_DATA, "Index out of the tree should be rejected");
+        assert!(unsafe { zwaves_merkle_root(ptr::null(), 0, 0, &to_bytes(&leaf), &mut out) } == ZWAVES_OK);
+        assert!(out == to_bytes(&leaf));
+    }
+
+    #[test]
+    fn test_ffi_errors() {
+        let mut out = [0u8; ZWAVES_FR_LEN];
+        assert!(unsafe { zwaves_hash(ptr::null(), &mut out) } == ZWAVES_ERR_NULL_POINTER);
+        assert!(unsafe { zwaves_hash(&[0xffu8; ZWAVES_FR_LEN], &mut out) } == ZWAVES_ERR_INVALID_DATA, "Elements out of the field should be rejected");
+
+        let empty = || ZwavesBuffer { data: ptr::null(), len: 0 };
+        let mut valid = 0u8;
+        assert!(unsafe { zwaves_verify(ZwavesBuffer { data: ptr::null(), len: 10 }, empty(), empty(), &mut valid) } == ZWAVES_ERR_NULL_POINTER);
+        assert!(unsafe { zwaves_verify(empty(), empty(), empty(), &mut valid) } == ZWAVES_ERR_INVALID_DATA, "Proof of wrong length should be rejected");
+        assert!(unsafe { zwaves_prove_transfer(empty(), ptr::null(), ptr::null_mut()) } == ZWAVES_ERR_NULL_POINTER);
+        assert!(unsafe { zwaves_prove_transfer_seeded(empty(), ptr::null(), empty(), ptr::null_mut()) } == ZWAVES_ERR_INVALID_DATA, "Empty seed should be rejected");
+        assert!(unsafe { zwaves_check_transfer_policy(ptr::null(), ptr::null()) } == ZWAVES_ERR_NULL_POINTER);
+        assert!(guarded(|| panic!("internal error")) == ZWAVES_ERR_PANIC, "Panic should not unwind into the caller");
+    }
+
+    #[test]
+    fn test_ffi_memory_limit() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = generate_random_parameters::<Bls12, _, _>(Squares(100), rng).unwrap();
+        let data = envelope::wrap_parameters(&params).unwrap().to_bytes().unwrap();
+        let params = || ZwavesBuffer { data: data.as_ptr(), len: data.len() };
+        let transfer: ZwavesTransfer = unsafe { mem::zeroed() };
+        let mut proof = [0u8; ZWAVES_PROOF_LEN];
+
+        // the only test proving, the limit of the process does not reach the others
+        assert!(zwaves_set_memory_limit(1000) == ZWAVES_OK);
+        assert!(unsafe { zwaves_prove_transfer(params(), &transfer, &mut proof) } == ZWAVES_ERR_MEMORY, "Proof over the limit should be rejected");
+        assert!(zwaves_set_memory_limit(0) == ZWAVES_OK);
+        assert!(unsafe { zwaves_prove_transfer(params(), &transfer, &mut proof) } == ZWAVES_ERR_INVALID_DATA, "Without a limit the transfer is read");
+
+        let (mut allocated, mut peak) = (0, 0);
+        assert!(unsafe { zwaves_memory_usage(&mut allocated, &mut peak, 1) } == ZWAVES_OK);
+        assert!(allocated > 0 && peak >= allocated, "Heap of the test should be counted");
+        assert!(unsafe { zwaves_memory_usage(ptr::null_mut(), &mut peak, 0) } == ZWAVES_ERR_NULL_POINTER);
+    }
+}
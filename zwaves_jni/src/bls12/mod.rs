@@ -1,42 +1,12 @@
 use std::io;
 
-use bellman::groth16::Proof;
-use pairing::bls12_381::{Bls12, Fr};
-
-use zwaves_primitives::{
-    serialization::read_fr_vec,
-    verifier::{verify_proof, TruncatedVerifyingKey},
-};
+use zwaves_primitives::verifier::groth16_verify_bytes;
 
 #[cfg(test)]
 pub mod tests;
 
 pub fn groth16_verify(vk: &[u8], proof: &[u8], inputs: &[u8]) -> io::Result<u8> {
-    let buff_vk_len = vk.len();
-    let buff_proof_len = proof.len();
-    let buff_inputs_len = inputs.len();
-
-    if (buff_vk_len % 48 != 0) || (buff_inputs_len % 32 != 0) {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong buffer length"));
-    }
-
-    let inputs_len = buff_inputs_len / 32;
-
-    if ((buff_vk_len / 48) != (inputs_len + 8)) || (buff_proof_len != 192) {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong buffer length"));
-    }
-
-    let vk = TruncatedVerifyingKey::<Bls12>::read(vk)?;
-    let proof = Proof::<Bls12>::read(proof)?;
-    let inputs = read_fr_vec::<Fr>(inputs)?;
-
-    if (inputs.len() != inputs_len) || (vk.ic.len() != (inputs_len + 1)) {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong buffer parsing"));
-    }
-
-    Ok(verify_proof(&vk, &proof, inputs.as_slice())
-        .map(|r| r as u8)
-        .unwrap_or(0))
+    groth16_verify_bytes(vk, proof, inputs).map(|r| r as u8)
 }
 
 #[cfg(test)]
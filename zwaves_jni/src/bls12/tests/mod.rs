@@ -95,7 +95,7 @@ mod tests {
         let preimage = rng.gen();
         let params = JubjubBls12::new();
 
-        let image = pedersen_hasher::hash::<Bls12>(&preimage, &params);
+        let image = pedersen_hasher::hash::<Bls12>(&preimage, pedersen_hasher::HASH_PERSONALIZATION, &params);
 
         println!("Preimage: {}", preimage);
         println!("Hash: {}", image);
@@ -0,0 +1,172 @@
+use napi::bindgen_prelude::*;
+use napi::{Env, JsFunction, JsUnknown, NapiRaw};
+use napi_derive::napi;
+
+use bellman::groth16::create_random_proof;
+use pairing::bls12_381::{Bls12, Fr};
+
+use zwaves_circuit::validate::validate_witness;
+use zwaves_primitives::envelope;
+use zwaves_primitives::entropy::{EntropySource, SystemEntropy};
+use zwaves_primitives::metrics::{self, Counter, Timing};
+use zwaves_primitives::memory;
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use zwaves_primitives::transactions::NoteData;
+use zwaves_primitives::witness_source::{WitnessSource, spend_witness as build_spend_witness};
+use zwaves_wasm::{read_fr, read_fr_list, read_note, read_transfer, write_spend_witness};
+
+use std::io;
+
+
+// The API of zwaves_wasm with native speed: the same functions under the same names (camelCase),
+// the same encodings, and zwavesCall with the same methods, so a relayer can switch between the
+// two packages without touching its calls. Proving runs on the libuv thread pool and returns a
+// promise, several proofs are computed at once (see UV_THREADPOOL_SIZE).
+
+fn napi_err(e: io::Error) -> Error {
+    Error::new(Status::GenericFailure, e.to_string())
+}
+
+fn read_u64(x: BigInt, name: &str) -> Result<u64> {
+    match x.get_u64() {
+        (false, v, true) => Ok(v),
+        _ => Err(Error::new(Status::InvalidArg, format!("{} should fit into uint64", name)))
+    }
+}
+
+
+#[napi]
+pub fn hash(x: Buffer) -> Result<Buffer> {
+    Ok(zwaves_wasm::hash_native(&x).map_err(napi_err)?.into())
+}
+
+#[napi]
+pub fn merkle_hash(left: Buffer, right: Buffer, level: u32) -> Result<Buffer> {
+    Ok(zwaves_wasm::merkle_hash_native(&left, &right, level as usize).map_err(napi_err)?.into())
+}
+
+#[napi]
+pub fn merkle_root(sibling: Buffer, index: BigInt, leaf: Buffer) -> Result<Buffer> {
+    Ok(zwaves_wasm::merkle_root_native(&sibling, read_u64(index, "index")?, &leaf).map_err(napi_err)?.into())
+}
+
+#[napi]
+pub fn empty_root(level: u32) -> Result<Buffer> {
+    Ok(zwaves_wasm::empty_root_native(level as usize).map_err(napi_err)?.into())
+}
+
+#[napi]
+pub fn note_hash(asset_id: Buffer, amount: Buffer, native_amount: Buffer, txid: Buffer, owner: Buffer) -> Result<Buffer> {
+    Ok(zwaves_wasm::note_hash_native(&asset_id, &amount, &native_amount, &txid, &owner).map_err(napi_err)?.into())
+}
+
+#[napi]
+pub fn verify(vk: Buffer, proof: Buffer, inputs: Buffer) -> Result<bool> {
+    zwaves_wasm::verify_native(&vk, &proof, &inputs).map_err(napi_err)
+}
+
+#[napi]
+pub fn zwaves_call(method: String, params_json: String) -> String {
+    zwaves_wasm::rpc::call(&method, &params_json)
+}
+
+
+// As JsWitnessSource of zwaves_wasm: the callbacks take the index as a BigInt and return a
+// Buffer or Uint8Array
+struct NodeWitnessSource<'a> {
+    env: &'a Env,
+    get_note: &'a JsFunction,
+    get_merkle_path: &'a JsFunction
+}
+
+impl<'a> NodeWitnessSource<'a> {
+    fn request(&self, f: &JsFunction, name: &str, index: u64) -> io::Result<Vec<u8>> {
+        let failed = |e: Error| io::Error::new(io::ErrorKind::Other, format!("{} failed: {}", name, e.reason));
+        let index = self.env.create_bigint_from_u64(index).and_then(|x| x.into_unknown()).map_err(failed)?;
+        let res: JsUnknown = f.call(None, &[index]).map_err(failed)?;
+        let res = unsafe { Uint8Array::from_napi_value(self.env.raw(), res.raw()) }
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{} should return a Uint8Array", name)))?;
+        Ok(res.to_vec())
+    }
+}
+
+impl<'a> WitnessSource<Bls12> for NodeWitnessSource<'a> {
+    fn note(&mut self, index: u64) -> io::Result<NoteData<Bls12>> {
+        read_note(&self.request(self.get_note, "get_note", index)?)
+    }
+
+    fn merkle_path(&mut self, index: u64) -> io::Result<Vec<Fr>> {
+        read_fr_list(&self.request(self.get_merkle_path, "get_merkle_path", index)?)
+    }
+}
+
+#[napi]
+pub fn spend_witness(env: Env, index: BigInt, depth: u32, root: Buffer, get_note: JsFunction, get_merkle_path: JsFunction) -> Result<Buffer> {
+    let root = read_fr(&root).map_err(napi_err)?;
+    let mut source = NodeWitnessSource { env: &env, get_note: &get_note, get_merkle_path: &get_merkle_path };
+    let w = build_spend_witness(&mut source, read_u64(index, "index")?, depth as usize, &root, &JUBJUB_PARAMS).map_err(napi_err)?;
+    Ok(write_spend_witness(&w).into())
+}
+
+
+fn prove_transfer_native(params: &[u8], transfer: &[u8]) -> io::Result<Vec<u8>> {
+    let params = envelope::unwrap_parameters::<Bls12>(params)?;
+    memory::check_proving_memory_limit(&params)?;
+    let c = read_transfer(transfer)?;
+    validate_witness(&c)?;
+    let mut rng = SystemEntropy.rng()?;
+    let proof = metrics::time(Timing::Prove, || create_random_proof(c, &params, &mut rng)).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    metrics::inc(Counter::ProofsGenerated, 1);
+    let mut res = vec![];
+    proof.write(&mut res)?;
+    Ok(res)
+}
+
+pub struct ProveTransfer {
+    params: Vec<u8>,
+    transfer: Vec<u8>
+}
+
+impl Task for ProveTransfer {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Vec<u8>> {
+        prove_transfer_native(&self.params, &self.transfer).map_err(napi_err)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Vec<u8>) -> Result<Buffer> {
+        Ok(output.into())
+    }
+}
+
+// params is the enveloped transfer parameters file written by split, the proof is 192 bytes
+#[napi(ts_return_type = "Promise<Buffer>")]
+pub fn prove_transfer(params: Buffer, transfer: Buffer) -> AsyncTask<ProveTransfer> {
+    AsyncTask::new(ProveTransfer { params: params.to_vec(), transfer: transfer.to_vec() })
+}
+
+// The heap of the process, counted by the allocator of zwaves_wasm; see set_memory_limit there
+#[napi]
+pub fn set_memory_limit(limit: i64) -> Result<()> {
+    if limit < 0 {
+        return Err(Error::new(Status::InvalidArg, "limit should not be negative".to_string()));
+    }
+    zwaves_wasm::set_memory_limit(limit as usize);
+    Ok(())
+}
+
+#[napi]
+pub fn memory_allocated() -> i64 {
+    zwaves_wasm::memory_allocated() as i64
+}
+
+#[napi]
+pub fn memory_peak() -> i64 {
+    zwaves_wasm::memory_peak() as i64
+}
+
+#[napi]
+pub fn reset_memory_peak() {
+    zwaves_wasm::reset_memory_peak();
+}
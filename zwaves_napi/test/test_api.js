@@ -0,0 +1,35 @@
+const zwaves = require("../index.node");
+const assert = require("assert");
+
+
+const zero = Buffer.alloc(32);
+const leaf = zwaves.hash(zero);
+const empty = [0, 1, 2, 3, 4].map(zwaves.emptyRoot);
+
+let root = zwaves.merkleRoot(Buffer.concat(empty.slice(0, 4)), 5n, empty[0]);
+assert(root.equals(empty[4]), "root of the empty tree");
+assert(zwaves.merkleHash(empty[3], empty[3], 3).equals(root));
+assert.throws(() => zwaves.merkleRoot(empty[0], 2n, leaf), "index out of the tree");
+
+let res = JSON.parse(zwaves.zwavesCall("hash@1", JSON.stringify({ x: zero.toString("hex") })));
+assert(res.result === leaf.toString("hex"), "zwavesCall is the same module");
+
+const note = Buffer.concat([zero, zero, zero, zero, leaf]);
+const hash = zwaves.noteHash(zero, zero, zero, zero, leaf);
+const sibling = Buffer.concat(empty.slice(0, 4));
+root = zwaves.merkleRoot(sibling, 3n, hash);
+const witness = zwaves.spendWitness(3n, 4, root, index => {
+    assert(index === 3n);
+    return note;
+}, () => new Uint8Array(sibling));
+assert(witness.length === note.length + sibling.length + 8);
+assert.throws(() => zwaves.spendWitness(3n, 4, empty[4], () => note, () => sibling), "witness is checked against the root");
+
+assert(zwaves.memoryPeak() >= zwaves.memoryAllocated() && zwaves.memoryAllocated() > 0, "heap is counted");
+assert.throws(() => zwaves.setMemoryLimit(-1), "negative limit");
+zwaves.setMemoryLimit(0);
+
+zwaves.proveTransfer(Buffer.alloc(0), Buffer.alloc(0)).then(() => assert.fail("proving should fail"), e => {
+    assert(e instanceof Error);
+    console.log("ok");
+});
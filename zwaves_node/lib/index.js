@@ -71,31 +71,58 @@ function randrange(from, to) {
 }
 
 function merkleHash(a, b, l) {
-    assert(l<63, "Merkle level should be lesser 63");
+    // level 62 is reserved for bagging the peaks of an MMR, see MERKLE_RESERVED_LEVEL of zwaves_primitives
+    assert(l<62, "Merkle level should be lesser 62");
     return debufferizeBigints(native.merkle_hash(bufferizeBigints(a), bufferizeBigints(b), l));
 }
 
-function utxoAccumulator(mpc_params, data) {
-    const proof = native.utxo_accumulator(mpc_params, bufferizeBigints(data));
+function utxoAccumulatorInputs(data) {
     const pair_hash = merkleHash(data.note_hashes[0], data.note_hashes[1], 0);
     const zero_hash = merkleHash(0n, 0n, 0);
     
     const roots = [zero_hash, pair_hash].map((leaf, i) => MerkleTree.getRoot(data.proof_sibling[i], data.proof_index, leaf, 1));
-    const publicInputs = [...data.note_hashes, data.proof_index, ...roots];
-    return {proof, publicInputs};
+    return [...data.note_hashes, data.proof_index, ...roots];
+}
+
+function utxoAccumulator(mpc_params, data) {
+    const proof = native.utxo_accumulator(mpc_params, bufferizeBigints(data));
+    return {proof, publicInputs: utxoAccumulatorInputs(data)};
 }
 
 const nullifier = (hash, sk) => debufferizeBigints(native.nullifier(bufferizeBigints(hash), bufferizeBigints(sk)));
+const nullifier_key = (sk) => debufferizeBigints(native.nullifier_key(bufferizeBigints(sk)));
+const nullifier_from_key = (hash, nk) => debufferizeBigints(native.nullifier_from_key(bufferizeBigints(hash), bufferizeBigints(nk)));
 
 
-function transfer(mpc_params, data) {
-    const proof = native.transfer(mpc_params, bufferizeBigints(data));
+function transferInputs(data) {
     const nf = data.in_note.map(n => nullifier(note_hash(n), data.sk));
     const out_hash = data.out_note.map(n=>note_hash(n));
-    const publicInputs = [data.receiver, data.root_hash, data.packed_asset, ...out_hash, ...nf];
-    return {proof, publicInputs};
+    const rk = spendAuthRk(data.sk, data.alpha);
+    return [data.receiver, data.root_hash, data.packed_asset, ...out_hash, ...nf, ...rk];
 }
 
+function transfer(mpc_params, data) {
+    const proof = native.transfer(mpc_params, bufferizeBigints(data));
+    return {proof, publicInputs: transferInputs(data)};
+}
+
+
+// Same as utxoAccumulator and transfer, but the proof is computed on the libuv thread pool
+// and the event loop is not blocked. Concurrent calls are proven in parallel.
+function proveAsync(native_fn, mpc_params, data) {
+    return new Promise((resolve, reject) => native_fn(mpc_params, bufferizeBigints(data), (err, proof) => err ? reject(err) : resolve(proof)));
+}
+
+const utxoAccumulatorAsync = (mpc_params, data) => proveAsync(native.utxo_accumulator_async, mpc_params, data)
+    .then(proof => ({proof, publicInputs: utxoAccumulatorInputs(data)}));
+const transferAsync = (mpc_params, data) => proveAsync(native.transfer_async, mpc_params, data)
+    .then(proof => ({proof, publicInputs: transferInputs(data)}));
+
+
+
+const bundlePack = (bundle) => native.bundle_pack(bufferizeBigints(bundle));
+const bundleUnpack = (buf) => debufferizeBigints(native.bundle_unpack(buf));
+const bundleVerify = (vk, buf) => native.bundle_verify(vk, buf);
 
 
 function verify(mpc_params, {proof, publicInputs}) {
@@ -111,8 +138,11 @@ const u32_random = ()=>randrange(0n, 2n**32n);
 const extract_vk = (mpc_params) => native.extract_vk(mpc_params);
 const note_hash = (note) => debufferizeBigints(native.note_hash(bufferizeBigints(note)));
 const pubkey = (sk) => debufferizeBigints(native.pubkey(bufferizeBigints(sk)));
+const spendAuthRk = (sk, alpha) => debufferizeBigints(native.spend_auth_rk(bufferizeBigints(sk), bufferizeBigints(alpha)));
+const spendAuthSign = (sk, alpha, msg) => native.spend_auth_sign(bufferizeBigints(sk), bufferizeBigints(alpha), msg);
+const edhBatch = (pks, sk) => debufferizeBigints(native.edh_batch(bufferizeBigints(pks), bufferizeBigints(sk)));
 
-const maxheight = 64;
+const maxheight = 63;
 const merkleDefaults = Array(maxheight);
 merkleDefaults[0] = 0n;
 for (let i = 1; i < maxheight; i++) {
@@ -121,16 +151,37 @@ for (let i = 1; i < maxheight; i++) {
 
 
 
+// Returned for every leaf of a push: the proof is taken from the same state as the root, so it
+// is consistent with it whatever is pushed afterwards
+class InsertReceipt {
+    constructor(position, root, proof) {
+        this.position = position;
+        this.root = root;
+        this.proof = proof;
+    }
+}
+
+// A 4-ary tree (arity 4) of proof_length levels is the binary tree of 2*proof_length rows, see
+// compress4 of zwaves_primitives, only its proofs differ: the three other children of every node,
+// flat, as MerkleTree(arity=4) of the python bindings gives them.
 class MerkleTree{
-    constructor(proof_length) {
-        assert(proof_length < maxheight, `height should be less or equal ${maxheight}`);
+    constructor(proof_length, arity) {
+        arity = typeof arity === "undefined" ? 2 : arity;
+        assert(arity == 2 || arity == 4, "arity should be 2 or 4");
+        this.arity = arity;
         this.proof_length = proof_length;
-        this._merkleState = Array(proof_length+1).fill(0).map(() => []);
+        this.depth = arity == 4 ? 2*proof_length : proof_length;
+        assert(this.depth < maxheight, `height should be less than ${maxheight}`);
+        this._merkleState = Array(this.depth+1).fill(0).map(() => []);
+        // first leaf index and height of every pushMany, and the last height each root was current at
+        this._leafHeights = [];
+        this._lastHeight = undefined;
+        this._rootHeights = new Map();
     }
 
     cell(row, index) {
         index = BigInt(index);
-        assert(row <= this.proof_length, "too big row");
+        assert(row <= this.depth, "too big row");
         if (index < this.size(row)) {
             return this._merkleState[row][index];
         } else {
@@ -143,12 +194,13 @@ class MerkleTree{
         return BigInt(this._merkleState[n].length);
     }
 
-    pushMany(elements) {
+    // Returns an InsertReceipt per element
+    pushMany(elements, height) {
         let index = this.size();
         let s = BigInt(elements.length);
         this._merkleState[0].push(...elements);
 
-        for (let i = 1; i<= this.proof_length; i++) {
+        for (let i = 1; i<= this.depth; i++) {
             let rl = this.size(i);
             this._merkleState[i].push(...Array(parseInt(1n + (index+s>>BigInt(i)) - rl)).fill(0n));
             
@@ -156,18 +208,87 @@ class MerkleTree{
                 this._merkleState[i][j] = merkleHash(this.cell(i-1, j*2n), this.cell(i-1, j*2n+1n), i-1);
             }
         }
+
+        if (s > 0n)
+            this._leafHeights.push({index, height});
+
+        if (typeof height !== "undefined") {
+            assert(this._lastHeight === undefined || this._lastHeight <= height, "heights should not decrease");
+            this._lastHeight = height;
+            // an empty block keeps the root current, so its height moves on with the blocks
+            this._rootHeights.set(this.root(), height);
+        }
+
+        const root = this.root();
+        return elements.map((e, i) => new InsertReceipt(index + BigInt(i), root, this.proof(index + BigInt(i))));
+    }
+
+    // Height of the block the leaf was added in, undefined if it was pushed without a height
+    leafHeight(index) {
+        index = BigInt(index);
+        if (index >= this.size())
+            return undefined;
+        let lo = 0, hi = this._leafHeights.length;
+        while (lo < hi) {
+            const mid = (lo + hi) >> 1;
+            if (this._leafHeights[mid].index <= index)
+                lo = mid + 1;
+            else
+                hi = mid;
+        }
+        return lo == 0 ? undefined : this._leafHeights[lo-1].height;
+    }
+
+    // Last height at which the root was current, undefined for unknown roots
+    rootHeight(root) {
+        return this._rootHeights.get(BigInt(root));
+    }
+
+    // Policy check for verifiers: the root is known and was replaced no more than maxAge blocks ago.
+    // Push empty blocks to keep the current root recent
+    isRootRecent(root, currentHeight, maxAge) {
+        const height = this.rootHeight(root);
+        return typeof height !== "undefined" && height <= currentHeight && currentHeight - height <= maxAge;
     }
 
     root() {
-        return this.cell(this.proof_length, 0n)
+        return this.cell(this.depth, 0n)
     }
 
     proof(index, offset) {
         index = BigInt(index);
         offset = typeof offset === "undefined" ? 0 : offset;
+        if (this.arity == 4) {
+            // the other children of the node above row 2i, i.e. the siblings in row 2i
+            return Array(this.proof_length).fill(0).flatMap((o, i) => {
+                const node = index >> BigInt(2*i);
+                return [0n, 1n, 2n, 3n].filter(k => k != (node & 3n)).map(k => this.cell(2*i, (node & ~3n) | k));
+            }).slice(3*offset);
+        }
         return Array(this.proof_length).fill(0).map((o, i) => this.cell(i, (index >> BigInt(i))^1n)).slice(offset);
     }
 
+    // Nodes that differ from the other snapshot, top-down from the root. Equal subtrees are skipped,
+    // so the cost is proportional to the number of changed leaves, not to the tree size.
+    diff(other) {
+        assert(this.depth == other.depth, "trees should have the same height");
+        const res = [];
+        let level = [0n];
+        for (let row = this.depth; row >= 0 && level.length > 0; row--) {
+            const next = [];
+            for (let index of level) {
+                const old = other.cell(row, index);
+                const cur = this.cell(row, index);
+                if (old == cur)
+                    continue;
+                res.push({row, index, old, new: cur});
+                next.push(index*2n, index*2n+1n);
+            }
+            level = next;
+        }
+        return res;
+    }
+
     static getRoot(proof, index, leaf, offset) {
         index = BigInt(index);
         offset = typeof offset === "undefined" ? 0 : offset;
@@ -177,11 +298,24 @@ class MerkleTree{
         }
         return root;
     }
+
+    // Root of a 4-ary proof, three siblings per level
+    static getRoot4(proof, index, leaf) {
+        index = BigInt(index);
+        let root = leaf;
+        for (let i = 0; 3*i < proof.length; i++) {
+            const children = proof.slice(3*i, 3*i+3);
+            children.splice(Number((index >> BigInt(2*i)) & 3n), 0, root);
+            root = merkleHash(merkleHash(children[0], children[1], 2*i), merkleHash(children[2], children[3], 2*i), 2*i+1);
+        }
+        return root;
+    }
 }
 
 
 
 
-module.exports = {MerkleTree, merkleDefaults, merkleHash, utxoAccumulator, verify, fr_random, fs_random, u64_random, fr_order, fs_order, extract_vk, u32_random, note_hash, pubkey,
-    randrange, nullifier, transfer, bufferizeBigints, debufferizeBigints}; 
+module.exports = {MerkleTree, InsertReceipt, merkleDefaults, merkleHash, utxoAccumulator, verify, fr_random, fs_random, u64_random, fr_order, fs_order, extract_vk, u32_random, note_hash, pubkey,
+    randrange, nullifier, nullifier_key, nullifier_from_key, transfer, utxoAccumulatorAsync, transferAsync, bufferizeBigints, debufferizeBigints,
+    bundlePack, bundleUnpack, bundleVerify, edhBatch, spendAuthRk, spendAuthSign}; 
 
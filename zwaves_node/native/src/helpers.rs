@@ -19,11 +19,12 @@ use sapling_crypto::circuit::num::{AllocatedNum, Num};
 use bellman::groth16::{Proof, generate_random_parameters, prepare_verifying_key, create_random_proof, verify_proof};
 use zwaves_circuit::circuit::{Transfer, MERKLE_PROOF_LEN};
 use zwaves_primitives::transactions::NoteData;
-use zwaves_primitives::fieldtools::fr_to_repr_bool;
+use zwaves_primitives::fieldtools::{fr_to_repr_bool, fr_to_repr_u64};
 use zwaves_primitives::serialization::read_fr_repr_be;
 use arrayvec::ArrayVec;
 
 use zwaves_primitives::verifier;
+use phase2;
 
 
 
@@ -76,6 +77,21 @@ pub fn fr_to_js<'a>(cx: &mut FunctionContext<'a>, fr: &Fr) -> JsResult<'a, JsBuf
     Ok(hash_js_buf)
 }
 
+pub fn bytes_to_js<'a>(cx: &mut FunctionContext<'a>, data: &[u8]) -> JsResult<'a, JsBuffer> {
+    let mut js_buf = JsBuffer::new(cx, data.len() as u32)?;
+    buf_copy_from_slice(cx, data, &mut js_buf);
+    Ok(js_buf)
+}
+
+pub fn read_obj_u64(cx: &mut FunctionContext, obj: Handle<JsObject>, key: &str) -> NeonResult<u64> {
+    let value = read_obj_fr(cx, obj, key)?;
+    let limbs = fr_to_repr_u64(&value).into_iter().collect::<Vec<u64>>();
+    if limbs[1..].iter().any(|&x| x != 0) {
+        return cx.throw_error(format!("{} should fit into uint64", key));
+    }
+    Ok(limbs[0])
+}
+
 pub fn proof_to_js<'a>(cx: &mut FunctionContext<'a>, proof: &Proof<Bls12>) -> JsResult<'a, JsBuffer> {
     let mut proof_cur = Cursor::new(Vec::<u8>::new());
     proof.write(&mut proof_cur).unwrap();
@@ -84,6 +100,36 @@ pub fn proof_to_js<'a>(cx: &mut FunctionContext<'a>, proof: &Proof<Bls12>) -> Js
     Ok(proof_js_buf)
 }
 
+// Proving on the libuv thread pool, so the event loop keeps running and several proofs
+// can be computed at once. Parameters are parsed in the background too, the circuit is parsed
+// on the main thread before scheduling.
+pub struct ProveTask<C> {
+    pub mpc_params: Vec<u8>,
+    pub circuit: C
+}
+
+impl<C: Circuit<Bls12> + Clone + Send + 'static> Task for ProveTask<C> {
+    type Output = Vec<u8>;
+    type Error = String;
+    type JsEvent = JsBuffer;
+
+    fn perform(&self) -> Result<Vec<u8>, String> {
+        let mut rng = OsRng::new().map_err(|e| e.to_string())?;
+        let params = phase2::MPCParameters::read(&self.mpc_params[..], false).map_err(|_| "Could not read mpc params".to_string())?;
+        let proof = create_random_proof(self.circuit.clone(), params.get_params(), &mut rng).map_err(|_| "Could not create proof".to_string())?;
+        let mut res = vec![];
+        proof.write(&mut res).unwrap();
+        Ok(res)
+    }
+
+    fn complete(self, mut cx: TaskContext, result: Result<Vec<u8>, String>) -> JsResult<JsBuffer> {
+        let proof = result.or_else(|e| cx.throw_error(e))?;
+        let mut proof_js_buf = JsBuffer::new(&mut cx, proof.len() as u32)?;
+        cx.borrow_mut(&mut proof_js_buf, |data| data.as_mut_slice().copy_from_slice(&proof));
+        Ok(proof_js_buf)
+    }
+}
+
 pub fn verifier_to_js<'a>(cx: &mut FunctionContext<'a>, verifier: &verifier::TruncatedVerifyingKey<Bls12>) -> JsResult<'a, JsBuffer> {
     let mut verifier_cur = Cursor::new(Vec::<u8>::new());
     verifier.write(&mut verifier_cur).unwrap();
@@ -13,7 +13,7 @@ pub mod helpers;
 
 use neon::prelude::*;
 
-use pairing::bls12_381::{Fr, Bls12};
+use pairing::bls12_381::{Fr, FrRepr, Bls12};
 use pairing::{Field, PrimeField, PrimeFieldRepr};
 
 use rand::os::OsRng;
@@ -26,15 +26,18 @@ use std::io::Cursor;
 
 use bellman::{Circuit, ConstraintSystem, SynthesisError};
 use sapling_crypto::jubjub::{JubjubEngine, JubjubParams, JubjubBls12};
-use sapling_crypto::pedersen_hash::{Personalization};
 use sapling_crypto::circuit::{pedersen_hash};
 use sapling_crypto::circuit::num::{AllocatedNum, Num};
 use bellman::groth16::{Proof, generate_random_parameters, prepare_verifying_key, create_random_proof, verify_proof};
-use zwaves_circuit::circuit::{Transfer, MERKLE_PROOF_LEN, UtxoAccumulator};
+use zwaves_circuit::circuit::{Transfer, NotePath, MERKLE_PROOF_LEN, UtxoAccumulator};
 use zwaves_primitives::transactions::NoteData;
 use zwaves_primitives::fieldtools::fr_to_repr_bool;
 use zwaves_primitives::serialization::read_fr_repr_be;
 use zwaves_primitives::verifier;
+use zwaves_primitives::bundle::TransactionBundle;
+use zwaves_primitives::out_amount::OUT_CIPHERTEXT_LEN;
+use zwaves_primitives::spend_auth;
+use sapling_crypto::redjubjub::Signature;
 use arrayvec::ArrayVec;
 
 use crate::helpers::*;
@@ -77,7 +80,7 @@ pub fn note_hash(mut cx: FunctionContext) ->JsResult<JsBuffer> {
     let note_obj : Handle<JsObject> = cx.argument(0)?;
     let note = parse_note_data(&mut cx, note_obj)?;
     
-    let hash = zwaves_primitives::transactions::note_hash(&note, &JUBJUB_PARAMS);
+    let hash = zwaves_primitives::transactions::note_hash(&note, &JUBJUB_PARAMS).or_else(|e| cx.throw_error(e.to_string()))?;
     fr_to_js(&mut cx, &hash)
 }
 
@@ -90,6 +93,22 @@ pub fn nullifier(mut cx: FunctionContext) ->JsResult<JsBuffer> {
     fr_to_js(&mut cx, &nf)
 }
 
+pub fn nullifier_key(mut cx: FunctionContext) ->JsResult<JsBuffer> {
+    let sk : Handle<JsBuffer> = cx.argument(0)?;
+    let sk = read_buf_fr(&mut cx, sk)?;
+    let nk = zwaves_primitives::transactions::nullifier_key::<Bls12>(&sk, &JUBJUB_PARAMS);
+    fr_to_js(&mut cx, &nk)
+}
+
+pub fn nullifier_from_key(mut cx: FunctionContext) ->JsResult<JsBuffer> {
+    let note_hash : Handle<JsBuffer> = cx.argument(0)?;
+    let note_hash = read_buf_fr(&mut cx, note_hash)?;
+    let nk : Handle<JsBuffer> = cx.argument(1)?;
+    let nk = read_buf_fr(&mut cx, nk)?;
+    let nf = zwaves_primitives::transactions::nullifier_from_key::<Bls12>(&note_hash, &nk);
+    fr_to_js(&mut cx, &nf)
+}
+
 pub fn pubkey(mut cx: FunctionContext) ->JsResult<JsBuffer> {
     let sk : Handle<JsBuffer> = cx.argument(0)?;
     let sk = read_buf_fr(&mut cx, sk)?;
@@ -97,15 +116,75 @@ pub fn pubkey(mut cx: FunctionContext) ->JsResult<JsBuffer> {
     fr_to_js(&mut cx, &nf)
 }
 
+// [rk.x, rk.y], the last public inputs of transfer
+pub fn spend_auth_rk(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let sk : Handle<JsBuffer> = cx.argument(0)?;
+    let sk = read_buf_fr(&mut cx, sk)?;
+    let alpha : Handle<JsBuffer> = cx.argument(1)?;
+    let alpha = read_buf_fr(&mut cx, alpha)?;
+
+    let ak = spend_auth::spend_auth_key::<Bls12>(&sk, &JUBJUB_PARAMS);
+    let (x, y) = spend_auth::rk_to_inputs(&spend_auth::randomized_public_key(&ak, &alpha, &JUBJUB_PARAMS));
+
+    let js_res = JsArray::new(&mut cx, 2);
+    let x = fr_to_js(&mut cx, &x)?;
+    js_res.set(&mut cx, 0, x)?;
+    let y = fr_to_js(&mut cx, &y)?;
+    js_res.set(&mut cx, 1, y)?;
+    Ok(js_res)
+}
+
+pub fn spend_auth_sign(mut cx: FunctionContext) -> JsResult<JsBuffer> {
+    let mut rng = OsRng::new().unwrap();
+    let sk : Handle<JsBuffer> = cx.argument(0)?;
+    let sk = read_buf_fr(&mut cx, sk)?;
+    let alpha : Handle<JsBuffer> = cx.argument(1)?;
+    let alpha = read_buf_fr(&mut cx, alpha)?;
+    let msg : Handle<JsBuffer> = cx.argument(2)?;
+    let msg = cx.borrow(&msg, |data| data.as_slice().to_vec());
+
+    let sig = spend_auth::sign::<Bls12, _>(&sk, &alpha, &msg, &mut rng, &JUBJUB_PARAMS);
+    let mut res = vec![];
+    sig.write(&mut res).unwrap();
+    bytes_to_js(&mut cx, &res)
+}
+
 pub fn edh(mut cx: FunctionContext) ->JsResult<JsBuffer> {
     let pk : Handle<JsBuffer> = cx.argument(0)?;
     let pk = read_buf_fr(&mut cx, pk)?;
     let sk : Handle<JsBuffer> = cx.argument(1)?;
     let sk = read_buf_fr(&mut cx, sk)?;
-    let nf = zwaves_primitives::transactions::edh::<Bls12>(&pk, &sk, &JUBJUB_PARAMS).ok_or(()).or_else(|_| cx.throw_error("Not an elliptic curve point"))?;
+    let nf = zwaves_primitives::transactions::edh::<Bls12>(&pk, &sk, &JUBJUB_PARAMS).or_else(|e| cx.throw_error(e.to_string()))?;
     fr_to_js(&mut cx, &nf)
 }
 
+// Keys which are not on the curve give undefined at their position.
+pub fn edh_batch(mut cx: FunctionContext) ->JsResult<JsArray> {
+    let pks : Handle<JsArray> = cx.argument(0)?;
+    let pks = pks.to_vec(&mut cx)?;
+    let pks = pks.iter().map(|&x| read_val_fr(&mut cx, x)).collect::<NeonResult<Vec<Fr>>>()?;
+    let sk : Handle<JsBuffer> = cx.argument(1)?;
+    let sk = read_buf_fr(&mut cx, sk)?;
+
+    let key = zwaves_primitives::scan::ViewingKeyPrecomp::new::<Bls12>(&sk);
+    let res = zwaves_primitives::scan::edh_batch::<Bls12>(&pks, &key, &JUBJUB_PARAMS);
+
+    let js_res = JsArray::new(&mut cx, res.len() as u32);
+    for (i, r) in res.iter().enumerate() {
+        match r {
+            Ok(x) => {
+                let x = fr_to_js(&mut cx, x)?;
+                js_res.set(&mut cx, i as u32, x)?;
+            },
+            Err(_) => {
+                let x = cx.undefined();
+                js_res.set(&mut cx, i as u32, x)?;
+            }
+        }
+    }
+    Ok(js_res)
+}
+
 
 
 
@@ -143,16 +222,22 @@ pub fn parse_transfer(cx: &mut FunctionContext, transfer_obj:Handle<JsObject>) -
             return cx.throw_error(format!("Merkle proof length should be {}.", MERKLE_PROOF_LEN));
         }
 
-        if fr_to_repr_bool::<Fr>(&index).into_iter().skip(MERKLE_PROOF_LEN).any(|e| e) {
+        let bits = fr_to_repr_bool::<Fr>(&index).into_iter().collect::<Vec<_>>();
+        if bits.iter().skip(MERKLE_PROOF_LEN).any(|&e| e) {
             return cx.throw_error("Index value should not be bigger than 2^MERKLE_PROOF_LEN");
         }
+        let index = bits.iter().take(MERKLE_PROOF_LEN).rev().fold(0u64, |acc, &b| acc << 1 | b as u64);
 
-        let item = item.into_iter().zip(fr_to_repr_bool::<Fr>(&index)).map(|(e, b)| (read_val_fr(cx, e).unwrap(), b)).collect::<Vec<(Fr, bool)>>();
-        Ok(Some(item))
-    }).collect::<NeonResult<ArrayVec<[Option<Vec<(Fr, bool)>>;2]>>>()?.into_inner().or_else(|_| cx.throw_error("in_proof_sibling.length should be 2"))?;
+        let mut sibling = vec![];
+        for e in item {
+            sibling.push(read_val_fr(cx, e)?);
+        }
+        NotePath::from_slice(&sibling, index).map(Some).or_else(|e| cx.throw_error(e.to_string()))
+    }).collect::<NeonResult<ArrayVec<[Option<NotePath<Bls12>>;2]>>>()?.into_inner().or_else(|_| cx.throw_error("in_proof_sibling.length should be 2"))?;
 
     let root_hash = Some(read_obj_fr(cx, transfer_obj, "root_hash")?);
     let sk = Some(read_obj_fr(cx, transfer_obj, "sk")?);
+    let alpha = Some(read_obj_fr(cx, transfer_obj, "alpha")?);
     let packed_asset = Some(read_obj_fr(cx, transfer_obj, "packed_asset")?);
     let receiver = Some(read_obj_fr(cx, transfer_obj, "receiver")?);
 
@@ -164,6 +249,7 @@ pub fn parse_transfer(cx: &mut FunctionContext, transfer_obj:Handle<JsObject>) -
         in_proof,
         root_hash,
         sk,
+        alpha,
         packed_asset,
         params: &JUBJUB_PARAMS
     })
@@ -185,6 +271,19 @@ pub fn transfer(mut cx: FunctionContext) -> JsResult<JsBuffer> {
 }
 
 
+pub fn transfer_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let mpc_params_buff : Handle<JsBuffer> = cx.argument(0)?;
+    let mpc_params = cx.borrow(&mpc_params_buff, |data| data.as_slice().to_vec());
+
+    let transfer_obj : Handle<JsObject> = cx.argument(1)?;
+    let circuit = parse_transfer(&mut cx, transfer_obj)?;
+    let callback = cx.argument::<JsFunction>(2)?;
+
+    ProveTask { mpc_params, circuit }.schedule(callback);
+    Ok(cx.undefined())
+}
+
+
 pub fn parse_utxo_accumulator(cx: &mut FunctionContext, transfer_obj:Handle<JsObject>) -> NeonResult<UtxoAccumulator<'static, Bls12>> {
 
     let note_hashes = transfer_obj.get(cx, "note_hashes")?;
@@ -224,10 +323,11 @@ pub fn merkle_hash(mut cx: FunctionContext) -> JsResult<JsBuffer> {
     let right_handle = cx.argument::<JsBuffer>(1)?;
     let right = read_buf_fr(&mut cx, right_handle)?;
     let n = cx.argument::<JsNumber>(2)?.value();
-    if n.fract() != 0.0 {
-        return cx.throw_error("3rd parameter should be integer");
+    if n.fract() != 0.0 || n < 0.0 {
+        return cx.throw_error("3rd parameter should be a non-negative integer");
     }
-    let hash = zwaves_primitives::pedersen_hasher::compress::<Bls12>(&left, &right, Personalization::MerkleTree(n.round() as usize), &JUBJUB_PARAMS);
+    let personalization = zwaves_primitives::pedersen_hasher::merkle_personalization(n as usize).or_else(|e| cx.throw_error(e.to_string()))?;
+    let hash = zwaves_primitives::pedersen_hasher::compress::<Bls12>(&left, &right, personalization, &JUBJUB_PARAMS);
     fr_to_js(&mut cx, &hash)
 }
 
@@ -247,15 +347,161 @@ pub fn utxo_accumulator(mut cx: FunctionContext) -> JsResult<JsBuffer> {
 }
 
 
+pub fn utxo_accumulator_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let mpc_params_buff : Handle<JsBuffer> = cx.argument(0)?;
+    let mpc_params = cx.borrow(&mpc_params_buff, |data| data.as_slice().to_vec());
+
+    let acc_obj : Handle<JsObject> = cx.argument(1)?;
+    let circuit = parse_utxo_accumulator(&mut cx, acc_obj)?;
+    let callback = cx.argument::<JsFunction>(2)?;
+
+    ProveTask { mpc_params, circuit }.schedule(callback);
+    Ok(cx.undefined())
+}
+
+
+pub fn parse_bundle(cx: &mut FunctionContext, bundle_obj:Handle<JsObject>) -> NeonResult<TransactionBundle<Bls12>> {
+    let proof = bundle_obj.get(cx, "proof")?;
+    let proof = proof.downcast::<JsBuffer>().or_else(|_| cx.throw_error("proof should be Buffer"))?;
+    let proof = cx.borrow(&proof, |data| Proof::<Bls12>::read(data.as_slice())).or_else(|_| cx.throw_error("Wrong proof format"))?;
+
+    let public_inputs = bundle_obj.get(cx, "public_inputs")?;
+    let public_inputs = public_inputs.downcast::<JsArray>().or_else(|_| cx.throw_error("public_inputs should be Array"))?.to_vec(cx)?;
+    let public_inputs = public_inputs.iter().map(|&x| read_val_fr(cx, x)).collect::<NeonResult<Vec<Fr>>>()?;
+
+    let encrypted_notes = bundle_obj.get(cx, "encrypted_notes")?;
+    let encrypted_notes = encrypted_notes.downcast::<JsArray>().or_else(|_| cx.throw_error("encrypted_notes should be Array"))?.to_vec(cx)?;
+    let encrypted_notes = encrypted_notes.iter().map(|&x| {
+        let note = x.downcast::<JsBuffer>().or_else(|_| cx.throw_error("encrypted note should be Buffer"))?;
+        Ok(cx.borrow(&note, |data| data.as_slice().to_vec()))
+    }).collect::<NeonResult<Vec<Vec<u8>>>>()?;
+
+    let out_ciphertexts = bundle_obj.get(cx, "out_ciphertexts")?;
+    let out_ciphertexts = out_ciphertexts.downcast::<JsArray>().or_else(|_| cx.throw_error("out_ciphertexts should be Array"))?.to_vec(cx)?;
+    let out_ciphertexts = out_ciphertexts.iter().map(|&x| {
+        let ct = x.downcast::<JsBuffer>().or_else(|_| cx.throw_error("out ciphertext should be Buffer"))?;
+        let ct = cx.borrow(&ct, |data| data.as_slice().to_vec());
+        if ct.len() != OUT_CIPHERTEXT_LEN {
+            return cx.throw_error(format!("out ciphertext should be {} bytes", OUT_CIPHERTEXT_LEN));
+        }
+        let mut res = [0u8; OUT_CIPHERTEXT_LEN];
+        res.copy_from_slice(&ct);
+        Ok(res)
+    }).collect::<NeonResult<Vec<_>>>()?;
+
+    let binding_sig = bundle_obj.get(cx, "binding_sig")?;
+    let binding_sig = binding_sig.downcast::<JsBuffer>().or_else(|_| cx.throw_error("binding_sig should be Buffer"))?;
+    let binding_sig = cx.borrow(&binding_sig, |data| Signature::read(data.as_slice())).or_else(|_| cx.throw_error("Wrong binding_sig format"))?;
+
+    let spend_auth_sig = bundle_obj.get(cx, "spend_auth_sig")?;
+    let spend_auth_sig = spend_auth_sig.downcast::<JsBuffer>().or_else(|_| cx.throw_error("spend_auth_sig should be Buffer"))?;
+    let spend_auth_sig = cx.borrow(&spend_auth_sig, |data| Signature::read(data.as_slice())).or_else(|_| cx.throw_error("Wrong spend_auth_sig format"))?;
+
+    let fee = read_obj_u64(cx, bundle_obj, "fee")?;
+
+    Ok(TransactionBundle {
+        proof,
+        public_inputs,
+        encrypted_notes,
+        out_ciphertexts,
+        binding_sig,
+        spend_auth_sig,
+        fee
+    })
+}
+
+
+pub fn bundle_pack(mut cx: FunctionContext) -> JsResult<JsBuffer> {
+    let bundle_obj : Handle<JsObject> = cx.argument(0)?;
+    let bundle = parse_bundle(&mut cx, bundle_obj)?;
+    bundle.validate().or_else(|e| cx.throw_error(format!("Wrong bundle: {}", e)))?;
+    bytes_to_js(&mut cx, &bundle.to_bytes())
+}
+
+
+pub fn bundle_unpack(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let bundle_buff : Handle<JsBuffer> = cx.argument(0)?;
+    let bundle = cx.borrow(&bundle_buff, |data| TransactionBundle::<Bls12>::from_bytes(data.as_slice()))
+        .or_else(|e| cx.throw_error(format!("Wrong bundle format: {}", e)))?;
+    bundle.validate().or_else(|e| cx.throw_error(format!("Wrong bundle: {}", e)))?;
+
+    let res = JsObject::new(&mut cx);
+
+    let proof = proof_to_js(&mut cx, &bundle.proof)?;
+    res.set(&mut cx, "proof", proof)?;
+
+    let public_inputs = JsArray::new(&mut cx, bundle.public_inputs.len() as u32);
+    for (i, x) in bundle.public_inputs.iter().enumerate() {
+        let x = fr_to_js(&mut cx, x)?;
+        public_inputs.set(&mut cx, i as u32, x)?;
+    }
+    res.set(&mut cx, "public_inputs", public_inputs)?;
+
+    let encrypted_notes = JsArray::new(&mut cx, bundle.encrypted_notes.len() as u32);
+    for (i, x) in bundle.encrypted_notes.iter().enumerate() {
+        let x = bytes_to_js(&mut cx, x)?;
+        encrypted_notes.set(&mut cx, i as u32, x)?;
+    }
+    res.set(&mut cx, "encrypted_notes", encrypted_notes)?;
+
+    let out_ciphertexts = JsArray::new(&mut cx, bundle.out_ciphertexts.len() as u32);
+    for (i, x) in bundle.out_ciphertexts.iter().enumerate() {
+        let x = bytes_to_js(&mut cx, x)?;
+        out_ciphertexts.set(&mut cx, i as u32, x)?;
+    }
+    res.set(&mut cx, "out_ciphertexts", out_ciphertexts)?;
+
+    let mut binding_sig = vec![];
+    bundle.binding_sig.write(&mut binding_sig).unwrap();
+    let binding_sig = bytes_to_js(&mut cx, &binding_sig)?;
+    res.set(&mut cx, "binding_sig", binding_sig)?;
+
+    let mut spend_auth_sig = vec![];
+    bundle.spend_auth_sig.write(&mut spend_auth_sig).unwrap();
+    let spend_auth_sig = bytes_to_js(&mut cx, &spend_auth_sig)?;
+    res.set(&mut cx, "spend_auth_sig", spend_auth_sig)?;
+
+    let fee = fr_to_js(&mut cx, &Fr::from_repr(FrRepr::from(bundle.fee)).unwrap())?;
+    res.set(&mut cx, "fee", fee)?;
+
+    Ok(res)
+}
+
+
+pub fn bundle_verify(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let verifier_buff : Handle<JsBuffer> = cx.argument(0)?;
+    let tvk = cx.borrow(&verifier_buff, |data| verifier::TruncatedVerifyingKey::<Bls12>::read(data.as_slice()))
+        .or_else(|_| cx.throw_error("Wrong verifying key format"))?;
+
+    let bundle_buff : Handle<JsBuffer> = cx.argument(1)?;
+    let bundle = cx.borrow(&bundle_buff, |data| TransactionBundle::<Bls12>::from_bytes(data.as_slice()))
+        .or_else(|e| cx.throw_error(format!("Wrong bundle format: {}", e)))?;
+    bundle.validate().or_else(|e| cx.throw_error(format!("Wrong bundle: {}", e)))?;
+
+    let res = verifier::verify_proof(&tvk, &bundle.proof, &bundle.public_inputs).or_else(|_| cx.throw_error("Error during proof verification"))?;
+    Ok(JsBoolean::new(&mut cx, res))
+}
+
+
 register_module!(mut cx, {
     cx.export_function("verify", verify)?;
     cx.export_function("transfer", transfer)?;  
+    cx.export_function("transfer_async", transfer_async)?;
     cx.export_function("extract_vk", extract_vk)?;  
     cx.export_function("utxo_accumulator", utxo_accumulator)?;        
+    cx.export_function("utxo_accumulator_async", utxo_accumulator_async)?;
     cx.export_function("merkle_hash", merkle_hash)?;
+    cx.export_function("bundle_pack", bundle_pack)?;
+    cx.export_function("bundle_unpack", bundle_unpack)?;
+    cx.export_function("bundle_verify", bundle_verify)?;
     cx.export_function("nullifier", nullifier)?;
+    cx.export_function("nullifier_key", nullifier_key)?;
+    cx.export_function("nullifier_from_key", nullifier_from_key)?;
     cx.export_function("edh", edh)?;
+    cx.export_function("edh_batch", edh_batch)?;
     cx.export_function("pubkey", pubkey)?;
+    cx.export_function("spend_auth_rk", spend_auth_rk)?;
+    cx.export_function("spend_auth_sign", spend_auth_sign)?;
     cx.export_function("note_hash", note_hash)
     
 });
@@ -0,0 +1,18 @@
+const {MerkleTree} = require("../lib/index");
+const assert = require("assert");
+
+const leaves = [...Array(20).keys()].map(i => BigInt(i + 1));
+const binary = new MerkleTree(6);
+binary.pushMany(leaves);
+const tree = new MerkleTree(3, 4);
+const receipts = tree.pushMany(leaves);
+assert(tree.root() == binary.root(), "4-ary tree should have the binary root");
+
+for (let r of receipts) {
+    assert(r.proof.length == 9, "3 siblings per level");
+    assert(MerkleTree.getRoot4(r.proof, r.position, leaves[r.position]) == r.root, `proof of leaf ${r.position} should lead to the root`);
+}
+assert(MerkleTree.getRoot4(tree.proof(5), 4n, leaves[5]) != tree.root(), "wrong position should give another root");
+assert(tree.diff(binary).length == 0);
+assert.throws(() => new MerkleTree(3, 3), "arity should be 2 or 4");
+console.log(tree.root());
@@ -0,0 +1,14 @@
+const {MerkleTree} = require("../lib/index");
+const assert = require("assert");
+
+let a = new MerkleTree(48);
+let b = new MerkleTree(48);
+a.pushMany([1n, 2n, 3n, 4n, 5n]);
+b.pushMany([1n, 2n, 3n, 4n, 5n]);
+assert(a.diff(b).length == 0, "equal trees should have no diff");
+
+b.pushMany([6n]);
+const d = b.diff(a);
+assert(d.length == 49, "one changed leaf should change one node per level");
+assert(d[d.length-1].row == 0 && d[d.length-1].index == 5n && d[d.length-1].old == 0n && d[d.length-1].new == 6n);
+console.log(d.map(({row, index}) => `${row}:${index}`).join(" "));
@@ -0,0 +1,21 @@
+const {MerkleTree} = require("../lib/index");
+const assert = require("assert");
+
+let mt = new MerkleTree(48);
+mt.pushMany([1n, 2n], 100);
+const root100 = mt.root();
+mt.pushMany([3n], 105);
+
+assert(mt.leafHeight(1) == 100 && mt.leafHeight(2) == 105 && mt.leafHeight(3) === undefined);
+assert(mt.rootHeight(root100) == 100 && mt.rootHeight(mt.root()) == 105);
+assert(mt.isRootRecent(root100, 110, 10), "root 10 blocks old should pass");
+assert(!mt.isRootRecent(root100, 111, 10), "root 11 blocks old should fail");
+assert(!mt.isRootRecent(12345n, 110, 10), "unknown root should fail");
+
+const root105 = mt.root();
+for (let h = 106; h <= 120; h++)
+    mt.pushMany([], h);
+assert(mt.root() == root105 && mt.rootHeight(root105) == 120, "empty blocks should refresh the current root");
+assert(mt.isRootRecent(root105, 120, 10), "current root should stay recent over empty blocks");
+assert(!mt.isRootRecent(root100, 120, 10), "replaced roots should age out");
+console.log("ok");
@@ -0,0 +1,14 @@
+const {MerkleTree, InsertReceipt} = require("../lib/index");
+const assert = require("assert");
+
+let mt = new MerkleTree(48);
+const first = mt.pushMany([1n, 2n, 3n]);
+assert(first.length == 3 && first[2] instanceof InsertReceipt);
+assert(first[2].position == 2n && first[2].root == mt.root());
+first.forEach((r, i) => assert(MerkleTree.getRoot(r.proof, r.position, [1n, 2n, 3n][i]) == r.root, "receipt proof should lead to its root"));
+
+const second = mt.pushMany([4n]);
+assert(second[0].position == 3n && second[0].root == mt.root() && second[0].root != first[0].root);
+assert(MerkleTree.getRoot(first[0].proof, 0n, 1n) == first[0].root, "old receipts should stay valid for their root");
+assert(mt.pushMany([]).length == 0);
+console.log("ok");
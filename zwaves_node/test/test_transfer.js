@@ -56,6 +56,7 @@ let data = {
     in_proof_sibling,
     root_hash,
     sk,
+    alpha: fs_random(),
     packed_asset,
     receiver
 
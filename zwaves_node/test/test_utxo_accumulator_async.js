@@ -0,0 +1,35 @@
+let {utxoAccumulatorAsync, MerkleTree, fr_random, verify, extract_vk} = require("../lib/index.js");
+const assert = require("assert");
+
+
+let fs = require("fs");
+let mpc_params = fs.readFileSync("../zwaves_setup/mpc_params_accumulator");
+let vk = extract_vk(mpc_params);
+
+let mt = new MerkleTree(48);
+let jobs = [];
+
+for (let i = 0; i < 4; i++) {
+    const index = mt.size();
+    let proof_zero = mt.proof(index).slice(1);
+    let pair = [fr_random(), fr_random()];
+    mt.pushMany(pair);
+    let proof_pair = mt.proof(index).slice(1);
+    jobs.push({note_hashes: pair, proof_index: index, proof_sibling: [proof_zero, proof_pair]});
+}
+
+let ticks = 0;
+const timer = setInterval(() => ticks++, 10);
+
+const start = Date.now();
+Promise.all(jobs.map(data => utxoAccumulatorAsync(mpc_params, data))).then(res => {
+    clearInterval(timer);
+    console.log(`${res.length} proofs in ${Date.now() - start} ms, event loop ticked ${ticks} times`);
+    res.forEach(r => assert(verify(vk, r), "proof should be valid"));
+    assert(ticks > 0, "event loop should not be blocked");
+    console.log("ok");
+}).catch(e => {
+    clearInterval(timer);
+    console.error(e);
+    process.exit(1);
+});
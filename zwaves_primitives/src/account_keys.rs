@@ -0,0 +1,131 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::PrimeField;
+use num::BigUint;
+
+use crate::field::{from_biguint, to_biguint};
+use crate::sapling_keys::{prf_expand, SPENDING_KEY_LEN};
+use crate::transactions::{self, NoteData};
+
+
+// Spending keys of a wallet account derived from its 32 byte account key with prf_expand, as
+// ExpandedSpendingKey derives ask and nsk. Receiving keys j = 0, 1, ... own the notes paid to
+// the addresses the wallet gives out, one address per key (see address_book); change goes to a
+// sequence of change keys under a tag of its own, so change notes never carry a receiving
+// address. A wallet restored from the account key finds both by walking the sequences (see
+// find_receiving_key and find_change_key).
+//
+// The transfer circuit spends the notes of one key: notes of different change keys are spent
+// in different transfers, or are refreshed to one key first.
+pub const RECEIVING_KEY_TAG: u8 = 0x80;
+pub const CHANGE_KEY_TAG: u8 = 0x81;
+pub const CHANGE_KEY_GAP: u32 = 20;
+pub const RECEIVING_KEY_GAP: u32 = 20;
+
+
+// 512 bits reduced modulo r, the bias is negligible
+fn to_fr<F: PrimeField>(data: &[u8; 64]) -> F {
+    let mut modulus = to_biguint(&{ let mut x = F::one(); x.negate(); x });
+    modulus += 1u32;
+    from_biguint(&(BigUint::from_bytes_le(data) % modulus)).unwrap()
+}
+
+fn indexed_key<F: PrimeField>(account_key: &[u8; SPENDING_KEY_LEN], tag: u8, index: u32) -> F {
+    let mut t = vec![tag];
+    t.extend_from_slice(&index.to_le_bytes());
+    to_fr(&prf_expand(account_key, &t))
+}
+
+pub fn receiving_key<E: JubjubEngine>(account_key: &[u8; SPENDING_KEY_LEN], index: u32) -> E::Fr {
+    indexed_key(account_key, RECEIVING_KEY_TAG, index)
+}
+
+pub fn change_key<E: JubjubEngine>(account_key: &[u8; SPENDING_KEY_LEN], index: u32) -> E::Fr {
+    indexed_key(account_key, CHANGE_KEY_TAG, index)
+}
+
+// The owner notes paid to the receiving key of index carry
+pub fn receiving_address<E: JubjubEngine>(account_key: &[u8; SPENDING_KEY_LEN], index: u32, params: &E::Params) -> E::Fr {
+    transactions::pubkey::<E>(&receiving_key::<E>(account_key, index), params)
+}
+
+// Change output of a transfer, owned by the change key of index
+pub fn change_note<E: JubjubEngine>(
+    account_key: &[u8; SPENDING_KEY_LEN],
+    index: u32,
+    asset_id: E::Fr,
+    amount: E::Fr,
+    native_amount: E::Fr,
+    txid: E::Fr,
+    params: &E::Params
+) -> NoteData<E> {
+    let owner = transactions::pubkey::<E>(&change_key::<E>(account_key, index), params);
+    NoteData { asset_id, amount, native_amount, txid, owner }
+}
+
+fn find_key<E: JubjubEngine>(
+    account_key: &[u8; SPENDING_KEY_LEN],
+    tag: u8,
+    owner: &E::Fr,
+    last_used: Option<u32>,
+    gap: u32,
+    params: &E::Params
+) -> Option<(u32, E::Fr)> {
+    let end = last_used.map_or(0, |i| i.saturating_add(1)).saturating_add(gap);
+    (0..end).map(|i| (i, indexed_key::<E::Fr>(account_key, tag, i)))
+        .find(|(_, sk)| transactions::pubkey::<E>(sk, params) == *owner)
+}
+
+// Index and key of the change key owning a note, searching up to gap indices past the last
+// used one, as wallets search for addresses after a restore
+pub fn find_change_key<E: JubjubEngine>(
+    account_key: &[u8; SPENDING_KEY_LEN],
+    owner: &E::Fr,
+    last_used: Option<u32>,
+    gap: u32,
+    params: &E::Params
+) -> Option<(u32, E::Fr)> {
+    find_key::<E>(account_key, CHANGE_KEY_TAG, owner, last_used, gap, params)
+}
+
+// Same for the receiving keys
+pub fn find_receiving_key<E: JubjubEngine>(
+    account_key: &[u8; SPENDING_KEY_LEN],
+    owner: &E::Fr,
+    last_used: Option<u32>,
+    gap: u32,
+    params: &E::Params
+) -> Option<(u32, E::Fr)> {
+    find_key::<E>(account_key, RECEIVING_KEY_TAG, owner, last_used, gap, params)
+}
+
+
+#[cfg(test)]
+mod account_keys_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use pairing::Field;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    #[test]
+    fn test_change_keys() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let account_key = rng.gen::<[u8; 32]>();
+
+        let receiving = receiving_address::<Bls12>(&account_key, 0, params);
+        let note = change_note::<Bls12>(&account_key, 5, Fr::zero(), Fr::one(), Fr::zero(), rng.gen(), params);
+        assert!(note.owner != receiving, "Change should not go to the receiving address");
+        assert!(change_key::<Bls12>(&account_key, 5) != change_key::<Bls12>(&account_key, 6));
+        assert!(change_key::<Bls12>(&account_key, 5) == change_key::<Bls12>(&account_key, 5), "Derivation should be deterministic");
+
+        let found = find_change_key::<Bls12>(&account_key, &note.owner, Some(2), CHANGE_KEY_GAP, params);
+        assert!(found == Some((5, change_key::<Bls12>(&account_key, 5))));
+        assert!(find_change_key::<Bls12>(&account_key, &note.owner, None, 5, params).is_none(), "Search should stop at the gap");
+        assert!(find_change_key::<Bls12>(&account_key, &receiving, None, CHANGE_KEY_GAP, params).is_none());
+        assert!(find_receiving_key::<Bls12>(&account_key, &receiving, None, RECEIVING_KEY_GAP, params) == Some((0, receiving_key::<Bls12>(&account_key, 0))));
+        assert!(find_receiving_key::<Bls12>(&account_key, &note.owner, None, RECEIVING_KEY_GAP, params).is_none(), "Change key should not be a receiving key");
+    }
+}
@@ -0,0 +1,202 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+
+use crate::account_keys::{receiving_address, find_receiving_key};
+use crate::sapling_keys::SPENDING_KEY_LEN;
+
+
+// Receiving addresses of a wallet account, the owners of the receiving keys j = 0, 1, ... of
+// account_keys: a note commits to the owner only, so an address is a key of its own and not a
+// diversified address of one key. Every device restored from the same account key derives the
+// same sequence.
+//
+// The book records the indices it has handed out and the lowest index not handed out yet, and
+// saves them to the wallet store before an address is returned. Devices sharing a seed do not
+// share the store: a device which finds a note paid to an address it did not give out (see
+// find_index) marks the index used, and the next address it gives out comes after it.
+pub const ADDRESS_BOOK_VERSION: u8 = 2;
+
+
+pub trait AddressStore {
+    fn load(&mut self) -> io::Result<Option<Vec<u8>>>;
+    fn save(&mut self, state: &[u8]) -> io::Result<()>;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryAddressStore {
+    state: Option<Vec<u8>>
+}
+
+impl AddressStore for MemoryAddressStore {
+    fn load(&mut self) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.state.clone())
+    }
+
+    fn save(&mut self, state: &[u8]) -> io::Result<()> {
+        self.state = Some(state.to_vec());
+        Ok(())
+    }
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct AddressBookState {
+    next: u32,
+    issued: BTreeSet<u32>
+}
+
+impl AddressBookState {
+    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(ADDRESS_BOOK_VERSION)?;
+        writer.write_u32::<BigEndian>(self.next)?;
+        writer.write_u32::<BigEndian>(self.issued.len() as u32)?;
+        for &i in self.issued.iter() {
+            writer.write_u32::<BigEndian>(i)?;
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        if reader.read_u8()? != ADDRESS_BOOK_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown address book version"));
+        }
+        let next = reader.read_u32::<BigEndian>()?;
+        let n = reader.read_u32::<BigEndian>()?;
+        let mut issued = BTreeSet::new();
+        for _ in 0..n {
+            let i = reader.read_u32::<BigEndian>()?;
+            if i >= next || issued.iter().next_back().map_or(false, |&last| last >= i) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "issued indices should be ascending and below the next index"));
+            }
+            issued.insert(i);
+        }
+        Ok(AddressBookState { next, issued })
+    }
+}
+
+
+pub struct AddressBook<S: AddressStore = MemoryAddressStore> {
+    store: S,
+    account_key: [u8; SPENDING_KEY_LEN],
+    state: AddressBookState
+}
+
+impl<S: AddressStore> AddressBook<S> {
+    // An empty store starts a new book
+    pub fn open(account_key: [u8; SPENDING_KEY_LEN], mut store: S) -> io::Result<Self> {
+        let state = match store.load()? {
+            Some(data) => AddressBookState::read(&data[..])?,
+            None => AddressBookState::default()
+        };
+        Ok(AddressBook { store, account_key, state })
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    // Lowest index neither handed out nor seen in use
+    pub fn next_index(&self) -> u32 {
+        self.state.next
+    }
+
+    pub fn is_issued(&self, index: u32) -> bool {
+        self.state.issued.contains(&index)
+    }
+
+    pub fn issued(&self) -> impl Iterator<Item = u32> + '_ {
+        self.state.issued.iter().cloned()
+    }
+
+    // The store is updated first, the book is unchanged if saving fails
+    fn commit(&mut self, state: AddressBookState) -> io::Result<()> {
+        let mut data = vec![];
+        state.write(&mut data)?;
+        self.store.save(&data)?;
+        self.state = state;
+        Ok(())
+    }
+
+    fn bump(index: u32) -> io::Result<u32> {
+        index.checked_add(1).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "receiving key indices are exhausted"))
+    }
+
+    pub fn address<E: JubjubEngine>(&self, index: u32, params: &E::Params) -> E::Fr {
+        receiving_address::<E>(&self.account_key, index, params)
+    }
+
+    pub fn next_unused_address<E: JubjubEngine>(&mut self, params: &E::Params) -> io::Result<(u32, E::Fr)> {
+        let index = self.state.next;
+        let addr = self.address::<E>(index, params);
+        let mut state = self.state.clone();
+        state.issued.insert(index);
+        state.next = Self::bump(index)?;
+        self.commit(state)?;
+        Ok((index, addr))
+    }
+
+    // For an index found in use, e.g. handed out by another device restored from the seed
+    pub fn mark_used(&mut self, index: u32) -> io::Result<()> {
+        if index < self.state.next {
+            return Ok(());
+        }
+        let mut state = self.state.clone();
+        state.next = Self::bump(index)?;
+        self.commit(state)
+    }
+
+    // Index of the address a note is paid to, searching up to gap indices past the next one
+    pub fn find_index<E: JubjubEngine>(&self, owner: &E::Fr, gap: u32, params: &E::Params) -> Option<u32> {
+        let last = self.state.next.checked_sub(1);
+        find_receiving_key::<E>(&self.account_key, owner, last, gap, params).map(|(i, _)| i)
+    }
+}
+
+
+#[cfg(test)]
+mod address_book_tests {
+    use super::*;
+    use pairing::bls12_381::Bls12;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::account_keys::RECEIVING_KEY_GAP;
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    #[test]
+    fn test_address_book() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let account_key = rng.gen::<[u8; 32]>();
+
+        let mut book = AddressBook::open(account_key, MemoryAddressStore::default()).unwrap();
+        let (i0, a0) = book.next_unused_address::<Bls12>(params).unwrap();
+        let (i1, a1) = book.next_unused_address::<Bls12>(params).unwrap();
+        assert!(i0 == 0 && i1 == 1 && a0 != a1, "Addresses should not be reused");
+        assert!(receiving_address::<Bls12>(&account_key, i1, params) == a1);
+
+        let mut book = AddressBook::open(account_key, book.store().clone()).unwrap();
+        assert!(book.next_index() == i1 + 1 && book.issued().collect::<Vec<_>>() == vec![i0, i1], "State should be restored from the store");
+
+        // Another device restored from the seed: it finds a note paid to a1 and does not hand it out
+        let mut other = AddressBook::open(account_key, MemoryAddressStore::default()).unwrap();
+        let found = other.find_index::<Bls12>(&a1, RECEIVING_KEY_GAP, params).unwrap();
+        assert!(found == i1 && !other.is_issued(found));
+        other.mark_used(found).unwrap();
+        let (j, _) = other.next_unused_address::<Bls12>(params).unwrap();
+        assert!(j > i1);
+        let (i2, _) = book.next_unused_address::<Bls12>(params).unwrap();
+        assert!(i2 == j, "Derivation should be deterministic");
+        assert!(other.find_index::<Bls12>(&rng.gen(), RECEIVING_KEY_GAP, params).is_none());
+
+        let mut data = book.store().clone().load().unwrap().unwrap();
+        data[0] = ADDRESS_BOOK_VERSION + 1;
+        assert!(AddressBook::open(account_key, MemoryAddressStore { state: Some(data.clone()) }).is_err());
+        data[0] = ADDRESS_BOOK_VERSION;
+        data[1..5].copy_from_slice(&i2.to_be_bytes());
+        assert!(AddressBook::open(account_key, MemoryAddressStore { state: Some(data) }).is_err(), "Issued index past the next one should be rejected");
+    }
+}
@@ -0,0 +1,882 @@
+use pairing::bls12_381::{Bls12, Fr, FrRepr, Fq, Fq2, Fq6, Fq12, G1, G1Affine, G2Affine};
+use pairing::{Engine, CurveAffine, CurveProjective, Field, PrimeField, PrimeFieldRepr};
+use bellman::groth16::Proof;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(any(test, feature = "testing"))]
+use rand::Rng;
+
+use blake2_rfc::blake2s::Blake2s;
+
+use crate::fieldtools;
+use crate::point::read_point;
+use crate::verifier::PreparedTruncatedVerifyingKey;
+
+use std::io::{self, Read, Write};
+
+
+// SnarkPack-style aggregation of n Groth16 proofs over the same verifying key (n a power of two).
+//
+// The prover commits to the A, B and C points of all proofs with pairing commitments under
+// keys built from a powers-of-tau style SRS (two independent powers, alpha and beta), draws
+// r from the transcript and proves with one GIPA run (TIPP for A*B, MIPP for C) that
+//
+//   ip_ab = prod e(A_i, B_i)^(r^i),   agg_c = sum r^i C_i
+//
+// for the committed points. The final commitment keys are checked with KZG openings, so the
+// verifier does O(log n) pairings plus one Groth16 check of the randomized sum:
+//
+//   ip_ab == e(alpha, beta)^(sum r^i) * e(sum r^i acc_i, gamma) * e(agg_c, delta)
+//
+// where acc_i is the input accumulator of the i-th proof. The transcript starts with the
+// aggregation key and the Groth16 key, so the challenges are bound to both.
+pub const AGGREGATE_PERSONALIZATION: &[u8; 8] = b"ZWaggreg";
+
+// Srs file: u32 BE count | g_alpha | g_beta (compressed) | h_alpha | h_beta (compressed),
+// count points each. 2n powers aggregate up to n proofs.
+pub const MAX_SRS_POWERS: usize = 1 << 17;
+
+
+// Powers of alpha and beta in both groups, 2n of each for up to n proofs
+#[derive(Clone)]
+pub struct Srs {
+    pub g_alpha: Vec<G1Affine>,
+    pub g_beta: Vec<G1Affine>,
+    pub h_alpha: Vec<G2Affine>,
+    pub h_beta: Vec<G2Affine>
+}
+
+// Commitment key, the same powers of alpha and beta
+#[derive(Clone)]
+pub struct Key<G: CurveAffine> {
+    pub a: Vec<G>,
+    pub b: Vec<G>
+}
+
+// v = h^(alpha^i), w = g^(alpha^(n+i)) for i < n, and the same for beta. The verifier key
+// is kept for the transcript.
+#[derive(Clone)]
+pub struct ProverKey {
+    pub vkey: Key<G2Affine>,
+    pub wkey: Key<G1Affine>,
+    pub vk: VerifierKey
+}
+
+#[derive(Clone)]
+pub struct VerifierKey {
+    pub n: usize,
+    pub g: G1Affine,
+    pub h: G2Affine,
+    pub g_alpha: G1Affine,
+    pub g_beta: G1Affine,
+    pub h_alpha: G2Affine,
+    pub h_beta: G2Affine,
+    pub g_alpha_n: G1Affine,
+    pub g_beta_n: G1Affine
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Commitment(pub Fq12, pub Fq12);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GipaRound {
+    pub t_l: Commitment,
+    pub t_r: Commitment,
+    pub z_l: Fq12,
+    pub z_r: Fq12,
+    pub u_l: Commitment,
+    pub u_r: Commitment,
+    pub zc_l: G1Affine,
+    pub zc_r: G1Affine
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregateProof {
+    pub com_ab: Commitment,
+    pub com_c: Commitment,
+    pub ip_ab: Fq12,
+    pub agg_c: G1Affine,
+    pub rounds: Vec<GipaRound>,
+    pub final_a: G1Affine,
+    pub final_b: G2Affine,
+    pub final_c: G1Affine,
+    pub final_vkey: (G2Affine, G2Affine),
+    pub final_wkey: (G1Affine, G1Affine),
+    pub vkey_opening: (G2Affine, G2Affine),
+    pub wkey_opening: (G1Affine, G1Affine)
+}
+
+
+fn powers(x: &Fr, n: usize) -> Vec<Fr> {
+    let mut res = Vec::with_capacity(n);
+    let mut p = Fr::one();
+    for _ in 0..n {
+        res.push(p);
+        p.mul_assign(x);
+    }
+    res
+}
+
+fn normalize<G: CurveProjective>(mut v: Vec<G>) -> Vec<G::Affine> {
+    G::batch_normalization(&mut v);
+    v.iter().map(|p| p.into_affine()).collect()
+}
+
+fn scale<G: CurveAffine<Scalar = Fr>>(points: &[G], scalars: &[Fr]) -> Vec<G> {
+    normalize(points.iter().zip(scalars.iter()).map(|(p, s)| p.mul(s.into_repr())).collect())
+}
+
+fn multiexp<G: CurveAffine<Scalar = Fr>>(points: &[G], scalars: &[Fr]) -> G::Projective {
+    let mut res = G::Projective::zero();
+    for (p, s) in points.iter().zip(scalars.iter()) {
+        res.add_assign(&p.mul(s.into_repr()));
+    }
+    res
+}
+
+// left + x * right
+fn fold<G: CurveAffine<Scalar = Fr>>(left: &[G], right: &[G], x: &Fr) -> Vec<G> {
+    normalize(left.iter().zip(right.iter()).map(|(l, r)| {
+        let mut p = r.mul(x.into_repr());
+        p.add_assign_mixed(l);
+        p
+    }).collect())
+}
+
+impl<G: CurveAffine<Scalar = Fr>> Key<G> {
+    fn split(&self, m: usize) -> (Self, Self) {
+        (Key { a: self.a[..m].to_vec(), b: self.b[..m].to_vec() }, Key { a: self.a[m..].to_vec(), b: self.b[m..].to_vec() })
+    }
+
+    fn fold(left: &Self, right: &Self, x: &Fr) -> Self {
+        Key { a: fold(&left.a, &right.a, x), b: fold(&left.b, &right.b, x) }
+    }
+
+    fn scale(&self, scalars: &[Fr]) -> Self {
+        Key { a: scale(&self.a, scalars), b: scale(&self.b, scalars) }
+    }
+}
+
+
+fn pairing_product(pairs: &[(G1Affine, G2Affine)]) -> Fq12 {
+    let prepared = pairs.iter().map(|(a, b)| (a.prepare(), b.prepare())).collect::<Vec<_>>();
+    let refs = prepared.iter().map(|(a, b)| (a, b)).collect::<Vec<_>>();
+    Bls12::final_exponentiation(&Bls12::miller_loop(refs.iter())).unwrap()
+}
+
+fn inner_product(a: &[G1Affine], b: &[G2Affine]) -> Fq12 {
+    pairing_product(&a.iter().cloned().zip(b.iter().cloned()).collect::<Vec<_>>())
+}
+
+// (prod e(a_i, v_i) * e(w_i, b_i)) under the alpha and the beta keys
+fn commit_ab(vkey: &Key<G2Affine>, wkey: &Key<G1Affine>, a: &[G1Affine], b: &[G2Affine]) -> Commitment {
+    let part = |v: &[G2Affine], w: &[G1Affine]| {
+        let mut res = inner_product(a, v);
+        res.mul_assign(&inner_product(w, b));
+        res
+    };
+    Commitment(part(&vkey.a, &wkey.a), part(&vkey.b, &wkey.b))
+}
+
+fn commit_c(vkey: &Key<G2Affine>, c: &[G1Affine]) -> Commitment {
+    Commitment(inner_product(c, &vkey.a), inner_product(c, &vkey.b))
+}
+
+// x * l^e * r^(e^-1)
+fn fold_gt(x: &Fq12, l: &Fq12, r: &Fq12, e: &Fr, e_inv: &Fr) -> Fq12 {
+    let mut res = *x;
+    res.mul_assign(&l.pow(e.into_repr()));
+    res.mul_assign(&r.pow(e_inv.into_repr()));
+    res
+}
+
+fn fold_commitment(x: &Commitment, l: &Commitment, r: &Commitment, e: &Fr, e_inv: &Fr) -> Commitment {
+    Commitment(fold_gt(&x.0, &l.0, &r.0, e, e_inv), fold_gt(&x.1, &l.1, &r.1, e, e_inv))
+}
+
+
+// The folded keys are f(alpha) in the exponent for f(X) = prod_k (1 + c_k X^(2^k)), c_k being
+// the challenge folding bit k of the index.
+fn poly_coeffs(c: &[Fr]) -> Vec<Fr> {
+    let mut res = vec![Fr::one()];
+    for ck in c.iter() {
+        let high = res.iter().map(|x| { let mut x = *x; x.mul_assign(ck); x }).collect::<Vec<_>>();
+        res.extend(high);
+    }
+    res
+}
+
+fn poly_eval(c: &[Fr], z: &Fr) -> Fr {
+    let mut res = Fr::one();
+    let mut zp = *z;
+    for ck in c.iter() {
+        let mut t = *ck;
+        t.mul_assign(&zp);
+        t.add_assign(&Fr::one());
+        res.mul_assign(&t);
+        zp.square();
+    }
+    res
+}
+
+// q(X) = (f(X) - f(z)) / (X - z)
+fn poly_quotient(f: &[Fr], z: &Fr) -> Vec<Fr> {
+    let mut q = vec![Fr::zero(); f.len() - 1];
+    let mut acc = Fr::zero();
+    for i in (1..f.len()).rev() {
+        acc.mul_assign(z);
+        acc.add_assign(&f[i]);
+        q[i - 1] = acc;
+    }
+    q
+}
+
+// Challenges of the v key (folded with x^-1) and of the rescaled w key (folded with x,
+// w_i scaled by r^-i), ordered by index bit
+fn key_challenges(xs: &[Fr], r_inv: &Fr) -> (Vec<Fr>, Vec<Fr>) {
+    let mut cv = vec![];
+    let mut cw = vec![];
+    let mut rp = *r_inv;
+    for x in xs.iter().rev() {
+        cv.push(x.inverse().unwrap());
+        let mut t = *x;
+        t.mul_assign(&rp);
+        cw.push(t);
+        rp.square();
+    }
+    (cv, cw)
+}
+
+
+fn gt_coeffs(x: &Fq12) -> [Fq; 12] {
+    let mut res = [Fq::zero(); 12];
+    for (i, c6) in [x.c0, x.c1].iter().enumerate() {
+        for (j, c2) in [c6.c0, c6.c1, c6.c2].iter().enumerate() {
+            res[i * 6 + j * 2] = c2.c0;
+            res[i * 6 + j * 2 + 1] = c2.c1;
+        }
+    }
+    res
+}
+
+pub(crate) fn write_gt<W: Write>(x: &Fq12, mut writer: W) -> io::Result<()> {
+    for c in gt_coeffs(x).iter() {
+        c.into_repr().write_be(&mut writer)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_gt<R: Read>(mut reader: R) -> io::Result<Fq12> {
+    let mut c = [Fq::zero(); 12];
+    for x in c.iter_mut() {
+        let mut repr = <Fq as PrimeField>::Repr::default();
+        repr.read_be(&mut reader)?;
+        *x = Fq::from_repr(repr).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))?;
+    }
+    let fq6 = |c: &[Fq]| Fq6 {
+        c0: Fq2 { c0: c[0], c1: c[1] },
+        c1: Fq2 { c0: c[2], c1: c[3] },
+        c2: Fq2 { c0: c[4], c1: c[5] }
+    };
+    let res = Fq12 { c0: fq6(&c[..6]), c1: fq6(&c[6..]) };
+    // Fq12 elements outside of the order r subgroup would let a proof pick values no pairing gives
+    if res.is_zero() || res.pow(Fr::char()) != Fq12::one() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not in the target group"));
+    }
+    Ok(res)
+}
+
+
+struct Transcript {
+    state: [u8; 32],
+    data: Vec<u8>
+}
+
+impl Transcript {
+    fn new(vk: &VerifierKey, pvk: &PreparedTruncatedVerifyingKey<Bls12>, inputs: &[Vec<Fr>]) -> Self {
+        let mut t = Transcript { state: [0; 32], data: vec![] };
+        t.data.extend_from_slice(&(vk.n as u64).to_le_bytes());
+        for p in [vk.g, vk.g_alpha, vk.g_beta, vk.g_alpha_n, vk.g_beta_n].iter() {
+            t.g1(p);
+        }
+        for p in [vk.h, vk.h_alpha, vk.h_beta].iter() {
+            t.g2(p);
+        }
+        t.gt(&pvk.alpha_g1_beta_g2);
+        t.g2(&pvk.gamma_g2);
+        t.g2(&pvk.delta_g2);
+        for p in pvk.ic.iter() {
+            t.g1(p);
+        }
+        for x in inputs.iter().flatten() {
+            x.into_repr().write_le(&mut t.data).unwrap();
+        }
+        t
+    }
+
+    fn g1(&mut self, p: &G1Affine) {
+        self.data.extend_from_slice(p.into_compressed().as_ref());
+    }
+
+    fn g2(&mut self, p: &G2Affine) {
+        self.data.extend_from_slice(p.into_compressed().as_ref());
+    }
+
+    fn gt(&mut self, x: &Fq12) {
+        write_gt(x, &mut self.data).unwrap();
+    }
+
+    fn commitment(&mut self, c: &Commitment) {
+        self.gt(&c.0);
+        self.gt(&c.1);
+    }
+
+    fn round(&mut self, r: &GipaRound) {
+        self.commitment(&r.t_l);
+        self.commitment(&r.t_r);
+        self.gt(&r.z_l);
+        self.gt(&r.z_r);
+        self.commitment(&r.u_l);
+        self.commitment(&r.u_r);
+        self.g1(&r.zc_l);
+        self.g1(&r.zc_r);
+    }
+
+    fn finals(&mut self, proof: &AggregateProof) {
+        self.g1(&proof.final_a);
+        self.g2(&proof.final_b);
+        self.g1(&proof.final_c);
+        self.g2(&proof.final_vkey.0);
+        self.g2(&proof.final_vkey.1);
+        self.g1(&proof.final_wkey.0);
+        self.g1(&proof.final_wkey.1);
+    }
+
+    // Nonzero challenge, so that it can be inverted
+    fn challenge(&mut self) -> Fr {
+        loop {
+            let mut h = Blake2s::with_params(32, &[], &[], AGGREGATE_PERSONALIZATION);
+            h.update(&self.state);
+            h.update(&self.data);
+            self.state.copy_from_slice(h.finalize().as_bytes());
+            self.data.clear();
+
+            let mut repr = FrRepr::default();
+            repr.read_le(&self.state[..]).unwrap();
+            let x: Fr = fieldtools::affine(repr);
+            if !x.is_zero() {
+                return x;
+            }
+        }
+    }
+}
+
+
+impl Srs {
+    // The trapdoor is known to whoever runs this, so it is for tests only. Real parameters
+    // have to come from a ceremony, see read.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn new_insecure<R: Rng>(n: usize, rng: &mut R) -> Self {
+        let (alpha, beta) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+        let (g, h) = (G1Affine::one(), G2Affine::one());
+        let (alpha_powers, beta_powers) = (powers(&alpha, 2 * n), powers(&beta, 2 * n));
+
+        Srs {
+            g_alpha: scale(&vec![g; 2 * n], &alpha_powers),
+            g_beta: scale(&vec![g; 2 * n], &beta_powers),
+            h_alpha: scale(&vec![h; 2 * n], &alpha_powers),
+            h_beta: scale(&vec![h; 2 * n], &beta_powers)
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u32::<BigEndian>(self.g_alpha.len() as u32)?;
+        for p in self.g_alpha.iter().chain(self.g_beta.iter()) {
+            writer.write_all(p.into_compressed().as_ref())?;
+        }
+        for p in self.h_alpha.iter().chain(self.h_beta.iter()) {
+            writer.write_all(p.into_compressed().as_ref())?;
+        }
+        Ok(())
+    }
+
+    // Output of a ceremony. The points are checked to be in the subgroups and to be
+    // consecutive powers of the same alpha and beta in both groups, starting from the generators.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let m = reader.read_u32::<BigEndian>()? as usize;
+        if m < 4 || m > MAX_SRS_POWERS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong number of srs powers"));
+        }
+        let g1s = |r: &mut R| (0..m).map(|_| read_point::<G1Affine, _>(&mut *r, true)).collect::<io::Result<Vec<_>>>();
+        let g_alpha = g1s(&mut reader)?;
+        let g_beta = g1s(&mut reader)?;
+        let g2s = |r: &mut R| (0..m).map(|_| read_point::<G2Affine, _>(&mut *r, true)).collect::<io::Result<Vec<_>>>();
+        let h_alpha = g2s(&mut reader)?;
+        let h_beta = g2s(&mut reader)?;
+        let srs = Srs { g_alpha, g_beta, h_alpha, h_beta };
+        if !srs.check_powers() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "srs points are not powers of one trapdoor"));
+        }
+        Ok(srs)
+    }
+
+    // g^(a^i) and h^(a^i) for one a: e(g_(i+1), h) == e(g_i, h_1) and e(g, h_(i+1)) == e(g_1, h_i),
+    // each batched with random powers of rho drawn from the transcript of the points
+    fn check_powers(&self) -> bool {
+        let (g, h) = (G1Affine::one(), G2Affine::one());
+        let mut t = Transcript { state: [0; 32], data: vec![] };
+        for p in self.g_alpha.iter().chain(self.g_beta.iter()) {
+            t.g1(p);
+        }
+        for p in self.h_alpha.iter().chain(self.h_beta.iter()) {
+            t.g2(p);
+        }
+        let rho = powers(&t.challenge(), self.g_alpha.len() - 1);
+
+        [(&self.g_alpha, &self.h_alpha), (&self.g_beta, &self.h_beta)].iter().all(|(gs, hs)| {
+            let m = gs.len();
+            let (g_lo, g_hi) = (multiexp(&gs[..m - 1], &rho).into_affine(), multiexp(&gs[1..], &rho).into_affine());
+            let (h_lo, h_hi) = (multiexp(&hs[..m - 1], &rho).into_affine(), multiexp(&hs[1..], &rho).into_affine());
+            gs[0] == g && hs[0] == h && !gs[1].is_zero()
+                && pairing_product(&[(g_hi, h), (neg(&g_lo), hs[1])]) == Fq12::one()
+                && pairing_product(&[(g, h_hi), (neg(&gs[1]), h_lo)]) == Fq12::one()
+        })
+    }
+
+    // Keys for aggregating exactly n proofs
+    pub fn specialize(&self, n: usize) -> io::Result<(ProverKey, VerifierKey)> {
+        if n < 2 || !n.is_power_of_two() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "number of proofs should be a power of two"));
+        }
+        if 2 * n > self.g_alpha.len() || 2 * n > self.h_alpha.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "srs is too small"));
+        }
+
+        let vk = VerifierKey {
+            n,
+            g: self.g_alpha[0],
+            h: self.h_alpha[0],
+            g_alpha: self.g_alpha[1],
+            g_beta: self.g_beta[1],
+            h_alpha: self.h_alpha[1],
+            h_beta: self.h_beta[1],
+            g_alpha_n: self.g_alpha[n],
+            g_beta_n: self.g_beta[n]
+        };
+        let pk = ProverKey {
+            vkey: Key { a: self.h_alpha[..n].to_vec(), b: self.h_beta[..n].to_vec() },
+            wkey: Key { a: self.g_alpha[n..2 * n].to_vec(), b: self.g_beta[n..2 * n].to_vec() },
+            vk: vk.clone()
+        };
+        Ok((pk, vk))
+    }
+}
+
+
+pub fn aggregate_proofs(
+    pk: &ProverKey,
+    pvk: &PreparedTruncatedVerifyingKey<Bls12>,
+    proofs: &[Proof<Bls12>],
+    inputs: &[Vec<Fr>]
+) -> io::Result<AggregateProof>
+{
+    let n = pk.vkey.a.len();
+    if proofs.len() != n || inputs.len() != n {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "number of proofs should match the aggregation key"));
+    }
+
+    let mut a = proofs.iter().map(|p| p.a).collect::<Vec<_>>();
+    let b = proofs.iter().map(|p| p.b).collect::<Vec<_>>();
+    let mut c = proofs.iter().map(|p| p.c).collect::<Vec<_>>();
+
+    let com_ab = commit_ab(&pk.vkey, &pk.wkey, &a, &b);
+    let com_c = commit_c(&pk.vkey, &c);
+
+    if inputs.iter().any(|x| x.len() + 1 != pvk.ic.len()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "number of public inputs should match the verifying key"));
+    }
+
+    let mut t = Transcript::new(&pk.vk, pvk, inputs);
+    t.commitment(&com_ab);
+    t.commitment(&com_c);
+    let r = t.challenge();
+    let r_inv = r.inverse().unwrap();
+
+    // B_i^(r^i) against w_i^(r^-i) keeps com_ab unchanged
+    let mut r_vec = powers(&r, n);
+    let mut b = scale(&b, &r_vec);
+    let mut vkey = pk.vkey.clone();
+    let mut wkey = pk.wkey.scale(&powers(&r_inv, n));
+
+    let ip_ab = inner_product(&a, &b);
+    let agg_c = multiexp(&c, &r_vec).into_affine();
+    t.gt(&ip_ab);
+    t.g1(&agg_c);
+
+    let mut rounds = vec![];
+    let mut xs = vec![];
+    while a.len() > 1 {
+        let m = a.len() / 2;
+        let (a_l, a_r) = a.split_at(m);
+        let (b_l, b_r) = b.split_at(m);
+        let (c_l, c_r) = c.split_at(m);
+        let (r_l, r_r) = r_vec.split_at(m);
+        let (v_l, v_r) = vkey.split(m);
+        let (w_l, w_r) = wkey.split(m);
+
+        let round = GipaRound {
+            t_l: commit_ab(&v_l, &w_r, a_r, b_l),
+            t_r: commit_ab(&v_r, &w_l, a_l, b_r),
+            z_l: inner_product(a_r, b_l),
+            z_r: inner_product(a_l, b_r),
+            u_l: commit_c(&v_l, c_r),
+            u_r: commit_c(&v_r, c_l),
+            zc_l: multiexp(c_r, r_l).into_affine(),
+            zc_r: multiexp(c_l, r_r).into_affine()
+        };
+        t.round(&round);
+        let x = t.challenge();
+        let x_inv = x.inverse().unwrap();
+
+        let next_a = fold(a_l, a_r, &x);
+        let next_b = fold(b_l, b_r, &x_inv);
+        let next_c = fold(c_l, c_r, &x);
+        let next_r = r_l.iter().zip(r_r.iter()).map(|(l, r)| {
+            let mut res = *r;
+            res.mul_assign(&x_inv);
+            res.add_assign(l);
+            res
+        }).collect::<Vec<_>>();
+        a = next_a;
+        b = next_b;
+        c = next_c;
+        r_vec = next_r;
+        vkey = Key::fold(&v_l, &v_r, &x_inv);
+        wkey = Key::fold(&w_l, &w_r, &x);
+
+        rounds.push(round);
+        xs.push(x);
+    }
+
+    let mut proof = AggregateProof {
+        com_ab,
+        com_c,
+        ip_ab,
+        agg_c,
+        rounds,
+        final_a: a[0],
+        final_b: b[0],
+        final_c: c[0],
+        final_vkey: (vkey.a[0], vkey.b[0]),
+        final_wkey: (wkey.a[0], wkey.b[0]),
+        vkey_opening: (G2Affine::zero(), G2Affine::zero()),
+        wkey_opening: (G1Affine::zero(), G1Affine::zero())
+    };
+    t.finals(&proof);
+    let z = t.challenge();
+
+    let (cv, cw) = key_challenges(&xs, &r_inv);
+    let qv = poly_quotient(&poly_coeffs(&cv), &z);
+    let qw = poly_quotient(&poly_coeffs(&cw), &z);
+    proof.vkey_opening = (multiexp(&pk.vkey.a, &qv).into_affine(), multiexp(&pk.vkey.b, &qv).into_affine());
+    proof.wkey_opening = (multiexp(&pk.wkey.a, &qw).into_affine(), multiexp(&pk.wkey.b, &qw).into_affine());
+
+    Ok(proof)
+}
+
+
+fn neg<G: CurveAffine>(p: &G) -> G {
+    let mut p = *p;
+    p.negate();
+    p
+}
+
+// x - s * y
+fn sub_mul<G: CurveAffine<Scalar = Fr>>(x: &G, y: &G, s: &Fr) -> G {
+    let mut res = y.mul(s.into_repr());
+    res.negate();
+    res.add_assign_mixed(x);
+    res.into_affine()
+}
+
+// commitment = h^f(tau) for tau the trapdoor behind g_tau = g^tau, opening = h^q(tau)
+fn check_v_opening(vk: &VerifierKey, g_tau: &G1Affine, commitment: &G2Affine, opening: &G2Affine, z: &Fr, y: &Fr) -> bool {
+    pairing_product(&[
+        (sub_mul(g_tau, &vk.g, z), *opening),
+        (neg(&vk.g), sub_mul(commitment, &vk.h, y))
+    ]) == Fq12::one()
+}
+
+// commitment = g^(tau^n f(tau)), opening = g^(tau^n q(tau))
+fn check_w_opening(vk: &VerifierKey, h_tau: &G2Affine, g_tau_n: &G1Affine, commitment: &G1Affine, opening: &G1Affine, z: &Fr, y: &Fr) -> bool {
+    pairing_product(&[
+        (*opening, sub_mul(h_tau, &vk.h, z)),
+        (neg(&sub_mul(commitment, g_tau_n, y)), vk.h)
+    ]) == Fq12::one()
+}
+
+// Malformed proofs and inputs are errors; a false result means the aggregate is well formed but wrong.
+pub fn verify_aggregate_proof(
+    vk: &VerifierKey,
+    pvk: &PreparedTruncatedVerifyingKey<Bls12>,
+    inputs: &[Vec<Fr>],
+    proof: &AggregateProof
+) -> io::Result<bool>
+{
+    let n = vk.n;
+    if inputs.len() != n {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "number of inputs should match the aggregation key"));
+    }
+    if inputs.iter().any(|x| x.len() + 1 != pvk.ic.len()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "number of public inputs should match the verifying key"));
+    }
+    // n comes with the key, it is not trusted to be a power of two either
+    if !n.is_power_of_two() || proof.rounds.len() != n.trailing_zeros() as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong number of rounds"));
+    }
+
+    let mut t = Transcript::new(vk, pvk, inputs);
+    t.commitment(&proof.com_ab);
+    t.commitment(&proof.com_c);
+    let r = t.challenge();
+    let r_inv = r.inverse().unwrap();
+    t.gt(&proof.ip_ab);
+    t.g1(&proof.agg_c);
+
+    let mut com_ab = proof.com_ab;
+    let mut ip_ab = proof.ip_ab;
+    let mut com_c = proof.com_c;
+    let mut agg_c = proof.agg_c.into_projective();
+    let mut xs = vec![];
+    for round in proof.rounds.iter() {
+        t.round(round);
+        let x = t.challenge();
+        let x_inv = x.inverse().unwrap();
+
+        com_ab = fold_commitment(&com_ab, &round.t_l, &round.t_r, &x, &x_inv);
+        ip_ab = fold_gt(&ip_ab, &round.z_l, &round.z_r, &x, &x_inv);
+        com_c = fold_commitment(&com_c, &round.u_l, &round.u_r, &x, &x_inv);
+        agg_c.add_assign(&round.zc_l.mul(x.into_repr()));
+        agg_c.add_assign(&round.zc_r.mul(x_inv.into_repr()));
+        xs.push(x);
+    }
+    t.finals(proof);
+    let z = t.challenge();
+
+    let vkey = Key { a: vec![proof.final_vkey.0], b: vec![proof.final_vkey.1] };
+    let wkey = Key { a: vec![proof.final_wkey.0], b: vec![proof.final_wkey.1] };
+    let (cv, cw) = key_challenges(&xs, &r_inv);
+
+    // the last step of GIPA, r folds into f_v(r)
+    let gipa = com_ab == commit_ab(&vkey, &wkey, &[proof.final_a], &[proof.final_b])
+        && ip_ab == inner_product(&[proof.final_a], &[proof.final_b])
+        && com_c == commit_c(&vkey, &[proof.final_c])
+        && agg_c.into_affine() == proof.final_c.mul(poly_eval(&cv, &r).into_repr()).into_affine();
+    if !gipa {
+        return Ok(false);
+    }
+
+    let (yv, yw) = (poly_eval(&cv, &z), poly_eval(&cw, &z));
+    let keys = check_v_opening(vk, &vk.g_alpha, &proof.final_vkey.0, &proof.vkey_opening.0, &z, &yv)
+        && check_v_opening(vk, &vk.g_beta, &proof.final_vkey.1, &proof.vkey_opening.1, &z, &yv)
+        && check_w_opening(vk, &vk.h_alpha, &vk.g_alpha_n, &proof.final_wkey.0, &proof.wkey_opening.0, &z, &yw)
+        && check_w_opening(vk, &vk.h_beta, &vk.g_beta_n, &proof.final_wkey.1, &proof.wkey_opening.1, &z, &yw);
+    if !keys {
+        return Ok(false);
+    }
+
+    // ip_ab * e(sum r^i acc_i, -gamma) * e(agg_c, -delta) == e(alpha, beta)^(sum r^i)
+    let r_vec = powers(&r, n);
+    let mut r_sum = Fr::zero();
+    let mut acc = G1::zero();
+    for (ri, x) in r_vec.iter().zip(inputs.iter()) {
+        r_sum.add_assign(ri);
+        let mut acc_i = pvk.ic[0].into_projective();
+        for (xj, b) in x.iter().zip(pvk.ic.iter().skip(1)) {
+            acc_i.add_assign(&b.mul(xj.into_repr()));
+        }
+        acc_i.mul_assign(ri.into_repr());
+        acc.add_assign(&acc_i);
+    }
+
+    let mut res = Bls12::final_exponentiation(&Bls12::miller_loop([
+        (&acc.into_affine().prepare(), &pvk.neg_gamma_g2),
+        (&proof.agg_c.prepare(), &pvk.neg_delta_g2)
+    ].iter())).unwrap();
+    res.mul_assign(&proof.ip_ab);
+
+    Ok(res == pvk.alpha_g1_beta_g2.pow(r_sum.into_repr()))
+}
+
+
+fn write_commitment<W: Write>(c: &Commitment, mut writer: W) -> io::Result<()> {
+    write_gt(&c.0, &mut writer)?;
+    write_gt(&c.1, &mut writer)
+}
+
+fn read_commitment<R: Read>(mut reader: R) -> io::Result<Commitment> {
+    Ok(Commitment(read_gt(&mut reader)?, read_gt(&mut reader)?))
+}
+
+impl AggregateProof {
+    // GT elements as 12 Fq coordinates (48 bytes BE each), points compressed.
+    // The number of rounds is one byte, log2 of the number of proofs.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_commitment(&self.com_ab, &mut writer)?;
+        write_commitment(&self.com_c, &mut writer)?;
+        write_gt(&self.ip_ab, &mut writer)?;
+        writer.write_all(self.agg_c.into_compressed().as_ref())?;
+
+        writer.write_all(&[self.rounds.len() as u8])?;
+        for r in self.rounds.iter() {
+            write_commitment(&r.t_l, &mut writer)?;
+            write_commitment(&r.t_r, &mut writer)?;
+            write_gt(&r.z_l, &mut writer)?;
+            write_gt(&r.z_r, &mut writer)?;
+            write_commitment(&r.u_l, &mut writer)?;
+            write_commitment(&r.u_r, &mut writer)?;
+            writer.write_all(r.zc_l.into_compressed().as_ref())?;
+            writer.write_all(r.zc_r.into_compressed().as_ref())?;
+        }
+
+        writer.write_all(self.final_a.into_compressed().as_ref())?;
+        writer.write_all(self.final_b.into_compressed().as_ref())?;
+        writer.write_all(self.final_c.into_compressed().as_ref())?;
+        writer.write_all(self.final_vkey.0.into_compressed().as_ref())?;
+        writer.write_all(self.final_vkey.1.into_compressed().as_ref())?;
+        writer.write_all(self.final_wkey.0.into_compressed().as_ref())?;
+        writer.write_all(self.final_wkey.1.into_compressed().as_ref())?;
+        writer.write_all(self.vkey_opening.0.into_compressed().as_ref())?;
+        writer.write_all(self.vkey_opening.1.into_compressed().as_ref())?;
+        writer.write_all(self.wkey_opening.0.into_compressed().as_ref())?;
+        writer.write_all(self.wkey_opening.1.into_compressed().as_ref())?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let com_ab = read_commitment(&mut reader)?;
+        let com_c = read_commitment(&mut reader)?;
+        let ip_ab = read_gt(&mut reader)?;
+        let agg_c = read_point::<G1Affine, _>(&mut reader, true)?;
+
+        let mut len = [0u8; 1];
+        reader.read_exact(&mut len)?;
+        if len[0] as usize >= 64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many rounds"));
+        }
+        let mut rounds = vec![];
+        for _ in 0..len[0] {
+            rounds.push(GipaRound {
+                t_l: read_commitment(&mut reader)?,
+                t_r: read_commitment(&mut reader)?,
+                z_l: read_gt(&mut reader)?,
+                z_r: read_gt(&mut reader)?,
+                u_l: read_commitment(&mut reader)?,
+                u_r: read_commitment(&mut reader)?,
+                zc_l: read_point(&mut reader, true)?,
+                zc_r: read_point(&mut reader, true)?
+            });
+        }
+
+        Ok(AggregateProof {
+            com_ab,
+            com_c,
+            ip_ab,
+            agg_c,
+            rounds,
+            final_a: read_point(&mut reader, true)?,
+            final_b: read_point(&mut reader, true)?,
+            final_c: read_point(&mut reader, true)?,
+            final_vkey: (read_point(&mut reader, true)?, read_point(&mut reader, true)?),
+            final_wkey: (read_point(&mut reader, true)?, read_point(&mut reader, true)?),
+            vkey_opening: (read_point(&mut reader, true)?, read_point(&mut reader, true)?),
+            wkey_opening: (read_point(&mut reader, true)?, read_point(&mut reader, true)?)
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod aggregate_tests {
+    use super::*;
+    use bellman::groth16::{generate_random_parameters, create_random_proof};
+    use rand::os::OsRng;
+
+    use crate::verifier::{prepare_truncated_verifying_key, truncate_verifying_key};
+    use crate::test_circuits::Mul;
+
+    #[test]
+    fn test_aggregate_proofs() {
+        let mut rng = OsRng::new().unwrap();
+        let params = generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap();
+        let pvk = prepare_truncated_verifying_key(&truncate_verifying_key(&params.vk));
+
+        let n = 8;
+        let srs = Srs::new_insecure(n, &mut rng);
+        let (pk, vk) = srs.specialize(n).unwrap();
+
+        let mut proofs = vec![];
+        let mut inputs = vec![];
+        for _ in 0..n {
+            let (x, y) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+            let mut z = x;
+            z.mul_assign(&y);
+            proofs.push(create_random_proof(Mul(Some((x, y))), &params, &mut rng).unwrap());
+            inputs.push(vec![x, z]);
+        }
+
+        let proof = aggregate_proofs(&pk, &pvk, &proofs, &inputs).unwrap();
+        assert!(proof.rounds.len() == 3);
+        assert!(verify_aggregate_proof(&vk, &pvk, &inputs, &proof).unwrap(), "Aggregate should be valid");
+
+        let mut data = vec![];
+        proof.write(&mut data).unwrap();
+        assert!(AggregateProof::read(&data[..]).unwrap() == proof);
+        assert!(AggregateProof::read(&data[..data.len() - 1]).is_err());
+
+        // ip_ab squared is still in GT, ip_ab times a non-residue is not
+        let mut outside = proof.ip_ab;
+        outside.c0.c0.c0.add_assign(&Fq::one());
+        let mut data = vec![];
+        write_gt(&outside, &mut data).unwrap();
+        assert!(read_gt(&data[..]).is_err(), "Element outside of GT should be rejected");
+
+        let other_pvk = prepare_truncated_verifying_key(&truncate_verifying_key(&generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap().vk));
+        assert!(!verify_aggregate_proof(&vk, &other_pvk, &inputs, &proof).unwrap(), "Aggregate should be bound to the verifying key");
+        let mut other_vk = vk.clone();
+        other_vk.n = 6;
+        assert!(verify_aggregate_proof(&other_vk, &pvk, &inputs[..6], &proof).is_err());
+
+        let mut data = vec![];
+        srs.write(&mut data).unwrap();
+        let read = Srs::read(&data[..]).unwrap();
+        assert!(read.g_alpha == srs.g_alpha && read.h_beta == srs.h_beta);
+        let mut wrong = srs.clone();
+        wrong.g_beta.swap(2, 3);
+        let mut data = vec![];
+        wrong.write(&mut data).unwrap();
+        assert!(Srs::read(&data[..]).is_err(), "Srs with powers out of order should be rejected");
+
+        let mut wrong_inputs = inputs.clone();
+        wrong_inputs.swap(0, 1);
+        assert!(!verify_aggregate_proof(&vk, &pvk, &wrong_inputs, &proof).unwrap(), "Swapped inputs should be rejected");
+
+        let mut wrong = proof.clone();
+        wrong.ip_ab.square();
+        assert!(!verify_aggregate_proof(&vk, &pvk, &inputs, &wrong).unwrap(), "Wrong inner product should be rejected");
+
+        let mut wrong = proof.clone();
+        wrong.final_c = proof.agg_c;
+        assert!(!verify_aggregate_proof(&vk, &pvk, &inputs, &wrong).unwrap(), "Wrong final point should be rejected");
+
+        // a proof that does not verify on its own spoils the aggregate
+        let mut wrong_proofs = proofs.clone();
+        wrong_proofs[3].c = proofs[4].c;
+        let wrong = aggregate_proofs(&pk, &pvk, &wrong_proofs, &inputs).unwrap();
+        assert!(!verify_aggregate_proof(&vk, &pvk, &inputs, &wrong).unwrap(), "Invalid proof should spoil the aggregate");
+
+        assert!(aggregate_proofs(&pk, &pvk, &proofs[..4], &inputs[..4]).is_err());
+        assert!(verify_aggregate_proof(&vk, &pvk, &inputs[..4], &proof).is_err());
+        assert!(srs.specialize(16).is_err());
+        assert!(srs.specialize(6).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use pairing::Field;
+use sapling_crypto::jubjub::{JubjubEngine, JubjubParams, FixedGenerators, Unknown, PrimeOrder, edwards::Point};
+
+use std::io;
+
+use crate::fieldtools;
+use crate::point::PointError;
+use crate::transactions::{blake2s_to_fr, NoteData};
+
+
+// Verifiable encryption to an auditor key. The circuit (zwaves_circuit::gadgets::audit) proves that the
+// public ciphertext is the encryption of its own witness, so the auditor can read what was
+// sent without the data becoming public.
+//
+// epk = r*G, k = y(r*A), c[i] = m[i] + blake2s(k (32 bytes LE) || i (1 byte)) mod r, personalized
+// with AUDIT_MASK_PERSONALIZATION. The masks have to be a PRF of k: a Pedersen hash is linear in
+// its input bits and one known plaintext would give away the other masks. epk is published as its
+// y coordinate: -epk gives -r*A, which has the same y, so the auditor does not need the sign of x.
+pub const AUDIT_MASK_PERSONALIZATION: &[u8; 8] = b"ZWaudit_";
+pub const AUDIT_GENERATOR: FixedGenerators = FixedGenerators::SpendingKeyGenerator;
+pub const AUDIT_MAX_FIELDS: usize = 256;
+pub const AUDIT_NOTE_FIELDS: usize = 5;
+
+
+#[derive(Clone)]
+pub struct AuditCiphertext<E: JubjubEngine> {
+    pub epk: E::Fr,
+    pub data: Vec<E::Fr>
+}
+
+
+pub fn auditor_key<E: JubjubEngine>(sk: &E::Fr, params: &E::Params) -> Point<E, PrimeOrder> {
+    params.generator(AUDIT_GENERATOR).mul(fieldtools::f2f::<E::Fr, E::Fs>(sk), params)
+}
+
+pub fn mask<E: JubjubEngine>(k: &E::Fr, i: usize) -> E::Fr {
+    assert!(i < AUDIT_MAX_FIELDS);
+    let mut data = fieldtools::fr_to_repr_u8(k).into_iter().collect::<Vec<u8>>();
+    data.push(i as u8);
+    blake2s_to_fr::<E>(AUDIT_MASK_PERSONALIZATION, &data)
+}
+
+pub fn encrypt<E: JubjubEngine>(fields: &[E::Fr], auditor_pk: &Point<E, Unknown>, r: &E::Fr, params: &E::Params) -> AuditCiphertext<E> {
+    assert!(fields.len() <= AUDIT_MAX_FIELDS, "at most {} fields could be encrypted", AUDIT_MAX_FIELDS);
+    let r = fieldtools::f2f::<E::Fr, E::Fs>(r);
+    let epk = params.generator(AUDIT_GENERATOR).mul(r, params).into_xy().1;
+    let k = auditor_pk.mul(r, params).into_xy().1;
+
+    let data = fields.iter().enumerate().map(|(i, m)| {
+        let mut c = mask::<E>(&k, i);
+        c.add_assign(m);
+        c
+    }).collect();
+
+    AuditCiphertext { epk, data }
+}
+
+pub fn decrypt<E: JubjubEngine>(ct: &AuditCiphertext<E>, sk: &E::Fr, params: &E::Params) -> io::Result<Vec<E::Fr>> {
+    if ct.data.len() > AUDIT_MAX_FIELDS {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("audit ciphertext should have at most {} fields", AUDIT_MAX_FIELDS)));
+    }
+    let epk = Point::<E, Unknown>::get_for_y(ct.epk, false, params).ok_or(PointError::NotOnCurve)?;
+    let k = epk.mul(fieldtools::f2f::<E::Fr, E::Fs>(sk), params).into_xy().1;
+
+    Ok(ct.data.iter().enumerate().map(|(i, c)| {
+        let mut m = *c;
+        m.sub_assign(&mask::<E>(&k, i));
+        m
+    }).collect())
+}
+
+
+// Notes are encrypted field by field, in the order of zwaves_circuit::gadgets::transactions::Note
+pub fn note_fields<E: JubjubEngine>(note: &NoteData<E>) -> [E::Fr; AUDIT_NOTE_FIELDS] {
+    [note.asset_id, note.amount, note.native_amount, note.txid, note.owner]
+}
+
+pub fn encrypt_note<E: JubjubEngine>(note: &NoteData<E>, auditor_pk: &Point<E, Unknown>, r: &E::Fr, params: &E::Params) -> AuditCiphertext<E> {
+    encrypt(&note_fields(note), auditor_pk, r, params)
+}
+
+pub fn decrypt_note<E: JubjubEngine>(ct: &AuditCiphertext<E>, sk: &E::Fr, params: &E::Params) -> io::Result<NoteData<E>> {
+    if ct.data.len() != AUDIT_NOTE_FIELDS {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("audit ciphertext of a note should have {} fields", AUDIT_NOTE_FIELDS)));
+    }
+    let m = decrypt(ct, sk, params)?;
+    Ok(NoteData { asset_id: m[0], amount: m[1], native_amount: m[2], txid: m[3], owner: m[4] })
+}
+
+
+#[cfg(test)]
+mod audit_tests {
+    use super::*;
+    use pairing::PrimeField;
+    use pairing::bls12_381::{Bls12, Fr};
+    use sapling_crypto::jubjub::JubjubBls12;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    #[test]
+    fn test_audit_encryption() {
+        let mut rng = OsRng::new().unwrap();
+        let params = JubjubBls12::new();
+
+        let sk = rng.gen::<Fr>();
+        let pk = auditor_key::<Bls12>(&sk, &params).into();
+        let note = NoteData::<Bls12> {
+            asset_id: Fr::from_str("1").unwrap(),
+            amount: Fr::from_str("1000").unwrap(),
+            native_amount: Fr::from_str("7").unwrap(),
+            txid: rng.gen(),
+            owner: rng.gen()
+        };
+
+        let r = rng.gen::<Fr>();
+        let ct = encrypt_note(&note, &pk, &r, &params);
+        assert!(ct.data[1] != note.amount);
+        let decrypted = decrypt_note(&ct, &sk, &params).unwrap();
+        assert!(note_fields(&decrypted) == note_fields(&note), "Auditor should decrypt the note");
+
+        assert!(decrypt_note(&ct, &rng.gen::<Fr>(), &params).unwrap().amount != note.amount, "Other keys should not decrypt the note");
+        assert!(encrypt_note(&note, &pk, &rng.gen::<Fr>(), &params).epk != ct.epk, "Encryption should be randomized");
+
+        let mut short = ct.clone();
+        short.data.pop();
+        assert!(decrypt_note(&short, &sk, &params).err().unwrap().kind() == io::ErrorKind::InvalidData);
+    }
+}
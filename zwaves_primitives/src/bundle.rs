@@ -0,0 +1,481 @@
+use sapling_crypto::jubjub::{JubjubEngine, FixedGenerators};
+use sapling_crypto::redjubjub::{Signature, PublicKey};
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+use bellman::groth16::Proof;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use std::io::{self, Read, Write};
+
+use crate::serialization::read_fr_repr_be;
+use crate::point::read_proof;
+use crate::verifier::VerifierContext;
+use crate::sighash::{self, BundleParts, SIGHASH_LEN};
+use crate::spend_auth;
+use crate::signing_request::{SigningRequest, SIGNATURE_LEN};
+use crate::public_values::TransferPublicValues;
+use crate::out_amount::{self, OUT_CIPHERTEXT_LEN};
+use crate::out_note::{self, SentNote, OUT_NOTE_CIPHERTEXT_LEN};
+
+pub use crate::public_values::TRANSFER_PUBLIC_INPUTS;
+
+
+pub const TRANSFER_OUTPUTS: usize = 2;
+pub const MAX_ENCRYPTED_NOTE_LEN: usize = 1024;
+
+// 2 since the out ciphertexts. Version 1 had no version byte and started with the proof, whose
+// first byte has the compression flag 0x80 set, so it is rejected as an unknown version.
+pub const BUNDLE_VERSION: u8 = 2;
+
+const FR_LEN: usize = 32;
+
+
+// Everything a relayer needs to forward a transfer to the node.
+//
+// Canonical encoding (all integers big-endian):
+// version (1) | proof (192) | u32 inputs count | inputs (32 each) | u32 notes count | (u32 len | note)* |
+// u32 out ciphertexts count | out ciphertext (24 each) | binding sig (64) | spend auth sig (64) | fee u64
+//
+// out_ciphertexts are the output amounts encrypted to the sender's ovk, see out_amount. An
+// encrypted note may end with the whole note encrypted to the sender's ovk, see out_note.
+pub struct TransactionBundle<E: JubjubEngine> {
+    pub proof: Proof<E>,
+    pub public_inputs: Vec<E::Fr>,
+    pub encrypted_notes: Vec<Vec<u8>>,
+    pub out_ciphertexts: Vec<[u8; OUT_CIPHERTEXT_LEN]>,
+    pub binding_sig: Signature,
+    pub spend_auth_sig: Signature,
+    pub fee: u64
+}
+
+
+impl<E: JubjubEngine> TransactionBundle<E> {
+    // Public inputs in the layout of public_values::TransferPublicValues. The fields are public,
+    // so a bundle built by hand may have a wrong number of inputs: that is an error here, as
+    // in validate().
+    pub fn public_values(&self) -> io::Result<TransferPublicValues<E::Fr>> {
+        TransferPublicValues::from_slice(&self.public_inputs)
+    }
+
+    pub fn receiver(&self) -> io::Result<E::Fr> {
+        Ok(self.public_values()?.receiver)
+    }
+
+    pub fn root_hash(&self) -> io::Result<E::Fr> {
+        Ok(self.public_values()?.root_hash)
+    }
+
+    pub fn packed_asset(&self) -> io::Result<E::Fr> {
+        Ok(self.public_values()?.packed_asset)
+    }
+
+    pub fn out_hashes(&self) -> io::Result<[E::Fr; 2]> {
+        Ok(self.public_values()?.out_hash)
+    }
+
+    pub fn nullifiers(&self) -> io::Result<[E::Fr; 2]> {
+        Ok(self.public_values()?.nf)
+    }
+
+    pub fn rk(&self, params: &E::Params) -> io::Result<PublicKey<E>> {
+        let rk = self.public_values()?.rk;
+        Ok(spend_auth::rk_from_inputs::<E>(&rk[0], &rk[1], params)?)
+    }
+
+    pub fn signing_message(&self) -> [u8; SIGHASH_LEN] {
+        sighash::signing_message(&BundleParts {
+            proof: &self.proof,
+            public_inputs: &self.public_inputs,
+            encrypted_notes: &self.encrypted_notes,
+            out_ciphertexts: &self.out_ciphertexts,
+            fee: self.fee
+        })
+    }
+
+    // Fills out_ciphertexts from the output hashes, before the bundle is signed
+    pub fn encrypt_out_amounts(&mut self, ovk: &[u8; 32], amounts: &[u64; TRANSFER_OUTPUTS]) -> io::Result<()> {
+        self.out_ciphertexts = self.out_hashes()?.iter().zip(amounts.iter())
+            .map(|(h, a)| out_amount::encrypt_amount(ovk, h, *a))
+            .collect();
+        Ok(())
+    }
+
+    // Amounts sent by the owner of ovk, None for the outputs it can not decrypt
+    pub fn recover_out_amounts(&self, ovk: &[u8; 32]) -> io::Result<Vec<Option<u64>>> {
+        Ok(self.out_hashes()?.iter().zip(self.out_ciphertexts.iter())
+            .map(|(h, ct)| out_amount::decrypt_amount(ovk, h, ct))
+            .collect())
+    }
+
+    // Notes sent by the owner of ovk with their memos, from the tails of the encrypted notes;
+    // None for the outputs of other senders and for notes sent without the tail
+    pub fn recover_sent_notes(&self, ovk: &[u8; 32], params: &E::Params) -> io::Result<Vec<Option<SentNote<E>>>> {
+        Ok(self.out_hashes()?.iter().zip(self.encrypted_notes.iter())
+            .map(|(h, n)| n.len().checked_sub(OUT_NOTE_CIPHERTEXT_LEN).and_then(|start| out_note::decrypt_note(ovk, h, &n[start..], params)))
+            .collect())
+    }
+
+    // For a spend authorization signed elsewhere, see signing_request
+    pub fn signing_request(&self, alpha: &E::Fr) -> io::Result<SigningRequest<E>> {
+        Ok(SigningRequest {
+            proof_digest: sighash::proof_digest(&self.proof),
+            public_values: self.public_values()?,
+            outputs_digest: sighash::outputs_digest(&self.encrypted_notes, &self.out_ciphertexts),
+            alpha: *alpha,
+            fee: self.fee
+        })
+    }
+
+    // The signature is set only if it is valid for rk and this bundle, so a wrong answer of the
+    // signer is an error here and not a rejected transaction
+    pub fn set_spend_auth_sig(&mut self, sig: &[u8], params: &E::Params) -> io::Result<()> {
+        if sig.len() != SIGNATURE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("signature should be {} bytes", SIGNATURE_LEN)));
+        }
+        let sig = Signature::read(sig)?;
+        if !spend_auth::verify(&self.rk(params)?, &self.signing_message(), &sig, params) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "spend authorization signature is not valid for the bundle"));
+        }
+        self.spend_auth_sig = sig;
+        Ok(())
+    }
+
+    pub fn verify_binding_sig(&self, pk: &PublicKey<E>, p_g: FixedGenerators, params: &E::Params) -> bool {
+        pk.verify(&self.signing_message(), &self.binding_sig, p_g, params)
+    }
+
+    // The key is taken from the public inputs, so the signature is bound to the proof.
+    pub fn verify_spend_auth_sig(&self, params: &E::Params) -> bool {
+        match self.rk(params) {
+            Ok(rk) => spend_auth::verify(&rk, &self.signing_message(), &self.spend_auth_sig, params),
+            Err(_) => false
+        }
+    }
+
+    // Structural checks which are cheap enough to run before the proof is verified.
+    pub fn validate(&self) -> io::Result<()> {
+        let values = self.public_values()?;
+
+        if self.encrypted_notes.len() != TRANSFER_OUTPUTS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bundle should have {} encrypted notes", TRANSFER_OUTPUTS)));
+        }
+
+        if self.encrypted_notes.iter().any(|n| n.is_empty() || n.len() > MAX_ENCRYPTED_NOTE_LEN) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong encrypted note length"));
+        }
+
+        if self.out_ciphertexts.len() != TRANSFER_OUTPUTS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bundle should have {} out ciphertexts", TRANSFER_OUTPUTS)));
+        }
+
+        if values.nf[0] == values.nf[1] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "nullifiers should be different"));
+        }
+
+        if values.out_hash.iter().any(|h| h.is_zero()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "output hash should be nonzero"));
+        }
+
+        Ok(())
+    }
+
+    pub fn verify(&self, ctx: &VerifierContext<E>) -> io::Result<bool> {
+        self.validate()?;
+        ctx.verify(&self.proof, &self.public_inputs)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(BUNDLE_VERSION)?;
+        self.proof.write(&mut writer)?;
+
+        writer.write_u32::<BigEndian>(self.public_inputs.len() as u32)?;
+        for input in self.public_inputs.iter() {
+            input.into_repr().write_be(&mut writer)?;
+        }
+
+        writer.write_u32::<BigEndian>(self.encrypted_notes.len() as u32)?;
+        for note in self.encrypted_notes.iter() {
+            writer.write_u32::<BigEndian>(note.len() as u32)?;
+            writer.write_all(note)?;
+        }
+
+        writer.write_u32::<BigEndian>(self.out_ciphertexts.len() as u32)?;
+        for ct in self.out_ciphertexts.iter() {
+            writer.write_all(ct)?;
+        }
+
+        self.binding_sig.write(&mut writer)?;
+        self.spend_auth_sig.write(&mut writer)?;
+        writer.write_u64::<BigEndian>(self.fee)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        if reader.read_u8()? != BUNDLE_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown bundle version"));
+        }
+        let proof = read_proof::<E, _>(&mut reader, true)?;
+
+        let n_inputs = reader.read_u32::<BigEndian>()? as usize;
+        if n_inputs != TRANSFER_PUBLIC_INPUTS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong number of public inputs"));
+        }
+        let mut public_inputs = Vec::with_capacity(n_inputs);
+        let mut fr_buf = [0u8; FR_LEN];
+        for _ in 0..n_inputs {
+            reader.read_exact(&mut fr_buf)?;
+            let repr = read_fr_repr_be::<E::Fr>(&fr_buf)?;
+            public_inputs.push(E::Fr::from_repr(repr).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))?);
+        }
+
+        let n_notes = reader.read_u32::<BigEndian>()? as usize;
+        if n_notes != TRANSFER_OUTPUTS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong number of encrypted notes"));
+        }
+        let mut encrypted_notes = Vec::with_capacity(n_notes);
+        for _ in 0..n_notes {
+            let len = reader.read_u32::<BigEndian>()? as usize;
+            if len > MAX_ENCRYPTED_NOTE_LEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "encrypted note is too long"));
+            }
+            let mut note = vec![0u8; len];
+            reader.read_exact(&mut note)?;
+            encrypted_notes.push(note);
+        }
+
+        let n_out = reader.read_u32::<BigEndian>()? as usize;
+        if n_out != TRANSFER_OUTPUTS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong number of out ciphertexts"));
+        }
+        let mut out_ciphertexts = Vec::with_capacity(n_out);
+        for _ in 0..n_out {
+            let mut ct = [0u8; OUT_CIPHERTEXT_LEN];
+            reader.read_exact(&mut ct)?;
+            out_ciphertexts.push(ct);
+        }
+
+        let binding_sig = Signature::read(&mut reader)?;
+        let spend_auth_sig = Signature::read(&mut reader)?;
+        let fee = reader.read_u64::<BigEndian>()?;
+
+        Ok(TransactionBundle {
+            proof,
+            public_inputs,
+            encrypted_notes,
+            out_ciphertexts,
+            binding_sig,
+            spend_auth_sig,
+            fee
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = vec![];
+        self.write(&mut res).unwrap();
+        res
+    }
+
+    // Canonical decoding: trailing bytes are rejected, so every bundle has exactly one encoding.
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut reader = data;
+        let res = Self::read(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "trailing bytes after bundle"));
+        }
+        Ok(res)
+    }
+}
+
+
+#[cfg(test)]
+mod bundle_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr, G1, G2};
+    use pairing::CurveProjective;
+    use sapling_crypto::jubjub::JubjubBls12;
+    use sapling_crypto::redjubjub::PrivateKey;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    fn rand_bundle<R: Rng>(rng: &mut R) -> TransactionBundle<Bls12> {
+        let proof = Proof::<Bls12> {
+            a: rng.gen::<G1>().into_affine(),
+            b: rng.gen::<G2>().into_affine(),
+            c: rng.gen::<G1>().into_affine()
+        };
+
+        let sig_bytes = (0..64).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+        TransactionBundle {
+            proof,
+            public_inputs: (0..TRANSFER_PUBLIC_INPUTS).map(|_| rng.gen::<Fr>()).collect(),
+            encrypted_notes: vec![vec![1u8; 100], vec![2u8; 120]],
+            out_ciphertexts: vec![rng.gen(), rng.gen()],
+            binding_sig: Signature::read(&sig_bytes[..]).unwrap(),
+            spend_auth_sig: Signature::read(&sig_bytes[..]).unwrap(),
+            fee: 100500
+        }
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let mut rng = OsRng::new().unwrap();
+        let bundle = rand_bundle(&mut rng);
+        bundle.validate().unwrap();
+
+        let data = bundle.to_bytes();
+        let decoded = TransactionBundle::<Bls12>::from_bytes(&data).unwrap();
+        assert!(decoded.to_bytes() == data, "Encoding should be canonical");
+        assert!(decoded.proof == bundle.proof && decoded.public_inputs == bundle.public_inputs);
+
+        let mut extended = data.clone();
+        extended.push(0);
+        assert!(TransactionBundle::<Bls12>::from_bytes(&extended).is_err(), "Trailing bytes should be rejected");
+        assert!(TransactionBundle::<Bls12>::from_bytes(&data[..data.len()-1]).is_err(), "Truncated bundle should be rejected");
+        assert!(TransactionBundle::<Bls12>::from_bytes(&data[1..]).is_err(), "Bundle of version 1 should be rejected");
+        let mut other = data.clone();
+        other[0] = BUNDLE_VERSION + 1;
+        assert!(TransactionBundle::<Bls12>::from_bytes(&other).is_err(), "Unknown version should be rejected");
+    }
+
+    #[test]
+    fn test_bundle_validate() {
+        let mut rng = OsRng::new().unwrap();
+
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.public_inputs[6] = bundle.public_inputs[5];
+        assert!(bundle.validate().is_err(), "Equal nullifiers should be rejected");
+
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.encrypted_notes.pop();
+        assert!(bundle.validate().is_err(), "Missing encrypted note should be rejected");
+
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.encrypted_notes[0] = vec![0u8; MAX_ENCRYPTED_NOTE_LEN + 1];
+        assert!(bundle.validate().is_err(), "Too long encrypted note should be rejected");
+
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.out_ciphertexts.pop();
+        assert!(bundle.validate().is_err(), "Missing out ciphertext should be rejected");
+    }
+
+    #[test]
+    fn test_bundle_out_amounts() {
+        let mut rng = OsRng::new().unwrap();
+        let ovk = rng.gen::<[u8; 32]>();
+
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.encrypt_out_amounts(&ovk, &[100500, 0]).unwrap();
+        let decoded = TransactionBundle::<Bls12>::from_bytes(&bundle.to_bytes()).unwrap();
+        assert!(decoded.recover_out_amounts(&ovk).unwrap() == vec![Some(100500), Some(0)]);
+        assert!(decoded.recover_out_amounts(&rng.gen::<[u8; 32]>()).unwrap() == vec![None, None], "Other ovk should recover nothing");
+    }
+
+    #[test]
+    fn test_bundle_sent_notes() {
+        let params = &*crate::pedersen_hasher::JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let ovk = rng.gen::<[u8; 32]>();
+        let note = crate::transactions::NoteData::<Bls12> { asset_id: Fr::zero(), amount: Fr::one(), native_amount: Fr::zero(), txid: rng.gen(), owner: rng.gen() };
+
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.public_inputs[3] = crate::transactions::note_hash(&note, params).unwrap();
+        let ct = out_note::encrypt_note(&ovk, &bundle.out_hashes().unwrap()[0], &SentNote { note, memo: [7; out_note::MEMO_LEN] });
+        bundle.encrypted_notes[0].extend_from_slice(&ct);
+        bundle.validate().unwrap();
+
+        let sent = bundle.recover_sent_notes(&ovk, params).unwrap();
+        assert!(sent[0].as_ref().map(|s| s.memo[0]) == Some(7) && sent[1].is_none());
+        assert!(bundle.recover_sent_notes(&rng.gen::<[u8; 32]>(), params).unwrap().iter().all(|s| s.is_none()), "Other ovk should recover nothing");
+    }
+
+    #[test]
+    fn test_bundle_binding_sig() {
+        let mut rng = OsRng::new().unwrap();
+        let params = JubjubBls12::new();
+        let p_g = FixedGenerators::SpendingKeyGenerator;
+
+        let sk = PrivateKey::<Bls12>(rng.gen());
+        let pk = PublicKey::from_private(&sk, p_g, &params);
+
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.binding_sig = sk.sign(&bundle.signing_message(), &mut rng, p_g, &params);
+        assert!(bundle.verify_binding_sig(&pk, p_g, &params), "Signature should be valid");
+
+        let decoded = TransactionBundle::<Bls12>::from_bytes(&bundle.to_bytes()).unwrap();
+        assert!(decoded.verify_binding_sig(&pk, p_g, &params), "Signature should survive encoding");
+
+        let other = rand_bundle(&mut rng);
+
+        let mut mixed = TransactionBundle::<Bls12>::from_bytes(&bundle.to_bytes()).unwrap();
+        mixed.encrypted_notes.swap(0, 1);
+        assert!(!mixed.verify_binding_sig(&pk, p_g, &params), "Reordered outputs should break the signature");
+
+        let mut mixed = TransactionBundle::<Bls12>::from_bytes(&bundle.to_bytes()).unwrap();
+        mixed.out_ciphertexts[1][0] ^= 1;
+        assert!(!mixed.verify_binding_sig(&pk, p_g, &params), "Changed out ciphertext should break the signature");
+
+        let mut mixed = TransactionBundle::<Bls12>::from_bytes(&bundle.to_bytes()).unwrap();
+        mixed.proof = other.proof;
+        assert!(!mixed.verify_binding_sig(&pk, p_g, &params), "Foreign proof should break the signature");
+
+        let mut mixed = TransactionBundle::<Bls12>::from_bytes(&bundle.to_bytes()).unwrap();
+        mixed.fee += 1;
+        assert!(!mixed.verify_binding_sig(&pk, p_g, &params), "Changed fee should break the signature");
+    }
+
+    #[test]
+    fn test_bundle_spend_auth_sig() {
+        let mut rng = OsRng::new().unwrap();
+        let params = JubjubBls12::new();
+
+        let sk = rng.gen::<Fr>();
+        let alpha = rng.gen::<Fr>();
+        let rk = spend_auth::randomized_public_key(&spend_auth::spend_auth_key::<Bls12>(&sk, &params), &alpha, &params);
+        let (x, y) = spend_auth::rk_to_inputs(&rk);
+
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.public_inputs[7] = x;
+        bundle.public_inputs[8] = y;
+        bundle.spend_auth_sig = spend_auth::sign::<Bls12, _>(&sk, &alpha, &bundle.signing_message(), &mut rng, &params);
+        assert!(bundle.verify_spend_auth_sig(&params), "Signature should be valid");
+
+        let decoded = TransactionBundle::<Bls12>::from_bytes(&bundle.to_bytes()).unwrap();
+        assert!(decoded.verify_spend_auth_sig(&params), "Signature should survive encoding");
+
+        let mut mixed = decoded;
+        mixed.fee += 1;
+        assert!(!mixed.verify_spend_auth_sig(&params), "Changed fee should break the signature");
+
+        let other = spend_auth::sign::<Bls12, _>(&rng.gen(), &alpha, &bundle.signing_message(), &mut rng, &params);
+        bundle.spend_auth_sig = other;
+        assert!(!bundle.verify_spend_auth_sig(&params), "Signature of another key should be rejected");
+    }
+
+    #[test]
+    fn test_bundle_external_signer() {
+        let mut rng = OsRng::new().unwrap();
+        let params = JubjubBls12::new();
+
+        let sk = rng.gen::<Fr>();
+        let alpha = rng.gen::<Fr>();
+        let (x, y) = spend_auth::rk_to_inputs(&spend_auth::randomized_public_key(&spend_auth::spend_auth_key::<Bls12>(&sk, &params), &alpha, &params));
+        let mut bundle = rand_bundle(&mut rng);
+        bundle.public_inputs[7] = x;
+        bundle.public_inputs[8] = y;
+
+        // Only the request bytes go to the device and only the signature comes back
+        let request = bundle.signing_request(&alpha).unwrap();
+        assert!(request.sighash() == bundle.signing_message(), "Device should recompute the message of the bundle");
+        let sig = SigningRequest::<Bls12>::from_bytes(&request.to_bytes()).unwrap().sign(&sk, &mut rng, &params).unwrap();
+        let mut sig_bytes = vec![];
+        sig.write(&mut sig_bytes).unwrap();
+
+        let mut other = TransactionBundle::<Bls12>::from_bytes(&bundle.to_bytes()).unwrap();
+        other.fee += 1;
+        assert!(other.set_spend_auth_sig(&sig_bytes, &params).is_err(), "Signature of another bundle should be rejected");
+        assert!(bundle.set_spend_auth_sig(&sig_bytes[1..], &params).is_err());
+        bundle.set_spend_auth_sig(&sig_bytes, &params).unwrap();
+        assert!(bundle.verify_spend_auth_sig(&params));
+    }
+}
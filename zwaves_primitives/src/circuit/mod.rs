@@ -1,6 +0,0 @@
-#[cfg(test)]
-pub mod test;
-
-pub mod merkle_proof;
-pub mod transactions;
-pub mod bitify;
\ No newline at end of file
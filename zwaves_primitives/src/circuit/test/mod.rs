@@ -1,2 +0,0 @@
-pub mod compress_test;
-pub mod transaction_test;
\ No newline at end of file
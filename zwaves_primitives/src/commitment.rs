@@ -0,0 +1,187 @@
+use sapling_crypto::jubjub::{JubjubEngine, JubjubParams, FixedGenerators, PrimeOrder, edwards::Point};
+use sapling_crypto::pedersen_hash::pedersen_hash;
+use pairing::PrimeField;
+use rand::Rng;
+
+use crate::fieldtools::get_bits_le_fixed;
+use crate::pedersen_hasher::{self, HASH_PERSONALIZATION, leaf_personalization};
+
+
+// Commitments to one field element. Protocol code states what it relies on with the marker
+// traits, e.g. C: Commitment<E> + Hiding<E> for an amount which must not leak, and a scheme
+// without the property does not compile in its place:
+//   Binding: no commitment opens to two values.
+//   Hiding: the commitment tells nothing of the value to whoever lacks the randomness.
+// A scheme which is not hiding is deterministic: equal values give equal commitments, and a
+// value of a small set (an amount, a flag) is found by trying them all.
+//
+// open computes the commitment of a value and randomness, verify checks an opening against a
+// commitment. The gadgets of zwaves_circuit::gadgets::commitment give the same commitments
+// in-circuit, with the randomness as its RANDOMNESS_BITS little-endian bits.
+pub trait Commitment<E: JubjubEngine> {
+    type Randomness: Clone;
+    const RANDOMNESS_BITS: usize;
+
+    fn randomness<R: Rng>(rng: &mut R) -> Self::Randomness;
+    fn randomness_bits(r: &Self::Randomness) -> Vec<bool>;
+    fn open(value: &E::Fr, r: &Self::Randomness, params: &E::Params) -> E::Fr;
+
+    fn commit<R: Rng>(value: &E::Fr, rng: &mut R, params: &E::Params) -> (E::Fr, Self::Randomness) {
+        let r = Self::randomness(rng);
+        (Self::open(value, &r, params), r)
+    }
+
+    fn verify(commitment: &E::Fr, value: &E::Fr, r: &Self::Randomness, params: &E::Params) -> bool {
+        Self::open(value, r, params) == *commitment
+    }
+}
+
+pub trait Binding<E: JubjubEngine>: Commitment<E> {}
+pub trait Hiding<E: JubjubEngine>: Commitment<E> {}
+
+
+// x(H(value) + r*G) with H the Pedersen hash of the 255 bits of the value and G the note
+// commitment randomness base, as the note commitments of Sapling. Perfectly hiding for a uniform
+// r, binding as long as the discrete logs between the generators are unknown; x is injective on
+// the prime order subgroup, so the coordinate binds as the point does. H is personalized with
+// its own leaf domain, so with r = 0 it is not HashCommitment or pedersen_hasher::hash.
+pub struct PedersenCommitment;
+
+pub const PEDERSEN_COMMITMENT_DOMAIN: usize = 1;
+
+impl<E: JubjubEngine> Commitment<E> for PedersenCommitment {
+    type Randomness = E::Fs;
+    const RANDOMNESS_BITS: usize = E::Fs::NUM_BITS as usize;
+
+    fn randomness<R: Rng>(rng: &mut R) -> E::Fs {
+        rng.gen()
+    }
+
+    fn randomness_bits(r: &E::Fs) -> Vec<bool> {
+        let repr = r.into_repr();
+        (0..<Self as Commitment<E>>::RANDOMNESS_BITS).map(|i| repr.as_ref()[i / 64] >> (i % 64) & 1 == 1).collect()
+    }
+
+    fn open(value: &E::Fr, r: &E::Fs, params: &E::Params) -> E::Fr {
+        let bits = get_bits_le_fixed(value, E::Fr::NUM_BITS as usize);
+        let h = pedersen_hash::<E, _>(leaf_personalization(PEDERSEN_COMMITMENT_DOMAIN), bits, params);
+        h.add(&params.generator(FixedGenerators::NoteCommitmentRandomness).mul(*r, params), params).into_xy().0
+    }
+}
+
+impl<E: JubjubEngine> Binding<E> for PedersenCommitment {}
+impl<E: JubjubEngine> Hiding<E> for PedersenCommitment {}
+
+
+// pedersen_hasher::hash of the value, no randomness: binding (collision resistance of the
+// Pedersen hash over a fixed length) and cheaper, but not hiding. For values which are hidden
+// anyway, e.g. already uniformly random or hashed with a secret, or which are public.
+pub struct HashCommitment;
+
+impl<E: JubjubEngine> Commitment<E> for HashCommitment {
+    type Randomness = ();
+    const RANDOMNESS_BITS: usize = 0;
+
+    fn randomness<R: Rng>(_: &mut R) {}
+
+    fn randomness_bits(_: &()) -> Vec<bool> {
+        vec![]
+    }
+
+    fn open(value: &E::Fr, _: &(), params: &E::Params) -> E::Fr {
+        pedersen_hasher::hash::<E>(value, HASH_PERSONALIZATION, params)
+    }
+}
+
+impl<E: JubjubEngine> Binding<E> for HashCommitment {}
+
+
+// v*G + r*H over two fixed generators, by default the value commitment bases of Sapling. It is
+// additively homomorphic, commitments to v1 and v2 with r1 and r2 add up to the commitment to
+// v1 + v2 with r1 + r2, so it is kept a point and is not a Commitment: balances are checked on
+// sums of them. Hiding for a uniform r, binding while the discrete log of H to G is unknown.
+// The value is a scalar of Fs, a sum of values wraps around its modulus unless every value is
+// range checked, as the 64 bit value bits of the circuit are.
+#[derive(Clone, Copy)]
+pub struct FixedBaseCommitter {
+    pub value_base: FixedGenerators,
+    pub randomness_base: FixedGenerators
+}
+
+pub const VALUE_COMMITTER: FixedBaseCommitter = FixedBaseCommitter {
+    value_base: FixedGenerators::ValueCommitmentValue,
+    randomness_base: FixedGenerators::ValueCommitmentRandomness
+};
+
+impl FixedBaseCommitter {
+    pub fn commit<E: JubjubEngine>(&self, value: &E::Fs, r: &E::Fs, params: &E::Params) -> Point<E, PrimeOrder> {
+        let v = params.generator(self.value_base).mul(*value, params);
+        v.add(&params.generator(self.randomness_base).mul(*r, params), params)
+    }
+
+    pub fn commit_u64<E: JubjubEngine>(&self, value: u64, r: &E::Fs, params: &E::Params) -> Point<E, PrimeOrder> {
+        self.commit(&E::Fs::from_repr(value.into()).unwrap(), r, params)
+    }
+
+    pub fn verify<E: JubjubEngine>(&self, cv: &Point<E, PrimeOrder>, value: &E::Fs, r: &E::Fs, params: &E::Params) -> bool {
+        self.commit(value, r, params) == *cv
+    }
+}
+
+
+#[cfg(test)]
+mod commitment_tests {
+    use super::*;
+    use pairing::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+    use sapling_crypto::jubjub::fs::Fs;
+    use rand::os::OsRng;
+
+    use crate::field;
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    // Something which relies on hiding, an amount committed to in public
+    fn commit_amount<C: Commitment<Bls12> + Hiding<Bls12>, R: Rng>(amount: u64, rng: &mut R) -> (Fr, C::Randomness) {
+        C::commit(&field::from_u64(amount), rng, &JUBJUB_PARAMS)
+    }
+
+    fn check<C: Commitment<Bls12> + Binding<Bls12>>(rng: &mut OsRng) -> (Fr, Fr) {
+        let params = &*JUBJUB_PARAMS;
+        let value = rng.gen::<Fr>();
+        let (c, r) = C::commit(&value, rng, params);
+        assert!(C::verify(&c, &value, &r, params));
+        assert!(!C::verify(&c, &rng.gen(), &r, params), "Commitment should not open to another value");
+        assert!(C::randomness_bits(&r).len() == C::RANDOMNESS_BITS);
+        (c, C::commit(&value, rng, params).0)
+    }
+
+    #[test]
+    fn test_commitments() {
+        let mut rng = OsRng::new().unwrap();
+        let (a, b) = check::<PedersenCommitment>(&mut rng);
+        assert!(a != b, "Hiding commitments of one value should differ");
+        let (a, b) = check::<HashCommitment>(&mut rng);
+        assert!(a == b);
+
+        let amount = field::from_u64(100500);
+        let (c, r) = commit_amount::<PedersenCommitment, _>(100500, &mut rng);
+        assert!(<PedersenCommitment as Commitment<Bls12>>::verify(&c, &amount, &r, &JUBJUB_PARAMS));
+        let unblinded = <PedersenCommitment as Commitment<Bls12>>::open(&amount, &Fs::zero(), &JUBJUB_PARAMS);
+        assert!(unblinded != <HashCommitment as Commitment<Bls12>>::open(&amount, &(), &JUBJUB_PARAMS), "Zero randomness should not give the hash commitment");
+    }
+
+    #[test]
+    fn test_fixed_base_committer() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let (r1, r2) = (rng.gen::<Fs>(), rng.gen::<Fs>());
+        let cv1 = VALUE_COMMITTER.commit_u64::<Bls12>(100, &r1, params);
+        let cv2 = VALUE_COMMITTER.commit_u64::<Bls12>(23, &r2, params);
+        let mut r = r1;
+        r.add_assign(&r2);
+        assert!(cv1.add(&cv2, params) == VALUE_COMMITTER.commit_u64::<Bls12>(123, &r, params), "Commitments should add up");
+        assert!(VALUE_COMMITTER.verify::<Bls12>(&cv1, &Fs::from_repr(100.into()).unwrap(), &r1, params));
+        assert!(!VALUE_COMMITTER.verify::<Bls12>(&cv1, &Fs::from_repr(101.into()).unwrap(), &r1, params));
+        assert!(VALUE_COMMITTER.commit_u64::<Bls12>(100, &r2, params) != cv1);
+    }
+}
@@ -0,0 +1,257 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::PrimeField;
+use sha2::{Sha256, Digest};
+use rand::Rng;
+
+use crate::field;
+use crate::multipack::{pack_bits_to_fields, unpack_fields_to_bits, bytes_to_bits_le};
+use crate::transactions::{condition_hash, conditioned_owner, blake2s_to_fr, NoteData};
+
+use std::io;
+
+
+// Spending conditions of programmable notes. A condition type has a unique id and commits to
+// its parameters with a single field element, params_hash. The note owner is
+// conditioned_owner(pk, condition_hash(TYPE_ID, params_hash)), the spending circuit runs the
+// matching zwaves_circuit::gadgets::condition::ConditionGadget. Id 0 is reserved for plain notes (owner = pk).
+pub struct ConditionContext {
+    pub height: u64
+}
+
+pub trait Condition<E: JubjubEngine> {
+    const TYPE_ID: u64;
+
+    fn params_hash(&self) -> E::Fr;
+    fn is_satisfied(&self, ctx: &ConditionContext) -> bool;
+
+    fn condition_hash(&self) -> E::Fr {
+        condition_hash::<E>(Self::TYPE_ID, &self.params_hash())
+    }
+
+    fn owner(&self, pk: &E::Fr) -> E::Fr {
+        conditioned_owner::<E>(pk, &self.condition_hash())
+    }
+
+    // The note with its owner pk replaced by the owner under this condition
+    fn lock_note(&self, note: &NoteData<E>) -> NoteData<E> {
+        NoteData { owner: self.owner(&note.owner), ..note.clone() }
+    }
+}
+
+
+// Spendable from the given height on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timelock {
+    pub unlock_height: u64
+}
+
+pub const TIMELOCK_TYPE_ID: u64 = 1;
+
+impl Timelock {
+    // Checked by a wallet before proving a spend at height, the circuit would not be satisfied
+    pub fn check_spendable(&self, height: u64) -> io::Result<()> {
+        if height < self.unlock_height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("note is locked until height {}", self.unlock_height)));
+        }
+        Ok(())
+    }
+}
+
+impl<E: JubjubEngine> Condition<E> for Timelock {
+    const TYPE_ID: u64 = TIMELOCK_TYPE_ID;
+
+    fn params_hash(&self) -> E::Fr {
+        field::from_u64(self.unlock_height)
+    }
+
+    fn is_satisfied(&self, ctx: &ConditionContext) -> bool {
+        ctx.height >= self.unlock_height
+    }
+}
+
+
+// Spendable by revealing the SHA-256 preimage of digest, together with the key of the owner pk.
+// The building block of atomic swaps with transparent chains: the spend publishes the preimage
+// as its inputs (preimage_inputs), so the counterparty can claim the other leg with it. Only
+// 32 byte preimages, the usual secret of HTLC scripts. Hashlock notes have no refund path, a
+// swap that needs one locks the other leg with a timelock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hashlock {
+    pub digest: [u8; 32]
+}
+
+pub const HASHLOCK_TYPE_ID: u64 = 2;
+pub const HASHLOCK_PERSONALIZATION: &[u8; 8] = b"ZWhshlck";
+pub const HASHLOCK_PREIMAGE_LEN: usize = 32;
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut res = [0u8; 32];
+    res.copy_from_slice(&Sha256::digest(data));
+    res
+}
+
+impl Hashlock {
+    pub fn from_preimage(preimage: &[u8; HASHLOCK_PREIMAGE_LEN]) -> Self {
+        Hashlock { digest: sha256(preimage) }
+    }
+
+    // A new secret and its lock, for the side that starts the swap
+    pub fn random<R: Rng>(rng: &mut R) -> ([u8; HASHLOCK_PREIMAGE_LEN], Self) {
+        let mut preimage = [0u8; HASHLOCK_PREIMAGE_LEN];
+        rng.fill_bytes(&mut preimage);
+        (preimage, Self::from_preimage(&preimage))
+    }
+
+    pub fn check_preimage(&self, preimage: &[u8]) -> io::Result<()> {
+        if preimage.len() != HASHLOCK_PREIMAGE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("preimage should be {} bytes", HASHLOCK_PREIMAGE_LEN)));
+        }
+        if sha256(preimage) != self.digest {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "preimage does not match the hashlock"));
+        }
+        Ok(())
+    }
+
+    // Public inputs revealing the preimage, bytes_to_bits_le packed into fields
+    pub fn preimage_inputs<F: PrimeField>(preimage: &[u8; HASHLOCK_PREIMAGE_LEN]) -> Vec<F> {
+        pack_bits_to_fields(&bytes_to_bits_le(preimage))
+    }
+
+    // The preimage back from the inputs of a spend, for the counterparty to claim with
+    pub fn preimage_from_inputs<F: PrimeField>(inputs: &[F]) -> io::Result<[u8; HASHLOCK_PREIMAGE_LEN]> {
+        let bits = unpack_fields_to_bits(inputs, 8 * HASHLOCK_PREIMAGE_LEN)?;
+        let mut res = [0u8; HASHLOCK_PREIMAGE_LEN];
+        for (byte, chunk) in res.iter_mut().zip(bits.chunks(8)) {
+            *byte = chunk.iter().enumerate().fold(0u8, |acc, (i, &b)| acc | ((b as u8) << i));
+        }
+        Ok(res)
+    }
+}
+
+impl<E: JubjubEngine> Condition<E> for Hashlock {
+    const TYPE_ID: u64 = HASHLOCK_TYPE_ID;
+
+    fn params_hash(&self) -> E::Fr {
+        blake2s_to_fr::<E>(HASHLOCK_PERSONALIZATION, &self.digest)
+    }
+
+    // The preimage is checked by check_preimage, it doesn't depend on the context
+    fn is_satisfied(&self, _ctx: &ConditionContext) -> bool {
+        true
+    }
+}
+
+// Known condition types, so a wallet can tell which circuit spends a note. Third-party
+// conditions are registered next to the built-in ones and may not reuse their ids.
+#[derive(Clone, Debug)]
+pub struct ConditionRegistry {
+    entries: Vec<(u64, String)>
+}
+
+impl ConditionRegistry {
+    pub fn new() -> Self {
+        ConditionRegistry { entries: vec![] }
+    }
+
+    pub fn builtin() -> Self {
+        let mut res = Self::new();
+        res.register(TIMELOCK_TYPE_ID, "timelock").unwrap();
+        res.register(HASHLOCK_TYPE_ID, "hashlock").unwrap();
+        res
+    }
+
+    pub fn register(&mut self, type_id: u64, name: &str) -> io::Result<()> {
+        if type_id == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "condition type id 0 is reserved"));
+        }
+        if let Some(other) = self.name(type_id) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("condition type id {} is taken by {}", type_id, other)));
+        }
+        self.entries.push((type_id, name.to_string()));
+        Ok(())
+    }
+
+    pub fn name(&self, type_id: u64) -> Option<&str> {
+        self.entries.iter().find(|(id, _)| *id == type_id).map(|(_, name)| name.as_str())
+    }
+
+    // Type id of a note owner, if it was built from one of the registered types
+    pub fn identify<E: JubjubEngine>(&self, owner: &E::Fr, pk: &E::Fr, params_hash: &E::Fr) -> Option<u64> {
+        self.entries.iter().map(|(id, _)| *id)
+            .find(|&id| conditioned_owner::<E>(pk, &condition_hash::<E>(id, params_hash)) == *owner)
+    }
+}
+
+impl Default for ConditionRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+
+#[cfg(test)]
+mod condition_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    #[test]
+    fn test_conditions() {
+        let mut rng = OsRng::new().unwrap();
+        let pk = rng.gen::<Fr>();
+
+        let lock = Timelock { unlock_height: 100 };
+        assert!(!Condition::<Bls12>::is_satisfied(&lock, &ConditionContext { height: 99 }));
+        assert!(Condition::<Bls12>::is_satisfied(&lock, &ConditionContext { height: 100 }));
+
+        let owner = Condition::<Bls12>::owner(&lock, &pk);
+        assert!(owner != pk);
+        assert!(owner != Condition::<Bls12>::owner(&Timelock { unlock_height: 101 }, &pk));
+        assert!(owner != Condition::<Bls12>::owner(&lock, &rng.gen::<Fr>()));
+        assert!(lock.check_spendable(99).is_err() && lock.check_spendable(100).is_ok());
+
+        let note = NoteData::<Bls12> { asset_id: rng.gen(), amount: rng.gen(), native_amount: rng.gen(), txid: rng.gen(), owner: pk };
+        let locked = lock.lock_note(&note);
+        assert!(locked.owner == owner && locked.amount == note.amount && locked.txid == note.txid);
+
+        let mut registry = ConditionRegistry::builtin();
+        assert!(registry.name(TIMELOCK_TYPE_ID) == Some("timelock"));
+        assert!(registry.identify::<Bls12>(&owner, &pk, &field::from_u64(100)) == Some(TIMELOCK_TYPE_ID));
+        assert!(registry.identify::<Bls12>(&owner, &pk, &field::from_u64(101)).is_none());
+
+        assert!(registry.register(0, "plain").is_err(), "Id 0 should be reserved");
+        assert!(registry.register(TIMELOCK_TYPE_ID, "other").is_err(), "Duplicate id should be rejected");
+        assert!(registry.register(HASHLOCK_TYPE_ID, "other").is_err(), "Duplicate id should be rejected");
+        registry.register(3, "custom").unwrap();
+        assert!(registry.name(3) == Some("custom"));
+        // the same parameters under another type give another owner
+        assert!(registry.identify::<Bls12>(&conditioned_owner::<Bls12>(&pk, &condition_hash::<Bls12>(3, &field::from_u64(100))), &pk, &field::from_u64(100)) == Some(3));
+    }
+
+    #[test]
+    fn test_hashlock() {
+        let mut rng = OsRng::new().unwrap();
+        let pk = rng.gen::<Fr>();
+
+        // sha256 of 32 zero bytes
+        assert!(hex::encode(sha256(&[0u8; 32])) == "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925");
+
+        let (preimage, lock) = Hashlock::random(&mut rng);
+        assert!(lock.check_preimage(&preimage).is_ok());
+        assert!(lock.check_preimage(&preimage[1..]).is_err());
+        let mut wrong = preimage;
+        wrong[0] ^= 1;
+        assert!(lock.check_preimage(&wrong).is_err());
+
+        let inputs = Hashlock::preimage_inputs::<Fr>(&preimage);
+        assert!(inputs.len() == 2);
+        assert!(Hashlock::preimage_from_inputs(&inputs).unwrap() == preimage);
+        assert!(Hashlock::preimage_from_inputs(&inputs[..1]).is_err());
+
+        let owner = Condition::<Bls12>::owner(&lock, &pk);
+        assert!(owner != Condition::<Bls12>::owner(&Hashlock::from_preimage(&wrong), &pk));
+        let params_hash = Condition::<Bls12>::params_hash(&lock);
+        assert!(ConditionRegistry::builtin().identify::<Bls12>(&owner, &pk, &params_hash) == Some(HASHLOCK_TYPE_ID));
+    }
+}
@@ -0,0 +1,227 @@
+use pairing::bls12_381::{Bls12, Fr, G1Affine, G2Affine};
+use pairing::PrimeField;
+use bellman::groth16::Proof;
+
+use std::fmt;
+
+use crate::point::read_point;
+use crate::serialization::read_fr_repr_be;
+use crate::verifier::{TruncatedVerifyingKey, verify_proof, pack_inputs, PROOF_LEN, RIDE_INPUT_LEN, RIDE_VK_CHUNK_LEN};
+
+
+// Why groth16Verify says false, for integrators: the verification of the contract is redone
+// step by step on the same bytes, and the first step that diverges is reported.
+//   Encoding  the bytes do not parse: lengths, points, inputs above the modulus
+//   Key       the key is not the expected one: another circuit (input count) or another setup
+//   Inputs    the packed inputs are not the expected values, with the first wrong one
+//   Pairing   everything is well formed and as expected, the proof itself is wrong
+// The expected key and inputs are optional, without them the Key and Inputs steps are skipped.
+// Diagnosis is slower than verification and is meant for debugging, not for the hot path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyStage {
+    Encoding,
+    Key,
+    Inputs,
+    Pairing
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifyDiagnosis {
+    // None when the proof verifies
+    pub stage: Option<VerifyStage>,
+    pub reason: String
+}
+
+impl fmt::Display for VerifyDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.stage {
+            Some(stage) => write!(f, "{:?}: {}", stage, self.reason),
+            None => write!(f, "{}", self.reason)
+        }
+    }
+}
+
+fn fail<T, S: Into<String>>(stage: VerifyStage, reason: S) -> Result<T, VerifyDiagnosis> {
+    Err(VerifyDiagnosis { stage: Some(stage), reason: reason.into() })
+}
+
+
+const VK_G2_LEN: usize = 2 * RIDE_VK_CHUNK_LEN;
+
+fn g1(data: &[u8], name: &str) -> Result<G1Affine, VerifyDiagnosis> {
+    read_point::<G1Affine, _>(data, true).or_else(|e| fail(VerifyStage::Encoding, format!("{}: {}", name, e)))
+}
+
+fn g2(data: &[u8], name: &str) -> Result<G2Affine, VerifyDiagnosis> {
+    read_point::<G2Affine, _>(data, true).or_else(|e| fail(VerifyStage::Encoding, format!("{}: {}", name, e)))
+}
+
+fn read_vk(vk: &[u8]) -> Result<TruncatedVerifyingKey<Bls12>, VerifyDiagnosis> {
+    let ic_start = RIDE_VK_CHUNK_LEN + 3 * VK_G2_LEN;
+    Ok(TruncatedVerifyingKey {
+        alpha_g1: g1(&vk[..RIDE_VK_CHUNK_LEN], "vk alpha")?,
+        beta_g2: g2(&vk[RIDE_VK_CHUNK_LEN..], "vk beta")?,
+        gamma_g2: g2(&vk[RIDE_VK_CHUNK_LEN + VK_G2_LEN..], "vk gamma")?,
+        delta_g2: g2(&vk[RIDE_VK_CHUNK_LEN + 2 * VK_G2_LEN..], "vk delta")?,
+        ic: vk[ic_start..].chunks(RIDE_VK_CHUNK_LEN).enumerate()
+            .map(|(i, c)| g1(c, &format!("vk ic[{}]", i)))
+            .collect::<Result<Vec<_>, _>>()?
+    })
+}
+
+fn read_input(data: &[u8]) -> Option<Fr> {
+    read_fr_repr_be::<Fr>(data).ok().and_then(|r| Fr::from_repr(r).ok())
+}
+
+fn little_endian(data: &[u8]) -> Option<Fr> {
+    read_input(&data.iter().rev().cloned().collect::<Vec<_>>())
+}
+
+fn encoding(vk: &[u8], proof: &[u8], inputs: &[u8]) -> Result<(TruncatedVerifyingKey<Bls12>, Proof<Bls12>, Vec<Fr>), VerifyDiagnosis> {
+    let min_vk = RIDE_VK_CHUNK_LEN + 3 * VK_G2_LEN + RIDE_VK_CHUNK_LEN;
+    if vk.len() < min_vk || vk.len() % RIDE_VK_CHUNK_LEN != 0 {
+        return fail(VerifyStage::Encoding, format!("vk is {} bytes, it should be 384 + 48 per input", vk.len()));
+    }
+    if inputs.len() % RIDE_INPUT_LEN != 0 {
+        return fail(VerifyStage::Encoding, format!("inputs are {} bytes, not a multiple of {}", inputs.len(), RIDE_INPUT_LEN));
+    }
+    if proof.len() != PROOF_LEN {
+        return fail(VerifyStage::Encoding, format!("proof is {} bytes, {} expected", proof.len(), PROOF_LEN));
+    }
+    let vk_inputs = (vk.len() - min_vk) / RIDE_VK_CHUNK_LEN;
+    if vk_inputs != inputs.len() / RIDE_INPUT_LEN {
+        return fail(VerifyStage::Encoding, format!("vk takes {} inputs, {} given", vk_inputs, inputs.len() / RIDE_INPUT_LEN));
+    }
+
+    let tvk = read_vk(vk)?;
+    let proof = Proof {
+        a: g1(&proof[..48], "proof a")?,
+        b: g2(&proof[48..144], "proof b")?,
+        c: g1(&proof[144..], "proof c")?
+    };
+    let inputs = inputs.chunks(RIDE_INPUT_LEN).enumerate().map(|(i, c)| match read_input(c) {
+        Some(x) => Ok(x),
+        None if little_endian(c).is_some() => fail(VerifyStage::Encoding, format!("input {} is above the modulus, it looks little-endian", i)),
+        None => fail(VerifyStage::Encoding, format!("input {} is above the modulus", i))
+    }).collect::<Result<Vec<_>, _>>()?;
+    Ok((tvk, proof, inputs))
+}
+
+fn key(tvk: &TruncatedVerifyingKey<Bls12>, expected: &TruncatedVerifyingKey<Bls12>) -> Result<(), VerifyDiagnosis> {
+    if tvk.ic.len() != expected.ic.len() {
+        return fail(VerifyStage::Key, format!("vk is of another circuit: {} inputs, {} expected", tvk.ic.len() - 1, expected.ic.len() - 1));
+    }
+    let g2s = [("beta", &tvk.beta_g2, &expected.beta_g2), ("gamma", &tvk.gamma_g2, &expected.gamma_g2), ("delta", &tvk.delta_g2, &expected.delta_g2)];
+    let differs = if tvk.alpha_g1 != expected.alpha_g1 {
+        Some("alpha".to_string())
+    } else if let Some((name, _, _)) = g2s.iter().find(|(_, a, b)| a != b) {
+        Some(name.to_string())
+    } else {
+        tvk.ic.iter().zip(expected.ic.iter()).position(|(a, b)| a != b).map(|i| format!("ic[{}]", i))
+    };
+    match differs {
+        Some(name) => fail(VerifyStage::Key, format!("vk is of another setup or circuit version, {} differs", name)),
+        None => Ok(())
+    }
+}
+
+fn packing(data: &[u8], inputs: &[Fr], expected: &[Fr]) -> Result<(), VerifyDiagnosis> {
+    if inputs.len() != expected.len() {
+        return fail(VerifyStage::Inputs, format!("{} inputs given, {} expected", inputs.len(), expected.len()));
+    }
+    let packed = pack_inputs(expected);
+    let i = match inputs.iter().zip(expected.iter()).position(|(a, b)| a != b) {
+        Some(i) => i,
+        None => return Ok(())
+    };
+    let chunk = &data[i * RIDE_INPUT_LEN..(i + 1) * RIDE_INPUT_LEN];
+    let reason = if little_endian(chunk) == Some(expected[i]) {
+        format!("input {} is little-endian, groth16Verify takes 32 bytes big-endian", i)
+    } else if let Some(j) = expected.iter().position(|x| *x == inputs[i]) {
+        format!("input {} is expected input {}, the inputs are out of order", i, j)
+    } else {
+        format!("input {} is {}, {} expected", i, hex(chunk), hex(&packed[i * RIDE_INPUT_LEN..(i + 1) * RIDE_INPUT_LEN]))
+    };
+    fail(VerifyStage::Inputs, reason)
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+
+// Bytes as groth16Verify of Ride takes them, see verifier::groth16_verify_bytes
+pub fn diagnose(vk: &[u8], proof: &[u8], inputs: &[u8], expected_vk: Option<&TruncatedVerifyingKey<Bls12>>, expected_inputs: Option<&[Fr]>) -> VerifyDiagnosis {
+    let res = encoding(vk, proof, inputs).and_then(|(tvk, proof, values)| {
+        if let Some(expected) = expected_vk {
+            key(&tvk, expected)?;
+        }
+        if let Some(expected) = expected_inputs {
+            packing(inputs, &values, expected)?;
+        }
+        match verify_proof(&tvk, &proof, &values) {
+            Ok(true) => Ok(()),
+            _ => fail(VerifyStage::Pairing, "pairing check fails, the proof is not a proof of these inputs under this vk")
+        }
+    });
+    match res {
+        Ok(()) => VerifyDiagnosis { stage: None, reason: "proof verifies".to_string() },
+        Err(d) => d
+    }
+}
+
+
+#[cfg(test)]
+mod diagnose_tests {
+    use super::*;
+    use bellman::groth16::{generate_random_parameters, create_random_proof};
+    use pairing::{Field, CurveAffine};
+    use rand::{Rng, XorShiftRng, SeedableRng};
+
+    use crate::verifier::truncate_verifying_key;
+    use crate::test_circuits::Mul;
+
+    fn bytes(tvk: &TruncatedVerifyingKey<Bls12>) -> Vec<u8> {
+        let mut res = vec![];
+        tvk.write(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let mut rng = XorShiftRng::from_seed([0x6dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap();
+        let tvk = truncate_verifying_key(&params.vk);
+        let (x, y) = (Fr::from_str("5").unwrap(), rng.gen::<Fr>());
+        let mut z = x;
+        z.mul_assign(&y);
+        let mut proof = vec![];
+        create_random_proof(Mul(Some((x, y))), &params, &mut rng).unwrap().write(&mut proof).unwrap();
+
+        let vk = bytes(&tvk);
+        let inputs = pack_inputs(&[x, z]);
+        let stage = |vk: &[u8], proof: &[u8], inputs: &[u8]| diagnose(vk, proof, inputs, Some(&tvk), Some(&[x, z])).stage;
+        assert!(diagnose(&vk, &proof, &inputs, Some(&tvk), Some(&[x, z])).stage.is_none());
+
+        assert!(stage(&vk[..vk.len() - 48], &proof, &inputs) == Some(VerifyStage::Encoding));
+        let swapped = pack_inputs(&[z, x]);
+        let d = diagnose(&vk, &proof, &swapped, Some(&tvk), Some(&[x, z]));
+        assert!(d.reason == "input 0 is expected input 1, the inputs are out of order", "Swapped inputs should be named");
+        assert!(diagnose(&vk, &proof, &swapped, None, None).stage == Some(VerifyStage::Pairing));
+
+        let mut le = inputs.clone();
+        le[..32].reverse();
+        let d = diagnose(&vk, &proof, &le, Some(&tvk), Some(&[x, z]));
+        assert!(d.reason == "input 0 is little-endian, groth16Verify takes 32 bytes big-endian");
+
+        let other = truncate_verifying_key(&generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap().vk);
+        let d = diagnose(&bytes(&other), &proof, &inputs, Some(&tvk), None);
+        assert!(d.to_string() == "Key: vk is of another setup or circuit version, alpha differs");
+
+        let mut wrong = proof.clone();
+        wrong[..48].copy_from_slice(G1Affine::one().into_compressed().as_ref());
+        assert!(stage(&vk, &wrong, &inputs) == Some(VerifyStage::Pairing), "Wrong proof should fail only at the pairing");
+        wrong[0] ^= 0x40;
+        assert!(stage(&vk, &wrong, &inputs) == Some(VerifyStage::Encoding));
+    }
+}
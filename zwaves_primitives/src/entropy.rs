@@ -0,0 +1,148 @@
+use rand::{ChaChaRng, SeedableRng, Rng};
+use rand::os::OsRng;
+use blake2_rfc::blake2s::Blake2s;
+
+use std::io;
+
+
+// Where proving and signing randomness comes from. Some embedded wasm hosts expose no system
+// entropy at all, there the caller passes its own seed. SeededEntropy is only as good as the
+// seed and repeats itself for a repeated seed; MixedEntropy xors a seeded stream with system
+// bytes, so the output is uniform if either of them is.
+pub const ENTROPY_SEED_PERSONALIZATION: &[u8; 8] = b"ZWentrpy";
+
+
+pub trait EntropySource {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> io::Result<()>;
+
+    // ChaCha keyed with 32 bytes of the source, for the APIs taking R: Rng
+    fn rng(&mut self) -> io::Result<ChaChaRng> {
+        let mut key = [0u8; 32];
+        self.fill_bytes(&mut key)?;
+        Ok(chacha_from_bytes(&key))
+    }
+}
+
+fn chacha_from_bytes(key: &[u8; 32]) -> ChaChaRng {
+    let mut words = [0u32; 8];
+    for (w, c) in words.iter_mut().zip(key.chunks(4)) {
+        *w = u32::from(c[0]) | u32::from(c[1]) << 8 | u32::from(c[2]) << 16 | u32::from(c[3]) << 24;
+    }
+    ChaChaRng::from_seed(&words)
+}
+
+
+// getrandom and friends, through OsRng. Fails where the host has no entropy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemEntropy;
+
+impl EntropySource for SystemEntropy {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> io::Result<()> {
+        OsRng::new()?.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+
+pub struct SeededEntropy {
+    rng: ChaChaRng
+}
+
+impl SeededEntropy {
+    // The seed is hashed, so any length works, but it should carry at least 32 bytes of entropy
+    pub fn new(seed: &[u8]) -> Self {
+        let mut h = Blake2s::with_params(32, &[], &[], ENTROPY_SEED_PERSONALIZATION);
+        h.update(seed);
+        let mut key = [0u8; 32];
+        key.copy_from_slice(h.finalize().as_bytes());
+        SeededEntropy { rng: chacha_from_bytes(&key) }
+    }
+}
+
+impl EntropySource for SeededEntropy {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> io::Result<()> {
+        self.rng.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+
+pub struct MixedEntropy<S: EntropySource = SystemEntropy> {
+    seeded: SeededEntropy,
+    system: S
+}
+
+impl MixedEntropy<SystemEntropy> {
+    pub fn new(seed: &[u8]) -> Self {
+        Self::with_source(seed, SystemEntropy)
+    }
+}
+
+impl<S: EntropySource> MixedEntropy<S> {
+    pub fn with_source(seed: &[u8], system: S) -> Self {
+        MixedEntropy { seeded: SeededEntropy::new(seed), system }
+    }
+}
+
+impl<S: EntropySource> EntropySource for MixedEntropy<S> {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> io::Result<()> {
+        let mut buf = vec![0u8; dest.len()];
+        self.system.fill_bytes(&mut buf)?;
+        self.seeded.fill_bytes(dest)?;
+        for (d, b) in dest.iter_mut().zip(buf.iter()) {
+            *d ^= b;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod entropy_tests {
+    use super::*;
+
+    struct Broken;
+
+    impl EntropySource for Broken {
+        fn fill_bytes(&mut self, _: &mut [u8]) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::Other, "no entropy"))
+        }
+    }
+
+    struct Zero;
+
+    impl EntropySource for Zero {
+        fn fill_bytes(&mut self, dest: &mut [u8]) -> io::Result<()> {
+            dest.iter_mut().for_each(|b| *b = 0);
+            Ok(())
+        }
+    }
+
+    fn bytes<S: EntropySource>(s: &mut S) -> [u8; 64] {
+        let mut res = [0u8; 64];
+        s.fill_bytes(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_entropy_sources() {
+        let mut a = SeededEntropy::new(b"seed");
+        let first = bytes(&mut a);
+        assert!(first[..] == bytes(&mut SeededEntropy::new(b"seed"))[..], "Seeded entropy should be deterministic");
+        assert!(first[..] != bytes(&mut a)[..], "Seeded stream should not repeat");
+        assert!(first[..] != bytes(&mut SeededEntropy::new(b"seeD"))[..]);
+
+        // mixing with zeros gives the seeded stream, so the seed goes into the output
+        assert!(bytes(&mut MixedEntropy::with_source(b"seed", Zero))[..] == first[..]);
+        assert!(bytes(&mut MixedEntropy::new(b"seed"))[..] != first[..]);
+        assert!(bytes(&mut MixedEntropy::new(b"seed"))[..] != bytes(&mut MixedEntropy::new(b"seed"))[..], "Mixed entropy should take system bytes");
+        assert!(bytes(&mut SystemEntropy)[..] != bytes(&mut SystemEntropy)[..]);
+
+        let mut broken = MixedEntropy::with_source(b"seed", Broken);
+        assert!(broken.fill_bytes(&mut [0u8; 32]).is_err(), "Missing system entropy should not be replaced silently");
+        assert!(broken.rng().is_err());
+
+        let x = SeededEntropy::new(b"seed").rng().unwrap().gen::<[u64; 4]>();
+        assert!(x == SeededEntropy::new(b"seed").rng().unwrap().gen::<[u64; 4]>());
+    }
+}
@@ -0,0 +1,320 @@
+use pairing::Engine;
+use pairing::bls12_381::{Bls12, Fr};
+use bellman::groth16::{Proof, Parameters};
+
+use blake2_rfc::blake2s::Blake2s;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use std::io::{self, Read, Write};
+
+use crate::verifier::{TruncatedVerifyingKey, PreparedTruncatedVerifyingKey};
+use crate::point::read_proof;
+use crate::nullifier_set::{NullifierSet, NullifierBackend};
+use crate::tree_delta::{TreeDelta, WitnessTracker};
+use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+
+// Layout: magic (4) | type (1) | version (u16 BE) | payload length (u32 BE) | payload | blake2s checksum (32)
+// The checksum covers everything before it.
+pub const ENVELOPE_MAGIC: [u8; 4] = *b"ZWVS";
+pub const ENVELOPE_VERSION: u16 = 1;
+pub const ENVELOPE_HEADER_LEN: usize = 11;
+pub const ENVELOPE_CHECKSUM_LEN: usize = 32;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArtifactType {
+    Proof,
+    VerifyingKey,
+    Parameters,
+    TreeSnapshot,
+    Wallet,
+    PreparedVerifyingKey,
+    NullifierSet
+}
+
+impl ArtifactType {
+    pub fn tag(&self) -> u8 {
+        match *self {
+            ArtifactType::Proof => 1,
+            ArtifactType::VerifyingKey => 2,
+            ArtifactType::Parameters => 3,
+            ArtifactType::TreeSnapshot => 4,
+            ArtifactType::Wallet => 5,
+            ArtifactType::PreparedVerifyingKey => 6,
+            ArtifactType::NullifierSet => 7
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(ArtifactType::Proof),
+            2 => Some(ArtifactType::VerifyingKey),
+            3 => Some(ArtifactType::Parameters),
+            4 => Some(ArtifactType::TreeSnapshot),
+            5 => Some(ArtifactType::Wallet),
+            6 => Some(ArtifactType::PreparedVerifyingKey),
+            7 => Some(ArtifactType::NullifierSet),
+            _ => None
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    pub kind: ArtifactType,
+    pub version: u16,
+    pub payload: Vec<u8>
+}
+
+
+fn checksum(data: &[u8]) -> [u8; ENVELOPE_CHECKSUM_LEN] {
+    let mut h = Blake2s::new(ENVELOPE_CHECKSUM_LEN);
+    h.update(data);
+    let mut res = [0u8; ENVELOPE_CHECKSUM_LEN];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+
+fn check_payload_len(len: usize) -> io::Result<()> {
+    if len as u64 > u32::max_value() as u64 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "envelope payload is too big"));
+    }
+    Ok(())
+}
+
+
+impl Envelope {
+    pub fn new(kind: ArtifactType, payload: Vec<u8>) -> io::Result<Self> {
+        check_payload_len(payload.len())?;
+        Ok(Envelope {
+            kind,
+            version: ENVELOPE_VERSION,
+            payload
+        })
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        check_payload_len(self.payload.len())?;
+        let mut res = Vec::with_capacity(ENVELOPE_HEADER_LEN + self.payload.len() + ENVELOPE_CHECKSUM_LEN);
+        res.extend_from_slice(&ENVELOPE_MAGIC);
+        res.push(self.kind.tag());
+        res.write_u16::<BigEndian>(self.version).unwrap();
+        res.write_u32::<BigEndian>(self.payload.len() as u32).unwrap();
+        res.extend_from_slice(&self.payload);
+        let sum = checksum(&res);
+        res.extend_from_slice(&sum);
+        Ok(res)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes()?)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Self::parse(&data)
+    }
+
+    // The only entry point for reading artifacts: every failure carries its reason.
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < ENVELOPE_HEADER_LEN + ENVELOPE_CHECKSUM_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "envelope is too short"));
+        }
+
+        if data[0..4] != ENVELOPE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a zwaves envelope: wrong magic"));
+        }
+
+        let mut header = &data[4..ENVELOPE_HEADER_LEN];
+        let tag = header.read_u8()?;
+        let version = header.read_u16::<BigEndian>()?;
+        let payload_len = header.read_u32::<BigEndian>()?;
+
+        let kind = ArtifactType::from_tag(tag)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unknown envelope type {}", tag)))?;
+
+        if version == 0 || version > ENVELOPE_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported envelope version {}", version)));
+        }
+
+        // the length is untrusted and usize is 32 bits on wasm32
+        let body_len = (payload_len as usize).checked_add(ENVELOPE_HEADER_LEN)
+            .filter(|n| n.checked_add(ENVELOPE_CHECKSUM_LEN) == Some(data.len()))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "envelope length does not match its header"))?;
+
+        if checksum(&data[..body_len])[..] != data[body_len..] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "envelope checksum mismatch"));
+        }
+
+        Ok(Envelope {
+            kind,
+            version,
+            payload: data[ENVELOPE_HEADER_LEN..body_len].to_vec()
+        })
+    }
+
+    pub fn into_payload(self, kind: ArtifactType) -> io::Result<Vec<u8>> {
+        if self.kind != kind {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("expected {:?} envelope, got {:?}", kind, self.kind)));
+        }
+        Ok(self.payload)
+    }
+}
+
+
+pub fn wrap_proof<E: Engine>(proof: &Proof<E>) -> io::Result<Envelope> {
+    let mut payload = vec![];
+    proof.write(&mut payload)?;
+    Envelope::new(ArtifactType::Proof, payload)
+}
+
+pub fn unwrap_proof<E: Engine>(data: &[u8]) -> io::Result<Proof<E>> {
+    let payload = Envelope::parse(data)?.into_payload(ArtifactType::Proof)?;
+    read_proof::<E, _>(payload.as_slice(), true)
+}
+
+pub fn wrap_verifying_key<E: Engine>(tvk: &TruncatedVerifyingKey<E>) -> io::Result<Envelope> {
+    let mut payload = vec![];
+    tvk.write(&mut payload)?;
+    Envelope::new(ArtifactType::VerifyingKey, payload)
+}
+
+pub fn unwrap_verifying_key<E: Engine>(data: &[u8]) -> io::Result<TruncatedVerifyingKey<E>> {
+    let payload = Envelope::parse(data)?.into_payload(ArtifactType::VerifyingKey)?;
+    TruncatedVerifyingKey::<E>::read(payload.as_slice())
+}
+
+pub fn wrap_parameters<E: Engine>(params: &Parameters<E>) -> io::Result<Envelope> {
+    let mut payload = vec![];
+    params.write(&mut payload)?;
+    Envelope::new(ArtifactType::Parameters, payload)
+}
+
+// Points are checked on read: parameters may come from an untrusted mirror.
+pub fn unwrap_parameters<E: Engine>(data: &[u8]) -> io::Result<Parameters<E>> {
+    let payload = Envelope::parse(data)?.into_payload(ArtifactType::Parameters)?;
+    Parameters::<E>::read(payload.as_slice(), true)
+}
+
+pub fn wrap_prepared_verifying_key(pvk: &PreparedTruncatedVerifyingKey<Bls12>) -> io::Result<Envelope> {
+    let mut payload = vec![];
+    pvk.write(&mut payload)?;
+    Envelope::new(ArtifactType::PreparedVerifyingKey, payload)
+}
+
+pub fn unwrap_prepared_verifying_key(data: &[u8]) -> io::Result<PreparedTruncatedVerifyingKey<Bls12>> {
+    let payload = Envelope::parse(data)?.into_payload(ArtifactType::PreparedVerifyingKey)?;
+    let mut reader = payload.as_slice();
+    let res = PreparedTruncatedVerifyingKey::<Bls12>::read(&mut reader)?;
+    if !reader.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "trailing bytes after prepared key"));
+    }
+    Ok(res)
+}
+
+pub fn wrap_nullifier_set<B: NullifierBackend>(set: &NullifierSet<B>) -> io::Result<Envelope> {
+    let mut payload = vec![];
+    set.write_snapshot(&mut payload)?;
+    Envelope::new(ArtifactType::NullifierSet, payload)
+}
+
+pub fn unwrap_nullifier_set<B: NullifierBackend>(data: &[u8], backend: B) -> io::Result<NullifierSet<B>> {
+    let payload = Envelope::parse(data)?.into_payload(ArtifactType::NullifierSet)?;
+    let mut reader = payload.as_slice();
+    let res = NullifierSet::read_snapshot::<Fr, _>(&mut reader, backend)?;
+    if !reader.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "trailing bytes after nullifier set"));
+    }
+    Ok(res)
+}
+
+// A tree snapshot is the TreeDelta a wallet starts from: the frontier, with the leaves since start
+pub fn wrap_tree_snapshot(delta: &TreeDelta<Bls12>) -> io::Result<Envelope> {
+    Envelope::new(ArtifactType::TreeSnapshot, delta.to_bytes())
+}
+
+pub fn unwrap_tree_snapshot(data: &[u8]) -> io::Result<TreeDelta<Bls12>> {
+    let payload = Envelope::parse(data)?.into_payload(ArtifactType::TreeSnapshot)?;
+    let mut reader = payload.as_slice();
+    let res = TreeDelta::<Bls12>::read(&mut reader)?;
+    if !reader.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "trailing bytes after tree snapshot"));
+    }
+    Ok(res)
+}
+
+// The wallet file is the tracker of the paths of the wallet's notes
+pub fn wrap_wallet(tracker: &WitnessTracker<Bls12>) -> io::Result<Envelope> {
+    let mut payload = vec![];
+    tracker.write(&mut payload)?;
+    Envelope::new(ArtifactType::Wallet, payload)
+}
+
+pub fn unwrap_wallet(data: &[u8]) -> io::Result<WitnessTracker<Bls12>> {
+    let payload = Envelope::parse(data)?.into_payload(ArtifactType::Wallet)?;
+    let mut reader = payload.as_slice();
+    let res = WitnessTracker::<Bls12>::read(&mut reader, &JUBJUB_PARAMS)?;
+    if !reader.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "trailing bytes after wallet"));
+    }
+    Ok(res)
+}
+
+
+#[cfg(test)]
+mod envelope_tests {
+    use super::*;
+    use pairing::Field;
+    use crate::tree::MerkleTree;
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let e = Envelope::new(ArtifactType::TreeSnapshot, vec![1, 2, 3, 4, 5]).unwrap();
+        let data = e.to_bytes().unwrap();
+        assert!(Envelope::parse(&data).unwrap() == e, "Envelope should survive roundtrip");
+        assert!(Envelope::parse(&data).unwrap().into_payload(ArtifactType::Proof).is_err(), "Type mismatch should be reported");
+    }
+
+    #[test]
+    fn test_wallet_envelopes() {
+        let params = &*JUBJUB_PARAMS;
+        let mut tree = MerkleTree::<Bls12>::new(8, params);
+        tree.push_many(&[Fr::one(), Fr::zero(), Fr::one()], params).unwrap();
+        let snapshot = TreeDelta::from_tree(&tree, 1).unwrap();
+        let data = wrap_tree_snapshot(&snapshot).unwrap().to_bytes().unwrap();
+        assert!(unwrap_tree_snapshot(&data).unwrap().to_bytes() == snapshot.to_bytes());
+        assert!(unwrap_wallet(&data).is_err(), "Snapshot should not be read as a wallet");
+
+        let mut wallet = WitnessTracker::<Bls12>::new(8, params);
+        wallet.apply(&TreeDelta::from_tree(&tree, 0).unwrap(), &[2], params).unwrap();
+        let restored = unwrap_wallet(&wrap_wallet(&wallet).unwrap().to_bytes().unwrap()).unwrap();
+        assert!(restored.root() == tree.root() && restored.witness(2) == wallet.witness(2));
+    }
+
+    #[test]
+    fn test_envelope_corruption() {
+        let data = Envelope::new(ArtifactType::Wallet, vec![7; 100]).unwrap().to_bytes().unwrap();
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] ^= 1;
+        assert!(Envelope::parse(&bad_magic).is_err());
+
+        let mut bad_payload = data.clone();
+        bad_payload[ENVELOPE_HEADER_LEN + 10] ^= 1;
+        assert!(Envelope::parse(&bad_payload).is_err());
+
+        let mut bad_type = data.clone();
+        bad_type[4] = 0xff;
+        assert!(Envelope::parse(&bad_type).is_err());
+
+        assert!(Envelope::parse(&data[..data.len() - 1]).is_err());
+
+        let mut huge = data.clone();
+        huge[7..11].copy_from_slice(&[0xff; 4]);
+        assert!(Envelope::parse(&huge).unwrap_err().kind() == io::ErrorKind::InvalidData, "Untrusted length should not overflow");
+    }
+}
@@ -0,0 +1,259 @@
+use pairing::{Field, PrimeField, PrimeFieldRepr, SqrtField, LegendreSymbol};
+use num::BigUint;
+
+use std::io;
+
+
+// Checked conversions between integers and field elements. Conversions from the field
+// return None when the value does not fit.
+
+pub fn from_u64<F: PrimeField>(x: u64) -> F {
+    F::from_repr(F::Repr::from(x)).unwrap()
+}
+
+pub fn from_u128<F: PrimeField>(x: u128) -> F {
+    let mut repr = F::Repr::default();
+    repr.as_mut()[0] = x as u64;
+    repr.as_mut()[1] = (x >> 64) as u64;
+    F::from_repr(repr).unwrap()
+}
+
+pub fn from_i128<F: PrimeField>(x: i128) -> F {
+    let mut res = from_u128::<F>(x.unsigned_abs());
+    if x < 0 {
+        res.negate();
+    }
+    res
+}
+
+pub fn to_u64<F: PrimeField>(x: &F) -> Option<u64> {
+    let repr = x.into_repr();
+    if repr.as_ref()[1..].iter().any(|&l| l != 0) {
+        return None;
+    }
+    Some(repr.as_ref()[0])
+}
+
+pub fn to_u128<F: PrimeField>(x: &F) -> Option<u128> {
+    let repr = x.into_repr();
+    if repr.as_ref()[2..].iter().any(|&l| l != 0) {
+        return None;
+    }
+    Some(repr.as_ref()[0] as u128 | (repr.as_ref()[1] as u128) << 64)
+}
+
+// Elements above p/2 are read as negative, like the signed amounts in packed assets.
+pub fn to_i128<F: PrimeField>(x: &F) -> Option<i128> {
+    if let Some(v) = to_u128(x) {
+        if v <= i128::max_value() as u128 {
+            return Some(v as i128);
+        }
+    }
+
+    let mut neg = *x;
+    neg.negate();
+    match to_u128(&neg) {
+        Some(v) if v <= 1u128 << 127 => Some((v as i128).wrapping_neg()),
+        _ => None
+    }
+}
+
+pub fn from_biguint<F: PrimeField>(x: &BigUint) -> Option<F> {
+    let bytes = x.to_bytes_le();
+    let mut buf = vec![0u8; F::Repr::default().as_ref().len() * 8];
+    if bytes.len() > buf.len() {
+        return None;
+    }
+    buf[..bytes.len()].copy_from_slice(&bytes);
+
+    let mut repr = F::Repr::default();
+    repr.read_le(&buf[..]).ok()?;
+    F::from_repr(repr).ok()
+}
+
+pub fn to_biguint<F: PrimeField>(x: &F) -> BigUint {
+    let mut buf = vec![];
+    x.into_repr().write_le(&mut buf).unwrap();
+    BigUint::from_bytes_le(&buf)
+}
+
+
+pub fn pow<F: Field>(x: &F, exp: u64) -> F {
+    x.pow([exp])
+}
+
+// Montgomery's trick: a single inversion for the whole slice. Zeros are left as they are.
+pub fn batch_inverse<F: Field>(v: &mut [F]) {
+    let mut prod = Vec::with_capacity(v.len());
+    let mut acc = F::one();
+    for e in v.iter().filter(|e| !e.is_zero()) {
+        prod.push(acc);
+        acc.mul_assign(e);
+    }
+
+    // acc is a product of nonzero elements
+    let mut acc = acc.inverse().unwrap();
+
+    for (e, p) in v.iter_mut().rev().filter(|e| !e.is_zero()).zip(prod.into_iter().rev()) {
+        let mut inv = acc;
+        inv.mul_assign(&p);
+        acc.mul_assign(e);
+        *e = inv;
+    }
+}
+
+
+// -1, 0 or 1
+pub fn legendre<F: SqrtField>(x: &F) -> i8 {
+    match x.legendre() {
+        LegendreSymbol::Zero => 0,
+        LegendreSymbol::QuadraticResidue => 1,
+        LegendreSymbol::QuadraticNonResidue => -1
+    }
+}
+
+pub fn is_square<F: SqrtField>(x: &F) -> bool {
+    legendre(x) >= 0
+}
+
+// Of the two roots returns the one with the smaller representation, so the result
+// does not depend on the sqrt algorithm.
+pub fn sqrt<F: PrimeField + SqrtField>(x: &F) -> Option<F> {
+    let a = x.sqrt()?;
+    let mut b = a;
+    b.negate();
+    Some(if a.into_repr() <= b.into_repr() { a } else { b })
+}
+
+
+// Fixed-size big-endian encoding, the byte order of serialization::read_fr_vec. Decoding
+// rejects values >= modulus with InvalidData instead of reducing them: a reduced x + p would
+// decode to the same element as x, so two encodings of one value would both be accepted.
+pub trait CanonicalBytes: PrimeField {
+    fn to_canonical_bytes(&self) -> Vec<u8>;
+    fn from_canonical_bytes(data: &[u8]) -> io::Result<Self>;
+}
+
+impl<F: PrimeField> CanonicalBytes for F {
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        let mut res = vec![];
+        self.into_repr().write_be(&mut res).unwrap();
+        res
+    }
+
+    fn from_canonical_bytes(data: &[u8]) -> io::Result<Self> {
+        let len = F::Repr::default().as_ref().len() * 8;
+        if data.len() != len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("expected {} bytes, got {}", len, data.len())));
+        }
+
+        let mut repr = F::Repr::default();
+        repr.read_be(data)?;
+        F::from_repr(repr).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "value is not reduced modulo the field order"))
+    }
+}
+
+#[cfg(test)]
+mod field_tests {
+    use super::*;
+    use pairing::bls12_381::Fr;
+    use rand::os::OsRng;
+    use rand::Rng;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_int_conversions() {
+        let mut rng = OsRng::new().unwrap();
+
+        let x = rng.gen::<u64>();
+        assert!(to_u64(&from_u64::<Fr>(x)) == Some(x));
+
+        let x = (rng.gen::<u64>() as u128) << 64 | rng.gen::<u64>() as u128;
+        assert!(to_u128(&from_u128::<Fr>(x)) == Some(x));
+        assert!(to_u64(&from_u128::<Fr>(x | 1 << 64)).is_none());
+
+        for &x in [0, 1, -1, (rng.gen::<u64>() as i128) << 60, -((rng.gen::<u64>() as i128) << 60), i128::max_value(), i128::min_value()].iter() {
+            assert!(to_i128(&from_i128::<Fr>(x)) == Some(x), "i128 should survive roundtrip");
+        }
+        assert!(from_i128::<Fr>(-5) == Fr::from_str("52435875175126190479447740508185965837690552500527637822603658699938581184508").unwrap());
+
+        let big = Fr::from_str("340282366920938463463374607431768211456").unwrap(); // 2^128
+        assert!(to_u128(&big).is_none() && to_i128(&big).is_none());
+    }
+
+    #[test]
+    fn test_biguint_conversions() {
+        let mut rng = OsRng::new().unwrap();
+        let x = rng.gen::<Fr>();
+        assert!(from_biguint::<Fr>(&to_biguint(&x)) == Some(x));
+
+        let p = BigUint::from_str("52435875175126190479447740508185965837690552500527637822603658699938581184513").unwrap();
+        let p_minus_one = &p - 1u32;
+        assert!(from_biguint::<Fr>(&p_minus_one).is_some());
+        assert!(from_biguint::<Fr>(&p).is_none(), "Modulus should not be accepted");
+        assert!(from_biguint::<Fr>(&(BigUint::from(1u32) << 300)).is_none());
+    }
+
+    #[test]
+    fn test_canonical_bytes() {
+        let mut rng = OsRng::new().unwrap();
+        let x = rng.gen::<Fr>();
+        let bytes = x.to_canonical_bytes();
+        assert!(bytes.len() == 32);
+        assert!(Fr::from_canonical_bytes(&bytes).unwrap() == x);
+        assert!(Fr::from_canonical_bytes(&bytes[1..]).is_err() && Fr::from_canonical_bytes(&[&bytes[..], &[0]].concat()).is_err());
+
+        let p = BigUint::from_str("52435875175126190479447740508185965837690552500527637822603658699938581184513").unwrap();
+        let be = |v: &BigUint| {
+            let b = v.to_bytes_be();
+            [vec![0u8; 32 - b.len()], b].concat()
+        };
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        assert!(Fr::from_canonical_bytes(&be(&(&p - 1u32))).unwrap() == minus_one);
+        assert!(minus_one.to_canonical_bytes() == be(&(&p - 1u32)));
+
+        // x + p and 2^256 - 1 are not reduced
+        let e = Fr::from_canonical_bytes(&be(&(to_biguint(&x) + &p))).err().unwrap();
+        assert!(e.kind() == io::ErrorKind::InvalidData, "Non-reduced value should be rejected");
+        assert!(Fr::from_canonical_bytes(&be(&p)).is_err(), "Modulus should be rejected");
+        assert!(Fr::from_canonical_bytes(&[0xff; 32]).is_err());
+    }
+
+    #[test]
+    fn test_batch_inverse() {
+        let mut rng = OsRng::new().unwrap();
+        let mut v = (0..10).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        v[3] = Fr::zero();
+        let expected = v.iter().map(|x| x.inverse().unwrap_or(Fr::zero())).collect::<Vec<_>>();
+        batch_inverse(&mut v);
+        assert!(v == expected);
+    }
+
+    #[test]
+    fn test_sqrt() {
+        let mut rng = OsRng::new().unwrap();
+        let mut x = rng.gen::<Fr>();
+        x.square();
+
+        let r = sqrt(&x).unwrap();
+        let mut r2 = r;
+        r2.square();
+        assert!(r2 == x && is_square(&x));
+        let mut neg = r;
+        neg.negate();
+        assert!(r.into_repr() < neg.into_repr(), "Smaller root should be returned");
+
+        assert!(legendre(&Fr::zero()) == 0);
+        let nr = loop {
+            let y = rng.gen::<Fr>();
+            if !is_square(&y) {
+                break y;
+            }
+        };
+        assert!(sqrt(&nr).is_none() && legendre(&nr) == -1);
+        let mut nr2 = nr;
+        nr2.square();
+        assert!(pow(&nr, 2) == nr2);
+    }
+}
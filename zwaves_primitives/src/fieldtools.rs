@@ -1,6 +1,7 @@
 use pairing::{PrimeField, Field, PrimeFieldRepr};
 use itertools::Itertools;
 use std::mem::transmute;
+use std::io;
 
 
 #[derive(Debug)]
@@ -102,6 +103,134 @@ pub fn fr_to_repr_bool<P:PrimeField>(x: &P) -> impl IntoIterator<Item=bool> {
 }
 
 
+// Bit strings carrying their order in the type. Everything hashed here (Pedersen inputs,
+// merkle paths, the nullifier preimage) is little-endian, and a reversed string is still a
+// valid input that gives a wrong root, so the hashing APIs take LeBits and a BeBits has to
+// be converted explicitly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeBits(pub Vec<bool>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BeBits(pub Vec<bool>);
+
+impl LeBits {
+    pub fn to_be(&self) -> BeBits {
+        BeBits(self.0.iter().rev().cloned().collect())
+    }
+
+    // None when the value does not fit the field
+    pub fn to_fr<P: PrimeField>(&self) -> Option<P> {
+        let mut repr = P::Repr::default();
+        for (i, _) in self.0.iter().enumerate().filter(|(_, b)| **b) {
+            *repr.as_mut().get_mut(i / 64)? |= 1 << (i % 64);
+        }
+        P::from_repr(repr).ok()
+    }
+}
+
+impl BeBits {
+    pub fn to_le(&self) -> LeBits {
+        LeBits(self.0.iter().rev().cloned().collect())
+    }
+}
+
+impl From<BeBits> for LeBits {
+    fn from(bits: BeBits) -> Self {
+        bits.to_le()
+    }
+}
+
+impl From<LeBits> for BeBits {
+    fn from(bits: LeBits) -> Self {
+        bits.to_be()
+    }
+}
+
+impl ::std::ops::Deref for LeBits {
+    type Target = [bool];
+
+    fn deref(&self) -> &[bool] {
+        &self.0
+    }
+}
+
+impl ::std::ops::Deref for BeBits {
+    type Target = [bool];
+
+    fn deref(&self) -> &[bool] {
+        &self.0
+    }
+}
+
+impl IntoIterator for LeBits {
+    type Item = bool;
+    type IntoIter = ::std::vec::IntoIter<bool>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+// Lowest n bits of x, padded with zeros past the repr
+pub fn get_bits_le_fixed<P: PrimeField>(x: &P, n: usize) -> LeBits {
+    LeBits(fr_to_repr_bool(x).into_iter().chain(::std::iter::repeat(false)).take(n).collect())
+}
+
+// Same, but x should fit n bits: dropping a set bit changes the value which gets committed
+pub fn get_bits_le_checked<P: PrimeField>(x: &P, n: usize) -> io::Result<LeBits> {
+    let significant = significant_bits(x);
+    if significant > n {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("value has {} significant bits, {} expected", significant, n)));
+    }
+    Ok(get_bits_le_fixed(x, n))
+}
+
+pub fn significant_bits<P: PrimeField>(x: &P) -> usize {
+    fr_to_repr_bool(x).into_iter().enumerate().filter(|&(_, b)| b).last().map_or(0, |(i, _)| i + 1)
+}
+
+// Decompositions shared with the circuit (zwaves_circuit::gadgets::bitify::to_bits_le_strict, to_bits_le),
+// the two agree on every value: the same bits, or an error where the circuit is unsatisfied.
+//   to_bits_le_strict  NUM_BITS bits of the canonical representation, any x
+//   to_bits_le         n bits, x should fit them, n at most CAPACITY
+// A NUM_BITS decomposition without the strict check is not offered: 2^NUM_BITS > r, so a value
+// below 2^NUM_BITS - r has a second decomposition, x + r, which the circuit accepts and the
+// native code never gives.
+pub fn to_bits_le_strict<P: PrimeField>(x: &P) -> LeBits {
+    get_bits_le_fixed(x, P::NUM_BITS as usize)
+}
+
+pub fn to_bits_le<P: PrimeField>(x: &P, n: usize) -> io::Result<LeBits> {
+    if n > P::CAPACITY as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} bits do not give a unique decomposition, use to_bits_le_strict", n)));
+    }
+    get_bits_le_checked(x, n)
+}
+
+// Comparison and selection of zwaves_circuit::gadgets::compare, same results, and an error where the gadget is
+// unsatisfied. less_than is the top bit of a - b + 2^n as there, so operands are not range checked by
+// themselves, only the difference should fit n + 1 bits
+pub fn less_than<P: PrimeField>(a: &P, b: &P, n: usize) -> io::Result<bool> {
+    if n >= P::CAPACITY as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} bit operands do not fit the field", n)));
+    }
+    let mut t = P::one();
+    (0..n).for_each(|_| t.double());
+    t.add_assign(a);
+    t.sub_assign(b);
+    Ok(!get_bits_le_checked(&t, n + 1)?.0[n])
+}
+
+pub fn is_equal<P: PrimeField>(a: &P, b: &P) -> bool {
+    a == b
+}
+
+// a if condition else b
+pub fn conditionally_select<P: PrimeField>(a: &P, b: &P, condition: bool) -> P {
+    if condition { *a } else { *b }
+}
+
+
 pub fn repr_u64_to_fr<'a, I:IntoIterator<Item=&'a u64>, P:PrimeField>(r:I) -> P {
     let mut res = P::char();
     res.as_mut().iter_mut().zip(r).for_each(|(a, b)| *a = *b);
@@ -139,6 +268,25 @@ mod fieldtools_tests {
         assert!(v.into_iter().enumerate().all(|(i,x)| (i > 0) ^ (x == 1) ), "Should be converted into 1, 0, 0, 0, ...");
     }
 
+    #[test]
+    fn test_bit_order_roundtrip() {
+        let x = Fr::from_str("6").unwrap();
+        let le = get_bits_le_fixed(&x, 4);
+        assert!(le.0 == vec![false, true, true, false]);
+        assert!(le.to_be().0 == vec![false, true, true, false].into_iter().rev().collect::<Vec<_>>());
+        assert!(BeBits::from(le.clone()).to_le() == le && LeBits::from(le.to_be()) == le);
+        assert!(le.to_fr::<Fr>() == Some(x));
+        assert!(le.to_be().to_le().to_fr::<Fr>() == Some(x));
+
+        let mut y = Fr::one();
+        y.negate();
+        let bits = get_bits_le_fixed(&y, Fr::NUM_BITS as usize);
+        assert!(bits.len() == 255 && bits.to_fr::<Fr>() == Some(y));
+        assert!(get_bits_le_fixed(&y, 300).to_fr::<Fr>() == Some(y), "Padding should not change the value");
+        assert!(LeBits(vec![true; 255]).to_fr::<Fr>().is_none(), "Values out of the field should be rejected");
+        assert!(LeBits(vec![true; 257]).to_fr::<Fr>().is_none());
+    }
+
 }
 
 
@@ -0,0 +1,512 @@
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+use sapling_crypto::jubjub::{JubjubEngine, JubjubParams, FixedGenerators, Unknown, edwards::Point};
+use sapling_crypto::redjubjub::{PublicKey, Signature};
+use sapling_crypto::util::hash_to_scalar;
+use rand::Rng;
+
+use crate::fieldtools;
+use crate::spend_auth::SPEND_AUTH_GENERATOR;
+
+use std::io;
+
+
+// FROST threshold signatures for spend authorization: t of n participants hold shares of sk
+// and nobody knows sk itself. The key is created by a Pedersen DKG (every participant deals a
+// Feldman-committed polynomial of degree t-1 and proves knowledge of its constant term), and
+// signed in two rounds (preprocess nonces, then one share per signer). The aggregate is a plain
+// RedJubjub signature for rk = group key + alpha*G, so spend_auth::verify accepts it as is:
+// the signers never learn alpha, the coordinator adds c*alpha when aggregating.
+//
+// The circuit needs nk = x(sk*ProofGenerationKey) too; it is combined from nk_share, every
+// share carrying a DLEQ proof against the verification share of its participant. Threshold
+// notes use transactions::threshold_owner(x(ak), nk) as the owner and are spent with
+// zwaves_circuit::gadgets::transactions::threshold_authority.
+//
+// Participant ids are 1..=n, commitments are passed sorted by id. A DKG runs under a context
+// string unique to the session and the group (e.g. a hash of the session id and the list of
+// participants), which the proofs of knowledge are bound to as in the FROST paper, so a proof of
+// one session can not be replayed in another by the same participant id.
+const POK_PERSONALIZATION: &[u8; 16] = b"ZWaves_FROST_PoK";
+const BINDING_PERSONALIZATION: &[u8; 16] = b"ZWaves_FROST_rho";
+const DLEQ_PERSONALIZATION: &[u8; 16] = b"ZWaves_FROST_DLQ";
+// Challenge of RedJubjub, see redjubjub::h_star
+const CHALLENGE_PERSONALIZATION: &[u8; 16] = b"Zcash_RedJubjubH";
+
+const NK_GENERATOR: FixedGenerators = FixedGenerators::ProofGenerationKey;
+
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn scalar<E: JubjubEngine>(x: u64) -> E::Fs {
+    E::Fs::from_repr(x.into()).unwrap()
+}
+
+fn point_bytes<E: JubjubEngine>(p: &Point<E, Unknown>) -> [u8; 32] {
+    let mut res = [0u8; 32];
+    p.write(&mut res[..]).unwrap();
+    res
+}
+
+fn base_mul<E: JubjubEngine>(g: FixedGenerators, s: E::Fs, params: &E::Params) -> Point<E, Unknown> {
+    params.generator(g).mul(s, params).into()
+}
+
+fn check_ids<T>(items: &[T], id: impl Fn(&T) -> u16) -> io::Result<()> {
+    for (i, x) in items.iter().enumerate() {
+        if id(x) == 0 {
+            return Err(invalid("participant id 0 is not allowed".to_string()));
+        }
+        if i > 0 && id(&items[i - 1]) >= id(x) {
+            return Err(invalid("participants should be sorted by id without repetitions".to_string()));
+        }
+    }
+    Ok(())
+}
+
+// Lagrange coefficient of id at 0 over the set ids
+pub fn lagrange<E: JubjubEngine>(id: u16, ids: &[u16]) -> E::Fs {
+    let mut num = E::Fs::one();
+    let mut den = E::Fs::one();
+    for &j in ids.iter().filter(|&&j| j != id) {
+        num.mul_assign(&scalar::<E>(j as u64));
+        let mut d = scalar::<E>(j as u64);
+        d.sub_assign(&scalar::<E>(id as u64));
+        den.mul_assign(&d);
+    }
+    num.mul_assign(&den.inverse().expect("participant ids should be distinct"));
+    num
+}
+
+
+pub struct SecretPolynomial<E: JubjubEngine> {
+    pub id: u16,
+    context: Vec<u8>,
+    coefficients: Vec<E::Fs>
+}
+
+impl<E: JubjubEngine> SecretPolynomial<E> {
+    // Secret share for participant to, sent over a private channel
+    pub fn share(&self, to: u16) -> E::Fs {
+        let x = scalar::<E>(to as u64);
+        self.coefficients.iter().rev().fold(E::Fs::zero(), |mut acc, c| {
+            acc.mul_assign(&x);
+            acc.add_assign(c);
+            acc
+        })
+    }
+}
+
+// Broadcast by every participant in the DKG
+#[derive(Clone)]
+pub struct DkgCommitment<E: JubjubEngine> {
+    pub id: u16,
+    pub commitments: Vec<Point<E, Unknown>>,
+    pub proof_r: Point<E, Unknown>,
+    pub proof_z: E::Fs
+}
+
+// H(context, id, c0, R), the context length prefixed so that it can't shift into the id
+fn pok_challenge<E: JubjubEngine>(context: &[u8], id: u16, c0: &Point<E, Unknown>, r: &Point<E, Unknown>) -> E::Fs {
+    let mut data = (context.len() as u64).to_le_bytes().to_vec();
+    data.extend_from_slice(context);
+    data.extend_from_slice(&id.to_le_bytes());
+    data.extend_from_slice(&point_bytes(c0));
+    hash_to_scalar::<E>(POK_PERSONALIZATION, &data, &point_bytes(r))
+}
+
+// context is the one of the session, dkg_finish checks the proofs of the others against it
+pub fn dkg_round1<E: JubjubEngine, R: Rng>(id: u16, threshold: usize, context: &[u8], rng: &mut R, params: &E::Params) -> (SecretPolynomial<E>, DkgCommitment<E>) {
+    assert!(id != 0 && threshold > 0);
+    let coefficients = (0..threshold).map(|_| rng.gen::<E::Fs>()).collect::<Vec<_>>();
+    let commitments = coefficients.iter().map(|c| base_mul::<E>(SPEND_AUTH_GENERATOR, *c, params)).collect::<Vec<_>>();
+
+    let k = rng.gen::<E::Fs>();
+    let proof_r = base_mul::<E>(SPEND_AUTH_GENERATOR, k, params);
+    let mut proof_z = pok_challenge::<E>(context, id, &commitments[0], &proof_r);
+    proof_z.mul_assign(&coefficients[0]);
+    proof_z.add_assign(&k);
+
+    (SecretPolynomial { id, context: context.to_vec(), coefficients }, DkgCommitment { id, commitments, proof_r, proof_z })
+}
+
+// sum_k commitments[k] * x^k, the public image of the polynomial at x
+fn eval_commitments<E: JubjubEngine>(commitments: &[Point<E, Unknown>], x: u16, params: &E::Params) -> Point<E, Unknown> {
+    let x = scalar::<E>(x as u64);
+    commitments.iter().rev().fold(Point::zero(), |acc, c| acc.mul(x, params).add(c, params))
+}
+
+
+#[derive(Clone)]
+pub struct KeyPackage<E: JubjubEngine> {
+    pub id: u16,
+    pub threshold: usize,
+    pub share: E::Fs,
+    pub group_key: Point<E, Unknown>,
+    // s_j*G of every participant, public
+    pub verification_shares: Vec<(u16, Point<E, Unknown>)>
+}
+
+impl<E: JubjubEngine> KeyPackage<E> {
+    pub fn public_key(&self) -> PublicKey<E> {
+        PublicKey(self.group_key.clone())
+    }
+
+    pub fn verification_share(&self, id: u16) -> Option<&Point<E, Unknown>> {
+        self.verification_shares.iter().find(|(j, _)| *j == id).map(|(_, p)| p)
+    }
+}
+
+// shares are the ones received from every other participant, (dealer id, share)
+pub fn dkg_finish<E: JubjubEngine>(
+    secret: &SecretPolynomial<E>,
+    commitments: &[DkgCommitment<E>],
+    shares: &[(u16, E::Fs)],
+    params: &E::Params
+) -> io::Result<KeyPackage<E>>
+{
+    check_ids(commitments, |c| c.id)?;
+    let threshold = secret.coefficients.len();
+    if commitments.len() < threshold {
+        return Err(invalid(format!("{} participants for threshold {}", commitments.len(), threshold)));
+    }
+
+    let mut share = E::Fs::zero();
+    for c in commitments.iter() {
+        if c.commitments.len() != threshold {
+            return Err(invalid(format!("participant {}: wrong threshold", c.id)));
+        }
+        let mut lhs = c.commitments[0].mul(pok_challenge::<E>(&secret.context, c.id, &c.commitments[0], &c.proof_r), params);
+        lhs = lhs.add(&c.proof_r, params);
+        if base_mul::<E>(SPEND_AUTH_GENERATOR, c.proof_z, params) != lhs {
+            return Err(invalid(format!("participant {}: wrong proof of knowledge", c.id)));
+        }
+
+        let s = if c.id == secret.id {
+            secret.share(secret.id)
+        } else {
+            shares.iter().find(|(j, _)| *j == c.id).map(|(_, s)| *s)
+                .ok_or_else(|| invalid(format!("participant {}: missing share", c.id)))?
+        };
+        if base_mul::<E>(SPEND_AUTH_GENERATOR, s, params) != eval_commitments(&c.commitments, secret.id, params) {
+            return Err(invalid(format!("participant {}: share does not match the commitments", c.id)));
+        }
+        share.add_assign(&s);
+    }
+
+    let group_key = commitments.iter().fold(Point::zero(), |acc, c| acc.add(&c.commitments[0], params));
+    let verification_shares = commitments.iter().map(|c| {
+        let y = commitments.iter().fold(Point::zero(), |acc, d| acc.add(&eval_commitments(&d.commitments, c.id, params), params));
+        (c.id, y)
+    }).collect();
+
+    Ok(KeyPackage { id: secret.id, threshold, share, group_key, verification_shares })
+}
+
+
+pub struct SigningNonces<E: JubjubEngine> {
+    pub id: u16,
+    d: E::Fs,
+    e: E::Fs
+}
+
+#[derive(Clone)]
+pub struct SigningCommitment<E: JubjubEngine> {
+    pub id: u16,
+    pub d: Point<E, Unknown>,
+    pub e: Point<E, Unknown>
+}
+
+// Round 1. The nonces are used for one signature only, sign_share takes them by value.
+pub fn preprocess<E: JubjubEngine, R: Rng>(id: u16, rng: &mut R, params: &E::Params) -> (SigningNonces<E>, SigningCommitment<E>) {
+    let d = rng.gen::<E::Fs>();
+    let e = rng.gen::<E::Fs>();
+    let commitment = SigningCommitment {
+        id,
+        d: base_mul::<E>(SPEND_AUTH_GENERATOR, d, params),
+        e: base_mul::<E>(SPEND_AUTH_GENERATOR, e, params)
+    };
+    (SigningNonces { id, d, e }, commitment)
+}
+
+fn binding_factor<E: JubjubEngine>(id: u16, commitments: &[SigningCommitment<E>], msg: &[u8]) -> E::Fs {
+    let mut data = vec![];
+    for c in commitments.iter() {
+        data.extend_from_slice(&c.id.to_le_bytes());
+        data.extend_from_slice(&point_bytes(&c.d));
+        data.extend_from_slice(&point_bytes(&c.e));
+    }
+    data.extend_from_slice(&id.to_le_bytes());
+    hash_to_scalar::<E>(BINDING_PERSONALIZATION, &data, msg)
+}
+
+// R and the RedJubjub challenge c = H*(Rbar || msg)
+fn group_commitment<E: JubjubEngine>(commitments: &[SigningCommitment<E>], msg: &[u8], params: &E::Params) -> ([u8; 32], E::Fs) {
+    let r = commitments.iter().fold(Point::zero(), |acc, c| {
+        acc.add(&c.d, params).add(&c.e.mul(binding_factor::<E>(c.id, commitments, msg), params), params)
+    });
+    let rbar = point_bytes(&r);
+    (rbar, hash_to_scalar::<E>(CHALLENGE_PERSONALIZATION, &rbar, msg))
+}
+
+fn signer_ids<E: JubjubEngine>(commitments: &[SigningCommitment<E>], threshold: usize) -> io::Result<Vec<u16>> {
+    check_ids(commitments, |c| c.id)?;
+    if commitments.len() < threshold {
+        return Err(invalid(format!("{} signers for threshold {}", commitments.len(), threshold)));
+    }
+    Ok(commitments.iter().map(|c| c.id).collect())
+}
+
+// Round 2: z_i = d_i + e_i*rho_i + lambda_i*s_i*c
+pub fn sign_share<E: JubjubEngine>(
+    key: &KeyPackage<E>,
+    nonces: SigningNonces<E>,
+    commitments: &[SigningCommitment<E>],
+    msg: &[u8],
+    params: &E::Params
+) -> io::Result<E::Fs>
+{
+    let ids = signer_ids(commitments, key.threshold)?;
+    let own = commitments.iter().find(|c| c.id == key.id)
+        .ok_or_else(|| invalid(format!("participant {} is not a signer", key.id)))?;
+    if nonces.id != key.id || own.d != base_mul::<E>(SPEND_AUTH_GENERATOR, nonces.d, params) || own.e != base_mul::<E>(SPEND_AUTH_GENERATOR, nonces.e, params) {
+        return Err(invalid("nonces do not match the commitment".to_string()));
+    }
+
+    let (_, c) = group_commitment(commitments, msg, params);
+    let mut z = lagrange::<E>(key.id, &ids);
+    z.mul_assign(&key.share);
+    z.mul_assign(&c);
+    let mut t = binding_factor::<E>(key.id, commitments, msg);
+    t.mul_assign(&nonces.e);
+    z.add_assign(&t);
+    z.add_assign(&nonces.d);
+    Ok(z)
+}
+
+// Lets the coordinator find a cheating signer: z_i*G = D_i + rho_i*E_i + lambda_i*c*Y_i
+pub fn verify_share<E: JubjubEngine>(
+    key: &KeyPackage<E>,
+    id: u16,
+    z: &E::Fs,
+    commitments: &[SigningCommitment<E>],
+    msg: &[u8],
+    params: &E::Params
+) -> bool
+{
+    let ids = match signer_ids(commitments, key.threshold) {
+        Ok(ids) => ids,
+        Err(_) => return false
+    };
+    let (own, y) = match (commitments.iter().find(|c| c.id == id), key.verification_share(id)) {
+        (Some(own), Some(y)) => (own, y),
+        _ => return false
+    };
+
+    let (_, c) = group_commitment(commitments, msg, params);
+    let mut k = lagrange::<E>(id, &ids);
+    k.mul_assign(&c);
+    let rhs = own.d.add(&own.e.mul(binding_factor::<E>(id, commitments, msg), params), params).add(&y.mul(k, params), params);
+    base_mul::<E>(SPEND_AUTH_GENERATOR, *z, params) == rhs
+}
+
+// Signature for rk = group key + alpha*G, see spend_auth::randomized_public_key
+pub fn aggregate<E: JubjubEngine>(
+    commitments: &[SigningCommitment<E>],
+    shares: &[(u16, E::Fs)],
+    msg: &[u8],
+    alpha: &E::Fr,
+    params: &E::Params
+) -> io::Result<Signature>
+{
+    check_ids(commitments, |c| c.id)?;
+    if shares.len() != commitments.len() || shares.iter().zip(commitments.iter()).any(|((id, _), c)| *id != c.id) {
+        return Err(invalid("one share per signer is expected, in the order of commitments".to_string()));
+    }
+
+    let (rbar, c) = group_commitment(commitments, msg, params);
+    let mut z = fieldtools::f2f::<E::Fr, E::Fs>(alpha);
+    z.mul_assign(&c);
+    for (_, s) in shares.iter() {
+        z.add_assign(s);
+    }
+
+    let mut data = rbar.to_vec();
+    z.into_repr().write_le(&mut data)?;
+    Signature::read(&data[..])
+}
+
+
+// s_i*H with a proof that it has the same discrete log as the verification share s_i*G
+#[derive(Clone)]
+pub struct NkShare<E: JubjubEngine> {
+    pub id: u16,
+    pub point: Point<E, Unknown>,
+    pub proof_c: E::Fs,
+    pub proof_z: E::Fs
+}
+
+fn dleq_challenge<E: JubjubEngine>(y: &Point<E, Unknown>, n: &Point<E, Unknown>, a: &Point<E, Unknown>, b: &Point<E, Unknown>) -> E::Fs {
+    let mut data = point_bytes(y).to_vec();
+    data.extend_from_slice(&point_bytes(n));
+    data.extend_from_slice(&point_bytes(a));
+    hash_to_scalar::<E>(DLEQ_PERSONALIZATION, &data, &point_bytes(b))
+}
+
+pub fn nk_share<E: JubjubEngine, R: Rng>(key: &KeyPackage<E>, rng: &mut R, params: &E::Params) -> NkShare<E> {
+    let y = base_mul::<E>(SPEND_AUTH_GENERATOR, key.share, params);
+    let point = base_mul::<E>(NK_GENERATOR, key.share, params);
+
+    let k = rng.gen::<E::Fs>();
+    let proof_c = dleq_challenge(&y, &point, &base_mul::<E>(SPEND_AUTH_GENERATOR, k, params), &base_mul::<E>(NK_GENERATOR, k, params));
+    let mut proof_z = proof_c;
+    proof_z.mul_assign(&key.share);
+    proof_z.add_assign(&k);
+
+    NkShare { id: key.id, point, proof_c, proof_z }
+}
+
+// nk = x(sum lambda_i * s_i*H) = x(sk*H), the same value as transactions::nullifier_key(sk)
+pub fn combine_nk<E: JubjubEngine>(key: &KeyPackage<E>, shares: &[NkShare<E>], params: &E::Params) -> io::Result<E::Fr> {
+    check_ids(shares, |s| s.id)?;
+    if shares.len() < key.threshold {
+        return Err(invalid(format!("{} nk shares for threshold {}", shares.len(), key.threshold)));
+    }
+    let ids = shares.iter().map(|s| s.id).collect::<Vec<_>>();
+
+    let mut res = Point::zero();
+    for s in shares.iter() {
+        let y = key.verification_share(s.id).ok_or_else(|| invalid(format!("unknown participant {}", s.id)))?;
+        let mut neg_c = s.proof_c;
+        neg_c.negate();
+        let a = base_mul::<E>(SPEND_AUTH_GENERATOR, s.proof_z, params).add(&y.mul(neg_c, params), params);
+        let b = base_mul::<E>(NK_GENERATOR, s.proof_z, params).add(&s.point.mul(neg_c, params), params);
+        if dleq_challenge(y, &s.point, &a, &b) != s.proof_c {
+            return Err(invalid(format!("participant {}: wrong nk share", s.id)));
+        }
+        res = res.add(&s.point.mul(lagrange::<E>(s.id, &ids), params), params);
+    }
+    Ok(res.into_xy().0)
+}
+
+
+#[cfg(test)]
+mod frost_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use sapling_crypto::jubjub::fs::Fs;
+    use rand::os::OsRng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+    use crate::spend_auth::{randomized_public_key, verify};
+
+    fn dkg(n: u16, t: usize) -> Vec<KeyPackage<Bls12>> {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let round1 = (1..=n).map(|id| dkg_round1::<Bls12, _>(id, t, b"test group", &mut rng, params)).collect::<Vec<_>>();
+        let commitments = round1.iter().map(|(_, c)| c.clone()).collect::<Vec<_>>();
+        round1.iter().map(|(secret, _)| {
+            let shares = round1.iter().filter(|(s, _)| s.id != secret.id).map(|(s, _)| (s.id, s.share(secret.id))).collect::<Vec<_>>();
+            dkg_finish(secret, &commitments, &shares, params).unwrap()
+        }).collect()
+    }
+
+    fn sign(keys: &[&KeyPackage<Bls12>], msg: &[u8], alpha: &Fr) -> (Vec<SigningCommitment<Bls12>>, Vec<(u16, Fs)>, io::Result<Signature>) {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let round1 = keys.iter().map(|k| preprocess::<Bls12, _>(k.id, &mut rng, params)).collect::<Vec<_>>();
+        let commitments = round1.iter().map(|(_, c)| c.clone()).collect::<Vec<_>>();
+        let shares = keys.iter().zip(round1.into_iter())
+            .map(|(k, (nonces, _))| (k.id, sign_share(k, nonces, &commitments, msg, params).unwrap()))
+            .collect::<Vec<_>>();
+        let sig = aggregate(&commitments, &shares, msg, alpha, params);
+        (commitments, shares, sig)
+    }
+
+    #[test]
+    fn test_frost() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let keys = dkg(3, 2);
+        assert!(keys.iter().all(|k| k.group_key == keys[0].group_key), "Group key should be the same for everyone");
+
+        // the shares interpolate to the secret of the group key
+        let mut sk = Fs::zero();
+        for k in [&keys[0], &keys[2]].iter() {
+            let mut t = lagrange::<Bls12>(k.id, &[1, 3]);
+            t.mul_assign(&k.share);
+            sk.add_assign(&t);
+        }
+        assert!(base_mul::<Bls12>(SPEND_AUTH_GENERATOR, sk, params) == keys[0].group_key);
+
+        let msg = [7u8; 32];
+        let alpha = rng.gen::<Fr>();
+        let rk = randomized_public_key(&keys[0].public_key(), &alpha, params);
+
+        let (commitments, shares, sig) = sign(&[&keys[0], &keys[2]], &msg, &alpha);
+        let sig = sig.unwrap();
+        assert!(verify(&rk, &msg, &sig, params), "Threshold signature should be valid for rk");
+        assert!(!verify(&keys[0].public_key(), &msg, &sig, params));
+        assert!(!verify(&rk, &[8u8; 32], &sig, params));
+        for (id, z) in shares.iter() {
+            assert!(verify_share(&keys[1], *id, z, &commitments, &msg, params), "Share of {} should be valid", id);
+        }
+        let mut wrong = shares[1].1;
+        wrong.add_assign(&Fs::one());
+        assert!(!verify_share(&keys[1], 3, &wrong, &commitments, &msg, params), "Wrong share should be detected");
+        let forged = aggregate(&commitments, &[shares[0], (3, wrong)], &msg, &alpha, params).unwrap();
+        assert!(!verify(&rk, &msg, &forged, params));
+
+        let (_, _, sig) = sign(&[&keys[1], &keys[2]], &msg, &alpha);
+        assert!(verify(&rk, &msg, &sig.unwrap(), params), "Any 2 signers should do");
+
+        let round1 = preprocess::<Bls12, _>(1, &mut rng, params);
+        assert!(sign_share(&keys[0], round1.0, &[round1.1], &msg, params).is_err(), "Single signer is below threshold");
+
+        // nk is the same for any subset and matches the nullifier key of sk
+        let nk_shares = keys.iter().map(|k| nk_share(k, &mut rng, params)).collect::<Vec<_>>();
+        let nk = combine_nk(&keys[0], &nk_shares[..2], params).unwrap();
+        assert!(combine_nk(&keys[1], &nk_shares[1..], params).unwrap() == nk);
+        assert!(nk == base_mul::<Bls12>(NK_GENERATOR, sk, params).into_xy().0);
+        let mut bad = nk_shares[..2].to_vec();
+        bad[1].point = bad[0].point.clone();
+        assert!(combine_nk(&keys[0], &bad, params).is_err(), "Wrong nk share should be rejected");
+    }
+
+    #[test]
+    fn test_dkg_rejects_cheating() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let round1 = (1..=3).map(|id| dkg_round1::<Bls12, _>(id, 2, b"session 1", &mut rng, params)).collect::<Vec<_>>();
+        let commitments = round1.iter().map(|(_, c)| c.clone()).collect::<Vec<_>>();
+        let shares = |to: u16| round1.iter().filter(|(s, _)| s.id != to).map(|(s, _)| (s.id, s.share(to))).collect::<Vec<_>>();
+        dkg_finish(&round1[0].0, &commitments, &shares(1), params).unwrap();
+
+        let mut wrong = shares(1);
+        wrong[0].1.add_assign(&Fs::one());
+        assert!(dkg_finish(&round1[0].0, &commitments, &wrong, params).is_err(), "Wrong share should be rejected");
+        assert!(dkg_finish(&round1[0].0, &commitments, &shares(1)[..1], params).is_err(), "Missing share should be rejected");
+
+        let mut c = commitments.clone();
+        c[2].proof_z.add_assign(&Fs::one());
+        assert!(dkg_finish(&round1[0].0, &c, &shares(1), params).is_err(), "Wrong proof of knowledge should be rejected");
+
+        let mut c = commitments.clone();
+        c.swap(1, 2);
+        assert!(dkg_finish(&round1[0].0, &c, &shares(1), params).is_err(), "Unsorted participants should be rejected");
+
+        // participant 3 dealing anew, with shares consistent with its commitments: accepted in
+        // the same session only
+        let redeal = |context: &[u8]| {
+            let (other, commitment) = dkg_round1::<Bls12, _>(3, 2, context, &mut OsRng::new().unwrap(), params);
+            let mut c = commitments.clone();
+            c[2] = commitment;
+            let mut s = shares(1);
+            s[1].1 = other.share(1);
+            dkg_finish(&round1[0].0, &c, &s, params)
+        };
+        assert!(redeal(b"session 1").is_ok());
+        assert!(redeal(b"session 2").is_err(), "Proof of knowledge of another context should be rejected");
+    }
+}
@@ -0,0 +1,267 @@
+use sapling_crypto::jubjub::{JubjubEngine, JubjubParams, FixedGenerators, PrimeOrder, edwards::Point};
+use sapling_crypto::constants;
+use blake2_rfc::blake2s::Blake2s;
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::group_hash::{GH_FIRST_BLOCK, group_hash_exact};
+
+use std::io::{self, BufRead, Write};
+
+
+// Nothing-up-my-sleeve transcript of the generators: every generator is the group hash of a
+// public domain and message, with the smallest counter byte that gives a point. Anyone can
+// recompute the digests, check that the smaller counters fail and that the points are distinct,
+// so no generator was picked with a known discrete log relative to another one.
+//
+// One line per generator, fields separated by a space:
+//   name domain(hex) message(hex) counter digest(hex) point(hex, edwards::Point::write)
+pub const TRANSCRIPT_HEADER: &str = "# zwaves generators v1";
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Derivation {
+    pub name: String,
+    pub domain: [u8; 8],
+    pub message: Vec<u8>,
+    pub counter: u8,
+    pub digest: [u8; 32],
+    pub point: [u8; 32]
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn digest(domain: &[u8; 8], message: &[u8], counter: u8) -> [u8; 32] {
+    let mut h = Blake2s::with_params(32, &[], &[], domain);
+    h.update(GH_FIRST_BLOCK);
+    h.update(message);
+    h.update(&[counter]);
+    let mut res = [0u8; 32];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+fn tag(message: &[u8], counter: u8) -> Vec<u8> {
+    let mut res = message.to_vec();
+    res.push(counter);
+    res
+}
+
+fn point_bytes<E: JubjubEngine>(p: &Point<E, PrimeOrder>) -> [u8; 32] {
+    let mut res = [0u8; 32];
+    p.write(&mut res[..]).unwrap();
+    res
+}
+
+
+// Same point as group_hash::group_hash(domain, message)
+pub fn derive<E: JubjubEngine>(name: &str, domain: &[u8; 8], message: &[u8], params: &E::Params) -> (Derivation, Point<E, PrimeOrder>) {
+    assert!(!name.is_empty() && !name.contains(char::is_whitespace), "name should be one word");
+
+    for counter in 0..u8::max_value() {
+        if let Some(p) = group_hash_exact::<E>(domain, &tag(message, counter), params) {
+            let d = Derivation {
+                name: name.to_string(),
+                domain: *domain,
+                message: message.to_vec(),
+                counter,
+                digest: digest(domain, message, counter),
+                point: point_bytes(&p)
+            };
+            return (d, p);
+        }
+    }
+    panic!("group hash failed for every counter");
+}
+
+// Every generator of JubjubBls12: the Pedersen hash segments and the fixed bases
+pub fn sapling_derivations<E: JubjubEngine>(params: &E::Params) -> Vec<Derivation> {
+    let mut res = vec![];
+    for m in 0..params.pedersen_hash_generators().len() as u32 {
+        let mut segment = vec![];
+        segment.write_u32::<LittleEndian>(m).unwrap();
+        res.push(derive::<E>(&format!("pedersen_hash_{}", m), constants::PEDERSEN_HASH_GENERATORS_PERSONALIZATION, &segment, params).0);
+    }
+
+    let fixed: [(&str, &[u8; 8], &[u8]); 6] = [
+        ("proof_generation_key", constants::PROOF_GENERATION_KEY_BASE_GENERATOR_PERSONALIZATION, b""),
+        ("note_commitment_randomness", constants::PEDERSEN_HASH_GENERATORS_PERSONALIZATION, b"r"),
+        ("nullifier_position", constants::NULLIFIER_POSITION_IN_TREE_GENERATOR_PERSONALIZATION, b""),
+        ("value_commitment_value", constants::VALUE_COMMITMENT_GENERATOR_PERSONALIZATION, b"v"),
+        ("value_commitment_randomness", constants::VALUE_COMMITMENT_GENERATOR_PERSONALIZATION, b"r"),
+        ("spending_key_generator", constants::SPENDING_KEY_GENERATOR_PERSONALIZATION, b"")
+    ];
+    for (name, domain, message) in fixed.iter() {
+        res.push(derive::<E>(name, domain, message, params).0);
+    }
+    res
+}
+
+// Points of sapling_derivations in the same order
+pub fn sapling_generators<E: JubjubEngine>(params: &E::Params) -> Vec<Point<E, PrimeOrder>> {
+    let fixed = [
+        FixedGenerators::ProofGenerationKey,
+        FixedGenerators::NoteCommitmentRandomness,
+        FixedGenerators::NullifierPosition,
+        FixedGenerators::ValueCommitmentValue,
+        FixedGenerators::ValueCommitmentRandomness,
+        FixedGenerators::SpendingKeyGenerator
+    ];
+    params.pedersen_hash_generators().iter().cloned()
+        .chain(fixed.iter().map(|g| params.generator(*g).clone()))
+        .collect()
+}
+
+
+// Recomputes every derivation from its domain and message. Returns the points in transcript order.
+pub fn verify_derivations<E: JubjubEngine>(derivations: &[Derivation], params: &E::Params) -> io::Result<Vec<Point<E, PrimeOrder>>> {
+    let mut points: Vec<Point<E, PrimeOrder>> = vec![];
+
+    for d in derivations.iter() {
+        for c in 0..d.counter {
+            if group_hash_exact::<E>(&d.domain, &tag(&d.message, c), params).is_some() {
+                return Err(invalid(format!("{}: counter {} already gives a point", d.name, c)));
+            }
+        }
+        if digest(&d.domain, &d.message, d.counter) != d.digest {
+            return Err(invalid(format!("{}: wrong digest", d.name)));
+        }
+        let p = group_hash_exact::<E>(&d.domain, &tag(&d.message, d.counter), params)
+            .ok_or_else(|| invalid(format!("{}: digest is not a point", d.name)))?;
+        if point_bytes(&p) != d.point {
+            return Err(invalid(format!("{}: wrong point", d.name)));
+        }
+        if points.contains(&p) {
+            return Err(invalid(format!("{}: duplicate generator", d.name)));
+        }
+        points.push(p);
+    }
+    Ok(points)
+}
+
+// The Jubjub parameters in use are exactly the ones of the transcript
+pub fn check_sapling_params<E: JubjubEngine>(derivations: &[Derivation], params: &E::Params) -> io::Result<()> {
+    let points = verify_derivations::<E>(derivations, params)?;
+    if points != sapling_generators::<E>(params) {
+        return Err(invalid("generators differ from the jubjub parameters".to_string()));
+    }
+    Ok(())
+}
+
+
+fn to_hex(data: &[u8]) -> String {
+    if data.is_empty() {
+        return "-".to_string();
+    }
+    hex::encode(data)
+}
+
+fn from_hex(s: &str) -> io::Result<Vec<u8>> {
+    if s == "-" {
+        return Ok(vec![]);
+    }
+    hex::decode(s).map_err(|e| invalid(format!("wrong hex: {}", e)))
+}
+
+fn from_hex_fixed(s: &str, out: &mut [u8]) -> io::Result<()> {
+    let data = from_hex(s)?;
+    if data.len() != out.len() {
+        return Err(invalid(format!("expected {} bytes", out.len())));
+    }
+    out.copy_from_slice(&data);
+    Ok(())
+}
+
+pub fn write_transcript<W: Write>(derivations: &[Derivation], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "{}", TRANSCRIPT_HEADER)?;
+    for d in derivations.iter() {
+        writeln!(writer, "{} {} {} {} {} {}", d.name, to_hex(&d.domain), to_hex(&d.message), d.counter, to_hex(&d.digest), to_hex(&d.point))?;
+    }
+    Ok(())
+}
+
+// Empty lines and lines starting with # are skipped
+pub fn read_transcript<R: BufRead>(reader: R) -> io::Result<Vec<Derivation>> {
+    let mut res = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 6 {
+            return Err(invalid(format!("expected 6 fields: {}", line)));
+        }
+        let mut d = Derivation {
+            name: fields[0].to_string(),
+            domain: [0; 8],
+            message: from_hex(fields[2])?,
+            counter: fields[3].parse().map_err(|_| invalid(format!("wrong counter: {}", fields[3])))?,
+            digest: [0; 32],
+            point: [0; 32]
+        };
+        from_hex_fixed(fields[1], &mut d.domain)?;
+        from_hex_fixed(fields[4], &mut d.digest)?;
+        from_hex_fixed(fields[5], &mut d.point)?;
+        res.push(d);
+    }
+    Ok(res)
+}
+
+
+#[cfg(test)]
+mod generators_tests {
+    use super::*;
+    use pairing::bls12_381::Bls12;
+
+    use crate::group_hash::{GENERATOR_PERSONALIZATION, generator};
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    #[test]
+    fn test_sapling_transcript() {
+        let params = &*JUBJUB_PARAMS;
+        let derivations = sapling_derivations::<Bls12>(params);
+        assert!(derivations.len() == 11);
+        check_sapling_params::<Bls12>(&derivations, params).unwrap();
+
+        let mut data = vec![];
+        write_transcript(&derivations, &mut data).unwrap();
+        let read = read_transcript(&data[..]).unwrap();
+        assert!(read == derivations);
+        check_sapling_params::<Bls12>(&read, params).unwrap();
+
+        let mut wrong = derivations.clone();
+        wrong[3].digest[0] ^= 1;
+        assert!(verify_derivations::<Bls12>(&wrong, params).is_err(), "Wrong digest should be rejected");
+
+        let mut wrong = derivations.clone();
+        wrong[7].point = wrong[8].point;
+        assert!(verify_derivations::<Bls12>(&wrong, params).is_err(), "Wrong point should be rejected");
+
+        let mut wrong = derivations.clone();
+        wrong.swap(0, 1);
+        verify_derivations::<Bls12>(&wrong, params).unwrap();
+        assert!(check_sapling_params::<Bls12>(&wrong, params).is_err(), "Reordered generators should not match params");
+
+        let mut wrong = derivations.clone();
+        wrong.push(wrong[0].clone());
+        assert!(verify_derivations::<Bls12>(&wrong, params).is_err(), "Duplicate generator should be rejected");
+
+        // a later counter that also gives a point is not the canonical derivation
+        let (d, p) = derive::<Bls12>("test", GENERATOR_PERSONALIZATION, b"test", params);
+        assert!(p == generator::<Bls12>(b"test", params));
+        let mut later = d.clone();
+        for c in (d.counter + 1)..u8::max_value() {
+            if let Some(p) = group_hash_exact::<Bls12>(GENERATOR_PERSONALIZATION, &tag(b"test", c), params) {
+                later.counter = c;
+                later.digest = digest(GENERATOR_PERSONALIZATION, b"test", c);
+                later.point = point_bytes(&p);
+                break;
+            }
+        }
+        assert!(verify_derivations::<Bls12>(&[later], params).is_err(), "Skipped counters should be rejected");
+    }
+}
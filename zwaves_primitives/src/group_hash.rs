@@ -0,0 +1,92 @@
+use sapling_crypto::jubjub::{JubjubEngine, PrimeOrder, edwards::Point};
+use sapling_crypto::{constants, group_hash::group_hash as sapling_group_hash};
+
+
+// Sapling group hash GH^domain(message): blake2s personalized with the 8 byte domain over
+// GH_FIRST_BLOCK (the URS) and the message, read as a compressed Jubjub point and multiplied
+// by the cofactor. The result is in the prime order subgroup and is never the identity.
+pub const GH_FIRST_BLOCK: &[u8; 64] = constants::GH_FIRST_BLOCK;
+
+// Sapling diversified base g_d = GH^"Zcash_gd"(d) for an 11 byte diversifier d
+pub const DIVERSIFIER_PERSONALIZATION: &[u8; 8] = constants::KEY_DIVERSIFICATION_PERSONALIZATION;
+pub const DIVERSIFIER_LEN: usize = 11;
+
+// Protocol generators of this crate, see generator
+pub const GENERATOR_PERSONALIZATION: &[u8; 8] = b"ZWgenrtr";
+
+
+// About half of the messages do not hash to a point, None is returned for them.
+pub fn group_hash_exact<E: JubjubEngine>(domain: &[u8; 8], message: &[u8], params: &E::Params) -> Option<Point<E, PrimeOrder>> {
+    sapling_group_hash::<E>(message, domain, params)
+}
+
+// Always succeeds: hashes message || i for i = 0, 1, ... and returns the first point, the same
+// way the Sapling fixed generators are derived (params.generator(SpendingKeyGenerator) is
+// group_hash(SPENDING_KEY_GENERATOR_PERSONALIZATION, &[])).
+pub fn group_hash<E: JubjubEngine>(domain: &[u8; 8], message: &[u8], params: &E::Params) -> Point<E, PrimeOrder> {
+    let mut tag = message.to_vec();
+    tag.push(0);
+
+    loop {
+        if let Some(p) = group_hash_exact::<E>(domain, &tag, params) {
+            return p;
+        }
+        let i = tag.len() - 1;
+        // 2^-256 chance to get here, never reuse the counter
+        assert!(tag[i] != u8::max_value(), "group hash failed for every counter");
+        tag[i] += 1;
+    }
+}
+
+// None for diversifiers that are not valid, the wallet should pick the next one
+pub fn diversified_base<E: JubjubEngine>(d: &[u8; DIVERSIFIER_LEN], params: &E::Params) -> Option<Point<E, PrimeOrder>> {
+    group_hash_exact::<E>(DIVERSIFIER_PERSONALIZATION, d, params)
+}
+
+// Deterministic generator named by a protocol-specific label, independent from the Sapling ones
+pub fn generator<E: JubjubEngine>(name: &[u8], params: &E::Params) -> Point<E, PrimeOrder> {
+    group_hash::<E>(GENERATOR_PERSONALIZATION, name, params)
+}
+
+
+#[cfg(test)]
+mod group_hash_tests {
+    use super::*;
+    use pairing::bls12_381::Bls12;
+    use sapling_crypto::jubjub::{JubjubParams, FixedGenerators};
+    use sapling_crypto::primitives::Diversifier;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    #[test]
+    fn test_group_hash() {
+        let params = &*JUBJUB_PARAMS;
+
+        let gens = [
+            (constants::SPENDING_KEY_GENERATOR_PERSONALIZATION, &b""[..], FixedGenerators::SpendingKeyGenerator),
+            (constants::PROOF_GENERATION_KEY_BASE_GENERATOR_PERSONALIZATION, &b""[..], FixedGenerators::ProofGenerationKey),
+            (constants::VALUE_COMMITMENT_GENERATOR_PERSONALIZATION, &b"r"[..], FixedGenerators::ValueCommitmentRandomness)
+        ];
+        for (domain, message, g) in gens.iter() {
+            assert!(group_hash::<Bls12>(domain, message, params) == *params.generator(*g), "Sapling generators should match");
+        }
+
+        let g = generator::<Bls12>(b"test", params);
+        assert!(g == generator::<Bls12>(b"test", params));
+        assert!(g != generator::<Bls12>(b"test2", params));
+        assert!(g != group_hash::<Bls12>(DIVERSIFIER_PERSONALIZATION, b"test", params), "Domains should be separated");
+        assert!(g != Point::zero());
+
+        let mut rng = OsRng::new().unwrap();
+        let mut valid = 0;
+        for _ in 0..16 {
+            let d = rng.gen::<[u8; DIVERSIFIER_LEN]>();
+            let g_d = diversified_base::<Bls12>(&d, params);
+            assert!(g_d == Diversifier(d).g_d::<Bls12>(params), "Diversified base should match Sapling");
+            valid += g_d.is_some() as usize;
+        }
+        assert!(valid > 0);
+    }
+}
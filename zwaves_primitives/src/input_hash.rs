@@ -0,0 +1,48 @@
+use pairing::{Engine, PrimeField, PrimeFieldRepr};
+use bellman::SynthesisError;
+use bellman::groth16::Proof;
+
+use blake2_rfc::blake2s::Blake2s;
+
+use crate::fieldtools;
+use crate::verifier::{self, TruncatedVerifyingKey, PreparedTruncatedVerifyingKey};
+
+
+// Hashed input mode (zwaves_circuit::gadgets::input_hash::HashedInputs): the logical public inputs of a circuit
+// are hashed into one field element, which is the only public input of the proof. This keeps
+// the on-chain verifier at one input whatever the circuit exposes.
+//
+// Same packing as the nullifier prf: every input as 32 bytes LE, the blake2s digest read
+// as a 256 bit LE number and reduced mod r.
+pub const INPUT_HASH_PERSONALIZATION: &[u8; 8] = b"ZWinputs";
+
+
+pub fn input_hash<E: Engine>(inputs: &[E::Fr]) -> E::Fr {
+    let mut h = Blake2s::with_params(32, &[], &[], INPUT_HASH_PERSONALIZATION);
+    for x in inputs.iter() {
+        h.update(&fieldtools::fr_to_repr_u8(x).into_iter().collect::<Vec<u8>>());
+    }
+
+    let mut res = <E::Fr as PrimeField>::Repr::default();
+    res.read_le(h.finalize().as_bytes()).unwrap();
+    fieldtools::affine(res)
+}
+
+
+pub fn verify_proof<E: Engine>(
+    tvk: &TruncatedVerifyingKey<E>,
+    proof: &Proof<E>,
+    inputs: &[E::Fr]
+) -> Result<bool, SynthesisError>
+{
+    verifier::verify_proof(tvk, proof, &[input_hash::<E>(inputs)])
+}
+
+pub fn verify_prepared_proof<E: Engine>(
+    pvk: &PreparedTruncatedVerifyingKey<E>,
+    proof: &Proof<E>,
+    inputs: &[E::Fr]
+) -> Result<bool, SynthesisError>
+{
+    verifier::verify_prepared_proof(pvk, proof, &[input_hash::<E>(inputs)])
+}
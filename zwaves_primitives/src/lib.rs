@@ -2,8 +2,63 @@
 extern crate lazy_static;
 
 pub mod pedersen_hasher;
-pub mod circuit;
+pub mod pedersen_segments;
+pub mod commitment;
 pub mod verifier;
+pub mod diagnose;
+pub mod snarkjs;
 pub mod serialization;
 pub mod fieldtools;
-pub mod transactions;
\ No newline at end of file
+pub mod multipack;
+pub mod field;
+pub mod transactions;
+pub mod envelope;
+pub mod bundle;
+pub mod sighash;
+pub mod spend_auth;
+pub mod signing_request;
+pub mod frost;
+pub mod memory;
+pub mod metrics;
+pub mod point;
+pub mod sapling_keys;
+pub mod account_keys;
+pub mod address_book;
+pub mod mnemonic;
+pub mod scan;
+pub mod input_hash;
+pub mod audit;
+pub mod aggregate;
+pub mod group_hash;
+pub mod generators;
+pub mod mmr;
+pub mod limbs;
+pub mod uint64;
+pub mod poseidon_params;
+pub mod poseidon;
+pub mod entropy;
+pub mod public_values;
+pub mod migration;
+pub mod tree;
+pub mod tree_delta;
+pub mod witness_source;
+pub mod nullifier_set;
+pub mod merkle_path;
+pub mod proof_cache;
+pub mod out_amount;
+pub mod out_note;
+pub mod note_selection;
+pub mod condition;
+pub mod waves_tx;
+pub mod ride;
+
+#[cfg(feature = "recursion")]
+pub mod recursion;
+
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(any(test, feature = "testing"))]
+pub mod test_circuits;
@@ -0,0 +1,134 @@
+use pairing::PrimeField;
+
+use crate::field;
+
+
+// Integers wider than the field (u256 balances of external tokens) are kept as 64 bit limbs,
+// little-endian, one field element per limb. Every limb is range checked in the circuit, so
+// the arithmetic below is exact and carries are explicit.
+pub const LIMB_BITS: usize = 64;
+pub const U128_LIMBS: usize = 2;
+pub const U256_LIMBS: usize = 4;
+
+// Little-endian 64 bit words
+pub type U256 = [u64; U256_LIMBS];
+
+
+pub fn u128_to_words(x: u128) -> [u64; U128_LIMBS] {
+    [x as u64, (x >> 64) as u64]
+}
+
+pub fn words_to_u128(w: &[u64]) -> Option<u128> {
+    if w.len() > U128_LIMBS && w[U128_LIMBS..].iter().any(|&x| x != 0) {
+        return None;
+    }
+    Some(w.iter().take(U128_LIMBS).enumerate().fold(0u128, |acc, (i, &x)| acc | (x as u128) << (64 * i)))
+}
+
+// Big-endian bytes, as uint256 is encoded by Ethereum
+pub fn u256_from_be_bytes(data: &[u8; 32]) -> U256 {
+    let mut res = [0u64; U256_LIMBS];
+    for (i, w) in res.iter_mut().enumerate() {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&data[32 - 8 * (i + 1)..32 - 8 * i]);
+        *w = u64::from_be_bytes(b);
+    }
+    res
+}
+
+pub fn u256_to_be_bytes(x: &U256) -> [u8; 32] {
+    let mut res = [0u8; 32];
+    for (i, w) in x.iter().enumerate() {
+        res[32 - 8 * (i + 1)..32 - 8 * i].copy_from_slice(&w.to_be_bytes());
+    }
+    res
+}
+
+
+pub fn to_limbs<F: PrimeField>(words: &[u64]) -> Vec<F> {
+    words.iter().map(|&w| field::from_u64(w)).collect()
+}
+
+// None if some limb does not fit in 64 bits
+pub fn from_limbs<F: PrimeField>(limbs: &[F]) -> Option<Vec<u64>> {
+    limbs.iter().map(field::to_u64).collect()
+}
+
+// The value as a single field element, for values narrower than the field (up to 3 limbs)
+pub fn pack<F: PrimeField>(words: &[u64]) -> F {
+    assert!(words.len() * LIMB_BITS < F::CAPACITY as usize, "value does not fit in the field");
+    let mut repr = F::Repr::default();
+    repr.as_mut()[..words.len()].copy_from_slice(words);
+    F::from_repr(repr).unwrap()
+}
+
+// Sum and carry out of the top limb
+pub fn add(a: &[u64], b: &[u64]) -> (Vec<u64>, bool) {
+    assert!(a.len() == b.len());
+    let mut carry = false;
+    let res = a.iter().zip(b.iter()).map(|(&x, &y)| {
+        let (s, c1) = x.overflowing_add(y);
+        let (s, c2) = s.overflowing_add(carry as u64);
+        carry = c1 || c2;
+        s
+    }).collect();
+    (res, carry)
+}
+
+// Difference modulo 2^(64 n) and borrow, the borrow is set iff a < b
+pub fn sub(a: &[u64], b: &[u64]) -> (Vec<u64>, bool) {
+    assert!(a.len() == b.len());
+    let mut borrow = false;
+    let res = a.iter().zip(b.iter()).map(|(&x, &y)| {
+        let (d, b1) = x.overflowing_sub(y);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        borrow = b1 || b2;
+        d
+    }).collect();
+    (res, borrow)
+}
+
+
+#[cfg(test)]
+mod limbs_tests {
+    use super::*;
+    use pairing::bls12_381::Fr;
+    use num::BigUint;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    fn big(w: &[u64]) -> BigUint {
+        w.iter().rev().fold(BigUint::from(0u32), |acc, &x| (acc << 64) + BigUint::from(x))
+    }
+
+    #[test]
+    fn test_limbs() {
+        let mut rng = OsRng::new().unwrap();
+
+        let x = (rng.gen::<u64>() as u128) << 64 | rng.gen::<u64>() as u128;
+        assert!(words_to_u128(&u128_to_words(x)) == Some(x));
+        assert!(words_to_u128(&[1, 2, 0, 0]) == Some(2 << 64 | 1));
+        assert!(words_to_u128(&[1, 2, 3, 0]).is_none());
+        assert!(pack::<Fr>(&u128_to_words(x)) == field::from_u128(x));
+
+        let a = rng.gen::<U256>();
+        let b = rng.gen::<U256>();
+        assert!(u256_from_be_bytes(&u256_to_be_bytes(&a)) == a);
+        assert!(big(&a).to_bytes_be() == u256_to_be_bytes(&a).iter().cloned().skip_while(|&x| x == 0).collect::<Vec<_>>());
+        assert!(from_limbs(&to_limbs::<Fr>(&a)) == Some(a.to_vec()));
+        let mut wide = to_limbs::<Fr>(&a);
+        wide[2] = field::from_u128(1 << 64);
+        assert!(from_limbs(&wide).is_none(), "Limb above 64 bits should be rejected");
+
+        let modulus = BigUint::from(1u32) << 256;
+        let (s, carry) = add(&a, &b);
+        assert!(big(&s) + if carry { modulus.clone() } else { BigUint::from(0u32) } == big(&a) + big(&b));
+        let (d, borrow) = sub(&a, &b);
+        assert!(borrow == (big(&a) < big(&b)));
+        assert!((big(&d) + big(&b)) % &modulus == big(&a));
+
+        assert!(add(&[u64::max_value(); 4], &[1, 0, 0, 0]) == (vec![0; 4], true));
+        assert!(sub(&[0; 4], &[1, 0, 0, 0]) == (vec![u64::max_value(); 4], true));
+        assert!(sub(&a, &a) == (vec![0; 4], false));
+    }
+}
@@ -0,0 +1,237 @@
+use pairing::Engine;
+use bellman::groth16::Parameters;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{io, mem};
+
+
+// Allocator wrapper reporting current and peak heap usage, with an optional cap.
+//
+// zwaves_wasm (and so zwaves_napi) and zwaves_ffi install it over System as their
+// `#[global_allocator]` and register it with register_heap. An allocation over the cap returns
+// null, which Rust turns into an abort, so the cap is only the last line of defence: the proving
+// limit below is checked before every proof and gives a clean error instead.
+pub struct CountingAllocator<A> {
+    inner: A,
+    allocated: AtomicUsize,
+    peak: AtomicUsize,
+    limit: AtomicUsize
+}
+
+impl<A> CountingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        CountingAllocator {
+            inner,
+            allocated: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            limit: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn allocated(&self) -> usize {
+        self.allocated.load(Ordering::Relaxed)
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_peak(&self) {
+        self.peak.store(self.allocated(), Ordering::Relaxed);
+    }
+
+    // 0 means no limit
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    fn reserve(&self, size: usize) -> bool {
+        let limit = self.limit();
+        let prev = self.allocated.fetch_add(size, Ordering::Relaxed);
+        if limit != 0 && prev + size > limit {
+            self.allocated.fetch_sub(size, Ordering::Relaxed);
+            return false;
+        }
+        self.peak.fetch_max(prev + size, Ordering::Relaxed);
+        true
+    }
+
+    fn release(&self, size: usize) {
+        self.allocated.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if !self.reserve(layout.size()) {
+            return std::ptr::null_mut();
+        }
+        let ptr = self.inner.alloc(layout);
+        if ptr.is_null() {
+            self.release(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if !self.reserve(layout.size()) {
+            return std::ptr::null_mut();
+        }
+        let ptr = self.inner.alloc_zeroed(layout);
+        if ptr.is_null() {
+            self.release(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.release(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() {
+            if !self.reserve(new_size - layout.size()) {
+                return std::ptr::null_mut();
+            }
+            let res = self.inner.realloc(ptr, layout, new_size);
+            if res.is_null() {
+                self.release(new_size - layout.size());
+            }
+            res
+        } else {
+            let res = self.inner.realloc(ptr, layout, new_size);
+            if !res.is_null() {
+                self.release(layout.size() - new_size);
+            }
+            res
+        }
+    }
+}
+
+
+// Upper estimate of the heap used by a proof on top of the parameters, for bellman's
+// create_proof and for zwaves_circuit::prover: three FFT domains alive at once, the reduced
+// h coefficients, the input/aux assignments as field elements and as their representations,
+// and the dense copies of the representations for the A and B queries.
+pub fn proving_memory_estimate<E: Engine>(params: &Parameters<E>) -> usize {
+    let fr = mem::size_of::<E::Fr>();
+    let domain = (params.h.len() + 1).next_power_of_two();
+    let assignment = params.vk.ic.len() + params.l.len();
+
+    4 * domain * fr + 5 * assignment * fr
+}
+
+// ErrorKind::OutOfMemory when the estimate is over limit
+pub fn check_proving_memory<E: Engine>(params: &Parameters<E>, limit: usize) -> io::Result<()> {
+    let needed = proving_memory_estimate(params);
+    if needed > limit {
+        return Err(io::Error::new(io::ErrorKind::OutOfMemory, format!("proving needs about {} bytes, but the limit is {}", needed, limit)));
+    }
+    Ok(())
+}
+
+// Same check against the room left under the allocator cap, taking current usage into account.
+pub fn check_proving_memory_with<E: Engine, A>(params: &Parameters<E>, allocator: &CountingAllocator<A>) -> io::Result<()> {
+    let limit = allocator.limit();
+    if limit == 0 {
+        return Ok(());
+    }
+    check_proving_memory(params, limit.saturating_sub(allocator.allocated()))
+}
+
+
+// The heap a proof may use, 0 for no limit: the memory limit of a wasm instance or of the host
+// minus what it needs for itself. Set by the bindings (set_memory_limit, setMemoryLimit,
+// zwaves_set_memory_limit) and checked before every proof by check_proving_memory_limit.
+static PROVING_MEMORY_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+// The global allocator of the binary, when it is a CountingAllocator
+static HEAP: OnceLock<&'static CountingAllocator<System>> = OnceLock::new();
+
+pub fn set_proving_memory_limit(limit: usize) {
+    PROVING_MEMORY_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+pub fn proving_memory_limit() -> usize {
+    PROVING_MEMORY_LIMIT.load(Ordering::Relaxed)
+}
+
+// Called by the crate installing the allocator, the first registration stays
+pub fn register_heap(allocator: &'static CountingAllocator<System>) {
+    let _ = HEAP.set(allocator);
+}
+
+pub fn heap() -> Option<&'static CountingAllocator<System>> {
+    HEAP.get().copied()
+}
+
+// The proof must fit under the proving limit on top of what is allocated already, when the
+// allocator is registered, or on its own otherwise.
+pub fn check_proving_memory_limit<E: Engine>(params: &Parameters<E>) -> io::Result<()> {
+    let limit = proving_memory_limit();
+    if limit == 0 {
+        return Ok(());
+    }
+    let allocated = heap().map_or(0, |h| h.allocated());
+    check_proving_memory(params, limit.saturating_sub(allocated))
+}
+
+
+#[cfg(test)]
+mod memory_tests {
+    use super::*;
+    use std::alloc::System;
+    use bellman::groth16::generate_random_parameters;
+    use pairing::bls12_381::Bls12;
+    use rand::os::OsRng;
+
+    use crate::test_circuits::Squares;
+
+    #[test]
+    fn test_counting_allocator() {
+        let a = CountingAllocator::new(System);
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+
+        unsafe {
+            let p = a.alloc(layout);
+            assert!(!p.is_null());
+            assert!(a.allocated() == 1000 && a.peak() == 1000);
+
+            let p = a.realloc(p, layout, 3000);
+            assert!(!p.is_null());
+            assert!(a.allocated() == 3000 && a.peak() == 3000);
+
+            a.set_limit(3500);
+            assert!(a.alloc(layout).is_null(), "Allocation over the limit should fail");
+            assert!(a.allocated() == 3000, "Failed allocation should not be counted");
+
+            a.dealloc(p, Layout::from_size_align(3000, 8).unwrap());
+            assert!(a.allocated() == 0 && a.peak() == 3000);
+            a.reset_peak();
+            assert!(a.peak() == 0);
+        }
+    }
+
+    #[test]
+    fn test_check_proving_memory() {
+        let rng = &mut OsRng::new().unwrap();
+        let small = generate_random_parameters::<Bls12, _, _>(Squares(10), rng).unwrap();
+        let big = generate_random_parameters::<Bls12, _, _>(Squares(1000), rng).unwrap();
+
+        assert!(proving_memory_estimate(&small) < proving_memory_estimate(&big));
+        assert!(check_proving_memory(&big, proving_memory_estimate(&big)).is_ok());
+        assert!(check_proving_memory(&big, proving_memory_estimate(&small)).unwrap_err().kind() == io::ErrorKind::OutOfMemory, "Limit below the estimate should be reported");
+
+        let a = CountingAllocator::new(System);
+        assert!(check_proving_memory_with(&big, &a).is_ok(), "No limit means no error");
+        a.set_limit(proving_memory_estimate(&small));
+        assert!(check_proving_memory_with(&big, &a).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::Field;
+
+use std::io;
+
+use crate::pedersen_hasher::{merkle_defaults, merkle_root, MERKLE_MAX_DEPTH};
+use crate::tree::MerkleTree;
+
+
+// Sibling path of a leaf with the depth of the tree in the type, so a path of a tree of another
+// depth does not fit a circuit built for DEPTH. The depth bounds are checked when the type is
+// instantiated: a path or defaults table deeper than the pedersen personalizations allow is a
+// compile error, not a panic in merkle_defaults or the circuit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerklePath<E: JubjubEngine, const DEPTH: usize> {
+    pub sibling: [E::Fr; DEPTH],
+    pub index: u64
+}
+
+// Depths of MerkleTree::new, and defaults tables of such trees, levels 0..=depth
+struct DepthCheck<const DEPTH: usize>;
+
+impl<const DEPTH: usize> DepthCheck<DEPTH> {
+    const PATH: () = assert!(DEPTH < MERKLE_MAX_DEPTH, "merkle depth should be less than MERKLE_MAX_DEPTH");
+    const TABLE: () = assert!(DEPTH >= 1 && DEPTH <= MERKLE_MAX_DEPTH, "defaults table should have 1 to MERKLE_MAX_DEPTH levels");
+}
+
+pub fn merkle_defaults_table<E: JubjubEngine, const LEN: usize>(params: &E::Params) -> [E::Fr; LEN] {
+    let () = DepthCheck::<LEN>::TABLE;
+    let mut res = [E::Fr::zero(); LEN];
+    res.copy_from_slice(&merkle_defaults::<E>(LEN, params));
+    res
+}
+
+impl<E: JubjubEngine, const DEPTH: usize> MerklePath<E, DEPTH> {
+    pub fn new(sibling: [E::Fr; DEPTH], index: u64) -> io::Result<Self> {
+        let () = DepthCheck::<DEPTH>::PATH;
+        if index >> DEPTH != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("index should be less than 2^{}", DEPTH)));
+        }
+        Ok(MerklePath { sibling, index })
+    }
+
+    // Paths coming from the outside, the one place the length is checked at runtime
+    pub fn from_slice(sibling: &[E::Fr], index: u64) -> io::Result<Self> {
+        if sibling.len() != DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("merkle path should have {} siblings, got {}", DEPTH, sibling.len())));
+        }
+        let mut res = [E::Fr::zero(); DEPTH];
+        res.copy_from_slice(sibling);
+        Self::new(res, index)
+    }
+
+    pub fn from_tree(tree: &MerkleTree<E>, index: u64) -> io::Result<Self> {
+        if tree.depth() != DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("tree has depth {}, {} expected", tree.depth(), DEPTH)));
+        }
+        if index >= tree.size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no leaf at the index"));
+        }
+        Self::from_slice(&tree.try_proof(index)?, index)
+    }
+
+    pub fn index_bits(&self) -> [bool; DEPTH] {
+        let mut res = [false; DEPTH];
+        res.iter_mut().enumerate().for_each(|(i, b)| *b = (self.index >> i) & 1 == 1);
+        res
+    }
+
+    pub fn root(&self, leaf: &E::Fr, params: &E::Params) -> E::Fr {
+        merkle_root::<E>(&self.sibling, self.index, leaf, params)
+    }
+}
+
+
+#[cfg(test)]
+mod merkle_path_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    #[test]
+    fn test_merkle_path() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let leaves = (0..5).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let mut tree = MerkleTree::<Bls12>::new(4, params);
+        tree.push_many(&leaves, params).unwrap();
+
+        let path = MerklePath::<Bls12, 4>::from_tree(&tree, 3).unwrap();
+        assert!(path.root(&leaves[3], params) == tree.root());
+        assert!(path.index_bits() == [true, true, false, false]);
+        assert!(merkle_defaults_table::<Bls12, 5>(params)[..] == merkle_defaults::<Bls12>(5, params)[..]);
+
+        assert!(MerklePath::<Bls12, 3>::from_tree(&tree, 3).is_err(), "Path of another depth should be rejected");
+        assert!(MerklePath::<Bls12, 4>::from_tree(&tree, 5).is_err());
+        assert!(MerklePath::<Bls12, 4>::new(path.sibling, 16).is_err(), "Index should fit the depth");
+        assert!(MerklePath::<Bls12, 4>::from_slice(&path.sibling[..3], 3).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+
+// Process wide counters of the costly operations, for operators exporting stats of a prover or
+// a verifier service without wrapping every call: the library counts at the call sites, the
+// service reads snapshot() or serves render_prometheus(). The counters are atomics, relaxed:
+// every value is exact, a snapshot is not taken at one instant across counters.
+//
+// Counting is compiled in with the "metrics" feature only. Without it inc and time do nothing
+// and every snapshot is zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    PedersenHashes,
+    PedersenCacheHits,
+    ProofsGenerated,
+    ProofsVerified,
+    ProofsRejected
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    Prove,
+    Verify
+}
+
+const COUNTERS: [Counter; 5] = [Counter::PedersenHashes, Counter::PedersenCacheHits, Counter::ProofsGenerated, Counter::ProofsVerified, Counter::ProofsRejected];
+const TIMINGS: [Timing; 2] = [Timing::Prove, Timing::Verify];
+
+impl Counter {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Counter::PedersenHashes => "zwaves_pedersen_hashes_total",
+            Counter::PedersenCacheHits => "zwaves_pedersen_cache_hits_total",
+            Counter::ProofsGenerated => "zwaves_proofs_generated_total",
+            Counter::ProofsVerified => "zwaves_proofs_verified_total",
+            Counter::ProofsRejected => "zwaves_proofs_rejected_total"
+        }
+    }
+}
+
+impl Timing {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Timing::Prove => "zwaves_prove_seconds",
+            Timing::Verify => "zwaves_verify_seconds"
+        }
+    }
+}
+
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static COUNTER_VALUES: [AtomicU64; 5] = [ZERO; 5];
+static TIMING_CALLS: [AtomicU64; 2] = [ZERO; 2];
+static TIMING_NANOS: [AtomicU64; 2] = [ZERO; 2];
+
+#[inline]
+pub fn inc(counter: Counter, n: u64) {
+    if cfg!(feature = "metrics") {
+        COUNTER_VALUES[counter as usize].fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+#[inline]
+pub fn time<T, F: FnOnce() -> T>(timing: Timing, f: F) -> T {
+    if !cfg!(feature = "metrics") {
+        return f();
+    }
+    let start = Instant::now();
+    let res = f();
+    let elapsed = start.elapsed();
+    TIMING_CALLS[timing as usize].fetch_add(1, Ordering::Relaxed);
+    TIMING_NANOS[timing as usize].fetch_add(elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos()), Ordering::Relaxed);
+    res
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub counters: [u64; 5],
+    // (calls, total nanoseconds)
+    pub timings: [(u64, u64); 2]
+}
+
+impl MetricsSnapshot {
+    pub fn counter(&self, counter: Counter) -> u64 {
+        self.counters[counter as usize]
+    }
+
+    // seconds, None before the first call
+    pub fn average(&self, timing: Timing) -> Option<f64> {
+        let (calls, nanos) = self.timings[timing as usize];
+        if calls == 0 {
+            return None;
+        }
+        Some(nanos as f64 / calls as f64 / 1e9)
+    }
+
+    // Text exposition format: counters, and a summary (_count, _sum) per timing
+    pub fn render_prometheus(&self) -> String {
+        let mut res = String::new();
+        for c in COUNTERS.iter() {
+            writeln!(res, "# TYPE {} counter\n{} {}", c.name(), c.name(), self.counter(*c)).unwrap();
+        }
+        for t in TIMINGS.iter() {
+            let (calls, nanos) = self.timings[*t as usize];
+            writeln!(res, "# TYPE {} summary\n{}_count {}\n{}_sum {}", t.name(), t.name(), calls, t.name(), nanos as f64 / 1e9).unwrap();
+        }
+        res
+    }
+}
+
+pub fn snapshot() -> MetricsSnapshot {
+    let mut res = MetricsSnapshot::default();
+    for (v, c) in res.counters.iter_mut().zip(COUNTER_VALUES.iter()) {
+        *v = c.load(Ordering::Relaxed);
+    }
+    for (i, v) in res.timings.iter_mut().enumerate() {
+        *v = (TIMING_CALLS[i].load(Ordering::Relaxed), TIMING_NANOS[i].load(Ordering::Relaxed));
+    }
+    res
+}
+
+pub fn render_prometheus() -> String {
+    snapshot().render_prometheus()
+}
+
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus() {
+        let s = MetricsSnapshot { counters: [10, 4, 2, 3, 1], timings: [(2, 3_000_000_000), (0, 0)] };
+        assert!(s.average(Timing::Prove) == Some(1.5) && s.average(Timing::Verify).is_none());
+        let text = s.render_prometheus();
+        assert!(text.contains("# TYPE zwaves_pedersen_hashes_total counter\nzwaves_pedersen_hashes_total 10\n"));
+        assert!(text.contains("zwaves_pedersen_cache_hits_total 4\n"));
+        assert!(text.contains("zwaves_prove_seconds_count 2\nzwaves_prove_seconds_sum 3\n"));
+    }
+
+    // other tests run in parallel and count too, only growth is checked
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_counting() {
+        let before = snapshot();
+        inc(Counter::ProofsVerified, 2);
+        assert!(time(Timing::Verify, || 7) == 7);
+        let after = snapshot();
+        assert!(after.counter(Counter::ProofsVerified) >= before.counter(Counter::ProofsVerified) + 2);
+        assert!(after.timings[Timing::Verify as usize].0 > before.timings[Timing::Verify as usize].0);
+    }
+}
@@ -0,0 +1,382 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::pedersen_hash::Personalization;
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use std::io::{self, Read, Write};
+
+use crate::pedersen_hasher::{compress, MERKLE_MAX_DEPTH};
+use crate::poseidon::Poseidon;
+use crate::serialization::read_fr_repr_be;
+
+
+// Rebuilds the note tree under another node hash, side by side with the current Pedersen
+// tree. The leaves are fed in order, in chunks of any size; both trees keep only their
+// frontier (the pending left node of every level), so the state is small and can be saved
+// between chunks and resumed. When all the leaves are in, the artifact states the old root,
+// the new root and the leaf count of the same leaf sequence: anyone holding the leaves can
+// recompute it, and the old root is checked against the root on chain.
+//
+// Empty leaves are zero in both trees, node hashes depend on the level like MerkleTree(level).
+pub const MIGRATION_VERSION: u32 = 1;
+
+
+pub trait TreeHasher<E: JubjubEngine> {
+    // Written into the state and the artifact, a state is only resumed with the same hashers
+    fn id(&self) -> &'static str;
+    fn compress(&self, left: &E::Fr, right: &E::Fr, level: usize) -> E::Fr;
+}
+
+pub struct PedersenTreeHasher<'a, E: JubjubEngine> {
+    pub params: &'a E::Params
+}
+
+impl<'a, E: JubjubEngine> TreeHasher<E> for PedersenTreeHasher<'a, E> {
+    fn id(&self) -> &'static str {
+        "pedersen"
+    }
+
+    fn compress(&self, left: &E::Fr, right: &E::Fr, level: usize) -> E::Fr {
+        compress::<E>(left, right, Personalization::MerkleTree(level), self.params)
+    }
+}
+
+// The node is the first rate element of the permutation of [level, left, right]: the level is
+// the capacity element, so the levels are separated as with MerkleTree(level)
+pub struct PoseidonTreeHasher<'a, E: JubjubEngine> {
+    poseidon: &'a Poseidon<E::Fr>
+}
+
+impl<'a, E: JubjubEngine> PoseidonTreeHasher<'a, E> {
+    pub fn new(poseidon: &'a Poseidon<E::Fr>) -> io::Result<Self> {
+        if poseidon.t() != 3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "tree hasher needs the poseidon permutation of width 3"));
+        }
+        Ok(PoseidonTreeHasher { poseidon })
+    }
+}
+
+impl<'a, E: JubjubEngine> TreeHasher<E> for PoseidonTreeHasher<'a, E> {
+    fn id(&self) -> &'static str {
+        "poseidon"
+    }
+
+    // any u64 is below the modulus
+    fn compress(&self, left: &E::Fr, right: &E::Fr, level: usize) -> E::Fr {
+        let mut state = [E::Fr::from_repr((level as u64).into()).unwrap(), *left, *right];
+        self.poseidon.permute_in_place(&mut state);
+        state[1]
+    }
+}
+
+
+// Append-only tree of fixed depth, keeping the frontier and the empty subtree roots.
+// filled[depth] is the root once the tree is full.
+struct Frontier<E: JubjubEngine> {
+    filled: Vec<E::Fr>,
+    defaults: Vec<E::Fr>
+}
+
+impl<E: JubjubEngine> Frontier<E> {
+    fn new<H: TreeHasher<E>>(hasher: &H, depth: usize) -> Self {
+        let mut defaults = vec![E::Fr::zero()];
+        for l in 0..depth {
+            let d = defaults[l];
+            defaults.push(hasher.compress(&d, &d, l));
+        }
+        Frontier { filled: vec![E::Fr::zero(); depth + 1], defaults }
+    }
+
+    fn append<H: TreeHasher<E>>(&mut self, hasher: &H, count: u64, leaf: &E::Fr) {
+        let mut cur = *leaf;
+        for l in 0..self.filled.len() {
+            if (count >> l) & 1 == 0 {
+                self.filled[l] = cur;
+                return;
+            }
+            cur = hasher.compress(&self.filled[l], &cur, l);
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.defaults.len() - 1
+    }
+
+    fn root<H: TreeHasher<E>>(&self, hasher: &H, count: u64) -> E::Fr {
+        if count >> self.depth() == 1 {
+            return self.filled[self.depth()];
+        }
+        let mut cur = self.defaults[0];
+        for l in 0..self.depth() {
+            cur = if (count >> l) & 1 == 1 {
+                hasher.compress(&self.filled[l], &cur, l)
+            } else {
+                hasher.compress(&cur, &self.defaults[l], l)
+            };
+        }
+        cur
+    }
+}
+
+
+fn write_fr<E: JubjubEngine, W: Write>(x: &E::Fr, mut writer: W) -> io::Result<()> {
+    x.into_repr().write_be(&mut writer)
+}
+
+fn read_fr<E: JubjubEngine, R: Read>(mut reader: R) -> io::Result<E::Fr> {
+    let mut buf = [0u8; 32];
+    reader.read_exact(&mut buf)?;
+    E::Fr::from_repr(read_fr_repr_be::<E::Fr>(&buf)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))
+}
+
+fn write_id<W: Write>(id: &str, mut writer: W) -> io::Result<()> {
+    writer.write_u8(id.len() as u8)?;
+    writer.write_all(id.as_bytes())
+}
+
+fn check_id<R: Read>(expected: &str, mut reader: R) -> io::Result<()> {
+    let mut id = vec![0u8; reader.read_u8()? as usize];
+    reader.read_exact(&mut id)?;
+    if id != expected.as_bytes() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("migration state is for another hasher, expected {}", expected)));
+    }
+    Ok(())
+}
+
+
+pub struct Migration<E: JubjubEngine, O: TreeHasher<E>, N: TreeHasher<E>> {
+    old_hasher: O,
+    new_hasher: N,
+    count: u64,
+    old: Frontier<E>,
+    new: Frontier<E>
+}
+
+impl<E: JubjubEngine, O: TreeHasher<E>, N: TreeHasher<E>> Migration<E, O, N> {
+    pub fn new(old_hasher: O, new_hasher: N, depth: usize) -> Self {
+        assert!(depth <= MERKLE_MAX_DEPTH, "tree depth is limited by {}", MERKLE_MAX_DEPTH);
+        let old = Frontier::new(&old_hasher, depth);
+        let new = Frontier::new(&new_hasher, depth);
+        Migration { old_hasher, new_hasher, count: 0, old, new }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.old.depth()
+    }
+
+    pub fn leaf_count(&self) -> u64 {
+        self.count
+    }
+
+    // Next chunk of leaves, continuing at leaf_count()
+    pub fn push_chunk(&mut self, leaves: &[E::Fr]) -> io::Result<()> {
+        if self.count + leaves.len() as u64 > 1 << self.depth() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many leaves for the tree depth"));
+        }
+        for leaf in leaves.iter() {
+            self.old.append(&self.old_hasher, self.count, leaf);
+            self.new.append(&self.new_hasher, self.count, leaf);
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    pub fn old_root(&self) -> E::Fr {
+        self.old.root(&self.old_hasher, self.count)
+    }
+
+    pub fn new_root(&self) -> E::Fr {
+        self.new.root(&self.new_hasher, self.count)
+    }
+
+    // Fails when the leaves fed so far do not give the current root of the old tree
+    pub fn finish(&self, expected_old_root: &E::Fr) -> io::Result<MigrationArtifact<E>> {
+        let old_root = self.old_root();
+        if old_root != *expected_old_root {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "leaves do not match the old root"));
+        }
+        Ok(MigrationArtifact {
+            old_hasher: self.old_hasher.id(),
+            new_hasher: self.new_hasher.id(),
+            depth: self.depth(),
+            old_root,
+            new_root: self.new_root(),
+            leaf_count: self.count
+        })
+    }
+
+    // version u32 | old id | new id | depth u32 | count u64 | old frontier | new frontier,
+    // ids are u8 length prefixed, integers big-endian, field elements 32 bytes big-endian
+    pub fn write_state<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u32::<BigEndian>(MIGRATION_VERSION)?;
+        write_id(self.old_hasher.id(), &mut writer)?;
+        write_id(self.new_hasher.id(), &mut writer)?;
+        writer.write_u32::<BigEndian>(self.depth() as u32)?;
+        writer.write_u64::<BigEndian>(self.count)?;
+        for x in self.old.filled.iter().chain(self.new.filled.iter()) {
+            write_fr::<E, _>(x, &mut writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_state<R: Read>(old_hasher: O, new_hasher: N, mut reader: R) -> io::Result<Self> {
+        if reader.read_u32::<BigEndian>()? != MIGRATION_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown migration state version"));
+        }
+        check_id(old_hasher.id(), &mut reader)?;
+        check_id(new_hasher.id(), &mut reader)?;
+        let depth = reader.read_u32::<BigEndian>()? as usize;
+        if depth > MERKLE_MAX_DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong tree depth"));
+        }
+        let count = reader.read_u64::<BigEndian>()?;
+        if count > 1 << depth {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many leaves for the tree depth"));
+        }
+
+        let mut res = Self::new(old_hasher, new_hasher, depth);
+        res.count = count;
+        for l in 0..=depth {
+            res.old.filled[l] = read_fr::<E, _>(&mut reader)?;
+        }
+        for l in 0..=depth {
+            res.new.filled[l] = read_fr::<E, _>(&mut reader)?;
+        }
+        Ok(res)
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct MigrationArtifact<E: JubjubEngine> {
+    pub old_hasher: &'static str,
+    pub new_hasher: &'static str,
+    pub depth: usize,
+    pub old_root: E::Fr,
+    pub new_root: E::Fr,
+    pub leaf_count: u64
+}
+
+impl<E: JubjubEngine> PartialEq for MigrationArtifact<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.old_hasher == other.old_hasher && self.new_hasher == other.new_hasher && self.depth == other.depth &&
+            self.old_root == other.old_root && self.new_root == other.new_root && self.leaf_count == other.leaf_count
+    }
+}
+
+impl<E: JubjubEngine> MigrationArtifact<E> {
+    // version u32 | old id | new id | depth u32 | leaf count u64 | old root | new root
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u32::<BigEndian>(MIGRATION_VERSION)?;
+        write_id(self.old_hasher, &mut writer)?;
+        write_id(self.new_hasher, &mut writer)?;
+        writer.write_u32::<BigEndian>(self.depth as u32)?;
+        writer.write_u64::<BigEndian>(self.leaf_count)?;
+        write_fr::<E, _>(&self.old_root, &mut writer)?;
+        write_fr::<E, _>(&self.new_root, &mut writer)
+    }
+
+    pub fn read<R: Read, O: TreeHasher<E>, N: TreeHasher<E>>(old_hasher: &O, new_hasher: &N, mut reader: R) -> io::Result<Self> {
+        if reader.read_u32::<BigEndian>()? != MIGRATION_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown migration artifact version"));
+        }
+        check_id(old_hasher.id(), &mut reader)?;
+        check_id(new_hasher.id(), &mut reader)?;
+        let depth = reader.read_u32::<BigEndian>()? as usize;
+        if depth > MERKLE_MAX_DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong tree depth"));
+        }
+        let leaf_count = reader.read_u64::<BigEndian>()?;
+        if leaf_count > 1 << depth {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many leaves for the tree depth"));
+        }
+        Ok(MigrationArtifact {
+            old_hasher: old_hasher.id(),
+            new_hasher: new_hasher.id(),
+            depth,
+            leaf_count,
+            old_root: read_fr::<E, _>(&mut reader)?,
+            new_root: read_fr::<E, _>(&mut reader)?
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod migration_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::{JUBJUB_PARAMS, subtree_root};
+    use crate::poseidon::POSEIDON_PARAMS;
+
+    fn poseidon() -> PoseidonTreeHasher<'static, Bls12> {
+        PoseidonTreeHasher::new(&*POSEIDON_PARAMS).unwrap()
+    }
+
+    fn migration() -> Migration<Bls12, PedersenTreeHasher<'static, Bls12>, PoseidonTreeHasher<'static, Bls12>> {
+        Migration::new(PedersenTreeHasher { params: &*JUBJUB_PARAMS }, poseidon(), 4)
+    }
+
+    #[test]
+    fn test_migration() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let leaves = (0..11).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let mut padded = leaves.clone();
+        padded.resize(16, Fr::zero());
+        let old_root = subtree_root::<Bls12>(&padded, params).unwrap();
+
+        let mut whole = migration();
+        whole.push_chunk(&leaves).unwrap();
+        let artifact = whole.finish(&old_root).unwrap();
+        assert!(artifact.leaf_count == 11 && artifact.new_root != old_root);
+        assert!(whole.finish(&leaves[0]).is_err(), "Wrong old root should be rejected");
+
+        let mut level = padded.clone();
+        for l in 0..4 {
+            let l = Fr::from_repr((l as u64).into()).unwrap();
+            level = level.chunks(2).map(|p| POSEIDON_PARAMS.permute(&[l, p[0], p[1]]).unwrap()[1]).collect();
+        }
+        assert!(artifact.new_root == level[0]);
+
+        // chunks of 3, 5 and 3 leaves with the state saved and resumed between them
+        let mut state = vec![];
+        for chunk in [&leaves[..3], &leaves[3..8], &leaves[8..]].iter() {
+            let mut m = if state.is_empty() {
+                migration()
+            } else {
+                Migration::read_state(PedersenTreeHasher { params }, poseidon(), &state[..]).unwrap()
+            };
+            m.push_chunk(chunk).unwrap();
+            state.clear();
+            m.write_state(&mut state).unwrap();
+        }
+        let resumed = Migration::<Bls12, _, _>::read_state(PedersenTreeHasher { params }, poseidon(), &state[..]).unwrap();
+        assert!(resumed.finish(&old_root).unwrap() == artifact, "Chunked migration should give the same artifact");
+        assert!(Migration::<Bls12, _, _>::read_state(poseidon(), poseidon(), &state[..]).is_err(), "State of other hashers should be rejected");
+
+        let mut data = vec![];
+        artifact.write(&mut data).unwrap();
+        let read = |data: &[u8]| MigrationArtifact::<Bls12>::read(&PedersenTreeHasher { params }, &poseidon(), data);
+        assert!(read(&data).unwrap() == artifact);
+        // version | 8 pedersen | 8 poseidon | depth at 22 | leaf count at 26
+        let mut wrong = data.clone();
+        wrong[22..26].copy_from_slice(&(MERKLE_MAX_DEPTH as u32 + 1).to_be_bytes());
+        assert!(read(&wrong).is_err(), "Depth over MERKLE_MAX_DEPTH should be rejected");
+        let mut wrong = data.clone();
+        wrong[26..34].copy_from_slice(&17u64.to_be_bytes());
+        assert!(read(&wrong).is_err(), "More leaves than the tree has should be rejected");
+        wrong[26..34].copy_from_slice(&16u64.to_be_bytes());
+        assert!(read(&wrong).is_ok());
+
+        let mut full = migration();
+        full.push_chunk(&padded).unwrap();
+        assert!(full.old_root() == old_root && full.new_root() == artifact.new_root, "Full tree should keep its root");
+        assert!(full.push_chunk(&leaves[..1]).is_err(), "Leaves out of the tree should be rejected");
+        assert!(migration().old_root() == crate::pedersen_hasher::empty_root(4));
+    }
+}
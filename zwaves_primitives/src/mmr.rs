@@ -0,0 +1,200 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::pedersen_hash::Personalization;
+use pairing::{Field, PrimeField};
+
+use std::convert::TryFrom;
+
+use crate::pedersen_hasher::{compress, MERKLE_MAX_DEPTH, MERKLE_RESERVED_LEVEL};
+
+
+// Merkle Mountain Range: an append-only accumulator with no fixed depth. The leaves are split
+// into perfect subtrees (peaks) by the binary expansion of the size, highest first. A peak of
+// height h is hashed like the fixed-depth tree (MerkleTree(level) personalization), so it is
+// equal to pedersen_hasher::subtree_root of its 2^h leaves.
+//
+// root = compress(size, bag), bag = compress(p_0, compress(p_1, ... p_last)) with the bag
+// personalization, so MMRs of different sizes never share a root. The bag personalization is
+// the level reserved for it in pedersen_hasher.rs, which no tree node is hashed with, and the
+// peaks are at most MERKLE_MAX_DEPTH high.
+pub const MMR_BAG_PERSONALIZATION: Personalization = Personalization::MerkleTree(MERKLE_RESERVED_LEVEL);
+pub const MMR_MAX_HEIGHT: usize = MERKLE_MAX_DEPTH;
+
+
+// Heights of the peaks of an MMR with size leaves, highest first
+pub fn peak_heights(size: u64) -> Vec<usize> {
+    (0..64).rev().filter(|h| (size >> h) & 1 == 1).collect()
+}
+
+pub fn bag_peaks<E: JubjubEngine>(peaks: &[E::Fr], size: u64, params: &E::Params) -> E::Fr {
+    let mut bag = match peaks.last() {
+        Some(p) => *p,
+        None => E::Fr::zero()
+    };
+    for p in peaks.iter().rev().skip(1) {
+        bag = compress::<E>(p, &bag, MMR_BAG_PERSONALIZATION, params);
+    }
+    compress::<E>(&E::Fr::from_repr(size.into()).unwrap(), &bag, MMR_BAG_PERSONALIZATION, params)
+}
+
+
+// Inclusion proof of the leaf at index: the merkle path inside its peak and all the peaks
+#[derive(Clone, Debug, PartialEq)]
+pub struct MmrProof<E: JubjubEngine> {
+    pub index: u64,
+    pub size: u64,
+    pub sibling: Vec<E::Fr>,
+    pub peaks: Vec<E::Fr>
+}
+
+// Position of the peak containing the leaf at index, and the index inside this peak
+pub fn peak_position(index: u64, size: u64) -> Option<(usize, u64)> {
+    if index >= size {
+        return None;
+    }
+    let mut start = 0;
+    for (pos, h) in peak_heights(size).into_iter().enumerate() {
+        if index < start + (1 << h) {
+            return Some((pos, index - start));
+        }
+        start += 1 << h;
+    }
+    None
+}
+
+pub fn verify_proof<E: JubjubEngine>(root: &E::Fr, leaf: &E::Fr, proof: &MmrProof<E>, params: &E::Params) -> bool {
+    let heights = peak_heights(proof.size);
+    let (pos, local) = match peak_position(proof.index, proof.size) {
+        Some(p) => p,
+        None => return false
+    };
+    if proof.peaks.len() != heights.len() || proof.sibling.len() != heights[pos] {
+        return false;
+    }
+
+    let mut cur = *leaf;
+    for (i, s) in proof.sibling.iter().enumerate() {
+        cur = if (local >> i) & 1 == 1 {
+            compress::<E>(s, &cur, Personalization::MerkleTree(i), params)
+        } else {
+            compress::<E>(&cur, s, Personalization::MerkleTree(i), params)
+        };
+    }
+
+    cur == proof.peaks[pos] && bag_peaks::<E>(&proof.peaks, proof.size, params) == *root
+}
+
+
+// Keeps every node, levels[h][j] covers the leaves j*2^h .. (j+1)*2^h
+#[derive(Clone)]
+pub struct Mmr<E: JubjubEngine> {
+    levels: Vec<Vec<E::Fr>>
+}
+
+impl<E: JubjubEngine> Mmr<E> {
+    pub fn new() -> Self {
+        Mmr { levels: vec![vec![]] }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.levels[0].len() as u64
+    }
+
+    pub fn leaf(&self, index: u64) -> Option<E::Fr> {
+        usize::try_from(index).ok().and_then(|i| self.levels[0].get(i)).cloned()
+    }
+
+    pub fn append(&mut self, leaf: E::Fr, params: &E::Params) {
+        assert!(self.size() + 1 < 1 << MMR_MAX_HEIGHT, "mmr is full");
+        self.levels[0].push(leaf);
+
+        let mut h = 0;
+        while self.levels[h].len() % 2 == 0 {
+            let n = self.levels[h].len();
+            let node = compress::<E>(&self.levels[h][n - 2], &self.levels[h][n - 1], Personalization::MerkleTree(h), params);
+            if self.levels.len() == h + 1 {
+                self.levels.push(vec![]);
+            }
+            self.levels[h + 1].push(node);
+            h += 1;
+        }
+    }
+
+    pub fn peaks(&self) -> Vec<E::Fr> {
+        let size = self.size();
+        peak_heights(size).into_iter().map(|h| self.levels[h][(size >> h) as usize - 1]).collect()
+    }
+
+    pub fn root(&self, params: &E::Params) -> E::Fr {
+        bag_peaks::<E>(&self.peaks(), self.size(), params)
+    }
+
+    pub fn proof(&self, index: u64) -> Option<MmrProof<E>> {
+        let size = self.size();
+        let (pos, _) = peak_position(index, size)?;
+        let height = peak_heights(size)[pos];
+        let sibling = (0..height).map(|l| self.levels[l][((index >> l) ^ 1) as usize]).collect();
+        Some(MmrProof { index, size, sibling, peaks: self.peaks() })
+    }
+}
+
+impl<E: JubjubEngine> Default for Mmr<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod mmr_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::{JUBJUB_PARAMS, subtree_root};
+
+    #[test]
+    fn test_mmr() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let leaves = (0..11).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+
+        let mut mmr = Mmr::<Bls12>::new();
+        let mut roots = vec![mmr.root(params)];
+        for x in leaves.iter() {
+            mmr.append(*x, params);
+            roots.push(mmr.root(params));
+        }
+        assert!(mmr.size() == 11);
+        assert!(peak_heights(11) == vec![3, 1, 0]);
+        assert!(mmr.peaks()[0] == subtree_root::<Bls12>(&leaves[..8], params).unwrap(), "Peak should be the subtree root");
+        for (i, a) in roots.iter().enumerate() {
+            assert!(roots.iter().skip(i + 1).all(|b| a != b), "Every append should change the root");
+        }
+
+        let root = mmr.root(params);
+        for (i, x) in leaves.iter().enumerate() {
+            let proof = mmr.proof(i as u64).unwrap();
+            assert!(verify_proof::<Bls12>(&root, x, &proof, params), "Proof of leaf {} should be valid", i);
+            assert!(!verify_proof::<Bls12>(&root, &leaves[(i + 1) % 11], &proof, params), "Wrong leaf should be rejected");
+        }
+        assert!(mmr.proof(11).is_none());
+
+        let mut proof = mmr.proof(9).unwrap();
+        proof.index = 8;
+        assert!(!verify_proof::<Bls12>(&root, &leaves[9], &proof, params), "Wrong index should be rejected");
+
+        let mut proof = mmr.proof(3).unwrap();
+        proof.size = 12;
+        assert!(!verify_proof::<Bls12>(&root, &leaves[3], &proof, params), "Wrong size should be rejected");
+
+        // an old proof is checked against the root of its own size
+        let old = {
+            let mut m = Mmr::<Bls12>::new();
+            leaves[..5].iter().for_each(|x| m.append(*x, params));
+            m.proof(2).unwrap()
+        };
+        assert!(verify_proof::<Bls12>(&roots[5], &leaves[2], &old, params));
+        assert!(!verify_proof::<Bls12>(&root, &leaves[2], &old, params));
+    }
+}
@@ -0,0 +1,228 @@
+use blake2_rfc::blake2b::Blake2b;
+use hmac::Hmac;
+use sha2::{Digest, Sha256, Sha512};
+use unicode_normalization::UnicodeNormalization;
+use lazy_static::lazy_static;
+use rand::Rng;
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::sapling_keys::SPENDING_KEY_LEN;
+
+
+// Wallet backups as BIP-39 mnemonic phrases (ZIP-339 for zcash wallets): 12 to 24 words of a
+// 2048 word list encode 128 to 256 bits of entropy and a checksum of its sha256. The seed is
+// PBKDF2-HMAC-SHA512 of the phrase with the salt "mnemonic" || passphrase, both NFKD, and the
+// account key is the master spending key of ZIP-32 derived from the seed, so the same phrase
+// and passphrase give the keys of account_keys on every device.
+//
+// Only the word lists separated by ASCII spaces are included; the phrase is compared with the
+// list after NFKD, so phrases typed with combining accents are accepted.
+pub const SEED_LEN: usize = 64;
+pub const PBKDF2_ROUNDS: usize = 2048;
+pub const MASTER_KEY_PERSONALIZATION: &[u8; 16] = b"ZcashIP32Sapling";
+pub const MNEMONIC_WORDS: [usize; 5] = [12, 15, 18, 21, 24];
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    English,
+    Czech,
+    French,
+    Italian,
+    Spanish
+}
+
+struct Wordlist {
+    words: Vec<&'static str>,
+    index: HashMap<String, u16>
+}
+
+impl Wordlist {
+    fn new(data: &'static str) -> Self {
+        let words = data.lines().collect::<Vec<_>>();
+        assert!(words.len() == 2048, "word list should have 2048 words");
+        let index = words.iter().enumerate().map(|(i, w)| (w.nfkd().collect::<String>(), i as u16)).collect();
+        Wordlist { words, index }
+    }
+}
+
+lazy_static! {
+    static ref ENGLISH: Wordlist = Wordlist::new(include_str!("wordlists/english.txt"));
+    static ref CZECH: Wordlist = Wordlist::new(include_str!("wordlists/czech.txt"));
+    static ref FRENCH: Wordlist = Wordlist::new(include_str!("wordlists/french.txt"));
+    static ref ITALIAN: Wordlist = Wordlist::new(include_str!("wordlists/italian.txt"));
+    static ref SPANISH: Wordlist = Wordlist::new(include_str!("wordlists/spanish.txt"));
+}
+
+impl Language {
+    pub const ALL: [Language; 5] = [Language::English, Language::Czech, Language::French, Language::Italian, Language::Spanish];
+
+    fn wordlist(&self) -> &'static Wordlist {
+        match *self {
+            Language::English => &ENGLISH,
+            Language::Czech => &CZECH,
+            Language::French => &FRENCH,
+            Language::Italian => &ITALIAN,
+            Language::Spanish => &SPANISH
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mnemonic {
+    language: Language,
+    entropy: Vec<u8>
+}
+
+fn checksum(entropy: &[u8]) -> u8 {
+    Sha256::digest(entropy)[0]
+}
+
+impl Mnemonic {
+    pub fn from_entropy(entropy: &[u8], language: Language) -> io::Result<Self> {
+        if entropy.len() < 16 || entropy.len() > 32 || entropy.len() % 4 != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "entropy should be 16, 20, 24, 28 or 32 bytes"));
+        }
+        Ok(Mnemonic { language, entropy: entropy.to_vec() })
+    }
+
+    pub fn generate<R: Rng>(rng: &mut R, words: usize, language: Language) -> io::Result<Self> {
+        if !MNEMONIC_WORDS.contains(&words) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "mnemonic should have 12, 15, 18, 21 or 24 words"));
+        }
+        let entropy = (0..words / 3 * 4).map(|_| rng.gen()).collect::<Vec<u8>>();
+        Self::from_entropy(&entropy, language)
+    }
+
+    // Words separated by any whitespace; a wrong word or checksum is an error, so typos in a
+    // backup are caught before a wallet of other keys is restored
+    pub fn parse(phrase: &str, language: Language) -> io::Result<Self> {
+        let list = language.wordlist();
+        let phrase = phrase.nfkd().collect::<String>();
+        let indices = phrase.split_whitespace()
+            .map(|w| list.index.get(w).cloned().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unknown word {:?}", w))))
+            .collect::<io::Result<Vec<_>>>()?;
+        if !MNEMONIC_WORDS.contains(&indices.len()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "mnemonic should have 12, 15, 18, 21 or 24 words"));
+        }
+
+        let bits = indices.iter().flat_map(|&i| (0..11).rev().map(move |j| (i >> j) & 1 == 1)).collect::<Vec<_>>();
+        let cs_len = bits.len() / 33;
+        let (entropy_bits, cs_bits) = bits.split_at(bits.len() - cs_len);
+        let entropy = entropy_bits.chunks(8).map(|b| b.iter().fold(0u8, |acc, &x| acc << 1 | x as u8)).collect::<Vec<_>>();
+        let cs = cs_bits.iter().fold(0u8, |acc, &x| acc << 1 | x as u8);
+        if cs != checksum(&entropy) >> (8 - cs_len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong mnemonic checksum"));
+        }
+        Self::from_entropy(&entropy, language)
+    }
+
+    // The first language in which the phrase is valid
+    pub fn parse_any(phrase: &str) -> io::Result<Self> {
+        Language::ALL.iter().filter_map(|&l| Self::parse(phrase, l).ok()).next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "phrase is not a valid mnemonic in any language"))
+    }
+
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy
+    }
+
+    pub fn words(&self) -> Vec<&'static str> {
+        let list = self.language.wordlist();
+        let mut bits = self.entropy.iter().flat_map(|&b| (0..8).rev().map(move |j| (b >> j) & 1 == 1)).collect::<Vec<_>>();
+        let cs_len = bits.len() / 32;
+        let cs = checksum(&self.entropy);
+        bits.extend((0..cs_len).map(|j| (cs >> (7 - j)) & 1 == 1));
+        bits.chunks(11).map(|b| list.words[b.iter().fold(0usize, |acc, &x| acc << 1 | x as usize)]).collect()
+    }
+
+    pub fn phrase(&self) -> String {
+        self.words().join(" ")
+    }
+
+    pub fn to_seed(&self, passphrase: &str) -> [u8; SEED_LEN] {
+        let phrase = self.phrase().nfkd().collect::<String>();
+        let salt = format!("mnemonic{}", passphrase).nfkd().collect::<String>();
+        let mut seed = [0u8; SEED_LEN];
+        pbkdf2::pbkdf2::<Hmac<Sha512>>(phrase.as_bytes(), salt.as_bytes(), PBKDF2_ROUNDS, &mut seed);
+        seed
+    }
+
+    // Master spending key of ZIP-32 for the seed, the account key of account_keys
+    pub fn to_account_key(&self, passphrase: &str) -> [u8; SPENDING_KEY_LEN] {
+        master_spending_key(&self.to_seed(passphrase))
+    }
+}
+
+pub fn master_spending_key(seed: &[u8]) -> [u8; SPENDING_KEY_LEN] {
+    let mut h = Blake2b::with_params(64, &[], &[], MASTER_KEY_PERSONALIZATION);
+    h.update(seed);
+    let mut sk = [0u8; SPENDING_KEY_LEN];
+    sk.copy_from_slice(&h.finalize().as_bytes()[..SPENDING_KEY_LEN]);
+    sk
+}
+
+
+#[cfg(test)]
+mod mnemonic_tests {
+    use super::*;
+    use rand::os::OsRng;
+
+    // Vectors of trezor/python-mnemonic, passphrase "TREZOR"
+    #[test]
+    fn test_bip39_vectors() {
+        let vectors = [
+            ("00000000000000000000000000000000",
+             "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+             "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"),
+            ("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+             "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+             "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad")
+        ];
+        for (entropy, phrase, seed) in vectors.iter() {
+            let m = Mnemonic::from_entropy(&hex::decode(entropy).unwrap(), Language::English).unwrap();
+            assert!(m.phrase() == *phrase);
+            assert!(Mnemonic::parse(phrase, Language::English).unwrap() == m);
+            assert!(hex::encode(&m.to_seed("TREZOR")[..]) == *seed);
+        }
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        let mut rng = OsRng::new().unwrap();
+        for &l in Language::ALL.iter() {
+            let m = Mnemonic::generate(&mut rng, 24, l).unwrap();
+            assert!(m.words().len() == 24 && m.entropy().len() == 32);
+            let parsed = Mnemonic::parse(&format!("  {} ", m.words().join("\n")), l).unwrap();
+            assert!(parsed == m && parsed.to_account_key("x") == m.to_account_key("x"));
+        }
+
+        let m = Mnemonic::generate(&mut rng, 12, Language::English).unwrap();
+        assert!(m.to_account_key("") != m.to_account_key("passphrase"), "Passphrase should change the keys");
+        assert!(Mnemonic::parse_any(&m.phrase()).unwrap().language() == Language::English);
+
+        let mut words = m.words();
+        words.swap(0, 1);
+        if words[0] != words[1] {
+            // one in 16 swaps keeps the 4 bit checksum
+            let swapped = Mnemonic::parse(&words.join(" "), Language::English);
+            assert!(swapped.is_err() || swapped.unwrap() != m);
+        }
+        assert!(Mnemonic::parse("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon", Language::English).is_err(), "Wrong checksum should be rejected");
+        assert!(Mnemonic::parse("abandon abandon abandon", Language::English).is_err());
+        assert!(Mnemonic::parse(&m.phrase().replacen(m.words()[0], "zwaves", 1), Language::English).is_err(), "Unknown word should be rejected");
+        assert!(Mnemonic::generate(&mut rng, 13, Language::English).is_err());
+
+        // NFC and NFD spellings of the same french word
+        let french = Mnemonic::from_entropy(&[0x42; 16], Language::French).unwrap();
+        assert!(Mnemonic::parse(&french.phrase().nfd().collect::<String>(), Language::French).unwrap() == french);
+        assert!(Mnemonic::parse(&french.phrase().nfc().collect::<String>(), Language::French).unwrap() == french);
+    }
+}
@@ -0,0 +1,76 @@
+use pairing::PrimeField;
+
+use std::io;
+
+use crate::fieldtools::{LeBits, get_bits_le_fixed};
+
+
+// Bit strings longer than a field element (512 bit hashes, signatures) as public inputs, the
+// multipacking of librustzcash: chunks of CAPACITY bits, little-endian, the last chunk shorter.
+// zwaves_circuit::gadgets::multipack packs the same way in the circuit, so the verifier computes the inputs of
+// a bit string with pack_bits_to_fields and never needs to know how the circuit got them.
+pub fn packed_len<F: PrimeField>(n: usize) -> usize {
+    (n + F::CAPACITY as usize - 1) / F::CAPACITY as usize
+}
+
+pub fn pack_bits_to_fields<F: PrimeField>(bits: &LeBits) -> Vec<F> {
+    bits.chunks(F::CAPACITY as usize).map(|chunk| {
+        let mut res = F::zero();
+        let mut coeff = F::one();
+        for &b in chunk.iter() {
+            if b {
+                res.add_assign(&coeff);
+            }
+            coeff.double();
+        }
+        res
+    }).collect()
+}
+
+// n bits back from the fields, every field should fit its chunk
+pub fn unpack_fields_to_bits<F: PrimeField>(fields: &[F], n: usize) -> io::Result<LeBits> {
+    let capacity = F::CAPACITY as usize;
+    if fields.len() != packed_len::<F>(n) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} bits are packed into {} fields, got {}", n, packed_len::<F>(n), fields.len())));
+    }
+    let mut res = vec![];
+    for (i, x) in fields.iter().enumerate() {
+        let len = capacity.min(n - i * capacity);
+        let bits = get_bits_le_fixed(x, F::NUM_BITS as usize);
+        if bits[len..].iter().any(|&b| b) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("field {} does not fit {} bits", i, len)));
+        }
+        res.extend_from_slice(&bits[..len]);
+    }
+    Ok(LeBits(res))
+}
+
+// Bits of every byte from the lowest, the order of sapling_crypto::circuit::multipack::bytes_to_bits_le
+pub fn bytes_to_bits_le(bytes: &[u8]) -> LeBits {
+    LeBits(bytes.iter().flat_map(|&v| (0..8).map(move |i| (v >> i) & 1 == 1)).collect())
+}
+
+
+#[cfg(test)]
+mod multipack_tests {
+    use super::*;
+    use pairing::bls12_381::Fr;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    #[test]
+    fn test_pack_roundtrip() {
+        let mut rng = OsRng::new().unwrap();
+        let bytes = (0..64).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+        let bits = bytes_to_bits_le(&bytes);
+        let fields = pack_bits_to_fields::<Fr>(&bits);
+        assert!(fields.len() == 3 && packed_len::<Fr>(512) == 3);
+        assert!(unpack_fields_to_bits(&fields, 512).unwrap() == bits);
+
+        // 512 = 254 + 254 + 4
+        let mut bad = fields.clone();
+        bad[2] = Fr::from_str("16").unwrap();
+        assert!(unpack_fields_to_bits(&bad, 512).is_err(), "Last field should fit the 4 bits left");
+        assert!(unpack_fields_to_bits(&fields[..2], 512).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use sapling_crypto::jubjub::JubjubEngine;
+
+use std::io;
+
+use crate::fieldtools::fr_to_repr_u64;
+use crate::transactions::NoteData;
+
+
+// Choice of the input notes of a transfer among the notes a wallet tracks. A transfer spends at
+// most two notes, so every strategy picks one note or a pair of notes of the asset covering the
+// target, and the rest goes to the change output.
+//
+// LargestFirst spends the largest notes, so the fewest notes are touched. SmallestFirst spends
+// the smallest notes which still cover the target and prefers pairs, which sweeps dust into the
+// change. Privacy minimizes the change, an exact match needs no change output at all and leaves
+// no new note linked to the sender; ties go to pairs, so the transfers look alike.
+pub const MAX_SELECTED_NOTES: usize = 2;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    LargestFirst,
+    SmallestFirst,
+    Privacy
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    // indices into the notes passed to select_notes
+    pub inputs: Vec<usize>,
+    pub total: u64,
+    pub change: u64
+}
+
+
+fn amount<E: JubjubEngine>(note: &NoteData<E>) -> io::Result<u64> {
+    let limbs = fr_to_repr_u64(&note.amount).into_iter().collect::<Vec<_>>();
+    if limbs[1..].iter().any(|&l| l != 0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "note amount does not fit 64 bits"));
+    }
+    Ok(limbs[0])
+}
+
+pub fn select_notes<E: JubjubEngine>(
+    notes: &[NoteData<E>],
+    asset_id: &E::Fr,
+    target_amount: u64,
+    strategy: SelectionStrategy
+) -> io::Result<Selection> {
+    if target_amount == 0 {
+        return Ok(Selection { inputs: vec![], total: 0, change: 0 });
+    }
+
+    // (amount, index) of the notes of the asset, ascending
+    let mut candidates = vec![];
+    for (i, n) in notes.iter().enumerate() {
+        let a = amount(n)?;
+        if n.asset_id == *asset_id && a != 0 {
+            candidates.push((a, i));
+        }
+    }
+    candidates.sort();
+
+    let target = u128::from(target_amount);
+    let n = candidates.len();
+    let pair_sum = |i: usize, j: usize| u128::from(candidates[i].0) + u128::from(candidates[j].0);
+
+    // positions in candidates
+    let chosen: Option<Vec<usize>> = match strategy {
+        SelectionStrategy::LargestFirst => {
+            if n > 0 && u128::from(candidates[n - 1].0) >= target {
+                Some(vec![n - 1])
+            } else if n > 1 && pair_sum(n - 2, n - 1) >= target {
+                Some(vec![n - 2, n - 1])
+            } else {
+                None
+            }
+        },
+        SelectionStrategy::SmallestFirst => {
+            // the smallest largest note, with the smallest partner covering the rest
+            (0..n).find_map(|j| {
+                match (0..j).find(|&i| pair_sum(i, j) >= target) {
+                    Some(i) => Some(vec![i, j]),
+                    None if u128::from(candidates[j].0) >= target => Some(vec![j]),
+                    None => None
+                }
+            })
+        },
+        SelectionStrategy::Privacy => {
+            let mut best: Option<(u128, Vec<usize>)> = None;
+            for j in 0..n {
+                let mut sets = vec![vec![j]];
+                sets.extend((0..j).map(|i| vec![i, j]));
+                for s in sets {
+                    let total = s.iter().map(|&k| u128::from(candidates[k].0)).sum::<u128>();
+                    if total < target {
+                        continue;
+                    }
+                    let better = match best {
+                        Some((c, ref b)) => total - target < c || (total - target == c && s.len() > b.len()),
+                        None => true
+                    };
+                    if better {
+                        best = Some((total - target, s));
+                    }
+                }
+            }
+            best.map(|(_, s)| s)
+        }
+    };
+
+    match chosen {
+        Some(s) => {
+            let total = s.iter().map(|&k| u128::from(candidates[k].0)).sum::<u128>();
+            if total > u128::from(u64::max_value()) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "total of the selected notes does not fit 64 bits"));
+            }
+            Ok(Selection {
+                inputs: s.iter().map(|&k| candidates[k].1).collect(),
+                total: total as u64,
+                change: (total - target) as u64
+            })
+        },
+        None => {
+            let best = candidates.iter().rev().take(MAX_SELECTED_NOTES).map(|c| u128::from(c.0)).sum::<u128>();
+            Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("not enough funds: at most {} in {} notes, {} needed", best, MAX_SELECTED_NOTES, target_amount)))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod note_selection_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use pairing::{Field, PrimeField};
+
+    fn notes(amounts: &[u64]) -> Vec<NoteData<Bls12>> {
+        amounts.iter().map(|&a| NoteData {
+            asset_id: Fr::zero(),
+            amount: Fr::from_repr(a.into()).unwrap(),
+            native_amount: Fr::zero(),
+            txid: Fr::zero(),
+            owner: Fr::one()
+        }).collect()
+    }
+
+    #[test]
+    fn test_select_notes() {
+        let mut wallet = notes(&[50, 3, 20, 7, 100, 30]);
+        wallet[5].asset_id = Fr::one();
+        let select = |target, s| select_notes(&wallet, &Fr::zero(), target, s);
+
+        assert!(select(60, SelectionStrategy::LargestFirst).unwrap() == Selection { inputs: vec![4], total: 100, change: 40 });
+        assert!(select(140, SelectionStrategy::LargestFirst).unwrap() == Selection { inputs: vec![0, 4], total: 150, change: 10 });
+        assert!(select(22, SelectionStrategy::SmallestFirst).unwrap() == Selection { inputs: vec![1, 2], total: 23, change: 1 }, "Dust should be swept first");
+        assert!(select(6, SelectionStrategy::SmallestFirst).unwrap().inputs == vec![1, 3]);
+        assert!(select(2, SelectionStrategy::SmallestFirst).unwrap().inputs == vec![1], "Smallest note should be spent alone if it covers the target");
+        assert!(select(57, SelectionStrategy::Privacy).unwrap() == Selection { inputs: vec![3, 0], total: 57, change: 0 }, "Exact match should be preferred");
+        assert!(select(50, SelectionStrategy::Privacy).unwrap().inputs == vec![0]);
+
+        for s in [SelectionStrategy::LargestFirst, SelectionStrategy::SmallestFirst, SelectionStrategy::Privacy].iter() {
+            let res = select(100, *s).unwrap();
+            assert!(res.inputs.len() <= MAX_SELECTED_NOTES && res.total - res.change == 100);
+            assert!(!res.inputs.contains(&5), "Notes of other assets should not be selected");
+            let err = select(151, *s).unwrap_err();
+            assert!(err.to_string() == "not enough funds: at most 150 in 2 notes, 151 needed");
+        }
+        assert!(select(0, SelectionStrategy::Privacy).unwrap().inputs.is_empty());
+    }
+}
@@ -0,0 +1,251 @@
+use pairing::{PrimeField, PrimeFieldRepr};
+use blake2_rfc::blake2s::Blake2s;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+
+use crate::serialization::read_fr_repr_be;
+
+
+// Nullifiers of the spent notes, kept by the node next to the commitment tree: a bundle is
+// valid only if none of its nullifiers is in the set, and its nullifiers go in together when
+// the block is applied. The store is a backend (in memory here; a disk store implements the
+// same trait), the set puts a bloom filter in front of it: nearly every nullifier a node sees
+// is new, and the filter answers those without touching the backend.
+//
+// Snapshot (big-endian): version u8 | u64 count | nullifiers (32 each, ascending). The filter
+// is not written, it is rebuilt on read.
+pub const NULLIFIER_SET_VERSION: u8 = 1;
+pub const NULLIFIER_LEN: usize = 32;
+
+const BLOOM_HASHES: usize = 6;
+const BLOOM_BITS_PER_ITEM: usize = 16;
+const BLOOM_MIN_ITEMS: usize = 1024;
+
+pub type Nullifier = [u8; NULLIFIER_LEN];
+
+
+pub trait NullifierBackend {
+    fn contains(&self, nf: &Nullifier) -> io::Result<bool>;
+    // the set checks the batch first, the backend only stores it
+    fn insert_batch(&mut self, nfs: &[Nullifier]) -> io::Result<()>;
+    fn len(&self) -> usize;
+    fn nullifiers(&self) -> io::Result<Vec<Nullifier>>;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryBackend(HashSet<Nullifier>);
+
+impl NullifierBackend for MemoryBackend {
+    fn contains(&self, nf: &Nullifier) -> io::Result<bool> {
+        Ok(self.0.contains(nf))
+    }
+
+    fn insert_batch(&mut self, nfs: &[Nullifier]) -> io::Result<()> {
+        self.0.extend(nfs.iter().cloned());
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn nullifiers(&self) -> io::Result<Vec<Nullifier>> {
+        Ok(self.0.iter().cloned().collect())
+    }
+}
+
+
+// Sized for BLOOM_BITS_PER_ITEM bits an item, about 0.1% false positives at capacity; the set
+// doubles it when it gets full
+#[derive(Clone, Debug)]
+struct BloomFilter {
+    bits: Vec<u64>,
+    capacity: usize
+}
+
+impl BloomFilter {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(BLOOM_MIN_ITEMS);
+        BloomFilter { bits: vec![0; capacity * BLOOM_BITS_PER_ITEM / 64], capacity }
+    }
+
+    // double hashing of two 64 bit halves of blake2s
+    fn positions(&self, nf: &Nullifier) -> impl Iterator<Item=usize> {
+        let mut h = Blake2s::new(16);
+        h.update(nf);
+        let d = h.finalize();
+        let (mut a, mut b) = ([0u8; 8], [0u8; 8]);
+        a.copy_from_slice(&d.as_bytes()[..8]);
+        b.copy_from_slice(&d.as_bytes()[8..]);
+        let (a, b) = (u64::from_le_bytes(a), u64::from_le_bytes(b) | 1);
+        let m = (self.bits.len() * 64) as u64;
+        (0..BLOOM_HASHES as u64).map(move |i| (a.wrapping_add(i.wrapping_mul(b)) % m) as usize)
+    }
+
+    fn insert(&mut self, nf: &Nullifier) {
+        for p in self.positions(nf).collect::<Vec<_>>() {
+            self.bits[p / 64] |= 1 << (p % 64);
+        }
+    }
+
+    fn maybe_contains(&self, nf: &Nullifier) -> bool {
+        self.positions(nf).all(|p| self.bits[p / 64] & (1 << (p % 64)) != 0)
+    }
+}
+
+
+pub struct NullifierSet<B: NullifierBackend = MemoryBackend> {
+    backend: B,
+    filter: BloomFilter
+}
+
+pub fn nullifier_bytes<F: PrimeField>(nf: &F) -> Nullifier {
+    let mut res = [0u8; NULLIFIER_LEN];
+    nf.into_repr().write_be(&mut res[..]).unwrap();
+    res
+}
+
+impl NullifierSet<MemoryBackend> {
+    pub fn new() -> Self {
+        Self::with_backend(MemoryBackend::default()).unwrap()
+    }
+}
+
+impl<B: NullifierBackend> NullifierSet<B> {
+    // The filter is built from what the backend already holds
+    pub fn with_backend(backend: B) -> io::Result<Self> {
+        let mut filter = BloomFilter::new(backend.len() * 2);
+        for nf in backend.nullifiers()?.iter() {
+            filter.insert(nf);
+        }
+        Ok(NullifierSet { backend, filter })
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn len(&self) -> usize {
+        self.backend.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains<F: PrimeField>(&self, nf: &F) -> io::Result<bool> {
+        self.contains_bytes(&nullifier_bytes(nf))
+    }
+
+    pub fn contains_bytes(&self, nf: &Nullifier) -> io::Result<bool> {
+        if !self.filter.maybe_contains(nf) {
+            return Ok(false);
+        }
+        self.backend.contains(nf)
+    }
+
+    // Nullifiers of a bundle or a block: none of them may be spent or repeat in the batch
+    pub fn check_unspent<F: PrimeField>(&self, nfs: &[F]) -> io::Result<()> {
+        let bytes = nfs.iter().map(nullifier_bytes).collect::<Vec<_>>();
+        self.check_batch(&bytes)
+    }
+
+    fn check_batch(&self, nfs: &[Nullifier]) -> io::Result<()> {
+        let mut seen = HashSet::new();
+        for (i, nf) in nfs.iter().enumerate() {
+            if !seen.insert(nf) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("nullifier {} repeats in the batch", i)));
+            }
+            if self.contains_bytes(nf)? {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("nullifier {} is already spent", i)));
+            }
+        }
+        Ok(())
+    }
+
+    // All or nothing: a batch with a spent or repeated nullifier is rejected whole
+    pub fn insert_batch<F: PrimeField>(&mut self, nfs: &[F]) -> io::Result<()> {
+        let bytes = nfs.iter().map(nullifier_bytes).collect::<Vec<_>>();
+        self.check_batch(&bytes)?;
+        self.backend.insert_batch(&bytes)?;
+        if self.backend.len() > self.filter.capacity {
+            self.filter = BloomFilter::new(self.backend.len() * 2);
+            for nf in self.backend.nullifiers()?.iter() {
+                self.filter.insert(nf);
+            }
+        } else {
+            bytes.iter().for_each(|nf| self.filter.insert(nf));
+        }
+        Ok(())
+    }
+
+    pub fn write_snapshot<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut nfs = self.backend.nullifiers()?;
+        nfs.sort();
+        writer.write_u8(NULLIFIER_SET_VERSION)?;
+        writer.write_u64::<BigEndian>(nfs.len() as u64)?;
+        for nf in nfs.iter() {
+            writer.write_all(nf)?;
+        }
+        Ok(())
+    }
+
+    // Into an empty backend; nullifiers should be ascending field elements
+    pub fn read_snapshot<F: PrimeField, R: Read>(mut reader: R, mut backend: B) -> io::Result<Self> {
+        if reader.read_u8()? != NULLIFIER_SET_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown nullifier set version"));
+        }
+        if backend.len() != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "backend should be empty"));
+        }
+        let n = reader.read_u64::<BigEndian>()?;
+        let mut nfs: Vec<Nullifier> = vec![];
+        for _ in 0..n {
+            let mut nf = [0u8; NULLIFIER_LEN];
+            reader.read_exact(&mut nf)?;
+            F::from_repr(read_fr_repr_be::<F>(&nf)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))?;
+            if nfs.last().map_or(false, |last| *last >= nf) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "nullifiers should be ascending"));
+            }
+            nfs.push(nf);
+        }
+        backend.insert_batch(&nfs)?;
+        Self::with_backend(backend)
+    }
+}
+
+
+#[cfg(test)]
+mod nullifier_set_tests {
+    use super::*;
+    use pairing::bls12_381::Fr;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    #[test]
+    fn test_nullifier_set() {
+        let mut rng = OsRng::new().unwrap();
+        let mut set = NullifierSet::new();
+        let block = (0..3000).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        set.insert_batch(&block).unwrap();
+        assert!(set.len() == 3000 && block.iter().all(|nf| set.contains(nf).unwrap()), "Filter should grow with the set");
+
+        let fresh = rng.gen::<Fr>();
+        assert!(!set.contains(&fresh).unwrap());
+        assert!(set.insert_batch(&[fresh, block[7]]).unwrap_err().to_string() == "nullifier 1 is already spent");
+        assert!(set.insert_batch(&[fresh, fresh]).is_err(), "Repeated nullifier should be rejected");
+        assert!(!set.contains(&fresh).unwrap(), "Rejected batch should not be inserted");
+
+        let mut data = vec![];
+        set.write_snapshot(&mut data).unwrap();
+        let read = NullifierSet::read_snapshot::<Fr, _>(&data[..], MemoryBackend::default()).unwrap();
+        assert!(read.len() == 3000 && read.contains(&block[2999]).unwrap() && !read.contains(&fresh).unwrap());
+
+        let mut unsorted = data.clone();
+        unsorted[9..41].copy_from_slice(&data[41..73]);
+        assert!(NullifierSet::read_snapshot::<Fr, _>(&unsorted[..], MemoryBackend::default()).is_err());
+        assert!(NullifierSet::read_snapshot::<Fr, _>(&data[..data.len() - 1], MemoryBackend::default()).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use pairing::{PrimeField, PrimeFieldRepr};
+use blake2_rfc::blake2s::Blake2s;
+use byteorder::{ByteOrder, LittleEndian};
+
+
+// Amount of an output encrypted to the sender's outgoing viewing key, so the sender can later
+// report what was sent without keeping the notes. Like the ock of Sapling, the key is bound to
+// the output: ock = H(ovk || out_hash), out_hash is the note hash from the public inputs.
+//
+// ciphertext = amount (u64 LE) xor H(ock || 0)[..8] || H(ock || 1 || ct)[..16]
+// The tag tells the sender's outputs from the others when a wallet scans bundles with its ovk.
+pub const OCK_PERSONALIZATION: &[u8; 8] = b"ZWock___";
+pub const OUT_AMOUNT_PERSONALIZATION: &[u8; 8] = b"ZWoutamt";
+pub const OUT_CIPHERTEXT_LEN: usize = 24;
+
+const TAG_LEN: usize = 16;
+
+
+pub fn ock<F: PrimeField>(ovk: &[u8; 32], out_hash: &F) -> [u8; 32] {
+    let mut data = vec![];
+    out_hash.into_repr().write_le(&mut data).unwrap();
+    let mut h = Blake2s::with_params(32, &[], &[], OCK_PERSONALIZATION);
+    h.update(ovk);
+    h.update(&data);
+    let mut res = [0u8; 32];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+fn prf(ock: &[u8; 32], data: &[&[u8]]) -> [u8; 32] {
+    let mut h = Blake2s::with_params(32, &[], &[], OUT_AMOUNT_PERSONALIZATION);
+    h.update(ock);
+    data.iter().for_each(|d| h.update(d));
+    let mut res = [0u8; 32];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+pub fn encrypt_amount<F: PrimeField>(ovk: &[u8; 32], out_hash: &F, amount: u64) -> [u8; OUT_CIPHERTEXT_LEN] {
+    let ock = ock(ovk, out_hash);
+    let mut res = [0u8; OUT_CIPHERTEXT_LEN];
+    LittleEndian::write_u64(&mut res[..8], amount);
+    res[..8].iter_mut().zip(prf(&ock, &[&[0]]).iter()).for_each(|(c, k)| *c ^= k);
+    let tag = prf(&ock, &[&[1], &res[..8]]);
+    res[8..].copy_from_slice(&tag[..TAG_LEN]);
+    res
+}
+
+// None for an output of another sender or a broken ciphertext
+pub fn decrypt_amount<F: PrimeField>(ovk: &[u8; 32], out_hash: &F, ciphertext: &[u8; OUT_CIPHERTEXT_LEN]) -> Option<u64> {
+    let ock = ock(ovk, out_hash);
+    let tag = prf(&ock, &[&[1], &ciphertext[..8]]);
+    if tag[..TAG_LEN] != ciphertext[8..] {
+        return None;
+    }
+    let mut data = [0u8; 8];
+    data.iter_mut().zip(ciphertext[..8].iter().zip(prf(&ock, &[&[0]]).iter())).for_each(|(d, (c, k))| *d = c ^ k);
+    Some(LittleEndian::read_u64(&data))
+}
+
+
+#[cfg(test)]
+mod out_amount_tests {
+    use super::*;
+    use pairing::bls12_381::Fr;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    #[test]
+    fn test_out_amount() {
+        let mut rng = OsRng::new().unwrap();
+        let ovk = rng.gen::<[u8; 32]>();
+        let out_hash = rng.gen::<Fr>();
+
+        let ct = encrypt_amount(&ovk, &out_hash, 100500);
+        assert!(decrypt_amount(&ovk, &out_hash, &ct) == Some(100500));
+        assert!(decrypt_amount(&rng.gen::<[u8; 32]>(), &out_hash, &ct).is_none(), "Other ovk should not decrypt");
+        assert!(decrypt_amount(&ovk, &rng.gen::<Fr>(), &ct).is_none(), "Ciphertext should be bound to its output");
+        assert!(encrypt_amount(&ovk, &rng.gen::<Fr>(), 100500)[..] != ct[..]);
+
+        let mut broken = ct;
+        broken[0] ^= 1;
+        assert!(decrypt_amount(&ovk, &out_hash, &broken).is_none(), "Changed amount should be detected");
+    }
+}
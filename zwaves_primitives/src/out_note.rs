@@ -0,0 +1,137 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::{PrimeField, PrimeFieldRepr};
+use blake2_rfc::blake2s::Blake2s;
+
+use crate::out_amount::ock;
+use crate::serialization::read_fr_repr_be;
+use crate::transactions::{note_hash, NoteData};
+
+
+// The whole note of an output (recipient, asset, amounts, txid) and its memo encrypted to the
+// sender's ovk, so a wallet restored from its keys rebuilds what it sent and not only what it
+// received. The key is the ock of out_amount, bound to the output hash; the decrypted note is
+// checked against that hash, so a recovered note is the note of the output on chain.
+//
+// plaintext = asset_id | amount | native_amount | txid | owner (32 bytes BE each) | memo (512)
+// ciphertext = plaintext xor H(ock || 0 || i) for the 32 byte blocks i || H(ock || 1 || ct)[..16]
+//
+// The ciphertext is the tail of the encrypted note of its output in a bundle, after whatever
+// the recipient reads (see TransactionBundle::recover_sent_notes).
+pub const OUT_NOTE_PERSONALIZATION: &[u8; 8] = b"ZWoutnte";
+pub const MEMO_LEN: usize = 512;
+pub const OUT_NOTE_PLAINTEXT_LEN: usize = 5 * 32 + MEMO_LEN;
+pub const OUT_NOTE_CIPHERTEXT_LEN: usize = OUT_NOTE_PLAINTEXT_LEN + TAG_LEN;
+
+const TAG_LEN: usize = 16;
+
+
+pub struct SentNote<E: JubjubEngine> {
+    pub note: NoteData<E>,
+    pub memo: [u8; MEMO_LEN]
+}
+
+fn prf(ock: &[u8; 32], data: &[&[u8]]) -> [u8; 32] {
+    let mut h = Blake2s::with_params(32, &[], &[], OUT_NOTE_PERSONALIZATION);
+    h.update(ock);
+    data.iter().for_each(|d| h.update(d));
+    let mut res = [0u8; 32];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+fn apply_keystream(ock: &[u8; 32], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(32).enumerate() {
+        let k = prf(ock, &[&[0], &(i as u32).to_le_bytes()]);
+        chunk.iter_mut().zip(k.iter()).for_each(|(c, k)| *c ^= k);
+    }
+}
+
+fn tag(ock: &[u8; 32], ct: &[u8]) -> [u8; TAG_LEN] {
+    let mut res = [0u8; TAG_LEN];
+    res.copy_from_slice(&prf(ock, &[&[1], ct])[..TAG_LEN]);
+    res
+}
+
+fn note_fields<E: JubjubEngine>(note: &NoteData<E>) -> [E::Fr; 5] {
+    [note.asset_id, note.amount, note.native_amount, note.txid, note.owner]
+}
+
+
+// out_hash is the note hash of the output, as in the public inputs
+pub fn encrypt_note<E: JubjubEngine>(ovk: &[u8; 32], out_hash: &E::Fr, sent: &SentNote<E>) -> [u8; OUT_NOTE_CIPHERTEXT_LEN] {
+    let ock = ock(ovk, out_hash);
+    let mut res = [0u8; OUT_NOTE_CIPHERTEXT_LEN];
+    for (i, x) in note_fields(&sent.note).iter().enumerate() {
+        x.into_repr().write_be(&mut res[i * 32..(i + 1) * 32]).unwrap();
+    }
+    res[5 * 32..OUT_NOTE_PLAINTEXT_LEN].copy_from_slice(&sent.memo);
+    apply_keystream(&ock, &mut res[..OUT_NOTE_PLAINTEXT_LEN]);
+    let tag = tag(&ock, &res[..OUT_NOTE_PLAINTEXT_LEN]);
+    res[OUT_NOTE_PLAINTEXT_LEN..].copy_from_slice(&tag);
+    res
+}
+
+// None for an output of another sender, a broken ciphertext or a note of another hash
+pub fn decrypt_note<E: JubjubEngine>(ovk: &[u8; 32], out_hash: &E::Fr, ciphertext: &[u8], params: &E::Params) -> Option<SentNote<E>> {
+    if ciphertext.len() != OUT_NOTE_CIPHERTEXT_LEN {
+        return None;
+    }
+    let ock = ock(ovk, out_hash);
+    if tag(&ock, &ciphertext[..OUT_NOTE_PLAINTEXT_LEN])[..] != ciphertext[OUT_NOTE_PLAINTEXT_LEN..] {
+        return None;
+    }
+    let mut data = ciphertext[..OUT_NOTE_PLAINTEXT_LEN].to_vec();
+    apply_keystream(&ock, &mut data);
+
+    let mut f = vec![];
+    for chunk in data[..5 * 32].chunks(32) {
+        f.push(E::Fr::from_repr(read_fr_repr_be::<E::Fr>(chunk).ok()?).ok()?);
+    }
+    let note = NoteData { asset_id: f[0], amount: f[1], native_amount: f[2], txid: f[3], owner: f[4] };
+    if note_hash(&note, params).ok()? != *out_hash {
+        return None;
+    }
+    let mut memo = [0u8; MEMO_LEN];
+    memo.copy_from_slice(&data[5 * 32..]);
+    Some(SentNote { note, memo })
+}
+
+
+#[cfg(test)]
+mod out_note_tests {
+    use super::*;
+    use pairing::bls12_381::Bls12;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+    use crate::transactions::note_hash;
+    use crate::field;
+
+    #[test]
+    fn test_out_note() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let ovk = rng.gen::<[u8; 32]>();
+        let note = NoteData::<Bls12> { asset_id: field::from_u64(7), amount: field::from_u64(100500), native_amount: field::from_u64(3), txid: rng.gen(), owner: rng.gen() };
+        let out_hash = note_hash(&note, params).unwrap();
+        let mut memo = [0u8; MEMO_LEN];
+        memo[..5].copy_from_slice(b"rent!");
+
+        let ct = encrypt_note(&ovk, &out_hash, &SentNote { note: note.clone(), memo });
+        let sent = decrypt_note::<Bls12>(&ovk, &out_hash, &ct, params).unwrap();
+        assert!(sent.note.owner == note.owner && sent.note.amount == note.amount && sent.memo[..] == memo[..]);
+        assert!(decrypt_note::<Bls12>(&rng.gen::<[u8; 32]>(), &out_hash, &ct, params).is_none(), "Other ovk should not decrypt");
+        assert!(decrypt_note::<Bls12>(&ovk, &rng.gen(), &ct, params).is_none(), "Ciphertext should be bound to its output");
+        assert!(decrypt_note::<Bls12>(&ovk, &out_hash, &ct[1..], params).is_none());
+
+        let mut broken = ct;
+        broken[100] ^= 1;
+        assert!(decrypt_note::<Bls12>(&ovk, &out_hash, &broken, params).is_none(), "Changed note should be detected");
+
+        // A sender encrypting a note other than the output's is caught by the hash
+        let other = NoteData::<Bls12> { amount: field::from_u64(1), ..note };
+        let ct = encrypt_note(&ovk, &out_hash, &SentNote { note: other, memo });
+        assert!(decrypt_note::<Bls12>(&ovk, &out_hash, &ct, params).is_none());
+    }
+}
@@ -1,44 +1,125 @@
-use sapling_crypto::jubjub::{JubjubEngine};
+use sapling_crypto::jubjub::{JubjubEngine, JubjubBls12};
 use sapling_crypto::pedersen_hash::{pedersen_hash, Personalization};
 
 use pairing::{Field, PrimeField};
-use crate::fieldtools;
+use pairing::bls12_381::{Bls12, Fr};
+use crate::fieldtools::{LeBits, get_bits_le_fixed, get_bits_le_checked};
+use crate::metrics::{self, Counter};
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::io;
+use std::sync::RwLock;
 
 use num::Integer;
 
 
-pub fn u64_to_bits_le(x:u64) -> Vec<bool> {
+pub fn u64_to_bits_le(x:u64) -> LeBits {
     let mut res = Vec::with_capacity(64);
     for i in 0..63 {
         res.push((x & (1u64<<i)) != 0);
     }
-    res
+    LeBits(res)
+}
+
+
+
+// Presets for hash and hash_bits. HASH_PERSONALIZATION is the historical one, every existing
+// hash of a single element (test vectors included) uses it.
+//
+// Sapling has no free personalization left (NoteCommitment and the 63 merkle levels take all
+// 6 bits), so leaf domains reuse the merkle levels below MERKLE_RESERVED_LEVEL. This is safe for a single element: it is
+// 87 generator chunks long, a compress is 172, and the two could not collide on the same level.
+pub const HASH_PERSONALIZATION: Personalization = Personalization::NoteCommitment;
+
+pub fn leaf_personalization(domain: usize) -> Personalization {
+    assert!(domain < MERKLE_MAX_DEPTH, "leaf domain should be less than {}", MERKLE_MAX_DEPTH);
+    Personalization::MerkleTree(domain)
+}
+
+
+// Hashes of small inputs are kept once computed: an input with no bit set above the lowest
+// SMALL_INPUT_BITS is looked up by (personalization, length, low bits) first. The zero leaf,
+// small integers and zero padded memos are most of what tree defaults and scanning hash. Only
+// Bls12 is cached, every JubjubBls12 has the same generators so params do not matter.
+pub const SMALL_INPUT_BITS: usize = 8;
+
+lazy_static! {
+    static ref SMALL_HASHES: RwLock<HashMap<(u8, usize, u8), Fr>> = RwLock::new(HashMap::new());
+}
+
+fn to_u8(bits: &[bool]) -> u8 {
+    bits.iter().enumerate().fold(0u8, |acc, (i, &b)| acc | ((b as u8) << i))
+}
+
+fn small_input_key(personalization: &Personalization, input: &LeBits) -> Option<(u8, usize, u8)> {
+    let low = SMALL_INPUT_BITS.min(input.len());
+    if input[low..].iter().any(|&b| b) {
+        return None;
+    }
+    Some((to_u8(&personalization.get_bits()), input.len(), to_u8(&input[..low])))
 }
 
+fn hash_bits_uncached<E:JubjubEngine>(personalization: Personalization, input: LeBits, params: &E::Params) -> E::Fr {
+    metrics::inc(Counter::PedersenHashes, 1);
+    pedersen_hash::<E, _>(personalization, input, params).into_xy().0
+}
 
+pub fn hash_bits<E:JubjubEngine>(personalization: Personalization, input: LeBits, params: &E::Params) -> E::Fr {
+    let key = match small_input_key(&personalization, &input) {
+        Some(key) if TypeId::of::<E>() == TypeId::of::<Bls12>() => key,
+        _ => return hash_bits_uncached::<E>(personalization, input, params)
+    };
+    if let Some(res) = SMALL_HASHES.read().unwrap().get(&key) {
+        metrics::inc(Counter::PedersenCacheHits, 1);
+        return *(res as &dyn Any).downcast_ref::<E::Fr>().unwrap();
+    }
+    let res = hash_bits_uncached::<E>(personalization, input, params);
+    SMALL_HASHES.write().unwrap().insert(key, *(&res as &dyn Any).downcast_ref::<Fr>().unwrap());
+    res
+}
 
-pub fn hash_bits<E, I>(input: I, params: &E::Params) -> E::Fr 
-    where I: IntoIterator<Item=bool>,
-    E: JubjubEngine
-{
-    pedersen_hash::<E, _>(Personalization::NoteCommitment, input, params).into_xy().0
+// hash_fixed with NUM_BITS, every field element fits
+pub fn hash<E:JubjubEngine>(data: &E::Fr, personalization: Personalization, params: &E::Params) -> E::Fr {
+    hash_bits::<E>(personalization, get_bits_le_fixed(data, E::Fr::NUM_BITS as usize), params)
 }
 
-pub fn hash<E:JubjubEngine>(data: &E::Fr, params: &E::Params) -> E::Fr {
-    hash_bits::<E, _>(fieldtools::fr_to_repr_bool(data).into_iter().take(E::Fr::NUM_BITS as usize), params)
+// Hash of data as a bits long string. A value with more significant bits is an error, it would
+// lose its top bits silently otherwise.
+//
+// The length is not encoded: the pedersen hash pads the input with zeros to 3 bit chunks, so
+// lengths rounding up to the same multiple of 3 give the same hash (64 and 66 bits do). Lengths
+// from different chunk counts hash differently, but a domain should fix its length and not rely
+// on it for separation, that is what the personalization is for.
+//
+// Generators cover 945 bits, the personalization takes 6.
+pub const HASH_MAX_BITS: usize = 939;
+
+pub fn hash_fixed<E:JubjubEngine>(data: &E::Fr, bits: usize, personalization: Personalization, params: &E::Params) -> io::Result<E::Fr> {
+    if bits == 0 || bits > HASH_MAX_BITS {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("hash length should be from 1 to {} bits", HASH_MAX_BITS)));
+    }
+    Ok(hash_bits::<E>(personalization, get_bits_le_checked(data, bits)?, params))
 }
 
 
 
 pub fn compress<E:JubjubEngine>(left: &E::Fr, right: &E::Fr, p: Personalization, params: &E::Params) -> E::Fr {
-    let bits = fieldtools::fr_to_repr_bool(left).into_iter().take(E::Fr::NUM_BITS as usize).chain(
-        fieldtools::fr_to_repr_bool(right).into_iter().take(E::Fr::NUM_BITS as usize));
+    let bits = get_bits_le_fixed(left, E::Fr::NUM_BITS as usize).into_iter().chain(
+        get_bits_le_fixed(right, E::Fr::NUM_BITS as usize));
 
+    metrics::inc(Counter::PedersenHashes, 1);
     pedersen_hash::<E, _>(p, bits, params).into_xy().0
 
 }
 
+// Levels count from the leaves: the node over two leaves is MerkleTree(0) and sibling[i] is
+// hashed with MerkleTree(i), so the root of a tree of depth d is at level d and the length of
+// the path is the depth. Nothing here knows the depth of the tree: a path cut short or padded
+// gives the root of a tree of another depth, a valid looking wrong root, and the index bits
+// past the path are ignored. Paths from the outside go through merkle_root_checked.
 pub fn merkle_root<E:JubjubEngine>(sibling: &[E::Fr], index:u64, leaf: &E::Fr, params: &E::Params) -> E::Fr {
+    assert!(sibling.len() <= MERKLE_MAX_DEPTH, "merkle path of {} levels, trees are at most {} deep", sibling.len(), MERKLE_MAX_DEPTH);
     let index_bits = u64_to_bits_le(index);
 
     let mut cur = leaf.clone();
@@ -49,18 +130,98 @@ pub fn merkle_root<E:JubjubEngine>(sibling: &[E::Fr], index:u64, leaf: &E::Fr, p
     cur
 }
 
+pub fn merkle_root_checked<E:JubjubEngine>(sibling: &[E::Fr], index: u64, leaf: &E::Fr, expected_depth: usize, params: &E::Params) -> io::Result<E::Fr> {
+    if expected_depth > MERKLE_MAX_DEPTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("merkle depth should be at most {}", MERKLE_MAX_DEPTH)));
+    }
+    if sibling.len() != expected_depth {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("merkle path should have {} siblings, got {}", expected_depth, sibling.len())));
+    }
+    if expected_depth < 64 && index >> expected_depth != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("index should be less than 2^{}", expected_depth)));
+    }
+    Ok(merkle_root::<E>(sibling, index, leaf, params))
+}
+
+
+// Quaternary trees. Pedersen has generators for 945 bits, less than four elements, so a 4-ary
+// node is two binary levels: 4-ary level l is MerkleTree(2l) over the pairs and MerkleTree(2l+1)
+// on top, and a 4-ary tree of depth d has the root of the binary tree of depth 2d (empty roots
+// are empty_root(2l)). The proof has the three other children of every node, left to right, so
+// a hash with four inputs can replace compress4 without changing the proof layout.
+pub const MERKLE4_MAX_DEPTH: usize = MERKLE_MAX_DEPTH / 2;
+
+pub fn compress4<E:JubjubEngine>(x: &[E::Fr; 4], level: usize, params: &E::Params) -> io::Result<E::Fr> {
+    if level >= MERKLE4_MAX_DEPTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("4-ary level should be less than {}", MERKLE4_MAX_DEPTH)));
+    }
+    let left = compress::<E>(&x[0], &x[1], Personalization::MerkleTree(2 * level), params);
+    let right = compress::<E>(&x[2], &x[3], Personalization::MerkleTree(2 * level), params);
+    Ok(compress::<E>(&left, &right, Personalization::MerkleTree(2 * level + 1), params))
+}
+
+// Children of a 4-ary node with cur at position and the other children in order
+pub fn children4<T: Clone>(cur: &T, sibling: &[T; 3], position: usize) -> [T; 4] {
+    assert!(position < 4, "position should be less than 4");
+    let mut res = sibling.to_vec();
+    res.insert(position, cur.clone());
+    [res[0].clone(), res[1].clone(), res[2].clone(), res[3].clone()]
+}
+
+// index digits in base 4, lowest first
+pub fn merkle_root4<E:JubjubEngine>(sibling: &[[E::Fr; 3]], index: u64, leaf: &E::Fr, params: &E::Params) -> io::Result<E::Fr> {
+    let mut cur = leaf.clone();
+    for (i, s) in sibling.iter().enumerate() {
+        let position = ((index >> (2 * i)) & 3) as usize;
+        cur = compress4::<E>(&children4(&cur, s, position), i, params)?;
+    }
+    Ok(cur)
+}
+
+
+// Root of a subtree over 2^k leaves, k = log2(leaves.len()).
+pub fn subtree_root<E:JubjubEngine>(leaves: &[E::Fr], params: &E::Params) -> Option<E::Fr> {
+    if !leaves.len().is_power_of_two() || leaves.len().trailing_zeros() as usize > MERKLE_MAX_DEPTH {
+        return None;
+    }
+
+    let mut level = leaves.to_vec();
+    let mut i = 0;
+    while level.len() > 1 {
+        level = level.chunks(2).map(|p| compress::<E>(&p[0], &p[1], Personalization::MerkleTree(i), params)).collect();
+        i += 1;
+    }
+    Some(level[0])
+}
+
+// Same as merkle_root, starting at level k: sibling[i] is the sibling at level k+i and
+// index is the number of the 2^k block, i.e. the first leaf index >> k.
+pub fn merkle_root_from_subtree<E:JubjubEngine>(sibling: &[E::Fr], index: u64, subtree_root: &E::Fr, k: usize, params: &E::Params) -> io::Result<E::Fr> {
+    if k + sibling.len() > MERKLE_MAX_DEPTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("merkle path up from level {} of {} levels, trees are at most {} deep", k, sibling.len(), MERKLE_MAX_DEPTH)));
+    }
+    let index_bits = u64_to_bits_le(index);
+
+    let mut cur = subtree_root.clone();
+    for i in 0..sibling.len() {
+        let (left, right) = if index_bits[i] { (sibling[i], cur) } else { (cur, sibling[i]) };
+        cur = compress::<E>(&left, &right, Personalization::MerkleTree(k + i), params);
+    }
+    Ok(cur)
+}
+
 
 pub fn update_merkle_proof<E:JubjubEngine>(sibling: &[E::Fr], index: u64, leaf: &[E::Fr], defaults: &[E::Fr], params: &E::Params) -> Option<Vec<E::Fr>> {
     let proofsz = sibling.len();
     let leafsz = leaf.len();
     let maxproofsz = defaults.len();
-    let index2 = index + leafsz as u64;
-    
-    if proofsz > maxproofsz {
+    let index2 = index.checked_add(leafsz as u64)?;
+
+    if proofsz > maxproofsz || proofsz > MERKLE_MAX_DEPTH {
         return None;
     }
 
-    if index2 >= u64::pow(2, proofsz as u32) {
+    if index2 >> proofsz != 0 {
         return None;
     }
 
@@ -136,12 +297,66 @@ pub fn update_merkle_root_and_proof<E:JubjubEngine>(root: &E::Fr, sibling: &[E::
 
 
 
+// Personalization::MerkleTree(i) is defined for i < 63 (6 bits, the 64th value is
+// NoteCommitment) and sapling_crypto panics deep in the hash on a higher level. The last one,
+// MERKLE_RESERVED_LEVEL, bags the peaks of mmr.rs and no tree may hash with it: a bagged MMR root
+// would otherwise be a tree node over the same kind of children. The node of level i + 1 is
+// hashed with MerkleTree(i), so the highest root level is 62 and a binary tree is at most 62 deep
+// (2^62 leaves), a 4-ary one MERKLE4_MAX_DEPTH. merkle_root asserts the bound with this message
+// (paths from the outside go through merkle_root_checked), the other functions taking a path
+// return an error, levels from the outside go through merkle_personalization and the trees of
+// tree.rs, tree_delta.rs and merkle_path.rs refuse a larger depth.
+pub const MERKLE_RESERVED_LEVEL: usize = 62;
+pub const MERKLE_MAX_DEPTH: usize = MERKLE_RESERVED_LEVEL;
+
+pub fn merkle_personalization(level: usize) -> io::Result<Personalization> {
+    if level >= MERKLE_MAX_DEPTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("merkle level {} is out of the personalizations, trees are at most {} deep", level, MERKLE_MAX_DEPTH)));
+    }
+    Ok(Personalization::MerkleTree(level))
+}
+
+
+/// Roots of empty subtrees for levels `0..n`: level 0 is the zero leaf and
+/// `defaults[i+1] = compress(defaults[i], defaults[i], MerkleTree(i))`.
+///
+/// For Bls12 the levels are taken from the table of `empty_roots`, computed once for all trees,
+/// so a tree of depth 48 or 61 does not hash its defaults again on every `MerkleTree::new`.
 pub fn merkle_defaults<E:JubjubEngine>(n:usize, params:&E::Params) -> Vec<E::Fr> {
-    (0..n).scan((0, E::Fr::zero()), |state, _| {
-        let (i, p) = *state;
-        *state = (i+1, compress::<E>(&p, &p, Personalization::MerkleTree(i), params));
-        Some(p)
-    }).collect()
+    assert!(n <= MERKLE_MAX_DEPTH + 1, "merkle defaults are defined up to level {}", MERKLE_MAX_DEPTH);
+    if TypeId::of::<E>() == TypeId::of::<Bls12>() {
+        return empty_roots()[..n].iter().map(|x| *(x as &dyn Any).downcast_ref::<E::Fr>().unwrap()).collect();
+    }
+    merkle_defaults_uncached::<E>(n, params)
+}
+
+fn merkle_defaults_uncached<E:JubjubEngine>(n:usize, params:&E::Params) -> Vec<E::Fr> {
+    let mut res = Vec::with_capacity(n);
+    let mut cur = E::Fr::zero();
+    for i in 0..n {
+        res.push(cur);
+        if i + 1 < n {
+            cur = compress::<E>(&cur, &cur, Personalization::MerkleTree(i), params);
+        }
+    }
+    res
+}
+
+
+lazy_static! {
+    pub static ref JUBJUB_PARAMS: JubjubBls12 = JubjubBls12::new();
+    static ref EMPTY_ROOTS: Vec<Fr> = merkle_defaults_uncached::<Bls12>(MERKLE_MAX_DEPTH + 1, &JUBJUB_PARAMS);
+}
+
+/// Root of an empty subtree of height `level` for BLS12-381, computed once on first use.
+pub fn empty_root(level: usize) -> Fr {
+    assert!(level <= MERKLE_MAX_DEPTH, "empty root is defined up to level {}", MERKLE_MAX_DEPTH);
+    EMPTY_ROOTS[level]
+}
+
+/// Full table of empty subtree roots for levels `0..=MERKLE_MAX_DEPTH`.
+pub fn empty_roots() -> &'static [Fr] {
+    &EMPTY_ROOTS
 }
 
 
@@ -149,17 +364,33 @@ pub fn merkle_defaults<E:JubjubEngine>(n:usize, params:&E::Params) -> Vec<E::Fr>
 #[cfg(test)]
 mod pedersen_hasher_tests {
     use super::*;
-    use pairing::bls12_381::{Bls12, Fr, FrRepr};
-    use sapling_crypto::jubjub::{JubjubBls12};
+    use pairing::bls12_381::FrRepr;
+    use crate::fieldtools;
+
+    #[test]
+    fn test_small_input_cache() {
+        let params = &*JUBJUB_PARAMS;
+        for &x in [0u64, 1, 255, 256, 1 << 40].iter() {
+            let direct = pedersen_hash::<Bls12, _>(HASH_PERSONALIZATION, get_bits_le_fixed(&crate::field::from_u64::<Fr>(x), Fr::NUM_BITS as usize), params).into_xy().0;
+            assert!(hash::<Bls12>(&crate::field::from_u64(x), HASH_PERSONALIZATION, params) == direct, "Hash of {} should not change", x);
+            assert!(hash::<Bls12>(&crate::field::from_u64(x), HASH_PERSONALIZATION, params) == direct, "Cached hash of {} should be the same", x);
+        }
+        // the length and personalization are part of the key
+        let zero = Fr::zero();
+        assert!(hash_fixed::<Bls12>(&zero, 64, HASH_PERSONALIZATION, params).unwrap() != hash::<Bls12>(&zero, HASH_PERSONALIZATION, params));
+        assert!(hash::<Bls12>(&zero, leaf_personalization(0), params) != hash::<Bls12>(&zero, HASH_PERSONALIZATION, params));
+        assert!(small_input_key(&HASH_PERSONALIZATION, &LeBits(vec![false; 9])).is_some());
+        assert!(small_input_key(&HASH_PERSONALIZATION, &LeBits(vec![false, false, false, false, false, false, false, false, true])).is_none());
+    }
 
     
     #[test]
     fn test_update_merkle_proof() {
         let params = JubjubBls12::new();
         let defaults = merkle_defaults::<Bls12>(48, &params);
-        let elements0 : Vec<_> =  (0..23).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), &params)).collect();
-        let elements1 : Vec<_> =  (23..907).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), &params)).collect();
-        let elements2 : Vec<_> =  (0..907).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), &params)).collect();
+        let elements0 : Vec<_> =  (0..23).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), HASH_PERSONALIZATION, &params)).collect();
+        let elements1 : Vec<_> =  (23..907).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), HASH_PERSONALIZATION, &params)).collect();
+        let elements2 : Vec<_> =  (0..907).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), HASH_PERSONALIZATION, &params)).collect();
 
         let proof0 = update_merkle_proof::<Bls12>(&defaults, 0, &elements0, &defaults, &params).unwrap();
         let proof1 = update_merkle_proof::<Bls12>(&proof0, elements0.len() as u64, &elements1, &defaults, &params).unwrap();
@@ -173,9 +404,9 @@ mod pedersen_hasher_tests {
         let params = JubjubBls12::new();
         let defaults = merkle_defaults::<Bls12>(48, &params);
 
-        let elements0 : Vec<_> =  (0..23).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), &params)).collect();
-        let elements1 : Vec<_> =  (23..907).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), &params)).collect();
-        let elements2 : Vec<_> =  (0..907).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), &params)).collect();
+        let elements0 : Vec<_> =  (0..23).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), HASH_PERSONALIZATION, &params)).collect();
+        let elements1 : Vec<_> =  (23..907).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), HASH_PERSONALIZATION, &params)).collect();
+        let elements2 : Vec<_> =  (0..907).map(|i| hash::<Bls12>(&Fr::from_repr(FrRepr([i as u64, 0u64, 0u64, 0u64])).unwrap(), HASH_PERSONALIZATION, &params)).collect();
 
         let root_default = merkle_root::<Bls12>(&defaults, 0, &Fr::zero(), &params);
 
@@ -186,5 +417,113 @@ mod pedersen_hasher_tests {
         assert!(proof1.into_iter().zip(proof2.into_iter()).all(|(x,y)| x==y), "Proofs must be same");
         assert!(root1==root2, "Roots must be same");
     }
+
+    #[test]
+    fn test_update_merkle_proof_deep() {
+        let params = JubjubBls12::new();
+        let defaults = merkle_defaults::<Bls12>(MERKLE_MAX_DEPTH + 1, &params);
+
+        let proof = update_merkle_proof::<Bls12>(&defaults[..MERKLE_MAX_DEPTH], 0, &[Fr::one()], &defaults, &params).unwrap();
+        let root = merkle_root::<Bls12>(&defaults[..MERKLE_MAX_DEPTH], 0, &Fr::one(), &params);
+        assert!(proof[0] == Fr::one() && merkle_root::<Bls12>(&proof, 1, &Fr::zero(), &params) == root, "Proof of the next leaf should include the pushed one");
+        assert!(update_merkle_proof::<Bls12>(&defaults, 0, &[Fr::one()], &defaults, &params).is_none(), "64 levels do not fit the personalizations");
+        assert!(update_merkle_proof::<Bls12>(&defaults[..3], 7, &[Fr::one()], &defaults, &params).is_none(), "Full tree should have no next leaf");
+        assert!(update_merkle_proof::<Bls12>(&defaults[..3], u64::MAX, &[Fr::one()], &defaults, &params).is_none());
+    }
+
+    #[test]
+    fn test_empty_roots() {
+        let params = JubjubBls12::new();
+        let defaults = merkle_defaults::<Bls12>(MERKLE_MAX_DEPTH + 1, &params);
+
+        assert!(defaults.as_slice() == empty_roots(), "Cached table must match merkle_defaults");
+        assert!(empty_root(0) == Fr::zero(), "Empty leaf must be zero");
+        assert!(empty_root(1) == Fr::from_str("2844901669415300281300718346195343338354231404922385839670861864158643284316").unwrap(), "Empty root of level 1 must match the circuit constant");
+
+        for i in 0..MERKLE_MAX_DEPTH {
+            assert!(empty_root(i+1) == compress::<Bls12>(&empty_root(i), &empty_root(i), Personalization::MerkleTree(i), &params), "Level {} must hash with MerkleTree({})", i+1, i);
+        }
+
+        assert!(merkle_root::<Bls12>(&defaults[..48], 0, &Fr::zero(), &params) == empty_root(48), "Root of empty tree must be empty_root(depth)");
+    }
+
+    #[test]
+    fn test_merkle_root_checked() {
+        let params = JubjubBls12::new();
+        let defaults = merkle_defaults::<Bls12>(8, &params);
+
+        assert!(merkle_root_checked::<Bls12>(&defaults[..8], 5, &Fr::zero(), 8, &params).unwrap() == empty_root(8));
+        // a path of depth 7 fed where depth 8 is expected gives a root, but not of the tree
+        assert!(merkle_root::<Bls12>(&defaults[..7], 5, &Fr::zero(), &params) == empty_root(7));
+        assert!(merkle_root_checked::<Bls12>(&defaults[..7], 5, &Fr::zero(), 8, &params).is_err(), "Path of another depth should be rejected");
+        assert!(merkle_root_checked::<Bls12>(&defaults[..3], 8, &Fr::zero(), 3, &params).is_err(), "Index should fit the depth");
+        assert!(merkle_root_checked::<Bls12>(&[], 0, &Fr::zero(), MERKLE_MAX_DEPTH + 1, &params).is_err());
+    }
+
+    #[test]
+    fn test_merkle_personalization() {
+        assert!(merkle_personalization(MERKLE_MAX_DEPTH - 1).unwrap().get_bits() == Personalization::MerkleTree(MERKLE_MAX_DEPTH - 1).get_bits());
+        assert!(merkle_personalization(MERKLE_RESERVED_LEVEL).is_err(), "The reserved level should be rejected");
+        assert!(merkle_personalization(MERKLE_RESERVED_LEVEL + 1).is_err(), "Level past the personalizations should be rejected");
+        let path = vec![Fr::zero(); MERKLE_MAX_DEPTH + 1];
+        let res = std::panic::catch_unwind(|| merkle_root::<Bls12>(&path, 0, &Fr::zero(), &JUBJUB_PARAMS));
+        assert!(res.unwrap_err().downcast_ref::<String>().unwrap().contains("at most 62 deep"));
+    }
+
+    #[test]
+    fn test_merkle_root4() {
+        let params = JubjubBls12::new();
+        let leaves = (0..16).map(|i| hash::<Bls12>(&Fr::from_str(&i.to_string()).unwrap(), HASH_PERSONALIZATION, &params)).collect::<Vec<_>>();
+        let nodes = leaves.chunks(4).map(|c| compress4::<Bls12>(&[c[0], c[1], c[2], c[3]], 0, &params).unwrap()).collect::<Vec<_>>();
+        let root = subtree_root::<Bls12>(&leaves, &params).unwrap();
+        assert!(compress4::<Bls12>(&[nodes[0], nodes[1], nodes[2], nodes[3]], 1, &params).unwrap() == root, "4-ary tree should have the binary root");
+
+        let others = |v: &[Fr], j: usize| {
+            let g = &v[j / 4 * 4..j / 4 * 4 + 4];
+            let o = (0..4).filter(|&k| k != j % 4).map(|k| g[k]).collect::<Vec<_>>();
+            [o[0], o[1], o[2]]
+        };
+        for i in [0, 5, 10, 15].iter().cloned() {
+            let sibling = [others(&leaves, i), others(&nodes, i / 4)];
+            assert!(merkle_root4::<Bls12>(&sibling, i as u64, &leaves[i], &params).unwrap() == root, "Proof of leaf {} should lead to the root", i);
+            assert!(merkle_root4::<Bls12>(&sibling, (i ^ 1) as u64, &leaves[i], &params).unwrap() != root, "Wrong position should give another root");
+        }
+        assert!(children4(&9, &[1, 2, 3], 2) == [1, 2, 9, 3]);
+        assert!(compress4::<Bls12>(&[empty_root(10); 4], 5, &params).unwrap() == empty_root(12), "Empty roots should be empty_root(2l)");
+        assert!(compress4::<Bls12>(&[empty_root(0); 4], MERKLE4_MAX_DEPTH, &params).is_err(), "Level past the personalizations should be rejected");
+    }
+
+    #[test]
+    fn test_hash_fixed() {
+        let params = JubjubBls12::new();
+        let x = Fr::from_str("1000").unwrap();
+        assert!(hash_fixed::<Bls12>(&x, Fr::NUM_BITS as usize, HASH_PERSONALIZATION, &params).unwrap() == hash::<Bls12>(&x, HASH_PERSONALIZATION, &params));
+        assert!(hash_fixed::<Bls12>(&x, 10, HASH_PERSONALIZATION, &params).is_ok());
+        assert!(hash_fixed::<Bls12>(&x, 64, HASH_PERSONALIZATION, &params).unwrap() != hash::<Bls12>(&x, HASH_PERSONALIZATION, &params));
+        assert!(hash_fixed::<Bls12>(&x, 64, HASH_PERSONALIZATION, &params).unwrap() == hash_fixed::<Bls12>(&x, 66, HASH_PERSONALIZATION, &params).unwrap(),
+            "Lengths are padded to 3 bit chunks and not encoded");
+
+        let err = hash_fixed::<Bls12>(&x, 9, HASH_PERSONALIZATION, &params).unwrap_err();
+        assert!(err.to_string() == "value has 10 significant bits, 9 expected", "Truncation should be an error");
+        assert!(hash_fixed::<Bls12>(&x, 0, HASH_PERSONALIZATION, &params).is_err());
+        assert!(hash_fixed::<Bls12>(&x, HASH_MAX_BITS + 1, HASH_PERSONALIZATION, &params).is_err());
+    }
+
+    #[test]
+    fn test_hash_personalization() {
+        let params = JubjubBls12::new();
+        let x = Fr::from_str("12345").unwrap();
+
+        let bits = fieldtools::fr_to_repr_bool(&x).into_iter().take(Fr::NUM_BITS as usize).collect::<Vec<_>>();
+        assert!(hash::<Bls12>(&x, HASH_PERSONALIZATION, &params) == pedersen_hash::<Bls12, _>(Personalization::NoteCommitment, bits.clone(), &params).into_xy().0);
+        assert!(hash_bits::<Bls12>(leaf_personalization(3), LeBits(bits), &params) == hash::<Bls12>(&x, leaf_personalization(3), &params));
+
+        let leaves = (0..4).map(|i| hash::<Bls12>(&x, leaf_personalization(i), &params)).collect::<Vec<_>>();
+        assert!(!leaves.contains(&hash::<Bls12>(&x, HASH_PERSONALIZATION, &params)), "Leaf domains should differ from the default");
+        for i in 0..4 {
+            assert!(!leaves[i+1..].contains(&leaves[i]), "Leaf domains should differ from each other");
+            assert!(leaves[i] != compress::<Bls12>(&x, &Fr::zero(), Personalization::MerkleTree(i), &params));
+        }
+    }
     
 }
\ No newline at end of file
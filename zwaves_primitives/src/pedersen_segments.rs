@@ -0,0 +1,215 @@
+use sapling_crypto::jubjub::{JubjubEngine, PrimeOrder, edwards, montgomery};
+use sapling_crypto::pedersen_hash::Personalization;
+use sapling_crypto::constants;
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use std::io;
+
+use crate::group_hash::group_hash;
+
+
+// Pedersen hash with the segmenting as a parameter. Sapling cuts the input into segments of 63
+// chunks of 3 bits, one generator per segment, and multiplies the generators natively with
+// tables of 8 bit windows. Generator m is the group hash of m (u32 LE) as in JubjubBls12::new,
+// so the first five are Sapling's and SAPLING_SEGMENTING gives pedersen_hash::pedersen_hash.
+//
+// A segment of c chunks is a scalar below 2^(4c); c = 63 is the longest for which the
+// montgomery additions of the circuit can not meet an exceptional case, so segments can only be
+// shorter. Shorter segments are another hash function, with generators past the fifth.
+//
+// The window is a native trade only, it does not change the hash: a segment costs about
+// 252 / exp_window additions and its tables 2^exp_window points per window. In the circuit a
+// chunk costs the same in every segmenting and a segment costs its conversion to edwards and an
+// addition; bench::bench_segmenting measures both for an input size.
+pub const MAX_CHUNKS_PER_SEGMENT: usize = 63;
+pub const MAX_EXP_WINDOW: u32 = 12;
+pub const CHUNK_BITS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segmenting {
+    pub chunks_per_segment: usize,
+    pub exp_window: u32
+}
+
+pub const SAPLING_SEGMENTING: Segmenting = Segmenting { chunks_per_segment: 63, exp_window: 8 };
+
+impl Segmenting {
+    pub fn segment_bits(&self) -> usize {
+        self.chunks_per_segment * CHUNK_BITS
+    }
+
+    // Segments of an input of bits, personalization included
+    pub fn segments(&self, bits: usize) -> usize {
+        (bits + self.segment_bits() - 1) / self.segment_bits()
+    }
+}
+
+
+pub struct SegmentedParams<E: JubjubEngine> {
+    segmenting: Segmenting,
+    exp: Vec<Vec<Vec<edwards::Point<E, PrimeOrder>>>>,
+    circuit: Vec<Vec<Vec<(E::Fr, E::Fr)>>>
+}
+
+impl<E: JubjubEngine> SegmentedParams<E> {
+    // Generators and tables for inputs of up to max_bits, personalization included
+    pub fn new(segmenting: Segmenting, max_bits: usize, params: &E::Params) -> io::Result<Self> {
+        if segmenting.chunks_per_segment == 0 || segmenting.chunks_per_segment > MAX_CHUNKS_PER_SEGMENT {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("segments should have 1 to {} chunks", MAX_CHUNKS_PER_SEGMENT)));
+        }
+        if segmenting.exp_window == 0 || segmenting.exp_window > MAX_EXP_WINDOW {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("exp window should be 1 to {} bits", MAX_EXP_WINDOW)));
+        }
+
+        let mut exp = vec![];
+        let mut circuit = vec![];
+        for m in 0..segmenting.segments(max_bits) as u32 {
+            let mut segment = vec![];
+            segment.write_u32::<LittleEndian>(m).unwrap();
+            let g = group_hash::<E>(constants::PEDERSEN_HASH_GENERATORS_PERSONALIZATION, &segment, params);
+            exp.push(exp_table(&g, segmenting.exp_window, params));
+            circuit.push(circuit_windows(&g, segmenting.chunks_per_segment, params));
+        }
+        Ok(SegmentedParams { segmenting, exp, circuit })
+    }
+
+    pub fn segmenting(&self) -> Segmenting {
+        self.segmenting
+    }
+
+    pub fn max_bits(&self) -> usize {
+        self.exp.len() * self.segmenting.segment_bits()
+    }
+
+    // Montgomery (x, y) of g, 2g, 3g, 4g for every chunk of every segment, as
+    // JubjubParams::pedersen_circuit_generators
+    pub fn circuit_generators(&self) -> &[Vec<Vec<(E::Fr, E::Fr)>>] {
+        &self.circuit
+    }
+}
+
+fn exp_table<E: JubjubEngine>(g: &edwards::Point<E, PrimeOrder>, window: u32, params: &E::Params) -> Vec<Vec<edwards::Point<E, PrimeOrder>>> {
+    let mut g = g.clone();
+    let mut tables = vec![];
+    let mut num_bits = 0;
+    while num_bits <= E::Fs::NUM_BITS {
+        let mut table = Vec::with_capacity(1 << window);
+        let mut base = edwards::Point::zero();
+        for _ in 0..(1 << window) {
+            table.push(base.clone());
+            base = base.add(&g, params);
+        }
+        tables.push(table);
+        num_bits += window;
+        for _ in 0..window {
+            g = g.double(params);
+        }
+    }
+    tables
+}
+
+fn circuit_windows<E: JubjubEngine>(g: &edwards::Point<E, PrimeOrder>, chunks: usize, params: &E::Params) -> Vec<Vec<(E::Fr, E::Fr)>> {
+    let mut gen = montgomery::Point::from_edwards(g, params);
+    let mut windows = vec![];
+    for _ in 0..chunks {
+        let mut coeffs = vec![];
+        let mut p = gen.clone();
+        for _ in 0..4 {
+            coeffs.push(p.into_xy().expect("cannot produce O"));
+            p = p.add(&gen, params);
+        }
+        windows.push(coeffs);
+        for _ in 0..4 {
+            gen = gen.double(params);
+        }
+    }
+    windows
+}
+
+
+// Same steps as pedersen_hash::pedersen_hash, with the segment length and window of sp
+pub fn pedersen_hash<E, I>(personalization: Personalization, bits: I, sp: &SegmentedParams<E>, params: &E::Params) -> io::Result<edwards::Point<E, PrimeOrder>>
+    where I: IntoIterator<Item = bool>,
+          E: JubjubEngine
+{
+    let mut bits = personalization.get_bits().into_iter().chain(bits).peekable();
+    let mut result = edwards::Point::zero();
+    let mut tables = sp.exp.iter();
+
+    while bits.peek().is_some() {
+        let mut acc = E::Fs::zero();
+        let mut cur = E::Fs::one();
+        for _ in 0..sp.segmenting.chunks_per_segment {
+            let a = match bits.next() {
+                Some(a) => a,
+                None => break
+            };
+            let b = bits.next().unwrap_or(false);
+            let c = bits.next().unwrap_or(false);
+
+            let mut tmp = cur;
+            if a {
+                tmp.add_assign(&cur);
+            }
+            cur.double();
+            if b {
+                tmp.add_assign(&cur);
+            }
+            if c {
+                tmp.negate();
+            }
+            acc.add_assign(&tmp);
+            cur.double();
+            cur.double();
+            cur.double();
+        }
+
+        let mut table = &tables.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("input is longer than {} bits", sp.max_bits())))?[..];
+        let window = sp.segmenting.exp_window;
+        let mask = (1u64 << window) - 1;
+        let mut acc = acc.into_repr();
+        while !acc.is_zero() {
+            result = result.add(&table[0][(acc.as_ref()[0] & mask) as usize], params);
+            acc.shr(window);
+            table = &table[1..];
+        }
+    }
+    Ok(result)
+}
+
+
+#[cfg(test)]
+mod pedersen_segments_tests {
+    use super::*;
+    use pairing::bls12_381::Bls12;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    #[test]
+    fn test_segmented_pedersen_hash() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let sapling = SegmentedParams::<Bls12>::new(SAPLING_SEGMENTING, 945, params).unwrap();
+        let window4 = SegmentedParams::<Bls12>::new(Segmenting { exp_window: 4, ..SAPLING_SEGMENTING }, 945, params).unwrap();
+        let short = SegmentedParams::<Bls12>::new(Segmenting { chunks_per_segment: 43, exp_window: 8 }, 945, params).unwrap();
+        assert!(sapling.max_bits() == 945 && short.max_bits() == 8 * 129);
+
+        for &n in [0, 1, 183, 184, 510, 939].iter() {
+            let bits = (0..n).map(|_| rng.gen()).collect::<Vec<bool>>();
+            let p = Personalization::MerkleTree(rng.gen_range(0, 63));
+            let expected = sapling_crypto::pedersen_hash::pedersen_hash::<Bls12, _>(p, bits.clone(), params);
+            assert!(pedersen_hash(p, bits.clone(), &sapling, params).unwrap() == expected, "Sapling segmenting should be Sapling's hash");
+            assert!(pedersen_hash(p, bits.clone(), &window4, params).unwrap() == expected, "Window should not change the hash");
+            if n + 6 > 129 {
+                assert!(pedersen_hash(p, bits.clone(), &short, params).unwrap() != expected);
+            }
+        }
+        assert!(pedersen_hash(Personalization::NoteCommitment, vec![true; 940], &sapling, params).is_err());
+        assert!(SegmentedParams::<Bls12>::new(Segmenting { chunks_per_segment: 64, exp_window: 8 }, 945, params).is_err());
+        assert!(SegmentedParams::<Bls12>::new(Segmenting { chunks_per_segment: 63, exp_window: 0 }, 945, params).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use pairing::{Engine, CurveAffine, EncodedPoint, Field, PrimeField, PrimeFieldRepr, GroupDecodingError};
+use bellman::groth16::Proof;
+use sapling_crypto::jubjub::{JubjubEngine, Unknown, edwards::Point};
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointError {
+    NotOnCurve,
+    WrongSubgroup,
+    NonCanonical,
+    Infinity
+}
+
+impl fmt::Display for PointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            PointError::NotOnCurve => "point is not on the curve",
+            PointError::WrongSubgroup => "point is not in the prime order subgroup",
+            PointError::NonCanonical => "point encoding is not canonical",
+            PointError::Infinity => "point at infinity"
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl Error for PointError {}
+
+impl From<GroupDecodingError> for PointError {
+    fn from(e: GroupDecodingError) -> Self {
+        match e {
+            GroupDecodingError::NotOnCurve => PointError::NotOnCurve,
+            GroupDecodingError::NotInSubgroup => PointError::WrongSubgroup,
+            _ => PointError::NonCanonical
+        }
+    }
+}
+
+// Point errors travel as io::ErrorKind::InvalidData, like every other decoding failure
+// of the crate. The reason could be recovered with PointError::from_io.
+impl From<PointError> for io::Error {
+    fn from(e: PointError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+impl PointError {
+    pub fn from_io(e: &io::Error) -> Option<Self> {
+        e.get_ref().and_then(|e| e.downcast_ref::<PointError>()).cloned()
+    }
+}
+
+
+// Strict mode checks the subgroup and is what verifiers should use. Lenient mode only
+// checks that the point is canonically encoded and on the curve. Infinity is rejected in both.
+pub fn decode_point<G: CurveAffine>(repr: &G::Compressed, strict: bool) -> Result<G, PointError> {
+    let p = if strict {
+        repr.into_affine()?
+    } else {
+        repr.into_affine_unchecked()?
+    };
+
+    if p.is_zero() {
+        return Err(PointError::Infinity);
+    }
+    Ok(p)
+}
+
+pub fn read_point<G: CurveAffine, R: Read>(mut reader: R, strict: bool) -> io::Result<G> {
+    let mut repr = G::Compressed::empty();
+    reader.read_exact(repr.as_mut())?;
+    Ok(decode_point::<G>(&repr, strict)?)
+}
+
+pub fn read_proof<E: Engine, R: Read>(mut reader: R, strict: bool) -> io::Result<Proof<E>> {
+    let a = read_point::<E::G1Affine, _>(&mut reader, strict)?;
+    let b = read_point::<E::G2Affine, _>(&mut reader, strict)?;
+    let c = read_point::<E::G1Affine, _>(&mut reader, strict)?;
+    Ok(Proof { a, b, c })
+}
+
+// Proof::read_from(reader) reads a proof in strict mode, consuming exactly 192 bytes.
+pub trait ReadProof: Sized {
+    fn read_from<R: Read>(reader: R) -> io::Result<Self>;
+}
+
+impl<E: Engine> ReadProof for Proof<E> {
+    fn read_from<R: Read>(reader: R) -> io::Result<Self> {
+        read_proof(reader, true)
+    }
+}
+
+
+// Same encoding as edwards::Point::read: y little-endian, sign of x in the top bit.
+pub fn read_edwards_point<E: JubjubEngine, R: Read>(reader: R, strict: bool, params: &E::Params) -> io::Result<Point<E, Unknown>> {
+    let mut y_repr = <E::Fr as PrimeField>::Repr::default();
+    y_repr.read_le(reader)?;
+
+    let x_sign = (y_repr.as_ref()[3] >> 63) == 1;
+    y_repr.as_mut()[3] &= 0x7fffffffffffffff;
+
+    let y = E::Fr::from_repr(y_repr).map_err(|_| PointError::NonCanonical)?;
+    let p = Point::<E, Unknown>::get_for_y(y, x_sign, params).ok_or(PointError::NotOnCurve)?;
+    // -0 = 0, the sign bit of x = 0 should be clear as in librustzcash
+    if x_sign && p.into_xy().0.is_zero() {
+        return Err(PointError::NonCanonical.into());
+    }
+    Ok(check_edwards_point(p, strict, params)?)
+}
+
+// Any x on the curve gives a point, the sign of y does not matter for the callers.
+pub fn edwards_point_for_x<E: JubjubEngine>(x: &E::Fr, strict: bool, params: &E::Params) -> Result<Point<E, Unknown>, PointError> {
+    let p = Point::<E, Unknown>::get_for_x(x.clone(), params).ok_or(PointError::NotOnCurve)?;
+    check_edwards_point(p, strict, params)
+}
+
+fn check_edwards_point<E: JubjubEngine>(p: Point<E, Unknown>, strict: bool, params: &E::Params) -> Result<Point<E, Unknown>, PointError> {
+    if strict && p.as_prime_order(params).is_none() {
+        return Err(PointError::WrongSubgroup);
+    }
+    Ok(p)
+}
+
+
+#[cfg(test)]
+mod point_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr, G1, G1Affine, G2};
+    use pairing::{CurveProjective, Field};
+    use sapling_crypto::jubjub::JubjubBls12;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    #[test]
+    fn test_read_point_errors() {
+        let mut rng = OsRng::new().unwrap();
+        let p = rng.gen::<G1>().into_affine();
+
+        let repr = p.into_compressed();
+        assert!(read_point::<G1Affine, _>(repr.as_ref(), true).unwrap() == p);
+
+        let repr = G1Affine::zero().into_compressed();
+        let e = read_point::<G1Affine, _>(repr.as_ref(), true).unwrap_err();
+        assert!(PointError::from_io(&e) == Some(PointError::Infinity));
+
+        let mut repr = p.into_compressed();
+        repr.as_mut()[0] &= 0x7f;
+        let e = read_point::<G1Affine, _>(repr.as_ref(), false).unwrap_err();
+        assert!(PointError::from_io(&e) == Some(PointError::NonCanonical), "Uncompressed flag should be rejected");
+
+        let proof = Proof::<Bls12> {
+            a: rng.gen::<G1>().into_affine(),
+            b: rng.gen::<G2>().into_affine(),
+            c: rng.gen::<G1>().into_affine()
+        };
+        let mut data = vec![];
+        proof.write(&mut data).unwrap();
+        assert!(read_proof::<Bls12, _>(&data[..], true).unwrap() == proof);
+    }
+
+    #[test]
+    fn test_read_edwards_point_errors() {
+        let mut rng = OsRng::new().unwrap();
+        let params = JubjubBls12::new();
+
+        // any x without a matching y
+        let x_off = loop {
+            let x = rng.gen::<Fr>();
+            if Point::<Bls12, Unknown>::get_for_x(x, &params).is_none() {
+                break x;
+            }
+        };
+        assert!(edwards_point_for_x::<Bls12>(&x_off, false, &params).err() == Some(PointError::NotOnCurve));
+
+        // (0, -1) has order 2
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        let mut data = vec![];
+        minus_one.into_repr().write_le(&mut data).unwrap();
+        assert!(read_edwards_point::<Bls12, _>(&data[..], false, &params).is_ok());
+        let e = read_edwards_point::<Bls12, _>(&data[..], true, &params).err().unwrap();
+        assert!(PointError::from_io(&e) == Some(PointError::WrongSubgroup), "Small order point should be rejected in strict mode");
+
+        let mut data = vec![];
+        Fr::char().write_le(&mut data).unwrap();
+        let e = read_edwards_point::<Bls12, _>(&data[..], false, &params).err().unwrap();
+        assert!(PointError::from_io(&e) == Some(PointError::NonCanonical));
+    }
+}
@@ -0,0 +1,115 @@
+use pairing::PrimeField;
+use pairing::bls12_381::Fr;
+
+use std::io;
+
+use crate::poseidon_params::{generate, PoseidonConstants};
+
+
+// The Poseidon permutation with the x^5 S-box (5 is coprime to r - 1 for BLS12-381) and the
+// constants of poseidon_params: RF / 2 full rounds, RP partial rounds with the S-box on the first
+// element only, RF / 2 full rounds; every round adds its t constants, applies the S-box and
+// multiplies by the MDS matrix. Same as poseidonperm_x5_255_3 of the reference implementation
+// for the parameters below.
+pub const POSEIDON_T: usize = 3;
+pub const POSEIDON_FULL_ROUNDS: usize = 8;
+pub const POSEIDON_PARTIAL_ROUNDS: usize = 57;
+
+
+lazy_static! {
+    pub static ref POSEIDON_PARAMS: Poseidon<Fr> = Poseidon::new(POSEIDON_T, POSEIDON_FULL_ROUNDS, POSEIDON_PARTIAL_ROUNDS).unwrap();
+}
+
+
+pub struct Poseidon<F: PrimeField> {
+    constants: PoseidonConstants<F>
+}
+
+fn sbox<F: PrimeField>(x: &mut F) {
+    let mut x2 = *x;
+    x2.square();
+    let mut x4 = x2;
+    x4.square();
+    x.mul_assign(&x4);
+}
+
+impl<F: PrimeField> Poseidon<F> {
+    pub fn new(t: usize, full_rounds: usize, partial_rounds: usize) -> io::Result<Self> {
+        if full_rounds % 2 != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "number of full rounds should be even"));
+        }
+        Ok(Poseidon { constants: generate(t, full_rounds, partial_rounds)? })
+    }
+
+    pub fn t(&self) -> usize {
+        self.constants.t
+    }
+
+    pub fn permute(&self, state: &[F]) -> io::Result<Vec<F>> {
+        if state.len() != self.t() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("poseidon state should have {} elements", self.t())));
+        }
+        let mut state = state.to_vec();
+        self.permute_in_place(&mut state);
+        Ok(state)
+    }
+
+    // state.len() == t
+    pub(crate) fn permute_in_place(&self, state: &mut [F]) {
+        let c = &self.constants;
+        let half = c.full_rounds / 2;
+        let mut tmp = vec![F::zero(); c.t];
+
+        for (r, rc) in c.round_constants.chunks(c.t).enumerate() {
+            for (x, k) in state.iter_mut().zip(rc.iter()) {
+                x.add_assign(k);
+            }
+            if r < half || r >= half + c.partial_rounds {
+                state.iter_mut().for_each(sbox);
+            } else {
+                sbox(&mut state[0]);
+            }
+
+            for (y, row) in tmp.iter_mut().zip(c.mds.iter()) {
+                *y = F::zero();
+                for (x, m) in state.iter().zip(row.iter()) {
+                    let mut p = *x;
+                    p.mul_assign(m);
+                    y.add_assign(&p);
+                }
+            }
+            state.copy_from_slice(&tmp);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod poseidon_tests {
+    use super::*;
+    use pairing::PrimeFieldRepr;
+
+    fn to_hex(x: &Fr) -> String {
+        let mut data = vec![];
+        x.into_repr().write_be(&mut data).unwrap();
+        hex::encode(data)
+    }
+
+    // test vector of poseidonperm_x5_255_3 of the reference implementation
+    const REFERENCE_OUTPUT: [&str; 3] = [
+        "28ce19420fc246a05553ad1e8c98f5c9d67166be2c18e9e4cb4b4e317dd2a78a",
+        "51f3e312c95343a896cfd8945ea82ba956c1118ce9b9859b6ea56637b4b1ddc4",
+        "3b2b69139b235626a0bfb56c9527ae66a7bf486ad8c11c14d1da0c69bbe0f79a"
+    ];
+
+    #[test]
+    fn test_poseidon_permutation() {
+        let input = (0..3).map(|i| Fr::from_str(&i.to_string()).unwrap()).collect::<Vec<_>>();
+        let output = POSEIDON_PARAMS.permute(&input).unwrap();
+        assert!(output.iter().map(to_hex).collect::<Vec<_>>() == REFERENCE_OUTPUT, "Permutation should match the reference");
+
+        assert!(POSEIDON_PARAMS.permute(&input[..2]).is_err());
+        assert!(Poseidon::<Fr>::new(3, 7, 57).is_err());
+
+    }
+}
@@ -0,0 +1,245 @@
+use pairing::{PrimeField, PrimeFieldRepr};
+
+use std::io::{self, BufRead, Write};
+
+use crate::serialization::read_fr_repr_be;
+
+
+// Poseidon constants derived as in the reference script of the paper (Grassi et al.,
+// generate_params_poseidon.sage): a Grain LFSR seeded with the parameters (prime field, x^alpha
+// S-box, field size, t, RF, RP) gives the round constants, rejecting values above the modulus,
+// then the xs and ys of a Cauchy MDS matrix 1 / (x_i + y_j) from the same stream. Constants
+// anyone can regenerate from (t, RF, RP), so none of them is a magic number.
+//
+// The script also runs its subspace trail tests on the matrix and draws another one if they
+// fail. They pass for all but a negligible fraction of the matrices over a 255 bit field and are
+// not rerun here: the matrix is the first one drawn, as in the script.
+//
+// Transcript, one item per line:
+//   params t RF RP
+//   rc <hex>              (RF + RP) t round constants, round by round
+//   mds <hex> ... <hex>   t rows of t elements
+// field elements as big-endian hex
+pub const POSEIDON_TRANSCRIPT_HEADER: &str = "# zwaves poseidon constants v1";
+
+const GRAIN_STATE_BITS: usize = 80;
+const GRAIN_WARMUP: usize = 160;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoseidonConstants<F: PrimeField> {
+    pub t: usize,
+    pub full_rounds: usize,
+    pub partial_rounds: usize,
+    pub round_constants: Vec<F>,
+    pub mds: Vec<Vec<F>>
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+
+struct Grain {
+    bits: [bool; GRAIN_STATE_BITS],
+    pos: usize
+}
+
+impl Grain {
+    // seed: field 1 (2 bits) | sbox 0 (4) | n (12) | t (12) | RF (10) | RP (10) | 30 ones, msb first
+    fn new(n: usize, t: usize, full_rounds: usize, partial_rounds: usize) -> Self {
+        let mut seed = vec![];
+        for &(v, len) in [(1, 2), (0, 4), (n, 12), (t, 12), (full_rounds, 10), (partial_rounds, 10)].iter() {
+            seed.extend((0..len).rev().map(|i| (v >> i) & 1 == 1));
+        }
+        seed.extend(std::iter::repeat(true).take(30));
+
+        let mut g = Grain { bits: [false; GRAIN_STATE_BITS], pos: 0 };
+        g.bits.copy_from_slice(&seed);
+        (0..GRAIN_WARMUP).for_each(|_| { g.step(); });
+        g
+    }
+
+    // b_{i+80} = b_{i+62} + b_{i+51} + b_{i+38} + b_{i+23} + b_{i+13} + b_i
+    fn step(&mut self) -> bool {
+        let b = |k: usize| self.bits[(self.pos + k) % GRAIN_STATE_BITS];
+        let bit = b(62) ^ b(51) ^ b(38) ^ b(23) ^ b(13) ^ b(0);
+        self.bits[self.pos] = bit;
+        self.pos = (self.pos + 1) % GRAIN_STATE_BITS;
+        bit
+    }
+
+    // self-shrinking: of a pair, the second bit if the first one is set, nothing otherwise
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let (control, bit) = (self.step(), self.step());
+            if control {
+                return bit;
+            }
+        }
+    }
+
+    // NUM_BITS bits, msb first
+    fn next_repr<F: PrimeField>(&mut self) -> F::Repr {
+        let mut repr = F::Repr::default();
+        for _ in 0..F::NUM_BITS {
+            repr.mul2();
+            if self.next_bit() {
+                repr.as_mut()[0] |= 1;
+            }
+        }
+        repr
+    }
+
+    fn next_rejecting<F: PrimeField>(&mut self) -> F {
+        loop {
+            if let Ok(x) = F::from_repr(self.next_repr::<F>()) {
+                return x;
+            }
+        }
+    }
+
+    // the value modulo r, below 2r as r has NUM_BITS bits
+    fn next_reduced<F: PrimeField>(&mut self) -> F {
+        let mut repr = self.next_repr::<F>();
+        if F::from_repr(repr).is_err() {
+            repr.sub_noborrow(&F::char());
+        }
+        F::from_repr(repr).unwrap()
+    }
+}
+
+
+pub fn generate<F: PrimeField>(t: usize, full_rounds: usize, partial_rounds: usize) -> io::Result<PoseidonConstants<F>> {
+    if t < 2 || t >= 1 << 12 || full_rounds >= 1 << 10 || partial_rounds >= 1 << 10 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("parameters t = {}, RF = {}, RP = {} do not fit the seed", t, full_rounds, partial_rounds)));
+    }
+    let mut g = Grain::new(F::NUM_BITS as usize, t, full_rounds, partial_rounds);
+    let round_constants = (0..(full_rounds + partial_rounds) * t).map(|_| g.next_rejecting()).collect();
+
+    // xs and ys all distinct and x_i + y_j nonzero, else the whole list is drawn again
+    let mds = loop {
+        let v = (0..2 * t).map(|_| g.next_reduced::<F>()).collect::<Vec<_>>();
+        if (0..2 * t).any(|i| v[i + 1..].contains(&v[i])) {
+            continue;
+        }
+        let rows = v[..t].iter().map(|x| v[t..].iter().map(|y| {
+            let mut s = *x;
+            s.add_assign(y);
+            s.inverse()
+        }).collect::<Option<Vec<_>>>()).collect::<Option<Vec<_>>>();
+        if let Some(rows) = rows {
+            break rows;
+        }
+    };
+
+    Ok(PoseidonConstants { t, full_rounds, partial_rounds, round_constants, mds })
+}
+
+// The constants are exactly the ones generate gives for their (t, RF, RP)
+pub fn verify<F: PrimeField>(c: &PoseidonConstants<F>) -> io::Result<()> {
+    let expected = generate::<F>(c.t, c.full_rounds, c.partial_rounds)?;
+    if c.round_constants.len() != expected.round_constants.len() {
+        return Err(invalid(format!("expected {} round constants, got {}", expected.round_constants.len(), c.round_constants.len())));
+    }
+    if let Some(i) = (0..c.round_constants.len()).find(|&i| c.round_constants[i] != expected.round_constants[i]) {
+        return Err(invalid(format!("round constant {} differs from the generator", i)));
+    }
+    if c.mds.len() != c.t || c.mds.iter().any(|row| row.len() != c.t) {
+        return Err(invalid(format!("mds should be {} by {}", c.t, c.t)));
+    }
+    for (i, (row, expected_row)) in c.mds.iter().zip(expected.mds.iter()).enumerate() {
+        if let Some(j) = (0..c.t).find(|&j| row[j] != expected_row[j]) {
+            return Err(invalid(format!("mds [{}][{}] differs from the generator", i, j)));
+        }
+    }
+    Ok(())
+}
+
+
+fn to_hex<F: PrimeField>(x: &F) -> String {
+    let mut data = vec![];
+    x.into_repr().write_be(&mut data).unwrap();
+    hex::encode(data)
+}
+
+fn from_hex<F: PrimeField>(s: &str) -> io::Result<F> {
+    let data = hex::decode(s).map_err(|e| invalid(format!("wrong hex: {}", e)))?;
+    if data.len() != F::Repr::default().as_ref().len() * 8 {
+        return Err(invalid(format!("wrong field element length: {}", s)));
+    }
+    F::from_repr(read_fr_repr_be::<F>(&data)?).map_err(|_| invalid(format!("not in field: {}", s)))
+}
+
+pub fn write_transcript<F: PrimeField, W: Write>(c: &PoseidonConstants<F>, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "{}", POSEIDON_TRANSCRIPT_HEADER)?;
+    writeln!(writer, "params {} {} {}", c.t, c.full_rounds, c.partial_rounds)?;
+    for x in c.round_constants.iter() {
+        writeln!(writer, "rc {}", to_hex(x))?;
+    }
+    for row in c.mds.iter() {
+        writeln!(writer, "mds {}", row.iter().map(to_hex).collect::<Vec<_>>().join(" "))?;
+    }
+    Ok(())
+}
+
+// Empty lines and lines starting with # are skipped. The constants are not checked, see verify.
+pub fn read_transcript<F: PrimeField, R: BufRead>(reader: R) -> io::Result<PoseidonConstants<F>> {
+    let mut params = None;
+    let (mut round_constants, mut mds) = (vec![], vec![]);
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        match fields[0] {
+            "params" if fields.len() == 4 && params.is_none() => {
+                let v = fields[1..].iter().map(|s| s.parse::<usize>().map_err(|_| invalid(format!("wrong number: {}", s)))).collect::<io::Result<Vec<_>>>()?;
+                params = Some((v[0], v[1], v[2]));
+            },
+            "rc" if fields.len() == 2 => round_constants.push(from_hex(fields[1])?),
+            "mds" => mds.push(fields[1..].iter().map(|s| from_hex(s)).collect::<io::Result<Vec<_>>>()?),
+            _ => return Err(invalid(format!("unexpected line: {}", line)))
+        }
+    }
+    let (t, full_rounds, partial_rounds) = params.ok_or_else(|| invalid("no params line".to_string()))?;
+    Ok(PoseidonConstants { t, full_rounds, partial_rounds, round_constants, mds })
+}
+
+
+#[cfg(test)]
+mod poseidon_params_tests {
+    use super::*;
+    use pairing::bls12_381::Fr;
+    use pairing::Field;
+
+    // first values of poseidonperm_x5_255_3 of the reference implementation
+    const REFERENCE_RC_0: &str = "6c4ffa723eaf1a7bf74905cc7dae4ca9ff4a2c3bc81d42e09540d1f250910880";
+    const REFERENCE_MDS_0_0: &str = "3d955d6c02fe4d7cb500e12f2b55eff668a7b4386bd27413766713c93f2acfcd";
+
+    #[test]
+    fn test_poseidon_constants() {
+        let c = generate::<Fr>(3, 8, 57).unwrap();
+        assert!(c.round_constants.len() == 65 * 3 && c.mds.len() == 3);
+        assert!(to_hex(&c.round_constants[0]) == REFERENCE_RC_0 && to_hex(&c.mds[0][0]) == REFERENCE_MDS_0_0, "Constants should match the reference script");
+
+        let mut data = vec![];
+        write_transcript(&c, &mut data).unwrap();
+        let read = read_transcript::<Fr, _>(&data[..]).unwrap();
+        assert!(read == c);
+        verify(&read).unwrap();
+
+        let mut wrong = c.clone();
+        wrong.round_constants[100].add_assign(&Fr::one());
+        assert!(verify(&wrong).unwrap_err().to_string() == "round constant 100 differs from the generator");
+        let mut wrong = c.clone();
+        wrong.mds[1].swap(0, 2);
+        assert!(verify(&wrong).is_err(), "Changed matrix should be rejected");
+        let mut wrong = c.clone();
+        wrong.partial_rounds = 56;
+        assert!(verify(&wrong).is_err(), "Constants of other parameters should be rejected");
+    }
+}
@@ -0,0 +1,191 @@
+use blake2_rfc::blake2s::Blake2s;
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+
+// Proofs already produced for a witness, for retries after a network failure: proving the same
+// witness again gives a valid proof of the same statement, the cache hands back the first one.
+// Keys are hashes of (circuit id, serialized witness). The circuit id should name the proving
+// parameters too, proofs made with other parameters do not verify.
+//
+// The store keeps the proofs, ProofCache keeps the recency and drops the least recently used
+// proofs from the store beyond the capacity. Entries found in a persistent store after a
+// restart are taken into the LRU order on first use. A cache links the proofs to the witness
+// hash, so it should live next to the wallet, not be shared.
+pub const PROOF_CACHE_PERSONALIZATION: &[u8; 8] = b"ZWprfcch";
+
+pub type ProofKey = [u8; 32];
+
+
+pub fn witness_key(circuit_id: &[u8], witness: &[u8]) -> ProofKey {
+    let mut h = Blake2s::with_params(32, &[], &[], PROOF_CACHE_PERSONALIZATION);
+    let mut len = vec![];
+    len.write_u64::<LittleEndian>(circuit_id.len() as u64).unwrap();
+    h.update(&len);
+    h.update(circuit_id);
+    h.update(witness);
+    let mut res = [0u8; 32];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+
+pub trait ProofStore {
+    fn get(&mut self, key: &ProofKey) -> io::Result<Option<Vec<u8>>>;
+    fn put(&mut self, key: ProofKey, proof: Vec<u8>) -> io::Result<()>;
+    fn remove(&mut self, key: &ProofKey) -> io::Result<()>;
+}
+
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    proofs: HashMap<ProofKey, Vec<u8>>
+}
+
+impl MemoryStore {
+    pub fn len(&self) -> usize {
+        self.proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
+    }
+}
+
+impl ProofStore for MemoryStore {
+    fn get(&mut self, key: &ProofKey) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.proofs.get(key).cloned())
+    }
+
+    fn put(&mut self, key: ProofKey, proof: Vec<u8>) -> io::Result<()> {
+        self.proofs.insert(key, proof);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &ProofKey) -> io::Result<()> {
+        self.proofs.remove(key);
+        Ok(())
+    }
+}
+
+
+pub struct ProofCache<S: ProofStore = MemoryStore> {
+    store: S,
+    capacity: usize,
+    tick: u64,
+    last_used: HashMap<ProofKey, u64>,
+    order: BTreeMap<u64, ProofKey>
+}
+
+impl ProofCache<MemoryStore> {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_store(capacity, MemoryStore::default())
+    }
+}
+
+impl<S: ProofStore> ProofCache<S> {
+    pub fn with_store(capacity: usize, store: S) -> Self {
+        assert!(capacity > 0, "capacity should be positive");
+        ProofCache { store, capacity, tick: 0, last_used: HashMap::new(), order: BTreeMap::new() }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn len(&self) -> usize {
+        self.last_used.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last_used.is_empty()
+    }
+
+    fn touch(&mut self, key: ProofKey) -> io::Result<()> {
+        if let Some(t) = self.last_used.insert(key, self.tick) {
+            self.order.remove(&t);
+        }
+        self.order.insert(self.tick, key);
+        self.tick += 1;
+
+        while self.last_used.len() > self.capacity {
+            let (t, old) = self.order.iter().next().map(|(t, k)| (*t, *k)).unwrap();
+            self.store.remove(&old)?;
+            self.order.remove(&t);
+            self.last_used.remove(&old);
+        }
+        Ok(())
+    }
+
+    pub fn get(&mut self, key: &ProofKey) -> io::Result<Option<Vec<u8>>> {
+        let res = self.store.get(key)?;
+        if res.is_some() {
+            self.touch(*key)?;
+        }
+        Ok(res)
+    }
+
+    pub fn insert(&mut self, key: ProofKey, proof: Vec<u8>) -> io::Result<()> {
+        self.store.put(key, proof)?;
+        self.touch(key)
+    }
+
+    pub fn remove(&mut self, key: &ProofKey) -> io::Result<()> {
+        if let Some(t) = self.last_used.remove(key) {
+            self.order.remove(&t);
+        }
+        self.store.remove(key)
+    }
+
+    // The cached proof, or the proof of prove() stored for the next retry. Failed proving
+    // stores nothing.
+    pub fn get_or_prove<F>(&mut self, key: ProofKey, prove: F) -> io::Result<Vec<u8>>
+        where F: FnOnce() -> io::Result<Vec<u8>>
+    {
+        if let Some(proof) = self.get(&key)? {
+            return Ok(proof);
+        }
+        let proof = prove()?;
+        self.insert(key, proof.clone())?;
+        Ok(proof)
+    }
+}
+
+
+#[cfg(test)]
+mod proof_cache_tests {
+    use super::*;
+
+    #[test]
+    fn test_proof_cache() {
+        let k = |i: u8| witness_key(b"transfer", &[i]);
+        assert!(witness_key(b"transfer", b"\x01") != witness_key(b"transfer\x01", b""), "Circuit id and witness should not run together");
+
+        let mut cache = ProofCache::new(2);
+        let mut calls = 0;
+        for _ in 0..2 {
+            let proof = cache.get_or_prove(k(0), || { calls += 1; Ok(vec![0; 192]) }).unwrap();
+            assert!(proof == vec![0; 192]);
+        }
+        assert!(calls == 1, "Retry should not prove again");
+        assert!(cache.get_or_prove(k(1), || Err(io::Error::new(io::ErrorKind::Other, "no params"))).is_err());
+        assert!(cache.get(&k(1)).unwrap().is_none(), "Failed proving should not be cached");
+
+        cache.insert(k(1), vec![1]).unwrap();
+        cache.get(&k(0)).unwrap();
+        cache.insert(k(2), vec![2]).unwrap();
+        assert!(cache.len() == 2 && cache.store().len() == 2);
+        assert!(cache.get(&k(1)).unwrap().is_none(), "Least recently used proof should be evicted");
+        assert!(cache.get(&k(0)).unwrap().is_some() && cache.get(&k(2)).unwrap() == Some(vec![2]));
+
+        // a persistent store outlives the LRU order
+        let mut store = MemoryStore::default();
+        store.put(k(3), vec![3]).unwrap();
+        let mut cache = ProofCache::with_store(1, store);
+        assert!(cache.is_empty() && cache.get(&k(3)).unwrap() == Some(vec![3]) && cache.len() == 1);
+        cache.remove(&k(3)).unwrap();
+        assert!(cache.is_empty() && cache.store().is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use pairing::{Engine, PrimeField, PrimeFieldRepr};
+use bellman::{ConstraintSystem, SynthesisError};
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use blake2_rfc::blake2s::Blake2s;
+
+use std::io;
+
+use crate::fieldtools;
+
+
+// Public values of every circuit, declared once. The field order below is the order of the
+// proof inputs: the circuits inputize through these structs (T = AllocatedNum), the verifier
+// and the bindings flatten and parse them (T = Fr), so a new public field is added here only.
+//
+// hash() is a versioned digest of the values, domain separated by the circuit name: a change
+// of the layout of a circuit bumps its version, so hashes of the old and the new layout
+// never collide.
+pub const PUBLIC_VALUES_PERSONALIZATION: &[u8; 8] = b"ZWpubval";
+
+
+macro_rules! public_values {
+    (@len T) => { 1 };
+    (@len [T; $n:expr]) => { $n };
+
+    (@named $res:ident, $name:expr, $x:expr, T) => {
+        $res.push(($name.to_string(), $x));
+    };
+    (@named $res:ident, $name:expr, $x:expr, [T; $n:expr]) => {
+        $res.extend($x.iter().enumerate().map(|(i, x)| (format!("{}[{}]", $name, i), x)));
+    };
+
+    (@read $it:ident, T) => {
+        *$it.next().unwrap()
+    };
+    (@read $it:ident, [T; $n:expr]) => {{
+        let mut a = [*$it.as_slice().first().unwrap(); $n];
+        a.iter_mut().for_each(|x| *x = *$it.next().unwrap());
+        a
+    }};
+
+    ($(#[$attr:meta])* $name:ident, len = $len:ident, version = $version:expr, { $($field:ident: $ty:tt),* }) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct $name<T> {
+            $(pub $field: $ty),*
+        }
+
+        pub const $len: usize = 0 $(+ public_values!(@len $ty))*;
+
+        impl<T> $name<T> {
+            pub const NAME: &'static str = stringify!($name);
+            pub const VERSION: u32 = $version;
+
+            // Inputs in proof order with their names, out_hash[0] style for arrays
+            pub fn named(&self) -> Vec<(String, &T)> {
+                let mut res = Vec::with_capacity($len);
+                $(public_values!(@named res, stringify!($field), &self.$field, $ty);)*
+                res
+            }
+        }
+
+        impl<T: Clone> $name<T> {
+            pub fn to_vec(&self) -> Vec<T> {
+                self.named().into_iter().map(|(_, x)| x.clone()).collect()
+            }
+        }
+
+        impl<T: Copy> $name<T> {
+            pub fn from_slice(inputs: &[T]) -> io::Result<Self> {
+                if inputs.len() != $len {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} should have {} inputs", Self::NAME, $len)));
+                }
+                let mut it = inputs.iter();
+                Ok($name { $($field: public_values!(@read it, $ty)),* })
+            }
+        }
+
+        impl<F: PrimeField> $name<F> {
+            pub fn hash(&self) -> F {
+                values_hash(Self::NAME, Self::VERSION, &self.to_vec())
+            }
+        }
+
+        impl<E: Engine> $name<AllocatedNum<E>> {
+            pub fn inputize<CS: ConstraintSystem<E>>(&self, cs: CS) -> Result<(), SynthesisError> {
+                self.inputize_from(cs, 0)
+            }
+
+            // The inputs from start on. A circuit which inputized the first ones where they are
+            // allocated keeps doing so: an inputize moved to the end reorders the constraints,
+            // and the parameters of the circuit would not fit it anymore.
+            pub fn inputize_from<CS: ConstraintSystem<E>>(&self, mut cs: CS, start: usize) -> Result<(), SynthesisError> {
+                for (name, x) in self.named().into_iter().skip(start) {
+                    x.inputize(cs.namespace(|| format!("inputize {}", name)))?;
+                }
+                Ok(())
+            }
+
+            pub fn get_value(&self) -> Option<$name<E::Fr>> {
+                let values = self.to_vec().iter().map(|x| x.get_value()).collect::<Option<Vec<_>>>()?;
+                $name::from_slice(&values).ok()
+            }
+        }
+    };
+}
+
+
+// name || version (u32 LE) || inputs (32 bytes LE each), reduced like input_hash
+fn values_hash<F: PrimeField>(name: &str, version: u32, inputs: &[F]) -> F {
+    let mut h = Blake2s::with_params(32, &[], &[], PUBLIC_VALUES_PERSONALIZATION);
+    h.update(name.as_bytes());
+    h.update(&version.to_le_bytes());
+    for x in inputs.iter() {
+        let mut data = vec![];
+        x.into_repr().write_le(&mut data).unwrap();
+        h.update(&data);
+    }
+
+    let mut res = F::Repr::default();
+    res.read_le(h.finalize().as_bytes()).unwrap();
+    fieldtools::affine(res)
+}
+
+
+public_values! {
+    // zwaves_circuit::circuit::Transfer, rk = (rk.x, rk.y)
+    TransferPublicValues, len = TRANSFER_PUBLIC_INPUTS, version = 1, {
+        receiver: T,
+        root_hash: T,
+        packed_asset: T,
+        out_hash: [T; 2],
+        nf: [T; 2],
+        rk: [T; 2]
+    }
+}
+
+public_values! {
+    // zwaves_circuit::circuit::UtxoAccumulator
+    UtxoAccumulatorPublicValues, len = UTXO_ACCUMULATOR_PUBLIC_INPUTS, version = 1, {
+        note_hashes: [T; 2],
+        index: T,
+        old_root: T,
+        new_root: T
+    }
+}
+
+public_values! {
+    // zwaves_circuit::circuit::AccountUpdate
+    AccountUpdatePublicValues, len = ACCOUNT_UPDATE_PUBLIC_INPUTS, version = 1, {
+        old_root: T,
+        new_root: T,
+        index: T,
+        packed_asset: T,
+        new_hash: T
+    }
+}
+
+
+#[cfg(test)]
+mod public_values_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use sapling_crypto::circuit::test::TestConstraintSystem;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    #[test]
+    fn test_public_values() {
+        let mut rng = OsRng::new().unwrap();
+        assert!(TRANSFER_PUBLIC_INPUTS == 9 && UTXO_ACCUMULATOR_PUBLIC_INPUTS == 5 && ACCOUNT_UPDATE_PUBLIC_INPUTS == 5);
+
+        let inputs = (0..TRANSFER_PUBLIC_INPUTS).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let values = TransferPublicValues::from_slice(&inputs).unwrap();
+        assert!(values.root_hash == inputs[1] && values.out_hash[1] == inputs[4] && values.nf == [inputs[5], inputs[6]] && values.rk[1] == inputs[8]);
+        assert!(values.to_vec() == inputs);
+        assert!(values.named().iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>() ==
+            vec!["receiver", "root_hash", "packed_asset", "out_hash[0]", "out_hash[1]", "nf[0]", "nf[1]", "rk[0]", "rk[1]"]);
+        assert!(TransferPublicValues::from_slice(&inputs[1..]).is_err(), "Wrong number of inputs should be rejected");
+
+        let mut other = values.clone();
+        other.nf.swap(0, 1);
+        assert!(values.hash() != other.hash(), "Order of the inputs should be hashed");
+        let account = AccountUpdatePublicValues::from_slice(&inputs[..5]).unwrap();
+        let utxo = UtxoAccumulatorPublicValues::from_slice(&inputs[..5]).unwrap();
+        assert!(account.hash() != utxo.hash(), "Circuits with the same number of inputs should not share hashes");
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let nums = inputs[..5].iter().enumerate()
+            .map(|(i, x)| AllocatedNum::alloc(cs.namespace(|| format!("x[{}]", i)), || Ok(*x)).unwrap())
+            .collect::<Vec<_>>();
+        let allocated = AccountUpdatePublicValues {
+            old_root: nums[0].clone(),
+            new_root: nums[1].clone(),
+            index: nums[2].clone(),
+            packed_asset: nums[3].clone(),
+            new_hash: nums[4].clone()
+        };
+        allocated.inputize(cs.namespace(|| "public values")).unwrap();
+        assert!(cs.is_satisfied());
+        assert!(cs.verify(&inputs[..5]), "Inputs should follow the declaration order");
+        assert!(allocated.get_value().unwrap() == account);
+    }
+}
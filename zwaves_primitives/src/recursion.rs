@@ -0,0 +1,66 @@
+use pairing::{Engine, PrimeField, PrimeFieldRepr};
+use bellman::SynthesisError;
+use bellman::groth16::Proof;
+
+use blake2_rfc::blake2s::Blake2s;
+
+use crate::fieldtools;
+use crate::verifier::{self, TruncatedVerifyingKey};
+
+
+// Experimental, for rollup recursion research. Nothing here verifies a pairing in-circuit.
+//
+// An outer circuit takes an inner proof and its public inputs as witnesses and exposes one
+// field element, the deferred hash, binding the inner verifying key, the proof bytes and the
+// inputs (zwaves_circuit::gadgets::recursion::deferred_proof). The pairing check is deferred to whoever checks
+// the outer proof: verify_deferred recomputes the hash and runs the usual Groth16 check, so the
+// outer circuit can rely on the inputs of the inner proof being the ones that were verified.
+//
+// deferred = blake2s(vk digest (32 bytes LE) || proof (192 bytes, compressed) || inputs (32 bytes LE each)) mod r
+pub const DEFERRED_PERSONALIZATION: &[u8; 8] = b"ZWdefer_";
+pub const VK_DIGEST_PERSONALIZATION: &[u8; 8] = b"ZWvkdgst";
+
+
+fn digest<E: Engine>(personalization: &[u8; 8], data: &[u8]) -> E::Fr {
+    let mut h = Blake2s::with_params(32, &[], &[], personalization);
+    h.update(data);
+    let mut res = <E::Fr as PrimeField>::Repr::default();
+    res.read_le(h.finalize().as_bytes()).unwrap();
+    fieldtools::affine(res)
+}
+
+// Digest of the serialized truncated verifying key, a constant of the outer circuit
+pub fn vk_digest<E: Engine>(tvk: &TruncatedVerifyingKey<E>) -> E::Fr {
+    let mut data = vec![];
+    tvk.write(&mut data).unwrap();
+    digest::<E>(VK_DIGEST_PERSONALIZATION, &data)
+}
+
+pub fn deferred_preimage<E: Engine>(vk_digest: &E::Fr, proof: &Proof<E>, inputs: &[E::Fr]) -> Vec<u8> {
+    let mut data = vec![];
+    vk_digest.into_repr().write_le(&mut data).unwrap();
+    proof.write(&mut data).unwrap();
+    for x in inputs.iter() {
+        x.into_repr().write_le(&mut data).unwrap();
+    }
+    data
+}
+
+pub fn deferred_hash<E: Engine>(vk_digest: &E::Fr, proof: &Proof<E>, inputs: &[E::Fr]) -> E::Fr {
+    digest::<E>(DEFERRED_PERSONALIZATION, &deferred_preimage(vk_digest, proof, inputs))
+}
+
+// True if the deferred hash exposed by the outer proof matches the inner proof and inputs,
+// and the inner proof is valid
+pub fn verify_deferred<E: Engine>(
+    tvk: &TruncatedVerifyingKey<E>,
+    proof: &Proof<E>,
+    inputs: &[E::Fr],
+    deferred: &E::Fr
+) -> Result<bool, SynthesisError>
+{
+    if deferred_hash(&vk_digest(tvk), proof, inputs) != *deferred {
+        return Ok(false);
+    }
+    verifier::verify_proof(tvk, proof, inputs)
+}
@@ -0,0 +1,313 @@
+use pairing::{PrimeField, PrimeFieldRepr};
+use serde_json::{json, Value};
+
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::serialization::read_fr_repr_be;
+use crate::waves_tx::base58_encode;
+
+
+// Data entries of the pool dApp (zwaves_demo/ride/zwaves.ride), written and read the way the
+// contract does, so that a change of the contract shows up here as a failing test.
+//
+//   U:<base58 out hash>   BooleanEntry   the note exists
+//   N:<base58 nf>         BooleanEntry   the note is spent
+//   R:<base58 root>       BooleanEntry   a known root
+//   M:<n>                 BinaryEntry    nf0 | nf1 | m of message n
+//   UM:<n>                BinaryEntry    out hash0 | out hash1 of message n
+//   RV:<n>                BinaryEntry    root after n accumulations
+//   MESSAGE_NUM, ROOT_NUM BinaryEntry    toBytes(Int), 8 bytes big-endian
+//
+// Field elements are 32 bytes big-endian, as they are in the public inputs.
+pub const MESSAGE_NUM_KEY: &str = "MESSAGE_NUM";
+pub const ROOT_NUM_KEY: &str = "ROOT_NUM";
+
+const FR_LEN: usize = 32;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataValue {
+    Binary(Vec<u8>),
+    Integer(i64),
+    Boolean(bool),
+    String(String)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataEntry {
+    pub key: String,
+    pub value: DataValue
+}
+
+
+pub fn fr_to_bytes<F: PrimeField>(x: &F) -> Vec<u8> {
+    let mut res = vec![];
+    x.into_repr().write_be(&mut res).unwrap();
+    res
+}
+
+pub fn fr_from_bytes<F: PrimeField>(data: &[u8]) -> io::Result<F> {
+    if data.len() != FR_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "field element should be 32 bytes"));
+    }
+    F::from_repr(read_fr_repr_be::<F>(data)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))
+}
+
+pub fn utxo_key<F: PrimeField>(out_hash: &F) -> String {
+    format!("U:{}", base58_encode(&fr_to_bytes(out_hash)))
+}
+
+pub fn nullifier_key<F: PrimeField>(nf: &F) -> String {
+    format!("N:{}", base58_encode(&fr_to_bytes(nf)))
+}
+
+pub fn root_key<F: PrimeField>(root: &F) -> String {
+    format!("R:{}", base58_encode(&fr_to_bytes(root)))
+}
+
+pub fn message_key(n: u64) -> String {
+    format!("M:{}", n)
+}
+
+pub fn utxo_message_key(n: u64) -> String {
+    format!("UM:{}", n)
+}
+
+pub fn root_value_key(n: u64) -> String {
+    format!("RV:{}", n)
+}
+
+fn counter(n: u64) -> DataValue {
+    DataValue::Binary((n as i64).to_be_bytes().to_vec())
+}
+
+
+impl DataEntry {
+    pub fn boolean(key: String, value: bool) -> Self {
+        DataEntry { key, value: DataValue::Boolean(value) }
+    }
+
+    pub fn binary(key: String, value: Vec<u8>) -> Self {
+        DataEntry { key, value: DataValue::Binary(value) }
+    }
+
+    pub fn integer(key: String, value: i64) -> Self {
+        DataEntry { key, value: DataValue::Integer(value) }
+    }
+
+    // Same as in the node API, binary values as "base64:..."
+    pub fn to_json(&self) -> String {
+        let (t, v) = match self.value {
+            DataValue::Binary(ref x) => ("binary", json!(format!("base64:{}", base64::encode(x)))),
+            DataValue::Integer(x) => ("integer", json!(x)),
+            DataValue::Boolean(x) => ("boolean", json!(x)),
+            DataValue::String(ref x) => ("string", json!(x))
+        };
+        json!({ "key": self.key, "type": t, "value": v }).to_string()
+    }
+}
+
+
+// Entries transferExternal and transferInternal write for message mn
+pub fn transfer_entries<F: PrimeField>(nf: &[F; 2], out_hash: &[F; 2], mn: u64, m: &[u8]) -> Vec<DataEntry> {
+    let mut message = fr_to_bytes(&nf[0]);
+    message.extend(fr_to_bytes(&nf[1]));
+    message.extend_from_slice(m);
+    let mut utxo_message = fr_to_bytes(&out_hash[0]);
+    utxo_message.extend(fr_to_bytes(&out_hash[1]));
+    vec![
+        DataEntry::boolean(nullifier_key(&nf[0]), true),
+        DataEntry::boolean(nullifier_key(&nf[1]), true),
+        DataEntry::boolean(utxo_key(&out_hash[0]), true),
+        DataEntry::boolean(utxo_key(&out_hash[1]), true),
+        DataEntry { key: MESSAGE_NUM_KEY.to_string(), value: counter(mn + 1) },
+        DataEntry::binary(message_key(mn), message),
+        DataEntry::binary(utxo_message_key(mn), utxo_message)
+    ]
+}
+
+// Entries utxoAccumulator writes for accumulation rn
+pub fn accumulator_entries<F: PrimeField>(rn: u64, new_root: &F) -> Vec<DataEntry> {
+    vec![
+        DataEntry { key: ROOT_NUM_KEY.to_string(), value: counter(rn + 1) },
+        DataEntry::binary(root_value_key(rn + 1), fr_to_bytes(new_root)),
+        DataEntry::boolean(root_key(new_root), true)
+    ]
+}
+
+
+// Data of the dApp as the node returns it (GET /addresses/data/{address}), a missing key
+// reads as the contract reads it
+#[derive(Clone, Debug, Default)]
+pub struct ContractState {
+    pub entries: BTreeMap<String, DataValue>
+}
+
+impl ContractState {
+    pub fn from_entries(entries: Vec<DataEntry>) -> Self {
+        ContractState { entries: entries.into_iter().map(|e| (e.key, e.value)).collect() }
+    }
+
+    pub fn from_json(data: &str) -> io::Result<Self> {
+        Ok(Self::from_entries(parse_entries(data)?))
+    }
+
+    pub fn apply(&mut self, entries: &[DataEntry]) {
+        entries.iter().for_each(|e| { self.entries.insert(e.key.clone(), e.value.clone()); });
+    }
+
+    fn flag(&self, key: &str) -> io::Result<bool> {
+        match self.entries.get(key) {
+            None => Ok(false),
+            Some(DataValue::Boolean(x)) => Ok(*x),
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} should be boolean", key)))
+        }
+    }
+
+    fn binary(&self, key: &str) -> io::Result<Option<&[u8]>> {
+        match self.entries.get(key) {
+            None => Ok(None),
+            Some(DataValue::Binary(x)) => Ok(Some(x)),
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} should be binary", key)))
+        }
+    }
+
+    fn counter(&self, key: &str) -> io::Result<u64> {
+        match self.binary(key)? {
+            None => Ok(0),
+            Some(x) if x.len() == 8 => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(x);
+                let n = i64::from_be_bytes(buf);
+                if n < 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is negative", key)));
+                }
+                Ok(n as u64)
+            },
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} should be 8 bytes", key)))
+        }
+    }
+
+    pub fn message_num(&self) -> io::Result<u64> {
+        self.counter(MESSAGE_NUM_KEY)
+    }
+
+    pub fn root_num(&self) -> io::Result<u64> {
+        self.counter(ROOT_NUM_KEY)
+    }
+
+    pub fn is_spent<F: PrimeField>(&self, nf: &F) -> io::Result<bool> {
+        self.flag(&nullifier_key(nf))
+    }
+
+    pub fn utxo_exists<F: PrimeField>(&self, out_hash: &F) -> io::Result<bool> {
+        self.flag(&utxo_key(out_hash))
+    }
+
+    // The empty root of the contract is not in its data
+    pub fn root_exists<F: PrimeField>(&self, root: &F, empty_root: &F) -> io::Result<bool> {
+        Ok(root == empty_root || self.flag(&root_key(root))?)
+    }
+
+    // Root after n accumulations, None for n = 0 where the contract uses its empty root
+    pub fn root_value<F: PrimeField>(&self, n: u64) -> io::Result<Option<F>> {
+        self.binary(&root_value_key(n))?.map(fr_from_bytes).transpose()
+    }
+
+    // nullifiers and m of message n
+    pub fn message<F: PrimeField>(&self, n: u64) -> io::Result<Option<([F; 2], Vec<u8>)>> {
+        match self.binary(&message_key(n))? {
+            None => Ok(None),
+            Some(x) if x.len() >= 2 * FR_LEN => Ok(Some((
+                [fr_from_bytes(&x[..FR_LEN])?, fr_from_bytes(&x[FR_LEN..2 * FR_LEN])?],
+                x[2 * FR_LEN..].to_vec()
+            ))),
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "message is too short"))
+        }
+    }
+
+    pub fn utxo_message<F: PrimeField>(&self, n: u64) -> io::Result<Option<[F; 2]>> {
+        match self.binary(&utxo_message_key(n))? {
+            None => Ok(None),
+            Some(x) if x.len() == 2 * FR_LEN => Ok(Some([fr_from_bytes(&x[..FR_LEN])?, fr_from_bytes(&x[FR_LEN..])?])),
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "utxo message should be 64 bytes"))
+        }
+    }
+}
+
+
+fn json_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("wrong data entries: {}", msg))
+}
+
+// An entry of the node API: an object with key, type and value, other fields are ignored
+fn entry(v: &Value) -> io::Result<DataEntry> {
+    let key = v.get("key").and_then(Value::as_str).ok_or_else(|| json_error("entry without a string key"))?;
+    let value = match (v.get("type").and_then(Value::as_str), v.get("value")) {
+        (Some("binary"), Some(Value::String(s))) => {
+            let b64 = if s.starts_with("base64:") { &s[7..] } else { &s[..] };
+            DataValue::Binary(base64::decode(b64).map_err(|_| json_error("wrong base64"))?)
+        },
+        (Some("integer"), Some(Value::Number(x))) if x.is_i64() => DataValue::Integer(x.as_i64().unwrap()),
+        (Some("boolean"), Some(Value::Bool(x))) => DataValue::Boolean(*x),
+        (Some("string"), Some(Value::String(s))) => DataValue::String(s.clone()),
+        _ => return Err(json_error(&format!("wrong type or value of {}", key)))
+    };
+    Ok(DataEntry { key: key.to_string(), value })
+}
+
+pub fn parse_entries(data: &str) -> io::Result<Vec<DataEntry>> {
+    let v: Value = serde_json::from_str(data).map_err(|e| json_error(&e.to_string()))?;
+    v.as_array().ok_or_else(|| json_error("array expected"))?.iter().map(entry).collect()
+}
+
+
+#[cfg(test)]
+mod ride_tests {
+    use super::*;
+    use pairing::bls12_381::Fr;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::empty_root;
+
+    #[test]
+    fn test_contract_empty_root() {
+        // emptyRoot of zwaves.ride
+        assert!(root_key(&empty_root(48)) == "R:4ABQyM1tpHEDkbHes1t7G1F3yyMJXQSvaUR1rqkZqKak", "Contract should use the empty root of depth 48");
+    }
+
+    #[test]
+    fn test_data_entries() {
+        let mut rng = OsRng::new().unwrap();
+        let (nf, out_hash) = ([rng.gen::<Fr>(), rng.gen::<Fr>()], [rng.gen::<Fr>(), rng.gen::<Fr>()]);
+        let new_root = rng.gen::<Fr>();
+
+        let mut entries = transfer_entries(&nf, &out_hash, 0, b"test");
+        entries.extend(accumulator_entries(0, &new_root));
+        entries.push(DataEntry { key: "note \"x\"".to_string(), value: DataValue::String("a\\b".to_string()) });
+        entries.push(DataEntry::integer("height".to_string(), -5));
+        assert!(entries[4].value == DataValue::Binary(vec![0, 0, 0, 0, 0, 0, 0, 1]), "Counters should be toBytes(Int)");
+
+        let json = format!("[\n{}\n]", entries.iter().map(|e| e.to_json()).collect::<Vec<_>>().join(",\n  "));
+        assert!(parse_entries(&json).unwrap() == entries, "Entries should survive the node API format");
+
+        let state = ContractState::from_json(&json).unwrap();
+        assert!(state.message_num().unwrap() == 1 && state.root_num().unwrap() == 1);
+        assert!(state.is_spent(&nf[1]).unwrap() && !state.is_spent(&out_hash[0]).unwrap());
+        assert!(state.utxo_exists(&out_hash[0]).unwrap());
+        assert!(state.root_exists(&new_root, &empty_root(48)).unwrap() && state.root_exists(&empty_root(48), &empty_root(48)).unwrap());
+        assert!(state.root_value::<Fr>(1).unwrap() == Some(new_root) && state.root_value::<Fr>(2).unwrap().is_none());
+        assert!(state.message::<Fr>(0).unwrap() == Some((nf, b"test".to_vec())));
+        assert!(state.utxo_message::<Fr>(0).unwrap() == Some(out_hash));
+
+        assert!(parse_entries("[]").unwrap().is_empty());
+        assert!(parse_entries("[{\"key\":\"MESSAGE_NUM\",\"type\":\"integer\",\"value\":\"1\"}]").is_err(), "Type and value should agree");
+        let wrong = ContractState::from_json("[{\"key\":\"MESSAGE_NUM\",\"type\":\"integer\",\"value\":1}]").unwrap();
+        assert!(wrong.message_num().is_err(), "Counter should be binary as the contract writes it");
+        assert!(parse_entries("[{\"key\":\"a\",\"type\":\"boolean\",\"value\":true}] x").is_err());
+        assert!(parse_entries("[{\"key\":\"a\",\"type\":\"integer\",\"value\":1.5}]").is_err(), "Integer should not be a float");
+        assert!(parse_entries("{\"key\":\"a\"}").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use pairing::{PrimeField, PrimeFieldRepr};
+use sapling_crypto::jubjub::{JubjubEngine, JubjubParams, FixedGenerators, PrimeOrder, ToUniform, edwards::Point};
+use sapling_crypto::primitives::{Diversifier, PaymentAddress, ViewingKey};
+use blake2_rfc::blake2b::Blake2b;
+
+use crate::point::{PointError, read_edwards_point};
+
+use std::io::{self, Read, Write};
+
+
+// Sapling key encodings of librustzcash (zcash_primitives::keys, zip32), so spending keys,
+// viewing keys and addresses can be moved between this crate and zcash tooling. Points are
+// edwards::Point::write (y little-endian, sign of x in the top bit), scalars are little-endian.
+// Decoding is as strict as librustzcash: canonical encodings, prime order subgroup.
+pub const PRF_EXPAND_PERSONALIZATION: &[u8; 16] = b"Zcash_ExpandSeed";
+pub const SPENDING_KEY_LEN: usize = 32;
+pub const EXPANDED_SPENDING_KEY_LEN: usize = 96;
+pub const FULL_VIEWING_KEY_LEN: usize = 96;
+pub const PAYMENT_ADDRESS_LEN: usize = 43;
+
+
+pub fn prf_expand(sk: &[u8; SPENDING_KEY_LEN], t: &[u8]) -> [u8; 64] {
+    let mut h = Blake2b::with_params(64, &[], &[], PRF_EXPAND_PERSONALIZATION);
+    h.update(sk);
+    h.update(t);
+    let mut res = [0u8; 64];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+fn read_scalar<E: JubjubEngine, R: Read>(reader: R) -> io::Result<E::Fs> {
+    let mut repr = <E::Fs as PrimeField>::Repr::default();
+    repr.read_le(reader)?;
+    E::Fs::from_repr(repr).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "scalar is not canonical"))
+}
+
+fn read_prime_order<E: JubjubEngine, R: Read>(reader: R, params: &E::Params) -> io::Result<Point<E, PrimeOrder>> {
+    let p = read_edwards_point::<E, _>(reader, true, params)?;
+    Ok(p.as_prime_order(params).ok_or(PointError::WrongSubgroup)?)
+}
+
+
+pub struct ExpandedSpendingKey<E: JubjubEngine> {
+    pub ask: E::Fs,
+    pub nsk: E::Fs,
+    pub ovk: [u8; 32]
+}
+
+impl<E: JubjubEngine> ExpandedSpendingKey<E> {
+    pub fn from_spending_key(sk: &[u8; SPENDING_KEY_LEN]) -> Self {
+        let mut ovk = [0u8; 32];
+        ovk.copy_from_slice(&prf_expand(sk, &[0x02])[..32]);
+        ExpandedSpendingKey {
+            ask: E::Fs::to_uniform(&prf_expand(sk, &[0x00])),
+            nsk: E::Fs::to_uniform(&prf_expand(sk, &[0x01])),
+            ovk
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.ask.into_repr().write_le(&mut writer)?;
+        self.nsk.into_repr().write_le(&mut writer)?;
+        writer.write_all(&self.ovk)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let ask = read_scalar::<E, _>(&mut reader)?;
+        let nsk = read_scalar::<E, _>(&mut reader)?;
+        let mut ovk = [0u8; 32];
+        reader.read_exact(&mut ovk)?;
+        Ok(ExpandedSpendingKey { ask, nsk, ovk })
+    }
+}
+
+
+pub struct FullViewingKey<E: JubjubEngine> {
+    pub vk: ViewingKey<E>,
+    pub ovk: [u8; 32]
+}
+
+impl<E: JubjubEngine> FullViewingKey<E> {
+    pub fn from_expanded_spending_key(expsk: &ExpandedSpendingKey<E>, params: &E::Params) -> Self {
+        FullViewingKey {
+            vk: ViewingKey {
+                ak: params.generator(FixedGenerators::SpendingKeyGenerator).mul(expsk.ask, params),
+                nk: params.generator(FixedGenerators::ProofGenerationKey).mul(expsk.nsk, params)
+            },
+            ovk: expsk.ovk
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.vk.ak.write(&mut writer)?;
+        self.vk.nk.write(&mut writer)?;
+        writer.write_all(&self.ovk)
+    }
+
+    // ak = 0 would let anybody sign for the key, librustzcash rejects it too
+    pub fn read<R: Read>(mut reader: R, params: &E::Params) -> io::Result<Self> {
+        let ak = read_prime_order::<E, _>(&mut reader, params)?;
+        if ak == Point::zero() {
+            return Err(PointError::Infinity.into());
+        }
+        let nk = read_prime_order::<E, _>(&mut reader, params)?;
+        let mut ovk = [0u8; 32];
+        reader.read_exact(&mut ovk)?;
+        Ok(FullViewingKey { vk: ViewingKey { ak, nk }, ovk })
+    }
+}
+
+
+// d (11 bytes) || pk_d
+pub fn write_payment_address<E: JubjubEngine, W: Write>(addr: &PaymentAddress<E>, mut writer: W) -> io::Result<()> {
+    writer.write_all(&addr.diversifier.0)?;
+    addr.pk_d.write(&mut writer)
+}
+
+// Rejects diversifiers without g_d and pk_d outside the prime order subgroup
+pub fn read_payment_address<E: JubjubEngine, R: Read>(mut reader: R, params: &E::Params) -> io::Result<PaymentAddress<E>> {
+    let mut d = [0u8; 11];
+    reader.read_exact(&mut d)?;
+    let diversifier = Diversifier(d);
+    if diversifier.g_d::<E>(params).is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid diversifier"));
+    }
+    let pk_d = read_prime_order::<E, _>(&mut reader, params)?;
+    Ok(PaymentAddress { pk_d, diversifier })
+}
+
+
+#[cfg(test)]
+mod sapling_keys_tests {
+    use super::*;
+    use pairing::Field;
+    use pairing::bls12_381::{Bls12, Fr};
+    use sapling_crypto::primitives::ProofGenerationKey;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    fn bytes(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    // First vector of sapling_key_components in zcash-hackworks/zcash-test-vectors, sk = 0
+    #[test]
+    fn test_zcash_vector() {
+        let params = &*JUBJUB_PARAMS;
+        let expsk = ExpandedSpendingKey::<Bls12>::from_spending_key(&[0; 32]);
+
+        let mut data = vec![];
+        expsk.write(&mut data).unwrap();
+        assert!(data == bytes("8548a14a473ea547aa2378402044f818cf1911cf5dd2054f678345f00d0e8806\
+                               30114ea0dd0bb61cf0eaeab6ec3331f581b0425e27338501262d7eac745e6e05\
+                               98d16913d99b04177caba44f6e4d224e03b5ac031d7ce45e865138e1b996d63b"), "ask || nsk || ovk");
+
+        let fvk = FullViewingKey::from_expanded_spending_key(&expsk, params);
+        let mut data = vec![];
+        fvk.write(&mut data).unwrap();
+        assert!(data == bytes("f344ec380fe1273e3098c2588c5d3a791fd7ba958032760777fd0efa8ef11620\
+                               f7cf9e77f2e58683383c1519ac7b062d30040e27a725fb88fb19a978bd3fd6ba\
+                               98d16913d99b04177caba44f6e4d224e03b5ac031d7ce45e865138e1b996d63b"), "ak || nk || ovk");
+
+        let mut ivk = vec![];
+        fvk.vk.ivk().into_repr().write_le(&mut ivk).unwrap();
+        assert!(ivk == bytes("b70b7cd0ed03cbdfd7ada9502ee245b13e569d54a5719d2daa0f5f1451479204"));
+
+        let mut d = [0u8; 11];
+        d.copy_from_slice(&bytes("f19d9b797e39f337445839"));
+        let addr = fvk.vk.into_payment_address(Diversifier(d), params).unwrap();
+        let mut data = vec![];
+        write_payment_address(&addr, &mut data).unwrap();
+        assert!(data == bytes("f19d9b797e39f337445839db4cd2b0aac4f7eb8ca131f16567c445a9555126d3c29f14e3d776e841ae7415"), "d || pk_d");
+        assert!(read_payment_address::<Bls12, _>(&data[..], params).unwrap().pk_d == addr.pk_d);
+    }
+
+    #[test]
+    fn test_key_encodings() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let expsk = ExpandedSpendingKey::<Bls12>::from_spending_key(&rng.gen());
+
+        let mut data = vec![];
+        expsk.write(&mut data).unwrap();
+        assert!(data.len() == EXPANDED_SPENDING_KEY_LEN);
+        let read = ExpandedSpendingKey::<Bls12>::read(&data[..]).unwrap();
+        assert!(read.ask == expsk.ask && read.nsk == expsk.nsk && read.ovk == expsk.ovk);
+        let mut wrong = data.clone();
+        wrong[31] = 0xff;
+        assert!(ExpandedSpendingKey::<Bls12>::read(&wrong[..]).is_err(), "Non-canonical scalar should be rejected");
+
+        let fvk = FullViewingKey::from_expanded_spending_key(&expsk, params);
+        let pgk = ProofGenerationKey { ak: fvk.vk.ak.clone(), nsk: expsk.nsk };
+        assert!(pgk.into_viewing_key(params).nk == fvk.vk.nk);
+        let mut data = vec![];
+        fvk.write(&mut data).unwrap();
+        assert!(data.len() == FULL_VIEWING_KEY_LEN);
+        let read = FullViewingKey::<Bls12>::read(&data[..], params).unwrap();
+        assert!(read.vk.ak == fvk.vk.ak && read.vk.nk == fvk.vk.nk && read.ovk == fvk.ovk);
+
+        // identity, and a point of order 2: (0, -1)
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        let mut wrong = data.clone();
+        wrong[..32].copy_from_slice(&identity);
+        assert!(FullViewingKey::<Bls12>::read(&wrong[..], params).is_err(), "ak = 0 should be rejected");
+        let mut small = vec![];
+        let mut y = Fr::one();
+        y.negate();
+        y.into_repr().write_le(&mut small).unwrap();
+        let mut wrong = data.clone();
+        wrong[32..64].copy_from_slice(&small);
+        assert!(FullViewingKey::<Bls12>::read(&wrong[..], params).is_err(), "Small order nk should be rejected");
+        identity[31] |= 0x80;
+        let mut wrong = data.clone();
+        wrong[32..64].copy_from_slice(&identity);
+        assert!(FullViewingKey::<Bls12>::read(&wrong[..], params).is_err(), "Sign bit of x = 0 should be rejected");
+
+        let addr = loop {
+            if let Some(addr) = fvk.vk.into_payment_address(Diversifier(rng.gen()), params) {
+                break addr;
+            }
+        };
+        let mut data = vec![];
+        write_payment_address(&addr, &mut data).unwrap();
+        assert!(data.len() == PAYMENT_ADDRESS_LEN);
+        assert!(read_payment_address::<Bls12, _>(&data[..], params).unwrap().pk_d == addr.pk_d);
+        let d = loop {
+            let d = rng.gen::<[u8; 11]>();
+            if Diversifier(d).g_d::<Bls12>(params).is_none() {
+                break d;
+            }
+        };
+        let mut wrong = data.clone();
+        wrong[..11].copy_from_slice(&d);
+        assert!(read_payment_address::<Bls12, _>(&wrong[..], params).is_err(), "Invalid diversifier should be rejected");
+    }
+}
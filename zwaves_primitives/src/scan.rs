@@ -0,0 +1,327 @@
+use pairing::{Field, PrimeField, PrimeFieldRepr, SqrtField};
+use sapling_crypto::jubjub::{JubjubEngine, JubjubParams};
+
+use crate::fieldtools;
+use crate::field::batch_inverse;
+use crate::point::PointError;
+
+
+const WINDOW: usize = 4;
+
+
+// Wallet scanning computes edh(epk, sk) for every ephemeral key on chain with the same sk.
+//
+// The bits of sk are taken once, every key is multiplied by them with a Montgomery ladder, and
+// all inversions of a batch are merged into one. The subgroup check multiplies by the public
+// group order with a 4-bit window table.
+pub struct ViewingKeyPrecomp {
+    bits: Vec<bool>,
+    order_digits: Vec<u8>
+}
+
+fn window_digits<R: PrimeFieldRepr>(repr: &R) -> Vec<u8> {
+    let limbs = repr.as_ref();
+    (0..limbs.len() * 64 / WINDOW).rev()
+        .map(|i| ((limbs[i * WINDOW / 64] >> (i * WINDOW % 64)) & ((1 << WINDOW) - 1)) as u8)
+        .collect()
+}
+
+// NUM_BITS bits, msb first: the same number of ladder steps for every sk
+fn scalar_bits<F: PrimeField>(x: &F) -> Vec<bool> {
+    let repr = x.into_repr();
+    let limbs = repr.as_ref();
+    (0..F::NUM_BITS as usize).rev().map(|i| (limbs[i / 64] >> (i % 64)) & 1 == 1).collect()
+}
+
+impl ViewingKeyPrecomp {
+    pub fn new<E: JubjubEngine>(sk: &E::Fr) -> Self {
+        ViewingKeyPrecomp {
+            bits: scalar_bits(&fieldtools::f2f::<E::Fr, E::Fs>(sk)),
+            order_digits: window_digits(&E::Fs::char())
+        }
+    }
+}
+
+
+// Extended twisted Edwards coordinates, same formulas as sapling_crypto::jubjub::edwards
+#[derive(Clone, Copy)]
+struct ExtPoint<F: PrimeField> {
+    x: F,
+    y: F,
+    t: F,
+    z: F
+}
+
+impl<F: PrimeField> ExtPoint<F> {
+    fn zero() -> Self {
+        ExtPoint { x: F::zero(), y: F::one(), t: F::zero(), z: F::one() }
+    }
+
+    fn double(&self) -> Self {
+        let mut a = self.x;
+        a.square();
+
+        let mut b = self.y;
+        b.square();
+
+        let mut c = self.z;
+        c.square();
+        c.double();
+
+        let mut d = a;
+        d.negate();
+
+        let mut e = self.x;
+        e.add_assign(&self.y);
+        e.square();
+        e.add_assign(&d);
+        e.sub_assign(&b);
+
+        let mut g = d;
+        g.add_assign(&b);
+
+        let mut f = g;
+        f.sub_assign(&c);
+
+        let mut h = d;
+        h.sub_assign(&b);
+
+        let mut x = e;
+        x.mul_assign(&f);
+        let mut y = g;
+        y.mul_assign(&h);
+        let mut t = e;
+        t.mul_assign(&h);
+        let mut z = f;
+        z.mul_assign(&g);
+
+        ExtPoint { x, y, t, z }
+    }
+
+    fn add(&self, other: &Self, edwards_d: &F) -> Self {
+        let mut a = self.x;
+        a.mul_assign(&other.x);
+
+        let mut b = self.y;
+        b.mul_assign(&other.y);
+
+        let mut c = *edwards_d;
+        c.mul_assign(&self.t);
+        c.mul_assign(&other.t);
+
+        let mut d = self.z;
+        d.mul_assign(&other.z);
+
+        let mut h = b;
+        h.add_assign(&a);
+
+        let mut e = self.x;
+        e.add_assign(&self.y);
+        let mut tmp = other.x;
+        tmp.add_assign(&other.y);
+        e.mul_assign(&tmp);
+        e.sub_assign(&h);
+
+        let mut f = d;
+        f.sub_assign(&c);
+
+        let mut g = d;
+        g.add_assign(&c);
+
+        let mut x = e;
+        x.mul_assign(&f);
+        let mut y = g;
+        y.mul_assign(&h);
+        let mut t = e;
+        t.mul_assign(&h);
+        let mut z = f;
+        z.mul_assign(&g);
+
+        ExtPoint { x, y, t, z }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.x.is_zero() && self.y == self.z
+    }
+
+    // (x, -y) is the other point with the same x
+    fn flip_y(&self) -> Self {
+        let mut res = *self;
+        res.y.negate();
+        res.t.negate();
+        res
+    }
+
+    fn table(&self, edwards_d: &F) -> Vec<Self> {
+        let mut table = vec![ExtPoint::zero(); 1 << WINDOW];
+        for i in 1..table.len() {
+            table[i] = table[i - 1].add(self, edwards_d);
+        }
+        table
+    }
+}
+
+// The group order is public, lookups indexed by its digits leak nothing
+fn mul_table<F: PrimeField>(table: &[ExtPoint<F>], digits: &[u8], edwards_d: &F) -> ExtPoint<F> {
+    let mut res = ExtPoint::zero();
+    for &digit in digits.iter() {
+        for _ in 0..WINDOW {
+            res = res.double();
+        }
+        res = res.add(&table[digit as usize], edwards_d);
+    }
+    res
+}
+
+// Swaps a and b when bit is one, with arithmetic instead of a branch on the bit
+fn cswap<F: PrimeField>(a: &mut ExtPoint<F>, b: &mut ExtPoint<F>, bit: bool) {
+    let mask = if bit { F::one() } else { F::zero() };
+    for (u, v) in [(&mut a.x, &mut b.x), (&mut a.y, &mut b.y), (&mut a.t, &mut b.t), (&mut a.z, &mut b.z)].iter_mut() {
+        let mut diff = **v;
+        diff.sub_assign(u);
+        diff.mul_assign(&mask);
+        u.add_assign(&diff);
+        v.sub_assign(&diff);
+    }
+}
+
+// Montgomery ladder: r0 = kp and r1 = (k + 1)p for the bits k seen so far, one addition and one
+// doubling per bit whatever the bit is, as the Edwards formulas are complete. The sequence of
+// operations does not depend on sk; the field arithmetic of pairing is not constant time.
+fn ladder<F: PrimeField>(p: &ExtPoint<F>, bits: &[bool], edwards_d: &F) -> ExtPoint<F> {
+    let mut r0 = ExtPoint::zero();
+    let mut r1 = *p;
+    for &bit in bits.iter() {
+        // bit 0: r1 = r0 + r1, r0 = 2r0; bit 1: r0 = r0 + r1, r1 = 2r1
+        cswap(&mut r0, &mut r1, bit);
+        r1 = r0.add(&r1, edwards_d);
+        r0 = r0.double();
+        cswap(&mut r0, &mut r1, bit);
+    }
+    r0
+}
+
+
+// Same results as transactions::edh, key by key.
+pub fn edh_batch<E: JubjubEngine>(pk_xs: &[E::Fr], key: &ViewingKeyPrecomp, params: &E::Params) -> Vec<Result<E::Fr, PointError>> {
+    let edwards_d = params.edwards_d();
+
+    // y^2 = - (x^2 + 1) / (dx^2 - 1)
+    let mut num = Vec::with_capacity(pk_xs.len());
+    let mut den = Vec::with_capacity(pk_xs.len());
+    let mut valid = Vec::with_capacity(pk_xs.len());
+    for x in pk_xs.iter() {
+        let mut x2 = *x;
+        x2.square();
+
+        let mut d = x2;
+        d.mul_assign(edwards_d);
+        d.sub_assign(&E::Fr::one());
+
+        let mut n = x2;
+        n.negate();
+        n.sub_assign(&E::Fr::one());
+
+        // get_for_x rejects dx^2 = 1, keep the slot for the batch inversion. n = 0 is the
+        // point with y = 0, which is on the curve.
+        valid.push(!d.is_zero());
+        if d.is_zero() {
+            d = E::Fr::one();
+        }
+
+        num.push(n);
+        den.push(d);
+    }
+    batch_inverse(&mut den);
+
+    let mut res = vec![Err(PointError::NotOnCurve); pk_xs.len()];
+    let mut points = vec![];
+    let mut indexes = vec![];
+
+    for (i, x) in pk_xs.iter().enumerate() {
+        let mut y2 = num[i];
+        y2.mul_assign(&den[i]);
+
+        let y = match y2.sqrt() {
+            Some(ref y) if valid[i] => *y,
+            _ => continue
+        };
+
+        let mut t = *x;
+        t.mul_assign(&y);
+        let p = ExtPoint { x: *x, y, t, z: E::Fr::one() };
+
+        // like get_for_x: take the root from the prime order subgroup, the other one otherwise
+        let p = if mul_table(&p.table(edwards_d), &key.order_digits, edwards_d).is_zero() { p } else { p.flip_y() };
+
+        points.push(ladder(&p, &key.bits, edwards_d));
+        indexes.push(i);
+    }
+
+    let mut zs = points.iter().map(|p| p.z).collect::<Vec<_>>();
+    batch_inverse(&mut zs);
+
+    for ((p, z_inv), i) in points.iter().zip(zs.iter()).zip(indexes.into_iter()) {
+        let mut x = p.x;
+        x.mul_assign(z_inv);
+        res[i] = Ok(x);
+    }
+
+    res
+}
+
+
+#[cfg(test)]
+mod scan_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use sapling_crypto::jubjub::JubjubBls12;
+    use sapling_crypto::jubjub::edwards::Point;
+    use sapling_crypto::jubjub::Unknown;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::transactions::{edh, pubkey};
+
+    #[test]
+    fn test_edh_batch() {
+        let mut rng = OsRng::new().unwrap();
+        let params = JubjubBls12::new();
+
+        let sk = rng.gen::<Fr>();
+        let key = ViewingKeyPrecomp::new::<Bls12>(&sk);
+
+        let mut pks = (0..8).map(|_| pubkey::<Bls12>(&rng.gen(), &params)).collect::<Vec<_>>();
+        let off_curve = loop {
+            let x = rng.gen::<Fr>();
+            if Point::<Bls12, Unknown>::get_for_x(x, &params).is_none() {
+                break x;
+            }
+        };
+        pks.insert(3, off_curve);
+
+        // x^2 = -1 is the point with y = 0
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        pks.push(minus_one.sqrt().unwrap());
+        assert!(edh::<Bls12>(&pks[pks.len() - 1], &sk, &params).is_ok());
+
+        // random points on the curve are almost never in the prime order subgroup
+        while pks.len() < 13 {
+            let x = rng.gen::<Fr>();
+            if Point::<Bls12, Unknown>::get_for_x(x, &params).is_some() {
+                pks.push(x);
+            }
+        }
+
+        let res = edh_batch::<Bls12>(&pks, &key, &params);
+        assert!(res.len() == pks.len());
+
+        for (pk, r) in pks.iter().zip(res.iter()) {
+            match edh::<Bls12>(pk, &sk, &params) {
+                Ok(expected) => assert!(*r == Ok(expected), "Batched edh should match edh"),
+                Err(_) => assert!(*r == Err(PointError::NotOnCurve), "Off curve key should be reported")
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use pairing::{Engine, PrimeField, PrimeFieldRepr};
+use bellman::groth16::Proof;
+
+use blake2_rfc::blake2s::Blake2s;
+use byteorder::{BigEndian, WriteBytesExt};
+
+use crate::out_amount::OUT_CIPHERTEXT_LEN;
+
+
+// Every leaf of the message tree is hashed with its own personalization, so a digest
+// of one part can never be replayed as a digest of another.
+pub const SIGHASH_PROOF_PERSONALIZATION: &[u8; 8] = b"ZWSH_prf";
+pub const SIGHASH_INPUTS_PERSONALIZATION: &[u8; 8] = b"ZWSH_inp";
+pub const SIGHASH_OUTPUTS_PERSONALIZATION: &[u8; 8] = b"ZWSH_out";
+pub const SIGHASH_PERSONALIZATION: &[u8; 8] = b"ZWSH_msg";
+
+pub const SIGHASH_LEN: usize = 32;
+
+// Leads the message, so a signature of another format version never verifies. 2 since the
+// outputs digest covers the out ciphertexts.
+pub const SIGHASH_VERSION: u8 = 2;
+
+
+pub struct BundleParts<'a, E: Engine> {
+    pub proof: &'a Proof<E>,
+    pub public_inputs: &'a [E::Fr],
+    pub encrypted_notes: &'a [Vec<u8>],
+    pub out_ciphertexts: &'a [[u8; OUT_CIPHERTEXT_LEN]],
+    pub fee: u64
+}
+
+
+fn digest(personalization: &[u8], data: &[u8]) -> [u8; SIGHASH_LEN] {
+    let mut h = Blake2s::with_params(SIGHASH_LEN, &[], &[], personalization);
+    h.update(data);
+    let mut res = [0u8; SIGHASH_LEN];
+    res.copy_from_slice(h.finalize().as_bytes());
+    res
+}
+
+
+pub fn proof_digest<E: Engine>(proof: &Proof<E>) -> [u8; SIGHASH_LEN] {
+    let mut data = vec![];
+    proof.write(&mut data).unwrap();
+    digest(SIGHASH_PROOF_PERSONALIZATION, &data)
+}
+
+pub fn inputs_digest<E: Engine>(public_inputs: &[E::Fr]) -> [u8; SIGHASH_LEN] {
+    let mut data = vec![];
+    data.write_u32::<BigEndian>(public_inputs.len() as u32).unwrap();
+    for input in public_inputs.iter() {
+        input.into_repr().write_be(&mut data).unwrap();
+    }
+    digest(SIGHASH_INPUTS_PERSONALIZATION, &data)
+}
+
+pub fn outputs_digest(encrypted_notes: &[Vec<u8>], out_ciphertexts: &[[u8; OUT_CIPHERTEXT_LEN]]) -> [u8; SIGHASH_LEN] {
+    let mut data = vec![];
+    data.write_u32::<BigEndian>(encrypted_notes.len() as u32).unwrap();
+    for note in encrypted_notes.iter() {
+        data.write_u32::<BigEndian>(note.len() as u32).unwrap();
+        data.extend_from_slice(note);
+    }
+    data.write_u32::<BigEndian>(out_ciphertexts.len() as u32).unwrap();
+    for ct in out_ciphertexts.iter() {
+        data.extend_from_slice(ct);
+    }
+    digest(SIGHASH_OUTPUTS_PERSONALIZATION, &data)
+}
+
+
+// msg = H_msg(version | H_prf(proof) | H_inp(inputs) | H_out(notes | out ciphertexts) | fee)
+//
+// Both the spend authorization and the binding signature are made over this message,
+// so no part of a bundle could be swapped with a part of another one.
+pub fn signing_message<E: Engine>(parts: &BundleParts<E>) -> [u8; SIGHASH_LEN] {
+    message_from_digests(&proof_digest(parts.proof), &inputs_digest::<E>(parts.public_inputs), &outputs_digest(parts.encrypted_notes, parts.out_ciphertexts), parts.fee)
+}
+
+// The last step alone, for a signer which gets the digests of the parts it can not check
+pub fn message_from_digests(proof: &[u8; SIGHASH_LEN], inputs: &[u8; SIGHASH_LEN], outputs: &[u8; SIGHASH_LEN], fee: u64) -> [u8; SIGHASH_LEN] {
+    let mut data = Vec::with_capacity(1 + 3 * SIGHASH_LEN + 8);
+    data.push(SIGHASH_VERSION);
+    data.extend_from_slice(proof);
+    data.extend_from_slice(inputs);
+    data.extend_from_slice(outputs);
+    data.write_u64::<BigEndian>(fee).unwrap();
+    digest(SIGHASH_PERSONALIZATION, &data)
+}
@@ -0,0 +1,148 @@
+use pairing::{PrimeField, PrimeFieldRepr};
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::redjubjub::Signature;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use rand::Rng;
+
+use std::io::{self, Read, Write};
+
+use crate::serialization::read_fr_repr_be;
+use crate::sighash::{self, SIGHASH_LEN};
+use crate::public_values::{TransferPublicValues, TRANSFER_PUBLIC_INPUTS};
+use crate::spend_auth;
+
+
+// Spend authorization signed outside of the process building the transfer, by a hardware
+// wallet holding sk. The builder proves with zwaves_circuit::circuit::AuthorityTransfer, which
+// takes ak and nk and not sk, exports a SigningRequest (TransactionBundle::signing_request) and
+// takes the signature back with TransactionBundle::set_spend_auth_sig, which checks it against
+// rk of the public inputs. Without sk the notes spent this way are the ones owned by
+// transactions::threshold_owner(x(ak), nk).
+//
+// The device does not sign a hash it is given: it recomputes the sighash from the public inputs
+// and the fee, which it shows to the user (receiver, packed asset, fee), and from the digests of
+// the proof and of the encrypted outputs, which it could not check anyway. It checks that rk of
+// the inputs is ak + alpha*G, so it can not be made to sign for another key or another randomizer
+// than the proof's.
+//
+// version (2) | proof digest (32) | public inputs (32 BE each) | outputs digest (32) |
+// alpha (32 BE) | fee (u64 BE)
+pub const SIGNING_REQUEST_VERSION: u8 = 2;
+pub const SIGNING_REQUEST_LEN: usize = 1 + SIGHASH_LEN + 32 * TRANSFER_PUBLIC_INPUTS + SIGHASH_LEN + 32 + 8;
+pub const SIGNATURE_LEN: usize = 64;
+
+
+pub struct SigningRequest<E: JubjubEngine> {
+    pub proof_digest: [u8; SIGHASH_LEN],
+    pub public_values: TransferPublicValues<E::Fr>,
+    pub outputs_digest: [u8; SIGHASH_LEN],
+    pub alpha: E::Fr,
+    pub fee: u64
+}
+
+fn read_fr<F: PrimeField, R: Read>(mut reader: R) -> io::Result<F> {
+    let mut data = [0u8; 32];
+    reader.read_exact(&mut data)?;
+    F::from_repr(read_fr_repr_be::<F>(&data)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))
+}
+
+impl<E: JubjubEngine> SigningRequest<E> {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(SIGNING_REQUEST_VERSION)?;
+        writer.write_all(&self.proof_digest)?;
+        for x in self.public_values.to_vec() {
+            x.into_repr().write_be(&mut writer)?;
+        }
+        writer.write_all(&self.outputs_digest)?;
+        self.alpha.into_repr().write_be(&mut writer)?;
+        writer.write_u64::<BigEndian>(self.fee)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        if reader.read_u8()? != SIGNING_REQUEST_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown signing request version"));
+        }
+        let mut proof_digest = [0u8; SIGHASH_LEN];
+        reader.read_exact(&mut proof_digest)?;
+        let inputs = (0..TRANSFER_PUBLIC_INPUTS).map(|_| read_fr::<E::Fr, _>(&mut reader)).collect::<io::Result<Vec<_>>>()?;
+        let public_values = TransferPublicValues::from_slice(&inputs)?;
+        let mut outputs_digest = [0u8; SIGHASH_LEN];
+        reader.read_exact(&mut outputs_digest)?;
+        let alpha = read_fr::<E::Fr, _>(&mut reader)?;
+        let fee = reader.read_u64::<BigEndian>()?;
+        Ok(SigningRequest { proof_digest, public_values, outputs_digest, alpha, fee })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = vec![];
+        self.write(&mut res).unwrap();
+        res
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.len() != SIGNING_REQUEST_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("signing request should be {} bytes", SIGNING_REQUEST_LEN)));
+        }
+        Self::read(data)
+    }
+
+    // TransactionBundle::signing_message of the bundle the request is made for
+    pub fn sighash(&self) -> [u8; SIGHASH_LEN] {
+        let inputs = sighash::inputs_digest::<E>(&self.public_values.to_vec());
+        sighash::message_from_digests(&self.proof_digest, &inputs, &self.outputs_digest, self.fee)
+    }
+
+    // What a device does with the request, for software signers and tests
+    pub fn sign<R: Rng>(&self, sk: &E::Fr, rng: &mut R, params: &E::Params) -> io::Result<Signature> {
+        let rk = spend_auth::randomized_public_key(&spend_auth::spend_auth_key::<E>(sk, params), &self.alpha, params);
+        let (x, y) = spend_auth::rk_to_inputs(&rk);
+        if [x, y] != self.public_values.rk {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "rk of the request is not of this key and alpha"));
+        }
+        Ok(spend_auth::sign::<E, _>(sk, &self.alpha, &self.sighash(), rng, params))
+    }
+}
+
+
+#[cfg(test)]
+mod signing_request_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::os::OsRng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    #[test]
+    fn test_signing_request() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let sk = rng.gen::<Fr>();
+        let alpha = rng.gen::<Fr>();
+        let rk = spend_auth::randomized_public_key(&spend_auth::spend_auth_key::<Bls12>(&sk, params), &alpha, params);
+        let mut inputs = (0..TRANSFER_PUBLIC_INPUTS).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let (x, y) = spend_auth::rk_to_inputs(&rk);
+        inputs[7] = x;
+        inputs[8] = y;
+        let req = SigningRequest::<Bls12> {
+            proof_digest: rng.gen(),
+            public_values: TransferPublicValues::from_slice(&inputs).unwrap(),
+            outputs_digest: rng.gen(),
+            alpha,
+            fee: 100500
+        };
+
+        let data = req.to_bytes();
+        assert!(data.len() == SIGNING_REQUEST_LEN);
+        let read = SigningRequest::<Bls12>::from_bytes(&data).unwrap();
+        assert!(read.to_bytes() == data);
+        assert!(SigningRequest::<Bls12>::from_bytes(&data[1..]).is_err());
+
+        let sig = read.sign(&sk, &mut rng, params).unwrap();
+        assert!(spend_auth::verify(&rk, &req.sighash(), &sig, params));
+        assert!(read.sign(&rng.gen(), &mut rng, params).is_err(), "Device should refuse a request for another key");
+
+        let mut other = SigningRequest::<Bls12>::from_bytes(&data).unwrap();
+        other.fee += 1;
+        assert!(other.sighash() != req.sighash(), "Shown fee should be bound to the signed message");
+    }
+}
@@ -0,0 +1,239 @@
+use pairing::{CurveAffine, EncodedPoint};
+use pairing::bls12_381::{Bls12, Fr, G1Affine, G2Affine};
+use bellman::groth16::Proof;
+use num::{BigUint, Num};
+use serde_json::{json, Value};
+
+use std::io;
+
+use crate::field::{from_biguint, to_biguint};
+use crate::point::PointError;
+use crate::verifier::{TruncatedVerifyingKey, verify_proof};
+
+
+// Groth16 keys, proofs and public inputs as the JSON of snarkjs on BLS12-381 (circom -p bls12381,
+// snarkjs with the bls12381 curve): verification_key.json, proof.json and public.json. Circuits
+// prototyped in circom verify with verifier.rs, and keys and proofs of this crate with snarkjs.
+//
+// Numbers are decimal strings. Points are projective with z = 1, G1 as [x, y, "1"] and G2 as
+// [[x.c0, x.c1], [y.c0, y.c1], ["1", "0"]]; the points are checked as read_point does in strict
+// mode. Infinity is rejected both ways, so a key with a zero IC entry (an input no constraint uses)
+// can't be exported and has to be fixed in the circuit. vk_alphabeta_12 is neither read nor written, snarkjs verifies without.
+pub const SNARKJS_PROTOCOL: &str = "groth16";
+pub const SNARKJS_CURVE: &str = "bls12381";
+
+const FQ_LEN: usize = 48;
+
+
+fn json_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("wrong snarkjs json: {}", msg))
+}
+
+fn parse(data: &str) -> io::Result<Value> {
+    serde_json::from_str(data).map_err(|e| json_error(&e.to_string()))
+}
+
+fn decimal(v: &Value, name: &str) -> io::Result<BigUint> {
+    v.as_str().and_then(|s| BigUint::from_str_radix(s, 10).ok())
+        .ok_or_else(|| json_error(&format!("{} should be a decimal string", name)))
+}
+
+fn is_g2<G: CurveAffine>() -> bool {
+    G::Uncompressed::size() == 4 * FQ_LEN
+}
+
+// Coordinates of the uncompressed encoding: x, y for G1 and x.c1, x.c0, y.c1, y.c0 for G2. Infinity
+// is refused as point_from_json refuses it, so whatever is written reads back
+fn point_to_json<G: CurveAffine>(p: &G, name: &str) -> io::Result<Value> {
+    if p.is_zero() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is at infinity", name)));
+    }
+    let c = coordinates(p);
+    Ok(if is_g2::<G>() {
+        json!([[c[1], c[0]], [c[3], c[2]], ["1", "0"]])
+    } else {
+        json!([c[0], c[1], "1"])
+    })
+}
+
+fn coordinates<G: CurveAffine>(p: &G) -> Vec<String> {
+    p.into_uncompressed().as_ref().chunks(FQ_LEN).map(|c| BigUint::from_bytes_be(c).to_str_radix(10)).collect()
+}
+
+fn point_from_json<G: CurveAffine>(v: &Value, name: &str) -> io::Result<G> {
+    let p = v.as_array().filter(|p| p.len() == 3).ok_or_else(|| json_error(&format!("{} should be a point of 3 coordinates", name)))?;
+    let (c, affine) = if is_g2::<G>() {
+        let fq2 = |v: &Value, i: usize| v.as_array().filter(|c| c.len() == 2).map(|c| c[i].clone())
+            .ok_or_else(|| json_error(&format!("{} should have coordinates of 2 elements", name)));
+        let z = (decimal(&fq2(&p[2], 0)?, name)?, decimal(&fq2(&p[2], 1)?, name)?);
+        (vec![fq2(&p[0], 1)?, fq2(&p[0], 0)?, fq2(&p[1], 1)?, fq2(&p[1], 0)?], z == (BigUint::from(1u32), BigUint::from(0u32)))
+    } else {
+        (vec![p[0].clone(), p[1].clone()], decimal(&p[2], name)? == BigUint::from(1u32))
+    };
+    if !affine {
+        return Err(json_error(&format!("{} should have z = 1", name)));
+    }
+
+    let mut repr = G::Uncompressed::empty();
+    for (chunk, x) in repr.as_mut().chunks_mut(FQ_LEN).zip(c.iter()) {
+        let bytes = decimal(x, name)?.to_bytes_be();
+        if bytes.len() > FQ_LEN {
+            return Err(PointError::NonCanonical.into());
+        }
+        chunk[FQ_LEN - bytes.len()..].copy_from_slice(&bytes);
+    }
+    let p = repr.into_affine().map_err(PointError::from)?;
+    if p.is_zero() {
+        return Err(PointError::Infinity.into());
+    }
+    Ok(p)
+}
+
+fn field<'a>(v: &'a Value, name: &str) -> io::Result<&'a Value> {
+    v.get(name).ok_or_else(|| json_error(&format!("{} is missing", name)))
+}
+
+fn check_header(v: &Value) -> io::Result<()> {
+    if field(v, "protocol")?.as_str() != Some(SNARKJS_PROTOCOL) {
+        return Err(json_error("protocol should be groth16"));
+    }
+    if field(v, "curve")?.as_str() != Some(SNARKJS_CURVE) {
+        return Err(json_error("curve should be bls12381"));
+    }
+    Ok(())
+}
+
+
+pub fn verifying_key_from_json(data: &str) -> io::Result<TruncatedVerifyingKey<Bls12>> {
+    let v = parse(data)?;
+    check_header(&v)?;
+    let ic = field(&v, "IC")?.as_array().ok_or_else(|| json_error("IC should be an array"))?
+        .iter().enumerate().map(|(i, p)| point_from_json::<G1Affine>(p, &format!("IC[{}]", i)))
+        .collect::<io::Result<Vec<_>>>()?;
+    if field(&v, "nPublic")?.as_u64() != Some(ic.len() as u64).filter(|&n| n > 0).map(|n| n - 1) {
+        return Err(json_error("nPublic should be the length of IC less one"));
+    }
+    Ok(TruncatedVerifyingKey {
+        alpha_g1: point_from_json(field(&v, "vk_alpha_1")?, "vk_alpha_1")?,
+        beta_g2: point_from_json(field(&v, "vk_beta_2")?, "vk_beta_2")?,
+        gamma_g2: point_from_json(field(&v, "vk_gamma_2")?, "vk_gamma_2")?,
+        delta_g2: point_from_json(field(&v, "vk_delta_2")?, "vk_delta_2")?,
+        ic
+    })
+}
+
+pub fn verifying_key_to_json(tvk: &TruncatedVerifyingKey<Bls12>) -> io::Result<String> {
+    let ic = tvk.ic.iter().enumerate().map(|(i, p)| point_to_json(p, &format!("IC[{}]", i))).collect::<io::Result<Vec<_>>>()?;
+    Ok(serde_json::to_string_pretty(&json!({
+        "protocol": SNARKJS_PROTOCOL,
+        "curve": SNARKJS_CURVE,
+        "nPublic": tvk.ic.len().saturating_sub(1),
+        "vk_alpha_1": point_to_json(&tvk.alpha_g1, "vk_alpha_1")?,
+        "vk_beta_2": point_to_json(&tvk.beta_g2, "vk_beta_2")?,
+        "vk_gamma_2": point_to_json(&tvk.gamma_g2, "vk_gamma_2")?,
+        "vk_delta_2": point_to_json(&tvk.delta_g2, "vk_delta_2")?,
+        "IC": ic
+    })).unwrap())
+}
+
+pub fn proof_from_json(data: &str) -> io::Result<Proof<Bls12>> {
+    let v = parse(data)?;
+    check_header(&v)?;
+    Ok(Proof {
+        a: point_from_json::<G1Affine>(field(&v, "pi_a")?, "pi_a")?,
+        b: point_from_json::<G2Affine>(field(&v, "pi_b")?, "pi_b")?,
+        c: point_from_json::<G1Affine>(field(&v, "pi_c")?, "pi_c")?
+    })
+}
+
+pub fn proof_to_json(proof: &Proof<Bls12>) -> io::Result<String> {
+    Ok(serde_json::to_string_pretty(&json!({
+        "pi_a": point_to_json(&proof.a, "pi_a")?,
+        "pi_b": point_to_json(&proof.b, "pi_b")?,
+        "pi_c": point_to_json(&proof.c, "pi_c")?,
+        "protocol": SNARKJS_PROTOCOL,
+        "curve": SNARKJS_CURVE
+    })).unwrap())
+}
+
+// Inputs at or above the modulus are rejected, not reduced
+pub fn inputs_from_json(data: &str) -> io::Result<Vec<Fr>> {
+    parse(data)?.as_array().ok_or_else(|| json_error("public inputs should be an array"))?
+        .iter().enumerate().map(|(i, x)| {
+            let name = format!("input {}", i);
+            from_biguint(&decimal(x, &name)?).ok_or_else(|| json_error(&format!("{} is not below the modulus", name)))
+        })
+        .collect()
+}
+
+pub fn inputs_to_json(inputs: &[Fr]) -> String {
+    serde_json::to_string_pretty(&inputs.iter().map(|x| to_biguint(x).to_str_radix(10)).collect::<Vec<_>>()).unwrap()
+}
+
+// snarkjs groth16 verify with the verifier of the node
+pub fn verify_json(vk: &str, proof: &str, inputs: &str) -> io::Result<bool> {
+    let tvk = verifying_key_from_json(vk)?;
+    let inputs = inputs_from_json(inputs)?;
+    if inputs.len() + 1 != tvk.ic.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("key takes {} public inputs, got {}", tvk.ic.len() - 1, inputs.len())));
+    }
+    Ok(verify_proof(&tvk, &proof_from_json(proof)?, &inputs).unwrap_or(false))
+}
+
+
+#[cfg(test)]
+mod snarkjs_tests {
+    use super::*;
+    use pairing::{Field, PrimeField};
+    use bellman::groth16::{generate_random_parameters, create_random_proof};
+    use rand::{Rng, XorShiftRng, SeedableRng};
+
+    use crate::verifier::truncate_verifying_key;
+    use crate::test_circuits::Mul;
+
+    #[test]
+    fn test_generators() {
+        let g1 = point_to_json(&G1Affine::one(), "g1").unwrap();
+        assert!(g1[0] == "3685416753713387016781088315183077757961620795782546409894578378688607592378376318836054947676345821548104185464507");
+        let g2 = point_to_json(&G2Affine::one(), "g2").unwrap();
+        assert!(g2[0][0] == "352701069587466618187139116011060144890029952792775240219908644239793785735715026873347600343865175952761926303160", "Fq2 should be written c0 first");
+        assert!(g2[0][1] == "3059144344244213709971259814753781636986470325476647558659373206291635324768958432433509563104347017837885763365758");
+        assert!(point_from_json::<G2Affine>(&g2, "g2").unwrap() == G2Affine::one());
+        assert!(point_from_json::<G1Affine>(&json!(["0", "1", "0"]), "g1").is_err(), "Infinity should be rejected");
+        assert!(point_to_json(&G1Affine::zero(), "g1").is_err() && point_to_json(&G2Affine::zero(), "g2").is_err(), "Infinity should not be written");
+        assert!(point_from_json::<G1Affine>(&json!([g1[0], "1", "1"]), "g1").is_err());
+        assert!(point_from_json::<G1Affine>(&json!([g1[0], g1[1], "2"]), "g1").is_err());
+    }
+
+    #[test]
+    fn test_snarkjs_json() {
+        let mut rng = XorShiftRng::from_seed([0x6dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap();
+        let tvk = truncate_verifying_key(&params.vk);
+        let (x, y) = (Fr::from_str("5").unwrap(), rng.gen::<Fr>());
+        let mut z = x;
+        z.mul_assign(&y);
+        let proof = create_random_proof(Mul(Some((x, y))), &params, &mut rng).unwrap();
+
+        let (vk, proof, inputs) = (verifying_key_to_json(&tvk).unwrap(), proof_to_json(&proof).unwrap(), inputs_to_json(&[x, z]));
+        assert!(verifying_key_to_json(&verifying_key_from_json(&vk).unwrap()).unwrap() == vk);
+        assert!(proof_to_json(&proof_from_json(&proof).unwrap()).unwrap() == proof);
+        assert!(inputs_from_json(&inputs).unwrap() == vec![x, z]);
+        assert!(verify_json(&vk, &proof, &inputs).unwrap());
+        assert!(!verify_json(&vk, &proof, &inputs_to_json(&[z, x])).unwrap());
+        assert!(verify_json(&vk, &proof, &inputs_to_json(&[x])).is_err());
+
+        let mut v = serde_json::from_str::<Value>(&vk).unwrap();
+        v["nPublic"] = json!(3);
+        assert!(verifying_key_from_json(&v.to_string()).is_err(), "nPublic should match IC");
+        v["nPublic"] = json!(2);
+        v["curve"] = json!("bn128");
+        assert!(verifying_key_from_json(&v.to_string()).is_err(), "Keys of other curves should be rejected");
+        assert!(inputs_from_json("[\"52435875175126190479447740508185965837690552500527637822603658699938581184513\"]").is_err());
+        assert!(inputs_from_json("[5]").is_err(), "Inputs should be strings as snarkjs writes them");
+
+        let mut zero_ic = tvk.clone();
+        zero_ic.ic[1] = G1Affine::zero();
+        assert!(verifying_key_to_json(&zero_ic).is_err(), "A key with a zero IC entry should not be exported");
+    }
+}
@@ -0,0 +1,93 @@
+use pairing::{PrimeField, PrimeFieldRepr};
+use sapling_crypto::jubjub::{JubjubEngine, FixedGenerators, Unknown, edwards::Point};
+use sapling_crypto::redjubjub::{PrivateKey, PublicKey, Signature};
+use rand::Rng;
+
+use crate::fieldtools;
+use crate::point::PointError;
+
+
+// Spend authorization as in Sapling: the account key ak = sk*G never appears in a transaction.
+// Every transfer picks a fresh alpha, proves rk = ak + alpha*G in the circuit, exposes rk as
+// public inputs and is signed with rsk = sk + alpha. Transactions of one account are unlinkable by rk.
+pub const SPEND_AUTH_GENERATOR: FixedGenerators = FixedGenerators::SpendingKeyGenerator;
+
+
+pub fn spend_auth_key<E: JubjubEngine>(sk: &E::Fr, params: &E::Params) -> PublicKey<E> {
+    PublicKey::from_private(&PrivateKey(fieldtools::f2f::<E::Fr, E::Fs>(sk)), SPEND_AUTH_GENERATOR, params)
+}
+
+pub fn randomized_private_key<E: JubjubEngine>(sk: &E::Fr, alpha: &E::Fr) -> PrivateKey<E> {
+    PrivateKey(fieldtools::f2f::<E::Fr, E::Fs>(sk)).randomize(fieldtools::f2f::<E::Fr, E::Fs>(alpha))
+}
+
+pub fn randomized_public_key<E: JubjubEngine>(ak: &PublicKey<E>, alpha: &E::Fr, params: &E::Params) -> PublicKey<E> {
+    ak.randomize(fieldtools::f2f::<E::Fr, E::Fs>(alpha), SPEND_AUTH_GENERATOR, params)
+}
+
+
+// rk is inputized by the circuit as (x, y)
+pub fn rk_to_inputs<E: JubjubEngine>(rk: &PublicKey<E>) -> (E::Fr, E::Fr) {
+    rk.0.into_xy()
+}
+
+pub fn rk_from_inputs<E: JubjubEngine>(x: &E::Fr, y: &E::Fr, params: &E::Params) -> Result<PublicKey<E>, PointError> {
+    let x_sign = x.into_repr().is_odd();
+    let p = Point::<E, Unknown>::get_for_y(*y, x_sign, params).ok_or(PointError::NotOnCurve)?;
+    if p.into_xy().0 != *x {
+        return Err(PointError::NotOnCurve);
+    }
+    if p.as_prime_order(params).is_none() {
+        return Err(PointError::WrongSubgroup);
+    }
+    Ok(PublicKey(p))
+}
+
+
+pub fn sign<E: JubjubEngine, R: Rng>(sk: &E::Fr, alpha: &E::Fr, msg: &[u8], rng: &mut R, params: &E::Params) -> Signature {
+    randomized_private_key::<E>(sk, alpha).sign(msg, rng, SPEND_AUTH_GENERATOR, params)
+}
+
+pub fn verify<E: JubjubEngine>(rk: &PublicKey<E>, msg: &[u8], sig: &Signature, params: &E::Params) -> bool {
+    rk.verify(msg, sig, SPEND_AUTH_GENERATOR, params)
+}
+
+
+#[cfg(test)]
+mod spend_auth_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use pairing::Field;
+    use sapling_crypto::jubjub::JubjubBls12;
+    use rand::os::OsRng;
+
+    use crate::transactions::pubkey;
+
+    #[test]
+    fn test_rerandomized_signature() {
+        let mut rng = OsRng::new().unwrap();
+        let params = JubjubBls12::new();
+
+        let sk = rng.gen::<Fr>();
+        let alpha = rng.gen::<Fr>();
+        let ak = spend_auth_key::<Bls12>(&sk, &params);
+        assert!(ak.0.into_xy().0 == pubkey::<Bls12>(&sk, &params), "ak should be the account public key");
+
+        let rk = randomized_public_key(&ak, &alpha, &params);
+        assert!(rk.0 != ak.0);
+        let rsk_pk = PublicKey::from_private(&randomized_private_key::<Bls12>(&sk, &alpha), SPEND_AUTH_GENERATOR, &params);
+        assert!(rsk_pk.0 == rk.0, "rsk should match rk");
+
+        let msg = [7u8; 32];
+        let sig = sign::<Bls12, _>(&sk, &alpha, &msg, &mut rng, &params);
+        assert!(verify(&rk, &msg, &sig, &params), "Signature should be valid for rk");
+        assert!(!verify(&ak, &msg, &sig, &params), "Signature should not be valid for ak");
+        assert!(!verify(&rk, &[8u8; 32], &sig, &params));
+
+        let (x, y) = rk_to_inputs(&rk);
+        assert!(rk_from_inputs::<Bls12>(&x, &y, &params).unwrap().0 == rk.0);
+        let mut wrong_x = x;
+        wrong_x.add_assign(&Fr::one());
+        assert!(rk_from_inputs::<Bls12>(&wrong_x, &y, &params).is_err(), "Inputs not on the curve should be rejected");
+    }
+}
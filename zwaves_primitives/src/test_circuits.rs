@@ -0,0 +1,35 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::Field;
+
+
+// Toy circuits for the tests of the proving and setup machinery, of this crate and of dependent
+// ones (feature testing).
+
+// x * y = z, x and z public
+pub struct Mul(pub Option<(Fr, Fr)>);
+
+impl Circuit<Bls12> for Mul {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let v = self.0;
+        let x = cs.alloc_input(|| "x", || v.map(|v| v.0).ok_or(SynthesisError::AssignmentMissing))?;
+        let y = cs.alloc(|| "y", || v.map(|v| v.1).ok_or(SynthesisError::AssignmentMissing))?;
+        let z = cs.alloc_input(|| "z", || v.map(|v| { let mut z = v.0; z.mul_assign(&v.1); z }).ok_or(SynthesisError::AssignmentMissing))?;
+        cs.enforce(|| "x * y = z", |lc| lc + x, |lc| lc + y, |lc| lc + z);
+        Ok(())
+    }
+}
+
+// n constraints x * x = y[i], for circuits of a given size
+pub struct Squares(pub usize);
+
+impl Circuit<Bls12> for Squares {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let x = cs.alloc_input(|| "x", || Ok(Fr::one()))?;
+        for i in 0..self.0 {
+            let y = cs.alloc(|| format!("y[{}]", i), || Ok(Fr::one()))?;
+            cs.enforce(|| format!("x*x = y[{}]", i), |lc| lc + x, |lc| lc + x, |lc| lc + y);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+// Known-answer vectors for BLS12-381 / Jubjub, written as decimal strings of Fr elements
+// so other implementations can compare against them without depending on this crate.
+// Every value was produced by the functions named next to it and is re-checked by the
+// tests below; if one of them fails, the hashing or key derivation has changed.
+
+// pedersen_hasher::hash(x, HASH_PERSONALIZATION): Personalization::NoteCommitment over the 255 low bits of x
+pub const HASH_OF_ZERO: &str = "24941433317938455865421383138551906354737338243222824094975840255863617789787";
+pub const HASH_OF_ONE: &str = "10985710189866320796865786411664192860677163431687330494390279772231514129199";
+pub const HASH_OF_2_POW_64: &str = "44487616341091352683743446130934353891075973436358345779604002669154890054740";
+pub const HASH_OF_2_POW_128: &str = "19613715155475958525446652868485634453174091153590783470087996717934092109202";
+pub const HASH_OF_2_POW_192: &str = "8439811083204077168595846571187133027418663913809706999281544652453349408571";
+pub const HASH_OF_2_POW_253: &str = "50684341250116429946094958920519251346903491469305264259839284599795483677233";
+
+// pedersen_hasher::empty_root(level) for level in 0..=MERKLE_MAX_DEPTH,
+// EMPTY_ROOTS[1] is also hardcoded as `twozeros` in zwaves_circuit::gadgets::transactions::utxo_accumulator
+pub const EMPTY_ROOTS: [&str; 63] = [
+    "0",
+    "2844901669415300281300718346195343338354231404922385839670861864158643284316",
+    "33308683348276694187392825460781357679539265865360162840571719278053972052752",
+    "1655622252715677696704309179818795634519219977940919423357642550411143781142",
+    "15069653910433145166396899463133316410612510748869463121567744507523031158890",
+    "17584230598266740292084698135140430242898221016773567418427137644600288962787",
+    "51192958974676156726569676837389677223844004764348855750368225676250164149410",
+    "13520478310938994325859876780536958404433156766437587088658738334223747589347",
+    "1823600773522124359715241633495918500943648263687553783808887556944791008810",
+    "20393849497429755471724365669557307213452255414120598296210181649904430534172",
+    "8449156461649055437442157867885172302063651262515597191526421774395560560033",
+    "10274826945920117420685554317823273908918671352321763641155670601635211363414",
+    "25441578685986614295460927860476513882880273309516546199364720670989334606834",
+    "16785905803889249209998911582578533975021013847583640771036328328320894107245",
+    "4683805090040438145410895207982732254916363815708971700661235430763786298886",
+    "23903071859596022730882417756681630052684239650276634681415696450892297809750",
+    "11667545487094670712782995720331496274091541679124613758812892330760360933597",
+    "17110092570471600233306092402323305194858106940657880216576478951488931710603",
+    "826701431822044854351580690161895448906510877094812898256250258729476483815",
+    "41682549830477304911802707273097723648350412357735780162859386526809444722954",
+    "1435444814617109367608347093652871630893332088122295051236952971084510402198",
+    "42146803083384739871274097693969732470829988181735856325194697500352137983445",
+    "18798103494703591318376252897074008553543529449894985977595585548110032597067",
+    "1211641623387103190586785071211248488799959228978710420998941312132114722188",
+    "28454067987431525241477169408127067097937865430045638866999761475735498492100",
+    "2460776585548260177984631389346746891700918616800092469467449711131451061982",
+    "33947701004933790994447503753818560610776317242959896774795918705667380518172",
+    "40643535241606932095873003782446032327209306732390576507011276192048387581778",
+    "47319034811220692484844678453092790938601775830204711181676161273619078067187",
+    "4822926502138428074311072105064396984544756266628792563316651772664542007161",
+    "51985517772835527863072395547347229602697220891191150136608491015310790401862",
+    "9850393512998782450847978374262579022348728719945614997180013717761317753082",
+    "39843375457292488281886410433058489604282428146710328649974447545735273034817",
+    "4783581669111414411249819367197579488823499232169767852420423411393345853228",
+    "2279322732204256693821270409558968881941291809634182873143735986119525535638",
+    "37111587106158609316168929669583945160264447403493532276827299496527323905392",
+    "37471193168925259126637855652460717166767913867564842241348117693682946041091",
+    "14214815432555095466545993467169854096379512018240463525267182647470254008021",
+    "13767443176840955331132566252012993171019095718413855628307384044967401766159",
+    "50039886104823644660992958271652309513454560971268500771130603445631664748319",
+    "41508462084311863378840940828656787706080664081596646714452591975341457860474",
+    "36252309820429414212244281476818408240341073301262233153502508729301099347732",
+    "43218568892241710969413886558564129282766714082798926887337955507854588165647",
+    "17672234134202330282733312801754652895610473475022397471533206295656498949618",
+    "46676488424845326045635442892882937613306299839784227269063108769239980562005",
+    "42136636733968980274779495215442797826742153361584536324758602326777048657207",
+    "18407005894140881962104009192971416180676618401990776107797451574799566673611",
+    "11876859103907277917684081558815391168909581737776683354913699638734338234533",
+    "21225244072045496948868962430252009390851647984006973458955828563446319276557",
+    "16681808132340633691461602252988198133444337276787744765909912691801463714763",
+    "47590959702858581348010701180863726860416365302699298462640254751237206475279",
+    "50457175340831168806382891794698048230878328701862480576135739921953255722129",
+    "19621114272480883973741472041713044239950527074059080176396465205723469763367",
+    "42057473176727816215126718531443706992418253986635323136775425817147359782195",
+    "26557956535440636527380172907906133058060204307896483045385396168487433172811",
+    "50421425344217164742415936517880720072339479632433652226005547983838332211193",
+    "50130072903892692387118533428862592199850724385517850746161334171560042313107",
+    "13545248608369243257954751736209201364322280750118437968482701136425928635416",
+    "41632419771609937596904853581157340450405050503163392702965549425636953963096",
+    "22414900434206625745575732465961768342561987181465209509792412224090635378717",
+    "42763661132315619023038414100615821613690477783813128260042431663022817780873",
+    "4684473289707893792196629469231840656895080801479155194784257790849732865527",
+    "33382474440040503881358258796508411011884697704562153264439069654336693406992",
+];
+
+// transactions::pubkey(1): x coordinate of SpendingKeyGenerator * 1
+pub const PUBKEY_OF_ONE: &str = "4139425550610461525665941076812662132363359224232624900223172373014329534291";
+
+// transactions::nullifier_key(1): x coordinate of ProofGenerationKey * 1
+pub const NULLIFIER_KEY_OF_ONE: &str = "9201111513613159952332790701602097324772839388200533360387436201225747309937";
+
+// transactions::nullifier(note_hash = 1, sk = 2), same input as zwaves_node/test/test_nullifier.js
+pub const NULLIFIER_OF_ONE_TWO: &str = "44927278123597493230022819343800455052696615432060983161931687091481991319034";
+
+// transactions::note_hash of the note
+// { asset_id: 0, amount: 1000, native_amount: 10, txid: 12345, owner: PUBKEY_OF_ONE }
+pub const NOTE_HASH_SAMPLE: &str = "25478228801523786440857979060609787275085277517212065914072164391504598128075";
+
+// spend_auth with sk = 12345, alpha = 67890: the x coordinate of ak = spend_auth_key(sk),
+// rk = randomized_public_key(ak, alpha) as rk_to_inputs gives it, and sign(sk, alpha, msg) of
+// the 32 byte message [7; 32], written by Signature::write as hex. RedJubjub draws its nonce T
+// from the rng; for this vector T is the 80 bytes 0, 1, ..., 79.
+pub const SPEND_AUTH_SK: &str = "12345";
+pub const SPEND_AUTH_ALPHA: &str = "67890";
+pub const SPEND_AUTH_AK_X: &str = "46563398463468622842100612978959191352176975062135905201088625630727854422657";
+pub const SPEND_AUTH_RK_X: &str = "25422111406412202883669825772074465845252695601900929686034007114785311317240";
+pub const SPEND_AUTH_RK_Y: &str = "35669361640459344424928195668808013084798344236256161478643594117675683047607";
+pub const SPEND_AUTH_SIGNATURE: &str = "49a10adda75d46d41cf5679892837dd5fde8c592f5adc0e9b26675a51b0a1e4b6dbcc3b407f8f2977d882ef0d1e10045434f13acd5e9a3302520f390e3381e09";
+
+
+#[cfg(test)]
+mod test_vectors_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr, FrRepr};
+    use pairing::{Field, PrimeField};
+    use crate::pedersen_hasher::{hash, empty_root, JUBJUB_PARAMS, MERKLE_MAX_DEPTH, HASH_PERSONALIZATION};
+    use crate::transactions::{pubkey, nullifier_key, nullifier, note_hash, NoteData};
+    use crate::spend_auth::{spend_auth_key, randomized_public_key, rk_to_inputs, sign, verify};
+    use rand::Rng;
+
+    fn fr(s: &str) -> Fr {
+        Fr::from_str(s).unwrap()
+    }
+
+    // the nonce of SPEND_AUTH_SIGNATURE, sign asks the rng for nothing else
+    struct FixedNonce;
+
+    impl Rng for FixedNonce {
+        fn next_u32(&mut self) -> u32 {
+            unreachable!()
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        }
+    }
+
+    fn pow2(n: usize) -> Fr {
+        let mut repr = FrRepr([0u64; 4]);
+        repr.0[n / 64] = 1u64 << (n % 64);
+        Fr::from_repr(repr).unwrap()
+    }
+
+    #[test]
+    fn test_hash_vectors() {
+        assert!(hash::<Bls12>(&Fr::zero(), HASH_PERSONALIZATION, &JUBJUB_PARAMS) == fr(HASH_OF_ZERO));
+        assert!(hash::<Bls12>(&Fr::one(), HASH_PERSONALIZATION, &JUBJUB_PARAMS) == fr(HASH_OF_ONE));
+        assert!(hash::<Bls12>(&pow2(64), HASH_PERSONALIZATION, &JUBJUB_PARAMS) == fr(HASH_OF_2_POW_64));
+        assert!(hash::<Bls12>(&pow2(128), HASH_PERSONALIZATION, &JUBJUB_PARAMS) == fr(HASH_OF_2_POW_128));
+        assert!(hash::<Bls12>(&pow2(192), HASH_PERSONALIZATION, &JUBJUB_PARAMS) == fr(HASH_OF_2_POW_192));
+        assert!(hash::<Bls12>(&pow2(253), HASH_PERSONALIZATION, &JUBJUB_PARAMS) == fr(HASH_OF_2_POW_253));
+    }
+
+    #[test]
+    fn test_empty_root_vectors() {
+        assert!(EMPTY_ROOTS.len() == MERKLE_MAX_DEPTH + 1);
+        assert!(EMPTY_ROOTS.iter().enumerate().all(|(i, &e)| empty_root(i) == fr(e)), "Empty roots should match");
+    }
+
+    #[test]
+    fn test_key_vectors() {
+        let one = Fr::one();
+        let two = fr("2");
+        assert!(pubkey::<Bls12>(&one, &JUBJUB_PARAMS) == fr(PUBKEY_OF_ONE));
+        assert!(nullifier_key::<Bls12>(&one, &JUBJUB_PARAMS) == fr(NULLIFIER_KEY_OF_ONE));
+        assert!(nullifier::<Bls12>(&one, &two, &JUBJUB_PARAMS) == fr(NULLIFIER_OF_ONE_TWO));
+
+        let note = NoteData::<Bls12> {
+            asset_id: Fr::zero(),
+            amount: fr("1000"),
+            native_amount: fr("10"),
+            txid: fr("12345"),
+            owner: fr(PUBKEY_OF_ONE)
+        };
+        assert!(note_hash::<Bls12>(&note, &JUBJUB_PARAMS).unwrap() == fr(NOTE_HASH_SAMPLE));
+    }
+
+    #[test]
+    fn test_spend_auth_vectors() {
+        let (sk, alpha) = (fr(SPEND_AUTH_SK), fr(SPEND_AUTH_ALPHA));
+        let ak = spend_auth_key::<Bls12>(&sk, &JUBJUB_PARAMS);
+        assert!(ak.0.into_xy().0 == fr(SPEND_AUTH_AK_X));
+        let rk = randomized_public_key::<Bls12>(&ak, &alpha, &JUBJUB_PARAMS);
+        assert!(rk_to_inputs(&rk) == (fr(SPEND_AUTH_RK_X), fr(SPEND_AUTH_RK_Y)));
+
+        let msg = [7u8; 32];
+        let sig = sign::<Bls12, _>(&sk, &alpha, &msg, &mut FixedNonce, &JUBJUB_PARAMS);
+        let mut sig_data = vec![];
+        sig.write(&mut sig_data).unwrap();
+        assert!(hex::encode(&sig_data) == SPEND_AUTH_SIGNATURE, "Signature should match");
+        assert!(verify::<Bls12>(&rk, &msg, &sig, &JUBJUB_PARAMS));
+    }
+}
@@ -0,0 +1,198 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use pairing::PrimeField;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use crate::bundle::TransactionBundle;
+use crate::fieldtools::fr_to_repr_u64;
+use crate::tree::{MerkleTree, InsertReceipt};
+use crate::verifier::VerifierContext;
+
+
+// In-memory pool for protocol-level tests: the note tree, the nullifier set, the known roots
+// and the balance of the pool per asset, updated by bundles the way the node does. Mock mode
+// skips the proof and the spend authorization signature, so the bundles of a test could carry
+// any proof.
+//
+// packed_asset = asset_id (64) | amount (i64) | native amount (i64), the signed amounts are
+// out - in of the transfer: positive for deposits, negative for withdrawals to the receiver.
+pub enum Verification<E: JubjubEngine> {
+    Mock,
+    Proofs(VerifierContext<E>)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AssetDelta {
+    pub asset_id: u64,
+    pub amount: i64,
+    pub native_amount: i64
+}
+
+pub fn unpack_asset<F: PrimeField>(packed_asset: &F) -> io::Result<AssetDelta> {
+    let limbs = fr_to_repr_u64(packed_asset).into_iter().collect::<Vec<_>>();
+    if limbs[3..].iter().any(|&l| l != 0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "packed asset does not fit 192 bits"));
+    }
+    Ok(AssetDelta { asset_id: limbs[0], amount: limbs[1] as i64, native_amount: limbs[2] as i64 })
+}
+
+
+pub struct PoolSimulator<E: JubjubEngine> {
+    params: E::Params,
+    verification: Verification<E>,
+    tree: MerkleTree<E>,
+    roots: BTreeSet<<E::Fr as PrimeField>::Repr>,
+    nullifiers: BTreeSet<<E::Fr as PrimeField>::Repr>,
+    balances: BTreeMap<u64, i128>,
+    native_balance: i128,
+    fees: u128
+}
+
+impl<E: JubjubEngine> PoolSimulator<E> {
+    pub fn new(depth: usize, params: E::Params, verification: Verification<E>) -> Self {
+        let tree = MerkleTree::<E>::new(depth, &params);
+        let mut roots = BTreeSet::new();
+        roots.insert(tree.root().into_repr());
+        PoolSimulator { params, verification, tree, roots, nullifiers: BTreeSet::new(), balances: BTreeMap::new(), native_balance: 0, fees: 0 }
+    }
+
+    pub fn tree(&self) -> &MerkleTree<E> {
+        &self.tree
+    }
+
+    pub fn root(&self) -> E::Fr {
+        self.tree.root()
+    }
+
+    pub fn is_spent(&self, nf: &E::Fr) -> bool {
+        self.nullifiers.contains(&nf.into_repr())
+    }
+
+    pub fn balance(&self, asset_id: u64) -> i128 {
+        self.balances.get(&asset_id).cloned().unwrap_or(0)
+    }
+
+    pub fn native_balance(&self) -> i128 {
+        self.native_balance
+    }
+
+    pub fn fees(&self) -> u128 {
+        self.fees
+    }
+
+    // Notes added without a transfer, for the initial state of a test
+    pub fn add_notes(&mut self, note_hashes: &[E::Fr]) -> io::Result<Vec<InsertReceipt<E>>> {
+        let res = self.tree.push_many(note_hashes, &self.params)?;
+        self.roots.insert(self.tree.root().into_repr());
+        Ok(res)
+    }
+
+    // Checks everything first, a rejected bundle leaves the pool as it was
+    pub fn apply(&mut self, bundle: &TransactionBundle<E>) -> io::Result<Vec<InsertReceipt<E>>> {
+        bundle.validate()?;
+        let values = bundle.public_values()?;
+
+        if !self.roots.contains(&values.root_hash.into_repr()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown root"));
+        }
+        if values.nf.iter().any(|nf| self.is_spent(nf)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "note is already spent"));
+        }
+
+        if let Verification::Proofs(ref ctx) = self.verification {
+            if !bundle.verify(ctx)? {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid proof"));
+            }
+            if !bundle.verify_spend_auth_sig(&self.params) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid spend authorization signature"));
+            }
+        }
+
+        let delta = unpack_asset(&values.packed_asset)?;
+        let balance = self.balance(delta.asset_id) + i128::from(delta.amount);
+        let native_balance = self.native_balance + i128::from(delta.native_amount);
+        if balance < 0 || native_balance < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "withdrawal exceeds the pool balance"));
+        }
+
+        let receipts = self.tree.push_many(&values.out_hash, &self.params)?;
+        self.roots.insert(self.tree.root().into_repr());
+        values.nf.iter().for_each(|nf| { self.nullifiers.insert(nf.into_repr()); });
+        self.balances.insert(delta.asset_id, balance);
+        self.native_balance = native_balance;
+        self.fees += u128::from(bundle.fee);
+        Ok(receipts)
+    }
+
+    pub fn assert_balance(&self, asset_id: u64, expected: i128) {
+        assert!(self.balance(asset_id) == expected, "pool balance of asset {} is {}, expected {}", asset_id, self.balance(asset_id), expected);
+    }
+
+    pub fn assert_native_balance(&self, expected: i128) {
+        assert!(self.native_balance == expected, "pool native balance is {}, expected {}", self.native_balance, expected);
+    }
+}
+
+
+#[cfg(test)]
+mod testing_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr, FrRepr, G1, G2};
+    use pairing::CurveProjective;
+    use sapling_crypto::jubjub::JubjubBls12;
+    use sapling_crypto::redjubjub::Signature;
+    use bellman::groth16::Proof;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::bundle::TRANSFER_PUBLIC_INPUTS;
+
+    fn packed_asset(asset_id: u64, amount: i64, native_amount: i64) -> Fr {
+        Fr::from_repr(FrRepr([asset_id, amount as u64, native_amount as u64, 0])).unwrap()
+    }
+
+    fn bundle<R: Rng>(rng: &mut R, root: Fr, packed_asset: Fr) -> TransactionBundle<Bls12> {
+        let sig_bytes = [0u8; 64];
+        let mut public_inputs = (0..TRANSFER_PUBLIC_INPUTS).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        public_inputs[1] = root;
+        public_inputs[2] = packed_asset;
+        TransactionBundle {
+            proof: Proof { a: rng.gen::<G1>().into_affine(), b: rng.gen::<G2>().into_affine(), c: rng.gen::<G1>().into_affine() },
+            public_inputs,
+            encrypted_notes: vec![vec![1], vec![2]],
+            out_ciphertexts: vec![[0; 24], [0; 24]],
+            binding_sig: Signature::read(&sig_bytes[..]).unwrap(),
+            spend_auth_sig: Signature::read(&sig_bytes[..]).unwrap(),
+            fee: 10
+        }
+    }
+
+    #[test]
+    fn test_pool_simulator() {
+        let mut rng = OsRng::new().unwrap();
+        let mut pool = PoolSimulator::<Bls12>::new(8, JubjubBls12::new(), Verification::Mock);
+        let genesis = pool.root();
+
+        let deposit = bundle(&mut rng, genesis, packed_asset(7, 100, 5));
+        let receipts = pool.apply(&deposit).unwrap();
+        assert!(receipts.len() == 2 && receipts[1].position == 1 && pool.root() != genesis);
+        assert!(pool.is_spent(&deposit.nullifiers().unwrap()[0]));
+        pool.assert_balance(7, 100);
+        pool.assert_native_balance(5);
+
+        let mut double_spend = bundle(&mut rng, pool.root(), packed_asset(7, 0, 0));
+        double_spend.public_inputs[6] = deposit.nullifiers().unwrap()[1];
+        assert!(pool.apply(&double_spend).is_err(), "Spent note should be rejected");
+        let unknown = rng.gen::<Fr>();
+        assert!(pool.apply(&bundle(&mut rng, unknown, packed_asset(7, 0, 0))).is_err(), "Unknown root should be rejected");
+        assert!(pool.apply(&bundle(&mut rng, genesis, packed_asset(7, -101, 0))).is_err(), "Pool should not go below zero");
+        assert!(pool.tree().size() == 2, "Rejected bundles should not change the pool");
+
+        pool.apply(&bundle(&mut rng, genesis, packed_asset(7, -60, -5))).unwrap();
+        pool.assert_balance(7, 40);
+        pool.assert_native_balance(0);
+        pool.assert_balance(8, 0);
+        assert!(pool.fees() == 20);
+    }
+}
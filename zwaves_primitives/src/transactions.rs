@@ -6,9 +6,13 @@ use sapling_crypto::pedersen_hash::{pedersen_hash, Personalization};
 use sapling_crypto::jubjub::edwards::{Point};
 use sapling_crypto::jubjub::{PrimeOrder, Unknown};
 use crate::fieldtools;
+use crate::pedersen_hasher::leaf_personalization;
+use crate::metrics::{self, Counter};
+use crate::point::{PointError, edwards_point_for_x};
 use blake2_rfc::blake2s::Blake2s;
 use byteorder::{LittleEndian, WriteBytesExt};
 use itertools::Itertools;
+use std::io;
 
 
 
@@ -25,32 +29,49 @@ pub struct NoteData<E: JubjubEngine> {
 }
 
 
-pub fn note_hash<E: JubjubEngine>(data: &NoteData<E>, params: &E::Params) -> E::Fr {
-    let total_bits = [data.asset_id, data.amount, data.native_amount, data.txid, data.owner].iter()
-    .zip([64, 64, 64, E::Fr::NUM_BITS, E::Fr::NUM_BITS].iter())
-        .flat_map(|(e, &sz)| fieldtools::fr_to_repr_bool(e).into_iter().take(sz as usize))
-        .collect::<Vec<bool>>();
-    pedersen_hash::<E, _>(Personalization::NoteCommitment, total_bits.into_iter(), &params).into_xy().0
+// Fields of a commitment, every one should fit its length: asset_id, amounts and the nonce
+// are range checked by the circuits, and a hash of the truncated value would be a note nobody
+// could spend.
+fn commitment_bits<F: PrimeField>(fields: &[F], sizes: &[u32]) -> io::Result<Vec<bool>> {
+    let mut res = vec![];
+    for (e, &sz) in fields.iter().zip(sizes.iter()) {
+        res.extend(fieldtools::get_bits_le_checked(e, sz as usize)?);
+    }
+    Ok(res)
+}
+
+// A field out of range is an error, notes from outside may have any values
+pub fn note_hash<E: JubjubEngine>(data: &NoteData<E>, params: &E::Params) -> io::Result<E::Fr> {
+    let total_bits = commitment_bits(&[data.asset_id, data.amount, data.native_amount, data.txid, data.owner],
+        &[64, 64, 64, E::Fr::NUM_BITS, E::Fr::NUM_BITS])?;
+    metrics::inc(Counter::PedersenHashes, 1);
+    Ok(pedersen_hash::<E, _>(Personalization::NoteCommitment, total_bits.into_iter(), &params).into_xy().0)
 }
 
 pub fn pubkey<E: JubjubEngine>(sk: &E::Fr, params: &E::Params) -> E::Fr {
     params.generator(FixedGenerators::SpendingKeyGenerator).mul(fieldtools::f2f::<E::Fr, E::Fs>(sk), params).into_xy().0
 }
 
-pub fn edh<E: JubjubEngine>(pk_x: &E::Fr, sk: &E::Fr, params: &E::Params) -> Option<E::Fr> {
-    let p = Point::<E, Unknown>::get_for_x(pk_x.clone(), params)?;
-    Some(p.mul(fieldtools::f2f::<E::Fr, E::Fs>(sk), params).into_xy().0)
+pub fn edh<E: JubjubEngine>(pk_x: &E::Fr, sk: &E::Fr, params: &E::Params) -> Result<E::Fr, PointError> {
+    let p = edwards_point_for_x::<E>(pk_x, false, params)?;
+    Ok(p.mul(fieldtools::f2f::<E::Fr, E::Fs>(sk), params).into_xy().0)
 }
 
-pub fn nullifier<E: JubjubEngine>(note_hash: &E::Fr, sk: &E::Fr, params: &E::Params) -> E::Fr {
-    
-    let sk_multiplied = params.generator(FixedGenerators::ProofGenerationKey).mul(fieldtools::f2f::<E::Fr, E::Fs>(sk), params).into_xy().0;
-
-    let mut h = Blake2s::with_params(32, &[], &[], constants::PRF_NF_PERSONALIZATION);
+// nk = x(sk * ProofGenerationKey) is enough to derive nullifiers, but not to spend:
+// it could be handed over to a detection service without revealing sk.
+pub fn nullifier_key<E: JubjubEngine>(sk: &E::Fr, params: &E::Params) -> E::Fr {
+    params.generator(FixedGenerators::ProofGenerationKey).mul(fieldtools::f2f::<E::Fr, E::Fs>(sk), params).into_xy().0
+}
 
+fn prf<E: JubjubEngine>(personalization: &[u8], x: &E::Fr, key: &E::Fr, tail: &[u8]) -> E::Fr {
+    let data = fieldtools::fr_to_repr_u8(x).into_iter().chain(fieldtools::fr_to_repr_u8(key)).chain(tail.iter().cloned()).collect::<Vec<u8>>();
+    blake2s_to_fr::<E>(personalization, &data)
+}
 
-    let data = fieldtools::fr_to_repr_u8(note_hash).into_iter().chain(fieldtools::fr_to_repr_u8(&sk_multiplied)).collect::<Vec<u8>>();
-    h.update(&data);
+// blake2s of data reduced into the field, the native side of blake2s followed by from_bits_le_to_num
+pub(crate) fn blake2s_to_fr<E: JubjubEngine>(personalization: &[u8], data: &[u8]) -> E::Fr {
+    let mut h = Blake2s::with_params(32, &[], &[], personalization);
+    h.update(data);
 
     let mut res = E::Fr::char();
 
@@ -63,3 +84,79 @@ pub fn nullifier<E: JubjubEngine>(note_hash: &E::Fr, sk: &E::Fr, params: &E::Par
 
     fieldtools::affine(res)
 }
+
+pub fn nullifier_from_key<E: JubjubEngine>(note_hash: &E::Fr, nk: &E::Fr) -> E::Fr {
+    prf::<E>(constants::PRF_NF_PERSONALIZATION, note_hash, nk, &[])
+}
+
+pub fn nullifier<E: JubjubEngine>(note_hash: &E::Fr, sk: &E::Fr, params: &E::Params) -> E::Fr {
+    nullifier_from_key::<E>(note_hash, &nullifier_key::<E>(sk, params))
+}
+
+// Epoch-scoped nullifier: the epoch id is appended to the prf input as 8 bytes LE, under its own
+// personalization. The same note gets unrelated nullifiers in different epochs and none of them
+// is equal to its plain nullifier, so the application decides which set a spend is checked against.
+pub const EPOCH_NF_PERSONALIZATION: &[u8; 8] = b"ZWnf_epc";
+
+pub fn epoch_nullifier_from_key<E: JubjubEngine>(note_hash: &E::Fr, nk: &E::Fr, epoch: u64) -> E::Fr {
+    prf::<E>(EPOCH_NF_PERSONALIZATION, note_hash, nk, &epoch.to_le_bytes())
+}
+
+pub fn epoch_nullifier<E: JubjubEngine>(note_hash: &E::Fr, sk: &E::Fr, epoch: u64, params: &E::Params) -> E::Fr {
+    epoch_nullifier_from_key::<E>(note_hash, &nullifier_key::<E>(sk, params), epoch)
+}
+
+
+
+// Programmable notes: instead of pk the owner field holds prf(pk, condition hash), and the
+// condition hash commits to a condition type and the hash of its parameters (see crate::condition).
+// The spender proves knowledge of sk and that the condition holds.
+pub const CONDITION_PERSONALIZATION: &[u8; 8] = b"ZWcondtn";
+pub const CONDITION_OWNER_PERSONALIZATION: &[u8; 8] = b"ZWcownr_";
+
+pub fn condition_hash<E: JubjubEngine>(type_id: u64, params_hash: &E::Fr) -> E::Fr {
+    prf::<E>(CONDITION_PERSONALIZATION, params_hash, &E::Fr::from_repr(type_id.into()).unwrap(), &[])
+}
+
+pub fn conditioned_owner<E: JubjubEngine>(pk: &E::Fr, condition_hash: &E::Fr) -> E::Fr {
+    prf::<E>(CONDITION_OWNER_PERSONALIZATION, pk, condition_hash, &[])
+}
+
+// Owner of the notes of a threshold group (crate::frost). Nobody knows sk and nk can not be
+// derived from ak in the circuit, so the note commits to nk next to x(ak): the spender can not
+// pick another nk and get another nullifier for the same note.
+pub const THRESHOLD_OWNER_PERSONALIZATION: &[u8; 8] = b"ZWthrown";
+
+pub fn threshold_owner<E: JubjubEngine>(ak_x: &E::Fr, nk: &E::Fr) -> E::Fr {
+    prf::<E>(THRESHOLD_OWNER_PERSONALIZATION, ak_x, nk, &[])
+}
+
+// Account model: one leaf per account, updated in place. The leaf is blinded with
+// r = PRF_nk(nonce), so the wallet could restore every state of the account from nk alone.
+pub const ACCOUNT_PRF_PERSONALIZATION: &[u8; 8] = b"ZWAc_PRF";
+
+// The leaf is not hashed under NoteCommitment as notes are, or a note with the same bits would
+// have the leaf of an account. It takes a leaf domain (756 bits with the personalization, it can
+// not collide with a compress of the same level either).
+pub const ACCOUNT_HASH_DOMAIN: usize = 0;
+
+#[derive(Clone)]
+pub struct AccountData<E: JubjubEngine> {
+    pub asset_id: E::Fr,
+    pub balance: E::Fr,
+    pub native_balance: E::Fr,
+    pub nonce: E::Fr,
+    pub owner: E::Fr
+}
+
+pub fn account_blinding<E: JubjubEngine>(nonce: &E::Fr, nk: &E::Fr) -> E::Fr {
+    prf::<E>(ACCOUNT_PRF_PERSONALIZATION, nonce, nk, &[])
+}
+
+pub fn account_hash<E: JubjubEngine>(data: &AccountData<E>, nk: &E::Fr, params: &E::Params) -> io::Result<E::Fr> {
+    let blinding = account_blinding::<E>(&data.nonce, nk);
+    let total_bits = commitment_bits(&[data.asset_id, data.balance, data.native_balance, data.nonce, data.owner, blinding],
+        &[64, 64, 64, 48, E::Fr::NUM_BITS, E::Fr::NUM_BITS])?;
+    metrics::inc(Counter::PedersenHashes, 1);
+    Ok(pedersen_hash::<E, _>(leaf_personalization(ACCOUNT_HASH_DOMAIN), total_bits.into_iter(), &params).into_xy().0)
+}
@@ -0,0 +1,689 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::pedersen_hash::Personalization;
+use pairing::PrimeField;
+
+use std::convert::TryFrom;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::pedersen_hasher::{compress, merkle_defaults, MERKLE_MAX_DEPTH};
+
+
+// Stateful append-only note tree, same layout as MerkleTree of the node and python bindings, with
+// the diff and the block heights (push_block, is_root_recent) of the node one.
+// Nodes are immutable and shared through Arc: a push copies the paths it changes and shares
+// everything else, so a clone is a snapshot that costs nothing and never changes.
+//
+// SharedTree serves witness queries while a block is applied: the writer works on its own
+// clone and publishes it when the block is done, readers keep the snapshot they took, which is
+// always the state after a whole block (an epoch). Readers take a lock only to clone the Arc.
+//
+// A node above the leaves with no children is a pruned subtree (prune_below), an insert always
+// gives a node at least one child.
+//
+// A 4-ary tree (with_arity) is the binary tree of twice its depth, see pedersen_hasher::compress4,
+// and only its proofs differ: the three other children of every 4-ary node, flat, as the python
+// MerkleTree(arity=4) gives them. Pruning keeps the children of the nodes of odd rows for them.
+struct Node<E: JubjubEngine> {
+    hash: E::Fr,
+    left: Link<E>,
+    right: Link<E>
+}
+
+type Link<E> = Option<Arc<Node<E>>>;
+
+
+#[derive(Clone, Debug)]
+pub struct InsertReceipt<E: JubjubEngine> {
+    pub position: u64,
+    pub root: E::Fr,
+    pub proof: Vec<E::Fr>
+}
+
+
+// A node of diff, old is the hash in the other tree
+#[derive(Clone, Debug)]
+pub struct NodeChange<E: JubjubEngine> {
+    pub row: usize,
+    pub index: u64,
+    pub old: E::Fr,
+    pub new: E::Fr
+}
+
+
+// First leaf and height of every push, the last height and the last height each root was current at.
+// Clones share them until a push changes them, so SharedTree copies them once per block.
+struct Heights<E: JubjubEngine> {
+    leaves: Vec<(u64, Option<u64>)>,
+    last: Option<u64>,
+    roots: BTreeMap<<E::Fr as PrimeField>::Repr, u64>
+}
+
+impl<E: JubjubEngine> Clone for Heights<E> {
+    fn clone(&self) -> Self {
+        Heights { leaves: self.leaves.clone(), last: self.last, roots: self.roots.clone() }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct MerkleTree<E: JubjubEngine> {
+    depth: usize,
+    arity: usize,
+    size: u64,
+    root: Link<E>,
+    defaults: Arc<Vec<E::Fr>>,
+    heights: Arc<Heights<E>>
+}
+
+impl<E: JubjubEngine> MerkleTree<E> {
+    pub fn new(depth: usize, params: &E::Params) -> Self {
+        Self::with_arity(depth, 2, params)
+    }
+
+    // levels of arity 2 or 4
+    pub fn with_arity(levels: usize, arity: usize, params: &E::Params) -> Self {
+        let depth = match arity {
+            2 => levels,
+            4 => 2 * levels,
+            _ => panic!("arity should be 2 or 4")
+        };
+        assert!(depth < MERKLE_MAX_DEPTH, "tree depth should be less than {}", MERKLE_MAX_DEPTH);
+        let heights = Heights { leaves: vec![], last: None, roots: BTreeMap::new() };
+        MerkleTree { depth, arity, size: 0, root: None, defaults: Arc::new(merkle_defaults::<E>(depth + 1, params)), heights: Arc::new(heights) }
+    }
+
+    // binary rows, twice the levels of a 4-ary tree
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn hash(&self, link: &Link<E>, row: usize) -> E::Fr {
+        match link {
+            Some(n) => n.hash,
+            None => self.defaults[row]
+        }
+    }
+
+    fn insert(&self, link: &Link<E>, row: usize, index: u64, leaf: &E::Fr, params: &E::Params) -> Arc<Node<E>> {
+        if row == 0 {
+            return Arc::new(Node { hash: *leaf, left: None, right: None });
+        }
+        let (mut left, mut right) = match link {
+            Some(n) => (n.left.clone(), n.right.clone()),
+            None => (None, None)
+        };
+        if (index >> (row - 1)) & 1 == 0 {
+            left = Some(self.insert(&left, row - 1, index, leaf, params));
+        } else {
+            right = Some(self.insert(&right, row - 1, index, leaf, params));
+        }
+        let hash = compress::<E>(&self.hash(&left, row - 1), &self.hash(&right, row - 1), Personalization::MerkleTree(row - 1), params);
+        Arc::new(Node { hash, left, right })
+    }
+
+    pub fn push_many(&mut self, leaves: &[E::Fr], params: &E::Params) -> io::Result<Vec<InsertReceipt<E>>> {
+        self.push(leaves, None, params)
+    }
+
+    // push_many of the leaves of the block at height, heights should not decrease
+    pub fn push_block(&mut self, leaves: &[E::Fr], height: u64, params: &E::Params) -> io::Result<Vec<InsertReceipt<E>>> {
+        self.push(leaves, Some(height), params)
+    }
+
+    fn push(&mut self, leaves: &[E::Fr], height: Option<u64>, params: &E::Params) -> io::Result<Vec<InsertReceipt<E>>> {
+        let start = self.size;
+        if start + leaves.len() as u64 > 1 << self.depth {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "tree is full"));
+        }
+        if let (Some(h), Some(last)) = (height, self.heights.last) {
+            if h < last {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "heights should not decrease"));
+            }
+        }
+        for leaf in leaves.iter() {
+            self.root = Some(self.insert(&self.root, self.depth, self.size, leaf, params));
+            self.size += 1;
+        }
+        let root = self.root();
+
+        // leaves pushed without a height after some with one have none
+        if height.is_some() || (!leaves.is_empty() && !self.heights.leaves.is_empty()) {
+            let heights = Arc::make_mut(&mut self.heights);
+            if !leaves.is_empty() {
+                heights.leaves.push((start, height));
+            }
+            if let Some(h) = height {
+                heights.last = Some(h);
+                // an empty block keeps the root current, so its height moves on with the blocks
+                heights.roots.insert(root.into_repr(), h);
+            }
+        }
+        Ok((start..self.size).map(|position| InsertReceipt { position, root, proof: self.proof(position) }).collect())
+    }
+
+    // Height of the block the leaf was pushed in, None if it was pushed without one
+    pub fn leaf_height(&self, index: u64) -> Option<u64> {
+        if index >= self.size {
+            return None;
+        }
+        let leaves = &self.heights.leaves;
+        match leaves.partition_point(|&(start, _)| start <= index) {
+            0 => None,
+            i => leaves[i - 1].1
+        }
+    }
+
+    // Last height at which the root was current, i.e. the block before the one that replaced it or
+    // the last block pushed for the current root. None for a root the tree never had at a block
+    pub fn root_height(&self, root: &E::Fr) -> Option<u64> {
+        self.heights.roots.get(&root.into_repr()).cloned()
+    }
+
+    // Policy check for verifiers: the root is known and was replaced no more than max_age blocks ago.
+    // Push empty blocks to keep the current root recent
+    pub fn is_root_recent(&self, root: &E::Fr, current_height: u64, max_age: u64) -> bool {
+        match self.root_height(root) {
+            Some(h) => h <= current_height && current_height - h <= max_age,
+            None => false
+        }
+    }
+
+    // Nodes which differ from the other snapshot, top-down from the root and left to right in a
+    // row, as diff of the js MerkleTree. Equal subtrees are skipped, so the cost is proportional
+    // to the number of changed leaves. Below a pruned node of either tree the changes are not known
+    // and are not listed.
+    pub fn diff(&self, other: &MerkleTree<E>) -> io::Result<Vec<NodeChange<E>>> {
+        if self.depth != other.depth {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "trees should have the same depth"));
+        }
+        let children = |link: &Link<E>| match link {
+            Some(n) => (n.left.clone(), n.right.clone()),
+            None => (None, None)
+        };
+        let opened = |link: &Link<E>, row: usize| link.as_ref().map_or(true, |n| !is_pruned(n, row));
+
+        let mut res = vec![];
+        let mut level = vec![(self.root.clone(), other.root.clone(), 0u64)];
+        for row in (0..=self.depth).rev() {
+            let mut next = vec![];
+            for (cur, old, index) in level {
+                let (new, old_hash) = (self.hash(&cur, row), other.hash(&old, row));
+                if new == old_hash {
+                    continue;
+                }
+                res.push(NodeChange { row, index, old: old_hash, new });
+                if row > 0 && opened(&cur, row) && opened(&old, row) {
+                    let ((cur_left, cur_right), (old_left, old_right)) = (children(&cur), children(&old));
+                    next.push((cur_left, old_left, 2 * index));
+                    next.push((cur_right, old_right, 2 * index + 1));
+                }
+            }
+            level = next;
+        }
+        Ok(res)
+    }
+
+    // Path from the root down to the node at (row, index), the sibling of every node on it.
+    // None if the path goes through a pruned subtree.
+    fn walk(&self, row: usize, index: u64) -> Option<(Link<E>, Vec<E::Fr>)> {
+        let mut cur = self.root.clone();
+        let mut sibling = vec![];
+        for r in (row..self.depth).rev() {
+            let (left, right) = match cur {
+                Some(ref n) if is_pruned(n, r + 1) => return None,
+                Some(ref n) => (n.left.clone(), n.right.clone()),
+                None => (None, None)
+            };
+            let (next, other) = if (index >> (r - row)) & 1 == 0 { (left, right) } else { (right, left) };
+            sibling.push(self.hash(&other, r));
+            cur = next;
+        }
+        sibling.reverse();
+        Some((cur, sibling))
+    }
+
+    fn pruned_error(row: usize, index: u64) -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, format!("cell ({}, {}) is pruned", row, index))
+    }
+
+    pub fn try_cell(&self, row: usize, index: u64) -> io::Result<E::Fr> {
+        if row > self.depth {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too big row"));
+        }
+        let (link, _) = self.walk(row, index).ok_or_else(|| Self::pruned_error(row, index))?;
+        Ok(self.hash(&link, row))
+    }
+
+    pub fn cell(&self, row: usize, index: u64) -> E::Fr {
+        assert!(row <= self.depth, "too big row");
+        self.try_cell(row, index).expect("cell is pruned")
+    }
+
+    pub fn root(&self) -> E::Fr {
+        self.hash(&self.root, self.depth)
+    }
+
+    pub fn try_proof(&self, index: u64) -> io::Result<Vec<E::Fr>> {
+        if self.arity == 4 {
+            // the other children of the node above row 2i, i.e. the siblings in row 2i
+            return (0..self.depth / 2).flat_map(|i| {
+                let node = index >> (2 * i);
+                (0..4).filter(move |&k| k != node & 3).map(move |k| (2 * i, node & !3 | k))
+            }).map(|(row, j)| self.try_cell(row, j)).collect();
+        }
+        Ok(self.walk(0, index).ok_or_else(|| Self::pruned_error(0, index))?.1)
+    }
+
+    pub fn proof(&self, index: u64) -> Vec<E::Fr> {
+        self.try_proof(index).expect("leaf is pruned")
+    }
+
+    // Nodes held by the tree, shared ones included
+    pub fn node_count(&self) -> usize {
+        fn count<E: JubjubEngine>(link: &Link<E>) -> usize {
+            link.as_ref().map_or(0, |n| 1 + count(&n.left) + count(&n.right))
+        }
+        count(&self.root)
+    }
+
+    // Drops the nodes under every subtree left of watermark without a tracked leaf, only the hash
+    // of the subtree stays. What remains serves the witnesses of the tracked leaves and of every
+    // leaf from watermark on, and the next inserts: they go right of size, and the pruned
+    // subtrees end before it. proof and cell of a pruned leaf fail until restore_path.
+    pub fn prune_below(&mut self, watermark: u64, tracked: &[u64]) {
+        let watermark = watermark.min(self.size);
+        let tracked = tracked.iter().cloned().collect::<BTreeSet<_>>();
+        self.root = prune(&self.root, self.depth, 0, watermark, &tracked, self.arity / 2);
+    }
+
+    // Brings the path of a pruned leaf back from the node store, a level at a time: the two
+    // children read from the store should hash to the node already in the tree, so a store out
+    // of sync with the tree is an error and the tree is left as it was. A 4-ary tree also gets
+    // the children of the sibling in every odd row, for its proof.
+    pub fn restore_path<S: TreeStore<E>>(&mut self, index: u64, store: &S, params: &E::Params) -> io::Result<()> {
+        check_position(index, self.depth)?;
+        let root = match self.root {
+            Some(ref n) => self.expand(n, self.depth, index, store, params)?,
+            None => return Ok(())
+        };
+        self.root = Some(root);
+        Ok(())
+    }
+
+    // The children of the node at (row, i), read from the store if it is pruned
+    fn open<S: TreeStore<E>>(&self, node: &Arc<Node<E>>, row: usize, i: u64, store: &S, params: &E::Params) -> io::Result<(Link<E>, Link<E>)> {
+        if !is_pruned(node, row) {
+            return Ok((node.left.clone(), node.right.clone()));
+        }
+        let hash_only = |hash| Some(Arc::new(Node { hash, left: None, right: None }));
+        let (l, r) = (store.node(row - 1, 2 * i)?, store.node(row - 1, 2 * i + 1)?);
+        if compress::<E>(&l, &r, Personalization::MerkleTree(row - 1), params) != node.hash {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("store does not match the tree at ({}, {})", row, i)));
+        }
+        Ok((hash_only(l), hash_only(r)))
+    }
+
+    fn expand<S: TreeStore<E>>(&self, node: &Arc<Node<E>>, row: usize, index: u64, store: &S, params: &E::Params) -> io::Result<Arc<Node<E>>> {
+        if row == 0 {
+            return Ok(node.clone());
+        }
+        let (mut left, mut right) = self.open(node, row, index >> row, store, params)?;
+        let (next, other) = if (index >> (row - 1)) & 1 == 0 { (&mut left, &mut right) } else { (&mut right, &mut left) };
+        if let Some(n) = next.take() {
+            *next = Some(self.expand(&n, row - 1, index, store, params)?);
+        }
+        if self.arity == 4 && row % 2 == 0 {
+            if let Some(n) = other.take() {
+                let (l, r) = self.open(&n, row - 1, (index >> (row - 1)) ^ 1, store, params)?;
+                *other = Some(Arc::new(Node { hash: n.hash, left: l, right: r }));
+            }
+        }
+        Ok(Arc::new(Node { hash: node.hash, left, right }))
+    }
+}
+
+fn is_pruned<E: JubjubEngine>(node: &Node<E>, row: usize) -> bool {
+    row > 0 && node.left.is_none() && node.right.is_none()
+}
+
+// The subtree at (row, start >> row), cut only in rows which are multiples of step
+fn prune<E: JubjubEngine>(link: &Link<E>, row: usize, start: u64, watermark: u64, tracked: &BTreeSet<u64>, step: usize) -> Link<E> {
+    let node = link.as_ref()?;
+    let end = start + (1 << row);
+    if start >= watermark || row == 0 {
+        return link.clone();
+    }
+    if end <= watermark && row % step == 0 && tracked.range(start..end).next().is_none() {
+        return Some(Arc::new(Node { hash: node.hash, left: None, right: None }));
+    }
+    let half = 1 << (row - 1);
+    Some(Arc::new(Node {
+        hash: node.hash,
+        left: prune(&node.left, row - 1, start, watermark, tracked, step),
+        right: prune(&node.right, row - 1, start + half, watermark, tracked, step)
+    }))
+}
+
+pub struct SharedTree<E: JubjubEngine> {
+    current: RwLock<(u64, Arc<MerkleTree<E>>)>,
+    writer: Mutex<()>
+}
+
+impl<E: JubjubEngine> SharedTree<E> {
+    pub fn new(tree: MerkleTree<E>) -> Self {
+        SharedTree { current: RwLock::new((0, Arc::new(tree))), writer: Mutex::new(()) }
+    }
+
+    // Epoch and tree of the last published state
+    pub fn snapshot(&self) -> (u64, Arc<MerkleTree<E>>) {
+        let cur = self.current.read().unwrap();
+        (cur.0, cur.1.clone())
+    }
+
+    // Writers are serialized. f changes a private copy, readers see the old state until f
+    // returns Ok, an error drops the copy.
+    pub fn apply<R, F>(&self, f: F) -> io::Result<R>
+        where F: FnOnce(&mut MerkleTree<E>) -> io::Result<R>
+    {
+        let _writer = self.writer.lock().unwrap();
+        let (epoch, tree) = self.snapshot();
+        let mut tree = (*tree).clone();
+        let res = f(&mut tree)?;
+        *self.current.write().unwrap() = (epoch + 1, Arc::new(tree));
+        Ok(res)
+    }
+}
+
+
+// Witnesses straight from the storage of the node, without a tree object. The node keeps the
+// tree as rows of cells (row 0 is the leaves, row depth the root), the way the js MerkleTree
+// does, and a cell never written is the default of its row.
+pub trait TreeStore<E: JubjubEngine> {
+    fn node(&self, row: usize, index: u64) -> io::Result<E::Fr>;
+}
+
+impl<E: JubjubEngine> TreeStore<E> for MerkleTree<E> {
+    fn node(&self, row: usize, index: u64) -> io::Result<E::Fr> {
+        self.try_cell(row, index)
+    }
+}
+
+pub struct FlatNodes<'a, E: JubjubEngine> {
+    pub rows: &'a [Vec<E::Fr>],
+    pub defaults: &'a [E::Fr]
+}
+
+impl<'a, E: JubjubEngine> TreeStore<E> for FlatNodes<'a, E> {
+    fn node(&self, row: usize, index: u64) -> io::Result<E::Fr> {
+        let default = self.defaults.get(row).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "too big row"))?;
+        Ok(self.rows.get(row).and_then(|r| usize::try_from(index).ok().and_then(|i| r.get(i))).cloned().unwrap_or(*default))
+    }
+}
+
+fn check_position(index: u64, depth: usize) -> io::Result<()> {
+    if depth >= MERKLE_MAX_DEPTH || index >> depth != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("no leaf {} in a tree of depth {}", index, depth)));
+    }
+    Ok(())
+}
+
+// Sibling path of the leaf, as MerkleTree::proof
+pub fn path_from_nodes<E: JubjubEngine, S: TreeStore<E>>(nodes: &S, index: u64, depth: usize) -> io::Result<Vec<E::Fr>> {
+    check_position(index, depth)?;
+    (0..depth).map(|row| nodes.node(row, (index >> row) ^ 1)).collect()
+}
+
+// (row, index) of the cells a write of the leaf changes, from the leaf up to the root. These
+// are the cells whose siblings path_from_nodes reads, so a node could tell which cached
+// witnesses a new leaf invalidates.
+pub fn nodes_touched_by(index: u64, depth: usize) -> io::Result<Vec<(usize, u64)>> {
+    check_position(index, depth)?;
+    Ok((0..=depth).map(|row| (row, index >> row)).collect())
+}
+
+
+#[cfg(test)]
+mod tree_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use pairing::{Field, PrimeField};
+    use rand::os::OsRng;
+    use rand::Rng;
+    use std::thread;
+
+    use crate::pedersen_hasher::{JUBJUB_PARAMS, merkle_root, merkle_root4, merkle_root_checked, empty_root};
+
+    #[test]
+    fn test_tree() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let leaves = (0..11).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+
+        let mut tree = MerkleTree::<Bls12>::new(8, params);
+        assert!(tree.root() == empty_root(8));
+        tree.push_many(&leaves[..4], params).unwrap();
+        let old = tree.clone();
+        let receipts = tree.push_many(&leaves[4..], params).unwrap();
+        assert!(receipts.len() == 7 && receipts[6].position == 10 && receipts[6].root == tree.root());
+
+        for (i, x) in leaves.iter().enumerate() {
+            assert!(merkle_root::<Bls12>(&tree.proof(i as u64), i as u64, x, params) == tree.root(), "Proof of leaf {} should lead to the root", i);
+        }
+        for r in receipts.iter() {
+            assert!(merkle_root::<Bls12>(&r.proof, r.position, &leaves[r.position as usize], params) == r.root);
+        }
+        assert!(tree.cell(0, 3) == leaves[3] && tree.cell(8, 0) == tree.root() && tree.cell(2, 63) == empty_root(2));
+        assert!(old.size() == 4 && merkle_root::<Bls12>(&old.proof(2), 2, &leaves[2], params) == old.root(), "Snapshot should not change");
+        assert!(old.root() != tree.root());
+
+        let mut small = MerkleTree::<Bls12>::new(2, params);
+        assert!(small.push_many(&leaves[..5], params).is_err(), "Leaves out of the tree should be rejected");
+        assert!(small.size() == 0);
+    }
+
+    #[test]
+    fn test_path_from_nodes() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let mut tree = MerkleTree::<Bls12>::new(5, params);
+        tree.push_many(&(0..13).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>(), params).unwrap();
+
+        // rows as the node stores them, the cells past the filled ones are not written
+        let rows = (0..=5).map(|r| (0..(13 + (1 << r) - 1) >> r).map(|i| tree.cell(r, i)).collect::<Vec<_>>()).collect::<Vec<_>>();
+        let defaults = (0..=5).map(empty_root).collect::<Vec<_>>();
+        let flat = FlatNodes::<Bls12> { rows: &rows, defaults: &defaults };
+
+        for i in [0u64, 7, 12, 13, 31].iter() {
+            let path = path_from_nodes(&flat, *i, 5).unwrap();
+            assert!(path == tree.proof(*i), "Path of {} should match the tree", i);
+            assert!(path_from_nodes(&tree, *i, 5).unwrap() == path);
+        }
+        assert!(path_from_nodes(&flat, 32, 5).is_err());
+
+        let touched = nodes_touched_by(12, 5).unwrap();
+        assert!(touched == vec![(0, 12), (1, 6), (2, 3), (3, 1), (4, 0), (5, 0)]);
+        let before = tree.clone();
+        tree.push_many(&[Fr::one()], params).unwrap();
+        for r in 0..=5 {
+            for i in 0..(1u64 << (5 - r)) {
+                let changed = before.cell(r, i) != tree.cell(r, i);
+                assert!(changed == nodes_touched_by(13, 5).unwrap().contains(&(r, i)), "Cell ({}, {}) should change only if touched", r, i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let leaves = (0..3).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        for &depth in [48, MERKLE_MAX_DEPTH - 1].iter() {
+            let mut tree = MerkleTree::<Bls12>::new(depth, params);
+            assert!(tree.root() == empty_root(depth));
+            tree.push_many(&leaves, params).unwrap();
+            assert!(merkle_root_checked::<Bls12>(&tree.proof(2), 2, &leaves[2], depth, params).unwrap() == tree.root());
+            assert!(tree.cell(depth - 1, 1) == empty_root(depth - 1) && tree.cell(0, (1 << depth) - 1) == Fr::zero());
+        }
+
+        // far cells of a node store are defaults, whatever the width of usize
+        let rows = vec![leaves.clone()];
+        let flat = FlatNodes::<Bls12> { rows: &rows, defaults: &[Fr::zero()] };
+        assert!(flat.node(0, 1).unwrap() == leaves[1] && flat.node(0, (1 << 32) + 1).unwrap() == Fr::zero());
+    }
+
+    #[test]
+    fn test_prune_below() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let leaves = (0..40).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let mut full = MerkleTree::<Bls12>::new(6, params);
+        full.push_many(&leaves, params).unwrap();
+
+        let mut tree = full.clone();
+        tree.prune_below(32, &[3, 17]);
+        assert!(tree.node_count() < full.node_count() / 2 && tree.root() == full.root());
+        for i in [3u64, 17, 32, 39, 50].iter() {
+            assert!(tree.try_proof(*i).unwrap() == full.proof(*i), "Path of {} should be kept", i);
+        }
+        assert!(tree.try_proof(5).is_err() && tree.try_cell(1, 3).is_err());
+        assert!(full.proof(5).len() == 6, "Snapshot should not be pruned");
+
+        // the frontier is kept, inserts give the same roots
+        let more = (0..5).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        tree.push_many(&more, params).unwrap();
+        full.push_many(&more, params).unwrap();
+        assert!(tree.root() == full.root() && tree.proof(44) == full.proof(44));
+
+        assert!(tree.restore_path(5, &MerkleTree::<Bls12>::new(6, params), params).is_err(), "Store out of sync should be rejected");
+        assert!(tree.try_proof(5).is_err());
+        tree.restore_path(5, &full, params).unwrap();
+        assert!(tree.proof(5) == full.proof(5) && tree.root() == full.root());
+        // the sibling leaf comes back with the path, the rest of the subtree does not
+        assert!(tree.proof(4) == full.proof(4) && tree.try_proof(6).is_err());
+    }
+
+    #[test]
+    fn test_tree_arity4() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let leaves = (0..40).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+        let mut binary = MerkleTree::<Bls12>::new(6, params);
+        binary.push_many(&leaves, params).unwrap();
+
+        let mut tree = MerkleTree::<Bls12>::with_arity(3, 4, params);
+        assert!(tree.depth() == 6 && tree.arity() == 4);
+        let receipts = tree.push_many(&leaves, params).unwrap();
+        assert!(tree.root() == binary.root(), "4-ary tree should have the binary root");
+        let root4 = |proof: &[Fr], i: u64| {
+            let sibling = proof.chunks(3).map(|c| [c[0], c[1], c[2]]).collect::<Vec<_>>();
+            merkle_root4::<Bls12>(&sibling, i, &leaves[i as usize], params)
+        };
+        for r in receipts.iter() {
+            assert!(r.proof.len() == 9 && root4(&r.proof, r.position) == r.root, "Proof of leaf {} should lead to the root", r.position);
+        }
+
+        let full = tree.clone();
+        tree.prune_below(32, &[3, 17]);
+        assert!(tree.node_count() < full.node_count() && tree.root() == full.root());
+        for i in [3u64, 17, 32, 39].iter() {
+            assert!(tree.try_proof(*i).unwrap() == full.proof(*i), "4-ary path of {} should be kept", i);
+        }
+        assert!(tree.try_proof(9).unwrap_err().kind() == io::ErrorKind::NotFound);
+        tree.restore_path(9, &binary, params).unwrap();
+        assert!(tree.proof(9) == full.proof(9) && root4(&tree.proof(9), 9) == tree.root());
+    }
+
+    #[test]
+    fn test_tree_diff() {
+        let params = &*JUBJUB_PARAMS;
+        let leaves = (1..=6).map(|i| Fr::from_str(&i.to_string()).unwrap()).collect::<Vec<_>>();
+        let mut a = MerkleTree::<Bls12>::new(48, params);
+        let mut b = MerkleTree::<Bls12>::new(48, params);
+        a.push_many(&leaves[..5], params).unwrap();
+        b.push_many(&leaves[..5], params).unwrap();
+        assert!(a.diff(&b).unwrap().is_empty(), "Equal trees should have no diff");
+
+        b.push_many(&leaves[5..], params).unwrap();
+        let d = b.diff(&a).unwrap();
+        assert!(d.len() == 49, "One changed leaf should change one node per level");
+        assert!(d[0].row == 48 && d[0].old == a.root() && d[0].new == b.root());
+        let last = &d[48];
+        assert!(last.row == 0 && last.index == 5 && last.old == Fr::zero() && last.new == leaves[5]);
+        assert!(a.diff(&MerkleTree::<Bls12>::new(8, params)).is_err(), "Trees of other depths should be rejected");
+
+        let mut pruned = b.clone();
+        pruned.prune_below(4, &[]);
+        assert!(pruned.diff(&a).unwrap().len() == 49, "Pruned subtrees left of the change should not matter");
+    }
+
+    #[test]
+    fn test_tree_heights() {
+        let params = &*JUBJUB_PARAMS;
+        let leaves = (1..=4).map(|i| Fr::from_str(&i.to_string()).unwrap()).collect::<Vec<_>>();
+        let mut tree = MerkleTree::<Bls12>::new(48, params);
+        tree.push_block(&leaves[..2], 100, params).unwrap();
+        let root100 = tree.root();
+        tree.push_block(&leaves[2..3], 105, params).unwrap();
+        let root105 = tree.root();
+        assert!(tree.push_block(&leaves[3..], 104, params).is_err() && tree.size() == 3, "Heights should not decrease");
+        tree.push_many(&leaves[3..], params).unwrap();
+
+        assert!(tree.leaf_height(1) == Some(100) && tree.leaf_height(2) == Some(105));
+        assert!(tree.leaf_height(3).is_none() && tree.leaf_height(4).is_none(), "Leaves pushed without a height should have none");
+        assert!(tree.root_height(&root100) == Some(100) && tree.root_height(&root105) == Some(105) && tree.root_height(&tree.root()).is_none());
+        assert!(tree.is_root_recent(&root100, 110, 10), "Root 10 blocks old should pass");
+        assert!(!tree.is_root_recent(&root100, 111, 10), "Root 11 blocks old should fail");
+        assert!(!tree.is_root_recent(&Fr::one(), 110, 10), "Unknown root should fail");
+
+        let root = tree.root();
+        for h in 106..=120 {
+            tree.push_block(&[], h, params).unwrap();
+        }
+        assert!(tree.root() == root && tree.root_height(&root) == Some(120), "Empty blocks should refresh the current root");
+        assert!(tree.is_root_recent(&root, 120, 10), "Current root should stay recent over empty blocks");
+        assert!(!tree.is_root_recent(&root105, 120, 10) && !tree.is_root_recent(&root100, 120, 10), "Replaced roots should age out");
+    }
+
+    #[test]
+    fn test_shared_tree() {
+        let params = &*JUBJUB_PARAMS;
+        let shared = Arc::new(SharedTree::new(MerkleTree::<Bls12>::new(16, params)));
+
+        let readers = (0..2).map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                while last < 4 {
+                    let (epoch, tree) = shared.snapshot();
+                    assert!(epoch >= last, "Epochs should not go back");
+                    assert!(tree.size() == epoch * 3, "Snapshot should hold whole blocks only");
+                    if tree.size() > 0 {
+                        let i = tree.size() - 1;
+                        assert!(merkle_root::<Bls12>(&tree.proof(i), i, &tree.cell(0, i), &*JUBJUB_PARAMS) == tree.root());
+                    }
+                    last = epoch;
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        let mut rng = OsRng::new().unwrap();
+        for _ in 0..4 {
+            let block = (0..3).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+            shared.apply(|t| t.push_many(&block, params)).unwrap();
+        }
+        assert!(shared.apply(|t| { t.push_many(&[Fr::one()], params)?; Err::<(), _>(io::Error::new(io::ErrorKind::Other, "abort")) }).is_err());
+        readers.into_iter().for_each(|r| r.join().unwrap());
+        let (epoch, tree) = shared.snapshot();
+        assert!(epoch == 4 && tree.size() == 12, "Failed block should not be published");
+    }
+}
@@ -0,0 +1,337 @@
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::pedersen_hash::Personalization;
+use pairing::{PrimeField, PrimeFieldRepr};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use crate::pedersen_hasher::{compress, merkle_defaults, merkle_root, MERKLE_MAX_DEPTH};
+use crate::serialization::read_fr_repr_be;
+use crate::tree::MerkleTree;
+
+
+// Light wallets keep the merkle paths of their own notes fresh from the leaves appended since
+// they last synced, instead of downloading the tree. A wallet keeps the frontier of the tree
+// (one complete node per set bit of the size) and a path per tracked note: every new leaf p
+// changes exactly one sibling of the path of w < p, the node at the level where the paths of
+// p and w join, and that node is on the path of p, which the frontier gives.
+//
+// Encoding (big-endian): start u64 | u32 count | leaves (32 each) | u32 count | frontier (32 each)
+// frontier is the frontier after the delta, the nodes of the set bits of the new size from
+// the lowest level up, so a wallet checks the delta and a new wallet starts from it.
+//
+// A WitnessTracker is saved as (big-endian): depth u8 | size u64 | u32 count | frontier (32 each) |
+// u32 count | (position u64 | leaf (32) | depth siblings (32 each))*. Reading recomputes the root
+// from the frontier and checks every path against it.
+pub const MAX_DELTA_LEAVES: usize = 1 << 20;
+
+
+fn write_fr<F: PrimeField, W: Write>(x: &F, mut writer: W) -> io::Result<()> {
+    x.into_repr().write_be(&mut writer)
+}
+
+fn read_fr<F: PrimeField, R: Read>(mut reader: R) -> io::Result<F> {
+    let mut buf = [0u8; 32];
+    reader.read_exact(&mut buf)?;
+    F::from_repr(read_fr_repr_be::<F>(&buf)?).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeDelta<E: JubjubEngine> {
+    pub start: u64,
+    pub leaves: Vec<E::Fr>,
+    pub frontier: Vec<E::Fr>
+}
+
+impl<E: JubjubEngine> TreeDelta<E> {
+    // Leaves of the tree from start on
+    pub fn from_tree(tree: &MerkleTree<E>, start: u64) -> io::Result<Self> {
+        if start > tree.size() || tree.size() - start > MAX_DELTA_LEAVES as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "wrong delta start"));
+        }
+        let size = tree.size();
+        Ok(TreeDelta {
+            start,
+            leaves: (start..size).map(|i| tree.try_cell(0, i)).collect::<io::Result<_>>()?,
+            frontier: (0..=tree.depth()).filter(|l| (size >> l) & 1 == 1).map(|l| tree.try_cell(l, (size >> l) - 1)).collect::<io::Result<_>>()?
+        })
+    }
+
+    pub fn end(&self) -> u64 {
+        self.start + self.leaves.len() as u64
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u64::<BigEndian>(self.start)?;
+        writer.write_u32::<BigEndian>(self.leaves.len() as u32)?;
+        for x in self.leaves.iter() {
+            write_fr(x, &mut writer)?;
+        }
+        writer.write_u32::<BigEndian>(self.frontier.len() as u32)?;
+        for x in self.frontier.iter() {
+            write_fr(x, &mut writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = vec![];
+        self.write(&mut res).unwrap();
+        res
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let start = reader.read_u64::<BigEndian>()?;
+        let n = reader.read_u32::<BigEndian>()? as usize;
+        if n > MAX_DELTA_LEAVES {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many leaves in the delta"));
+        }
+        let leaves = (0..n).map(|_| read_fr(&mut reader)).collect::<io::Result<Vec<_>>>()?;
+        let m = reader.read_u32::<BigEndian>()? as usize;
+        if m > MERKLE_MAX_DEPTH + 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frontier is too long"));
+        }
+        let frontier = (0..m).map(|_| read_fr(&mut reader)).collect::<io::Result<Vec<_>>>()?;
+        Ok(TreeDelta { start, leaves, frontier })
+    }
+}
+
+
+#[derive(Clone)]
+pub struct WitnessTracker<E: JubjubEngine> {
+    size: u64,
+    // filled[l] is the last complete node of level l, valid when bit l of size is set
+    filled: Vec<E::Fr>,
+    defaults: Vec<E::Fr>,
+    root: E::Fr,
+    witnesses: BTreeMap<u64, (E::Fr, Vec<E::Fr>)>
+}
+
+impl<E: JubjubEngine> WitnessTracker<E> {
+    pub fn new(depth: usize, params: &E::Params) -> Self {
+        assert!(depth < MERKLE_MAX_DEPTH, "tree depth should be less than {}", MERKLE_MAX_DEPTH);
+        let defaults = merkle_defaults::<E>(depth + 1, params);
+        WitnessTracker { size: 0, filled: defaults.clone(), root: defaults[depth], defaults, witnesses: BTreeMap::new() }
+    }
+
+    // A wallet created at a checkpoint, the frontier as in TreeDelta
+    pub fn from_frontier(depth: usize, size: u64, frontier: &[E::Fr], params: &E::Params) -> io::Result<Self> {
+        let mut res = Self::new(depth, params);
+        let levels = (0..=depth).filter(|l| (size >> l) & 1 == 1).collect::<Vec<_>>();
+        if size > 1 << depth || levels.len() != frontier.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frontier does not match the size"));
+        }
+        levels.iter().zip(frontier.iter()).for_each(|(&l, x)| res.filled[l] = *x);
+        res.size = size;
+        res.root = res.frontier_root(params);
+        Ok(res)
+    }
+
+    fn depth(&self) -> usize {
+        self.defaults.len() - 1
+    }
+
+    fn frontier_root(&self, params: &E::Params) -> E::Fr {
+        let depth = self.depth();
+        if self.size >> depth == 1 {
+            return self.filled[depth];
+        }
+        let mut cur = self.defaults[0];
+        for l in 0..depth {
+            cur = if (self.size >> l) & 1 == 1 {
+                compress::<E>(&self.filled[l], &cur, Personalization::MerkleTree(l), params)
+            } else {
+                compress::<E>(&cur, &self.defaults[l], Personalization::MerkleTree(l), params)
+            };
+        }
+        cur
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn root(&self) -> E::Fr {
+        self.root
+    }
+
+    pub fn frontier(&self) -> Vec<E::Fr> {
+        (0..=self.depth()).filter(|l| (self.size >> l) & 1 == 1).map(|l| self.filled[l]).collect()
+    }
+
+    // Sibling path of a tracked note, for pedersen_hasher::merkle_root
+    pub fn witness(&self, position: u64) -> Option<&[E::Fr]> {
+        self.witnesses.get(&position).map(|(_, path)| path.as_slice())
+    }
+
+    pub fn leaf(&self, position: u64) -> Option<E::Fr> {
+        self.witnesses.get(&position).map(|(leaf, _)| *leaf)
+    }
+
+    pub fn forget(&mut self, position: u64) {
+        self.witnesses.remove(&position);
+    }
+
+    fn append(&mut self, leaf: &E::Fr, track: bool, params: &E::Params) {
+        let p = self.size;
+        let depth = self.depth();
+
+        // the path of p bottom up, the right siblings are empty yet
+        let mut path = Vec::with_capacity(depth + 1);
+        path.push(*leaf);
+        for l in 0..depth {
+            let cur = path[l];
+            path.push(if (p >> l) & 1 == 1 {
+                compress::<E>(&self.filled[l], &cur, Personalization::MerkleTree(l), params)
+            } else {
+                compress::<E>(&cur, &self.defaults[l], Personalization::MerkleTree(l), params)
+            });
+        }
+
+        for (w, (_, sibling)) in self.witnesses.iter_mut() {
+            let r = 63 - (p ^ w).leading_zeros() as usize;
+            sibling[r] = path[r];
+        }
+        if track {
+            let sibling = (0..depth).map(|l| if (p >> l) & 1 == 1 { self.filled[l] } else { self.defaults[l] }).collect();
+            self.witnesses.insert(p, (*leaf, sibling));
+        }
+
+        self.size += 1;
+        for l in 0..=depth {
+            if self.size & ((1 << l) - 1) == 0 {
+                self.filled[l] = path[l];
+            }
+        }
+        self.root = path[depth];
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(self.depth() as u8)?;
+        writer.write_u64::<BigEndian>(self.size)?;
+        let frontier = self.frontier();
+        writer.write_u32::<BigEndian>(frontier.len() as u32)?;
+        for x in frontier.iter() {
+            write_fr(x, &mut writer)?;
+        }
+        writer.write_u32::<BigEndian>(self.witnesses.len() as u32)?;
+        for (p, (leaf, sibling)) in self.witnesses.iter() {
+            writer.write_u64::<BigEndian>(*p)?;
+            write_fr(leaf, &mut writer)?;
+            for x in sibling.iter() {
+                write_fr(x, &mut writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R, params: &E::Params) -> io::Result<Self> {
+        let depth = reader.read_u8()? as usize;
+        if depth >= MERKLE_MAX_DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("tree depth should be less than {}", MERKLE_MAX_DEPTH)));
+        }
+        let size = reader.read_u64::<BigEndian>()?;
+        let m = reader.read_u32::<BigEndian>()? as usize;
+        if m > depth + 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frontier is too long"));
+        }
+        let frontier = (0..m).map(|_| read_fr(&mut reader)).collect::<io::Result<Vec<_>>>()?;
+        let mut res = Self::from_frontier(depth, size, &frontier, params)?;
+
+        let n = reader.read_u32::<BigEndian>()?;
+        for _ in 0..n {
+            let p = reader.read_u64::<BigEndian>()?;
+            let leaf = read_fr(&mut reader)?;
+            let sibling = (0..depth).map(|_| read_fr(&mut reader)).collect::<io::Result<Vec<_>>>()?;
+            if p >= size || res.witnesses.contains_key(&p) || merkle_root::<E>(&sibling, p, &leaf, params) != res.root {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("witness of position {} does not match the tree", p)));
+            }
+            res.witnesses.insert(p, (leaf, sibling));
+        }
+        Ok(res)
+    }
+
+    // Applies the delta and starts tracking the positions in track. A delta which does not
+    // continue the tree or does not give the frontier it claims leaves the tracker as it was.
+    pub fn apply(&mut self, delta: &TreeDelta<E>, track: &[u64], params: &E::Params) -> io::Result<()> {
+        if delta.start != self.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("delta starts at {}, the tree has {} leaves", delta.start, self.size)));
+        }
+        if delta.end() > 1 << self.depth() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "delta overflows the tree"));
+        }
+        if let Some(p) = track.iter().find(|&&p| p < delta.start || p >= delta.end()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("position {} is not in the delta", p)));
+        }
+
+        let mut next = self.clone();
+        for (i, leaf) in delta.leaves.iter().enumerate() {
+            let p = delta.start + i as u64;
+            next.append(leaf, track.contains(&p), params);
+        }
+        if next.frontier() != delta.frontier {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "delta leaves do not give its frontier"));
+        }
+        *self = next;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tree_delta_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+
+    #[test]
+    fn test_tree_delta() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let mut leaves = |n: usize| (0..n).map(|_| rng.gen::<Fr>()).collect::<Vec<_>>();
+
+        let mut tree = MerkleTree::<Bls12>::new(6, params);
+        tree.push_many(&leaves(5), params).unwrap();
+        let checkpoint = TreeDelta::from_tree(&tree, 5).unwrap();
+        let mut wallet = WitnessTracker::<Bls12>::from_frontier(6, 5, &checkpoint.frontier, params).unwrap();
+        assert!(wallet.root() == tree.root());
+
+        for (n, track) in [(3usize, vec![6u64]), (9, vec![8, 15]), (1, vec![]), (46, vec![62])].iter() {
+            let start = tree.size();
+            tree.push_many(&leaves(*n), params).unwrap();
+            let delta = TreeDelta::<Bls12>::read(&TreeDelta::from_tree(&tree, start).unwrap().to_bytes()[..]).unwrap();
+            wallet.apply(&delta, track, params).unwrap();
+            assert!(wallet.root() == tree.root() && wallet.frontier() == delta.frontier);
+        }
+        assert!(tree.size() == 64 && wallet.root() == tree.root(), "Full tree should be followed");
+
+        for p in [6u64, 8, 15, 62].iter() {
+            let path = wallet.witness(*p).unwrap();
+            assert!(path == &tree.proof(*p)[..], "Witness {} should match the tree", p);
+            assert!(merkle_root::<Bls12>(path, *p, &wallet.leaf(*p).unwrap(), params) == tree.root());
+        }
+
+        let mut data = vec![];
+        wallet.write(&mut data).unwrap();
+        let restored = WitnessTracker::<Bls12>::read(&data[..], params).unwrap();
+        assert!(restored.root() == wallet.root() && restored.witness(15) == wallet.witness(15), "Tracker should survive a roundtrip");
+        let n = data.len();
+        data[n - 1] ^= 1;
+        assert!(WitnessTracker::<Bls12>::read(&data[..], params).is_err(), "Corrupted path should be detected");
+
+        let mut other = MerkleTree::<Bls12>::new(6, params);
+        other.push_many(&leaves(3), params).unwrap();
+        let mut wallet = WitnessTracker::<Bls12>::new(6, params);
+        let mut delta = TreeDelta::from_tree(&other, 0).unwrap();
+        delta.leaves[1] = Fr::from_str("1").unwrap();
+        assert!(wallet.apply(&delta, &[0], params).is_err(), "Forged leaves should be detected");
+        assert!(wallet.size() == 0 && wallet.witness(0).is_none());
+        assert!(wallet.apply(&TreeDelta::from_tree(&other, 1).unwrap(), &[], params).is_err(), "Gap should be rejected");
+    }
+}
@@ -0,0 +1,53 @@
+use pairing::PrimeField;
+
+use std::io;
+
+use crate::field;
+
+
+// 64 bit amounts and balances with the arithmetic of zwaves_circuit::gadgets::uint64::UInt64: wrapping add with
+// the carry out, wrapping sub with the borrow, and checked versions which fail where the gadget
+// is unsatisfied. Wallets compute the values of a transfer with these, so what the circuit
+// rejects is rejected before proving.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UInt64(pub u64);
+
+impl UInt64 {
+    // Sum modulo 2^64 and the carry out
+    pub fn add(self, other: Self) -> (Self, bool) {
+        let (res, carry) = self.0.overflowing_add(other.0);
+        (UInt64(res), carry)
+    }
+
+    pub fn add_checked(self, other: Self) -> io::Result<Self> {
+        self.0.checked_add(other.0).map(UInt64)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} + {} overflows 64 bits", self.0, other.0)))
+    }
+
+    // Difference modulo 2^64 and the borrow, set iff self < other
+    pub fn sub(self, other: Self) -> (Self, bool) {
+        let (res, borrow) = self.0.overflowing_sub(other.0);
+        (UInt64(res), borrow)
+    }
+
+    pub fn sub_checked(self, other: Self) -> io::Result<Self> {
+        self.0.checked_sub(other.0).map(UInt64)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} - {} is negative", self.0, other.0)))
+    }
+
+    pub fn lt(self, other: Self) -> bool {
+        self.0 < other.0
+    }
+
+    pub fn le(self, other: Self) -> bool {
+        self.0 <= other.0
+    }
+
+    pub fn to_fr<F: PrimeField>(self) -> F {
+        field::from_u64(self.0)
+    }
+
+    pub fn from_fr<F: PrimeField>(x: &F) -> io::Result<Self> {
+        field::to_u64(x).map(UInt64).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "value does not fit in 64 bits"))
+    }
+}
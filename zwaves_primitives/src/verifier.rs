@@ -3,6 +3,7 @@ use pairing::{
     CurveProjective,
     CurveAffine,
     PrimeField,
+    PrimeFieldRepr,
     Field,
     EncodedPoint
 };
@@ -14,8 +15,20 @@ use bellman::groth16::{
 
 use bellman::SynthesisError;
 
+use sapling_crypto::jubjub::JubjubEngine;
+
+use pairing::bls12_381::{Bls12, Fr, G1Affine, G2Affine};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::aggregate::{write_gt, read_gt};
+use crate::metrics::{self, Counter, Timing};
+use crate::pedersen_hasher;
+use crate::point::{read_point, decode_point, read_proof};
+use crate::serialization::read_fr_vec;
+
 use std::io::{Read, Write};
-use std::io;
+use std::{io, mem};
 
 
 #[derive(Clone)]
@@ -48,61 +61,16 @@ impl<E: Engine> TruncatedVerifyingKey<E> {
         mut reader: R
     ) -> io::Result<Self>
     {
-        let mut g1_repr = <E::G1Affine as CurveAffine>::Compressed::empty();
-        let mut g2_repr = <E::G2Affine as CurveAffine>::Compressed::empty();
-
-        reader.read_exact(g1_repr.as_mut())?;
-        let alpha_g1 = g1_repr
-                .into_affine()
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-                .and_then(|e| if e.is_zero() {
-                    Err(io::Error::new(io::ErrorKind::InvalidData, "point at infinity"))
-                } else {
-                    Ok(e)
-                })?;
-
-        reader.read_exact(g2_repr.as_mut())?;
-        let beta_g2 = g2_repr
-                .into_affine()
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-                .and_then(|e| if e.is_zero() {
-                    Err(io::Error::new(io::ErrorKind::InvalidData, "point at infinity"))
-                } else {
-                    Ok(e)
-                })?;
-
-        reader.read_exact(g2_repr.as_mut())?;
-        let gamma_g2 = g2_repr
-                .into_affine()
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-                .and_then(|e| if e.is_zero() {
-                    Err(io::Error::new(io::ErrorKind::InvalidData, "point at infinity"))
-                } else {
-                    Ok(e)
-                })?;
-
-        reader.read_exact(g2_repr.as_mut())?;
-        let delta_g2 = g2_repr
-                .into_affine()
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-                .and_then(|e| if e.is_zero() {
-                    Err(io::Error::new(io::ErrorKind::InvalidData, "point at infinity"))
-                } else {
-                    Ok(e)
-                })?;
+        let alpha_g1 = read_point::<E::G1Affine, _>(&mut reader, true)?;
+        let beta_g2 = read_point::<E::G2Affine, _>(&mut reader, true)?;
+        let gamma_g2 = read_point::<E::G2Affine, _>(&mut reader, true)?;
+        let delta_g2 = read_point::<E::G2Affine, _>(&mut reader, true)?;
 
         let mut ic = vec![];
+        let mut g1_repr = <E::G1Affine as CurveAffine>::Compressed::empty();
 
         while reader.read_exact(g1_repr.as_mut()).is_ok() {
-            let g1 = g1_repr
-                    .into_affine()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-                    .and_then(|e| if e.is_zero() {
-                        Err(io::Error::new(io::ErrorKind::InvalidData, "point at infinity"))
-                    } else {
-                        Ok(e)
-                    })?;
-            ic.push(g1);
+            ic.push(decode_point::<E::G1Affine>(&g1_repr, true)?);
         }
 
         Ok(TruncatedVerifyingKey {
@@ -152,12 +120,370 @@ pub fn verify_proof<'a, E: Engine>(
     let mut neg_a = proof.a.clone();
     neg_a.negate();
 
-    Ok(E::final_exponentiation(
+    let res = metrics::time(Timing::Verify, || E::final_exponentiation(
         &E::miller_loop([
             (&neg_a.prepare(), &proof.b.prepare()),
             (&tvk.alpha_g1.prepare(), &tvk.beta_g2.prepare()),
             (&acc.into_affine().prepare(), &tvk.gamma_g2.prepare()),
             (&proof.c.prepare(), &tvk.delta_g2.prepare())
         ].into_iter())
-    ).unwrap() == E::Fqk::one())
+    ).unwrap() == E::Fqk::one());
+    count_verified(res);
+    Ok(res)
+}
+
+fn count_verified(valid: bool) {
+    metrics::inc(if valid { Counter::ProofsVerified } else { Counter::ProofsRejected }, 1);
+}
+
+
+// gamma_g2 and delta_g2 are kept next to their prepared negations: the line coefficients of
+// the prepared points can not be read out of pairing, so they are prepared again on read.
+#[derive(Clone)]
+pub struct PreparedTruncatedVerifyingKey<E: Engine> {
+    pub alpha_g1_beta_g2: E::Fqk,
+    pub neg_gamma_g2: <E::G2Affine as CurveAffine>::Prepared,
+    pub neg_delta_g2: <E::G2Affine as CurveAffine>::Prepared,
+    pub gamma_g2: E::G2Affine,
+    pub delta_g2: E::G2Affine,
+    pub ic: Vec<E::G1Affine>
+}
+
+pub fn prepare_truncated_verifying_key<E: Engine>(
+    tvk: &TruncatedVerifyingKey<E>
+) -> PreparedTruncatedVerifyingKey<E>
+{
+    let mut gamma = tvk.gamma_g2;
+    gamma.negate();
+    let mut delta = tvk.delta_g2;
+    delta.negate();
+
+    PreparedTruncatedVerifyingKey {
+        alpha_g1_beta_g2: E::pairing(tvk.alpha_g1, tvk.beta_g2),
+        neg_gamma_g2: gamma.prepare(),
+        neg_delta_g2: delta.prepare(),
+        gamma_g2: tvk.gamma_g2,
+        delta_g2: tvk.delta_g2,
+        ic: tvk.ic.clone()
+    }
+}
+
+
+// Prepared key of a verifier process written out once, so a short-lived verifier starts
+// without the pairing of alpha and beta, the one costly step of the preparation.
+// Encoding: version u8 | alpha*beta as 12 Fq (48 bytes BE each) | gamma, delta compressed |
+// u32 BE count | ic compressed.
+// The pairing value is not checked on read (that would cost the pairing again): the file should
+// come from a trusted place, wrap it into an envelope against corruption.
+pub const PREPARED_VK_VERSION: u8 = 1;
+pub const MAX_PREPARED_VK_INPUTS: usize = 1 << 16;
+
+impl PreparedTruncatedVerifyingKey<Bls12> {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(PREPARED_VK_VERSION)?;
+        write_gt(&self.alpha_g1_beta_g2, &mut writer)?;
+        writer.write_all(self.gamma_g2.into_compressed().as_ref())?;
+        writer.write_all(self.delta_g2.into_compressed().as_ref())?;
+        writer.write_u32::<BigEndian>(self.ic.len() as u32)?;
+        for ic in &self.ic {
+            writer.write_all(ic.into_compressed().as_ref())?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        if reader.read_u8()? != PREPARED_VK_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown prepared key version"));
+        }
+        let alpha_g1_beta_g2 = read_gt(&mut reader)?;
+        let gamma_g2 = read_point::<G2Affine, _>(&mut reader, true)?;
+        let delta_g2 = read_point::<G2Affine, _>(&mut reader, true)?;
+        let n = reader.read_u32::<BigEndian>()? as usize;
+        if n == 0 || n > MAX_PREPARED_VK_INPUTS + 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong number of ic points"));
+        }
+        let ic = (0..n).map(|_| read_point::<G1Affine, _>(&mut reader, true)).collect::<io::Result<Vec<_>>>()?;
+
+        let mut gamma = gamma_g2;
+        gamma.negate();
+        let mut delta = delta_g2;
+        delta.negate();
+        Ok(PreparedTruncatedVerifyingKey {
+            alpha_g1_beta_g2,
+            neg_gamma_g2: gamma.prepare(),
+            neg_delta_g2: delta.prepare(),
+            gamma_g2,
+            delta_g2,
+            ic
+        })
+    }
+}
+
+pub fn verify_prepared_proof<'a, E: Engine>(
+    pvk: &'a PreparedTruncatedVerifyingKey<E>,
+    proof: &Proof<E>,
+    public_inputs: &[E::Fr]
+) -> Result<bool, SynthesisError>
+{
+    if (public_inputs.len() + 1) != pvk.ic.len() {
+        return Err(SynthesisError::MalformedVerifyingKey);
+    }
+
+    let mut acc = pvk.ic[0].into_projective();
+
+    for (i, b) in public_inputs.iter().zip(pvk.ic.iter().skip(1)) {
+        acc.add_assign(&b.mul(i.into_repr()));
+    }
+
+    Ok(check_prepared_pairing(pvk, proof, acc))
+}
+
+fn check_prepared_pairing<E: Engine>(
+    pvk: &PreparedTruncatedVerifyingKey<E>,
+    proof: &Proof<E>,
+    acc: E::G1
+) -> bool
+{
+    // A * B + inputs * (-gamma) + C * (-delta) == alpha * beta
+
+    let res = metrics::time(Timing::Verify, || E::final_exponentiation(
+        &E::miller_loop([
+            (&proof.a.prepare(), &proof.b.prepare()),
+            (&acc.into_affine().prepare(), &pvk.neg_gamma_g2),
+            (&proof.c.prepare(), &pvk.neg_delta_g2)
+        ].iter())
+    ).unwrap() == pvk.alpha_g1_beta_g2);
+    count_verified(res);
+    res
+}
+
+
+pub const PROOF_LEN: usize = 192;
+
+// Exact length of a streamed proof: the proof followed by the public inputs (32 bytes BE each).
+pub fn stream_len<E: Engine>(pvk: &PreparedTruncatedVerifyingKey<E>) -> usize {
+    PROOF_LEN + pvk.ic.len().saturating_sub(1) * mem::size_of::<<E::Fr as PrimeField>::Repr>()
+}
+
+// Verifies a proof read straight from the wire. The inputs are folded into the accumulator as
+// they are read, nothing is buffered, and exactly stream_len bytes are consumed, so the reader
+// stays at the start of the next frame. Short, malformed or non-canonical data is InvalidData
+// or UnexpectedEof; a false result means the proof is well formed but wrong.
+pub fn verify_stream<E: Engine, R: Read>(
+    pvk: &PreparedTruncatedVerifyingKey<E>,
+    mut reader: R
+) -> io::Result<bool>
+{
+    if pvk.ic.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "malformed verifying key"));
+    }
+
+    let proof = read_proof::<E, _>(&mut reader, true)?;
+
+    let mut acc = pvk.ic[0].into_projective();
+    let mut repr = <E::Fr as PrimeField>::Repr::default();
+
+    for b in pvk.ic.iter().skip(1) {
+        repr.read_be(&mut reader)?;
+        let input = E::Fr::from_repr(repr).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not in field"))?;
+        acc.add_assign(&b.mul(input.into_repr()));
+    }
+
+    Ok(check_prepared_pairing(pvk, &proof, acc))
+}
+
+
+// Everything a node needs to check proofs, built once and shared between threads
+// (wrap into Arc), so the verifying key is not prepared again for every proof.
+pub struct VerifierContext<E: JubjubEngine> {
+    pvk: PreparedTruncatedVerifyingKey<E>,
+    params: E::Params,
+    defaults: Vec<E::Fr>
+}
+
+impl<E: JubjubEngine> VerifierContext<E> {
+    pub fn new(tvk: &TruncatedVerifyingKey<E>, params: E::Params, depth: usize) -> Self {
+        let defaults = pedersen_hasher::merkle_defaults::<E>(depth + 1, &params);
+        VerifierContext {
+            pvk: prepare_truncated_verifying_key(tvk),
+            params,
+            defaults
+        }
+    }
+
+    // With a key prepared by another process, see PreparedTruncatedVerifyingKey::read
+    pub fn from_prepared(pvk: PreparedTruncatedVerifyingKey<E>, params: E::Params, depth: usize) -> Self {
+        let defaults = pedersen_hasher::merkle_defaults::<E>(depth + 1, &params);
+        VerifierContext { pvk, params, defaults }
+    }
+
+    pub fn pvk(&self) -> &PreparedTruncatedVerifyingKey<E> {
+        &self.pvk
+    }
+
+    pub fn params(&self) -> &E::Params {
+        &self.params
+    }
+
+    pub fn defaults(&self) -> &[E::Fr] {
+        &self.defaults
+    }
+
+    pub fn verify(&self, proof: &Proof<E>, public_inputs: &[E::Fr]) -> Result<bool, SynthesisError> {
+        verify_prepared_proof(&self.pvk, proof, public_inputs)
+    }
+
+    pub fn verify_stream<R: Read>(&self, reader: R) -> io::Result<bool> {
+        verify_stream(&self.pvk, reader)
+    }
+}
+
+
+// groth16Verify of Ride on BLS12-381, the checks of the native library of the node
+// (zwaves_jni) and the size limit of the script function. The node reads any Err as false,
+// so a bundle passes on chain only with Ok(true); Err tells why it would not.
+//
+// vk: alpha (48) | beta, gamma, delta (96 each) | ic (48 each), that is 8 + n chunks of 48
+// inputs: n elements of 32 bytes big-endian, each below the modulus
+pub const RIDE_MAX_INPUTS: usize = 16;
+pub const RIDE_INPUT_LEN: usize = 32;
+pub const RIDE_VK_CHUNK_LEN: usize = 48;
+
+pub fn pack_inputs<F: PrimeField>(inputs: &[F]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(inputs.len() * RIDE_INPUT_LEN);
+    for x in inputs.iter() {
+        x.into_repr().write_be(&mut res).unwrap();
+    }
+    res
+}
+
+pub fn groth16_verify_bytes(vk: &[u8], proof: &[u8], inputs: &[u8]) -> io::Result<bool> {
+    if vk.len() % RIDE_VK_CHUNK_LEN != 0 || inputs.len() % RIDE_INPUT_LEN != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong buffer length"));
+    }
+    let inputs_len = inputs.len() / RIDE_INPUT_LEN;
+    if vk.len() / RIDE_VK_CHUNK_LEN != inputs_len + 8 || proof.len() != PROOF_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong buffer length"));
+    }
+
+    let vk = TruncatedVerifyingKey::<Bls12>::read(vk)?;
+    let proof = Proof::<Bls12>::read(proof)?;
+    let inputs = read_fr_vec::<Fr>(inputs)?;
+    if inputs.len() != inputs_len || vk.ic.len() != inputs_len + 1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong buffer parsing"));
+    }
+
+    Ok(verify_proof(&vk, &proof, &inputs).unwrap_or(false))
+}
+
+pub fn ride_groth16_verify(vk: &[u8], proof: &[u8], inputs: &[u8]) -> io::Result<bool> {
+    if inputs.len() > RIDE_MAX_INPUTS * RIDE_INPUT_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("groth16Verify takes at most {} inputs", RIDE_MAX_INPUTS)));
+    }
+    groth16_verify_bytes(vk, proof, inputs)
+}
+
+
+#[cfg(test)]
+mod verifier_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use bellman::groth16::{generate_random_parameters, create_random_proof};
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::point::ReadProof;
+    use crate::serialization::write_fr_iter;
+    use crate::test_circuits::Mul;
+
+    use std::io::Cursor;
+
+    fn frame(proof: &Proof<Bls12>, inputs: &[Fr]) -> Vec<u8> {
+        let mut data = vec![];
+        proof.write(&mut data).unwrap();
+        let mut inputs_data = vec![0u8; inputs.len() * 32];
+        write_fr_iter(inputs, &mut inputs_data).unwrap();
+        data.extend(inputs_data);
+        data
+    }
+
+    #[test]
+    fn test_verify_stream() {
+        let mut rng = OsRng::new().unwrap();
+        let params = generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap();
+        let pvk = prepare_truncated_verifying_key(&truncate_verifying_key(&params.vk));
+
+        let (x, y) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+        let mut z = x;
+        z.mul_assign(&y);
+        let proof = create_random_proof(Mul(Some((x, y))), &params, &mut rng).unwrap();
+
+        let data = frame(&proof, &[x, z]);
+        assert!(data.len() == stream_len(&pvk));
+        assert!(Proof::<Bls12>::read_from(&data[..]).unwrap() == proof);
+
+        // the next frame should stay in the reader
+        let mut reader = Cursor::new([&data[..], &[1, 2, 3]].concat());
+        assert!(verify_stream(&pvk, &mut reader).unwrap(), "Proof should be valid");
+        assert!(reader.position() as usize == data.len(), "Only the frame should be consumed");
+        assert!(verify_prepared_proof(&pvk, &proof, &[x, z]).unwrap());
+
+        assert!(!verify_stream(&pvk, &frame(&proof, &[z, x])[..]).unwrap(), "Wrong inputs should be rejected");
+
+        let err = verify_stream(&pvk, &data[..data.len() - 1]).unwrap_err();
+        assert!(err.kind() == io::ErrorKind::UnexpectedEof, "Short frame should be rejected");
+
+        let mut bad = data.clone();
+        for b in bad[PROOF_LEN..PROOF_LEN + 32].iter_mut() {
+            *b = 0xff;
+        }
+        let err = verify_stream(&pvk, &bad[..]).unwrap_err();
+        assert!(err.kind() == io::ErrorKind::InvalidData, "Inputs out of the field should be rejected");
+    }
+
+    #[test]
+    fn test_prepared_key_roundtrip() {
+        let mut rng = OsRng::new().unwrap();
+        let params = generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap();
+        let pvk = prepare_truncated_verifying_key(&truncate_verifying_key(&params.vk));
+
+        let (x, y) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+        let mut z = x;
+        z.mul_assign(&y);
+        let proof = create_random_proof(Mul(Some((x, y))), &params, &mut rng).unwrap();
+
+        let mut data = vec![];
+        pvk.write(&mut data).unwrap();
+        let read = PreparedTruncatedVerifyingKey::<Bls12>::read(&data[..]).unwrap();
+        assert!(read.alpha_g1_beta_g2 == pvk.alpha_g1_beta_g2 && read.ic == pvk.ic);
+        assert!(verify_prepared_proof(&read, &proof, &[x, z]).unwrap(), "Proof should be valid with the read key");
+        assert!(!verify_prepared_proof(&read, &proof, &[z, x]).unwrap());
+
+        let mut bad = data.clone();
+        bad[0] = PREPARED_VK_VERSION + 1;
+        assert!(PreparedTruncatedVerifyingKey::<Bls12>::read(&bad[..]).is_err(), "Unknown version should be rejected");
+        assert!(PreparedTruncatedVerifyingKey::<Bls12>::read(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_ride_groth16_verify() {
+        let mut rng = OsRng::new().unwrap();
+        let params = generate_random_parameters::<Bls12, _, _>(Mul(None), &mut rng).unwrap();
+        let mut vk = vec![];
+        truncate_verifying_key(&params.vk).write(&mut vk).unwrap();
+
+        let (x, y) = (rng.gen::<Fr>(), rng.gen::<Fr>());
+        let mut z = x;
+        z.mul_assign(&y);
+        let mut proof = vec![];
+        create_random_proof(Mul(Some((x, y))), &params, &mut rng).unwrap().write(&mut proof).unwrap();
+
+        let inputs = pack_inputs(&[x, z]);
+        assert!(ride_groth16_verify(&vk, &proof, &inputs).unwrap());
+        assert!(!ride_groth16_verify(&vk, &proof, &pack_inputs(&[z, x])).unwrap(), "Wrong inputs should give false");
+        assert!(ride_groth16_verify(&vk, &proof, &inputs[..32]).is_err(), "Inputs should match the key");
+        assert!(ride_groth16_verify(&vk, &proof[..191], &inputs).is_err());
+        assert!(ride_groth16_verify(&vk, &proof, &[0xff; 64]).is_err(), "Inputs out of the field should be rejected");
+        assert!(ride_groth16_verify(&vk, &proof, &[0; 17 * 32]).unwrap_err().to_string() == "groth16Verify takes at most 16 inputs");
+    }
 }
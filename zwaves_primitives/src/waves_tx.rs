@@ -0,0 +1,238 @@
+use sapling_crypto::jubjub::JubjubEngine;
+
+use std::io;
+
+use crate::bundle::TransactionBundle;
+use crate::verifier::{pack_inputs, ride_groth16_verify};
+
+
+// InvokeScript transaction (type 16, version 1) calling the pool dApp with a bundle, as the
+// demo does: transferExternal(proof, v, m), every argument binary.
+//   proof  the compressed proof (192)
+//   v      the public inputs, 32 bytes big-endian each, as groth16Verify takes them
+//   m      the rest of the bundle encoding (notes, out ciphertexts, signatures, fee), so that
+//          proof | u32 count | v | m is the bundle again
+//
+// The payload is the JSON of the node API without proofs: the wallet signs the binary body
+// with the sender key and adds the signature to "proofs" before broadcasting.
+pub const INVOKE_SCRIPT_TX_TYPE: u8 = 16;
+pub const INVOKE_SCRIPT_TX_VERSION: u8 = 1;
+pub const TRANSFER_FUNCTION: &str = "transferExternal";
+
+// Limits of the Waves node
+pub const MAX_INVOKE_TX_SIZE: usize = 5 * 1024;
+pub const MAX_INVOKE_ARGS: usize = 22;
+pub const MAX_FUNCTION_NAME_LEN: usize = 255;
+pub const MAX_BINARY_ARG_LEN: usize = 32767;
+
+const PUBLIC_KEY_LEN: usize = 32;
+const ADDRESS_LEN: usize = 26;
+const SIGNATURE_LEN: usize = 64;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvokeScript {
+    pub chain_id: u8,
+    // base58
+    pub sender_public_key: String,
+    pub dapp: String,
+    pub function: String,
+    pub args: Vec<Vec<u8>>,
+    // WAVES attached to the call, the deposit of a transfer
+    pub payment: Option<u64>,
+    pub fee: u64,
+    pub timestamp: u64
+}
+
+
+pub fn base58_encode(data: &[u8]) -> String {
+    let mut digits: Vec<u8> = vec![];
+    for &x in data.iter() {
+        let mut carry = u32::from(x);
+        for d in digits.iter_mut() {
+            carry += u32::from(*d) << 8;
+            *d = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = data.iter().take_while(|&&x| x == 0).count();
+    ::std::iter::repeat(b'1').take(zeros).chain(digits.iter().rev().map(|&d| BASE58_ALPHABET[d as usize])).map(char::from).collect()
+}
+
+pub fn base58_decode(s: &str) -> io::Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = vec![];
+    for c in s.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|&a| a == c)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a base58 string"))? as u32;
+        for b in bytes.iter_mut() {
+            carry += u32::from(*b) * 58;
+            *b = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.bytes().take_while(|&c| c == b'1').count();
+    Ok(::std::iter::repeat(0).take(zeros).chain(bytes.into_iter().rev()).collect())
+}
+
+fn check_base58(s: &str, len: usize, what: &str) -> io::Result<()> {
+    if base58_decode(s).map(|x| x.len()).ok() != Some(len) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} should be {} bytes in base58", what, len)));
+    }
+    Ok(())
+}
+
+
+impl InvokeScript {
+    pub fn transfer<E: JubjubEngine>(bundle: &TransactionBundle<E>, sender_public_key: &str, dapp: &str, chain_id: u8,
+        payment: Option<u64>, fee: u64, timestamp: u64) -> io::Result<Self>
+    {
+        bundle.validate()?;
+        let mut proof = vec![];
+        bundle.proof.write(&mut proof)?;
+
+        let v = pack_inputs(&bundle.public_inputs);
+        let m = bundle.to_bytes()[1 + proof.len() + 4 + v.len()..].to_vec();
+
+        let res = InvokeScript {
+            chain_id,
+            sender_public_key: sender_public_key.to_string(),
+            dapp: dapp.to_string(),
+            function: TRANSFER_FUNCTION.to_string(),
+            args: vec![proof, v, m],
+            payment,
+            fee,
+            timestamp
+        };
+        res.validate()?;
+        Ok(res)
+    }
+
+    // groth16Verify(vk, proof, v) of the contract on the arguments of the call, before it is
+    // broadcast and the fee is paid
+    pub fn verify_proof(&self, vk: &[u8]) -> io::Result<bool> {
+        if self.args.len() < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "call has no proof and inputs"));
+        }
+        ride_groth16_verify(vk, &self.args[0], &self.args[1])
+    }
+
+    // Size of the binary form with one signature, the one the node limits:
+    // 0 | type | version | chain id | sender public key | dApp address | 1 | function call |
+    // payments | fee | fee asset | timestamp, then the proofs: version | count | (len | proof)
+    // function call = 9 | 1 | u32 len | name | u32 count | (1 | u32 len | arg)*
+    // payment = u16 len | amount | asset flag, behind a u16 count
+    pub fn binary_size(&self) -> usize {
+        let call = 2 + 4 + self.function.len() + 4 + self.args.iter().map(|a| 1 + 4 + a.len()).sum::<usize>();
+        let payments = 2 + self.payment.map_or(0, |_| 2 + 8 + 1);
+        let body = 4 + PUBLIC_KEY_LEN + ADDRESS_LEN + 1 + call + payments + 8 + 1 + 8;
+        body + 1 + 2 + 2 + SIGNATURE_LEN
+    }
+
+    pub fn validate(&self) -> io::Result<()> {
+        check_base58(&self.sender_public_key, PUBLIC_KEY_LEN, "sender public key")?;
+        check_base58(&self.dapp, ADDRESS_LEN, "dApp address")?;
+        if self.function.is_empty() || self.function.len() > MAX_FUNCTION_NAME_LEN || !self.function.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "wrong function name"));
+        }
+        if self.args.len() > MAX_INVOKE_ARGS {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} arguments, at most {} allowed", self.args.len(), MAX_INVOKE_ARGS)));
+        }
+        if let Some(i) = self.args.iter().position(|a| a.len() > MAX_BINARY_ARG_LEN) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("argument {} is longer than {} bytes", i, MAX_BINARY_ARG_LEN)));
+        }
+        if self.payment == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "zero payment"));
+        }
+        let size = self.binary_size();
+        if size > MAX_INVOKE_TX_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("transaction is {} bytes, at most {} allowed", size, MAX_INVOKE_TX_SIZE)));
+        }
+        Ok(())
+    }
+
+    // Strings are base58 or the checked function name, nothing to escape
+    pub fn to_json(&self) -> String {
+        let args = self.args.iter().map(|a| format!("{{\"type\":\"binary\",\"value\":\"base64:{}\"}}", base64::encode(a))).collect::<Vec<_>>();
+        let payment = self.payment.map_or(String::new(), |amount| format!("{{\"amount\":{},\"assetId\":null}}", amount));
+        format!(
+            "{{\"type\":{},\"version\":{},\"chainId\":{},\"senderPublicKey\":\"{}\",\"dApp\":\"{}\",\"call\":{{\"function\":\"{}\",\"args\":[{}]}},\"payment\":[{}],\"fee\":{},\"feeAssetId\":null,\"timestamp\":{},\"proofs\":[]}}",
+            INVOKE_SCRIPT_TX_TYPE, INVOKE_SCRIPT_TX_VERSION, self.chain_id, self.sender_public_key, self.dapp,
+            self.function, args.join(","), payment, self.fee, self.timestamp
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod waves_tx_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr, G1, G2};
+    use pairing::CurveProjective;
+    use sapling_crypto::redjubjub::Signature;
+    use bellman::groth16::Proof;
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::bundle::{TRANSFER_PUBLIC_INPUTS, MAX_ENCRYPTED_NOTE_LEN, BUNDLE_VERSION};
+
+    const SENDER: &str = "5AzfA9UfpWVYiwFwvdr77k6LWupSTGLb14b24oVdEpMM";
+    const DAPP: &str = "3MtBqEtkF8cYXprDG4rhxjWWqfGUd5c1bgn";
+
+    fn bundle(note_len: usize) -> TransactionBundle<Bls12> {
+        let mut rng = OsRng::new().unwrap();
+        let sig_bytes = [0u8; 64];
+        TransactionBundle {
+            proof: Proof { a: rng.gen::<G1>().into_affine(), b: rng.gen::<G2>().into_affine(), c: rng.gen::<G1>().into_affine() },
+            public_inputs: (0..TRANSFER_PUBLIC_INPUTS).map(|_| rng.gen::<Fr>()).collect(),
+            encrypted_notes: vec![vec![1; note_len], vec![2; note_len]],
+            out_ciphertexts: vec![[0; 24], [0; 24]],
+            binding_sig: Signature::read(&sig_bytes[..]).unwrap(),
+            spend_auth_sig: Signature::read(&sig_bytes[..]).unwrap(),
+            fee: 10
+        }
+    }
+
+    #[test]
+    fn test_invoke_transfer() {
+        let b = bundle(100);
+        let tx = InvokeScript::transfer(&b, SENDER, DAPP, b'T', Some(10000000), 900000, 1600000000000).unwrap();
+        assert!(tx.args[0].len() == 192 && tx.args[1].len() == 32 * TRANSFER_PUBLIC_INPUTS);
+
+        let mut joined = vec![BUNDLE_VERSION];
+        joined.extend_from_slice(&tx.args[0]);
+        joined.extend_from_slice(&(TRANSFER_PUBLIC_INPUTS as u32).to_be_bytes());
+        joined.extend_from_slice(&tx.args[1]);
+        joined.extend_from_slice(&tx.args[2]);
+        assert!(joined == b.to_bytes(), "Arguments should give the bundle back");
+
+        let json = tx.to_json();
+        assert!(json.starts_with("{\"type\":16,\"version\":1,\"chainId\":84,"));
+        assert!(json.contains("\"function\":\"transferExternal\"") && json.contains(&format!("\"value\":\"base64:{}\"", base64::encode(&tx.args[0]))));
+        assert!(json.contains("\"payment\":[{\"amount\":10000000,\"assetId\":null}]"));
+        assert!(tx.verify_proof(&[0; 48 * 8]).is_err(), "Key without inputs should not verify the call");
+
+        assert!(InvokeScript::transfer(&bundle(MAX_ENCRYPTED_NOTE_LEN), SENDER, DAPP, b'T', None, 900000, 0).is_ok(), "Largest bundle should fit");
+        let mut big = tx.clone();
+        big.args[2].resize(MAX_INVOKE_TX_SIZE, 0);
+        assert!(big.validate().unwrap_err().to_string().ends_with("at most 5120 allowed"), "Oversized transaction should be rejected");
+        assert!(InvokeScript::transfer(&b, &SENDER[..32], DAPP, b'T', None, 900000, 0).is_err(), "Wrong public key should be rejected");
+        assert!(InvokeScript::transfer(&b, SENDER, "3MtBqEtkF8cYXprDG4rhxjWWqfGUd5c1bg0", b'T', None, 900000, 0).is_err());
+    }
+
+    #[test]
+    fn test_base58() {
+        assert!(base58_encode(&base58_decode(DAPP).unwrap()) == DAPP);
+        assert!(base58_encode(&[0, 0, 1]) == "112" && base58_decode("112").unwrap() == vec![0, 0, 1]);
+        assert!(base58_encode(&[]) == "" && base58_decode("0OIl").is_err());
+    }
+}
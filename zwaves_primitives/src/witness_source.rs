@@ -0,0 +1,110 @@
+use sapling_crypto::jubjub::JubjubEngine;
+
+use std::io;
+
+use crate::pedersen_hasher::{merkle_root_checked, MERKLE_MAX_DEPTH};
+use crate::transactions::{note_hash, NoteData};
+
+
+// Wallet state asked for one piece at a time, for a host which keeps the state itself (the js
+// wallet under wasm) and would otherwise serialize all of it up front. A builder fetches the
+// note and the path of every input it spends and nothing else. Whatever the host returns is
+// checked against the root, so stale or wrong data is an error here and not an unsatisfied
+// circuit later.
+pub trait WitnessSource<E: JubjubEngine> {
+    fn note(&mut self, index: u64) -> io::Result<NoteData<E>>;
+    fn merkle_path(&mut self, index: u64) -> io::Result<Vec<E::Fr>>;
+}
+
+#[derive(Clone)]
+pub struct SpendWitness<E: JubjubEngine> {
+    pub note: NoteData<E>,
+    pub sibling: Vec<E::Fr>,
+    pub index: u64
+}
+
+pub fn spend_witness<E: JubjubEngine, S: WitnessSource<E>>(
+    source: &mut S,
+    index: u64,
+    depth: usize,
+    root: &E::Fr,
+    params: &E::Params
+) -> io::Result<SpendWitness<E>> {
+    if depth > MERKLE_MAX_DEPTH || index >> depth != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("no leaf {} in a tree of depth {}", index, depth)));
+    }
+    let note = source.note(index)?;
+    let sibling = source.merkle_path(index)?;
+    if merkle_root_checked::<E>(&sibling, index, &note_hash(&note, params)?, depth, params)? != *root {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("merkle path of note {} does not lead to the root", index)));
+    }
+    Ok(SpendWitness { note, sibling, index })
+}
+
+// The inputs of a transfer in order, each fetched only when it is built
+pub fn spend_witnesses<E: JubjubEngine, S: WitnessSource<E>>(
+    source: &mut S,
+    indices: &[u64],
+    depth: usize,
+    root: &E::Fr,
+    params: &E::Params
+) -> io::Result<Vec<SpendWitness<E>>> {
+    indices.iter().map(|&i| spend_witness(source, i, depth, root, params)).collect()
+}
+
+
+#[cfg(test)]
+mod witness_source_tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::os::OsRng;
+    use rand::Rng;
+
+    use crate::pedersen_hasher::JUBJUB_PARAMS;
+    use crate::tree::MerkleTree;
+    use crate::transactions::note_hash;
+    use crate::field;
+
+    struct Wallet {
+        notes: Vec<NoteData<Bls12>>,
+        tree: MerkleTree<Bls12>,
+        requests: Vec<u64>
+    }
+
+    impl WitnessSource<Bls12> for Wallet {
+        fn note(&mut self, index: u64) -> io::Result<NoteData<Bls12>> {
+            self.requests.push(index);
+            self.notes.get(index as usize).cloned().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no note"))
+        }
+
+        fn merkle_path(&mut self, index: u64) -> io::Result<Vec<Fr>> {
+            Ok(self.tree.proof(index))
+        }
+    }
+
+    #[test]
+    fn test_spend_witnesses() {
+        let params = &*JUBJUB_PARAMS;
+        let mut rng = OsRng::new().unwrap();
+        let notes = (0..6).map(|_| NoteData::<Bls12> { asset_id: field::from_u64(rng.gen::<u32>() as u64), amount: field::from_u64(rng.gen()), native_amount: field::from_u64(rng.gen()), txid: rng.gen(), owner: rng.gen() }).collect::<Vec<_>>();
+        let mut tree = MerkleTree::<Bls12>::new(8, params);
+        tree.push_many(&notes.iter().map(|n| note_hash(n, params).unwrap()).collect::<Vec<_>>(), params).unwrap();
+        let root = tree.root();
+        let mut wallet = Wallet { notes, tree, requests: vec![] };
+
+        let res = spend_witnesses(&mut wallet, &[4, 1], 8, &root, params).unwrap();
+        assert!(res.len() == 2 && res[0].index == 4 && res[1].note.txid == wallet.notes[1].txid);
+        assert!(wallet.requests == vec![4, 1], "Only the spent notes should be fetched");
+
+        assert!(spend_witness(&mut wallet, 2, 7, &root, params).is_err(), "Path of another depth should be rejected");
+        assert!(spend_witness(&mut wallet, 2, 8, &rng.gen(), params).is_err(), "Path to another root should be rejected");
+        assert!(spend_witness(&mut wallet, 1 << 8, 8, &root, params).is_err());
+        assert!(spend_witness(&mut wallet, 7, 8, &root, params).is_err(), "Missing note should be an error");
+
+        wallet.requests.clear();
+        assert!(spend_witness(&mut wallet, 2, MERKLE_MAX_DEPTH + 1, &root, params).is_err());
+        assert!(wallet.requests.is_empty(), "Too deep a tree should be rejected before fetching");
+        assert!(spend_witness(&mut wallet, 2, MERKLE_MAX_DEPTH, &root, params).is_err(), "Path of another depth should be rejected");
+        assert!(wallet.requests == vec![2], "The deepest tree should be accepted as merkle_root_checked does");
+    }
+}
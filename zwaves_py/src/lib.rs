@@ -0,0 +1,254 @@
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::{PyBytes, PyLong};
+
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+use sapling_crypto::pedersen_hash::Personalization;
+
+use zwaves_primitives::{pedersen_hasher, verifier};
+use zwaves_primitives::pedersen_hasher::{JUBJUB_PARAMS, MERKLE_MAX_DEPTH};
+use zwaves_primitives::serialization::read_fr_repr_be;
+use zwaves_primitives::transactions::{self, NoteData};
+use zwaves_primitives::point::read_proof;
+
+use std::convert::TryFrom;
+
+
+// Field elements are python ints in [0, r), keys and proofs are bytes in the same encoding as
+// the node bindings, so test vectors can be moved between both.
+
+fn read_fr(x: &Bound<PyAny>) -> PyResult<Fr> {
+    let err = || PyValueError::new_err("field element should be an int in [0, r)");
+    let data = x.downcast::<PyLong>()?.call_method1("to_bytes", (32, "big")).map_err(|_| err())?;
+    let repr = read_fr_repr_be::<Fr>(data.downcast::<PyBytes>()?.as_bytes()).map_err(|_| err())?;
+    Fr::from_repr(repr).map_err(|_| err())
+}
+
+fn fr_to_py(py: Python, x: &Fr) -> PyResult<PyObject> {
+    let mut data = vec![];
+    x.into_repr().write_be(&mut data).unwrap();
+    Ok(py.get_type_bound::<PyLong>().call_method1("from_bytes", (PyBytes::new_bound(py, &data), "big"))?.unbind())
+}
+
+fn check_level(level: usize) -> PyResult<()> {
+    if level >= MERKLE_MAX_DEPTH {
+        return Err(PyValueError::new_err(format!("merkle level should be less than {}", MERKLE_MAX_DEPTH)));
+    }
+    Ok(())
+}
+
+
+#[pyfunction]
+fn hash(py: Python, x: &Bound<PyAny>) -> PyResult<PyObject> {
+    fr_to_py(py, &pedersen_hasher::hash::<Bls12>(&read_fr(x)?, pedersen_hasher::HASH_PERSONALIZATION, &JUBJUB_PARAMS))
+}
+
+#[pyfunction]
+fn merkle_hash(py: Python, left: &Bound<PyAny>, right: &Bound<PyAny>, level: usize) -> PyResult<PyObject> {
+    let personalization = pedersen_hasher::merkle_personalization(level).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let res = pedersen_hasher::compress::<Bls12>(&read_fr(left)?, &read_fr(right)?, personalization, &JUBJUB_PARAMS);
+    fr_to_py(py, &res)
+}
+
+#[pyfunction]
+fn merkle_root(py: Python, sibling: Vec<Bound<PyAny>>, index: u64, leaf: &Bound<PyAny>) -> PyResult<PyObject> {
+    if sibling.len() > MERKLE_MAX_DEPTH || index >> sibling.len() != 0 {
+        return Err(PyValueError::new_err("index should be less than 2^len(sibling)"));
+    }
+    let sibling = sibling.iter().map(read_fr).collect::<PyResult<Vec<_>>>()?;
+    fr_to_py(py, &pedersen_hasher::merkle_root::<Bls12>(&sibling, index, &read_fr(leaf)?, &JUBJUB_PARAMS))
+}
+
+// sibling is flat, the three other children of every 4-ary node left to right
+#[pyfunction]
+fn merkle_root4(py: Python, sibling: Vec<Bound<PyAny>>, index: u64, leaf: &Bound<PyAny>) -> PyResult<PyObject> {
+    let depth = sibling.len() / 3;
+    if sibling.len() % 3 != 0 || depth > pedersen_hasher::MERKLE4_MAX_DEPTH || index >> (2 * depth) != 0 {
+        return Err(PyValueError::new_err("sibling should have 3 elements per level and index should be less than 4^levels"));
+    }
+    let sibling = sibling.iter().map(read_fr).collect::<PyResult<Vec<_>>>()?;
+    let sibling = sibling.chunks(3).map(|c| [c[0], c[1], c[2]]).collect::<Vec<_>>();
+    let root = pedersen_hasher::merkle_root4::<Bls12>(&sibling, index, &read_fr(leaf)?, &JUBJUB_PARAMS).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    fr_to_py(py, &root)
+}
+
+#[pyfunction]
+fn empty_root(py: Python, level: usize) -> PyResult<PyObject> {
+    if level > MERKLE_MAX_DEPTH {
+        return Err(PyValueError::new_err(format!("empty root is defined up to level {}", MERKLE_MAX_DEPTH)));
+    }
+    fr_to_py(py, &pedersen_hasher::empty_root(level))
+}
+
+#[pyfunction]
+fn note_hash(py: Python, asset_id: &Bound<PyAny>, amount: &Bound<PyAny>, native_amount: &Bound<PyAny>, txid: &Bound<PyAny>, owner: &Bound<PyAny>) -> PyResult<PyObject> {
+    let note = NoteData::<Bls12> {
+        asset_id: read_fr(asset_id)?,
+        amount: read_fr(amount)?,
+        native_amount: read_fr(native_amount)?,
+        txid: read_fr(txid)?,
+        owner: read_fr(owner)?
+    };
+    fr_to_py(py, &transactions::note_hash(&note, &JUBJUB_PARAMS).map_err(|e| PyValueError::new_err(e.to_string()))?)
+}
+
+// vk is the truncated verifying key, as returned by extract_vk of the node bindings
+#[pyfunction]
+fn verify(vk: &[u8], proof: &[u8], inputs: Vec<Bound<PyAny>>) -> PyResult<bool> {
+    let tvk = verifier::TruncatedVerifyingKey::<Bls12>::read(vk).map_err(|e| PyValueError::new_err(format!("wrong verifying key: {}", e)))?;
+    let proof = read_proof::<Bls12, _>(proof, true).map_err(|e| PyValueError::new_err(format!("wrong proof: {}", e)))?;
+    let inputs = inputs.iter().map(read_fr).collect::<PyResult<Vec<_>>>()?;
+    verifier::verify_proof(&tvk, &proof, &inputs).map_err(|e| PyValueError::new_err(format!("{}", e)))
+}
+
+
+// Returned for every leaf of push_many, like InsertReceipt of the node bindings. The proof is
+// taken from the same state as the root; for a 4-ary tree it is a 4-ary proof.
+#[pyclass]
+struct InsertReceipt {
+    #[pyo3(get)]
+    position: u64,
+    root: Fr,
+    proof: Vec<Fr>
+}
+
+#[pymethods]
+impl InsertReceipt {
+    #[getter]
+    fn root(&self, py: Python) -> PyResult<PyObject> {
+        fr_to_py(py, &self.root)
+    }
+
+    #[getter]
+    fn proof(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        self.proof.iter().map(|x| fr_to_py(py, x)).collect()
+    }
+}
+
+
+// Same layout as MerkleTree of the node bindings: one row per level, missing cells are empty roots.
+// A 4-ary tree of proof_length levels is stored as the binary tree of 2 * proof_length rows,
+// see pedersen_hasher::compress4; only its proofs differ.
+#[pyclass]
+struct MerkleTree {
+    proof_length: usize,
+    arity: usize,
+    state: Vec<Vec<Fr>>
+}
+
+impl MerkleTree {
+    fn depth(&self) -> usize {
+        self.state.len() - 1
+    }
+
+    fn get(&self, row: usize, index: u64) -> Fr {
+        match usize::try_from(index).ok().and_then(|i| self.state[row].get(i)) {
+            Some(x) => *x,
+            None => pedersen_hasher::empty_root(row)
+        }
+    }
+
+    fn proof_fr(&self, index: u64) -> Vec<Fr> {
+        if self.arity == 4 {
+            // the other children of the node above row 2i, i.e. the siblings in row 2i
+            return (0..self.proof_length).flat_map(|i| {
+                let node = index >> (2 * i);
+                (0..4).filter(move |&k| k != node & 3).map(move |k| (2 * i, node & !3 | k))
+            }).map(|(row, j)| self.get(row, j)).collect();
+        }
+        (0..self.proof_length).map(|i| self.get(i, (index >> i) ^ 1)).collect()
+    }
+
+    fn check_index(&self, index: u64) -> PyResult<()> {
+        if index >> self.depth() != 0 {
+            return Err(PyValueError::new_err("index should be less than arity^proof_length"));
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl MerkleTree {
+    #[new]
+    #[pyo3(signature = (proof_length, arity=2))]
+    fn new(proof_length: usize, arity: usize) -> PyResult<Self> {
+        let depth = match arity {
+            2 => proof_length,
+            4 => proof_length * 2,
+            _ => return Err(PyValueError::new_err("arity should be 2 or 4"))
+        };
+        check_level(depth)?;
+        Ok(MerkleTree { proof_length, arity, state: vec![vec![]; depth + 1] })
+    }
+
+    #[getter]
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    #[getter]
+    fn proof_length(&self) -> usize {
+        self.proof_length
+    }
+
+    fn size(&self) -> u64 {
+        self.state[0].len() as u64
+    }
+
+    fn push_many(&mut self, leaves: Vec<Bound<PyAny>>) -> PyResult<Vec<InsertReceipt>> {
+        let leaves = leaves.iter().map(read_fr).collect::<PyResult<Vec<_>>>()?;
+        if leaves.is_empty() {
+            return Ok(vec![]);
+        }
+        let start = self.size();
+        let end = start + leaves.len() as u64;
+        if end > 1 << self.depth() {
+            return Err(PyValueError::new_err("tree is full"));
+        }
+        self.state[0].extend(leaves);
+
+        for i in 1..=self.depth() {
+            let (from, to) = (start >> i, (end - 1) >> i);
+            self.state[i].resize(to as usize + 1, Fr::zero());
+            for j in from..=to {
+                let node = pedersen_hasher::compress::<Bls12>(&self.get(i - 1, j * 2), &self.get(i - 1, j * 2 + 1), Personalization::MerkleTree(i - 1), &JUBJUB_PARAMS);
+                self.state[i][j as usize] = node;
+            }
+        }
+
+        let root = self.get(self.depth(), 0);
+        Ok((start..end).map(|position| InsertReceipt { position, root, proof: self.proof_fr(position) }).collect())
+    }
+
+    fn cell(&self, py: Python, row: usize, index: u64) -> PyResult<PyObject> {
+        if row > self.depth() {
+            return Err(PyValueError::new_err("too big row"));
+        }
+        fr_to_py(py, &self.get(row, index))
+    }
+
+    fn root(&self, py: Python) -> PyResult<PyObject> {
+        fr_to_py(py, &self.get(self.depth(), 0))
+    }
+
+    fn proof(&self, py: Python, index: u64) -> PyResult<Vec<PyObject>> {
+        self.check_index(index)?;
+        self.proof_fr(index).iter().map(|x| fr_to_py(py, x)).collect()
+    }
+}
+
+
+#[pymodule]
+fn zwaves(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(hash, m)?)?;
+    m.add_function(wrap_pyfunction!(merkle_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(merkle_root, m)?)?;
+    m.add_function(wrap_pyfunction!(merkle_root4, m)?)?;
+    m.add_function(wrap_pyfunction!(empty_root, m)?)?;
+    m.add_function(wrap_pyfunction!(note_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_class::<MerkleTree>()?;
+    m.add_class::<InsertReceipt>()?;
+    Ok(())
+}
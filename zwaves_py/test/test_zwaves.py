@@ -0,0 +1,61 @@
+import random
+
+import zwaves
+
+
+r = 52435875175126190479447740508185965837690552500527637822603658699938581184513
+
+
+def raises(f, *args):
+    try:
+        f(*args)
+    except (ValueError, TypeError, OverflowError):
+        return True
+    return False
+
+
+mt = zwaves.MerkleTree(48)
+assert mt.root() == zwaves.empty_root(48)
+for i in range(48):
+    assert zwaves.merkle_hash(zwaves.empty_root(i), zwaves.empty_root(i), i) == zwaves.empty_root(i + 1)
+
+leaves = [random.randrange(r) for _ in range(37)]
+mt.push_many(leaves[:10])
+mt.push_many(leaves[10:])
+assert mt.size() == 37
+
+for i in [0, 9, 10, 36]:
+    assert zwaves.merkle_root(mt.proof(i), i, leaves[i]) == mt.root(), "proof should lead to the root"
+assert zwaves.merkle_root(mt.proof(37), 37, 0) == mt.root(), "empty leaves should be provable"
+
+receipts = zwaves.MerkleTree(48).push_many(leaves[:5])
+assert [x.position for x in receipts] == list(range(5))
+assert all(zwaves.merkle_root(x.proof, x.position, leaves[x.position]) == x.root for x in receipts), "receipt proof should lead to its root"
+
+other = zwaves.MerkleTree(48)
+other.push_many(leaves)
+assert other.root() == mt.root(), "root should not depend on how leaves are pushed"
+
+mt4 = zwaves.MerkleTree(24, 4)
+mt4.push_many(leaves)
+assert mt4.root() == mt.root(), "4-ary tree should have the root of the binary tree of double depth"
+for i in [0, 9, 10, 36, 37]:
+    proof = mt4.proof(i)
+    assert len(proof) == 72
+    assert zwaves.merkle_root4(proof, i, leaves[i] if i < 37 else 0) == mt4.root(), "4-ary proof should lead to the root"
+receipt = mt4.push_many([7])[0]
+assert receipt.position == 37 and receipt.root == mt4.root()
+assert zwaves.merkle_root4(receipt.proof, 37, 7) == receipt.root, "4-ary tree should return 4-ary proofs"
+assert raises(zwaves.MerkleTree, 4, 3), "only arity 2 and 4 should be supported"
+assert raises(zwaves.merkle_root4, mt4.proof(0)[:4], 0, 0), "4-ary proof should have 3 siblings per level"
+
+x = random.randrange(r)
+assert zwaves.hash(x) != x
+assert zwaves.note_hash(0, 1, 2, 3, 4) != zwaves.note_hash(0, 1, 2, 3, 5)
+
+assert raises(zwaves.hash, r), "elements out of the field should be rejected"
+assert raises(zwaves.hash, -1)
+assert raises(zwaves.merkle_root, mt.proof(0)[:3], 8, 0), "index out of the tree should be rejected"
+assert raises(zwaves.verify, b"", b"", []), "malformed verifying key should be rejected"
+
+print("ok")
@@ -0,0 +1,17 @@
+use pairing::bls12_381::Bls12;
+
+use std::fs::File;
+use std::io;
+
+use zwaves_circuit::bench::bench_parameters;
+use zwaves_primitives::envelope::wrap_parameters;
+
+
+// Writes the enveloped parameters of the proving benches, for bench(now, params) of zwaves_wasm,
+// which can not generate them itself. They are the same on every run.
+//
+// usage: bench_params params_file
+fn main() -> std::io::Result<()> {
+    let path = std::env::args().nth(1).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "usage: bench_params params_file"))?;
+    wrap_parameters::<Bls12>(&bench_parameters())?.write(File::create(path)?)
+}
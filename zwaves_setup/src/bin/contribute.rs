@@ -6,7 +6,7 @@ use rand::Rng;
 
 use std::fs::File;
 use std::io::{Write, Read};
-use zwaves_circuit::circuit::{UtxoAccumulator, Transfer};
+use zwaves_circuit::circuit::{UtxoAccumulator, Transfer, Blank};
 
 use hex::encode;
 
@@ -19,13 +19,7 @@ fn main() -> std::io::Result<()> {
     let mut params = phase2::MPCParameters::read(&params_file, true)?;
     drop(params_file);
 
-    let contributions = params.verify(UtxoAccumulator::<Bls12> {
-        params: &jubjub_params,
-        note_hashes: [None, None],
-        index: None,
-        old_proof: None,
-        new_proof: None
-    }).expect("parameters should be valid!");
+    let contributions = params.verify(UtxoAccumulator::<Bls12>::blank(&jubjub_params)).expect("parameters should be valid!");
 
 
     let hash = params.contribute(rng);
@@ -41,16 +35,7 @@ fn main() -> std::io::Result<()> {
     let mut params = phase2::MPCParameters::read(&params_file, true)?;
     drop(params_file);
 
-    let contributions = params.verify(Transfer::<Bls12> {
-        params: &jubjub_params,
-        receiver: None,
-        in_note: [None, None],
-        out_note: [None, None],
-        in_proof: [None, None],
-        root_hash: None,
-        sk: None,
-        packed_asset:None,
-    }).expect("parameters should be valid!");
+    let contributions = params.verify(Transfer::<Bls12>::blank(&jubjub_params)).expect("parameters should be valid!");
 
 
     let hash = params.contribute(rng);
@@ -0,0 +1,26 @@
+use sapling_crypto::jubjub::JubjubBls12;
+use pairing::bls12_381::Bls12;
+
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use zwaves_primitives::generators::{sapling_derivations, write_transcript, read_transcript, check_sapling_params};
+
+
+// Prints the derivation transcript of every Jubjub generator, or checks a published one
+// against the parameters built into this binary.
+//
+// usage: generators [transcript_file]
+fn main() -> std::io::Result<()> {
+    let jubjub_params = JubjubBls12::new();
+
+    match std::env::args().nth(1) {
+        None => write_transcript(&sapling_derivations::<Bls12>(&jubjub_params), io::stdout()),
+        Some(path) => {
+            let derivations = read_transcript(BufReader::new(File::open(path)?))?;
+            check_sapling_params::<Bls12>(&derivations, &jubjub_params)?;
+            println!("{} generators verified", derivations.len());
+            Ok(())
+        }
+    }
+}
@@ -6,7 +6,7 @@ use pairing::bls12_381::{Bls12, Fr};
 use std::fs::File;
 use std::io::Read;
 
-use zwaves_circuit::circuit::{Transfer, UtxoAccumulator};
+use zwaves_circuit::circuit::{Transfer, UtxoAccumulator, Blank};
 use hex::encode;
 
 
@@ -18,13 +18,7 @@ fn main() -> std::io::Result<()> {
     let mut params = phase2::MPCParameters::read(&params_file, true)?;
     drop(params_file);
 
-    let contributions = params.verify(UtxoAccumulator::<Bls12> {
-        params: &jubjub_params,
-        note_hashes: [None, None],
-        index: None,
-        old_proof: None,
-        new_proof: None
-    }).expect("parameters should be valid!");
+    let contributions = params.verify(UtxoAccumulator::<Bls12>::blank(&jubjub_params)).expect("parameters should be valid!");
 
     println!("List of all contributions:");
 
@@ -38,16 +32,7 @@ fn main() -> std::io::Result<()> {
     let mut params = phase2::MPCParameters::read(&params_file, true)?;
     drop(params_file);
 
-    let contributions = params.verify(Transfer::<Bls12> {
-        params: &jubjub_params,
-        receiver: None,
-        in_note: [None, None],
-        out_note: [None, None],
-        in_proof: [None, None],
-        root_hash: None,
-        packed_asset: None,
-        sk: None
-    }).expect("parameters should be valid!");
+    let contributions = params.verify(Transfer::<Bls12>::blank(&jubjub_params)).expect("parameters should be valid!");
 
     println!("List of all contributions:");
 
@@ -3,7 +3,7 @@ use pairing::bls12_381::{Bls12, Fr};
 use std::fs::File;
 use std::io::Write;
 
-use zwaves_circuit::circuit::{UtxoAccumulator, Transfer};
+use zwaves_circuit::circuit::{UtxoAccumulator, Transfer, Blank};
 
 
 
@@ -12,28 +12,13 @@ use zwaves_circuit::circuit::{UtxoAccumulator, Transfer};
 fn main() -> std::io::Result<()> {
     let jubjub_params = JubjubBls12::new();
 /*
-    let params = phase2::MPCParameters::new(UtxoAccumulator::<Bls12> {
-        params: &jubjub_params,
-        note_hashes: [None, None],
-        index: None,
-        old_proof: None,
-        new_proof: None
-    }).unwrap();
+    let params = phase2::MPCParameters::new(UtxoAccumulator::<Bls12>::blank(&jubjub_params)).unwrap();
 
     let params_file = File::create("mpc_params_accumulator")?;
     params.write(params_file)?;*/
 
 
-    let params = phase2::MPCParameters::new(Transfer::<Bls12> {
-        params: &jubjub_params,
-        receiver: None,
-        in_note: [None, None],
-        out_note: [None, None],
-        in_proof: [None, None],
-        root_hash: None,
-        packed_asset: None,
-        sk: None
-    }).unwrap();
+    let params = phase2::MPCParameters::new(Transfer::<Bls12>::blank(&jubjub_params)).unwrap();
 
     let params_file = File::create("mpc_params_transfer")?;
     params.write(params_file)?;
@@ -0,0 +1,30 @@
+use pairing::bls12_381::Fr;
+
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use zwaves_primitives::poseidon_params::{generate, verify, write_transcript, read_transcript};
+
+
+// Prints the Poseidon constants of (t, RF, RP) over the scalar field of BLS12-381, or checks
+// that a constants file is the output of the generator.
+//
+// usage: poseidon t full_rounds partial_rounds
+//        poseidon constants_file
+fn main() -> io::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.len() {
+        3 => {
+            let n = args.iter().map(|s| s.parse::<usize>().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("wrong number: {}", s))))
+                .collect::<io::Result<Vec<_>>>()?;
+            write_transcript(&generate::<Fr>(n[0], n[1], n[2])?, io::stdout())
+        },
+        1 => {
+            let constants = read_transcript::<Fr, _>(BufReader::new(File::open(&args[0])?))?;
+            verify(&constants)?;
+            println!("constants of t = {}, RF = {}, RP = {} verified", constants.t, constants.full_rounds, constants.partial_rounds);
+            Ok(())
+        },
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "usage: poseidon t full_rounds partial_rounds | poseidon constants_file"))
+    }
+}
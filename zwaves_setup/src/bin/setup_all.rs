@@ -0,0 +1,43 @@
+use std::fs::{self, File};
+use std::io::Write;
+
+use zwaves_circuit::setup::{protocol_circuits, setup_all, verify_report};
+use zwaves_primitives::envelope::{wrap_parameters, wrap_verifying_key};
+use zwaves_primitives::verifier::truncate_verifying_key;
+
+
+// Deterministic parameters for every protocol circuit, for testnets and release rehearsals.
+// Writes params_<name> and vk_<name> in the same format as split, plus the manifest and the
+// setup report. With --verify, reruns the setup and checks a published report instead.
+//
+// usage: setup_all <seed> [--verify <report>]
+fn main() -> std::io::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let usage = || std::io::Error::new(std::io::ErrorKind::InvalidInput, "usage: setup_all <seed> [--verify <report>]");
+    let seed = args.get(1).ok_or_else(usage)?;
+
+    if args.len() > 2 {
+        if args.len() != 4 || args[2] != "--verify" {
+            return Err(usage());
+        }
+        let report = verify_report(&fs::read_to_string(&args[3])?, &protocol_circuits(), seed.as_bytes())?;
+        println!("report is reproduced, it was made with {}", report.toolchain);
+        return Ok(());
+    }
+
+    let output = setup_all(&protocol_circuits(), seed.as_bytes())?;
+
+    for c in output.circuits.iter() {
+        let mut f = File::create(format!("params_{}", c.name))?;
+        f.write_all(&wrap_parameters(&c.params)?.to_bytes()?)?;
+
+        let mut f = File::create(format!("vk_{}", c.name))?;
+        f.write_all(&wrap_verifying_key(&truncate_verifying_key(&c.params.vk))?.to_bytes()?)?;
+    }
+
+    let manifest = output.manifest();
+    File::create("manifest")?.write_all(manifest.as_bytes())?;
+    File::create("report")?.write_all(output.report().to_text().as_bytes())?;
+    print!("{}", manifest);
+    Ok(())
+}
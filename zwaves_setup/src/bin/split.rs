@@ -0,0 +1,49 @@
+use sapling_crypto::jubjub::JubjubBls12;
+use pairing::bls12_381::Bls12;
+
+use std::fs::File;
+use std::io::Write;
+
+use zwaves_circuit::circuit::{UtxoAccumulator, Transfer, Blank};
+use zwaves_primitives::envelope::{wrap_parameters, wrap_verifying_key};
+use zwaves_primitives::verifier::truncate_verifying_key;
+
+
+// Strips the MPC transcript from mpc_params_<name> and writes per-circuit artifacts:
+// params_<name> (proving parameters) and vk_<name> (truncated verifying key), both enveloped.
+// Clients only download the files for the circuits they use.
+//
+// usage: split [accumulator] [transfer]
+fn main() -> std::io::Result<()> {
+    let jubjub_params = JubjubBls12::new();
+
+    let mut names = std::env::args().skip(1).collect::<Vec<_>>();
+    if names.is_empty() {
+        names = vec!["accumulator".to_string(), "transfer".to_string()];
+    }
+
+    for name in names.iter() {
+        let params_file = File::open(format!("mpc_params_{}", name))?;
+        let params = phase2::MPCParameters::read(&params_file, true)?;
+        drop(params_file);
+
+        let verified = match name.as_str() {
+            "accumulator" => params.verify(UtxoAccumulator::<Bls12>::blank(&jubjub_params)),
+            "transfer" => params.verify(Transfer::<Bls12>::blank(&jubjub_params)),
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown circuit {}", name)))
+        };
+        verified.expect("parameters should be valid!");
+
+        let bellman_params = params.get_params();
+
+        let mut f = File::create(format!("params_{}", name))?;
+        f.write_all(&wrap_parameters(bellman_params)?.to_bytes()?)?;
+
+        let mut f = File::create(format!("vk_{}", name))?;
+        f.write_all(&wrap_verifying_key(&truncate_verifying_key(&bellman_params.vk))?.to_bytes()?)?;
+
+        println!("Split {}: params_{}, vk_{}", name, name, name);
+    }
+
+    Ok(())
+}
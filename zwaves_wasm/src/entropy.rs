@@ -0,0 +1,79 @@
+use js_sys::{Function, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+
+use zwaves_primitives::entropy::{EntropySource, SystemEntropy, SeededEntropy, MixedEntropy};
+
+use std::io;
+
+
+// crypto.getRandomValues of the host, what getrandom is in browsers and node. OsRng has nothing
+// to read on wasm32-unknown-unknown, so this is the system entropy of the module there.
+pub struct JsEntropy;
+
+// getRandomValues fills at most 65536 bytes per call
+const GET_RANDOM_VALUES_MAX: usize = 65536;
+
+impl EntropySource for JsEntropy {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> io::Result<()> {
+        let unavailable = || io::Error::new(io::ErrorKind::Other, "crypto.getRandomValues is not available, pass a seed");
+        let crypto = Reflect::get(&js_sys::global(), &JsValue::from_str("crypto")).ok()
+            .filter(|c| c.is_object())
+            .ok_or_else(unavailable)?;
+        let get_random_values = Reflect::get(&crypto, &JsValue::from_str("getRandomValues")).ok()
+            .and_then(|f| f.dyn_into::<Function>().ok())
+            .ok_or_else(unavailable)?;
+
+        for chunk in dest.chunks_mut(GET_RANDOM_VALUES_MAX) {
+            let buf = Uint8Array::new_with_length(chunk.len() as u32);
+            get_random_values.call1(&crypto, &buf).map_err(|_| unavailable())?;
+            buf.copy_to(chunk);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub type HostEntropy = JsEntropy;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type HostEntropy = SystemEntropy;
+
+
+// The randomness of one call: the host's, a seed alone for hosts without entropy, or a seed
+// mixed with the host's. A seed alone must be fresh and secret for every call.
+pub enum Entropy {
+    Host(HostEntropy),
+    Seeded(SeededEntropy),
+    Mixed(MixedEntropy<HostEntropy>)
+}
+
+impl Entropy {
+    pub fn new(seed: Option<&[u8]>, mix: bool) -> io::Result<Self> {
+        match seed {
+            Some([]) => Err(io::Error::new(io::ErrorKind::InvalidInput, "seed should not be empty")),
+            Some(s) if mix => Ok(Entropy::Mixed(MixedEntropy::with_source(s, host_entropy()))),
+            Some(s) => Ok(Entropy::Seeded(SeededEntropy::new(s))),
+            None => Ok(Entropy::Host(host_entropy()))
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn host_entropy() -> HostEntropy {
+    JsEntropy
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn host_entropy() -> HostEntropy {
+    SystemEntropy
+}
+
+impl EntropySource for Entropy {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> io::Result<()> {
+        match self {
+            Entropy::Host(e) => e.fill_bytes(dest),
+            Entropy::Seeded(e) => e.fill_bytes(dest),
+            Entropy::Mixed(e) => e.fill_bytes(dest)
+        }
+    }
+}
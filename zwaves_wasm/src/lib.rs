@@ -0,0 +1,377 @@
+use wasm_bindgen::prelude::*;
+use js_sys::{Function, Reflect, Uint8Array};
+
+use pairing::bls12_381::{Bls12, Fr};
+use pairing::{Field, PrimeField, PrimeFieldRepr};
+
+use zwaves_primitives::{pedersen_hasher, verifier, envelope};
+use zwaves_primitives::pedersen_hasher::{JUBJUB_PARAMS, MERKLE_MAX_DEPTH};
+use zwaves_primitives::serialization::read_fr_repr_be;
+use zwaves_primitives::transactions::{self, NoteData};
+use zwaves_primitives::point::read_proof;
+use zwaves_primitives::witness_source::{WitnessSource, SpendWitness, spend_witness as build_spend_witness};
+use zwaves_primitives::entropy::EntropySource;
+use zwaves_primitives::metrics::{self, Counter, Timing};
+use zwaves_primitives::memory::{self, CountingAllocator};
+use zwaves_circuit::bench as circuit_bench;
+use zwaves_circuit::circuit::{Transfer, NotePath, MERKLE_PROOF_LEN};
+use zwaves_circuit::prover::{self, Threads, ProverScratch};
+use zwaves_circuit::validate::validate_witness;
+
+use std::alloc::System;
+use std::io;
+#[cfg(feature = "threads")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub mod rpc;
+pub mod entropy;
+
+
+// Field elements are 32 byte big-endian arrays in [0, r), lists of them are flat, keys and
+// proofs are bytes in the same encoding as the node and python bindings, so test vectors can be
+// moved between all of them. The bindings are thin wrappers over the io::Result functions below,
+// which are also what the tests call: JsValue is not available out of wasm.
+
+pub fn read_fr(data: &[u8]) -> io::Result<Fr> {
+    let err = || io::Error::new(io::ErrorKind::InvalidData, "field element should be 32 big-endian bytes in [0, r)");
+    if data.len() != 32 {
+        return Err(err());
+    }
+    let repr = read_fr_repr_be::<Fr>(data).map_err(|_| err())?;
+    Fr::from_repr(repr).map_err(|_| err())
+}
+
+pub fn read_fr_list(data: &[u8]) -> io::Result<Vec<Fr>> {
+    if data.len() % 32 != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "list of field elements should be 32 bytes per element"));
+    }
+    data.chunks(32).map(read_fr).collect()
+}
+
+// asset_id | amount | native_amount | txid | owner
+pub fn read_note(data: &[u8]) -> io::Result<NoteData<Bls12>> {
+    if data.len() != 5 * 32 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "note should be 5 field elements"));
+    }
+    let f = read_fr_list(data)?;
+    Ok(NoteData { asset_id: f[0], amount: f[1], native_amount: f[2], txid: f[3], owner: f[4] })
+}
+
+pub fn write_fr(x: &Fr) -> Vec<u8> {
+    let mut data = vec![];
+    x.into_repr().write_be(&mut data).unwrap();
+    data
+}
+
+fn js_err(e: io::Error) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+
+pub fn hash_native(x: &[u8]) -> io::Result<Vec<u8>> {
+    Ok(write_fr(&pedersen_hasher::hash::<Bls12>(&read_fr(x)?, pedersen_hasher::HASH_PERSONALIZATION, &JUBJUB_PARAMS)))
+}
+
+pub fn merkle_hash_native(left: &[u8], right: &[u8], level: usize) -> io::Result<Vec<u8>> {
+    let personalization = pedersen_hasher::merkle_personalization(level)?;
+    Ok(write_fr(&pedersen_hasher::compress::<Bls12>(&read_fr(left)?, &read_fr(right)?, personalization, &JUBJUB_PARAMS)))
+}
+
+pub fn merkle_root_native(sibling: &[u8], index: u64, leaf: &[u8]) -> io::Result<Vec<u8>> {
+    let sibling = read_fr_list(sibling)?;
+    if sibling.len() > MERKLE_MAX_DEPTH || index >> sibling.len() != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "index should be less than 2^len(sibling)"));
+    }
+    Ok(write_fr(&pedersen_hasher::merkle_root::<Bls12>(&sibling, index, &read_fr(leaf)?, &JUBJUB_PARAMS)))
+}
+
+pub fn empty_root_native(level: usize) -> io::Result<Vec<u8>> {
+    if level > MERKLE_MAX_DEPTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("empty root is defined up to level {}", MERKLE_MAX_DEPTH)));
+    }
+    Ok(write_fr(&pedersen_hasher::empty_root(level)))
+}
+
+pub fn note_hash_native(asset_id: &[u8], amount: &[u8], native_amount: &[u8], txid: &[u8], owner: &[u8]) -> io::Result<Vec<u8>> {
+    let note = NoteData::<Bls12> {
+        asset_id: read_fr(asset_id)?,
+        amount: read_fr(amount)?,
+        native_amount: read_fr(native_amount)?,
+        txid: read_fr(txid)?,
+        owner: read_fr(owner)?
+    };
+    Ok(write_fr(&transactions::note_hash(&note, &JUBJUB_PARAMS)?))
+}
+
+pub fn verify_native(vk: &[u8], proof: &[u8], inputs: &[u8]) -> io::Result<bool> {
+    let tvk = verifier::TruncatedVerifyingKey::<Bls12>::read(vk)?;
+    let proof = read_proof::<Bls12, _>(proof, true)?;
+    verifier::verify_proof(&tvk, &proof, &read_fr_list(inputs)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+}
+
+
+// note | sibling | index (u64 big-endian)
+pub fn write_spend_witness(w: &SpendWitness<Bls12>) -> Vec<u8> {
+    let mut res = vec![];
+    for x in [&w.note.asset_id, &w.note.amount, &w.note.native_amount, &w.note.txid, &w.note.owner].iter().cloned().chain(w.sibling.iter()) {
+        res.extend(write_fr(x));
+    }
+    res.extend_from_slice(&w.index.to_be_bytes());
+    res
+}
+
+
+// receiver | in_note[2] | in_proof_sibling[2] | in_proof_index[2] | out_note[2] | root_hash | sk | alpha | packed_asset,
+// the order of ZwavesTransfer in zwaves_ffi/include/zwaves.h. Notes are as read_note takes them, siblings
+// MERKLE_PROOF_LEN field elements each, indices u64 big-endian.
+const NOTE_LEN: usize = 5 * 32;
+const SIBLING_LEN: usize = MERKLE_PROOF_LEN * 32;
+pub const TRANSFER_LEN: usize = 32 + 2 * NOTE_LEN + 2 * SIBLING_LEN + 2 * 8 + 2 * NOTE_LEN + 4 * 32;
+
+pub fn read_transfer(data: &[u8]) -> io::Result<Transfer<'static, Bls12>> {
+    if data.len() != TRANSFER_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("transfer should be {} bytes", TRANSFER_LEN)));
+    }
+    let mut rest = data;
+    let mut take = |n: usize| {
+        let (head, tail) = rest.split_at(n);
+        rest = tail;
+        head
+    };
+
+    let receiver = read_fr(take(32))?;
+    let in_note = [Some(read_note(take(NOTE_LEN))?), Some(read_note(take(NOTE_LEN))?)];
+    let sibling = [read_fr_list(take(SIBLING_LEN))?, read_fr_list(take(SIBLING_LEN))?];
+    let mut in_proof = [None, None];
+    for (p, s) in in_proof.iter_mut().zip(sibling.iter()) {
+        let mut index = [0u8; 8];
+        index.copy_from_slice(take(8));
+        let mut path = [Fr::zero(); MERKLE_PROOF_LEN];
+        path.copy_from_slice(s);
+        *p = Some(NotePath::new(path, u64::from_be_bytes(index))?);
+    }
+    let out_note = [Some(read_note(take(NOTE_LEN))?), Some(read_note(take(NOTE_LEN))?)];
+
+    Ok(Transfer {
+        receiver: Some(receiver),
+        in_note,
+        in_proof,
+        out_note,
+        root_hash: Some(read_fr(take(32))?),
+        sk: Some(read_fr(take(32))?),
+        alpha: Some(read_fr(take(32))?),
+        packed_asset: Some(read_fr(take(32))?),
+        params: &JUBJUB_PARAMS
+    })
+}
+
+// Proves with bellman's parameters on the threads chosen by the caller, see zwaves_circuit::prover
+pub fn prove_transfer_native<S: EntropySource>(params: &[u8], transfer: &[u8], entropy: &mut S, threads: Threads) -> io::Result<Vec<u8>> {
+    let params = envelope::unwrap_parameters::<Bls12>(params)?;
+    let c = read_transfer(transfer)?;
+    validate_witness(&c)?;
+    let mut rng = entropy.rng()?;
+    let proof = metrics::time(Timing::Prove, || prover::create_random_proof(c, &params, &mut rng, threads, &mut ProverScratch::default()))?;
+    metrics::inc(Counter::ProofsGenerated, 1);
+    let mut res = vec![];
+    proof.write(&mut res)?;
+    Ok(res)
+}
+
+// Wallet state kept on the js side, see witness_source. Both callbacks take the index of the
+// note (a BigInt) and return a Uint8Array: the note as read_note expects it, and the flat path.
+struct JsWitnessSource<'a> {
+    get_note: &'a Function,
+    get_merkle_path: &'a Function
+}
+
+impl<'a> JsWitnessSource<'a> {
+    fn request(f: &Function, name: &str, index: u64) -> io::Result<Vec<u8>> {
+        let res = f.call1(&JsValue::NULL, &JsValue::from(index))
+            .map_err(|e| io::Error::other(format!("{} failed: {}", name, e.as_string().unwrap_or_default())))?;
+        if !res.is_instance_of::<Uint8Array>() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} should return a Uint8Array", name)));
+        }
+        Ok(Uint8Array::from(res).to_vec())
+    }
+}
+
+impl<'a> WitnessSource<Bls12> for JsWitnessSource<'a> {
+    fn note(&mut self, index: u64) -> io::Result<NoteData<Bls12>> {
+        read_note(&Self::request(self.get_note, "get_note", index)?)
+    }
+
+    fn merkle_path(&mut self, index: u64) -> io::Result<Vec<Fr>> {
+        read_fr_list(&Self::request(self.get_merkle_path, "get_merkle_path", index)?)
+    }
+}
+
+
+#[wasm_bindgen]
+pub fn hash(x: &[u8]) -> Result<Vec<u8>, JsValue> {
+    hash_native(x).map_err(js_err)
+}
+
+#[wasm_bindgen]
+pub fn merkle_hash(left: &[u8], right: &[u8], level: usize) -> Result<Vec<u8>, JsValue> {
+    merkle_hash_native(left, right, level).map_err(js_err)
+}
+
+#[wasm_bindgen]
+pub fn merkle_root(sibling: &[u8], index: u64, leaf: &[u8]) -> Result<Vec<u8>, JsValue> {
+    merkle_root_native(sibling, index, leaf).map_err(js_err)
+}
+
+#[wasm_bindgen]
+pub fn empty_root(level: usize) -> Result<Vec<u8>, JsValue> {
+    empty_root_native(level).map_err(js_err)
+}
+
+#[wasm_bindgen]
+pub fn note_hash(asset_id: &[u8], amount: &[u8], native_amount: &[u8], txid: &[u8], owner: &[u8]) -> Result<Vec<u8>, JsValue> {
+    note_hash_native(asset_id, amount, native_amount, txid, owner).map_err(js_err)
+}
+
+// vk is the truncated verifying key, as returned by extract_vk of the node bindings
+#[wasm_bindgen]
+pub fn verify(vk: &[u8], proof: &[u8], inputs: &[u8]) -> Result<bool, JsValue> {
+    verify_native(vk, proof, inputs).map_err(js_err)
+}
+
+// Note and path of one input, asked from the js wallet through the callbacks and checked against
+// root, as write_spend_witness encodes it
+#[wasm_bindgen]
+pub fn spend_witness(index: u64, depth: usize, root: &[u8], get_note: &Function, get_merkle_path: &Function) -> Result<Vec<u8>, JsValue> {
+    let root = read_fr(root).map_err(js_err)?;
+    let mut source = JsWitnessSource { get_note, get_merkle_path };
+    let w = build_spend_witness(&mut source, index, depth, &root, &JUBJUB_PARAMS).map_err(js_err)?;
+    Ok(write_spend_witness(&w))
+}
+
+// The fixed-seed workloads of zwaves_circuit::bench as JSON, now is a millisecond clock, usually
+// () => performance.now(). The proving workloads run when params, the enveloped file written by
+// bench_params of zwaves_setup, is passed; they prove on the pool of init_threads when it is ready.
+#[wasm_bindgen]
+pub fn bench(now: &Function, params: Option<Vec<u8>>) -> Result<String, JsValue> {
+    let mut err = None;
+    let mut clock = || match now.call0(&JsValue::NULL).ok().and_then(|t| t.as_f64()) {
+        Some(t) => t,
+        None => {
+            err = Some(JsValue::from_str("now should return a number"));
+            0.0
+        }
+    };
+    let mut res = circuit_bench::run_hash_benches(&mut clock);
+    if let Some(params) = params {
+        let params = envelope::unwrap_parameters::<Bls12>(&params).map_err(js_err)?;
+        res.extend(circuit_bench::run_prove_benches(&mut clock, &params, proving_threads()).map_err(js_err)?);
+    }
+    match err {
+        Some(e) => Err(e),
+        None => Ok(circuit_bench::to_json(&res))
+    }
+}
+
+// Threaded proving, for builds with the threads feature (see README.md). The pool of
+// wasm-bindgen-rayon runs on web workers sharing the memory of the module, which needs
+// SharedArrayBuffer, and browsers give it only to cross-origin isolated pages.
+#[cfg(feature = "threads")]
+static THREADS_READY: AtomicBool = AtomicBool::new(false);
+
+#[wasm_bindgen]
+pub fn threads_supported() -> bool {
+    if !cfg!(all(feature = "threads", target_arch = "wasm32")) {
+        return false;
+    }
+    let global = js_sys::global();
+    Reflect::has(&global, &JsValue::from_str("SharedArrayBuffer")).unwrap_or(false)
+        && Reflect::get(&global, &JsValue::from_str("crossOriginIsolated")).ok().and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+// Starts the pool of n workers; prove_transfer uses it once the promise is resolved
+#[cfg(all(feature = "threads", target_arch = "wasm32"))]
+#[wasm_bindgen]
+pub fn init_threads(n: usize) -> Result<js_sys::Promise, JsValue> {
+    if !threads_supported() {
+        return Err(JsValue::from_str("threads need SharedArrayBuffer and a cross-origin isolated page"));
+    }
+    let ready = Closure::wrap(Box::new(|_: JsValue| THREADS_READY.store(true, Ordering::Relaxed)) as Box<dyn FnMut(JsValue)>);
+    let res = wasm_bindgen_rayon::init_thread_pool(n).then(&ready);
+    ready.forget();
+    Ok(res)
+}
+
+fn proving_threads() -> Threads {
+    #[cfg(feature = "threads")]
+    {
+        if THREADS_READY.load(Ordering::Relaxed) {
+            return Threads::Rayon;
+        }
+    }
+    Threads::Single
+}
+
+// params is the enveloped transfer parameters file written by split, transfer is encoded as
+// read_transfer takes it, the proof is 192 bytes. On the pool of init_threads when it is ready,
+// on the calling thread otherwise. With threads it blocks on the workers, which browsers allow
+// only off the main thread: call it from a web worker.
+#[wasm_bindgen]
+pub fn prove_transfer(params: &[u8], transfer: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let mut entropy = entropy::Entropy::new(None, false).map_err(js_err)?;
+    prove_transfer_native(params, transfer, &mut entropy, proving_threads()).map_err(js_err)
+}
+
+// Heap usage of the module, and of zwaves_napi which links this crate, for the proving limit
+#[global_allocator]
+static ALLOCATOR: CountingAllocator<System> = CountingAllocator::new(System);
+
+// Proofs which would take the heap over limit bytes fail with an error before they start,
+// instead of growing the memory of the instance until it aborts. 0 removes the limit.
+#[wasm_bindgen]
+pub fn set_memory_limit(limit: usize) {
+    memory::register_heap(&ALLOCATOR);
+    memory::set_proving_memory_limit(limit);
+}
+
+#[wasm_bindgen]
+pub fn memory_allocated() -> usize {
+    ALLOCATOR.allocated()
+}
+
+#[wasm_bindgen]
+pub fn memory_peak() -> usize {
+    ALLOCATOR.peak()
+}
+
+#[wasm_bindgen]
+pub fn reset_memory_peak() {
+    ALLOCATOR.reset_peak();
+}
+
+// Every method above and the ones added later behind one stable export, see rpc.rs
+#[wasm_bindgen]
+pub fn zwaves_call(method: &str, params_json: &str) -> String {
+    rpc::call(method, params_json)
+}
+
+
+#[cfg(test)]
+mod wasm_tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_root_of_empty_tree() {
+        let sibling = (0..4).flat_map(|i| empty_root_native(i).unwrap()).collect::<Vec<_>>();
+        let root = merkle_root_native(&sibling, 5, &empty_root_native(0).unwrap()).unwrap();
+        assert!(root == empty_root_native(4).unwrap());
+        assert!(merkle_hash_native(&empty_root_native(3).unwrap(), &empty_root_native(3).unwrap(), 3).unwrap() == root);
+    }
+
+    #[test]
+    fn test_wrong_encoding() {
+        assert!(hash_native(&[0u8; 31]).is_err());
+        assert!(hash_native(&[0xffu8; 32]).is_err());
+        assert!(merkle_root_native(&[0u8; 33], 0, &[0u8; 32]).is_err());
+        assert!(merkle_root_native(&[0u8; 32], 2, &[0u8; 32]).is_err());
+    }
+}
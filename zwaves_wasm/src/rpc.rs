@@ -0,0 +1,492 @@
+use serde_json::{json, Value};
+use pairing::bls12_381::{Bls12, Fr};
+use rand::Rng;
+use sapling_crypto::redjubjub::Signature;
+
+use zwaves_primitives::bundle::TransactionBundle;
+use zwaves_primitives::entropy::EntropySource;
+use zwaves_primitives::pedersen_hasher::JUBJUB_PARAMS;
+use zwaves_primitives::out_amount::OUT_CIPHERTEXT_LEN;
+use zwaves_primitives::point::read_proof;
+use zwaves_primitives::signing_request::SIGNATURE_LEN;
+use zwaves_primitives::snarkjs;
+use zwaves_primitives::spend_auth;
+use zwaves_primitives::verifier;
+
+use std::io;
+use std::convert::TryFrom;
+
+use crate::{hash_native, merkle_hash_native, merkle_root_native, empty_root_native, note_hash_native, verify_native, read_fr, read_fr_list, write_fr};
+use crate::entropy::Entropy;
+
+
+// Single entry point of the wasm ABI: call(method, params) with JSON params, so the SDK gets new
+// methods without new exports. A method is "name@version" or just "name" for its latest version;
+// a version is never changed once released, a new behaviour is a new version next to the old one.
+// Field elements and byte strings are hex, field elements 32 bytes big-endian.
+//
+// The answer is {"result": ...} or {"error": {"code": ..., "message": "..."}} with the codes of
+// JSON-RPC 2.0, so a client can tell its own mistakes from failures of the module.
+pub const ABI_VERSION: u32 = 1;
+
+pub const ERR_PARSE: i64 = -32700;
+pub const ERR_METHOD_NOT_FOUND: i64 = -32601;
+pub const ERR_INVALID_PARAMS: i64 = -32602;
+pub const ERR_INTERNAL: i64 = -32603;
+
+struct Method {
+    name: &'static str,
+    version: u32,
+    handler: fn(&Value) -> io::Result<Value>
+}
+
+const METHODS: &[Method] = &[
+    Method { name: "methods", version: 1, handler: methods_v1 },
+    Method { name: "hash", version: 1, handler: hash_v1 },
+    Method { name: "merkle_hash", version: 1, handler: merkle_hash_v1 },
+    Method { name: "merkle_root", version: 1, handler: merkle_root_v1 },
+    Method { name: "empty_root", version: 1, handler: empty_root_v1 },
+    Method { name: "note_hash", version: 1, handler: note_hash_v1 },
+    Method { name: "verify", version: 1, handler: verify_v1 },
+    Method { name: "bundle_pack", version: 1, handler: bundle_pack_v1 },
+    Method { name: "bundle_unpack", version: 1, handler: bundle_unpack_v1 },
+    Method { name: "bundle_verify", version: 1, handler: bundle_verify_v1 },
+    Method { name: "random_fr", version: 1, handler: random_fr_v1 },
+    Method { name: "spend_auth_sign", version: 1, handler: spend_auth_sign_v1 },
+    Method { name: "snarkjs_verify", version: 1, handler: snarkjs_verify_v1 },
+    Method { name: "snarkjs_export_vk", version: 1, handler: snarkjs_export_vk_v1 },
+    Method { name: "snarkjs_import_vk", version: 1, handler: snarkjs_import_vk_v1 },
+    Method { name: "snarkjs_export_proof", version: 1, handler: snarkjs_export_proof_v1 },
+    Method { name: "snarkjs_import_proof", version: 1, handler: snarkjs_import_proof_v1 },
+    Method { name: "snarkjs_export_inputs", version: 1, handler: snarkjs_export_inputs_v1 },
+    Method { name: "snarkjs_import_inputs", version: 1, handler: snarkjs_import_inputs_v1 }
+];
+
+
+struct RpcError {
+    code: i64,
+    message: String
+}
+
+impl From<io::Error> for RpcError {
+    fn from(e: io::Error) -> Self {
+        let code = match e.kind() {
+            io::ErrorKind::Other => ERR_INTERNAL,
+            _ => ERR_INVALID_PARAMS
+        };
+        RpcError { code, message: e.to_string() }
+    }
+}
+
+fn find_method(method: &str) -> Option<&'static Method> {
+    match method.split_once('@') {
+        Some((name, version)) => {
+            let version = version.parse::<u32>().ok()?;
+            METHODS.iter().find(|m| m.name == name && m.version == version)
+        },
+        None => METHODS.iter().filter(|m| m.name == method).max_by_key(|m| m.version)
+    }
+}
+
+pub fn call(method: &str, params_json: &str) -> String {
+    let res = (|| {
+        let m = find_method(method).ok_or_else(|| RpcError { code: ERR_METHOD_NOT_FOUND, message: format!("unknown method {}", method) })?;
+        let params = serde_json::from_str::<Value>(params_json).map_err(|e| RpcError { code: ERR_PARSE, message: e.to_string() })?;
+        Ok((m.handler)(&params)?)
+    })();
+
+    match res {
+        Ok(result) => json!({ "result": result }),
+        Err(RpcError { code, message }) => json!({ "error": { "code": code, "message": message } })
+    }.to_string()
+}
+
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn param<'a>(params: &'a Value, name: &str) -> io::Result<&'a Value> {
+    params.get(name).ok_or_else(|| invalid(format!("missing parameter {}", name)))
+}
+
+fn bytes_param(params: &Value, name: &str) -> io::Result<Vec<u8>> {
+    let s = param(params, name)?.as_str().ok_or_else(|| invalid(format!("{} should be a hex string", name)))?;
+    hex::decode(s).map_err(|_| invalid(format!("{} should be a hex string", name)))
+}
+
+// A list of field elements, flat as the bindings take it
+fn bytes_list_param(params: &Value, name: &str) -> io::Result<Vec<u8>> {
+    let list = param(params, name)?.as_array().ok_or_else(|| invalid(format!("{} should be a list of hex strings", name)))?;
+    let mut res = vec![];
+    for (i, x) in list.iter().enumerate() {
+        let data = x.as_str().and_then(|s| hex::decode(s).ok()).ok_or_else(|| invalid(format!("{}[{}] should be a hex string", name, i)))?;
+        if data.len() != 32 {
+            return Err(invalid(format!("{}[{}] should be 32 bytes", name, i)));
+        }
+        res.extend(data);
+    }
+    Ok(res)
+}
+
+// A list of byte strings of any length
+fn bytes_vec_param(params: &Value, name: &str) -> io::Result<Vec<Vec<u8>>> {
+    let list = param(params, name)?.as_array().ok_or_else(|| invalid(format!("{} should be a list of hex strings", name)))?;
+    list.iter().enumerate().map(|(i, x)| {
+        x.as_str().and_then(|s| hex::decode(s).ok()).ok_or_else(|| invalid(format!("{}[{}] should be a hex string", name, i)))
+    }).collect()
+}
+
+fn sized_bytes_param(params: &Value, name: &str, len: usize) -> io::Result<Vec<u8>> {
+    let data = bytes_param(params, name)?;
+    if data.len() != len {
+        return Err(invalid(format!("{} should be {} bytes", name, len)));
+    }
+    Ok(data)
+}
+
+fn u64_param(params: &Value, name: &str) -> io::Result<u64> {
+    param(params, name)?.as_u64().ok_or_else(|| invalid(format!("{} should be a non-negative integer", name)))
+}
+
+// usize is 32 bits on wasm32, a larger value must not wrap into a valid one
+fn usize_param(params: &Value, name: &str) -> io::Result<usize> {
+    usize::try_from(u64_param(params, name)?).map_err(|_| invalid(format!("{} is too large", name)))
+}
+
+// Optional "entropy" of the methods using randomness: absent for the host's (crypto.getRandomValues),
+// {"seed": hex} for hosts without entropy, {"seed": hex, "mix": true} for the seed xored with the
+// host's, see entropy.rs
+fn entropy_param(params: &Value) -> io::Result<Entropy> {
+    match params.get("entropy") {
+        None | Some(Value::Null) => Entropy::new(None, false),
+        Some(e) => {
+            let mix = match e.get("mix") {
+                None => false,
+                Some(m) => m.as_bool().ok_or_else(|| invalid("entropy.mix should be a boolean".to_string()))?
+            };
+            Entropy::new(Some(&bytes_param(e, "seed")?), mix)
+        }
+    }
+}
+
+fn fr_param(params: &Value, name: &str) -> io::Result<Fr> {
+    read_fr(&bytes_param(params, name)?)
+}
+
+fn hex_result(data: Vec<u8>) -> io::Result<Value> {
+    Ok(Value::String(hex::encode(data)))
+}
+
+
+fn methods_v1(_params: &Value) -> io::Result<Value> {
+    let methods = METHODS.iter().map(|m| json!({ "name": m.name, "version": m.version })).collect::<Vec<_>>();
+    Ok(json!({ "abi_version": ABI_VERSION, "methods": methods }))
+}
+
+// {"x": fr}
+fn hash_v1(params: &Value) -> io::Result<Value> {
+    hex_result(hash_native(&bytes_param(params, "x")?)?)
+}
+
+// {"left": fr, "right": fr, "level": n}
+fn merkle_hash_v1(params: &Value) -> io::Result<Value> {
+    hex_result(merkle_hash_native(&bytes_param(params, "left")?, &bytes_param(params, "right")?, usize_param(params, "level")?)?)
+}
+
+// {"sibling": [fr], "index": n, "leaf": fr}
+fn merkle_root_v1(params: &Value) -> io::Result<Value> {
+    hex_result(merkle_root_native(&bytes_list_param(params, "sibling")?, u64_param(params, "index")?, &bytes_param(params, "leaf")?)?)
+}
+
+// {"level": n}
+fn empty_root_v1(params: &Value) -> io::Result<Value> {
+    hex_result(empty_root_native(usize_param(params, "level")?)?)
+}
+
+// {"asset_id": fr, "amount": fr, "native_amount": fr, "txid": fr, "owner": fr}
+fn note_hash_v1(params: &Value) -> io::Result<Value> {
+    hex_result(note_hash_native(
+        &bytes_param(params, "asset_id")?,
+        &bytes_param(params, "amount")?,
+        &bytes_param(params, "native_amount")?,
+        &bytes_param(params, "txid")?,
+        &bytes_param(params, "owner")?
+    )?)
+}
+
+// {"vk": hex, "proof": hex, "inputs": [fr]}
+fn verify_v1(params: &Value) -> io::Result<Value> {
+    Ok(Value::Bool(verify_native(&bytes_param(params, "vk")?, &bytes_param(params, "proof")?, &bytes_list_param(params, "inputs")?)?))
+}
+
+
+// The bundle as TransactionBundle::write encodes it, packed and unpacked on the relayer side.
+// Packing and unpacking validate the bundle, so a relayer never forwards a malformed one.
+fn read_bundle(params: &Value) -> io::Result<TransactionBundle<Bls12>> {
+    let bundle = TransactionBundle::<Bls12>::from_bytes(&bytes_param(params, "bundle")?)?;
+    bundle.validate()?;
+    Ok(bundle)
+}
+
+// {"proof": hex, "public_inputs": [fr], "encrypted_notes": [hex], "out_ciphertexts": [hex],
+//  "binding_sig": hex, "spend_auth_sig": hex, "fee": n}
+fn bundle_pack_v1(params: &Value) -> io::Result<Value> {
+    let proof = read_proof::<Bls12, _>(&sized_bytes_param(params, "proof", 192)?[..], true)?;
+    let out_ciphertexts = bytes_vec_param(params, "out_ciphertexts")?.into_iter().map(|ct| {
+        if ct.len() != OUT_CIPHERTEXT_LEN {
+            return Err(invalid(format!("out ciphertext should be {} bytes", OUT_CIPHERTEXT_LEN)));
+        }
+        let mut res = [0u8; OUT_CIPHERTEXT_LEN];
+        res.copy_from_slice(&ct);
+        Ok(res)
+    }).collect::<io::Result<Vec<_>>>()?;
+
+    let bundle = TransactionBundle::<Bls12> {
+        proof,
+        public_inputs: read_fr_list(&bytes_list_param(params, "public_inputs")?)?,
+        encrypted_notes: bytes_vec_param(params, "encrypted_notes")?,
+        out_ciphertexts,
+        binding_sig: Signature::read(&sized_bytes_param(params, "binding_sig", SIGNATURE_LEN)?[..])?,
+        spend_auth_sig: Signature::read(&sized_bytes_param(params, "spend_auth_sig", SIGNATURE_LEN)?[..])?,
+        fee: u64_param(params, "fee")?
+    };
+    bundle.validate()?;
+    hex_result(bundle.to_bytes())
+}
+
+// {"bundle": hex}, answers with the fields of bundle_pack
+fn bundle_unpack_v1(params: &Value) -> io::Result<Value> {
+    let bundle = read_bundle(params)?;
+    let mut proof = vec![];
+    bundle.proof.write(&mut proof)?;
+    let mut binding_sig = vec![];
+    bundle.binding_sig.write(&mut binding_sig)?;
+    let mut spend_auth_sig = vec![];
+    bundle.spend_auth_sig.write(&mut spend_auth_sig)?;
+
+    Ok(json!({
+        "proof": hex::encode(proof),
+        "public_inputs": bundle.public_inputs.iter().map(|x| hex::encode(write_fr(x))).collect::<Vec<_>>(),
+        "encrypted_notes": bundle.encrypted_notes.iter().map(hex::encode).collect::<Vec<_>>(),
+        "out_ciphertexts": bundle.out_ciphertexts.iter().map(hex::encode).collect::<Vec<_>>(),
+        "binding_sig": hex::encode(binding_sig),
+        "spend_auth_sig": hex::encode(spend_auth_sig),
+        "fee": bundle.fee
+    }))
+}
+
+// {"vk": hex, "bundle": hex}, vk as for verify
+fn bundle_verify_v1(params: &Value) -> io::Result<Value> {
+    let tvk = verifier::TruncatedVerifyingKey::<Bls12>::read(&bytes_param(params, "vk")?[..])?;
+    let bundle = read_bundle(params)?;
+    let res = verifier::verify_proof(&tvk, &bundle.proof, &bundle.public_inputs).map_err(|e| invalid(e.to_string()))?;
+    Ok(Value::Bool(res))
+}
+
+
+// {"entropy": ...}, a uniform field element, e.g. alpha for spend_auth_sign
+fn random_fr_v1(params: &Value) -> io::Result<Value> {
+    let mut rng = entropy_param(params)?.rng()?;
+    hex_result(write_fr(&rng.gen::<Fr>()))
+}
+
+// {"sk": fr, "alpha": fr, "msg": hex, "entropy": ...}, the spend authorization signature of msg
+// under the key randomized by alpha
+fn spend_auth_sign_v1(params: &Value) -> io::Result<Value> {
+    let mut rng = entropy_param(params)?.rng()?;
+    let sig = spend_auth::sign::<Bls12, _>(&fr_param(params, "sk")?, &fr_param(params, "alpha")?, &bytes_param(params, "msg")?, &mut rng, &JUBJUB_PARAMS);
+    let mut res = vec![];
+    sig.write(&mut res)?;
+    hex_result(res)
+}
+
+
+// The files of snarkjs (verification_key.json, proof.json, public.json) are passed as JSON values,
+// not strings, and converted to and from the binary vk, proof and inputs of verify, see snarkjs.rs
+// of zwaves_primitives
+fn snarkjs_param(params: &Value, name: &str) -> io::Result<String> {
+    Ok(param(params, name)?.to_string())
+}
+
+fn snarkjs_result(data: String) -> io::Result<Value> {
+    Ok(serde_json::from_str(&data).unwrap())
+}
+
+// {"vk": verification_key.json, "proof": proof.json, "inputs": public.json}
+fn snarkjs_verify_v1(params: &Value) -> io::Result<Value> {
+    Ok(Value::Bool(snarkjs::verify_json(&snarkjs_param(params, "vk")?, &snarkjs_param(params, "proof")?, &snarkjs_param(params, "inputs")?)?))
+}
+
+// {"vk": hex}, vk as for verify
+fn snarkjs_export_vk_v1(params: &Value) -> io::Result<Value> {
+    let tvk = verifier::TruncatedVerifyingKey::<Bls12>::read(&bytes_param(params, "vk")?[..])?;
+    snarkjs_result(snarkjs::verifying_key_to_json(&tvk)?)
+}
+
+// {"vk": verification_key.json}
+fn snarkjs_import_vk_v1(params: &Value) -> io::Result<Value> {
+    let mut res = vec![];
+    snarkjs::verifying_key_from_json(&snarkjs_param(params, "vk")?)?.write(&mut res)?;
+    hex_result(res)
+}
+
+// {"proof": hex}
+fn snarkjs_export_proof_v1(params: &Value) -> io::Result<Value> {
+    let proof = read_proof::<Bls12, _>(&sized_bytes_param(params, "proof", 192)?[..], true)?;
+    snarkjs_result(snarkjs::proof_to_json(&proof)?)
+}
+
+// {"proof": proof.json}
+fn snarkjs_import_proof_v1(params: &Value) -> io::Result<Value> {
+    let mut res = vec![];
+    snarkjs::proof_from_json(&snarkjs_param(params, "proof")?)?.write(&mut res)?;
+    hex_result(res)
+}
+
+// {"inputs": [fr]}
+fn snarkjs_export_inputs_v1(params: &Value) -> io::Result<Value> {
+    snarkjs_result(snarkjs::inputs_to_json(&read_fr_list(&bytes_list_param(params, "inputs")?)?))
+}
+
+// {"inputs": public.json}
+fn snarkjs_import_inputs_v1(params: &Value) -> io::Result<Value> {
+    let inputs = snarkjs::inputs_from_json(&snarkjs_param(params, "inputs")?)?;
+    Ok(Value::Array(inputs.iter().map(|x| Value::String(hex::encode(write_fr(x)))).collect()))
+}
+
+
+#[cfg(test)]
+mod rpc_tests {
+    use super::*;
+
+    fn parse(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_call() {
+        let zero = "00".repeat(32);
+        let res = parse(&call("hash", &format!("{{\"x\":\"{}\"}}", zero)));
+        assert!(res["result"] == Value::String(hex::encode(hash_native(&[0u8; 32]).unwrap())));
+        assert!(parse(&call("hash@1", &format!("{{\"x\":\"{}\"}}", zero))) == res);
+
+        let root = parse(&call("empty_root", "{\"level\":1}"));
+        let res = parse(&call("merkle_root", &format!("{{\"sibling\":[\"{}\"],\"index\":1,\"leaf\":\"{}\"}}", hex::encode(empty_root_native(0).unwrap()), hex::encode(empty_root_native(0).unwrap()))));
+        assert!(res == root && root["result"].is_string());
+
+        let methods = parse(&call("methods", "{}"));
+        assert!(methods["result"]["abi_version"] == json!(ABI_VERSION));
+        assert!(methods["result"]["methods"].as_array().unwrap().len() == METHODS.len());
+    }
+
+    #[test]
+    fn test_errors() {
+        let code = |method: &str, params: &str| parse(&call(method, params))["error"]["code"].as_i64().unwrap();
+        assert!(code("unknown", "{}") == ERR_METHOD_NOT_FOUND);
+        assert!(code("hash@2", "{}") == ERR_METHOD_NOT_FOUND);
+        assert!(code("hash@x", "{}") == ERR_METHOD_NOT_FOUND);
+        assert!(code("hash", "{") == ERR_PARSE);
+        assert!(code("hash", "{}") == ERR_INVALID_PARAMS);
+        assert!(code("hash", "{\"x\":\"zz\"}") == ERR_INVALID_PARAMS);
+        assert!(code("hash", &format!("{{\"x\":\"{}\"}}", "ff".repeat(32))) == ERR_INVALID_PARAMS);
+        assert!(code("empty_root", "{\"level\":-1}") == ERR_INVALID_PARAMS);
+        assert!(code("merkle_root", "{\"sibling\":[\"00\"],\"index\":0,\"leaf\":\"00\"}") == ERR_INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_bundle() {
+        use pairing::bls12_381::{Fr, G1Affine, G2Affine};
+        use pairing::{CurveAffine, PrimeField};
+        use zwaves_primitives::bundle::TRANSFER_PUBLIC_INPUTS;
+
+        let g1 = G1Affine::one().into_compressed();
+        let proof = [g1.as_ref(), G2Affine::one().into_compressed().as_ref(), g1.as_ref()].concat();
+        let inputs = (1..=TRANSFER_PUBLIC_INPUTS).map(|i| hex::encode(write_fr(&Fr::from_str(&i.to_string()).unwrap()))).collect::<Vec<_>>();
+        let mut fields = json!({
+            "proof": hex::encode(&proof),
+            "public_inputs": inputs,
+            "encrypted_notes": ["01", "0202"],
+            "out_ciphertexts": ["00".repeat(OUT_CIPHERTEXT_LEN), "11".repeat(OUT_CIPHERTEXT_LEN)],
+            "binding_sig": "00".repeat(SIGNATURE_LEN),
+            "spend_auth_sig": "00".repeat(SIGNATURE_LEN),
+            "fee": 100500
+        });
+
+        let packed = parse(&call("bundle_pack", &fields.to_string()))["result"].clone();
+        let unpacked = parse(&call("bundle_unpack", &json!({ "bundle": packed }).to_string()))["result"].clone();
+        assert!(unpacked == fields);
+
+        let code = |method: &str, params: &Value| parse(&call(method, &params.to_string()))["error"]["code"].as_i64().unwrap();
+        let bundle = packed.as_str().unwrap();
+        assert!(code("bundle_unpack", &json!({ "bundle": format!("{}00", bundle) })) == ERR_INVALID_PARAMS);
+        assert!(code("bundle_unpack", &json!({ "bundle": &bundle[..bundle.len()-2] })) == ERR_INVALID_PARAMS);
+        assert!(code("bundle_verify", &json!({ "vk": "", "bundle": bundle })) == ERR_INVALID_PARAMS);
+
+        fields["public_inputs"][6] = fields["public_inputs"][5].clone();
+        assert!(code("bundle_pack", &fields) == ERR_INVALID_PARAMS, "equal nullifiers should be rejected");
+    }
+
+    #[test]
+    fn test_snarkjs() {
+        use pairing::bls12_381::{G1Affine, G2Affine};
+        use pairing::{CurveAffine, PrimeField};
+
+        let tvk = verifier::TruncatedVerifyingKey::<Bls12> {
+            alpha_g1: G1Affine::one(),
+            beta_g2: G2Affine::one(),
+            gamma_g2: G2Affine::one(),
+            delta_g2: G2Affine::one(),
+            ic: vec![G1Affine::one(), G1Affine::one()]
+        };
+        let mut vk = vec![];
+        tvk.write(&mut vk).unwrap();
+        let g1 = G1Affine::one().into_compressed();
+        let proof = hex::encode([g1.as_ref(), G2Affine::one().into_compressed().as_ref(), g1.as_ref()].concat());
+        let inputs = json!([hex::encode(write_fr(&Fr::from_str("5").unwrap()))]);
+
+        let result = |method: &str, params: Value| parse(&call(method, &params.to_string()))["result"].clone();
+        let vk_json = result("snarkjs_export_vk", json!({ "vk": hex::encode(&vk) }));
+        assert!(vk_json["protocol"] == "groth16" && vk_json["nPublic"] == 1);
+        assert!(result("snarkjs_import_vk", json!({ "vk": vk_json })) == json!(hex::encode(&vk)));
+        let proof_json = result("snarkjs_export_proof", json!({ "proof": proof }));
+        assert!(result("snarkjs_import_proof", json!({ "proof": proof_json })) == json!(proof));
+        let inputs_json = result("snarkjs_export_inputs", json!({ "inputs": inputs }));
+        assert!(inputs_json == json!(["5"]) && result("snarkjs_import_inputs", json!({ "inputs": inputs_json })) == inputs);
+
+        let verify = |vk: &Value, inputs: &Value| parse(&call("snarkjs_verify", &json!({ "vk": vk, "proof": proof_json, "inputs": inputs }).to_string()));
+        assert!(verify(&vk_json, &inputs_json)["result"] == json!(verify_native(&vk, &hex::decode(&proof).unwrap(), &write_fr(&Fr::from_str("5").unwrap())).unwrap()),
+            "snarkjs_verify should agree with verify");
+        assert!(verify(&vk_json, &json!(["5", "6"]))["error"]["code"] == ERR_INVALID_PARAMS, "wrong number of inputs should be an error");
+        assert!(verify(&Value::String(vk_json.to_string()), &inputs_json)["error"]["code"] == ERR_INVALID_PARAMS, "files should be passed as JSON, not strings");
+    }
+
+    #[test]
+    fn test_entropy() {
+        use pairing::PrimeField;
+
+        let random_fr = |entropy: Value| parse(&call("random_fr", &json!({ "entropy": entropy }).to_string()))["result"].clone();
+        let seeded = random_fr(json!({ "seed": "01" }));
+        assert!(seeded.is_string() && seeded == random_fr(json!({ "seed": "01" })), "a seed alone should be deterministic");
+        assert!(seeded != random_fr(json!({ "seed": "02" })));
+        assert!(random_fr(json!({ "seed": "01", "mix": true })) != random_fr(json!({ "seed": "01", "mix": true })), "a mixed seed should not be");
+        assert!(random_fr(Value::Null).is_string());
+
+        let code = |params: Value| parse(&call("random_fr", &params.to_string()))["error"]["code"].as_i64().unwrap();
+        assert!(code(json!({ "entropy": { "seed": "" } })) == ERR_INVALID_PARAMS);
+        assert!(code(json!({ "entropy": { "seed": "01", "mix": 1 } })) == ERR_INVALID_PARAMS);
+
+        let sk = Fr::from_str("7").unwrap();
+        let alpha = Fr::from_str("11").unwrap();
+        let sign = |entropy: Value| {
+            let params = json!({ "sk": hex::encode(write_fr(&sk)), "alpha": hex::encode(write_fr(&alpha)), "msg": "abcd", "entropy": entropy });
+            hex::decode(parse(&call("spend_auth_sign", &params.to_string()))["result"].as_str().unwrap()).unwrap()
+        };
+        let sig = sign(json!({ "seed": "01" }));
+        assert!(sig == sign(json!({ "seed": "01" })));
+        let rk = spend_auth::randomized_public_key(&spend_auth::spend_auth_key::<Bls12>(&sk, &JUBJUB_PARAMS), &alpha, &JUBJUB_PARAMS);
+        for sig in [sig, sign(Value::Null)].iter() {
+            assert!(spend_auth::verify(&rk, &[0xab, 0xcd], &Signature::read(&sig[..]).unwrap(), &JUBJUB_PARAMS));
+        }
+    }
+}